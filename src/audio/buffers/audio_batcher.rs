@@ -1,6 +1,7 @@
 //! Audio sample batcher for reducing packet frequency.
 
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::audio::AudioSample;
 use crate::audio::frame::AudioBuffer;
@@ -11,21 +12,46 @@ use crate::pipeline::Node;
 /// Useful for reducing network packet frequency when input chunks are small.
 /// Accumulates incoming samples and only outputs when the buffer reaches
 /// the minimum sample count (calculated from min_ms at construction).
+/// A maximum set with [`with_max_ms`](Self::with_max_ms) caps the batch
+/// length, also against later [`set_min_ms`](Self::set_min_ms) calls.
 pub struct AudioBatcher<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     buffer: Mutex<Vec<Sample>>,
-    min_samples: usize,
+    min_samples: AtomicUsize,
+    max_samples: usize,
 }
 
 impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32>
     AudioBatcher<Sample, CHANNELS, SAMPLE_RATE>
 {
     pub fn new(min_ms: u32) -> Self {
-        let min_samples = (SAMPLE_RATE * CHANNELS as u32 * min_ms / 1000) as usize;
+        let min_samples = Self::samples_for(min_ms);
         Self {
             buffer: Mutex::new(Vec::with_capacity(min_samples * 2)),
-            min_samples,
+            min_samples: AtomicUsize::new(min_samples),
+            max_samples: usize::MAX,
         }
     }
+
+    /// Never batches more than `max_ms`.
+    pub fn with_max_ms(mut self, max_ms: u32) -> Self {
+        self.max_samples = Self::samples_for(max_ms);
+        self.min_samples
+            .fetch_min(self.max_samples, Ordering::Relaxed);
+        self
+    }
+
+    /// Changes the batch length, within the maximum; takes effect from the
+    /// next output.
+    pub fn set_min_ms(&self, min_ms: u32) {
+        self.min_samples.store(
+            Self::samples_for(min_ms).min(self.max_samples),
+            Ordering::Relaxed,
+        );
+    }
+
+    fn samples_for(min_ms: u32) -> usize {
+        (SAMPLE_RATE * CHANNELS as u32 * min_ms / 1000) as usize
+    }
 }
 
 impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
@@ -38,11 +64,41 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
         let mut buffer = self.buffer.lock().unwrap();
         buffer.extend(input.into_inner());
 
-        if buffer.len() >= self.min_samples {
-            let samples: Vec<Sample> = buffer.drain(..self.min_samples).collect();
+        let min_samples = self.min_samples.load(Ordering::Relaxed);
+        if buffer.len() >= min_samples {
+            let samples: Vec<Sample> = buffer.drain(..min_samples).collect();
             AudioBuffer::new(samples).ok()
         } else {
             None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block() -> AudioBuffer<f32, 2, 48000> {
+        AudioBuffer::new(vec![0.0; 480]).unwrap()
+    }
+
+    /// Samples per channel of the first batch out of `batcher`.
+    fn batch_len(batcher: &AudioBatcher<f32, 2, 48000>) -> usize {
+        loop {
+            if let Some(batch) = batcher.process(block()) {
+                return batch.data().len() / 2;
+            }
+        }
+    }
+
+    #[test]
+    fn maximum_caps_the_batch_length() {
+        let batcher = AudioBatcher::<f32, 2, 48000>::new(20).with_max_ms(5);
+        assert_eq!(batch_len(&batcher), 240);
+        batcher.set_min_ms(40);
+        assert_eq!(batch_len(&batcher), 240, "later changes stay capped");
+
+        let uncapped = AudioBatcher::<f32, 2, 48000>::new(20);
+        assert_eq!(batch_len(&uncapped), 960);
+    }
+}
@@ -8,6 +8,35 @@
 //! - On push: clamp read_seq forward if it falls outside target latency window
 //! - On pull: only hold back when read_seq would exceed write_seq (underrun)
 //! - Adapt target latency: increase on high loss, decrease when min latency stays high
+//!
+//! # Memory budget
+//!
+//! [`JitterBuffer::fit_memory`] shrinks the number of usable slots and the
+//! length of the debug snapshot history so the buffer stays within a byte
+//! budget. Fewer slots cap the target latency, so the buffer absorbs less
+//! network jitter; a shorter history only shortens the debug packet graph.
+//!
+//! # Epochs
+//!
+//! A sender that reconnects starts its sequence numbers over, so its frames
+//! look far behind the read position. By default they are dropped as late
+//! until enough arrive to assume a restart. With
+//! [`JitterBuffer::with_epoch_tolerance`] the frame timestamp breaks the tie:
+//! a frame behind the read position but clearly newer than anything seen
+//! starts a new epoch right away.
+//!
+//! # Concealment
+//!
+//! When the read position reaches a lost frame, a decoder set with
+//! [`JitterBuffer::with_recovery`] is asked first to rebuild it, e.g. from
+//! the inband FEC data of the frame after it. Only then is the frame known
+//! to be lost rather than reordered, since until it's due it may still
+//! arrive. What isn't rebuilt plays as silence unless set otherwise with
+//! [`JitterBuffer::with_concealment`]. [`Concealment::Repeat`] keeps a copy
+//! of the last frame received and plays it again in its place, fading out
+//! over a few lost frames in a row before falling back to silence. Every
+//! other repeat is played backwards, so each one joins the audio before it
+//! without a jump.
 
 use crate::audio::AudioSample;
 use crate::audio::effects::calculate_rms_level;
@@ -16,8 +45,9 @@ use crate::pipeline::{Pullable, Pushable};
 use crossbeam::atomic::AtomicCell;
 use std::collections::VecDeque;
 use std::sync::Mutex;
-use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
-use tracing::{debug, error};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, warn};
 
 const EMA_ALPHA: f64 = 0.01;
 const RESET_THRESHOLD_COUNT: u64 = 50;
@@ -34,6 +64,30 @@ const LOW_LOSS_THRESHOLD: f64 = 0.02; // 2% loss rate allows target decrease
 
 const SNAPSHOT_WINDOW_SIZE: usize = 200; // ~1 second at ~5ms/pull (256 samples @ 48kHz)
 
+// Floors for `fit_memory`: below these the buffer stops being useful.
+const MIN_SLOT_LIMIT: usize = 8;
+const MIN_SNAPSHOT_WINDOW_SIZE: usize = 20;
+
+/// How [`JitterBuffer`] fills in a lost frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Concealment {
+    /// Silence.
+    #[default]
+    Silence,
+    /// The last frame received, fading to silence over `max_frames` lost
+    /// frames in a row.
+    Repeat { max_frames: usize },
+}
+
+/// Duration of `frames` frames of `frame_size` interleaved samples.
+pub fn frames_to_ms(frames: u64, frame_size: u64, channels: usize, sample_rate: u32) -> f64 {
+    if channels == 0 || sample_rate == 0 {
+        return 0.0;
+    }
+    let frame_secs = frame_size as f64 / channels as f64 / sample_rate as f64;
+    frames as f64 * frame_secs * 1000.0
+}
+
 /// Separate Ts to different CPU cache lines, preventing cache invalidation.
 #[repr(align(64))]
 struct CachePadded<T>(T);
@@ -70,7 +124,11 @@ pub struct JitterBufferStats {
     target_latency: AtomicU64,
     latency_window: Mutex<VecDeque<u64>>,
     audio_level: AtomicU32,
+    /// When a frame that wasn't digital silence was last pushed.
+    last_audible: AtomicCell<Option<Instant>>,
     snapshots: Mutex<VecDeque<PullSnapshot>>,
+    snapshot_limit: AtomicUsize,
+    max_target_latency: AtomicU64,
 }
 
 impl JitterBufferStats {
@@ -81,7 +139,10 @@ impl JitterBufferStats {
             target_latency: AtomicU64::new(DEFAULT_TARGET_LATENCY),
             latency_window: Mutex::new(VecDeque::with_capacity(LATENCY_WINDOW_SIZE)),
             audio_level: AtomicU32::new(0),
+            last_audible: AtomicCell::new(None),
             snapshots: Mutex::new(VecDeque::with_capacity(SNAPSHOT_WINDOW_SIZE)),
+            snapshot_limit: AtomicUsize::new(SNAPSHOT_WINDOW_SIZE),
+            max_target_latency: AtomicU64::new(MAX_TARGET_LATENCY),
         }
     }
 
@@ -111,6 +172,32 @@ impl JitterBufferStats {
         snapshots.iter().cloned().collect()
     }
 
+    /// Returns how many pull snapshots are kept.
+    pub fn snapshot_limit(&self) -> usize {
+        self.snapshot_limit.load(Ordering::Acquire)
+    }
+
+    fn set_snapshot_limit(&self, limit: usize) {
+        self.snapshot_limit.store(limit, Ordering::Release);
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let excess = snapshots.len().saturating_sub(limit);
+        snapshots.drain(..excess);
+        snapshots.shrink_to(limit);
+    }
+
+    fn set_max_target_latency(&self, max: u64) {
+        self.max_target_latency.store(max, Ordering::Release);
+        self.target_latency.fetch_min(max, Ordering::AcqRel);
+    }
+
+    fn snapshots_memory(&self) -> usize {
+        let snapshots = self.snapshots.lock().unwrap();
+        snapshots
+            .iter()
+            .map(|s| std::mem::size_of::<PullSnapshot>() + s.slot_status.capacity())
+            .sum()
+    }
+
     fn record_latency(&self, latency: u64) {
         let mut window = self.latency_window.lock().unwrap();
         if window.len() >= LATENCY_WINDOW_SIZE {
@@ -119,6 +206,15 @@ impl JitterBufferStats {
         window.push_back(latency);
     }
 
+    /// Spread of the buffered frames seen by recent pulls, in frames: how
+    /// far arrivals wander around the steady state. `None` before any pull.
+    pub fn latency_spread(&self) -> Option<u64> {
+        let window = self.latency_window.lock().unwrap();
+        let max = window.iter().copied().max()?;
+        let min = window.iter().copied().min()?;
+        Some(max - min)
+    }
+
     fn min_latency_in_window(&self) -> Option<u64> {
         let window = self.latency_window.lock().unwrap();
         window.iter().copied().min()
@@ -148,9 +244,16 @@ impl JitterBufferStats {
         self.audio_level.store(level, Ordering::Release);
     }
 
+    /// When a frame with any non-zero sample was last pushed. DTX gaps
+    /// decoded to silence and all-zero audio leave it alone.
+    pub fn last_audible(&self) -> Option<Instant> {
+        self.last_audible.load()
+    }
+
     fn record_snapshot(&self, snapshot: PullSnapshot) {
+        let limit = self.snapshot_limit();
         let mut snapshots = self.snapshots.lock().unwrap();
-        if snapshots.len() >= SNAPSHOT_WINDOW_SIZE {
+        while snapshots.len() >= limit.max(1) {
             snapshots.pop_front();
         }
         snapshots.push_back(snapshot);
@@ -159,10 +262,11 @@ impl JitterBufferStats {
     fn adjust_target_latency(&self) {
         let loss_rate = self.loss_rate();
         let current_target = self.target_latency.load(Ordering::Acquire);
+        let max_target = self.max_target_latency.load(Ordering::Acquire);
 
         // Increase target latency when loss is high
-        if loss_rate > HIGH_LOSS_THRESHOLD && current_target < MAX_TARGET_LATENCY {
-            let new_target = (current_target + 1).min(MAX_TARGET_LATENCY);
+        if loss_rate > HIGH_LOSS_THRESHOLD && current_target < max_target {
+            let new_target = (current_target + 1).min(max_target);
             self.target_latency.store(new_target, Ordering::Release);
             debug!(
                 "JitterBuffer: Target latency increased {} -> {} (loss_rate={:.2}%)",
@@ -225,6 +329,11 @@ impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Slot<Sample, CHANNEL
         self.has_data.store(false, Ordering::Release);
         self.data.swap(None).map(|b| *b)
     }
+
+    fn clear(&self) {
+        self.has_data.store(false, Ordering::Release);
+        self.data.swap(None);
+    }
 }
 
 /// Leftover samples from a partially consumed frame.
@@ -261,6 +370,16 @@ impl<Sample> PartialFrameState<Sample> {
     }
 }
 
+/// Rebuilds lost frame `seq` of `frame_size` samples, if it can.
+type Recovery<Sample> = Box<dyn Fn(u64, usize) -> Option<Vec<Sample>> + Send + Sync>;
+
+/// The last frame received, kept to conceal losses after it.
+struct ConcealmentState<Sample> {
+    last: Vec<Sample>,
+    /// Frames lost since `last`.
+    lost: usize,
+}
+
 /// A jitter buffer that reorders out-of-order frames by sequence number.
 ///
 /// Implements both [`Sink`] for receiving frames and [`Source`] for retrieving them
@@ -278,6 +397,8 @@ impl<Sample> PartialFrameState<Sample> {
 pub struct JitterBuffer<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     slots: Box<[Slot<Sample, CHANNELS, SAMPLE_RATE>]>,
     capacity: usize,
+    /// Number of slots in use (at most `capacity`), lowered by `fit_memory`.
+    slot_limit: AtomicUsize,
     /// Next sequence number to read (will-be-read).
     /// The reader attempts to fetch from slot[read_seq % capacity].
     read_seq: CachePadded<AtomicU64>,
@@ -289,9 +410,17 @@ pub struct JitterBuffer<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     /// When count reaches RESET_THRESHOLD_COUNT, we assume the sender restarted
     /// and reset the buffer to accept the new sequence range.
     late_packet_count: AtomicU64,
+    /// Timestamp of the newest frame written, in microseconds.
+    newest_timestamp: AtomicU64,
+    /// How much newer than `newest_timestamp` a frame behind read_seq must be
+    /// to start a new epoch. `None` judges by sequence number alone.
+    epoch_tolerance_us: Option<u64>,
     stats: JitterBufferStats,
     /// A partially read frame. Here we store its left over for next pull's use.
     partial: Mutex<PartialFrameState<Sample>>,
+    concealment: Concealment,
+    concealment_state: Mutex<ConcealmentState<Sample>>,
+    recovery: Option<Recovery<Sample>>,
 }
 
 impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
@@ -303,16 +432,122 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         Self {
             slots: slots.into_boxed_slice(),
             capacity,
+            slot_limit: AtomicUsize::new(capacity),
             read_seq: CachePadded::new(AtomicU64::new(0)),
             write_seq: CachePadded::new(AtomicU64::new(0)),
             late_packet_count: AtomicU64::new(0),
+            newest_timestamp: AtomicU64::new(0),
+            epoch_tolerance_us: None,
             stats: JitterBufferStats::new(),
             partial: Mutex::new(PartialFrameState::new()),
+            concealment: Concealment::Silence,
+            concealment_state: Mutex::new(ConcealmentState {
+                last: Vec::new(),
+                lost: 0,
+            }),
+            recovery: None,
         }
     }
 
+    /// Fills lost frames as `concealment` says instead of with silence.
+    pub fn with_concealment(mut self, concealment: Concealment) -> Self {
+        self.concealment = concealment;
+        self
+    }
+
+    /// Asks `recovery` to rebuild each lost frame, given its sequence
+    /// number and the expected frame size, when it's due to play. Frames it
+    /// can't rebuild are concealed.
+    pub fn with_recovery(
+        mut self,
+        recovery: impl Fn(u64, usize) -> Option<Vec<Sample>> + Send + Sync + 'static,
+    ) -> Self {
+        self.recovery = Some(Box::new(recovery));
+        self
+    }
+
+    /// Starts a new epoch when a frame behind the read position carries a
+    /// timestamp more than `tolerance` newer than any frame seen, instead of
+    /// dropping it as late. `None` (the default) only looks at sequence
+    /// numbers.
+    pub fn with_epoch_tolerance(mut self, tolerance: Option<Duration>) -> Self {
+        self.epoch_tolerance_us = tolerance.map(|t| t.as_micros() as u64);
+        self
+    }
+
     fn slot_index(&self, seq: u64) -> usize {
-        (seq % self.capacity as u64) as usize
+        (seq % self.slot_limit() as u64) as usize
+    }
+
+    /// Returns the number of slots currently in use.
+    pub fn slot_limit(&self) -> usize {
+        self.slot_limit.load(Ordering::Acquire)
+    }
+
+    /// Estimated bytes held by one buffered frame.
+    fn frame_memory(&self) -> usize {
+        let frame_size = match self.stats.expected_frame_size() {
+            0 => SAMPLE_RATE as usize / 50 * CHANNELS, // assume 20ms until the first push
+            size => size as usize,
+        };
+        std::mem::size_of::<AudioFrame<Sample, CHANNELS, SAMPLE_RATE>>()
+            + frame_size * std::mem::size_of::<Sample>()
+    }
+
+    /// Estimated bytes currently held by buffered frames and history.
+    pub fn memory_usage(&self) -> usize {
+        let buffered = self
+            .slots
+            .iter()
+            .filter(|slot| slot.stored_seq().is_some())
+            .count();
+        let partial = self.partial.lock().unwrap().samples.capacity();
+        buffered * self.frame_memory()
+            + partial * std::mem::size_of::<Sample>()
+            + self.stats.snapshots_memory()
+    }
+
+    /// Shrinks slot count and snapshot history so the buffer fits in `budget`
+    /// bytes, or restores them to full size when the budget allows.
+    ///
+    /// Both are scaled by the same factor and never go below a small floor,
+    /// so a very small budget can still be exceeded. Changing the slot count
+    /// drops frames that no longer map to their slot, which sounds like a
+    /// short packet loss burst.
+    pub fn fit_memory(&self, budget: usize) {
+        let frame_memory = self.frame_memory();
+        let cost = |slots: usize, history: usize| {
+            slots * frame_memory + history * (std::mem::size_of::<PullSnapshot>() + slots)
+        };
+
+        let full_cost = cost(self.capacity, SNAPSHOT_WINDOW_SIZE);
+        let (slots, history) = if full_cost <= budget {
+            (self.capacity, SNAPSHOT_WINDOW_SIZE)
+        } else {
+            let scale = budget as f64 / full_cost as f64;
+            (
+                ((self.capacity as f64 * scale) as usize).clamp(MIN_SLOT_LIMIT, self.capacity),
+                ((SNAPSHOT_WINDOW_SIZE as f64 * scale) as usize)
+                    .clamp(MIN_SNAPSHOT_WINDOW_SIZE, SNAPSHOT_WINDOW_SIZE),
+            )
+        };
+
+        if self.stats.snapshot_limit() != history {
+            self.stats.set_snapshot_limit(history);
+        }
+
+        let previous = self.slot_limit.swap(slots, Ordering::AcqRel);
+        if previous != slots {
+            debug!(
+                "JitterBuffer: Slot limit {} -> {}, history {} (budget={}B)",
+                previous, slots, history, budget
+            );
+            for slot in &self.slots[slots..] {
+                slot.clear();
+            }
+            self.stats
+                .set_max_target_latency(MAX_TARGET_LATENCY.min(slots as u64 - 1));
+        }
     }
 
     /// Skip the current expected frame.
@@ -335,6 +570,29 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         &self.stats
     }
 
+    /// Returns the current target latency in milliseconds, timed by the
+    /// expected frame size. 0 until a frame has arrived.
+    pub fn target_latency_ms(&self) -> f64 {
+        frames_to_ms(
+            self.stats.target_latency(),
+            self.stats.expected_frame_size(),
+            CHANNELS,
+            SAMPLE_RATE,
+        )
+    }
+
+    /// Measured network jitter in milliseconds, from the
+    /// [latency spread](JitterBufferStats::latency_spread) timed by the
+    /// expected frame size. `None` until frames have arrived and been pulled.
+    pub fn jitter_ms(&self) -> Option<f64> {
+        let frame_size = self.stats.expected_frame_size();
+        if frame_size == 0 {
+            return None;
+        }
+        let spread = self.stats.latency_spread()?;
+        Some(frames_to_ms(spread, frame_size, CHANNELS, SAMPLE_RATE))
+    }
+
     /// Captures the current slot status between read_seq and write_seq.
     fn capture_slot_status(&self, read_seq: u64, write_seq: u64) -> Vec<bool> {
         let count = write_seq.saturating_sub(read_seq) as usize;
@@ -348,6 +606,91 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
             .collect()
     }
 
+    /// Sequence numbers between read_seq and write_seq whose frames haven't
+    /// arrived, nearest first. read_seq trails write_seq by at most the
+    /// target latency, so a frame filling one of these holes still plays.
+    pub fn missing_seqs(&self) -> Vec<u64> {
+        let read_seq = self.read_seq.load(Ordering::Acquire);
+        let write_seq = self.write_seq.load(Ordering::Acquire);
+        if write_seq == 0 {
+            return Vec::new();
+        }
+        let end = write_seq.min(read_seq.saturating_add(self.slot_limit() as u64));
+        (read_seq..end)
+            .filter(|&seq| self.slots[self.slot_index(seq)].stored_seq() != Some(seq))
+            .collect()
+    }
+
+    /// Whether a frame behind read_seq stamped `timestamp` is a new epoch
+    /// rather than a late arrival.
+    fn is_new_epoch(&self, timestamp: u64) -> bool {
+        let Some(tolerance) = self.epoch_tolerance_us else {
+            return false;
+        };
+        let newest = self.newest_timestamp.load(Ordering::Acquire);
+        newest > 0 && timestamp > newest.saturating_add(tolerance)
+    }
+
+    /// Forgets the previous epoch so the next push starts reading afresh.
+    fn reset_epoch(&self) {
+        for slot in self.slots.iter() {
+            slot.clear();
+        }
+        self.read_seq.store(0, Ordering::Release);
+        self.write_seq.store(0, Ordering::Release);
+        self.late_packet_count.store(0, Ordering::Release);
+    }
+
+    /// Drops everything buffered so reading restarts at the next frame
+    /// pushed.
+    pub fn reset(&self) {
+        self.reset_epoch();
+        *self.partial.lock().unwrap() = PartialFrameState::new();
+        self.concealment_state.lock().unwrap().last.clear();
+    }
+
+    /// Keeps `samples` to conceal the frames lost after them.
+    fn remember_frame(&self, samples: &[Sample]) {
+        if self.concealment == Concealment::Silence {
+            return;
+        }
+        let mut state = self.concealment_state.lock().unwrap();
+        state.last.clear();
+        state.last.extend_from_slice(samples);
+        state.lost = 0;
+    }
+
+    /// The `frame_size` samples played for a lost frame.
+    fn conceal(&self, frame_size: usize) -> Vec<Sample> {
+        let Concealment::Repeat { max_frames } = self.concealment else {
+            return vec![Sample::silence(); frame_size];
+        };
+        let mut state = self.concealment_state.lock().unwrap();
+        let lost = state.lost;
+        state.lost = lost.saturating_add(1);
+        if lost >= max_frames || state.last.len() != frame_size {
+            return vec![Sample::silence(); frame_size];
+        }
+
+        // Fades linearly from where the previous repeat left off, reaching
+        // silence at the end of the last one.
+        let frames = frame_size / CHANNELS;
+        let start = 1.0 - lost as f64 / max_frames as f64;
+        let step = 1.0 / (max_frames * frames) as f64;
+        let backwards = lost % 2 == 0;
+        let mut concealed = Vec::with_capacity(frame_size);
+        for i in 0..frames {
+            let source = if backwards { frames - 1 - i } else { i };
+            let gain = start - step * (i + 1) as f64;
+            concealed.extend(
+                state.last[source * CHANNELS..(source + 1) * CHANNELS]
+                    .iter()
+                    .map(|s| Sample::from_f64_normalized(s.to_f64_normalized() * gain)),
+            );
+        }
+        concealed
+    }
+
     /// Clamp read_seq forward to stay within target latency of write_seq.
     fn clamp_read_seq(&self, write_seq: u64) {
         let target_latency = self.stats.target_latency();
@@ -389,6 +732,19 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         slot.take(read_seq)
     }
 
+    /// Samples from a [`Self::collect_samples`] result, or `len` samples of
+    /// silence if it collected nothing. The output callback must always get
+    /// a full buffer; a panic here would take down the realtime audio thread.
+    fn samples_or_silence(collected: Option<(Vec<Sample>, u64)>, len: usize) -> Vec<Sample> {
+        match collected {
+            Some((samples, _seq)) => samples,
+            None => {
+                warn!("JitterBuffer: collected no samples, returning silence");
+                vec![Sample::silence(); len]
+            }
+        }
+    }
+
     /// Collect samples into the output buffer, handling partial frames and fetching new frames.
     fn collect_samples(&self, len: usize) -> Option<(Vec<Sample>, u64)> {
         let mut partial = self.partial.lock().unwrap();
@@ -426,6 +782,7 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                     result_seq = frame.sequence_number;
 
                     let samples = frame.samples.into_inner();
+                    self.remember_frame(&samples);
                     let needed = len - collected.len();
 
                     if samples.len() <= needed {
@@ -455,17 +812,27 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
 
                     let remaining = len - collected.len();
                     let frame_size = self.stats.expected_frame_size() as usize;
-                    let fill_count = if frame_size > 0 {
-                        frame_size.min(remaining)
-                    } else {
-                        remaining
-                    };
-
-                    collected.extend(std::iter::repeat_n(Sample::silence(), fill_count));
+                    if frame_size == 0 {
+                        collected.extend(std::iter::repeat_n(Sample::silence(), remaining));
+                        continue;
+                    }
 
-                    if frame_size > fill_count {
-                        let leftover = frame_size - fill_count;
-                        partial.store(std::iter::repeat_n(Sample::silence(), leftover), result_seq);
+                    let recovered = self
+                        .recovery
+                        .as_ref()
+                        .and_then(|recover| recover(read_seq, frame_size));
+                    let fill = match recovered {
+                        Some(samples) => {
+                            self.remember_frame(&samples);
+                            samples
+                        }
+                        None => self.conceal(frame_size),
+                    };
+                    if fill.len() <= remaining {
+                        collected.extend(fill);
+                    } else {
+                        collected.extend(fill[..remaining].iter().copied());
+                        partial.store(fill[remaining..].iter().copied(), result_seq);
                     }
                 }
             }
@@ -498,13 +865,34 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
     fn push(&self, input: AudioFrame<Sample, CHANNELS, SAMPLE_RATE>) {
         let frame_size = input.samples.data().len() as u64;
         self.stats.record_expected_frame_size(frame_size);
+        if input
+            .samples
+            .data()
+            .iter()
+            .any(|sample| sample.to_f64_normalized() != 0.0)
+        {
+            self.stats.last_audible.store(Some(Instant::now()));
+        }
 
         let seq = input.sequence_number;
+        let timestamp = input.timestamp;
         let slot_idx = self.slot_index(seq);
         let slot = &self.slots[slot_idx];
 
         let mut read_seq = self.read_seq.load(Ordering::Acquire);
-        let write_seq = self.write_seq.load(Ordering::Acquire);
+        let mut write_seq = self.write_seq.load(Ordering::Acquire);
+
+        if seq < read_seq && self.is_new_epoch(timestamp) {
+            debug!(
+                "JitterBuffer: New epoch at seq={} (read_seq={}), timestamp {}us past newest",
+                seq,
+                read_seq,
+                timestamp - self.newest_timestamp.load(Ordering::Acquire)
+            );
+            self.reset_epoch();
+            read_seq = 0;
+            write_seq = 0;
+        }
 
         if read_seq == 0 && write_seq == 0 && seq > 0 {
             self.read_seq
@@ -545,6 +933,7 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         }
 
         slot.write(seq, input);
+        self.newest_timestamp.fetch_max(timestamp, Ordering::AcqRel);
 
         let mut new_write_seq = write_seq;
         loop {
@@ -573,7 +962,13 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
     for JitterBuffer<Sample, CHANNELS, SAMPLE_RATE>
 {
     fn pull(&self, len: usize) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
-        let (samples, _seq) = self.collect_samples(len).unwrap();
+        // An empty request (e.g. a zero-sized device callback) must not move
+        // read_seq or feed the latency/loss statistics.
+        if len == 0 {
+            return AudioBuffer::new(Vec::new()).ok();
+        }
+
+        let samples = Self::samples_or_silence(self.collect_samples(len), len);
 
         debug_assert_eq!(
             samples.len(),
@@ -609,6 +1004,12 @@ mod tests {
         TestFrame::new(seq, samples).unwrap()
     }
 
+    fn make_frame_at(seq: u64, timestamp: u64) -> TestFrame {
+        let mut frame = make_frame(seq, 1920);
+        frame.timestamp = timestamp;
+        frame
+    }
+
     fn push(buffer: &TestBuffer, frame: TestFrame) {
         Pushable::push(buffer, frame);
     }
@@ -617,6 +1018,55 @@ mod tests {
         Pullable::pull(buffer, len)
     }
 
+    #[test]
+    fn test_zero_length_pull_is_empty_and_leaves_state() {
+        let buffer = TestBuffer::new(16);
+        push(&buffer, make_frame(1, 1920));
+        push(&buffer, make_frame(2, 1920));
+        pull(&buffer, 1000);
+
+        let read_seq = buffer.read_seq.load(Ordering::Acquire);
+        let write_seq = buffer.write_seq.load(Ordering::Acquire);
+        let snapshots = buffer.stats().recent_snapshots().len();
+
+        let pulled = pull(&buffer, 0).expect("zero-length pull should return a buffer");
+        assert!(pulled.data().is_empty());
+        assert_eq!(buffer.read_seq.load(Ordering::Acquire), read_seq);
+        assert_eq!(buffer.write_seq.load(Ordering::Acquire), write_seq);
+        assert_eq!(buffer.stats().recent_snapshots().len(), snapshots);
+
+        let next = pull(&buffer, 920).unwrap();
+        assert_eq!(next.data().len(), 920);
+    }
+
+    #[test]
+    fn test_fit_memory_shrinks_slots_and_history() {
+        let buffer = TestBuffer::new(64);
+        for seq in 1..=10 {
+            push(&buffer, make_frame(seq, 1920));
+            pull(&buffer, 1920);
+        }
+
+        buffer.fit_memory(usize::MAX);
+        assert_eq!(buffer.slot_limit(), 64);
+        assert_eq!(buffer.stats().snapshot_limit(), SNAPSHOT_WINDOW_SIZE);
+
+        let budget = 64 * 1024;
+        buffer.fit_memory(budget);
+        assert!(buffer.slot_limit() < 64);
+        assert!(buffer.stats().snapshot_limit() < SNAPSHOT_WINDOW_SIZE);
+        assert!(buffer.stats().target_latency() < buffer.slot_limit() as u64);
+        assert!(buffer.memory_usage() <= budget);
+
+        // Still plays after the resize.
+        push(&buffer, make_frame(11, 1920));
+        push(&buffer, make_frame(12, 1920));
+        assert_eq!(pull(&buffer, 1920).unwrap().data().len(), 1920);
+
+        buffer.fit_memory(usize::MAX);
+        assert_eq!(buffer.slot_limit(), 64);
+    }
+
     #[test]
     fn test_push_and_pull_single_frame() {
         let buffer = TestBuffer::new(16);
@@ -630,6 +1080,18 @@ mod tests {
         assert_eq!(pulled.data().len(), 1920);
     }
 
+    #[test]
+    fn test_target_latency_in_ms() {
+        let buffer = TestBuffer::new(16);
+        assert_eq!(buffer.target_latency_ms(), 0.0, "no frame size yet");
+
+        // 20 ms of stereo at 48 kHz.
+        push(&buffer, make_frame(1, 960 * 2));
+        assert_eq!(buffer.stats().target_latency(), 3);
+        assert_eq!(buffer.target_latency_ms(), 60.0);
+        assert_eq!(frames_to_ms(3, 480 * 2, 2, 48000), 30.0);
+    }
+
     #[test]
     fn test_pull_exact_length() {
         let buffer = TestBuffer::new(16);
@@ -765,6 +1227,89 @@ mod tests {
         }
     }
 
+    /// 20 ms of a continuous 440 Hz stereo tone, as frame `seq`.
+    fn make_tone_frame(seq: u64) -> TestFrame {
+        let samples = (0..960)
+            .flat_map(|i| {
+                let t = ((seq - 1) * 960 + i) as f32 / 48000.0;
+                let s = (std::f32::consts::TAU * 440.0 * t).sin() * 0.5;
+                [s, s]
+            })
+            .collect();
+        TestFrame::new(seq, samples).unwrap()
+    }
+
+    /// Plays frames 1, 2 and 4 of the tone, frame 3 lost.
+    fn play_with_loss(buffer: TestBuffer) -> Vec<f32> {
+        for seq in [1, 2, 4] {
+            push(&buffer, make_tone_frame(seq));
+        }
+        pull(&buffer, 1920 * 4).unwrap().data().to_vec()
+    }
+
+    #[test]
+    fn test_repeat_concealment_fills_a_lost_frame_smoothly() {
+        let rms = |samples: &[f32]| {
+            (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+        };
+        // Step from the last sample of frame 2 into the lost frame, left channel.
+        let jump = |out: &[f32]| (out[3840] - out[3838]).abs();
+        let tone_rms = rms(make_tone_frame(3).samples.data());
+
+        let silent = play_with_loss(TestBuffer::new(16));
+        assert_eq!(rms(&silent[3840..5760]), 0.0);
+        assert!(jump(&silent) > 0.1, "silence cuts off the tone");
+
+        let concealed = play_with_loss(
+            TestBuffer::new(16).with_concealment(Concealment::Repeat { max_frames: 3 }),
+        );
+        assert_eq!(concealed[..3840], silent[..3840]);
+        assert_eq!(concealed[5760..], silent[5760..], "frame 4 plays as is");
+        assert!(rms(&concealed[3840..5760]) > tone_rms * 0.7);
+        assert!(jump(&concealed) < 0.01, "jump of {}", jump(&concealed));
+    }
+
+    #[test]
+    fn test_repeat_concealment_fades_to_silence() {
+        let buffer = TestBuffer::new(16).with_concealment(Concealment::Repeat { max_frames: 2 });
+        push(&buffer, make_tone_frame(1));
+        push(&buffer, make_tone_frame(2));
+        pull(&buffer, 1920 * 2);
+        // Frames 3 to 5 are lost.
+        push(&buffer, make_tone_frame(6));
+        let out = pull(&buffer, 1920 * 4).unwrap().data().to_vec();
+        let peak = |range: std::ops::Range<usize>| {
+            out[range].iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+        };
+
+        let lost = [peak(0..1920), peak(1920..3840), peak(3840..5760)];
+        assert!(lost[0] > lost[1] && lost[1] > 0.0, "fades over two frames");
+        assert_eq!(lost[2], 0.0, "then silence");
+        assert!(peak(5760..7680) > 0.4, "frame 6 plays as is");
+    }
+
+    #[test]
+    fn test_recovery_is_asked_only_for_lost_frames() {
+        let asked = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let buffer = TestBuffer::new(16).with_recovery({
+            let asked = asked.clone();
+            move |seq, frame_size| {
+                asked.lock().unwrap().push((seq, frame_size));
+                Some(vec![0.25; frame_size])
+            }
+        });
+        // Frame 2 arrives after 4, and 3 never does.
+        push(&buffer, make_frame(1, 1920));
+        push(&buffer, make_frame(4, 1920));
+        push(&buffer, make_frame(2, 1920));
+        let out = pull(&buffer, 1920 * 4).unwrap().into_inner();
+
+        assert_eq!(*asked.lock().unwrap(), [(3, 1920)]);
+        assert_eq!(out[1920..3840], *make_frame(2, 1920).samples.data());
+        assert!(out[3840..5760].iter().all(|&s| s == 0.25));
+        assert_eq!(out[5760..], *make_frame(4, 1920).samples.data());
+    }
+
     #[test]
     fn test_pull_empty_buffer_returns_silence() {
         let buffer = TestBuffer::new(16);
@@ -779,6 +1324,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_pull_survives_empty_collection() {
+        let buffer = TestBuffer::new(16);
+
+        // collect_samples yields None when it gathers nothing; pull must not
+        // unwrap that but fill the request with silence.
+        let collected = buffer.collect_samples(0);
+        assert!(collected.is_none());
+        let samples = TestBuffer::samples_or_silence(collected, 960);
+        assert_eq!(samples.len(), 960);
+        assert!(samples.iter().all(|&x| x == 0.0));
+
+        for len in [1, 2, 960, 1920, 4000] {
+            let pulled = pull(&buffer, len).expect("pull should never fail");
+            assert_eq!(pulled.data().len(), len);
+        }
+    }
+
     #[test]
     fn test_continuous_push_pull_data_integrity() {
         let buffer = TestBuffer::new(16);
@@ -854,6 +1417,28 @@ mod tests {
         assert!(pulled3.is_some());
     }
 
+    #[test]
+    fn test_missing_seqs_lists_holes_ahead_of_the_reader() {
+        let buffer = TestBuffer::new(16);
+        assert!(buffer.missing_seqs().is_empty());
+
+        push(&buffer, make_frame(1, 1920));
+        push(&buffer, make_frame(2, 1920));
+        push(&buffer, make_frame(4, 1920));
+        assert_eq!(buffer.missing_seqs(), [3]);
+
+        // Reading past a hole gives up on it.
+        for _ in 0..3 {
+            pull(&buffer, 1920);
+        }
+        assert!(buffer.missing_seqs().is_empty());
+
+        push(&buffer, make_frame(7, 1920));
+        assert_eq!(buffer.missing_seqs(), [5, 6]);
+        push(&buffer, make_frame(5, 1920));
+        assert_eq!(buffer.missing_seqs(), [6]);
+    }
+
     #[test]
     fn test_underrun_holds_back() {
         let buffer = TestBuffer::new(16);
@@ -877,4 +1462,48 @@ mod tests {
             "read_seq should not advance on underrun"
         );
     }
+
+    #[test]
+    fn test_reconnect_with_newer_timestamps_starts_new_epoch() {
+        const FRAME_US: u64 = 20_000;
+        const RECONNECT_GAP_US: u64 = 5_000_000;
+
+        let run = |tolerance: Option<Duration>| {
+            let buffer = TestBuffer::new(32).with_epoch_tolerance(tolerance);
+            for seq in 500..510 {
+                push(&buffer, make_frame_at(seq, seq * FRAME_US));
+                pull(&buffer, 1920);
+            }
+
+            // The sender reconnects and counts from 1 again.
+            let restart = 510 * FRAME_US + RECONNECT_GAP_US;
+            for seq in 1..4 {
+                push(&buffer, make_frame_at(seq, restart + seq * FRAME_US));
+            }
+            pull(&buffer, 1920).unwrap()
+        };
+
+        let pulled = run(Some(Duration::from_millis(500)));
+        assert_eq!(pulled.data()[0], 1.0, "first frame of the new epoch plays");
+
+        let pulled = run(None);
+        assert!(
+            pulled.data().iter().all(|&s| s == 0.0),
+            "without a tolerance the frames are dropped as late"
+        );
+    }
+
+    #[test]
+    fn test_late_frame_within_tolerance_is_dropped() {
+        let buffer = TestBuffer::new(32).with_epoch_tolerance(Some(Duration::from_millis(500)));
+        for seq in 500..510 {
+            push(&buffer, make_frame_at(seq, seq * 20_000));
+        }
+        let read_before = buffer.read_seq.load(Ordering::Acquire);
+
+        push(&buffer, make_frame_at(400, 400 * 20_000));
+
+        assert_eq!(buffer.read_seq.load(Ordering::Acquire), read_before);
+        assert_eq!(buffer.write_seq.load(Ordering::Acquire), 509);
+    }
 }
@@ -3,13 +3,16 @@
 //! This module provides buffer types for audio processing pipelines:
 //!
 //! - [`SimpleBuffer`] - A simple FIFO buffer for audio samples
+//! - [`MonitorBuffer`] - A FIFO with bounded delay for monitoring the local mic
 //! - [`AudioBatcher`] - Batches audio samples to reduce packet frequency
 //! - [`JitterBuffer`] - Reorders out-of-order frames with adaptive latency control
 
 pub mod audio_batcher;
 pub mod jitter_buffer;
+pub mod monitor_buffer;
 pub mod simple_buffer;
 
 pub use audio_batcher::AudioBatcher;
-pub use jitter_buffer::{JitterBuffer, PullSnapshot};
+pub use jitter_buffer::{Concealment, JitterBuffer, PullSnapshot};
+pub use monitor_buffer::MonitorBuffer;
 pub use simple_buffer::SimpleBuffer;
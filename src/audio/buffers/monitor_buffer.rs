@@ -0,0 +1,69 @@
+//! A minimal-latency buffer for monitoring the local mic.
+//!
+//! Hearing yourself late is worse than not hearing yourself at all, so the
+//! monitor path never waits for audio to build up the way remote streams
+//! do in their jitter buffers. [`MonitorBuffer`] only bridges the mic and
+//! speaker callbacks, which run on their own clocks, and drops whatever is
+//! left over beyond a small backlog when the next mic block arrives.
+
+use crate::audio::AudioSample;
+use crate::audio::frame::AudioBuffer;
+use crate::pipeline::{Pullable, Pushable};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Default backlog kept between mic blocks.
+pub const DEFAULT_MONITOR_BACKLOG: Duration = Duration::from_millis(10);
+
+/// A FIFO between the mic and the speaker whose delay stays within one mic
+/// block plus `max_backlog`.
+///
+/// Unlike [`SimpleBuffer`](super::SimpleBuffer), which keeps everything
+/// pushed, audio the speaker hasn't pulled by the time the next mic block
+/// arrives is trimmed to the newest `max_backlog`. A speaker that runs
+/// slower than the mic thus never lets the delay grow.
+pub struct MonitorBuffer<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    queue: Mutex<VecDeque<Sample>>,
+    max_backlog_samples: usize,
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    MonitorBuffer<Sample, CHANNELS, SAMPLE_RATE>
+{
+    pub fn new(max_backlog: Duration) -> Self {
+        let frames = (max_backlog.as_secs_f64() * SAMPLE_RATE as f64).round() as usize;
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+            max_backlog_samples: frames * CHANNELS,
+        }
+    }
+}
+
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    Pushable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>
+    for MonitorBuffer<Sample, CHANNELS, SAMPLE_RATE>
+{
+    fn push(&self, input: AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>) {
+        let mut queue = self.queue.lock().unwrap();
+        let stale = queue.len().saturating_sub(self.max_backlog_samples);
+        queue.drain(..stale);
+        queue.extend(input.into_inner());
+    }
+}
+
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>
+    for MonitorBuffer<Sample, CHANNELS, SAMPLE_RATE>
+{
+    fn pull(&self, len: usize) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.is_empty() {
+            return None;
+        }
+
+        let actual_len = len.min(queue.len());
+        let samples: Vec<Sample> = queue.drain(..actual_len).collect();
+        AudioBuffer::new(samples).ok()
+    }
+}
@@ -0,0 +1,58 @@
+//! Conversion of interleaved PCM between channel counts.
+//!
+//! The whole pipeline runs at one compile-time `CHANNELS`, but a peer may
+//! send with another, e.g. a phone's mono mic into a stereo party. Decoded
+//! audio is brought to the local layout with [`adapt_channels`].
+
+use crate::audio::AudioSample;
+
+/// Converts interleaved `data` from `from` channels to `to`.
+///
+/// Mono is duplicated onto every output channel, and anything mixed down
+/// to mono becomes the arithmetic mean of each frame. Between other counts
+/// the leading channels are kept and missing ones are silent. A trailing
+/// partial frame is dropped.
+pub fn adapt_channels<Sample: AudioSample>(data: &[Sample], from: usize, to: usize) -> Vec<Sample> {
+    if from == to || from == 0 || to == 0 {
+        return data.to_vec();
+    }
+    let mut out = Vec::with_capacity(data.len() / from * to);
+    for frame in data.chunks_exact(from) {
+        if from == 1 {
+            out.extend(std::iter::repeat_n(frame[0], to));
+        } else if to == 1 {
+            let sum = frame.iter().map(|s| s.to_i64_for_mix()).sum();
+            out.push(Sample::from_i64_mixed(sum, from));
+        } else {
+            out.extend((0..to).map(|ch| frame.get(ch).copied().unwrap_or(Sample::silence())));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mono_is_duplicated_to_stereo() {
+        let mono = [0.1f32, -0.2, 0.3];
+        assert_eq!(
+            adapt_channels(&mono, 1, 2),
+            [0.1, 0.1, -0.2, -0.2, 0.3, 0.3]
+        );
+    }
+
+    #[test]
+    fn stereo_is_averaged_to_mono() {
+        let stereo = [0.2f32, 0.4, -0.5, 0.5, 1.0, 0.0];
+        let mono = adapt_channels(&stereo, 2, 1);
+        assert_eq!(mono.len(), 3);
+        for (got, want) in mono.iter().zip([0.3, 0.0, 0.5]) {
+            assert!((got - want).abs() < 1e-6, "{got} != {want}");
+        }
+
+        let stereo = [1000i16, 3001, -32768, -32768];
+        assert_eq!(adapt_channels(&stereo, 2, 1), [2000, -32768]);
+    }
+}
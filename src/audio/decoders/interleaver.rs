@@ -7,7 +7,7 @@ use std::marker::PhantomData;
 
 use super::symphonia_decoder::DecodedAudio;
 use crate::audio::AudioSample;
-use crate::audio::frame::AudioBuffer;
+use crate::audio::frame::{AudioBuffer, interleave};
 use crate::pipeline::Node;
 
 /// Interleaves per-channel decoded PCM into `AudioBuffer<Sample>`.
@@ -39,14 +39,26 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
         if num_frames == 0 {
             return None;
         }
-
-        let mut interleaved = Vec::with_capacity(num_frames * CHANNELS);
-        for f in 0..num_frames {
-            for ch in 0..CHANNELS {
-                interleaved.push(Sample::from_f64_normalized(input.channels[ch][f] as f64));
-            }
+        if input.channels.len() != CHANNELS {
+            tracing::warn!(
+                "Interleaver: got {} channels, expected {}",
+                input.channels.len(),
+                CHANNELS
+            );
+            return None;
         }
 
-        AudioBuffer::new(interleaved).ok()
+        let interleaved = match interleave(&input.channels) {
+            Ok(interleaved) => interleaved,
+            Err(e) => {
+                tracing::warn!("Interleaver: {}", e);
+                return None;
+            }
+        };
+        let samples = interleaved
+            .into_iter()
+            .map(|s| Sample::from_f64_normalized(s as f64))
+            .collect();
+        AudioBuffer::new(samples).ok()
     }
 }
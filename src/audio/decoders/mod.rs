@@ -5,13 +5,16 @@
 //! - [`SymphoniaDecoder`] — decodes compressed packets to per-channel f32 PCM
 //! - [`Interleaver`] — interleaves decoded PCM to AudioBuffer (no resampling)
 //! - [`FftResampler`] — resamples decoded PCM to target sample rate, or passes through when rates match
+//! - [`WireDecompressor`] — undoes lossless wire compression of PCM packets
 
 pub mod compressed_packet_queue;
 pub mod fft_resampler;
 pub mod interleaver;
+pub mod pcm_compression;
 pub mod symphonia_decoder;
 
 pub use compressed_packet_queue::{CompressedPacket, PacketCounter};
 pub use fft_resampler::FftResampler;
 pub use interleaver::Interleaver;
+pub use pcm_compression::{WireCompression, WireDecompressor, compress_for_wire};
 pub use symphonia_decoder::{DecodedAudio, SymphoniaDecoder};
@@ -0,0 +1,400 @@
+//! Lossless wire compression for PCM (WAV) synced-stream packets.
+//!
+//! WAV sources are shared packet-for-packet like any other file, which means
+//! raw PCM on the wire. Re-encoding to Opus would be lossy and stateful, so
+//! instead each packet is compressed on its own with a fixed second-order
+//! predictor and Rice-coded residuals (the same idea as FLAC's fixed
+//! subframes). Packets stay independent, so loss and retransmission work
+//! exactly as for uncompressed packets.
+//!
+//! Packet layout:
+//! - `u8` mode: stored (original bytes follow) or Rice
+//! - Rice mode: `u32` LE frame count, then per channel an 8-bit Rice
+//!   parameter followed by that channel's residuals, all in one bitstream.
+
+use rkyv::{Archive, Deserialize, Serialize};
+
+use super::compressed_packet_queue::CompressedPacket;
+use crate::audio::symphonia_compat::{WireCodecParams, WireCodecType};
+use crate::pipeline::Node;
+
+const MODE_STORED: u8 = 0;
+const MODE_RICE: u8 = 1;
+
+/// Quotients at or above this are escaped and written as raw bits, so noisy
+/// input can't blow up into huge unary runs.
+const ESCAPE_QUOTIENT: u64 = 24;
+/// Wide enough for any zigzagged order-2 residual of 32-bit PCM.
+const ESCAPE_BITS: u32 = 40;
+const MAX_RICE_PARAM: u32 = 32;
+
+/// How synced-stream packets are compressed on the wire.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[rkyv(compare(PartialEq))]
+pub enum WireCompression {
+    /// Packets are sent exactly as read from the container.
+    #[default]
+    None,
+    /// Integer PCM packets are compressed with [`compress_pcm`].
+    PcmRice,
+}
+
+impl WireCodecType {
+    /// Bytes per sample for integer PCM codecs that [`compress_pcm`] supports.
+    pub fn pcm_bytes_per_sample(self) -> Option<usize> {
+        match self {
+            Self::PcmS16Le => Some(2),
+            Self::PcmS24Le => Some(3),
+            Self::PcmS32Le => Some(4),
+            _ => None,
+        }
+    }
+}
+
+/// Compresses one packet of interleaved little-endian signed PCM.
+///
+/// Falls back to storing the packet verbatim when it doesn't shrink or
+/// doesn't hold a whole number of frames.
+pub fn compress_pcm(data: &[u8], bytes_per_sample: usize, channels: usize) -> Vec<u8> {
+    let frame_bytes = bytes_per_sample * channels;
+    if channels == 0 || data.is_empty() || !data.len().is_multiple_of(frame_bytes) {
+        return stored(data);
+    }
+
+    let frames = data.len() / frame_bytes;
+    let mut writer = BitWriter::default();
+    for ch in 0..channels {
+        let residuals: Vec<u64> = (0..frames)
+            .map(|n| {
+                let sample = |i: usize| {
+                    read_sample(
+                        data,
+                        (i * channels + ch) * bytes_per_sample,
+                        bytes_per_sample,
+                    )
+                };
+                let predicted = match n {
+                    0 => 0,
+                    1 => sample(0),
+                    _ => 2 * sample(n - 1) - sample(n - 2),
+                };
+                zigzag(sample(n) - predicted)
+            })
+            .collect();
+
+        let k = rice_param(&residuals);
+        writer.write(k as u64, 8);
+        for &r in &residuals {
+            writer.write_rice(r, k);
+        }
+    }
+
+    let bits = writer.finish();
+    // Header is 5 bytes vs 1 for a stored packet.
+    if 4 + bits.len() >= data.len() {
+        return stored(data);
+    }
+
+    let mut out = Vec::with_capacity(5 + bits.len());
+    out.push(MODE_RICE);
+    out.extend_from_slice(&(frames as u32).to_le_bytes());
+    out.extend_from_slice(&bits);
+    out
+}
+
+/// Reverses [`compress_pcm`]. Returns `None` for malformed input.
+pub fn decompress_pcm(data: &[u8], bytes_per_sample: usize, channels: usize) -> Option<Vec<u8>> {
+    let (&mode, rest) = data.split_first()?;
+    match mode {
+        MODE_STORED => Some(rest.to_vec()),
+        MODE_RICE => {
+            let frames = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+            let mut reader = BitReader::new(&rest[4..]);
+            // Every residual takes at least one bit; reject frame counts the
+            // payload can't possibly hold before allocating for them.
+            if frames * channels > (rest.len() - 4) * 8 {
+                return None;
+            }
+            let mut out = vec![0u8; frames * channels * bytes_per_sample];
+
+            for ch in 0..channels {
+                let k = reader.read(8)? as u32;
+                if k > MAX_RICE_PARAM {
+                    return None;
+                }
+                let (mut prev1, mut prev2) = (0i64, 0i64);
+                for n in 0..frames {
+                    let predicted = match n {
+                        0 => 0,
+                        1 => prev1,
+                        _ => 2 * prev1 - prev2,
+                    };
+                    let sample = predicted + unzigzag(reader.read_rice(k)?);
+                    write_sample(
+                        &mut out,
+                        (n * channels + ch) * bytes_per_sample,
+                        bytes_per_sample,
+                        sample,
+                    );
+                    prev2 = prev1;
+                    prev1 = sample;
+                }
+            }
+            Some(out)
+        }
+        _ => None,
+    }
+}
+
+/// Compresses a packet read from the source according to `params.compression`.
+pub fn compress_for_wire(params: &WireCodecParams, data: Vec<u8>) -> Vec<u8> {
+    match (params.compression, params.codec.pcm_bytes_per_sample()) {
+        (WireCompression::PcmRice, Some(bytes_per_sample)) => {
+            compress_pcm(&data, bytes_per_sample, params.channels as usize)
+        }
+        _ => data,
+    }
+}
+
+/// Undoes [`compress_for_wire`] in front of a [`SymphoniaDecoder`].
+///
+/// Passes packets through untouched when the stream isn't compressed.
+///
+/// [`SymphoniaDecoder`]: super::SymphoniaDecoder
+pub struct WireDecompressor {
+    pcm: Option<(usize, usize)>,
+}
+
+impl WireDecompressor {
+    pub fn new(params: &WireCodecParams) -> Self {
+        let pcm = match params.compression {
+            WireCompression::None => None,
+            WireCompression::PcmRice => params
+                .codec
+                .pcm_bytes_per_sample()
+                .map(|bytes_per_sample| (bytes_per_sample, params.channels as usize)),
+        };
+        Self { pcm }
+    }
+}
+
+impl Node for WireDecompressor {
+    type Input = CompressedPacket;
+    type Output = CompressedPacket;
+
+    fn process(&self, input: CompressedPacket) -> Option<CompressedPacket> {
+        let Some((bytes_per_sample, channels)) = self.pcm else {
+            return Some(input);
+        };
+        match decompress_pcm(&input.data, bytes_per_sample, channels) {
+            Some(data) => Some(CompressedPacket {
+                dur: input.dur,
+                data,
+            }),
+            None => {
+                tracing::error!("Failed to decompress PCM packet");
+                None
+            }
+        }
+    }
+}
+
+fn stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(1 + data.len());
+    out.push(MODE_STORED);
+    out.extend_from_slice(data);
+    out
+}
+
+fn read_sample(data: &[u8], offset: usize, bytes_per_sample: usize) -> i64 {
+    let mut value = 0i64;
+    for i in 0..bytes_per_sample {
+        value |= (data[offset + i] as i64) << (8 * i);
+    }
+    // Sign-extend from the sample width.
+    let shift = 64 - 8 * bytes_per_sample as u32;
+    (value << shift) >> shift
+}
+
+fn write_sample(out: &mut [u8], offset: usize, bytes_per_sample: usize, sample: i64) {
+    for i in 0..bytes_per_sample {
+        out[offset + i] = (sample >> (8 * i)) as u8;
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// Picks a Rice parameter close to log2 of the mean residual.
+fn rice_param(residuals: &[u64]) -> u32 {
+    if residuals.is_empty() {
+        return 0;
+    }
+    let mean = residuals.iter().map(|&r| r as u128).sum::<u128>() / residuals.len() as u128;
+    (u128::BITS - mean.leading_zeros())
+        .saturating_sub(1)
+        .min(MAX_RICE_PARAM)
+}
+
+fn mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    /// Writes the low `n` bits of `value`, MSB first. `n` must be at most 56.
+    fn write(&mut self, value: u64, n: u32) {
+        self.acc = (self.acc << n) | (value & mask(n));
+        self.bits += n;
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.out.push((self.acc >> self.bits) as u8);
+        }
+        self.acc &= mask(self.bits);
+    }
+
+    fn write_rice(&mut self, value: u64, k: u32) {
+        let quotient = value >> k;
+        if quotient < ESCAPE_QUOTIENT {
+            // `quotient` ones, a terminating zero, then the low `k` bits.
+            self.write(mask(quotient as u32) << 1, quotient as u32 + 1);
+            self.write(value, k);
+        } else {
+            self.write(mask(ESCAPE_QUOTIENT as u32), ESCAPE_QUOTIENT as u32);
+            self.write(value, ESCAPE_BITS);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.out.push((self.acc << (8 - self.bits)) as u8);
+        }
+        self.out
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn read_bit(&mut self) -> Option<u64> {
+        let byte = *self.data.get(self.pos / 8)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(bit as u64)
+    }
+
+    fn read(&mut self, n: u32) -> Option<u64> {
+        let mut value = 0u64;
+        for _ in 0..n {
+            value = (value << 1) | self.read_bit()?;
+        }
+        Some(value)
+    }
+
+    fn read_rice(&mut self, k: u32) -> Option<u64> {
+        let mut quotient = 0u64;
+        while quotient < ESCAPE_QUOTIENT {
+            if self.read_bit()? == 0 {
+                return Some((quotient << k) | self.read(k)?);
+            }
+            quotient += 1;
+        }
+        self.read(ESCAPE_BITS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A few seconds' worth of a chord plus low-level noise, as 16-bit PCM.
+    fn music_like_s16(frames: usize, channels: usize) -> Vec<u8> {
+        let mut seed = 0x1234_5678u32;
+        let mut data = Vec::with_capacity(frames * channels * 2);
+        for n in 0..frames {
+            let t = n as f64 / 44_100.0;
+            for ch in 0..channels {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let noise = ((seed >> 16) as f64 / 65_536.0 - 0.5) * 200.0;
+                let tone = (t * 220.0 * std::f64::consts::TAU).sin() * 6_000.0
+                    + (t * 277.2 * std::f64::consts::TAU + ch as f64).sin() * 4_000.0
+                    + (t * 329.6 * std::f64::consts::TAU).sin() * 3_000.0;
+                let sample = (tone + noise).round() as i16;
+                data.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn pcm_roundtrip_is_lossless_and_smaller() {
+        let data = music_like_s16(4096, 2);
+        let compressed = compress_pcm(&data, 2, 2);
+        assert_eq!(compressed[0], MODE_RICE);
+        assert!(
+            compressed.len() < data.len() * 3 / 4,
+            "expected real savings, got {} -> {} bytes",
+            data.len(),
+            compressed.len()
+        );
+        assert_eq!(decompress_pcm(&compressed, 2, 2).unwrap(), data);
+    }
+
+    #[test]
+    fn extreme_values_roundtrip() {
+        for bytes_per_sample in [2usize, 3, 4] {
+            let bits = 8 * bytes_per_sample as u32;
+            let max = (1i64 << (bits - 1)) - 1;
+            let min = -(1i64 << (bits - 1));
+            let samples = [min, max, min, max, 0, min, min, max, max, -1, 1, min];
+            let mut data = vec![0u8; samples.len() * bytes_per_sample];
+            for (i, &s) in samples.iter().enumerate() {
+                write_sample(&mut data, i * bytes_per_sample, bytes_per_sample, s);
+            }
+
+            let compressed = compress_pcm(&data, bytes_per_sample, 2);
+            assert_eq!(
+                decompress_pcm(&compressed, bytes_per_sample, 2).unwrap(),
+                data,
+                "{bits}-bit roundtrip"
+            );
+        }
+    }
+
+    #[test]
+    fn partial_frames_are_stored() {
+        let data = vec![1u8, 2, 3];
+        let compressed = compress_pcm(&data, 2, 2);
+        assert_eq!(compressed[0], MODE_STORED);
+        assert_eq!(decompress_pcm(&compressed, 2, 2).unwrap(), data);
+    }
+
+    #[test]
+    fn truncated_packet_is_rejected() {
+        let data = music_like_s16(256, 2);
+        let compressed = compress_pcm(&data, 2, 2);
+        assert!(decompress_pcm(&compressed[..compressed.len() / 2], 2, 2).is_none());
+    }
+}
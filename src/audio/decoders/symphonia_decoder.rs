@@ -1,6 +1,6 @@
 use std::sync::Mutex;
 
-use symphonia::core::audio::{AudioBufferRef, Signal};
+use symphonia::core::audio::{AudioBufferRef, Channels, Signal};
 
 use super::compressed_packet_queue::CompressedPacket;
 use crate::pipeline::Node;
@@ -60,51 +60,178 @@ impl<const CHANNELS: usize> Node for SymphoniaDecoder<CHANNELS> {
 
 /// Extract per-channel f32 samples from symphonia's `AudioBufferRef`.
 ///
-/// Handles all common sample formats (f32, s16, s32, u8) with proper normalization.
-/// If the source has fewer channels than `CHANNELS`, channels wrap around (modulo).
+/// Handles all common sample formats (f32, s16, s32, u8) with proper normalization,
+/// then maps the source channel layout onto `CHANNELS` with [`remap_channels`].
 fn extract_f32_channels<const CHANNELS: usize>(decoded: &AudioBufferRef) -> Vec<Vec<f32>> {
-    let (num_frames, num_src_channels) = match decoded {
-        AudioBufferRef::F32(buf) => (buf.frames(), buf.spec().channels.count()),
-        AudioBufferRef::S16(buf) => (buf.frames(), buf.spec().channels.count()),
-        AudioBufferRef::S32(buf) => (buf.frames(), buf.spec().channels.count()),
-        AudioBufferRef::U8(buf) => (buf.frames(), buf.spec().channels.count()),
+    let layout = decoded.spec().channels;
+    let source: Vec<Vec<f32>> = match decoded {
+        AudioBufferRef::F32(buf) => (0..buf.spec().channels.count())
+            .map(|ch| buf.chan(ch).to_vec())
+            .collect(),
+        AudioBufferRef::S16(buf) => (0..buf.spec().channels.count())
+            .map(|ch| buf.chan(ch).iter().map(|&s| s as f32 / 32768.0).collect())
+            .collect(),
+        AudioBufferRef::S32(buf) => (0..buf.spec().channels.count())
+            .map(|ch| {
+                buf.chan(ch)
+                    .iter()
+                    .map(|&s| s as f32 / 2147483648.0)
+                    .collect()
+            })
+            .collect(),
+        AudioBufferRef::U8(buf) => (0..buf.spec().channels.count())
+            .map(|ch| {
+                buf.chan(ch)
+                    .iter()
+                    .map(|&s| (s as f32 - 128.0) / 128.0)
+                    .collect()
+            })
+            .collect(),
         _ => return vec![Vec::new(); CHANNELS],
     };
+    remap_channels::<CHANNELS>(source, layout)
+}
 
-    let mut channels: Vec<Vec<f32>> = (0..CHANNELS)
-        .map(|_| Vec::with_capacity(num_frames))
-        .collect();
+/// -3 dB, the ITU-R BS.775 weight for centre and surround channels.
+const MINUS_3DB: f32 = std::f32::consts::FRAC_1_SQRT_2;
 
-    match decoded {
-        AudioBufferRef::F32(buf) => {
-            for f in 0..num_frames {
-                for ch in 0..CHANNELS {
-                    channels[ch].push(buf.chan(ch % num_src_channels)[f]);
-                }
-            }
-        }
-        AudioBufferRef::S16(buf) => {
-            for f in 0..num_frames {
-                for ch in 0..CHANNELS {
-                    channels[ch].push(buf.chan(ch % num_src_channels)[f] as f32 / 32768.0);
-                }
-            }
-        }
-        AudioBufferRef::S32(buf) => {
-            for f in 0..num_frames {
-                for ch in 0..CHANNELS {
-                    channels[ch].push(buf.chan(ch % num_src_channels)[f] as f32 / 2147483648.0);
-                }
+/// Stereo (left, right) weights for one source speaker position.
+///
+/// Follows the ITU-R BS.775 downmix: front channels pass at unity, the
+/// centre and surrounds are folded in at -3 dB, and LFE is dropped.
+/// Positions the recommendation doesn't cover are treated as the nearest
+/// front, centre or surround speaker.
+fn stereo_gains(position: Channels) -> (f32, f32) {
+    let front_left = Channels::FRONT_LEFT
+        | Channels::FRONT_LEFT_CENTRE
+        | Channels::FRONT_LEFT_WIDE
+        | Channels::FRONT_LEFT_HIGH;
+    let front_right = Channels::FRONT_RIGHT
+        | Channels::FRONT_RIGHT_CENTRE
+        | Channels::FRONT_RIGHT_WIDE
+        | Channels::FRONT_RIGHT_HIGH;
+    let surround_left = Channels::REAR_LEFT
+        | Channels::SIDE_LEFT
+        | Channels::REAR_LEFT_CENTRE
+        | Channels::TOP_FRONT_LEFT
+        | Channels::TOP_REAR_LEFT;
+    let surround_right = Channels::REAR_RIGHT
+        | Channels::SIDE_RIGHT
+        | Channels::REAR_RIGHT_CENTRE
+        | Channels::TOP_FRONT_RIGHT
+        | Channels::TOP_REAR_RIGHT;
+    let lfe = Channels::LFE1 | Channels::LFE2;
+
+    if front_left.contains(position) {
+        (1.0, 0.0)
+    } else if front_right.contains(position) {
+        (0.0, 1.0)
+    } else if surround_left.contains(position) {
+        (MINUS_3DB, 0.0)
+    } else if surround_right.contains(position) {
+        (0.0, MINUS_3DB)
+    } else if lfe.contains(position) {
+        (0.0, 0.0)
+    } else {
+        // Front/rear/top centre
+        (MINUS_3DB, MINUS_3DB)
+    }
+}
+
+/// Maps decoded source channels onto the `CHANNELS`-wide output.
+///
+/// - Same channel count: passed through unchanged.
+/// - Mono source: copied to every output channel (mono → stereo upmix).
+/// - More channels into stereo: ITU-R downmix via [`stereo_gains`], using the
+///   speaker positions in `layout`. No normalization is applied, so loud
+///   surround content can exceed full scale; the sample conversion after
+///   resampling clamps it.
+/// - Anything else: source channels wrap around (modulo).
+fn remap_channels<const CHANNELS: usize>(source: Vec<Vec<f32>>, layout: Channels) -> Vec<Vec<f32>> {
+    let num_frames = source.first().map_or(0, |c| c.len());
+    if source.is_empty() {
+        return vec![Vec::new(); CHANNELS];
+    }
+    if source.len() == CHANNELS {
+        return source;
+    }
+    if source.len() == 1 {
+        return vec![source[0].clone(); CHANNELS];
+    }
+
+    let positions: Vec<Channels> = layout.iter().collect();
+    if CHANNELS == 2 && source.len() > 2 && positions.len() == source.len() {
+        let mut left = vec![0.0f32; num_frames];
+        let mut right = vec![0.0f32; num_frames];
+        for (samples, &position) in source.iter().zip(&positions) {
+            let (gain_l, gain_r) = stereo_gains(position);
+            for (f, &s) in samples.iter().enumerate() {
+                left[f] += s * gain_l;
+                right[f] += s * gain_r;
             }
         }
-        AudioBufferRef::U8(buf) => {
-            for f in 0..num_frames {
-                for ch in 0..CHANNELS {
-                    channels[ch].push((buf.chan(ch % num_src_channels)[f] as f32 - 128.0) / 128.0);
-                }
-            }
+        return vec![left, right];
+    }
+
+    (0..CHANNELS)
+        .map(|ch| source[ch % source.len()].clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use symphonia::core::audio::{AudioBuffer, SignalSpec};
+
+    fn layout_5_1() -> Channels {
+        Channels::FRONT_LEFT
+            | Channels::FRONT_RIGHT
+            | Channels::FRONT_CENTRE
+            | Channels::LFE1
+            | Channels::REAR_LEFT
+            | Channels::REAR_RIGHT
+    }
+
+    fn decoded_buffer(layout: Channels, values: &[f32], frames: usize) -> AudioBuffer<f32> {
+        let mut buf = AudioBuffer::<f32>::new(frames as u64, SignalSpec::new(48000, layout));
+        buf.render_reserved(Some(frames));
+        for (ch, &value) in values.iter().enumerate() {
+            buf.chan_mut(ch).fill(value);
         }
-        _ => {}
+        buf
+    }
+
+    #[test]
+    fn surround_downmix_uses_itu_coefficients() {
+        // FL, FR, FC, LFE, RL, RR: each channel gets a distinct level.
+        let values = [0.1, 0.2, 0.3, 0.9, 0.4, 0.5];
+        let buf = decoded_buffer(layout_5_1(), &values, 16);
+
+        let out = extract_f32_channels::<2>(&AudioBufferRef::F32(Cow::Borrowed(&buf)));
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].len(), 16);
+
+        let expected_l = 0.1 + MINUS_3DB * 0.3 + MINUS_3DB * 0.4;
+        let expected_r = 0.2 + MINUS_3DB * 0.3 + MINUS_3DB * 0.5;
+        assert!(out[0].iter().all(|&s| (s - expected_l).abs() < 1e-6));
+        assert!(out[1].iter().all(|&s| (s - expected_r).abs() < 1e-6));
+    }
+
+    #[test]
+    fn mono_upmixes_to_both_channels() {
+        let buf = decoded_buffer(Channels::FRONT_CENTRE, &[0.25], 8);
+
+        let out = extract_f32_channels::<2>(&AudioBufferRef::F32(Cow::Borrowed(&buf)));
+        assert_eq!(out, vec![vec![0.25; 8], vec![0.25; 8]]);
+    }
+
+    #[test]
+    fn stereo_passes_through() {
+        let layout = Channels::FRONT_LEFT | Channels::FRONT_RIGHT;
+        let buf = decoded_buffer(layout, &[0.5, -0.5], 4);
+
+        let out = extract_f32_channels::<2>(&AudioBufferRef::F32(Cow::Borrowed(&buf)));
+        assert_eq!(out, vec![vec![0.5; 4], vec![-0.5; 4]]);
     }
-    channels
 }
@@ -0,0 +1,174 @@
+//! Automatic gain control for the mic.
+
+use std::sync::Mutex;
+
+use super::level_meter::calculate_rms;
+use crate::audio::frame::AudioBuffer;
+use crate::audio::sample::AudioSample;
+use crate::pipeline::Node;
+
+/// Buffers quieter than this (about -60 dBFS) are taken as silence and
+/// leave the gain where it is, so pauses don't pull the noise floor up.
+const SILENCE_RMS: f64 = 0.001;
+
+/// Level the AGC aims for and how quickly it gets there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AgcConfig {
+    /// RMS level (0.0 - 1.0) the output is brought to.
+    pub target_rms: f64,
+    /// Time constant for lowering the gain when the input gets louder.
+    pub attack_ms: f64,
+    /// Time constant for raising the gain when the input gets quieter.
+    pub release_ms: f64,
+    /// Highest gain applied, so a distant singer isn't boosted into noise.
+    pub max_gain: f64,
+}
+
+impl Default for AgcConfig {
+    fn default() -> Self {
+        Self {
+            target_rms: 0.1,
+            attack_ms: 50.0,
+            release_ms: 150.0,
+            max_gain: 10.0,
+        }
+    }
+}
+
+/// Evens out the level of a singer leaning in and out from the mic.
+///
+/// Each buffer's RMS sets the gain that would bring it to the target, and
+/// the applied gain moves towards that in dB with the attack or release
+/// time constant, ramping across the buffer so it never steps. Since the
+/// gain follows buffer RMS rather than peaks and takes tens of
+/// milliseconds to move, a single loud transient barely dents it, so the
+/// level doesn't pump.
+///
+/// # Example
+///
+/// ```ignore
+/// let agc = Agc::<f32, 2, 48000>::new(AgcConfig::default());
+/// let pipeline = push_chain![agc, => encoder.clone()];
+/// ```
+pub struct Agc<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    config: AgcConfig,
+    /// Gain currently applied, in dB.
+    gain_db: Mutex<f64>,
+    _marker: std::marker::PhantomData<Sample>,
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Agc<Sample, CHANNELS, SAMPLE_RATE> {
+    pub fn new(config: AgcConfig) -> Self {
+        Self {
+            config,
+            gain_db: Mutex::new(0.0),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Gain currently applied (1.0 = unchanged).
+    pub fn gain(&self) -> f64 {
+        db_to_linear(*self.gain_db.lock().unwrap())
+    }
+}
+
+fn db_to_linear(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
+    for Agc<Sample, CHANNELS, SAMPLE_RATE>
+where
+    Sample: AudioSample,
+{
+    type Input = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
+    type Output = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
+
+    fn process(&self, mut input: Self::Input) -> Option<Self::Output> {
+        let frames = input.samples_per_channel();
+        if frames == 0 {
+            return Some(input);
+        }
+        let rms = calculate_rms(input.data());
+        let mut gain_db = self.gain_db.lock().unwrap();
+        let start = db_to_linear(*gain_db);
+
+        if rms >= SILENCE_RMS {
+            let wanted_db = 20.0
+                * (self.config.target_rms / rms)
+                    .min(self.config.max_gain)
+                    .log10();
+            let time_ms = if wanted_db < *gain_db {
+                self.config.attack_ms
+            } else {
+                self.config.release_ms
+            };
+            let elapsed_ms = frames as f64 * 1000.0 / SAMPLE_RATE as f64;
+            *gain_db +=
+                (wanted_db - *gain_db) * (1.0 - (-elapsed_ms / time_ms.max(f64::EPSILON)).exp());
+        }
+
+        let end = db_to_linear(*gain_db);
+        for (i, frame) in input.data_mut().chunks_mut(CHANNELS).enumerate() {
+            let gain = start + (end - start) * (i + 1) as f64 / frames as f64;
+            for sample in frame {
+                *sample = Sample::from_f64_normalized(sample.to_f64_normalized() * gain);
+            }
+        }
+
+        Some(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: u32 = 48000;
+
+    /// Feeds `ms` of a 440 Hz sine at `amplitude` through `agc` in 10 ms
+    /// buffers and returns the RMS of the last 100 ms it put out.
+    fn settled_rms(agc: &Agc<f32, 2, SR>, amplitude: f64, ms: usize) -> f64 {
+        let block = SR as usize / 100;
+        let mut output = Vec::new();
+        for b in 0..ms / 10 {
+            let samples: Vec<f32> = (b * block..(b + 1) * block)
+                .flat_map(|n| {
+                    let t = n as f64 / SR as f64;
+                    let s = (amplitude * (2.0 * std::f64::consts::PI * 440.0 * t).sin()) as f32;
+                    [s, s]
+                })
+                .collect();
+            output.extend(
+                agc.process(AudioBuffer::new(samples).unwrap())
+                    .unwrap()
+                    .into_inner(),
+            );
+        }
+        calculate_rms(&output[output.len() - 2 * SR as usize / 10..])
+    }
+
+    #[test]
+    fn quiet_and_loud_singers_converge_to_the_target() {
+        let config = AgcConfig::default();
+
+        let quiet = Agc::<f32, 2, SR>::new(config);
+        let rms = settled_rms(&quiet, 0.03, 800);
+        assert!(
+            (rms / config.target_rms - 1.0).abs() < 0.1,
+            "quiet sine settled at {rms:.4}"
+        );
+
+        let loud = Agc::<f32, 2, SR>::new(config);
+        let rms = settled_rms(&loud, 0.8, 300);
+        assert!(
+            (rms / config.target_rms - 1.0).abs() < 0.1,
+            "loud sine settled at {rms:.4}"
+        );
+
+        // A whisper far below the target only gets the maximum gain.
+        let far = Agc::<f32, 2, SR>::new(config);
+        settled_rms(&far, 0.002, 2000);
+        assert!((far.gain() - config.max_gain).abs() < 0.01 * config.max_gain);
+    }
+}
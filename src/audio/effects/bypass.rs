@@ -0,0 +1,79 @@
+//! Runtime bypass for any effect.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::pipeline::Node;
+
+/// Wraps an effect so it can be switched off without rebuilding the pipeline.
+///
+/// While the flag is set, buffers skip the inner effect and pass through
+/// bit-identical; clearing it puts the effect back in the path. Handy for
+/// A/B-ing processed against raw audio while tuning.
+///
+/// # Example
+///
+/// ```ignore
+/// let bypass = Arc::new(AtomicBool::new(false));
+/// let limiter = Bypass::new(PeakLimiter::<f32, 2, 48000>::new(0.9), bypass.clone());
+/// let pipeline = push_chain![limiter, => encoder.clone()];
+/// bypass.store(true, Ordering::Relaxed);
+/// ```
+pub struct Bypass<N> {
+    inner: N,
+    bypass: Arc<AtomicBool>,
+}
+
+impl<N> Bypass<N> {
+    pub fn new(inner: N, bypass: Arc<AtomicBool>) -> Self {
+        Self { inner, bypass }
+    }
+}
+
+impl<T, N> Node for Bypass<N>
+where
+    N: Node<Input = T, Output = T>,
+{
+    type Input = T;
+    type Output = T;
+
+    fn process(&self, input: T) -> Option<T> {
+        if self.bypass.load(Ordering::Relaxed) {
+            Some(input)
+        } else {
+            self.inner.process(input)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::effects::PeakLimiter;
+    use crate::audio::frame::AudioBuffer;
+
+    fn loud_block() -> AudioBuffer<f32, 2, 48000> {
+        let samples = (0..960)
+            .flat_map(|n| {
+                let s = (n as f32 * 0.05).sin();
+                [s, -s]
+            })
+            .collect();
+        AudioBuffer::new(samples).unwrap()
+    }
+
+    #[test]
+    fn bypassed_effect_leaves_buffer_untouched() {
+        let bypass = Arc::new(AtomicBool::new(true));
+        let limiter = Bypass::new(PeakLimiter::<f32, 2, 48000>::new(0.5), bypass.clone());
+        let input = loud_block();
+
+        let out = limiter.process(input.clone()).unwrap();
+        assert_eq!(out.data(), input.data());
+
+        bypass.store(false, Ordering::Relaxed);
+        let out = limiter.process(input.clone()).unwrap();
+        assert_ne!(out.data(), input.data());
+        assert!(out.data().iter().all(|s| s.abs() <= 0.5 + 1e-6));
+    }
+}
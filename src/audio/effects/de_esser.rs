@@ -0,0 +1,237 @@
+//! De-esser for harsh sibilance on vocals.
+
+use std::sync::Mutex;
+
+use crate::audio::frame::AudioBuffer;
+use crate::audio::sample::AudioSample;
+use crate::pipeline::Node;
+
+/// Compression ratio applied to the high band above the threshold.
+const RATIO: f64 = 4.0;
+const ATTACK_MS: f64 = 1.0;
+const RELEASE_MS: f64 = 60.0;
+
+/// Where the de-esser splits the signal and when it starts acting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeEsserConfig {
+    /// Crossover frequency; everything above it counts as sibilance.
+    pub frequency_hz: f64,
+    /// High-band envelope level (0.0 - 1.0) above which the band is
+    /// compressed.
+    pub threshold: f64,
+}
+
+impl Default for DeEsserConfig {
+    fn default() -> Self {
+        Self {
+            frequency_hz: 6000.0,
+            threshold: 0.05,
+        }
+    }
+}
+
+/// Second-order Butterworth section (RBJ cookbook), transposed direct form II.
+#[derive(Clone, Copy)]
+pub(super) struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    pub(super) fn butterworth(frequency_hz: f64, sample_rate: f64, highpass: bool) -> Self {
+        let w = 2.0 * std::f64::consts::PI * frequency_hz / sample_rate;
+        let cos = w.cos();
+        let alpha = w.sin() * std::f64::consts::FRAC_1_SQRT_2;
+        let b = if highpass {
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0]
+        } else {
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0]
+        };
+        let a0 = 1.0 + alpha;
+        Self {
+            b: b.map(|c| c / a0),
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    pub(super) fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// Per-channel crossover filters and high-band envelope.
+#[derive(Clone, Copy)]
+struct ChannelState {
+    lowpass: [Biquad; 2],
+    highpass: [Biquad; 2],
+    envelope: f64,
+}
+
+/// Tames sibilance by compressing only the high band.
+///
+/// Each channel is split by a 4th-order Linkwitz-Riley crossover, whose
+/// bands sum back to the input's magnitude at every frequency. The high
+/// band's envelope drives a fast compressor on that band alone, so low
+/// frequencies pass through at their original level.
+///
+/// # Example
+///
+/// ```ignore
+/// let de_esser = DeEsser::<f32, 2, 48000>::new(DeEsserConfig::default());
+/// let pipeline = push_chain![de_esser, => encoder.clone()];
+/// ```
+pub struct DeEsser<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    threshold: f64,
+    attack_coeff: f64,
+    release_coeff: f64,
+    state: Mutex<[ChannelState; CHANNELS]>,
+    _marker: std::marker::PhantomData<Sample>,
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> DeEsser<Sample, CHANNELS, SAMPLE_RATE> {
+    pub fn new(config: DeEsserConfig) -> Self {
+        let rate = SAMPLE_RATE as f64;
+        let smoothing = |ms: f64| 1.0 - (-1000.0 / (ms * rate)).exp();
+        let lowpass = Biquad::butterworth(config.frequency_hz, rate, false);
+        let highpass = Biquad::butterworth(config.frequency_hz, rate, true);
+        let channel = ChannelState {
+            lowpass: [lowpass; 2],
+            highpass: [highpass; 2],
+            envelope: 0.0,
+        };
+        Self {
+            threshold: config.threshold.max(f64::EPSILON),
+            attack_coeff: smoothing(ATTACK_MS),
+            release_coeff: smoothing(RELEASE_MS),
+            state: Mutex::new([channel; CHANNELS]),
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
+    for DeEsser<Sample, CHANNELS, SAMPLE_RATE>
+where
+    Sample: AudioSample,
+{
+    type Input = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
+    type Output = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
+
+    fn process(&self, mut input: Self::Input) -> Option<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+
+        for frame in input.data_mut().chunks_mut(CHANNELS) {
+            for (sample, channel) in frame.iter_mut().zip(state.iter_mut()) {
+                let x = sample.to_f64_normalized();
+                let low = channel
+                    .lowpass
+                    .iter_mut()
+                    .fold(x, |acc, filter| filter.process(acc));
+                let high = channel
+                    .highpass
+                    .iter_mut()
+                    .fold(x, |acc, filter| filter.process(acc));
+
+                let level = high.abs();
+                let coeff = if level > channel.envelope {
+                    self.attack_coeff
+                } else {
+                    self.release_coeff
+                };
+                channel.envelope += (level - channel.envelope) * coeff;
+
+                let gain = if channel.envelope > self.threshold {
+                    (self.threshold / channel.envelope).powf(1.0 - 1.0 / RATIO)
+                } else {
+                    1.0
+                };
+                *sample = Sample::from_f64_normalized(low + high * gain);
+            }
+        }
+
+        Some(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const SR: u32 = 48000;
+    const LOW_HZ: f64 = 300.0;
+    const HIGH_HZ: f64 = 9000.0;
+
+    /// Amplitude of the `freq` component of the left channel.
+    fn amplitude(samples: &[f32], freq: f64) -> f64 {
+        let left: Vec<f64> = samples.iter().step_by(2).map(|&s| s as f64).collect();
+        let (re, im) = left
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (n, s)| {
+                let phase = 2.0 * PI * freq * n as f64 / SR as f64;
+                (re + s * phase.cos(), im - s * phase.sin())
+            });
+        2.0 * (re * re + im * im).sqrt() / left.len() as f64
+    }
+
+    #[test]
+    fn sibilant_burst_is_reduced_and_lows_kept() {
+        let de_esser = DeEsser::<f32, 2, SR>::new(DeEsserConfig::default());
+        // 0.5 s of a low tone, with a loud high-frequency burst over it.
+        let input: Vec<f32> = (0..SR as usize / 2)
+            .flat_map(|n| {
+                let t = n as f64 / SR as f64;
+                let s = 0.3 * (2.0 * PI * LOW_HZ * t).sin() + 0.5 * (2.0 * PI * HIGH_HZ * t).sin();
+                [s as f32, s as f32]
+            })
+            .collect();
+
+        let output = de_esser
+            .process(AudioBuffer::new(input.clone()).unwrap())
+            .unwrap();
+        // Skip the first 10 ms while the envelope attacks.
+        let settle = 2 * SR as usize / 100;
+        let (input, output) = (&input[settle..], &output.data()[settle..]);
+
+        let high_in = amplitude(input, HIGH_HZ);
+        let high_out = amplitude(output, HIGH_HZ);
+        assert!(
+            high_out < high_in * 0.5,
+            "high band {high_out:.3} not reduced from {high_in:.3}"
+        );
+
+        let low_in = amplitude(input, LOW_HZ);
+        let low_out = amplitude(output, LOW_HZ);
+        assert!(
+            (low_out - low_in).abs() < low_in * 0.02,
+            "low band changed from {low_in:.3} to {low_out:.3}"
+        );
+    }
+
+    #[test]
+    fn quiet_highs_pass_at_full_level() {
+        let de_esser = DeEsser::<f32, 2, SR>::new(DeEsserConfig::default());
+        let input: Vec<f32> = (0..4800)
+            .flat_map(|n| {
+                let t = n as f64 / SR as f64;
+                let s = 0.3 * (2.0 * PI * LOW_HZ * t).sin() + 0.01 * (2.0 * PI * HIGH_HZ * t).sin();
+                [s as f32, s as f32]
+            })
+            .collect();
+        let output = de_esser
+            .process(AudioBuffer::new(input.clone()).unwrap())
+            .unwrap();
+        let settle = 2 * SR as usize / 100;
+        let (input, output) = (&input[settle..], &output.data()[settle..]);
+        for freq in [LOW_HZ, HIGH_HZ] {
+            let (before, after) = (amplitude(input, freq), amplitude(output, freq));
+            assert!((after - before).abs() < before * 0.01);
+        }
+    }
+}
@@ -9,9 +9,10 @@ use crate::pipeline::Node;
 
 const UPDATE_INTERVAL: u32 = 32;
 
-pub fn calculate_rms_level<Sample: AudioSample>(samples: &[Sample]) -> u32 {
+/// RMS of `samples` relative to full scale (0.0 - 1.0).
+pub fn calculate_rms<Sample: AudioSample>(samples: &[Sample]) -> f64 {
     if samples.is_empty() {
-        return 0;
+        return 0.0;
     }
     let sum_sq: f64 = samples
         .iter()
@@ -20,8 +21,11 @@ pub fn calculate_rms_level<Sample: AudioSample>(samples: &[Sample]) -> u32 {
             v * v
         })
         .sum();
-    let rms = (sum_sq / samples.len() as f64).sqrt();
-    (rms * 100.0).min(100.0) as u32
+    (sum_sq / samples.len() as f64).sqrt()
+}
+
+pub fn calculate_rms_level<Sample: AudioSample>(samples: &[Sample]) -> u32 {
+    (calculate_rms(samples) * 100.0).min(100.0) as u32
 }
 
 /// Given an Arc<AtomicU32>, it updates volume to the u32 100 times every second. Range: 0-100.
@@ -0,0 +1,147 @@
+//! Safety peak limiter for captured input.
+
+use std::sync::Mutex;
+
+use tracing::warn;
+
+use crate::audio::frame::AudioBuffer;
+use crate::audio::sample::AudioSample;
+use crate::pipeline::Node;
+
+/// Default ceiling, about -0.9 dBFS.
+pub const DEFAULT_LIMITER_CEILING: f64 = 0.9;
+/// Time for the gain to recover most of the way back to unity.
+const RELEASE_MS: f64 = 200.0;
+
+struct LimiterState {
+    gain: f64,
+    limiting: bool,
+}
+
+/// Keeps input peaks below a ceiling so clipped audio is never encoded.
+///
+/// Attack is instant: a frame whose peak would exceed the ceiling lowers the
+/// gain just enough to land on it. The gain then recovers exponentially
+/// towards unity. Below the ceiling with the gain fully recovered, samples
+/// pass through untouched.
+///
+/// # Example
+///
+/// ```ignore
+/// let limiter = PeakLimiter::<f32, 2, 48000>::new(DEFAULT_LIMITER_CEILING);
+/// let pipeline = push_chain![limiter, => encoder.clone()];
+/// ```
+pub struct PeakLimiter<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    ceiling: f64,
+    release_coeff: f64,
+    state: Mutex<LimiterState>,
+    _marker: std::marker::PhantomData<Sample>,
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    PeakLimiter<Sample, CHANNELS, SAMPLE_RATE>
+{
+    /// `ceiling` is the highest allowed peak (0.0 - 1.0); `f64::INFINITY`
+    /// turns the limiter into a pass-through.
+    pub fn new(ceiling: f64) -> Self {
+        let release_frames = SAMPLE_RATE as f64 * RELEASE_MS / 1000.0;
+        Self {
+            ceiling: ceiling.max(f64::EPSILON),
+            release_coeff: 1.0 - (-1.0 / release_frames).exp(),
+            state: Mutex::new(LimiterState {
+                gain: 1.0,
+                limiting: false,
+            }),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Gain currently applied (1.0 = not limiting).
+    pub fn gain(&self) -> f64 {
+        self.state.lock().unwrap().gain
+    }
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
+    for PeakLimiter<Sample, CHANNELS, SAMPLE_RATE>
+where
+    Sample: AudioSample,
+{
+    type Input = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
+    type Output = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
+
+    fn process(&self, mut input: Self::Input) -> Option<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        let mut engaged = false;
+
+        for frame in input.data_mut().chunks_mut(CHANNELS) {
+            let peak = frame
+                .iter()
+                .map(|s| s.to_f64_normalized().abs())
+                .fold(0.0, f64::max);
+
+            state.gain += (1.0 - state.gain) * self.release_coeff;
+            if peak * state.gain > self.ceiling {
+                state.gain = self.ceiling / peak;
+                engaged = true;
+            }
+
+            if state.gain < 1.0 {
+                for sample in frame {
+                    *sample = Sample::from_f64_normalized(sample.to_f64_normalized() * state.gain);
+                }
+            }
+        }
+
+        if engaged && !state.limiting {
+            warn!(
+                "Input is clipping, limiter reducing gain to {:.1} dB",
+                20.0 * state.gain.log10()
+            );
+        }
+        state.limiting = engaged;
+
+        Some(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Limiter = PeakLimiter<f32, 2, 48000>;
+
+    fn block(amplitude: f32) -> AudioBuffer<f32, 2, 48000> {
+        let samples = (0..960)
+            .flat_map(|n| {
+                let s = amplitude * (n as f32 * 0.05).sin();
+                [s, -s]
+            })
+            .collect();
+        AudioBuffer::new(samples).unwrap()
+    }
+
+    fn peak(buffer: &AudioBuffer<f32, 2, 48000>) -> f32 {
+        buffer.data().iter().fold(0.0, |m, s| m.max(s.abs()))
+    }
+
+    #[test]
+    fn clipping_input_stays_below_full_scale() {
+        let limiter = Limiter::new(DEFAULT_LIMITER_CEILING);
+        for _ in 0..10 {
+            let out = limiter.process(block(1.0)).unwrap();
+            assert!(peak(&out) <= DEFAULT_LIMITER_CEILING as f32 + 1e-6);
+            assert!(peak(&out) < 1.0);
+        }
+        assert!(limiter.gain() < 1.0);
+    }
+
+    #[test]
+    fn normal_input_passes_unchanged() {
+        let limiter = Limiter::new(DEFAULT_LIMITER_CEILING);
+        let input = block(0.5);
+        let out = limiter.process(input.clone()).unwrap();
+        assert_eq!(out.data(), input.data());
+        assert_eq!(limiter.gain(), 1.0);
+    }
+}
@@ -4,13 +4,34 @@
 //! Effects transform audio buffers in-place.
 #![allow(dead_code)]
 
+pub mod agc;
+pub mod bypass;
+pub mod chain;
+pub mod de_esser;
 pub mod gain;
+pub mod high_pass;
 pub mod level_meter;
+pub mod limiter;
 pub mod noise_gate;
+pub mod pitch_shift;
+pub mod ramp;
+pub mod reverb;
 pub mod switch;
+pub mod time_stretch;
 pub mod vocal_remover;
 
-pub use gain::Gain;
+pub use agc::{Agc, AgcConfig};
+pub use bypass::Bypass;
+pub use chain::EffectChain;
+pub use de_esser::{DeEsser, DeEsserConfig};
+pub use gain::{Gain, LiveGain, gain_cell, load_gain, store_gain};
+pub use high_pass::{DEFAULT_HIGH_PASS_HZ, HighPass};
 pub use level_meter::{LevelMeter, calculate_rms_level};
-pub use switch::Switch;
+pub use limiter::{DEFAULT_LIMITER_CEILING, PeakLimiter};
+pub use noise_gate::{NoiseGate, NoiseGateConfig};
+pub use pitch_shift::PitchShift;
+pub use ramp::FadeRamp;
+pub use reverb::{Reverb, ReverbConfig};
+pub use switch::{DEFAULT_MUTE_FADE, Switch};
+pub use time_stretch::TimeStretch;
 pub use vocal_remover::DecodedVocalRemover;
@@ -5,22 +5,59 @@ use crate::audio::sample::AudioSample;
 use crate::pipeline::Node;
 use std::collections::VecDeque;
 use std::sync::Mutex;
+use std::time::Duration;
+
+/// When the gate opens and closes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseGateConfig {
+    /// RMS level (0.0 - 1.0) at which a closed gate opens.
+    pub open_threshold: f64,
+    /// RMS level below which an open gate starts to close. Kept under
+    /// `open_threshold`, so a level hovering around either one doesn't make
+    /// the gate chatter.
+    pub close_threshold: f64,
+    /// How long the gate stays open after the level drops below
+    /// `close_threshold`, so it doesn't close on short pauses between words.
+    pub hold: Duration,
+    /// Fade when opening or closing, instead of a click.
+    pub fade: Duration,
+}
+
+impl Default for NoiseGateConfig {
+    fn default() -> Self {
+        Self {
+            open_threshold: 0.02,
+            close_threshold: 0.01,
+            hold: Duration::from_millis(150),
+            fade: Duration::from_millis(5),
+        }
+    }
+}
 
 struct NoiseGateState {
+    config: NoiseGateConfig,
     window: VecDeque<f64>,
     sum_sq: f64,
+    open: bool,
+    /// Frames the gate stays open for below the close threshold.
+    hold_left: usize,
+    /// Current gain, fading between 0 and 1.
+    gain: f64,
 }
 
-/// A stateful noise gate that silences samples based on RMS energy of a sliding window.
+/// A stateful noise gate that silences audio based on RMS energy of a
+/// sliding window.
+///
+/// It opens at one level and closes at a lower one, stays open for a hold
+/// time after the level drops, and fades in and out.
 ///
 /// # Example
 ///
 /// ```ignore
-/// let gate = NoiseGate::<f32, 2, 48000>::new(0.01, 1024);
+/// let gate = NoiseGate::<f32, 2, 48000>::new(NoiseGateConfig::default(), 1024);
 /// let pipeline = source.pipe(gate);
 /// ```
 pub struct NoiseGate<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
-    threshold: f64,
     window_size: usize,
     state: Mutex<NoiseGateState>,
     _marker: std::marker::PhantomData<Sample>,
@@ -29,17 +66,38 @@ pub struct NoiseGate<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
 impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32>
     NoiseGate<Sample, CHANNELS, SAMPLE_RATE>
 {
-    pub fn new(threshold: f64, window_size: usize) -> Self {
+    /// Gate measuring RMS over the last `window_size` samples.
+    pub fn new(config: NoiseGateConfig, window_size: usize) -> Self {
         Self {
-            threshold,
             window_size,
             state: Mutex::new(NoiseGateState {
+                config,
                 window: VecDeque::with_capacity(window_size),
                 sum_sq: 0.0,
+                open: false,
+                hold_left: 0,
+                gain: 0.0,
             }),
             _marker: std::marker::PhantomData,
         }
     }
+
+    /// Changes the thresholds, hold and fade from the next buffer on.
+    pub fn set_config(&self, config: NoiseGateConfig) {
+        self.state.lock().unwrap().config = config;
+    }
+
+    pub fn config(&self) -> NoiseGateConfig {
+        self.state.lock().unwrap().config
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state.lock().unwrap().open
+    }
+}
+
+fn frames(duration: Duration, sample_rate: u32) -> usize {
+    (duration.as_secs_f64() * sample_rate as f64).round() as usize
 }
 
 impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
@@ -52,32 +110,161 @@ where
 
     fn process(&self, mut input: Self::Input) -> Option<Self::Output> {
         let mut state = self.state.lock().unwrap();
+        let config = state.config;
+        let close_threshold = config.close_threshold.min(config.open_threshold);
+        let hold_frames = frames(config.hold, SAMPLE_RATE);
+        let fade_step = 1.0 / frames(config.fade, SAMPLE_RATE).max(1) as f64;
 
-        for sample in input.data_mut() {
-            let val = sample.to_f64_normalized();
-            let sq = val * val;
+        for frame in input.data_mut().chunks_mut(CHANNELS) {
+            for sample in frame.iter() {
+                let val = sample.to_f64_normalized();
+                let sq = val * val;
 
-            state.window.push_back(sq);
-            state.sum_sq += sq;
+                state.window.push_back(sq);
+                state.sum_sq += sq;
 
-            if state.window.len() > self.window_size
-                && let Some(old_sq) = state.window.pop_front()
-            {
-                state.sum_sq -= old_sq;
+                if state.window.len() > self.window_size
+                    && let Some(old_sq) = state.window.pop_front()
+                {
+                    state.sum_sq -= old_sq;
+                }
             }
 
             let count = state.window.len() as f64;
             let rms = if count > 0.0 {
-                (state.sum_sq / count).sqrt()
+                (state.sum_sq.max(0.0) / count).sqrt()
             } else {
                 0.0
             };
 
-            if rms < self.threshold {
-                *sample = Sample::silence();
+            if rms >= config.open_threshold || (state.open && rms >= close_threshold) {
+                state.open = true;
+                state.hold_left = hold_frames;
+            } else if state.open {
+                if state.hold_left > 0 {
+                    state.hold_left -= 1;
+                } else {
+                    state.open = false;
+                }
+            }
+
+            let target = if state.open { 1.0 } else { 0.0 };
+            state.gain = if state.gain < target {
+                (state.gain + fade_step).min(target)
+            } else {
+                (state.gain - fade_step).max(target)
+            };
+
+            if state.gain < 1.0 {
+                let gain = state.gain;
+                for sample in frame.iter_mut() {
+                    *sample = Sample::from_f64_normalized(sample.to_f64_normalized() * gain);
+                }
             }
         }
 
         Some(input)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: u32 = 48000;
+    /// 10 ms of stereo.
+    const BLOCK: usize = 2 * SR as usize / 100;
+
+    /// 10 ms buffer with an RMS of `level`.
+    fn block(level: f32) -> AudioBuffer<f32, 2, SR> {
+        let samples = (0..BLOCK)
+            .map(|n| if n % 4 < 2 { level } else { -level })
+            .collect();
+        AudioBuffer::new(samples).unwrap()
+    }
+
+    fn gate(hold_ms: u64) -> NoiseGate<f32, 2, SR> {
+        NoiseGate::new(
+            NoiseGateConfig {
+                open_threshold: 0.1,
+                close_threshold: 0.05,
+                hold: Duration::from_millis(hold_ms),
+                fade: Duration::from_millis(2),
+            },
+            BLOCK / 2,
+        )
+    }
+
+    #[test]
+    fn level_around_the_threshold_does_not_chatter() {
+        let gate = gate(0);
+        let mut transitions = 0;
+        let mut was_open = gate.is_open();
+        for n in 0..100 {
+            gate.process(block(if n % 2 == 0 { 0.11 } else { 0.09 }));
+            if gate.is_open() != was_open {
+                transitions += 1;
+                was_open = gate.is_open();
+            }
+        }
+        assert!(gate.is_open());
+        assert_eq!(transitions, 1);
+    }
+
+    #[test]
+    fn gate_holds_through_a_brief_dip() {
+        let gate = gate(100);
+        for _ in 0..20 {
+            gate.process(block(0.2));
+        }
+
+        // 50 ms of silence, shorter than the hold.
+        for _ in 0..5 {
+            gate.process(block(0.0));
+            assert!(gate.is_open());
+        }
+        let resumed = gate.process(block(0.2)).unwrap();
+        assert_eq!(resumed.data(), block(0.2).data(), "no fade after the dip");
+
+        // A pause longer than the hold closes it, fading out.
+        for _ in 0..20 {
+            gate.process(block(0.0));
+        }
+        assert!(!gate.is_open());
+        let reopened = gate.process(block(0.2)).unwrap();
+        assert!(gate.is_open());
+
+        // The input level is constant, so the output gives the gain of
+        // every frame. It rises in equal steps over the fade from the frame
+        // the gate opens on, once the RMS window has caught up.
+        let gains: Vec<f64> = reopened
+            .data()
+            .chunks(2)
+            .map(|frame| frame[0].abs() as f64 / 0.2)
+            .collect();
+        let opened_at = gains.iter().position(|&gain| gain > 0.0).unwrap();
+        let fade_frames = frames(Duration::from_millis(2), SR);
+        assert!(opened_at + fade_frames < gains.len());
+        for (n, &gain) in gains[opened_at..].iter().enumerate() {
+            let expected = ((n + 1) as f64 / fade_frames as f64).min(1.0);
+            assert!(
+                (gain - expected).abs() < 1e-4,
+                "gain {gain} {n} frames after opening, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn config_changes_at_runtime() {
+        let gate = gate(0);
+        gate.process(block(0.09));
+        assert!(!gate.is_open());
+
+        gate.set_config(NoiseGateConfig {
+            open_threshold: 0.08,
+            ..gate.config()
+        });
+        gate.process(block(0.09));
+        assert!(gate.is_open());
+    }
+}
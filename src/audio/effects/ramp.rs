@@ -0,0 +1,171 @@
+//! Linear gain ramp for smooth output transitions.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::audio::frame::AudioBuffer;
+use crate::audio::sample::AudioSample;
+use crate::pipeline::Node;
+
+struct RampState {
+    gain: f64,
+    target: f64,
+}
+
+/// Fades audio in or out linearly over a fixed duration.
+///
+/// Used on the speaker path so swapping output devices fades the old device
+/// out before it is torn down and fades the new one in once it starts,
+/// instead of cutting abruptly. At unity gain samples pass through untouched.
+///
+/// # Example
+///
+/// ```ignore
+/// let ramp = Arc::new(FadeRamp::<f32, 2, 48000>::silent(Duration::from_millis(50)));
+/// let speaker = pull_chain![mixer =>, ramp.clone()];
+/// ramp.fade_in();
+/// ```
+pub struct FadeRamp<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    /// Gain change per frame; infinite for a zero duration.
+    step: f64,
+    duration: Duration,
+    state: Mutex<RampState>,
+    _marker: std::marker::PhantomData<Sample>,
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    FadeRamp<Sample, CHANNELS, SAMPLE_RATE>
+{
+    /// A ramp starting at `gain` (0.0 - 1.0) that takes `duration` for a
+    /// full fade.
+    pub fn new(duration: Duration, gain: f64) -> Self {
+        let frames = duration.as_secs_f64() * SAMPLE_RATE as f64;
+        Self {
+            step: 1.0 / frames,
+            duration,
+            state: Mutex::new(RampState { gain, target: gain }),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// A ramp that starts muted, ready to fade in.
+    pub fn silent(duration: Duration) -> Self {
+        Self::new(duration, 0.0)
+    }
+
+    pub fn fade_in(&self) {
+        self.state.lock().unwrap().target = 1.0;
+    }
+
+    pub fn fade_out(&self) {
+        self.state.lock().unwrap().target = 0.0;
+    }
+
+    /// Time a full fade takes.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Gain reached after the last processed frame.
+    pub fn gain(&self) -> f64 {
+        self.state.lock().unwrap().gain
+    }
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
+    for FadeRamp<Sample, CHANNELS, SAMPLE_RATE>
+where
+    Sample: AudioSample,
+{
+    type Input = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
+    type Output = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
+
+    fn process(&self, mut input: Self::Input) -> Option<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        if state.gain == 1.0 && state.target == 1.0 {
+            return Some(input);
+        }
+
+        for frame in input.data_mut().chunks_mut(CHANNELS) {
+            state.gain = if state.gain < state.target {
+                (state.gain + self.step).min(state.target)
+            } else {
+                (state.gain - self.step).max(state.target)
+            };
+            for sample in frame {
+                *sample = Sample::from_f64_normalized(sample.to_f64_normalized() * state.gain);
+            }
+        }
+
+        Some(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Ramp = FadeRamp<f32, 2, 48000>;
+
+    fn ones(frames: usize) -> AudioBuffer<f32, 2, 48000> {
+        AudioBuffer::new(vec![1.0; frames * 2]).unwrap()
+    }
+
+    /// Left channel of `frames` of full-scale input through `ramp`.
+    fn envelope(ramp: &Ramp, frames: usize) -> Vec<f32> {
+        let out = ramp.process(ones(frames)).unwrap();
+        out.data().chunks(2).map(|frame| frame[0]).collect()
+    }
+
+    fn assert_fade(env: &[f32], ramp_frames: usize, from: f32, to: f32) {
+        let is_monotonic = env.windows(2).all(|pair| {
+            if to < from {
+                pair[1] <= pair[0]
+            } else {
+                pair[1] >= pair[0]
+            }
+        });
+        assert!(is_monotonic);
+        assert!((env[0] - from).abs() < 0.01);
+        assert!((env[ramp_frames / 2] - (from + to) / 2.0).abs() < 0.01);
+        assert!(env[ramp_frames..].iter().all(|&g| g == to));
+    }
+
+    #[test]
+    fn swap_fades_out_then_in_over_duration() {
+        for ms in [10, 50] {
+            let duration = Duration::from_millis(ms);
+            let ramp_frames = (48 * ms) as usize;
+
+            let old_output = Ramp::new(duration, 1.0);
+            assert_eq!(envelope(&old_output, 100), vec![1.0; 100]);
+            old_output.fade_out();
+            assert_fade(
+                &envelope(&old_output, ramp_frames * 2),
+                ramp_frames,
+                1.0,
+                0.0,
+            );
+
+            let new_output = Ramp::silent(duration);
+            assert!(envelope(&new_output, 100).iter().all(|&g| g == 0.0));
+            new_output.fade_in();
+            assert_fade(
+                &envelope(&new_output, ramp_frames * 2),
+                ramp_frames,
+                0.0,
+                1.0,
+            );
+            assert_eq!(new_output.gain(), 1.0);
+        }
+    }
+
+    #[test]
+    fn zero_duration_switches_immediately() {
+        let ramp = Ramp::new(Duration::ZERO, 1.0);
+        ramp.fade_out();
+        assert!(envelope(&ramp, 10).iter().all(|&g| g == 0.0));
+        ramp.fade_in();
+        assert!(envelope(&ramp, 10).iter().all(|&g| g == 1.0));
+    }
+}
@@ -0,0 +1,237 @@
+//! Freeverb-style room reverb.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::audio::frame::AudioBuffer;
+use crate::audio::sample::AudioSample;
+use crate::pipeline::Node;
+
+/// Comb filter lengths from Freeverb, in samples at 44.1 kHz.
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+/// Allpass filter lengths from Freeverb, in samples at 44.1 kHz.
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+/// Extra delay per channel so channels decorrelate into a wider image.
+const STEREO_SPREAD: usize = 23;
+const TUNING_RATE: usize = 44100;
+const ALLPASS_FEEDBACK: f64 = 0.5;
+/// Input attenuation so eight summed combs don't clip.
+const INPUT_GAIN: f64 = 0.015;
+const WET_SCALE: f64 = 3.0;
+
+/// Room size, damping and mix of a [`Reverb`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReverbConfig {
+    /// 0.0 (small room, short tail) - 1.0 (large hall).
+    pub room_size: f64,
+    /// How quickly highs die out in the tail, 0.0 - 1.0.
+    pub damping: f64,
+    /// Share of reverberated signal in the output; 0.0 is fully dry.
+    pub wet: f64,
+}
+
+impl Default for ReverbConfig {
+    fn default() -> Self {
+        Self {
+            room_size: 0.5,
+            damping: 0.5,
+            wet: 0.2,
+        }
+    }
+}
+
+struct Comb {
+    buffer: Vec<f64>,
+    index: usize,
+    filter_store: f64,
+}
+
+impl Comb {
+    fn process(&mut self, input: f64, feedback: f64, damp: f64) -> f64 {
+        let output = self.buffer[self.index];
+        self.filter_store = output * (1.0 - damp) + self.filter_store * damp;
+        self.buffer[self.index] = input + self.filter_store * feedback;
+        self.index = (self.index + 1) % self.buffer.len();
+        output
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f64>,
+    index: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f64) -> f64 {
+        let delayed = self.buffer[self.index];
+        self.buffer[self.index] = input + delayed * ALLPASS_FEEDBACK;
+        self.index = (self.index + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+struct ChannelState {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+/// Schroeder/Freeverb reverb: parallel damped combs into series allpasses.
+///
+/// Delay lengths are scaled from Freeverb's 44.1 kHz tuning to
+/// `SAMPLE_RATE`, and every channel keeps its own delay lines, offset
+/// slightly so the tail spreads across channels.
+///
+/// # Example
+///
+/// ```ignore
+/// let reverb = Reverb::<f32, 2, 48000>::new(ReverbConfig::default());
+/// let pipeline = push_chain![reverb, => encoder.clone()];
+/// ```
+pub struct Reverb<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    feedback: f64,
+    damp: f64,
+    wet: f64,
+    state: Mutex<Vec<ChannelState>>,
+    _marker: std::marker::PhantomData<Sample>,
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Reverb<Sample, CHANNELS, SAMPLE_RATE> {
+    pub fn new(config: ReverbConfig) -> Self {
+        let scaled = |samples: usize| (samples * SAMPLE_RATE as usize / TUNING_RATE).max(1);
+        let state = (0..CHANNELS)
+            .map(|channel| {
+                let spread = channel * STEREO_SPREAD;
+                ChannelState {
+                    combs: COMB_TUNING
+                        .iter()
+                        .map(|&len| Comb {
+                            buffer: vec![0.0; scaled(len + spread)],
+                            index: 0,
+                            filter_store: 0.0,
+                        })
+                        .collect(),
+                    allpasses: ALLPASS_TUNING
+                        .iter()
+                        .map(|&len| Allpass {
+                            buffer: vec![0.0; scaled(len + spread)],
+                            index: 0,
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Self {
+            feedback: 0.7 + 0.28 * config.room_size.clamp(0.0, 1.0),
+            damp: 0.4 * config.damping.clamp(0.0, 1.0),
+            wet: config.wet.clamp(0.0, 1.0),
+            state: Mutex::new(state),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Time for the undamped tail to fall by 60 dB (RT60), set by the
+    /// longest comb. Damping shortens the tail further.
+    pub fn decay_time(&self) -> Duration {
+        let longest = COMB_TUNING.iter().max().unwrap() * SAMPLE_RATE as usize / TUNING_RATE;
+        let seconds = 3.0 * longest as f64 / SAMPLE_RATE as f64 / -self.feedback.log10();
+        Duration::from_secs_f64(seconds)
+    }
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
+    for Reverb<Sample, CHANNELS, SAMPLE_RATE>
+where
+    Sample: AudioSample,
+{
+    type Input = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
+    type Output = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
+
+    fn process(&self, mut input: Self::Input) -> Option<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+
+        for frame in input.data_mut().chunks_mut(CHANNELS) {
+            for (sample, channel) in frame.iter_mut().zip(state.iter_mut()) {
+                let dry = sample.to_f64_normalized();
+                let input = dry * INPUT_GAIN;
+                let combed: f64 = channel
+                    .combs
+                    .iter_mut()
+                    .map(|comb| comb.process(input, self.feedback, self.damp))
+                    .sum();
+                let tail = channel
+                    .allpasses
+                    .iter_mut()
+                    .fold(combed, |acc, allpass| allpass.process(acc));
+
+                *sample = Sample::from_f64_normalized(
+                    dry * (1.0 - self.wet) + tail * WET_SCALE * self.wet,
+                );
+            }
+        }
+
+        Some(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: u32 = 48000;
+
+    fn impulse(seconds: usize) -> AudioBuffer<f32, 2, SR> {
+        let mut samples = vec![0.0; seconds * SR as usize * 2];
+        samples[0] = 1.0;
+        samples[1] = 1.0;
+        AudioBuffer::new(samples).unwrap()
+    }
+
+    #[test]
+    fn impulse_rings_out_over_decay_time() {
+        let reverb = Reverb::<f32, 2, SR>::new(ReverbConfig {
+            wet: 1.0,
+            ..Default::default()
+        });
+        let output = reverb.process(impulse(3)).unwrap();
+
+        // Energy of the left channel in 50 ms windows.
+        let window = SR as usize / 20;
+        let energy: Vec<f64> = output
+            .data()
+            .iter()
+            .step_by(2)
+            .map(|&s| (s as f64).powi(2))
+            .collect::<Vec<_>>()
+            .chunks(window)
+            .map(|chunk| chunk.iter().sum())
+            .collect();
+        let peak = energy.iter().cloned().fold(0.0, f64::max);
+        assert!(peak > 0.0);
+
+        // First window 60 dB below the loudest one.
+        let decayed = energy.iter().position(|&e| e < peak * 1e-6).unwrap();
+        let measured = decayed as f64 * 0.05;
+        let expected = reverb.decay_time().as_secs_f64();
+        assert!(
+            measured > expected * 0.5 && measured < expected * 1.5,
+            "tail lasted {measured:.2}s, expected about {expected:.2}s"
+        );
+        assert!(energy[decayed..].iter().all(|&e| e < peak * 1e-6));
+    }
+
+    #[test]
+    fn fully_dry_is_transparent() {
+        let reverb = Reverb::<f32, 2, SR>::new(ReverbConfig {
+            wet: 0.0,
+            ..Default::default()
+        });
+        let input: Vec<f32> = (0..9600).map(|n| (n as f32 * 0.01).sin() * 0.5).collect();
+        for _ in 0..3 {
+            let output = reverb
+                .process(AudioBuffer::new(input.clone()).unwrap())
+                .unwrap();
+            assert_eq!(output.data(), &input[..]);
+        }
+    }
+}
@@ -1,39 +1,149 @@
 //! Switch effect for conditionally passing or blocking audio.
 
 use crate::audio::frame::AudioBuffer;
+use crate::audio::sample::AudioSample;
 use crate::pipeline::Node;
-use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Fade used when muting and unmuting the transmitted streams: 5 ms at
+/// 48 kHz.
+pub const DEFAULT_MUTE_FADE: usize = 240;
+
+struct SwitchState {
+    /// The flag as last seen, to notice when it's toggled.
+    enabled: bool,
+    /// Current gain, fading between 0 and 1.
+    gain: f64,
+    target: f64,
+}
 
 /// Conditionally passes or blocks audio based on an AtomicBool flag.
 /// Passes audio when flag is true, blocks when false.
 /// When it's disabled, downstream get no data at all, not even silence data.
-#[derive(Clone)]
+///
+/// With a fade, turning the flag off first fades the audio out, so muting
+/// mid-word doesn't click, and only blocks once it's silent; turning it on
+/// fades back in. The fade starts when the flag changes, and carries over
+/// buffer boundaries.
 pub struct Switch<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     enabled: Arc<AtomicBool>,
+    /// Gain change per frame; infinite without a fade.
+    step: f64,
+    state: Mutex<SwitchState>,
     _marker: std::marker::PhantomData<Sample>,
 }
 
 impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Switch<Sample, CHANNELS, SAMPLE_RATE> {
     pub fn new(enabled: Arc<AtomicBool>) -> Self {
+        let gain = if enabled.load(Ordering::Acquire) {
+            1.0
+        } else {
+            0.0
+        };
         Self {
+            state: Mutex::new(SwitchState {
+                enabled: gain == 1.0,
+                gain,
+                target: gain,
+            }),
             enabled,
+            step: f64::INFINITY,
             _marker: std::marker::PhantomData,
         }
     }
+
+    /// Fades over `samples` per channel when toggled, instead of cutting.
+    pub fn with_fade(mut self, samples: usize) -> Self {
+        self.step = 1.0 / samples as f64;
+        self
+    }
 }
 
-impl<Sample: Send + Sync, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
     for Switch<Sample, CHANNELS, SAMPLE_RATE>
 {
     type Input = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
     type Output = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
 
-    fn process(&self, input: Self::Input) -> Option<Self::Output> {
-        if self.enabled.load(Ordering::Acquire) {
-            Some(input)
-        } else {
-            None
+    fn process(&self, mut input: Self::Input) -> Option<Self::Output> {
+        let enabled = self.enabled.load(Ordering::Acquire);
+        let mut state = self.state.lock().unwrap();
+        if enabled != state.enabled {
+            state.enabled = enabled;
+            state.target = if enabled { 1.0 } else { 0.0 };
+        }
+
+        if state.gain == state.target {
+            return if state.gain == 1.0 { Some(input) } else { None };
         }
+
+        for frame in input.data_mut().chunks_mut(CHANNELS) {
+            state.gain = if state.gain < state.target {
+                (state.gain + self.step).min(state.target)
+            } else {
+                (state.gain - self.step).max(state.target)
+            };
+            for sample in frame {
+                *sample = Sample::from_f64_normalized(sample.to_f64_normalized() * state.gain);
+            }
+        }
+
+        Some(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Mute = Switch<f32, 2, 48000>;
+
+    fn ones(frames: usize) -> AudioBuffer<f32, 2, 48000> {
+        AudioBuffer::new(vec![0.8; frames * 2]).unwrap()
+    }
+
+    #[test]
+    fn toggling_mid_fade_never_jumps() {
+        let enabled = Arc::new(AtomicBool::new(true));
+        let switch = Mute::new(enabled.clone()).with_fade(100);
+        let mut left = Vec::new();
+        let mut blocked = 0;
+        // 40-frame buffers, so fades span several of them. Mute mid-stream,
+        // unmute before the fade-out finishes, then mute for good.
+        for n in 0..20 {
+            match n {
+                3 | 9 => enabled.store(false, Ordering::Release),
+                5 => enabled.store(true, Ordering::Release),
+                _ => {}
+            }
+            match switch.process(ones(40)) {
+                Some(out) => left.extend(out.data().chunks(2).map(|frame| frame[0])),
+                None => blocked += 1,
+            }
+        }
+
+        let max_jump = left
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f32::max);
+        assert!(max_jump <= 0.8 / 100.0 + 1e-6, "jump of {max_jump}");
+        assert_eq!(left[..120], [0.8; 120], "untouched until muted");
+        assert!(
+            left.last().unwrap().abs() < 1e-6,
+            "faded out before blocking"
+        );
+        assert_eq!(blocked, 8);
+    }
+
+    #[test]
+    fn without_fade_blocks_at_once() {
+        let enabled = Arc::new(AtomicBool::new(true));
+        let switch = Mute::new(enabled.clone());
+        assert_eq!(switch.process(ones(10)).unwrap().data(), ones(10).data());
+        enabled.store(false, Ordering::Release);
+        assert!(switch.process(ones(10)).is_none());
+        enabled.store(true, Ordering::Release);
+        assert_eq!(switch.process(ones(10)).unwrap().data(), ones(10).data());
     }
 }
@@ -0,0 +1,251 @@
+//! Tempo change without pitch change (WSOLA).
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use crate::audio::frame::AudioBuffer;
+use crate::audio::sample::AudioSample;
+use crate::pipeline::Node;
+
+/// Grain length; output advances by half a grain per overlap-add.
+const GRAIN: Duration = Duration::from_millis(40);
+/// How far each grain may move from its nominal position to line up with
+/// the previous one.
+const TOLERANCE: Duration = Duration::from_millis(5);
+
+struct StretchState<const CHANNELS: usize> {
+    /// Input not yet consumed by a grain.
+    input: Vec<[f64; CHANNELS]>,
+    /// Nominal start of the next grain in `input`, advanced by the analysis
+    /// hop.
+    position: f64,
+    /// Second half of the last grain, faded out under the next one, and
+    /// where in `input` it came from.
+    tail: Option<(Vec<[f64; CHANNELS]>, usize)>,
+}
+
+impl<const CHANNELS: usize> StretchState<CHANNELS> {
+    fn new() -> Self {
+        Self {
+            input: Vec::new(),
+            position: 0.0,
+            tail: None,
+        }
+    }
+}
+
+/// Changes tempo without changing pitch by waveform-similarity overlap-add.
+///
+/// Grains are read from the input every `tempo × hop` frames but written
+/// every `hop` frames, so the output is `1 / tempo` times as long. Each
+/// grain is nudged within a few milliseconds to the offset that best
+/// continues the previous grain's waveform, which keeps tones free of
+/// phasing. At tempo 1.0 audio passes through untouched.
+///
+/// Output trails input by about one grain (40 ms). Because the stretch
+/// changes how long the song plays, anything mapping party-clock time to a
+/// position in the source has to scale by the tempo.
+///
+/// # Example
+///
+/// ```ignore
+/// let stretch = Arc::new(TimeStretch::<f32, 2, 48000>::new());
+/// stretch.set_tempo(0.8); // 80% speed
+/// let pipeline = push_chain![decoder, stretch.clone(), => buffer.clone()];
+/// ```
+pub struct TimeStretch<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    /// Tempo as `f32` bits; 1.0 is the original speed.
+    tempo: AtomicU32,
+    grain: usize,
+    tolerance: usize,
+    state: Mutex<StretchState<CHANNELS>>,
+    _marker: std::marker::PhantomData<Sample>,
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    TimeStretch<Sample, CHANNELS, SAMPLE_RATE>
+{
+    pub fn new() -> Self {
+        let frames = |d: Duration| (d.as_secs_f64() * SAMPLE_RATE as f64) as usize;
+        Self {
+            tempo: AtomicU32::new(1.0f32.to_bits()),
+            grain: frames(GRAIN) & !1,
+            tolerance: frames(TOLERANCE),
+            state: Mutex::new(StretchState::new()),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Sets the playback speed; 0.5 plays at half speed, twice as long.
+    pub fn set_tempo(&self, tempo: f32) {
+        self.tempo.store(tempo.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Forgets buffered input, e.g. after a seek.
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = StretchState::new();
+    }
+
+    /// Start of the grain near `nominal` whose first half best matches
+    /// `reference`.
+    fn best_offset(&self, input: &[[f64; CHANNELS]], nominal: usize, reference: usize) -> usize {
+        let half = self.grain / 2;
+        let correlation = |start: usize| -> f64 {
+            input[start..start + half]
+                .iter()
+                .zip(&input[reference..reference + half])
+                .map(|(a, b)| a.iter().sum::<f64>() * b.iter().sum::<f64>())
+                .sum()
+        };
+        (nominal.saturating_sub(self.tolerance)..=nominal + self.tolerance)
+            .map(|start| (start, correlation(start)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(nominal, |(start, _)| start)
+    }
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Default
+    for TimeStretch<Sample, CHANNELS, SAMPLE_RATE>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
+    for TimeStretch<Sample, CHANNELS, SAMPLE_RATE>
+where
+    Sample: AudioSample,
+{
+    type Input = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
+    type Output = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
+
+    fn process(&self, input: Self::Input) -> Option<Self::Output> {
+        let tempo = f32::from_bits(self.tempo.load(Ordering::Relaxed)) as f64;
+        if tempo == 1.0 {
+            return Some(input);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        state.input.extend(
+            input
+                .data()
+                .chunks(CHANNELS)
+                .map(|frame| std::array::from_fn(|ch| frame[ch].to_f64_normalized())),
+        );
+
+        let half = self.grain / 2;
+        let hop_in = half as f64 * tempo;
+        let mut output = Vec::new();
+        loop {
+            let nominal = state.position.round() as usize;
+            if nominal + self.tolerance + self.grain > state.input.len() {
+                break;
+            }
+            let start = match &state.tail {
+                Some((_, continuation)) => self.best_offset(&state.input, nominal, *continuation),
+                None => nominal,
+            };
+
+            let grain = &state.input[start..start + self.grain];
+            for (i, frame) in grain[..half].iter().enumerate() {
+                let fade_in = (std::f64::consts::FRAC_PI_2 * i as f64 / half as f64)
+                    .sin()
+                    .powi(2);
+                let previous = state.tail.as_ref().map_or([0.0; CHANNELS], |(t, _)| t[i]);
+                for ch in 0..CHANNELS {
+                    let mixed = previous[ch] * (1.0 - fade_in) + frame[ch] * fade_in;
+                    output.push(Sample::from_f64_normalized(mixed));
+                }
+            }
+            state.tail = Some((grain[half..].to_vec(), start + half));
+            state.position += hop_in;
+
+            // Drop input no later grain or continuation can reach.
+            let consumed = (state.position as usize)
+                .saturating_sub(self.tolerance)
+                .min(start + half);
+            if consumed > 0 {
+                state.input.drain(..consumed);
+                state.position -= consumed as f64;
+                if let Some((_, continuation)) = &mut state.tail {
+                    *continuation -= consumed;
+                }
+            }
+        }
+
+        if output.is_empty() {
+            None
+        } else {
+            AudioBuffer::new(output).ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const SR: u32 = 48000;
+
+    /// Amplitude of the `freq` component of the left channel.
+    fn amplitude(samples: &[f32], freq: f64) -> f64 {
+        let left: Vec<f64> = samples.iter().step_by(2).map(|&s| s as f64).collect();
+        let (re, im) = left
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (n, s)| {
+                let phase = 2.0 * PI * freq * n as f64 / SR as f64;
+                (re + s * phase.cos(), im - s * phase.sin())
+            });
+        2.0 * (re * re + im * im).sqrt() / left.len() as f64
+    }
+
+    fn tone(frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|n| {
+                let s = (0.5 * (2.0 * PI * 440.0 * n as f64 / SR as f64).sin()) as f32;
+                [s, s]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn half_tempo_doubles_length_keeps_pitch() {
+        let stretch = TimeStretch::<f32, 2, SR>::new();
+        stretch.set_tempo(0.5);
+        let input = tone(SR as usize / 2);
+
+        let mut output = Vec::new();
+        for chunk in input.chunks(960) {
+            if let Some(buf) = stretch.process(AudioBuffer::new(chunk.to_vec()).unwrap()) {
+                output.extend_from_slice(buf.data());
+            }
+        }
+
+        let ratio = output.len() as f64 / input.len() as f64;
+        assert!(
+            (1.8..=2.05).contains(&ratio),
+            "output is {ratio:.2}x the input"
+        );
+
+        // Skip the fade-in of the first grain.
+        let settled = &output[2 * stretch.grain..];
+        let dominant = (100..1000)
+            .step_by(10)
+            .max_by(|&a, &b| amplitude(settled, a as f64).total_cmp(&amplitude(settled, b as f64)))
+            .unwrap();
+        assert_eq!(dominant, 440);
+    }
+
+    #[test]
+    fn normal_tempo_is_transparent() {
+        let stretch = TimeStretch::<f32, 2, SR>::new();
+        let input = AudioBuffer::new(tone(4800)).unwrap();
+        let output = stretch.process(input.clone()).unwrap();
+        assert_eq!(output.data(), input.data());
+    }
+}
@@ -19,7 +19,7 @@ use std::sync::{Arc, Mutex};
 
 use crate::audio::AudioSample;
 use crate::audio::decoders::DecodedAudio;
-use crate::audio::frame::AudioBuffer;
+use crate::audio::frame::{AudioBuffer, deinterleave, interleave};
 use crate::pipeline::Node;
 
 #[cfg(not(feature = "vocal-removal"))]
@@ -234,14 +234,9 @@ impl<const CHANNELS: usize, const SAMPLE_RATE: u32> DecodedVocalRemover<CHANNELS
         if flushed.is_empty() {
             return None;
         }
-        let num_frames = flushed.len() / CHANNELS;
-        let mut channels = vec![Vec::with_capacity(num_frames); CHANNELS];
-        for frame in flushed.chunks_exact(CHANNELS) {
-            for ch in 0..CHANNELS {
-                channels[ch].push(frame[ch]);
-            }
-        }
-        Some(DecodedAudio { channels })
+        Some(DecodedAudio {
+            channels: deinterleave(&flushed, CHANNELS),
+        })
     }
 }
 
@@ -264,27 +259,18 @@ impl<const CHANNELS: usize, const SAMPLE_RATE: u32> Node
             return None;
         }
 
-        let mut interleaved = Vec::with_capacity(num_frames * CHANNELS);
-        for frame in 0..num_frames {
-            for channel in 0..CHANNELS {
-                interleaved.push(input.channels[channel][frame]);
-            }
+        if input.channels.len() != CHANNELS {
+            return None;
         }
+        let interleaved = interleave(&input.channels).ok()?;
 
         let mut state = self.state.lock().unwrap();
         state.process_interleaved(&interleaved);
         // debug!("DecodedVocalRemover::process draining interleaved");
         let output = state.drain_interleaved(num_frames * CHANNELS)?;
 
-        let mut channels = (0..CHANNELS)
-            .map(|_| Vec::with_capacity(num_frames))
-            .collect::<Vec<_>>();
-        for frame in output.chunks_exact(CHANNELS) {
-            for channel in 0..CHANNELS {
-                channels[channel].push(frame[channel]);
-            }
-        }
-
-        Some(DecodedAudio { channels })
+        Some(DecodedAudio {
+            channels: deinterleave(&output, CHANNELS),
+        })
     }
 }
@@ -0,0 +1,196 @@
+//! HTTP(S) streaming source.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use symphonia::core::io::MediaSource;
+use tracing::info;
+
+/// Reads an HTTP(S) response body as it downloads.
+///
+/// The body is consumed front to back and never buffered whole, so endless
+/// streams such as internet radio work. Seeking is not supported; symphonia
+/// falls back to forward-only reading. `byte_len` is the `Content-Length`
+/// when the server sends one, and `None` for open-ended streams.
+pub struct HttpSource {
+    /// Only ever accessed through `&mut self`; the mutex just makes the
+    /// source `Sync` as `MediaSource` requires.
+    response: Mutex<reqwest::blocking::Response>,
+    content_length: Option<u64>,
+}
+
+impl HttpSource {
+    /// Sends the request and waits for the response headers.
+    ///
+    /// Blocks the calling thread; don't call it from inside an async runtime.
+    pub fn open(url: &str) -> Result<Self> {
+        let response = reqwest::blocking::get(url)
+            .with_context(|| format!("Failed to request {url}"))?
+            .error_for_status()
+            .with_context(|| format!("Server rejected {url}"))?;
+        let content_length = response.content_length();
+        info!(
+            "Streaming {} ({})",
+            url,
+            content_length.map_or("unknown length".to_string(), |len| format!("{len} bytes"))
+        );
+
+        Ok(Self {
+            response: Mutex::new(response),
+            content_length,
+        })
+    }
+}
+
+impl Read for HttpSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.response
+            .get_mut()
+            .map_err(|_| std::io::Error::other("HTTP source poisoned"))?
+            .read(buf)
+    }
+}
+
+impl Seek for HttpSource {
+    fn seek(&mut self, _pos: SeekFrom) -> std::io::Result<u64> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "HTTP source is not seekable",
+        ))
+    }
+}
+
+impl MediaSource for HttpSource {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        self.content_length
+    }
+}
+
+/// Display name for a URL: the last path segment, or the whole URL when the
+/// path is empty. Its extension doubles as the format hint.
+pub fn url_file_name(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let without_scheme = path.split_once("://").map_or(path, |(_, rest)| rest);
+    match without_scheme.split_once('/') {
+        Some((_, path)) if !path.trim_end_matches('/').is_empty() => path
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or(path)
+            .to_string(),
+        _ => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Cursor, Write};
+    use std::net::TcpListener;
+
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::DecoderOptions;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+
+    fn wav_bytes() -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut cursor = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut cursor, spec).unwrap();
+        for n in 0..4410i32 {
+            let s = ((n as f32 * 0.07).sin() * 12000.0) as i16;
+            writer.write_sample(s).unwrap();
+            writer.write_sample(-s).unwrap();
+        }
+        writer.finalize().unwrap();
+        cursor.into_inner()
+    }
+
+    /// Serves `body` once over HTTP/1.1 and returns the URL.
+    fn serve_once(body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: audio/wav\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(&body).unwrap();
+        });
+        format!("http://{addr}/music/tone.wav?token=abc")
+    }
+
+    fn decode_all(source: Box<dyn MediaSource>, extension: &str) -> Vec<f32> {
+        let mss = MediaSourceStream::new(source, Default::default());
+        let mut hint = Hint::new();
+        hint.with_extension(extension);
+        let mut format = symphonia::default::get_probe()
+            .format(
+                &hint,
+                mss,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .unwrap()
+            .format;
+        let track = format.default_track().unwrap();
+        let track_id = track.id;
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .unwrap();
+
+        let mut pcm = Vec::new();
+        while let Ok(packet) = format.next_packet() {
+            if packet.track_id() != track_id {
+                continue;
+            }
+            let decoded = decoder.decode(&packet).unwrap();
+            let mut buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+            buf.copy_interleaved_ref(decoded);
+            pcm.extend_from_slice(buf.samples());
+        }
+        pcm
+    }
+
+    #[test]
+    fn remote_file_decodes_like_local_file() {
+        let data = wav_bytes();
+        let url = serve_once(data.clone());
+
+        assert_eq!(url_file_name(&url), "tone.wav");
+        assert_eq!(
+            url_file_name("http://radio.example/"),
+            "http://radio.example/"
+        );
+        let extension = "wav";
+
+        let remote = HttpSource::open(&url).unwrap();
+        assert_eq!(remote.byte_len(), Some(data.len() as u64));
+        assert!(!remote.is_seekable());
+
+        let remote_pcm = decode_all(Box::new(remote), extension);
+        let local_pcm = decode_all(Box::new(Cursor::new(data)), extension);
+        assert_eq!(remote_pcm.len(), 4410 * 2);
+        assert_eq!(remote_pcm, local_pcm);
+    }
+}
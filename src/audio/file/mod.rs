@@ -0,0 +1,13 @@
+//! Byte sources for symphonia.
+//!
+//! Shared music is read through symphonia's [`MediaSource`]. Local files are
+//! fully loaded and wrapped in a `Cursor`; the sources here cover the rest:
+//!
+//! - [`HttpSource`] - Streams an HTTP(S) URL, for direct file links and
+//!   internet radio
+//!
+//! [`MediaSource`]: symphonia::core::io::MediaSource
+
+pub mod http;
+
+pub use http::{HttpSource, url_file_name};
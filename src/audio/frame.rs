@@ -1,6 +1,39 @@
 use anyhow::Result;
 use rkyv::{Archive, Deserialize, Serialize};
 
+/// Interleaves one slice per channel (planar, as symphonia decodes) into
+/// frames of one sample per channel (as cpal and [`AudioBuffer`] store).
+///
+/// Returns an error if the channels differ in length.
+pub fn interleave<T: Copy>(channels: &[impl AsRef<[T]>]) -> Result<Vec<T>> {
+    let num_frames = channels.first().map_or(0, |c| c.as_ref().len());
+    if let Some(ch) = channels.iter().position(|c| c.as_ref().len() != num_frames) {
+        anyhow::bail!(
+            "Channel {} has {} samples, channel 0 has {}",
+            ch,
+            channels[ch].as_ref().len(),
+            num_frames
+        );
+    }
+    let mut interleaved = Vec::with_capacity(num_frames * channels.len());
+    for f in 0..num_frames {
+        interleaved.extend(channels.iter().map(|c| c.as_ref()[f]));
+    }
+    Ok(interleaved)
+}
+
+/// Splits interleaved samples into one vector per channel. A trailing
+/// partial frame is dropped.
+pub fn deinterleave<T: Copy>(data: &[T], channels: usize) -> Vec<Vec<T>> {
+    let mut planar = vec![Vec::with_capacity(data.len() / channels); channels];
+    for frame in data.chunks_exact(channels) {
+        for (channel, &sample) in planar.iter_mut().zip(frame) {
+            channel.push(sample);
+        }
+    }
+    planar
+}
+
 /// A type-safe audio buffer with compile-time channel count and sample rate.
 ///
 /// This structure ensures that audio processing logic (like channel iteration)
@@ -28,6 +61,31 @@ impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         Ok(Self { data })
     }
 
+    /// Create a buffer from planar data, one slice per channel.
+    ///
+    /// Returns an error unless there are exactly `CHANNELS` slices of equal length.
+    pub fn from_planar(channels: &[impl AsRef<[Sample]>]) -> Result<Self>
+    where
+        Sample: Copy,
+    {
+        if channels.len() != CHANNELS {
+            anyhow::bail!(
+                "Got {} planar channels, expected {}",
+                channels.len(),
+                CHANNELS
+            );
+        }
+        Self::new(interleave(channels)?)
+    }
+
+    /// Splits the samples into one vector per channel.
+    pub fn to_planar(&self) -> Vec<Vec<Sample>>
+    where
+        Sample: Copy,
+    {
+        deinterleave(&self.data, CHANNELS)
+    }
+
     /// Create a new audio buffer with `num_frames` frames, all samples initialized to zero.
     pub fn new_zeroed(num_frames: usize) -> Self
     where
@@ -179,6 +237,27 @@ mod tests {
         assert_eq!(right, vec![10, 20, 30]);
     }
 
+    #[test]
+    fn test_planar_roundtrip_keeps_channel_placement() {
+        let left = [1i16, 2, 3];
+        let right = [10i16, 20, 30];
+        let buffer = AudioBuffer::<i16, 2, 48000>::from_planar(&[left, right]).unwrap();
+
+        assert_eq!(buffer.data(), &[1, 10, 2, 20, 3, 30]);
+        assert_eq!(*buffer.get(1, 0), 2);
+        assert_eq!(*buffer.get(2, 1), 30);
+        assert_eq!(buffer.to_planar(), vec![left.to_vec(), right.to_vec()]);
+
+        let surround: Vec<Vec<i16>> = (0..6).map(|ch| vec![ch, ch + 100]).collect();
+        let buffer = AudioBuffer::<i16, 6, 48000>::from_planar(&surround).unwrap();
+        assert_eq!(&buffer.data()[..6], &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(&buffer.data()[6..], &[100, 101, 102, 103, 104, 105]);
+        assert_eq!(buffer.to_planar(), surround);
+
+        assert!(AudioBuffer::<i16, 2, 48000>::from_planar(&[left]).is_err());
+        assert!(AudioBuffer::<i16, 2, 48000>::from_planar(&[&left[..], &right[..2]]).is_err());
+    }
+
     #[test]
     fn test_audio_frame_validation() {
         let _valid_frame = AudioFrame::<i16, 2, 48000>::new(1, vec![0; 960]).unwrap();
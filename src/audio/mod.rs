@@ -9,30 +9,41 @@
 //!
 //! # Codec
 //! - [`opus`] - Opus codec with FEC for network transmission
+//! - [`channel_adapter`] - Converts a peer's channel layout to ours
 //!
 //! # Sources
-//! - [`file`] - Audio file decoding with symphonia
+//! - [`file`] - Byte sources for symphonia (HTTP streaming)
 //!
 //! # Buffers
 //! - [`buffers::SimpleBuffer`] - Simple FIFO buffer
+//! - [`buffers::MonitorBuffer`] - Bounded-delay FIFO for the local mic monitor
 //! - [`buffers::AudioBatcher`] - Batches samples to reduce packet frequency
 //! - [`buffers::JitterBuffer`] - Reorders out-of-order frames with adaptive latency
 //!
 //! # Effects
 //! - [`effects::gain`] - Volume control
-//! - [`effects::mute`] - Silence output
-//! - [`effects::noise_gate`] - RMS-based noise gate
+//! - [`effects::switch`] - Mutes or blocks a stream, fading to avoid clicks
+//! - [`effects::noise_gate`] - RMS-based noise gate with hysteresis and hold
 //! - [`effects::level_meter`] - Audio level metering
+//! - [`effects::high_pass`] - Removes DC offset and rumble from the mic
+//! - [`effects::agc`] - Evens out the mic level
+//! - [`effects::limiter`] - Safety peak limiter on captured input
+//! - [`effects::ramp`] - Fades the speaker around output device swaps
 
 pub mod buffers;
+pub mod channel_adapter;
 pub mod decoders;
 pub mod effects;
+pub mod file;
 pub mod frame;
 pub mod opus;
 pub mod sample;
 pub mod symphonia_compat;
 
-pub use buffers::{AudioBatcher, JitterBuffer, PullSnapshot, SimpleBuffer};
-pub use effects::{Gain, LevelMeter};
-pub use opus::{OpusEncoder, RealtimeFrameDecoder, RealtimeOpusFrame};
+pub use buffers::{AudioBatcher, JitterBuffer, MonitorBuffer, PullSnapshot, SimpleBuffer};
+pub use effects::{Gain, LevelMeter, LiveGain};
+pub use opus::{
+    AudioCodec, ChannelCoupling, OpusEncoder, OpusEncoderConfig, OpusFrameDuration,
+    RealtimeFrameDecoder, RealtimeOpusFrame,
+};
 pub use sample::AudioSample;
@@ -2,24 +2,105 @@
 //!
 //! This module provides Opus encoding and decoding for network transmission.
 //! Opus is configured with:
-//! - **Low latency**: Uses the "restricted lowdelay" application mode (CELT-only).
+//! - **Low latency**: Uses the "restricted lowdelay" application mode (CELT-only),
+//!   unless an [`OpusEncoderConfig`] tunes the encoder for voice or music.
 //!
-//! Note: In-band FEC is NOT available in CELT mode (only works with SILK/voice mode).
-//! Packet loss recovery relies on PLC (Packet Loss Concealment) instead.
-
+//! # Packet loss
+//!
+//! [`FecController`](crate::party::realtime_stream::FecController) turns the
+//! encoder's inband FEC on and sets the expected loss percentage from the
+//! loss measured by the jitter buffers. Each packet then also carries a
+//! low-bitrate copy of the frame before it, which the decoder uses to
+//! rebuild that frame when it was lost (see
+//! [`OpusDecoder::decode_with_fec`] and [`RealtimeFrameDecoder::recover`]).
+//! Whether a frame was lost is only known when the jitter buffer reaches
+//! its slot and finds it empty, so that's when it asks for the rebuild;
+//! frames that merely arrive out of order are played as they were sent.
+//!
+//! In-band FEC is NOT available in CELT mode (only works with SILK/voice
+//! mode), so the low-latency default carries no FEC data and the controller
+//! leaves it alone; select [`OpusSignal::Voice`] to use it. Without FEC data, loss recovery relies
+//! on PLC (Packet Loss Concealment), for at most [`DEFAULT_PLC_LIMIT`]
+//! frames in a row (see [`OpusDecoder::with_plc_limit`]) before falling
+//! back to silence. Realtime streams run PLC from the same playout-time
+//! check as FEC.
+//!
+//! # Channel coupling
+//!
+//! How the two channels of a stereo stream are coded is chosen per encoder
+//! with [`ChannelCoupling`]:
+//! - [`Joint`](ChannelCoupling::Joint), the default, lets Opus code the pair
+//!   together (mid-side and intensity stereo). Best quality per bit for
+//!   stereo music.
+//! - [`Mono`](ChannelCoupling::Mono) downmixes and codes a single channel,
+//!   like libopus' "force channels = 1". For a single mic or any source
+//!   without a stereo image; the full bitrate goes to one channel.
+//! - [`Independent`](ChannelCoupling::Independent) codes each channel as its
+//!   own mono stream, so nothing leaks between them. For dual-mono sources
+//!   such as two mics hard-panned left and right, which joint coding can
+//!   smear into each other.
+//!
+//! libopus has no control that turns mid-side coding off, so `Independent`
+//! packs one mono packet per channel into each packet and must be decoded
+//! by a decoder built with the same coupling. The other two produce plain
+//! Opus packets that any decoder plays.
+//!
+//! # Encoder settings
+//!
+//! Bitrate, complexity and signal type come from an [`OpusEncoderConfig`]
+//! and can all be changed while the encoder runs, e.g. to trade quality for
+//! bandwidth on a congested network. Bitrate and complexity take effect
+//! with the next packet. A different signal type recreates the libopus
+//! encoders, which costs a click, but the node and the stream it feeds go
+//! on as before.
+//!
+//! # Frame duration
+//!
+//! Each packet codes as much audio as it is given, which must be one of the
+//! durations in [`OpusFrameDuration`]. Senders batch to the duration
+//! configured for the stream: short frames for low-latency speech, long
+//! ones for more efficient music. Packets carry their sample count, so
+//! receivers follow whatever duration the sender chose.
+//!
+//! # Raw PCM
+//!
+//! On a quiet LAN the codec's own delay can matter more than bandwidth. An
+//! encoder set to [`AudioCodec::RawPcmI16`] skips libopus and packs the
+//! interleaved samples as little-endian `i16`, about 1.5 Mbit/s for 48 kHz
+//! stereo. Each packet says which codec it's in, and decoders follow it,
+//! so a receiver plays either. Raw packets carry no FEC data, and a lost
+//! one is a gap like any other. A 20 ms stereo frame is 3840 bytes, more
+//! than one datagram holds, so senders cap raw frames at
+//! [`AudioCodec::max_frame_ms`] instead of relying on IP fragmentation.
+
+use std::collections::VecDeque;
 use std::sync::Mutex;
 
 use anyhow::{Context, Result};
 use opus::{Application, Bitrate, Channels, Decoder, Encoder};
+use rkyv::{Archive, Deserialize, Serialize};
 
 use super::AudioSample;
-use super::frame::AudioBuffer;
+use super::channel_adapter::adapt_channels;
+use super::frame::{AudioBuffer, AudioFrame};
 use crate::pipeline::Node;
 
-const OPUS_BITRATE: i32 = 128000;
+/// Bitrate the encoder targets, in bits per second.
+pub const OPUS_BITRATE: i32 = 128000;
 const MAX_OPUS_PACKET_SIZE: usize = 4000;
 const MAX_FRAME_SIZE: usize = 48000;
 
+/// Comfort noise level for DTX gaps before any audio has been decoded.
+const DTX_DEFAULT_NOISE_LEVEL: f64 = 0.0003;
+/// Upper bound on comfort noise, so DTX after loud audio stays a hiss.
+const DTX_MAX_NOISE_LEVEL: f64 = 0.003;
+/// Consecutive lost frames concealed by PLC before falling back to silence.
+/// Longer PLC runs turn into a smeared, "underwater" drone.
+pub const DEFAULT_PLC_LIMIT: usize = 5;
+/// Opus packets kept for rebuilding the frame before each from its FEC
+/// data, enough to cover the largest jitter buffer target latency.
+const RECENT_PACKETS: usize = 32;
+
 const VALID_FRAME_DURATIONS_MS: [f64; 6] = [2.5, 5.0, 10.0, 20.0, 40.0, 60.0];
 
 fn is_valid_opus_frame_size(samples_per_channel: usize, sample_rate: u32) -> bool {
@@ -32,6 +113,203 @@ fn is_valid_opus_frame_size(samples_per_channel: usize, sample_rate: u32) -> boo
     false
 }
 
+/// How an encoder codes the channels of a stream; see the
+/// [module docs](self#channel-coupling) for when to use which.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChannelCoupling {
+    /// One stream; Opus decides how to code the channels together.
+    #[default]
+    Joint,
+    /// Downmixed to one channel, played on all channels by the decoder.
+    Mono,
+    /// One mono stream per channel, each at an equal share of the bitrate.
+    Independent,
+}
+
+/// Codec a realtime stream is encoded with.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[rkyv(compare(PartialEq))]
+pub enum AudioCodec {
+    #[default]
+    Opus,
+    /// Interleaved little-endian `i16` samples, uncompressed.
+    RawPcmI16,
+}
+
+/// Largest raw PCM payload per packet, leaving room for headers under a
+/// typical 1500-byte MTU so frames don't depend on IP fragmentation.
+pub const MAX_RAW_PCM_BYTES: usize = 1200;
+
+impl AudioCodec {
+    /// Longest frame, in ms, that fits one packet of `channels` at
+    /// `sample_rate`, or `None` when frames of any duration do. Raw PCM
+    /// frames are kept within [`MAX_RAW_PCM_BYTES`], though never shorter
+    /// than the shortest [`OpusFrameDuration`].
+    pub fn max_frame_ms(self, channels: usize, sample_rate: u32) -> Option<u32> {
+        match self {
+            AudioCodec::Opus => None,
+            AudioCodec::RawPcmI16 => {
+                let bytes = |duration: OpusFrameDuration| {
+                    duration.samples_per_channel(sample_rate) * channels * 2
+                };
+                let fitting = OpusFrameDuration::ALL
+                    .into_iter()
+                    .take_while(|&duration| bytes(duration) <= MAX_RAW_PCM_BYTES)
+                    .last()
+                    .unwrap_or(OpusFrameDuration::Ms5);
+                Some(fitting.as_millis())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for AudioCodec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioCodec::Opus => write!(f, "Opus"),
+            AudioCodec::RawPcmI16 => write!(f, "PCM"),
+        }
+    }
+}
+
+/// Packs interleaved samples as raw PCM.
+fn pack_pcm(pcm: &[i16]) -> Vec<u8> {
+    pcm.iter().flat_map(|s| s.to_le_bytes()).collect()
+}
+
+/// Unpacks raw PCM, or `None` if `data` isn't `frame_size` samples.
+fn unpack_pcm<Sample: AudioSample>(data: &[u8], frame_size: usize) -> Option<Vec<Sample>> {
+    if data.len() != frame_size * 2 {
+        tracing::warn!(
+            "Raw PCM packet of {} bytes, expected {} samples",
+            data.len(),
+            frame_size
+        );
+        return None;
+    }
+    Some(
+        data.chunks_exact(2)
+            .map(|b| {
+                let s = i16::from_le_bytes([b[0], b[1]]);
+                Sample::from_f64_normalized(s.to_f64_normalized())
+            })
+            .collect(),
+    )
+}
+
+/// What an encoder is tuned for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OpusSignal {
+    /// Anything, at the lowest latency: the CELT-only "restricted lowdelay"
+    /// mode.
+    #[default]
+    Auto,
+    /// Speech, in Opus' VoIP mode. Clearer speech at low bitrates, and
+    /// inband FEC works, for a few ms more encoder lookahead.
+    Voice,
+    /// Music, in Opus' general audio mode, also with more lookahead.
+    Music,
+}
+
+impl OpusSignal {
+    /// Whether inband FEC has any effect. The CELT-only [`Auto`](Self::Auto)
+    /// mode carries no FEC data whatever the encoder is told.
+    pub fn carries_fec(self) -> bool {
+        self != OpusSignal::Auto
+    }
+
+    fn application(self) -> Application {
+        match self {
+            OpusSignal::Auto => Application::LowDelay,
+            OpusSignal::Voice => Application::Voip,
+            OpusSignal::Music => Application::Audio,
+        }
+    }
+}
+
+/// Bitrate, complexity and signal type of an encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpusEncoderConfig {
+    /// Total bitrate in bits per second.
+    pub bitrate_bps: i32,
+    /// From 0 (cheapest) to 10 (best quality).
+    pub complexity: i32,
+    pub signal_type: OpusSignal,
+}
+
+impl Default for OpusEncoderConfig {
+    fn default() -> Self {
+        Self::PRESETS[0].1
+    }
+}
+
+impl OpusEncoderConfig {
+    /// Named settings to pick from; the first is the default.
+    pub const PRESETS: [(&'static str, OpusEncoderConfig); 3] = [
+        (
+            "Default (128 kbps)",
+            OpusEncoderConfig {
+                bitrate_bps: OPUS_BITRATE,
+                complexity: 10,
+                signal_type: OpusSignal::Auto,
+            },
+        ),
+        (
+            "Voice (24 kbps)",
+            OpusEncoderConfig {
+                bitrate_bps: 24_000,
+                complexity: 10,
+                signal_type: OpusSignal::Voice,
+            },
+        ),
+        (
+            "Music (96 kbps)",
+            OpusEncoderConfig {
+                bitrate_bps: 96_000,
+                complexity: 10,
+                signal_type: OpusSignal::Music,
+            },
+        ),
+    ];
+}
+
+/// Audio per Opus packet. Longer frames spend fewer bits on overhead;
+/// shorter ones wait less for audio before sending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpusFrameDuration {
+    Ms5,
+    Ms10,
+    Ms20,
+    Ms40,
+    Ms60,
+}
+
+impl OpusFrameDuration {
+    /// Every duration, shortest first.
+    pub const ALL: [OpusFrameDuration; 5] = [
+        OpusFrameDuration::Ms5,
+        OpusFrameDuration::Ms10,
+        OpusFrameDuration::Ms20,
+        OpusFrameDuration::Ms40,
+        OpusFrameDuration::Ms60,
+    ];
+
+    pub fn as_millis(self) -> u32 {
+        match self {
+            OpusFrameDuration::Ms5 => 5,
+            OpusFrameDuration::Ms10 => 10,
+            OpusFrameDuration::Ms20 => 20,
+            OpusFrameDuration::Ms40 => 40,
+            OpusFrameDuration::Ms60 => 60,
+        }
+    }
+
+    /// Samples per channel in one frame at `sample_rate`.
+    pub fn samples_per_channel(self, sample_rate: u32) -> usize {
+        sample_rate as usize * self.as_millis() as usize / 1000
+    }
+}
+
 fn channels_to_opus(channels: usize) -> Result<Channels> {
     match channels {
         1 => Ok(Channels::Mono),
@@ -41,58 +319,218 @@ fn channels_to_opus(channels: usize) -> Result<Channels> {
 }
 
 pub struct OpusEncoderState {
-    encoder: Encoder,
+    /// One encoder, or one per channel for [`ChannelCoupling::Independent`].
+    encoders: Vec<Encoder>,
+    coupling: ChannelCoupling,
+    channels: usize,
+    sample_rate: u32,
+    /// Channels of each of `encoders`.
+    encoder_channels: Channels,
+    config: OpusEncoderConfig,
+    /// Expected loss FEC is tuned for, kept across recreated encoders.
+    fec: Option<u8>,
     output_buffer: Vec<u8>,
+    packet: Vec<u8>,
 }
 
 impl OpusEncoderState {
     pub fn new<const CHANNELS: usize, const SAMPLE_RATE: u32>() -> Result<Self> {
-        let channels = channels_to_opus(CHANNELS)?;
-
-        let mut encoder = Encoder::new(SAMPLE_RATE, channels, Application::LowDelay)
-            .context("Failed to create Opus encoder")?;
+        Self::with_coupling::<CHANNELS, SAMPLE_RATE>(ChannelCoupling::Joint)
+    }
 
-        encoder
-            .set_bitrate(Bitrate::Bits(OPUS_BITRATE))
-            .context("Failed to set bitrate")?;
+    pub fn with_coupling<const CHANNELS: usize, const SAMPLE_RATE: u32>(
+        coupling: ChannelCoupling,
+    ) -> Result<Self> {
+        Self::with_config::<CHANNELS, SAMPLE_RATE>(coupling, OpusEncoderConfig::default())
+    }
 
-        Ok(Self {
-            encoder,
+    pub fn with_config<const CHANNELS: usize, const SAMPLE_RATE: u32>(
+        coupling: ChannelCoupling,
+        config: OpusEncoderConfig,
+    ) -> Result<Self> {
+        let (encoder_channels, count) = match coupling {
+            ChannelCoupling::Joint => (channels_to_opus(CHANNELS)?, 1),
+            ChannelCoupling::Mono => (Channels::Mono, 1),
+            ChannelCoupling::Independent => (Channels::Mono, CHANNELS),
+        };
+
+        let mut state = Self {
+            encoders: Vec::with_capacity(count),
+            coupling,
+            channels: CHANNELS,
+            sample_rate: SAMPLE_RATE,
+            encoder_channels,
+            config,
+            fec: None,
             output_buffer: vec![0u8; MAX_OPUS_PACKET_SIZE],
-        })
+            packet: Vec::with_capacity(MAX_OPUS_PACKET_SIZE),
+        };
+        state.create_encoders(count)?;
+        Ok(state)
     }
 
-    pub fn encode(&mut self, pcm: &[i16]) -> Result<&[u8]> {
-        let len = self
-            .encoder
-            .encode(pcm, &mut self.output_buffer)
-            .context("Opus encoding failed")?;
+    /// Replaces the encoders with `count` fresh ones set up as configured.
+    fn create_encoders(&mut self, count: usize) -> Result<()> {
+        let application = self.config.signal_type.application();
+        self.encoders = (0..count)
+            .map(|_| {
+                Encoder::new(self.sample_rate, self.encoder_channels, application)
+                    .context("Failed to create Opus encoder")
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.set_bitrate(self.config.bitrate_bps)?;
+        self.set_complexity(self.config.complexity)?;
+        self.set_fec(self.fec)
+    }
 
-        Ok(&self.output_buffer[..len])
+    pub fn config(&self) -> OpusEncoderConfig {
+        self.config
+    }
+
+    /// Applies `config`, recreating the encoders only if the signal type
+    /// changed.
+    pub fn set_config(&mut self, config: OpusEncoderConfig) -> Result<()> {
+        let signal_changed = config.signal_type != self.config.signal_type;
+        self.config = config;
+        if signal_changed {
+            self.create_encoders(self.encoders.len())
+        } else {
+            self.set_bitrate(config.bitrate_bps)?;
+            self.set_complexity(config.complexity)
+        }
+    }
+
+    pub fn encode(&mut self, pcm: &[i16]) -> Result<&[u8]> {
+        match self.coupling {
+            ChannelCoupling::Joint => {
+                let len = self.encoders[0]
+                    .encode(pcm, &mut self.output_buffer)
+                    .context("Opus encoding failed")?;
+                Ok(&self.output_buffer[..len])
+            }
+            ChannelCoupling::Mono => {
+                let mono: Vec<i16> = pcm
+                    .chunks(self.channels)
+                    .map(|frame| {
+                        let sum: i32 = frame.iter().map(|&s| s as i32).sum();
+                        (sum / frame.len() as i32) as i16
+                    })
+                    .collect();
+                let len = self.encoders[0]
+                    .encode(&mono, &mut self.output_buffer)
+                    .context("Opus encoding failed")?;
+                Ok(&self.output_buffer[..len])
+            }
+            ChannelCoupling::Independent => {
+                // Every channel's packet but the last is prefixed with its
+                // length (u16, big endian).
+                self.packet.clear();
+                let count = self.encoders.len();
+                for (channel, encoder) in self.encoders.iter_mut().enumerate() {
+                    let samples: Vec<i16> =
+                        pcm.iter().skip(channel).step_by(count).copied().collect();
+                    let len = encoder
+                        .encode(&samples, &mut self.output_buffer)
+                        .context("Opus encoding failed")?;
+                    if channel + 1 < count {
+                        self.packet.extend_from_slice(&(len as u16).to_be_bytes());
+                    }
+                    self.packet.extend_from_slice(&self.output_buffer[..len]);
+                }
+                Ok(&self.packet)
+            }
+        }
     }
 
     pub fn reset(&mut self) {
-        if let Err(e) = self.encoder.reset_state() {
-            tracing::warn!("Failed to reset Opus encoder: {}", e);
+        for encoder in &mut self.encoders {
+            if let Err(e) = encoder.reset_state() {
+                tracing::warn!("Failed to reset Opus encoder: {}", e);
+            }
+        }
+    }
+
+    /// Sets the total bitrate in bits per second, split evenly between the
+    /// encoders of independently coded channels.
+    pub fn set_bitrate(&mut self, bitrate: i32) -> Result<()> {
+        let share = bitrate / self.encoders.len() as i32;
+        for encoder in &mut self.encoders {
+            encoder
+                .set_bitrate(Bitrate::Bits(share))
+                .context("Failed to set bitrate")?;
         }
+        self.config.bitrate_bps = bitrate;
+        Ok(())
+    }
+
+    /// Enables inband FEC tuned for `loss_percent` expected loss, or
+    /// disables it with `None`.
+    pub fn set_fec(&mut self, loss_percent: Option<u8>) -> Result<()> {
+        for encoder in &mut self.encoders {
+            encoder
+                .set_inband_fec(loss_percent.is_some())
+                .context("Failed to set inband FEC")?;
+            encoder
+                .set_packet_loss_perc(loss_percent.unwrap_or(0) as i32)
+                .context("Failed to set expected packet loss")?;
+        }
+        self.fec = loss_percent;
+        Ok(())
+    }
+
+    /// Expected loss FEC is tuned for, or `None` when it's off.
+    pub fn fec(&self) -> Option<u8> {
+        self.fec
+    }
+
+    /// Sets the computational complexity, from 0 (cheapest) to 10 (best
+    /// quality).
+    pub fn set_complexity(&mut self, complexity: i32) -> Result<()> {
+        for encoder in &mut self.encoders {
+            encoder
+                .set_complexity(complexity)
+                .context("Failed to set complexity")?;
+        }
+        self.config.complexity = complexity;
+        Ok(())
     }
 }
 
 pub struct OpusDecoderState {
-    decoder: Decoder,
+    /// One decoder, or one per channel for [`ChannelCoupling::Independent`].
+    decoders: Vec<Decoder>,
     output_buffer: Vec<i16>,
+    channel_buffer: Vec<i16>,
+    plc_limit: usize,
+    /// Lost frames concealed since the last decoded packet.
+    concealed: usize,
 }
 
 impl OpusDecoderState {
     pub fn new<const CHANNELS: usize, const SAMPLE_RATE: u32>() -> Result<Self> {
-        let channels = channels_to_opus(CHANNELS)?;
+        Self::with_coupling::<CHANNELS, SAMPLE_RATE>(ChannelCoupling::Joint)
+    }
+
+    /// Decoder for packets from an encoder with `coupling`. Joint and mono
+    /// packets decode alike, so only `Independent` differs.
+    pub fn with_coupling<const CHANNELS: usize, const SAMPLE_RATE: u32>(
+        coupling: ChannelCoupling,
+    ) -> Result<Self> {
+        let (channels, count) = match coupling {
+            ChannelCoupling::Joint | ChannelCoupling::Mono => (channels_to_opus(CHANNELS)?, 1),
+            ChannelCoupling::Independent => (Channels::Mono, CHANNELS),
+        };
 
-        let decoder =
-            Decoder::new(SAMPLE_RATE, channels).context("Failed to create Opus decoder")?;
+        let decoders = (0..count)
+            .map(|_| Decoder::new(SAMPLE_RATE, channels).context("Failed to create Opus decoder"))
+            .collect::<Result<Vec<_>>>()?;
 
         Ok(Self {
-            decoder,
+            decoders,
             output_buffer: vec![0i16; MAX_FRAME_SIZE],
+            channel_buffer: vec![0i16; MAX_FRAME_SIZE],
+            plc_limit: DEFAULT_PLC_LIMIT,
+            concealed: 0,
         })
     }
 
@@ -102,27 +540,113 @@ impl OpusDecoderState {
         frame_size: usize,
         channels: usize,
     ) -> Result<&[i16]> {
-        let samples_per_channel = self
-            .decoder
-            .decode(opus_data, &mut self.output_buffer[..frame_size], false)
-            .context("Opus decoding failed")?;
+        self.decode_inner(opus_data, frame_size, channels, false)
+    }
+
+    /// Reconstructs the frame before `opus_data` from the inband FEC data
+    /// it carries. Packets without FEC data yield PLC instead.
+    pub fn decode_fec(
+        &mut self,
+        opus_data: &[u8],
+        frame_size: usize,
+        channels: usize,
+    ) -> Result<&[i16]> {
+        self.decode_inner(opus_data, frame_size, channels, true)
+    }
+
+    fn decode_inner(
+        &mut self,
+        opus_data: &[u8],
+        frame_size: usize,
+        channels: usize,
+        fec: bool,
+    ) -> Result<&[i16]> {
+        self.concealed = 0;
+        if let [decoder] = self.decoders.as_mut_slice() {
+            let samples_per_channel = decoder
+                .decode(opus_data, &mut self.output_buffer[..frame_size], fec)
+                .context("Opus decoding failed")?;
+
+            let total_samples = samples_per_channel * channels;
+            return Ok(&self.output_buffer[..total_samples]);
+        }
 
-        let total_samples = samples_per_channel * channels;
-        Ok(&self.output_buffer[..total_samples])
+        let count = self.decoders.len();
+        let mut rest = opus_data;
+        let mut samples_per_channel = frame_size / count;
+        for (channel, decoder) in self.decoders.iter_mut().enumerate() {
+            let packet = if channel + 1 < count {
+                let (len, tail) = rest
+                    .split_first_chunk::<2>()
+                    .context("Truncated independent-channel Opus packet")?;
+                let len = u16::from_be_bytes(*len) as usize;
+                anyhow::ensure!(
+                    len <= tail.len(),
+                    "Truncated independent-channel Opus packet"
+                );
+                let (packet, tail) = tail.split_at(len);
+                rest = tail;
+                packet
+            } else {
+                rest
+            };
+            samples_per_channel = decoder
+                .decode(packet, &mut self.channel_buffer[..frame_size / count], fec)
+                .context("Opus decoding failed")?;
+            for (i, &sample) in self.channel_buffer[..samples_per_channel]
+                .iter()
+                .enumerate()
+            {
+                self.output_buffer[i * count + channel] = sample;
+            }
+        }
+
+        Ok(&self.output_buffer[..samples_per_channel * count])
     }
 
+    /// Conceals one lost frame with PLC, or with silence once `plc_limit`
+    /// frames in a row have been concealed.
     pub fn decode_missing(&mut self, frame_size: usize) -> Result<&[i16]> {
-        let _ = self
-            .decoder
-            .decode_float(&[], &mut vec![0.0f32; frame_size], false)
-            .ok();
+        let frame_size = frame_size.min(self.output_buffer.len());
+        if self.concealed >= self.plc_limit {
+            self.output_buffer[..frame_size].fill(0);
+            return Ok(&self.output_buffer[..frame_size]);
+        }
+        self.concealed += 1;
 
-        Ok(&self.output_buffer[..frame_size.min(self.output_buffer.len())])
+        if let [decoder] = self.decoders.as_mut_slice() {
+            decoder
+                .decode(&[], &mut self.output_buffer[..frame_size], false)
+                .context("Opus PLC failed")?;
+            return Ok(&self.output_buffer[..frame_size]);
+        }
+
+        let count = self.decoders.len();
+        let samples_per_channel = frame_size / count;
+        for (channel, decoder) in self.decoders.iter_mut().enumerate() {
+            decoder
+                .decode(&[], &mut self.channel_buffer[..samples_per_channel], false)
+                .context("Opus PLC failed")?;
+            for (i, &sample) in self.channel_buffer[..samples_per_channel]
+                .iter()
+                .enumerate()
+            {
+                self.output_buffer[i * count + channel] = sample;
+            }
+        }
+        Ok(&self.output_buffer[..samples_per_channel * count])
+    }
+
+    /// Caps how many lost frames in a row are concealed with PLC.
+    pub fn set_plc_limit(&mut self, limit: usize) {
+        self.plc_limit = limit;
     }
 
     pub fn reset(&mut self) {
-        if let Err(e) = self.decoder.reset_state() {
-            tracing::warn!("Failed to reset Opus decoder: {}", e);
+        for decoder in &mut self.decoders {
+            if let Err(e) = decoder.reset_state() {
+                tracing::warn!("Failed to reset Opus decoder: {}", e);
+            }
         }
     }
 }
@@ -130,9 +654,11 @@ impl OpusDecoderState {
 /// Pipeline node that encodes PCM audio to Opus format.
 ///
 /// Input: AudioBuffer<Sample> (PCM samples)
-/// Output: OpusPacket (compressed Opus data)
+/// Output: OpusPacket (compressed Opus data, or raw PCM if set
+/// [`with_codec`](Self::with_codec))
 pub struct OpusEncoder<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     state: Mutex<OpusEncoderState>,
+    codec: AudioCodec,
     _marker: std::marker::PhantomData<Sample>,
 }
 
@@ -140,21 +666,86 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
     OpusEncoder<Sample, CHANNELS, SAMPLE_RATE>
 {
     pub fn new() -> Result<Self> {
+        Self::with_coupling(ChannelCoupling::Joint)
+    }
+
+    /// Encoder coding its channels as `coupling` says.
+    pub fn with_coupling(coupling: ChannelCoupling) -> Result<Self> {
         Ok(Self {
-            state: Mutex::new(OpusEncoderState::new::<CHANNELS, SAMPLE_RATE>()?),
+            state: Mutex::new(OpusEncoderState::with_coupling::<CHANNELS, SAMPLE_RATE>(
+                coupling,
+            )?),
+            codec: AudioCodec::Opus,
             _marker: std::marker::PhantomData,
         })
     }
 
+    /// Encoder with the settings in `config`.
+    pub fn with_config(config: OpusEncoderConfig) -> Result<Self> {
+        Ok(Self {
+            state: Mutex::new(OpusEncoderState::with_config::<CHANNELS, SAMPLE_RATE>(
+                ChannelCoupling::Joint,
+                config,
+            )?),
+            codec: AudioCodec::Opus,
+            _marker: std::marker::PhantomData,
+        })
+    }
+
+    /// Codes packets as `codec`. With [`AudioCodec::RawPcmI16`] libopus is
+    /// skipped, and the Opus settings have no effect.
+    pub fn with_codec(mut self, codec: AudioCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    pub fn codec(&self) -> AudioCodec {
+        self.codec
+    }
+
+    /// Current settings, including changes made by the setters.
+    pub fn config(&self) -> OpusEncoderConfig {
+        self.state.lock().unwrap().config()
+    }
+
+    /// See [`OpusEncoderState::set_config`].
+    pub fn set_config(&self, config: OpusEncoderConfig) -> Result<()> {
+        self.state.lock().unwrap().set_config(config)
+    }
+
     pub fn reset(&self) {
         self.state.lock().unwrap().reset();
     }
+
+    /// See [`OpusEncoderState::set_bitrate`].
+    pub fn set_bitrate(&self, bitrate: i32) -> Result<()> {
+        self.state.lock().unwrap().set_bitrate(bitrate)
+    }
+
+    /// See [`OpusEncoderState::set_fec`].
+    pub fn set_fec(&self, loss_percent: Option<u8>) -> Result<()> {
+        self.state.lock().unwrap().set_fec(loss_percent)
+    }
+
+    pub fn fec(&self) -> Option<u8> {
+        self.state.lock().unwrap().fec()
+    }
+
+    /// See [`OpusEncoderState::set_complexity`].
+    pub fn set_complexity(&self, complexity: i32) -> Result<()> {
+        self.state.lock().unwrap().set_complexity(complexity)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct OpusPacket {
     pub data: Vec<u8>,
     pub frame_size: usize,
+    /// What `data` is coded as.
+    pub codec: AudioCodec,
+    /// Target bitrate `data` was encoded at in bits per second, 0 for raw
+    /// PCM or when unknown.
+    pub bitrate: u32,
 }
 
 impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
@@ -171,6 +762,14 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
             .collect();
 
         let frame_size = pcm_i16.len();
+        if self.codec == AudioCodec::RawPcmI16 {
+            return Some(OpusPacket {
+                data: pack_pcm(&pcm_i16),
+                frame_size,
+                codec: AudioCodec::RawPcmI16,
+                bitrate: 0,
+            });
+        }
         let samples_per_channel = frame_size / CHANNELS;
 
         if !is_valid_opus_frame_size(samples_per_channel, SAMPLE_RATE) {
@@ -193,6 +792,8 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
             Ok(encoded) => Some(OpusPacket {
                 data: encoded.to_vec(),
                 frame_size,
+                codec: AudioCodec::Opus,
+                bitrate: state.config().bitrate_bps.max(0) as u32,
             }),
             Err(e) => {
                 tracing::warn!("Opus encoding failed: {}", e);
@@ -215,16 +816,53 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
     OpusDecoder<Sample, CHANNELS, SAMPLE_RATE>
 {
     pub fn new() -> Result<Self> {
+        Self::with_coupling(ChannelCoupling::Joint)
+    }
+
+    /// Decoder matching an encoder built with `coupling`.
+    pub fn with_coupling(coupling: ChannelCoupling) -> Result<Self> {
         Ok(Self {
-            state: Mutex::new(OpusDecoderState::new::<CHANNELS, SAMPLE_RATE>()?),
+            state: Mutex::new(OpusDecoderState::with_coupling::<CHANNELS, SAMPLE_RATE>(
+                coupling,
+            )?),
             _marker: std::marker::PhantomData,
         })
     }
 
+    /// Conceals at most `limit` lost frames in a row with PLC, then fills
+    /// with silence until a packet arrives. Defaults to [`DEFAULT_PLC_LIMIT`].
+    pub fn with_plc_limit(self, limit: usize) -> Self {
+        self.state.lock().unwrap().set_plc_limit(limit);
+        self
+    }
+
     pub fn decode_packet(
         &self,
         packet: &OpusPacket,
     ) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        self.decode_with_fec(packet, false)
+    }
+
+    /// Decodes `packet`, or with `prev_missing` the lost frame before it,
+    /// reconstructed from the inband FEC data `packet` carries. The lost
+    /// frame is taken to be as long as `packet`'s.
+    ///
+    /// To recover a loss, call this with `prev_missing` first and then
+    /// without it for the packet's own frame.
+    ///
+    /// Raw PCM packets are unpacked as they are; they carry nothing for
+    /// the frame before.
+    pub fn decode_with_fec(
+        &self,
+        packet: &OpusPacket,
+        prev_missing: bool,
+    ) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        if packet.codec == AudioCodec::RawPcmI16 {
+            if prev_missing {
+                return None;
+            }
+            return AudioBuffer::new(unpack_pcm(&packet.data, packet.frame_size)?).ok();
+        }
         let samples_per_channel = packet.frame_size / CHANNELS;
 
         if !is_valid_opus_frame_size(samples_per_channel, SAMPLE_RATE) {
@@ -243,7 +881,12 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         }
 
         let mut state = self.state.lock().unwrap();
-        match state.decode(&packet.data, packet.frame_size, CHANNELS) {
+        let decoded = if prev_missing {
+            state.decode_fec(&packet.data, packet.frame_size, CHANNELS)
+        } else {
+            state.decode(&packet.data, packet.frame_size, CHANNELS)
+        };
+        match decoded {
             Ok(pcm_i16) => {
                 let samples: Vec<Sample> = pcm_i16
                     .iter()
@@ -304,6 +947,16 @@ pub struct RealtimeOpusFrame {
     pub timestamp: u64,
     pub opus_data: Vec<u8>,
     pub frame_size: usize,
+    /// The sender was silent and sent no audio for this frame (discontinuous
+    /// transmission). Played as comfort noise rather than decoded.
+    pub dtx: bool,
+    /// Channels the sender encoded with. `frame_size` counts interleaved
+    /// samples in this layout; the Opus decoder itself always outputs ours.
+    pub channels: usize,
+    /// What `opus_data` is coded as.
+    pub codec: AudioCodec,
+    /// The run of the sender's stream this frame belongs to.
+    pub epoch: u32,
 }
 
 impl RealtimeOpusFrame {
@@ -311,17 +964,44 @@ impl RealtimeOpusFrame {
         OpusPacket {
             data: self.opus_data.clone(),
             frame_size: self.frame_size,
+            codec: self.codec,
+            bitrate: 0,
         }
     }
 }
 
+struct ComfortNoiseState {
+    /// RMS of the most recent decoded frame, clamped to `DTX_MAX_NOISE_LEVEL`.
+    level: f64,
+    seed: u32,
+}
+
 /// Decodes Opus frames from network into AudioFrames for jitter buffer.
 ///
 /// This node preserves the sequence number through decoding:
 /// - Input: [`RealtimeOpusFrame`] (Opus data + sequence_number from network)
 /// - Output: [`AudioFrame`] (decoded PCM + sequence_number for jitter buffer)
+///
+/// DTX frames (flagged, or carrying no Opus data) become comfort noise at
+/// the level of the last decoded frame, which approximates the sender's
+/// background noise. This is separate from loss handling: a frame that never
+/// arrives is a gap in the jitter buffer, which can ask
+/// [`recover`](Self::recover) to rebuild it when it comes to play it.
+///
+/// When the epoch changes, the sender has restarted with a fresh encoder,
+/// so the decoder is reset to match.
+///
+/// Each frame is decoded as its codec says, so one decoder plays senders
+/// using Opus and raw PCM alike.
 pub struct RealtimeFrameDecoder<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     decoder: OpusDecoder<Sample, CHANNELS, SAMPLE_RATE>,
+    comfort_noise: bool,
+    /// Conceal losses FEC can't rebuild with PLC.
+    plc: bool,
+    noise: Mutex<ComfortNoiseState>,
+    epoch: Mutex<Option<u32>>,
+    /// The last Opus packets decoded, by sequence number.
+    recent: Mutex<VecDeque<(u64, OpusPacket)>>,
 }
 
 impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
@@ -330,20 +1010,150 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
     pub fn new() -> Result<Self> {
         Ok(Self {
             decoder: OpusDecoder::new()?,
+            comfort_noise: true,
+            plc: true,
+            noise: Mutex::new(ComfortNoiseState {
+                level: DTX_DEFAULT_NOISE_LEVEL,
+                seed: 0x2545_F491,
+            }),
+            epoch: Mutex::new(None),
+            recent: Mutex::new(VecDeque::with_capacity(RECENT_PACKETS)),
         })
     }
+
+    /// When disabled, DTX frames play as hard digital silence.
+    pub fn with_comfort_noise(mut self, enabled: bool) -> Self {
+        self.comfort_noise = enabled;
+        self
+    }
+
+    /// When disabled, [`recover`](Self::recover) leaves losses FEC can't
+    /// rebuild to the jitter buffer's concealment instead of PLC.
+    pub fn with_plc(mut self, enabled: bool) -> Self {
+        self.plc = enabled;
+        self
+    }
+
+    /// Rebuilds the lost frame `seq` from the inband FEC data of frame
+    /// `seq + 1`, if that one has arrived (libopus falls back to PLC when
+    /// it carries none). Otherwise conceals `frame_size` samples with PLC,
+    /// for at most the decoder's PLC limit of frames in a row before
+    /// silence.
+    ///
+    /// Meant to be called by the jitter buffer when it reaches the empty
+    /// slot. `None` leaves the loss to its concealment: with PLC disabled,
+    /// or before any Opus packet has been decoded.
+    pub fn recover(
+        &self,
+        seq: u64,
+        frame_size: usize,
+    ) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        let next = {
+            let recent = self.recent.lock().unwrap();
+            if recent.is_empty() {
+                return None;
+            }
+            recent
+                .iter()
+                .find(|(recent, _)| *recent == seq + 1)
+                .map(|(_, packet)| packet.clone())
+        };
+        match next {
+            Some(next) => self.decoder.decode_with_fec(&next, true),
+            None if self.plc => self.decoder.decode_missing(frame_size),
+            None => None,
+        }
+    }
+
+    fn remember_packet(&self, seq: u64, packet: OpusPacket) {
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == RECENT_PACKETS {
+            recent.pop_front();
+        }
+        recent.push_back((seq, packet));
+    }
+
+    /// Fills in `frame_size` interleaved samples of the sender's
+    /// `channels`-wide silence, adapted to our layout so a mono sender's
+    /// noise lands identically on every channel.
+    fn dtx_frame(
+        &self,
+        frame_size: usize,
+        channels: usize,
+    ) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        if !self.comfort_noise {
+            let frames = frame_size / channels.max(1);
+            return AudioBuffer::new(vec![Sample::silence(); frames * CHANNELS]).ok();
+        }
+
+        let mut noise = self.noise.lock().unwrap();
+        let level = noise.level;
+        let samples = (0..frame_size)
+            .map(|_| {
+                noise.seed = noise
+                    .seed
+                    .wrapping_mul(1_664_525)
+                    .wrapping_add(1_013_904_223);
+                let unit = (noise.seed >> 8) as f64 / (1u32 << 24) as f64;
+                Sample::from_f64_normalized((unit * 2.0 - 1.0) * level)
+            })
+            .collect::<Vec<_>>();
+        AudioBuffer::new(adapt_channels(&samples, channels, CHANNELS)).ok()
+    }
+
+    fn track_noise_level(&self, pcm: &[Sample]) {
+        if pcm.is_empty() {
+            return;
+        }
+        let sum_sq: f64 = pcm
+            .iter()
+            .map(|s| {
+                let v = s.to_f64_normalized();
+                v * v
+            })
+            .sum();
+        let rms = (sum_sq / pcm.len() as f64).sqrt();
+        self.noise.lock().unwrap().level = rms.min(DTX_MAX_NOISE_LEVEL);
+    }
 }
 
 impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
     for RealtimeFrameDecoder<Sample, CHANNELS, SAMPLE_RATE>
 {
     type Input = RealtimeOpusFrame;
-    type Output = super::frame::AudioFrame<Sample, CHANNELS, SAMPLE_RATE>;
+    type Output = AudioFrame<Sample, CHANNELS, SAMPLE_RATE>;
 
     fn process(&self, input: Self::Input) -> Option<Self::Output> {
-        let opus_packet = input.to_opus_packet();
-        let pcm_buffer = self.decoder.decode_packet(&opus_packet)?;
-        Some(super::frame::AudioFrame {
+        let previous = self.epoch.lock().unwrap().replace(input.epoch);
+        if previous.is_some_and(|previous| previous != input.epoch) {
+            tracing::debug!(
+                "Epoch changed ({:?} -> {}), resetting Opus decoder",
+                previous,
+                input.epoch
+            );
+            self.decoder.reset();
+            self.recent.lock().unwrap().clear();
+        }
+
+        let pcm_buffer = if input.dtx || input.opus_data.is_empty() {
+            self.dtx_frame(input.frame_size, input.channels)?
+        } else {
+            let pcm_buffer = match input.codec {
+                AudioCodec::Opus => {
+                    let packet = input.to_opus_packet();
+                    let pcm_buffer = self.decoder.decode_packet(&packet)?;
+                    self.remember_packet(input.sequence_number, packet);
+                    pcm_buffer
+                }
+                AudioCodec::RawPcmI16 => {
+                    let samples = unpack_pcm::<Sample>(&input.opus_data, input.frame_size)?;
+                    AudioBuffer::new(adapt_channels(&samples, input.channels, CHANNELS)).ok()?
+                }
+            };
+            self.track_noise_level(pcm_buffer.data());
+            pcm_buffer
+        };
+        Some(AudioFrame {
             sequence_number: input.sequence_number,
             timestamp: input.timestamp,
             samples: pcm_buffer,
@@ -443,6 +1253,179 @@ mod tests {
         assert!(max_diff < 1000, "Max diff too large: {}", max_diff);
     }
 
+    /// Left channel a tone, right channel silent, as from two hard-panned mics.
+    #[test]
+    fn test_channel_coupling_roundtrip() {
+        fn rms(samples: impl Iterator<Item = f32>) -> f32 {
+            let (sum, n) = samples.fold((0.0, 0), |(sum, n), s| (sum + s * s, n + 1));
+            (sum / n as f32).sqrt()
+        }
+
+        for coupling in [
+            ChannelCoupling::Joint,
+            ChannelCoupling::Mono,
+            ChannelCoupling::Independent,
+        ] {
+            let encoder = OpusEncoder::<f32, 2, 48000>::with_coupling(coupling).unwrap();
+            let decoder = OpusDecoder::<f32, 2, 48000>::with_coupling(coupling).unwrap();
+
+            let mut decoded = Vec::new();
+            for frame in 0..10 {
+                let samples: Vec<f32> = (0..960)
+                    .flat_map(|i| {
+                        let t = (frame * 960 + i) as f32 / 48000.0;
+                        [0.5 * (t * 440.0 * std::f32::consts::TAU).sin(), 0.0]
+                    })
+                    .collect();
+                let packet = encoder
+                    .process(AudioBuffer::new(samples).unwrap())
+                    .expect("Encoding should succeed");
+                let pcm = decoder.process(packet).expect("Decoding should succeed");
+                assert_eq!(pcm.data().len(), 960 * 2, "{coupling:?}");
+                decoded.extend_from_slice(pcm.data());
+            }
+
+            // Skip the codec's startup.
+            let settled = &decoded[960 * 2 * 2..];
+            let left = rms(settled.iter().step_by(2).copied());
+            let right = rms(settled.iter().skip(1).step_by(2).copied());
+            match coupling {
+                ChannelCoupling::Joint | ChannelCoupling::Independent => {
+                    assert!((left - 0.35).abs() < 0.05, "{coupling:?} left rms {left}");
+                    assert!(right < left * 0.05, "{coupling:?} right rms {right}");
+                }
+                ChannelCoupling::Mono => {
+                    assert!((left - 0.18).abs() < 0.03, "mono left rms {left}");
+                    assert!((left - right).abs() < 0.01, "mono right rms {right}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_lower_bitrate_shrinks_packets_without_new_encoder() {
+        let encoder =
+            OpusEncoder::<f32, 2, 48000>::with_config(OpusEncoderConfig::default()).unwrap();
+        // A busy signal: a chord over pseudo-random noise, so the encoder
+        // uses whatever bitrate it is given.
+        let mut seed = 1u32;
+        let mut frame = |n: usize| {
+            let samples = (n * 960..(n + 1) * 960)
+                .flat_map(|i| {
+                    seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    let noise = (seed >> 8) as f32 / (1 << 24) as f32 - 0.5;
+                    let t = i as f32 / 48000.0;
+                    let s = 0.2 * (2.0 * std::f32::consts::PI * 440.0 * t).sin()
+                        + 0.2 * (2.0 * std::f32::consts::PI * 1320.0 * t).sin()
+                        + 0.2 * noise;
+                    [s, s]
+                })
+                .collect();
+            AudioBuffer::<f32, 2, 48000>::new(samples).unwrap()
+        };
+        let mut mean_size = |encoder: &OpusEncoder<f32, 2, 48000>,
+                             frames: std::ops::Range<usize>| {
+            let count = frames.len();
+            frames
+                .map(|n| encoder.process(frame(n)).unwrap().data.len())
+                .sum::<usize>()
+                / count
+        };
+
+        let full = mean_size(&encoder, 0..25);
+        encoder.set_bitrate(24_000).unwrap();
+        let lowered = mean_size(&encoder, 25..50);
+        assert!(
+            lowered * 2 < full,
+            "{lowered} bytes per packet at 24 kbps, {full} at 128 kbps"
+        );
+        // 24 kbps is 60 bytes per 20 ms packet, give or take VBR.
+        assert!(lowered < 100, "{lowered} bytes per packet at 24 kbps");
+        assert_eq!(encoder.config().bitrate_bps, 24_000);
+
+        // Switching to a voice preset retunes the same encoder.
+        let voice = OpusEncoderConfig::PRESETS[1].1;
+        encoder.set_config(voice).unwrap();
+        assert_eq!(encoder.config(), voice);
+        assert!(mean_size(&encoder, 50..75) < full / 2);
+    }
+
+    #[test]
+    fn test_reset_mid_stream_roundtrips() {
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let decoder = OpusDecoder::<f32, 2, 48000>::new().unwrap();
+
+        let mut after_reset = Vec::new();
+        for frame in 0..12 {
+            if frame == 6 {
+                encoder.reset();
+                decoder.reset();
+            }
+            let samples: Vec<f32> = (0..960)
+                .flat_map(|i| {
+                    let t = (frame * 960 + i) as f32 / 48000.0;
+                    let s = 0.5 * (t * 440.0 * std::f32::consts::TAU).sin();
+                    [s, s]
+                })
+                .collect();
+            let packet = encoder.process(AudioBuffer::new(samples).unwrap()).unwrap();
+            let pcm = decoder.process(packet).expect("Decoding should succeed");
+            assert_eq!(pcm.data().len(), 960 * 2);
+            if frame >= 6 {
+                after_reset.push(pcm.data().to_vec());
+            }
+        }
+
+        // The first frame after the reset starts clean: no garbage carried
+        // over from the old stream, just the codec's fade-in.
+        let first = &after_reset[0];
+        assert!(first.iter().all(|s| s.is_finite() && s.abs() <= 0.6));
+
+        let settled: Vec<f32> = after_reset[1..].concat();
+        let rms = (settled.iter().map(|s| s * s).sum::<f32>() / settled.len() as f32).sqrt();
+        assert!((rms - 0.35).abs() < 0.05, "rms after reset {rms}");
+    }
+
+    #[test]
+    fn test_decoder_resets_on_new_epoch_not_late_frames() {
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let decoder = RealtimeFrameDecoder::<f32, 2, 48000>::new().unwrap();
+        let packets: Vec<_> = (0..24)
+            .map(|frame| {
+                let samples: Vec<f32> = (0..960)
+                    .flat_map(|i| {
+                        let s = 0.5 * ((frame * 960 + i) as f32 * 0.06).sin();
+                        [s, s]
+                    })
+                    .collect();
+                encoder.process(AudioBuffer::new(samples).unwrap()).unwrap()
+            })
+            .collect();
+        let play = |seq: u64, epoch: u32| {
+            let packet = &packets[seq as usize - 1];
+            decoder.process(RealtimeOpusFrame {
+                sequence_number: seq,
+                timestamp: seq * 20_000,
+                opus_data: packet.data.clone(),
+                frame_size: packet.frame_size,
+                dtx: false,
+                channels: 2,
+                codec: AudioCodec::Opus,
+                epoch,
+            });
+        };
+
+        for seq in 1..=22 {
+            play(seq, 7);
+        }
+        // Far behind the newest, but the same epoch: just late.
+        play(2, 7);
+        assert_eq!(decoder.recent.lock().unwrap().len(), 23);
+
+        play(1, 8);
+        assert_eq!(decoder.recent.lock().unwrap().len(), 1, "reset on restart");
+    }
+
     #[test]
     fn test_opus_plc_recovery() {
         let decoder: OpusDecoder<i16, 2, 48000> = OpusDecoder::new().unwrap();
@@ -450,4 +1433,245 @@ mod tests {
         let plc_output = decoder.decode_missing(960 * 2);
         assert!(plc_output.is_some());
     }
+
+    #[test]
+    fn test_plc_falls_back_to_silence_after_limit() {
+        const LIMIT: usize = 3;
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let decoder = OpusDecoder::<f32, 2, 48000>::new()
+            .unwrap()
+            .with_plc_limit(LIMIT);
+        let tone = |frame: usize| {
+            let samples: Vec<f32> = (0..960)
+                .flat_map(|i| {
+                    let t = (frame * 960 + i) as f32 / 48000.0;
+                    let s = 0.5 * (t * 440.0 * std::f32::consts::TAU).sin();
+                    [s, s]
+                })
+                .collect();
+            encoder.process(AudioBuffer::new(samples).unwrap()).unwrap()
+        };
+        let is_silent = |pcm: &AudioBuffer<f32, 2, 48000>| pcm.data().iter().all(|&s| s == 0.0);
+
+        for frame in 0..5 {
+            decoder.process(tone(frame)).unwrap();
+        }
+        for lost in 0..LIMIT + 2 {
+            let pcm = decoder.decode_missing(960 * 2).unwrap();
+            assert_eq!(pcm.data().len(), 960 * 2);
+            if lost < LIMIT {
+                assert!(
+                    !is_silent(&pcm),
+                    "lost frame {lost} should be concealed by PLC"
+                );
+            } else {
+                assert!(
+                    is_silent(&pcm),
+                    "lost frame {lost} should fall back to silence"
+                );
+            }
+        }
+
+        // A received packet starts a fresh run of PLC.
+        decoder.process(tone(10)).unwrap();
+        assert!(!is_silent(&decoder.decode_missing(960 * 2).unwrap()));
+    }
+
+    #[test]
+    fn test_burst_loss_is_concealed_with_capped_plc() {
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let decoder = RealtimeFrameDecoder::<f32, 2, 48000>::new().unwrap();
+        let is_silent = |pcm: &[f32]| pcm.iter().all(|&s| s == 0.0);
+        assert!(decoder.recover(1, 1920).is_none(), "nothing decoded yet");
+        for seq in 1..=5 {
+            let samples: Vec<f32> = (0..960)
+                .flat_map(|i| {
+                    let s = 0.5 * ((seq * 960 + i) as f32 * 0.06).sin();
+                    [s, s]
+                })
+                .collect();
+            let packet = encoder.process(AudioBuffer::new(samples).unwrap()).unwrap();
+            decoder.process(RealtimeOpusFrame {
+                sequence_number: seq as u64,
+                timestamp: seq as u64 * 20_000,
+                opus_data: packet.data,
+                frame_size: packet.frame_size,
+                dtx: false,
+                channels: 2,
+                codec: AudioCodec::Opus,
+                epoch: 0,
+            });
+        }
+
+        // Frames 6 onwards never arrive.
+        let concealed: Vec<_> = (6..6 + DEFAULT_PLC_LIMIT as u64 + 2)
+            .map(|seq| decoder.recover(seq, 1920).unwrap())
+            .collect();
+        assert!(!is_silent(concealed[0].data()), "PLC continues the tone");
+        for frame in &concealed[DEFAULT_PLC_LIMIT..] {
+            assert!(is_silent(frame.data()), "silence after the PLC limit");
+        }
+
+        let without_plc = RealtimeFrameDecoder::<f32, 2, 48000>::new()
+            .unwrap()
+            .with_plc(false);
+        assert!(without_plc.recover(6, 1920).is_none());
+    }
+
+    #[test]
+    fn test_fec_recovery_smooths_dropped_frames() {
+        use crate::audio::JitterBuffer;
+        use crate::pipeline::{Pullable, Pushable};
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        const FRAMES: u64 = 52;
+        let encoder =
+            OpusEncoder::<f32, 2, 48000>::with_config(OpusEncoderConfig::PRESETS[1].1).unwrap();
+        encoder.set_fec(Some(20)).unwrap();
+        let packets: Vec<OpusPacket> = (0..FRAMES as usize)
+            .map(|frame| {
+                let samples: Vec<f32> = (0..960)
+                    .flat_map(|i| {
+                        let t = (frame * 960 + i) as f32 / 48000.0;
+                        let s = 0.5 * (t * 440.0 * std::f32::consts::TAU).sin();
+                        [s, s]
+                    })
+                    .collect();
+                encoder.process(AudioBuffer::new(samples).unwrap()).unwrap()
+            })
+            .collect();
+        // Frames arrive in pairs and a pair is played once it's in. The
+        // first of every other pair is lost, and the other pairs arrive
+        // swapped, which is reordering, not loss.
+        let dropped = |seq: u64| seq % 4 == 1 && seq > 1;
+        let pairs: Vec<Vec<u64>> = (1..=FRAMES)
+            .step_by(2)
+            .map(|first| match first % 4 {
+                3 => vec![first + 1, first],
+                _ => [first, first + 1]
+                    .into_iter()
+                    .filter(|&seq| !dropped(seq))
+                    .collect(),
+            })
+            .collect();
+
+        // Plays the stream through a jitter buffer; whatever isn't
+        // recovered is silence. Returns the left channel and the number of
+        // recovered frames.
+        let play = |fec: bool| {
+            let decoder = Arc::new(RealtimeFrameDecoder::<f32, 2, 48000>::new().unwrap());
+            let recovered = Arc::new(AtomicUsize::new(0));
+            let mut jitter = JitterBuffer::<f32, 2, 48000>::new(16);
+            if fec {
+                let (decoder, recovered) = (decoder.clone(), recovered.clone());
+                jitter = jitter.with_recovery(move |seq, frame_size| {
+                    let samples = decoder.recover(seq, frame_size)?;
+                    recovered.fetch_add(1, Ordering::Relaxed);
+                    Some(samples.into_inner())
+                });
+            }
+
+            let mut left = Vec::new();
+            for pair in &pairs {
+                for &seq in pair {
+                    let packet = &packets[seq as usize - 1];
+                    let frame = decoder.process(RealtimeOpusFrame {
+                        sequence_number: seq,
+                        timestamp: seq * 20_000,
+                        opus_data: packet.data.clone(),
+                        frame_size: packet.frame_size,
+                        dtx: false,
+                        channels: 2,
+                        codec: AudioCodec::Opus,
+                        epoch: 0,
+                    });
+                    jitter.push(frame.unwrap());
+                }
+                let pulled = jitter.pull(960 * 2 * 2).unwrap();
+                left.extend(pulled.data().iter().step_by(2));
+            }
+            (left, recovered.load(Ordering::Relaxed))
+        };
+        let discontinuities = |left: &[f32]| {
+            left.windows(2)
+                .filter(|w| (w[1] - w[0]).abs() > 0.1)
+                .count()
+        };
+
+        let (silence_filled, none) = play(false);
+        let (fec_filled, recovered) = play(true);
+        assert_eq!(none, 0);
+        assert_eq!(
+            recovered as u64,
+            (1..=FRAMES).filter(|&seq| dropped(seq)).count() as u64,
+            "only the lost frames, not the reordered ones"
+        );
+        assert_eq!(fec_filled.len(), silence_filled.len());
+        let (silence_jumps, fec_jumps) = (
+            discontinuities(&silence_filled),
+            discontinuities(&fec_filled),
+        );
+        assert!(
+            fec_jumps < silence_jumps,
+            "{fec_jumps} jumps with FEC, {silence_jumps} with silence"
+        );
+    }
+
+    #[test]
+    fn test_fec_is_only_carried_outside_celt_mode() {
+        // Rebuilds frame 5 of a tone, from the FEC data frame 6 carries and
+        // by PLC, on decoders that have seen the same frames before it.
+        let recover = |config: OpusEncoderConfig| {
+            let encoder = OpusEncoder::<f32, 2, 48000>::with_config(config).unwrap();
+            encoder.set_fec(Some(20)).unwrap();
+            let packets: Vec<OpusPacket> = (0..7)
+                .map(|frame| {
+                    let samples: Vec<f32> = (0..960)
+                        .flat_map(|i| {
+                            let t = (frame * 960 + i) as f32 / 48000.0;
+                            let s = 0.5 * (t * 440.0 * std::f32::consts::TAU).sin();
+                            [s, s]
+                        })
+                        .collect();
+                    encoder.process(AudioBuffer::new(samples).unwrap()).unwrap()
+                })
+                .collect();
+            let decoder = || {
+                let decoder = OpusDecoder::<f32, 2, 48000>::new().unwrap();
+                for packet in &packets[..5] {
+                    decoder.decode_packet(packet).unwrap();
+                }
+                decoder
+            };
+            let fec = decoder().decode_with_fec(&packets[6], true).unwrap();
+            let plc = decoder().decode_missing(packets[6].frame_size).unwrap();
+            (fec.into_inner(), plc.into_inner())
+        };
+
+        let auto = OpusEncoderConfig::default();
+        assert!(!auto.signal_type.carries_fec());
+        let (fec, plc) = recover(auto);
+        assert_eq!(fec, plc, "CELT packets carry nothing but PLC");
+
+        let voice = OpusEncoderConfig::PRESETS[1].1;
+        assert!(voice.signal_type.carries_fec());
+        let (fec, plc) = recover(voice);
+        assert_ne!(fec, plc);
+    }
+
+    #[test]
+    fn test_raw_pcm_frames_fit_a_packet() {
+        assert_eq!(AudioCodec::Opus.max_frame_ms(2, 48000), None);
+        assert_eq!(AudioCodec::RawPcmI16.max_frame_ms(1, 48000), Some(10));
+        let max_ms = AudioCodec::RawPcmI16.max_frame_ms(2, 48000).unwrap();
+        assert_eq!(max_ms, 5);
+
+        let encoder = OpusEncoder::<f32, 2, 48000>::new()
+            .unwrap()
+            .with_codec(AudioCodec::RawPcmI16);
+        let samples = vec![0.25; 48 * max_ms as usize * 2];
+        let packet = encoder.process(AudioBuffer::new(samples).unwrap()).unwrap();
+        assert!(packet.data.len() <= MAX_RAW_PCM_BYTES);
+    }
 }
@@ -4,8 +4,11 @@
 //! If this file grows complex, consider forking symphonia.
 
 use rkyv::{Archive, Deserialize, Serialize};
+use symphonia::core::audio::Channels;
 use symphonia::core::codecs::{CodecParameters, CodecType};
 
+use crate::audio::decoders::WireCompression;
+
 /// Wire-serializable codec type enum.
 /// Maps to symphonia's CodecType constants.
 #[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
@@ -77,6 +80,8 @@ pub struct WireCodecParams {
     pub sample_rate: u32,
     pub channels: u8,
     pub extra_data: Option<Vec<u8>>,
+    /// Packet compression applied by the sender on top of the codec.
+    pub compression: WireCompression,
 }
 
 impl WireCodecParams {
@@ -91,6 +96,7 @@ impl WireCodecParams {
             sample_rate,
             channels,
             extra_data,
+            compression: WireCompression::None,
         })
     }
 
@@ -98,7 +104,8 @@ impl WireCodecParams {
         let mut params = CodecParameters::new();
         params
             .for_codec(self.codec.to_symphonia())
-            .with_sample_rate(self.sample_rate);
+            .with_sample_rate(self.sample_rate)
+            .with_channels(channel_layout(self.channels));
 
         if let Some(ref extra) = self.extra_data {
             params.with_extra_data(extra.clone().into_boxed_slice());
@@ -107,3 +114,34 @@ impl WireCodecParams {
         params
     }
 }
+
+/// Speakers of `count` channels in the usual WAV order (front left, front
+/// right, centre, LFE, rear left, rear right, ...), since only the count
+/// goes over the wire.
+fn channel_layout(count: u8) -> Channels {
+    let bits = 1u64
+        .checked_shl(count.into())
+        .map_or(u64::MAX, |bit| bit - 1);
+    Channels::from_bits_truncate(bits as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_count_reaches_the_decoder_params() {
+        let params = |channels| WireCodecParams {
+            codec: WireCodecType::Flac,
+            sample_rate: 44100,
+            channels,
+            extra_data: None,
+            compression: WireCompression::None,
+        };
+        let mono = params(1).to_symphonia().channels.unwrap();
+        assert_eq!(mono, Channels::FRONT_LEFT);
+        let surround = params(6).to_symphonia().channels.unwrap();
+        assert_eq!(surround.count(), 6);
+        assert!(surround.contains(Channels::LFE1));
+    }
+}
@@ -4,13 +4,25 @@
 //! - [`AudioInput`] for microphone capture
 //! - [`LoopbackInput`] for system audio capture (loopback recording)
 //! - [`AudioOutput`] for speaker playback
+//!
+//! Devices are opened in their native sample format. Samples are converted
+//! to the pipeline's `Sample` type as they're captured, and back as they're
+//! played, by [`samples_from_device`] and [`samples_to_device`]; everything
+//! in between runs on `Sample` alone.
+//!
+//! Capture devices are opened at the pipeline's `SAMPLE_RATE` when they
+//! offer it. One that doesn't, e.g. a headset only running at 44100 Hz, is
+//! opened at its default rate and resampled by a [`CaptureResampler`], so
+//! its audio isn't played back at the wrong pitch.
 
 use crate::audio::AudioSample;
 use crate::audio::frame::AudioBuffer;
 use crate::pipeline::{Pullable, Pushable};
 use anyhow::{Context, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{BufferSize, Device, DeviceId, StreamConfig};
+use cpal::{BufferSize, Device, DeviceId, SampleFormat, StreamConfig};
+use rubato::{FftFixedIn, Resampler};
+use std::ops::RangeInclusive;
 use std::sync::{Arc, Mutex};
 use tracing::{debug, error, info, warn};
 
@@ -87,13 +99,23 @@ impl<Sample: AudioSample + cpal::SizedSample, const CHANNELS: usize, const SAMPL
         let input_config = input_device.default_input_config()?;
         debug!("Input config: {input_config:#?}");
 
+        let ranges: Vec<_> = input_device
+            .supported_input_configs()
+            .context("Failed to list supported input configs")?
+            .collect();
+        let rates: Vec<RangeInclusive<u32>> = ranges
+            .iter()
+            .map(|range| range.min_sample_rate()..=range.max_sample_rate())
+            .collect();
+        let device_rate = choose_capture_rate(SAMPLE_RATE, input_config.sample_rate(), &rates);
+
         const MIN_BUFFER_MS: f32 = 5.0;
-        let min_buffer_size = ((SAMPLE_RATE as f32) * MIN_BUFFER_MS / 1000.0) as u32;
+        let min_buffer_size = ((device_rate as f32) * MIN_BUFFER_MS / 1000.0) as u32;
         // let min_buffer_size = 0.5;
 
         let config = StreamConfig {
             channels: CHANNELS as u16,
-            sample_rate: SAMPLE_RATE,
+            sample_rate: device_rate,
             buffer_size: match input_config.buffer_size() {
                 cpal::SupportedBufferSize::Range { min, .. } => {
                     BufferSize::Fixed((*min).max(min_buffer_size))
@@ -105,17 +127,17 @@ impl<Sample: AudioSample + cpal::SizedSample, const CHANNELS: usize, const SAMPL
             },
         };
 
-        let sink = self.sink.clone();
-        let stream = input_device.build_input_stream(
+        let supported: Vec<SampleFormat> =
+            ranges.iter().map(|range| range.sample_format()).collect();
+        let sample_format = choose_input_format(input_config.sample_format(), &supported)?;
+        info!("Input sample format: {sample_format:?} at {device_rate} Hz");
+
+        let stream = build_capture_stream(
+            &input_device,
             config,
-            move |data: &[Sample], _: &cpal::InputCallbackInfo| {
-                let owned: Vec<Sample> = Vec::from(data);
-                if let Ok(frame) = AudioBuffer::<Sample, CHANNELS, SAMPLE_RATE>::new(owned) {
-                    sink.push(frame);
-                }
-            },
-            |err| error!("An error occurred on the input audio stream: {}", err),
-            None,
+            sample_format,
+            self.sink.clone(),
+            "input",
         )?;
         stream.play()?;
         info!("Microphone input enabled");
@@ -157,9 +179,19 @@ impl<Sample: AudioSample + cpal::SizedSample, const CHANNELS: usize, const SAMPL
 
         let output_config = output_device.default_output_config()?;
 
+        let ranges: Vec<_> = output_device
+            .supported_output_configs()
+            .context("Failed to list supported output configs")?
+            .collect();
+        let rates: Vec<RangeInclusive<u32>> = ranges
+            .iter()
+            .map(|range| range.min_sample_rate()..=range.max_sample_rate())
+            .collect();
+        let device_rate = choose_capture_rate(SAMPLE_RATE, output_config.sample_rate(), &rates);
+
         let config = StreamConfig {
             channels: CHANNELS as u16,
-            sample_rate: SAMPLE_RATE,
+            sample_rate: device_rate,
             buffer_size: match output_config.buffer_size() {
                 cpal::SupportedBufferSize::Range { min, max } => {
                     let target = 256u32;
@@ -175,34 +207,323 @@ impl<Sample: AudioSample + cpal::SizedSample, const CHANNELS: usize, const SAMPL
         };
         debug!("Using output config for loopback: {:?}", config);
 
-        let sink = self.sink;
-        let stream = output_device.build_input_stream(
+        let supported: Vec<SampleFormat> =
+            ranges.iter().map(|range| range.sample_format()).collect();
+        let sample_format = choose_input_format(output_config.sample_format(), &supported)?;
+        info!("Loopback sample format: {sample_format:?} at {device_rate} Hz");
+
+        let stream =
+            build_capture_stream(&output_device, config, sample_format, self.sink, "loopback")?;
+        stream.play()?;
+        info!("Loopback recording started successfully");
+        Ok(stream)
+    }
+}
+
+/// Builds an input stream on `device` capturing `sample_format` at
+/// `config.sample_rate`, and pushes each callback's samples to `sink`
+/// converted to `Sample` and resampled to `SAMPLE_RATE`.
+fn build_capture_stream<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32>(
+    device: &Device,
+    config: StreamConfig,
+    sample_format: SampleFormat,
+    sink: Arc<dyn Pushable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+    what: &'static str,
+) -> Result<cpal::Stream>
+where
+    Sample: AudioSample,
+{
+    fn build<T, Sample, const CHANNELS: usize, const SAMPLE_RATE: u32>(
+        device: &Device,
+        config: StreamConfig,
+        sink: Arc<dyn Pushable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+        what: &'static str,
+    ) -> Result<cpal::Stream>
+    where
+        T: cpal::SizedSample,
+        f64: cpal::FromSample<T>,
+        Sample: AudioSample,
+    {
+        let mut resampler = CaptureResampler::<CHANNELS, SAMPLE_RATE>::new(config.sample_rate)?;
+        if resampler.is_some() {
+            info!(
+                "Resampling {what} from {} Hz to {SAMPLE_RATE} Hz",
+                config.sample_rate
+            );
+        }
+        let stream = device.build_input_stream(
             config,
-            move |data: &[Sample], _: &cpal::InputCallbackInfo| {
-                let owned: Vec<Sample> = Vec::from(data);
-                if let Ok(frame) = AudioBuffer::<Sample, CHANNELS, SAMPLE_RATE>::new(owned) {
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                let mut samples = samples_from_device(data);
+                if let Some(resampler) = &mut resampler {
+                    samples = resampler.process(&samples);
+                    if samples.is_empty() {
+                        return;
+                    }
+                }
+                if let Ok(frame) = AudioBuffer::<Sample, CHANNELS, SAMPLE_RATE>::new(samples) {
                     sink.push(frame);
                 }
             },
-            |err| error!("An error occurred on the loopback audio stream: {}", err),
+            move |err| error!("An error occurred on the {what} audio stream: {}", err),
             None,
         )?;
-        stream.play()?;
-        info!("Loopback recording started successfully");
         Ok(stream)
     }
+
+    match sample_format {
+        SampleFormat::F32 => build::<f32, _, CHANNELS, SAMPLE_RATE>(device, config, sink, what),
+        SampleFormat::I16 => build::<i16, _, CHANNELS, SAMPLE_RATE>(device, config, sink, what),
+        SampleFormat::I32 => build::<i32, _, CHANNELS, SAMPLE_RATE>(device, config, sink, what),
+        other => unreachable!("{other:?} is never chosen"),
+    }
+}
+
+/// Picks the rate to capture at: `target`, the pipeline's own, when the
+/// device offers it, otherwise the device's `default`, to be resampled.
+pub fn choose_capture_rate(target: u32, default: u32, supported: &[RangeInclusive<u32>]) -> u32 {
+    if supported.iter().any(|range| range.contains(&target)) {
+        target
+    } else {
+        default
+    }
+}
+
+/// Capture resampler chunk length, which it adds in latency.
+const CAPTURE_CHUNK_MS: u32 = 5;
+
+/// Resamples interleaved capture from a device running at another rate to
+/// the pipeline's `SAMPLE_RATE`.
+///
+/// Kept for the life of the stream, so the filter state carries over from
+/// one callback to the next and their boundaries don't click. Captured
+/// frames are held until there are enough for a full chunk, as in
+/// [`FftResampler`](crate::audio::decoders::FftResampler).
+pub struct CaptureResampler<const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    resampler: FftFixedIn<f32>,
+    /// Per-channel frames waiting for a full chunk.
+    pending: Vec<Vec<f32>>,
+}
+
+impl<const CHANNELS: usize, const SAMPLE_RATE: u32> CaptureResampler<CHANNELS, SAMPLE_RATE> {
+    /// Resampler from `device_rate`, or `None` if it is `SAMPLE_RATE`.
+    pub fn new(device_rate: u32) -> Result<Option<Self>> {
+        if device_rate == SAMPLE_RATE {
+            return Ok(None);
+        }
+        let chunk = (device_rate * CAPTURE_CHUNK_MS / 1000).max(1) as usize;
+        let resampler = FftFixedIn::<f32>::new(
+            device_rate as usize,
+            SAMPLE_RATE as usize,
+            chunk,
+            1,
+            CHANNELS,
+        )
+        .context(format!(
+            "Can't resample {device_rate} Hz capture to {SAMPLE_RATE} Hz"
+        ))?;
+        Ok(Some(Self {
+            resampler,
+            pending: vec![Vec::new(); CHANNELS],
+        }))
+    }
+
+    /// Resamples one callback's interleaved samples. Returns the full
+    /// chunks resampled so far, possibly none.
+    pub fn process<Sample: AudioSample>(&mut self, interleaved: &[Sample]) -> Vec<Sample> {
+        for frame in interleaved.chunks_exact(CHANNELS) {
+            for (pending, sample) in self.pending.iter_mut().zip(frame) {
+                pending.push(sample.to_f64_normalized() as f32);
+            }
+        }
+
+        let mut output = Vec::new();
+        loop {
+            let needed = self.resampler.input_frames_next();
+            if self.pending[0].len() < needed {
+                break;
+            }
+            let chunks: Vec<&[f32]> = self.pending.iter().map(|c| &c[..needed]).collect();
+            let resampled = self
+                .resampler
+                .process(&chunks, None)
+                .expect("Resampling failed");
+            for pending in &mut self.pending {
+                pending.drain(..needed);
+            }
+            output.reserve(resampled[0].len() * CHANNELS);
+            for i in 0..resampled[0].len() {
+                for channel in &resampled {
+                    output.push(Sample::from_f64_normalized(channel[i] as f64));
+                }
+            }
+        }
+        output
+    }
+}
+
+/// Converts samples captured in a device's format `T` to the pipeline's.
+pub fn samples_from_device<T, Sample>(device: &[T]) -> Vec<Sample>
+where
+    T: cpal::Sample,
+    f64: cpal::FromSample<T>,
+    Sample: AudioSample,
+{
+    device
+        .iter()
+        .map(|&s| Sample::from_f64_normalized(<f64 as cpal::FromSample<T>>::from_sample_(s)))
+        .collect()
+}
+
+/// Converts pipeline samples into a device's format `T`, filling as much of
+/// `device` as `data` covers.
+pub fn samples_to_device<Sample, T>(data: &[Sample], device: &mut [T])
+where
+    Sample: AudioSample,
+    T: cpal::Sample + cpal::FromSample<f64>,
+{
+    for (out, sample) in device.iter_mut().zip(data) {
+        *out = T::from_sample(sample.to_f64_normalized());
+    }
+}
+
+/// Copies interleaved `mix` into interleaved `device` frames of
+/// `device_channels` channels, pipeline channel `i` landing on hardware
+/// channel `map[i]`. Unmapped channels, and frames past the end of `mix`,
+/// are silent.
+pub fn map_output_channels<Sample: AudioSample>(
+    mix: &[Sample],
+    map: &[usize],
+    device: &mut [Sample],
+    device_channels: usize,
+) {
+    device.fill(Sample::silence());
+    for (out_frame, mix_frame) in device
+        .chunks_exact_mut(device_channels)
+        .zip(mix.chunks_exact(map.len()))
+    {
+        for (&channel, &sample) in map.iter().zip(mix_frame) {
+            out_frame[channel] = sample;
+        }
+    }
+}
+
+/// Default cap on the frames taken from the output source in one pull,
+/// about 85 ms at 48 kHz and far above the usual callback size.
+pub const DEFAULT_MAX_OUTPUT_PULL_FRAMES: usize = 4096;
+
+/// Fills interleaved `out` from `source` in pulls of at most `max_frames`
+/// frames each, so an oversized device callback, as some drivers request
+/// during glitches, neither holds the pipeline's locks nor allocates for
+/// all of it at once. Whatever the source can't fill is silent.
+pub fn pull_chunked<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>(
+    source: &dyn Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>,
+    out: &mut [Sample],
+    max_frames: usize,
+) {
+    for chunk in out.chunks_mut(max_frames.max(1) * CHANNELS) {
+        let filled = match source.pull(chunk.len()) {
+            Some(buffer) => {
+                let src = buffer.data();
+                let len = src.len().min(chunk.len());
+                chunk[..len].copy_from_slice(&src[..len]);
+                len
+            }
+            None => 0,
+        };
+        chunk[filled..].fill(Sample::silence());
+    }
+}
+
+/// Device sample formats the mix can be converted to, best first.
+const DEVICE_FORMATS: [SampleFormat; 3] = [SampleFormat::F32, SampleFormat::I16, SampleFormat::I32];
+
+/// Picks the sample format to open an output device with, given the ones it
+/// supports: `preferred` if set, otherwise the first of [`DEVICE_FORMATS`]
+/// the device has.
+pub fn choose_output_format(
+    supported: &[SampleFormat],
+    preferred: Option<SampleFormat>,
+) -> Result<SampleFormat> {
+    anyhow::ensure!(
+        !supported.is_empty(),
+        "Output device reports no supported sample formats"
+    );
+    if let Some(format) = preferred {
+        anyhow::ensure!(
+            DEVICE_FORMATS.contains(&format),
+            "Can't play {format:?} output, only {DEVICE_FORMATS:?}"
+        );
+        anyhow::ensure!(
+            supported.contains(&format),
+            "Output device doesn't support {format:?}, only {supported:?}"
+        );
+        return Ok(format);
+    }
+    DEVICE_FORMATS
+        .into_iter()
+        .find(|format| supported.contains(format))
+        .with_context(|| {
+            format!("Output device supports none of {DEVICE_FORMATS:?}, only {supported:?}")
+        })
+}
+
+/// Picks the sample format to capture with: the device's native `default`
+/// when it's one we convert, otherwise the best of `supported` as for
+/// output.
+pub fn choose_input_format(
+    default: SampleFormat,
+    supported: &[SampleFormat],
+) -> Result<SampleFormat> {
+    if DEVICE_FORMATS.contains(&default) {
+        return Ok(default);
+    }
+    choose_output_format(supported, None)
 }
 
 /// Plays audio to the default output device (speakers).
 pub struct AudioOutput<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     source: Arc<dyn Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+    /// Hardware channel for each pipeline channel; `None` plays on the
+    /// first `CHANNELS` outputs.
+    channel_map: Option<Vec<usize>>,
+    /// Device sample format to use; `None` picks the best supported one.
+    sample_format: Option<SampleFormat>,
+    /// Most frames pulled from the source at once.
+    max_pull_frames: usize,
 }
 
 impl<Sample: AudioSample + cpal::SizedSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
     AudioOutput<Sample, CHANNELS, SAMPLE_RATE>
 {
     pub fn new(source: Arc<dyn Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>) -> Self {
-        Self { source }
+        Self {
+            source,
+            channel_map: None,
+            sample_format: None,
+            max_pull_frames: DEFAULT_MAX_OUTPUT_PULL_FRAMES,
+        }
+    }
+
+    /// Plays pipeline channel `i` on hardware output `map[i]` (0-based), e.g.
+    /// `[2, 3]` for outputs 3/4 of a multichannel interface. The device is
+    /// opened with all of its channels; unmapped ones stay silent.
+    pub fn with_channel_map(mut self, map: Option<Vec<usize>>) -> Self {
+        self.channel_map = map;
+        self
+    }
+
+    /// Opens the device with `format` instead of the best one it supports.
+    pub fn with_sample_format(mut self, format: Option<SampleFormat>) -> Self {
+        self.sample_format = format;
+        self
+    }
+
+    /// Splits device callbacks larger than `frames` into several pulls
+    /// from the source. See [`pull_chunked`].
+    pub fn with_max_pull_frames(mut self, frames: usize) -> Self {
+        self.max_pull_frames = frames;
+        self
     }
 
     pub fn start(self, device_id: Option<&DeviceId>) -> Result<cpal::Stream> {
@@ -210,8 +531,36 @@ impl<Sample: AudioSample + cpal::SizedSample, const CHANNELS: usize, const SAMPL
         let output_config = output_device.default_output_config()?;
         debug!("Output config: {output_config:#?}");
 
+        let supported: Vec<SampleFormat> = output_device
+            .supported_output_configs()
+            .context("Failed to list supported output configs")?
+            .filter(|range| {
+                (range.min_sample_rate()..=range.max_sample_rate()).contains(&SAMPLE_RATE)
+            })
+            .map(|range| range.sample_format())
+            .collect();
+        let sample_format = choose_output_format(&supported, self.sample_format)
+            .context(format!("No usable output format at {SAMPLE_RATE} Hz"))?;
+        info!("Output sample format: {sample_format:?} (supported: {supported:?})");
+
+        let device_channels = match &self.channel_map {
+            Some(map) => {
+                anyhow::ensure!(
+                    map.len() == CHANNELS,
+                    "Channel map {map:?} must list {CHANNELS} output channels"
+                );
+                let highest = map.iter().max().map_or(0, |&c| c + 1);
+                highest.max(output_config.channels() as usize)
+            }
+            None => CHANNELS,
+        };
+        info!(
+            "Output channel map: {:?} of {} channels",
+            self.channel_map, device_channels
+        );
+
         let config = StreamConfig {
-            channels: CHANNELS as u16,
+            channels: device_channels as u16,
             sample_rate: SAMPLE_RATE,
             buffer_size: match output_config.buffer_size() {
                 cpal::SupportedBufferSize::Range { min, max } => {
@@ -230,29 +579,218 @@ impl<Sample: AudioSample + cpal::SizedSample, const CHANNELS: usize, const SAMPL
             },
         };
 
+        let stream = match sample_format {
+            SampleFormat::F32 => self.build_stream::<f32>(&output_device, config, device_channels),
+            SampleFormat::I16 => self.build_stream::<i16>(&output_device, config, device_channels),
+            SampleFormat::I32 => self.build_stream::<i32>(&output_device, config, device_channels),
+            other => unreachable!("{other:?} is never chosen"),
+        }?;
+        stream.play()?;
+        Ok(stream)
+    }
+
+    /// Builds a stream writing `T` samples, converting the mix as it goes.
+    fn build_stream<T: cpal::SizedSample + cpal::FromSample<f64>>(
+        self,
+        device: &Device,
+        config: StreamConfig,
+        device_channels: usize,
+    ) -> Result<cpal::Stream> {
         let source = self.source;
+        let channel_map = self.channel_map;
+        let max_pull_frames = self.max_pull_frames;
+        let mut mix = Vec::new();
+        let mut mapped = Vec::new();
         debug!("Building output stream");
-        let stream = output_device.build_output_stream(
+        let stream = device.build_output_stream(
             config,
-            move |data: &mut [Sample], _: &cpal::OutputCallbackInfo| {
-                if let Some(frame) = source.pull(data.len()) {
-                    let src = frame.data();
-                    let len = src.len().min(data.len());
-                    data[..len].copy_from_slice(&src[..len]);
-                    // Zero any remaining samples the source couldn't fill.
-                    for sample in &mut data[len..] {
-                        *sample = Sample::silence();
-                    }
+            move |device_data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let frames = device_data.len() / device_channels;
+                mix.resize(frames * CHANNELS, Sample::silence());
+                pull_chunked(&*source, &mut mix, max_pull_frames);
+                let data = if let Some(map) = &channel_map {
+                    mapped.resize(device_data.len(), Sample::silence());
+                    map_output_channels(&mix, map, &mut mapped, device_channels);
+                    &mapped
                 } else {
-                    for sample in data {
-                        *sample = Sample::silence();
-                    }
-                }
+                    &mix
+                };
+                samples_to_device(data, device_data);
             },
             |err| error!("An error occurred on the output audio stream: {}", err),
             None,
         )?;
-        stream.play()?;
         Ok(stream)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stereo_mix_lands_on_mapped_hardware_channels() {
+        let mix = [0.1f32, 0.2, 0.3, 0.4];
+        let mut device = [9.0f32; 12];
+
+        map_output_channels(&mix, &[2, 3], &mut device, 6);
+
+        assert_eq!(
+            device,
+            [0.0, 0.0, 0.1, 0.2, 0.0, 0.0, 0.0, 0.0, 0.3, 0.4, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn channels_can_be_swapped_and_short_mix_is_padded() {
+        let mix = [0.1f32, 0.2];
+        let mut device = [9.0f32; 8];
+
+        map_output_channels(&mix, &[3, 0], &mut device, 4);
+
+        assert_eq!(device, [0.2, 0.0, 0.0, 0.1, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    /// Hands out increasing samples, up to `short_at` per pull, and
+    /// records the lengths asked for.
+    struct CountingSource {
+        next: Mutex<f32>,
+        requests: Mutex<Vec<usize>>,
+        short_at: usize,
+    }
+
+    impl Pullable<AudioBuffer<f32, 2, 48000>> for CountingSource {
+        fn pull(&self, len: usize) -> Option<AudioBuffer<f32, 2, 48000>> {
+            self.requests.lock().unwrap().push(len);
+            let mut next = self.next.lock().unwrap();
+            let samples = (0..len.min(self.short_at))
+                .map(|_| {
+                    *next += 1.0;
+                    *next
+                })
+                .collect();
+            AudioBuffer::new(samples).ok()
+        }
+    }
+
+    #[test]
+    fn oversized_pull_is_split_into_capped_chunks() {
+        let source = CountingSource {
+            next: Mutex::new(0.0),
+            requests: Mutex::new(Vec::new()),
+            short_at: usize::MAX,
+        };
+        let mut out = vec![-1.0f32; 10_000 * 2];
+
+        pull_chunked(&source, &mut out, 4096);
+
+        assert_eq!(*source.requests.lock().unwrap(), [8192, 8192, 3616]);
+        assert_eq!(out.len(), 20_000);
+        assert!(out.iter().enumerate().all(|(i, &s)| s == (i + 1) as f32));
+
+        // A chunk the source can't fill is padded, and the next one is
+        // still asked for in full.
+        let source = CountingSource {
+            next: Mutex::new(0.0),
+            requests: Mutex::new(Vec::new()),
+            short_at: 10,
+        };
+        let mut out = vec![-1.0f32; 10 * 2];
+        pull_chunked(&source, &mut out, 8);
+        assert_eq!(*source.requests.lock().unwrap(), [16, 4]);
+        let expected: Vec<f32> = (1..=10)
+            .chain([0; 6])
+            .chain(11..=14)
+            .map(|s| s as f32)
+            .collect();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn output_format_prefers_f32_then_i16_then_i32() {
+        use SampleFormat::*;
+
+        assert_eq!(choose_output_format(&[I16, F32, I32], None).unwrap(), F32);
+        assert_eq!(choose_output_format(&[I32, U8, I16], None).unwrap(), I16);
+        assert_eq!(choose_output_format(&[F64, I32], None).unwrap(), I32);
+        assert_eq!(choose_output_format(&[I16, F32], Some(I16)).unwrap(), I16);
+
+        assert!(choose_output_format(&[], None).is_err());
+        assert!(choose_output_format(&[U8, F64], None).is_err());
+        assert!(choose_output_format(&[F32], Some(I16)).is_err());
+    }
+
+    #[test]
+    fn input_prefers_the_native_format() {
+        use SampleFormat::*;
+
+        assert_eq!(choose_input_format(I16, &[F32, I16]).unwrap(), I16);
+        assert_eq!(choose_input_format(U8, &[U8, I32, F32]).unwrap(), F32);
+        assert!(choose_input_format(U8, &[U8]).is_err());
+    }
+
+    #[test]
+    fn capture_rate_is_the_pipelines_when_offered() {
+        assert_eq!(choose_capture_rate(48000, 44100, &[8000..=48000]), 48000);
+        assert_eq!(choose_capture_rate(48000, 44100, &[44100..=44100]), 44100);
+        assert_eq!(
+            choose_capture_rate(48000, 44100, &[44100..=44100, 48000..=96000]),
+            48000
+        );
+    }
+
+    #[test]
+    fn capture_at_44100_comes_out_at_48000() {
+        assert!(
+            CaptureResampler::<2, 48000>::new(48000).unwrap().is_none(),
+            "a matching device isn't resampled"
+        );
+
+        let mut resampler = CaptureResampler::<2, 48000>::new(44100).unwrap().unwrap();
+        let chunk_out = resampler.resampler.output_frames_next();
+
+        // One second of a 441 Hz tone, in 10 ms callbacks.
+        let mut produced = 0;
+        for callback in 0..100 {
+            let samples: Vec<f32> = (0..441)
+                .flat_map(|i| {
+                    let t = (callback * 441 + i) as f32 / 44100.0;
+                    let s = (t * 441.0 * std::f32::consts::TAU).sin() * 0.5;
+                    [s, s]
+                })
+                .collect();
+            let output = resampler.process(&samples);
+            assert_eq!(output.len() % 2, 0, "whole frames");
+            produced += output.len() / 2;
+        }
+
+        // All of it, less what's held back for the next chunk.
+        assert!(produced <= 48000, "produced {produced}");
+        assert!(48000 - produced <= chunk_out, "produced {produced}");
+    }
+
+    #[test]
+    fn i16_device_round_trips_through_f32_processing() {
+        let captured: Vec<i16> = vec![0, 1, -1, 12_345, -20_000, i16::MAX, i16::MIN];
+
+        let pipeline: Vec<f32> = samples_from_device(&captured);
+        for (&device, &sample) in captured.iter().zip(&pipeline) {
+            assert!((sample - device as f32 / 32768.0).abs() < 1e-6);
+        }
+
+        // Halve in f32, then back out to the device.
+        let processed: Vec<f32> = pipeline.iter().map(|s| s * 0.5).collect();
+        let mut played = vec![0i16; captured.len()];
+        samples_to_device(&processed, &mut played);
+        for (&device, &out) in captured.iter().zip(&played) {
+            assert!(
+                (out as i32 - device as i32 / 2).abs() <= 1,
+                "{device} halved to {out}"
+            );
+        }
+
+        // Unprocessed, the round trip is exact.
+        samples_to_device(&pipeline, &mut played);
+        assert_eq!(played, captured);
+    }
+}
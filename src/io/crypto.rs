@@ -0,0 +1,174 @@
+//! Optional encryption of everything sent to the multicast group.
+//!
+//! Everyone at a party enters the same [`Passphrase`], from which each
+//! derives the same key with PBKDF2; no key is ever exchanged. Every
+//! datagram is then sealed with ChaCha20-Poly1305 under a random nonce,
+//! sent in front of the ciphertext:
+//!
+//! ```text
+//! [nonce: 12 bytes][ciphertext + tag]
+//! ```
+//!
+//! A datagram that doesn't authenticate, whether sent without encryption,
+//! under another passphrase, or tampered with, is dropped.
+
+use std::sync::Arc;
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use sha2::Sha256;
+
+const NONCE_LEN: usize = 12;
+/// Fixed, so that everyone derives the same key from the same passphrase.
+const KDF_SALT: &[u8] = b"wifi-party packet key v1";
+const KDF_ROUNDS: u32 = 100_000;
+
+/// Passphrase shared by everyone at a party.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Passphrase(String);
+
+impl Passphrase {
+    /// `None` for an empty passphrase, which means no encryption.
+    pub fn new(passphrase: impl Into<String>) -> Option<Self> {
+        let passphrase = passphrase.into();
+        (!passphrase.is_empty()).then_some(Self(passphrase))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Passphrase(..)")
+    }
+}
+
+/// Seals and opens datagrams with the key derived from a [`Passphrase`].
+pub struct PacketCipher {
+    cipher: ChaCha20Poly1305,
+}
+
+impl PacketCipher {
+    /// Derives the key, which takes a moment; go through a [`CipherCache`]
+    /// to do it only when the passphrase changes.
+    pub fn new(passphrase: &Passphrase) -> Self {
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.0.as_bytes(), KDF_SALT, KDF_ROUNDS, &mut key);
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+        }
+    }
+
+    /// Encrypts `plaintext` under a fresh random nonce.
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .expect("ChaCha20-Poly1305 encryption");
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// Decrypts a datagram from [`seal`](Self::seal), or `None` if it
+    /// doesn't authenticate under our key.
+    pub fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .ok()
+    }
+}
+
+/// The [`PacketCipher`] for the passphrase last asked for, so restarting a
+/// party under the same passphrase doesn't derive the key again.
+#[derive(Default)]
+pub struct CipherCache(Option<(Passphrase, Arc<PacketCipher>)>);
+
+impl CipherCache {
+    /// Cipher for `passphrase`, or `None` without one. The key is derived
+    /// only if the passphrase differs from the previous call's.
+    pub fn get(&mut self, passphrase: Option<&Passphrase>) -> Option<Arc<PacketCipher>> {
+        let Some(passphrase) = passphrase else {
+            self.0 = None;
+            return None;
+        };
+        match &self.0 {
+            Some((cached, cipher)) if cached == passphrase => Some(cipher.clone()),
+            _ => {
+                let cipher = Arc::new(PacketCipher::new(passphrase));
+                self.0 = Some((passphrase.clone(), cipher.clone()));
+                Some(cipher)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::party::tagged_packet::{REALTIME_TAG, TaggedPacket};
+
+    fn cipher(passphrase: &str) -> PacketCipher {
+        PacketCipher::new(&Passphrase::new(passphrase).unwrap())
+    }
+
+    #[test]
+    fn packet_round_trips() {
+        let packet = TaggedPacket::new(REALTIME_TAG, vec![1, 2, 3, 4]);
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&packet).unwrap();
+
+        let sealed = cipher("open sesame").seal(&bytes);
+        assert_ne!(&sealed[NONCE_LEN..], &bytes[..]);
+
+        let opened = cipher("open sesame").open(&sealed).unwrap();
+        let packet = rkyv::from_bytes::<TaggedPacket, rkyv::rancor::Error>(&opened).unwrap();
+        assert_eq!(packet.tag, REALTIME_TAG);
+        assert_eq!(packet.payload, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn wrong_key_or_tampering_is_dropped() {
+        let sealed = cipher("open sesame").seal(b"audio");
+
+        assert!(cipher("let me in").open(&sealed).is_none());
+        assert!(cipher("open sesame").open(b"audio").is_none());
+        assert!(cipher("open sesame").open(&[]).is_none());
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(cipher("open sesame").open(&tampered).is_none());
+    }
+
+    #[test]
+    fn key_is_derived_again_only_for_a_new_passphrase() {
+        let mut cache = CipherCache::default();
+        let sesame = Passphrase::new("open sesame");
+        let first = cache.get(sesame.as_ref()).unwrap();
+        let again = cache.get(sesame.as_ref()).unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+
+        let other = cache.get(Passphrase::new("let me in").as_ref()).unwrap();
+        assert!(!Arc::ptr_eq(&first, &other));
+        assert!(cache.get(None).is_none());
+        let sealed = first.seal(b"audio");
+        assert!(cache.get(sesame.as_ref()).unwrap().open(&sealed).is_some());
+    }
+
+    #[test]
+    fn empty_passphrase_means_no_encryption() {
+        assert!(Passphrase::new("").is_none());
+        assert_eq!(
+            format!("{:?}", Passphrase::new("secret")),
+            "Some(Passphrase(..))"
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Detects network interface changes that call for rebinding the sockets.
+//!
+//! Multicast membership is joined per interface when the socket is created.
+//! VPN clients add and remove tun interfaces and reroute traffic at runtime,
+//! which silently leaves the socket on a stale set of interfaces. Comparing
+//! periodic [`InterfaceSet`] snapshots tells when that happened. Only
+//! changes affecting the interface the group's traffic is routed through
+//! count; adapters coming and going elsewhere leave the sockets alone.
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket};
+
+use anyhow::Result;
+use network_interface::NetworkInterfaceConfig;
+
+/// Non-loopback interfaces carrying addresses of one IP family, by index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterfaceSet {
+    interfaces: BTreeMap<u32, (String, Vec<IpAddr>)>,
+    /// Index of the interface the system routes the group's traffic
+    /// through, if known.
+    route: Option<u32>,
+}
+
+/// Names of the interfaces that differ between two [`InterfaceSet`]s.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterfaceDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Still present, but with different addresses.
+    pub changed: Vec<String>,
+    /// Indices of the interfaces above.
+    touched: Vec<u32>,
+    /// The newer set's route.
+    route: Option<u32>,
+    /// The route moved to another interface.
+    rerouted: bool,
+}
+
+impl InterfaceSet {
+    /// The interfaces the system reports right now, with the one it routes
+    /// traffic to `group` through.
+    pub fn current(ipv6: bool, group: IpAddr) -> Result<Self> {
+        let interfaces = network_interface::NetworkInterface::show()?;
+        let set = Self::from_interfaces(
+            ipv6,
+            interfaces.into_iter().map(|iface| {
+                let addrs = iface.addr.iter().map(|addr| addr.ip()).collect();
+                (iface.index, iface.name, addrs)
+            }),
+        );
+        Ok(set.with_route(route_source(group)))
+    }
+
+    /// Marks the interface carrying `source`, the local address traffic to
+    /// the group is sent from, as the route.
+    pub fn with_route(mut self, source: Option<IpAddr>) -> Self {
+        self.route = source.and_then(|source| {
+            self.interfaces
+                .iter()
+                .find(|(_, (_, addrs))| addrs.contains(&source))
+                .map(|(&index, _)| index)
+        });
+        self
+    }
+
+    /// Keeps the interfaces with a non-loopback address of the chosen family.
+    pub fn from_interfaces(
+        ipv6: bool,
+        interfaces: impl IntoIterator<Item = (u32, String, Vec<IpAddr>)>,
+    ) -> Self {
+        let mut set = Self::default();
+        for (index, name, addrs) in interfaces {
+            let mut addrs: Vec<IpAddr> = addrs
+                .into_iter()
+                .filter(|ip| ip.is_ipv6() == ipv6 && !ip.is_loopback())
+                .collect();
+            if addrs.is_empty() {
+                continue;
+            }
+            addrs.sort();
+            set.interfaces.insert(index, (name, addrs));
+        }
+        set
+    }
+
+    /// What changed going from `self` to `newer`.
+    pub fn diff(&self, newer: &Self) -> InterfaceDiff {
+        let mut diff = InterfaceDiff {
+            route: newer.route,
+            rerouted: self.route != newer.route,
+            ..Default::default()
+        };
+        for (index, (name, addrs)) in &newer.interfaces {
+            match self.interfaces.get(index) {
+                None => diff.added.push(name.clone()),
+                Some((_, old_addrs)) if old_addrs != addrs => diff.changed.push(name.clone()),
+                Some(_) => continue,
+            }
+            diff.touched.push(*index);
+        }
+        for (index, (name, _)) in &self.interfaces {
+            if !newer.interfaces.contains_key(index) {
+                diff.removed.push(name.clone());
+                diff.touched.push(*index);
+            }
+        }
+        diff
+    }
+}
+
+impl InterfaceDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && !self.rerouted
+    }
+
+    /// Whether the sockets should be rebound. Only with automatic interface
+    /// selection (`send_interface_index` of `None`); an interface the user
+    /// picked is kept as is. Then only when the route moved or its
+    /// interface changed, or on any change while the route is unknown.
+    pub fn needs_rebind(&self, send_interface_index: Option<u32>) -> bool {
+        if send_interface_index.is_some() || self.is_empty() {
+            return false;
+        }
+        match self.route {
+            Some(route) => self.rerouted || self.touched.contains(&route),
+            None => true,
+        }
+    }
+}
+
+/// The local address the system sends traffic to `group` from. Connecting
+/// a UDP socket only picks the route; nothing is sent.
+fn route_source(group: IpAddr) -> Option<IpAddr> {
+    let unspecified: IpAddr = match group {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    let socket = UdpSocket::bind((unspecified, 0)).ok()?;
+    socket.connect((group, 9)).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn vpn_interface_changes_rebind_only_with_auto_selection() {
+        let lan = (
+            2,
+            "en0".to_string(),
+            vec![ip("192.168.1.20"), ip("fe80::1")],
+        );
+        let loopback = (1, "lo".to_string(), vec![ip("127.0.0.1")]);
+        let tun = (7, "utun3".to_string(), vec![ip("10.8.0.2")]);
+
+        let before = InterfaceSet::from_interfaces(false, [loopback.clone(), lan.clone()]);
+        let with_vpn =
+            InterfaceSet::from_interfaces(false, [loopback.clone(), lan.clone(), tun.clone()]);
+
+        let connected = before.diff(&with_vpn);
+        assert_eq!(connected.added, ["utun3"]);
+        assert!(connected.removed.is_empty());
+        assert!(connected.needs_rebind(None));
+        assert!(!connected.needs_rebind(Some(2)), "explicit choice is kept");
+
+        let disconnected = with_vpn.diff(&before);
+        assert_eq!(disconnected.removed, ["utun3"]);
+        assert!(disconnected.needs_rebind(None));
+
+        let readdressed = InterfaceSet::from_interfaces(
+            false,
+            [loopback, (2, "en0".to_string(), vec![ip("192.168.1.21")])],
+        );
+        assert_eq!(before.diff(&readdressed).changed, ["en0"]);
+
+        // A VPN that only brings IPv4 doesn't touch an IPv6 socket.
+        let v6_before = InterfaceSet::from_interfaces(true, [lan.clone()]);
+        let v6_after = InterfaceSet::from_interfaces(true, [lan, tun]);
+        let v6_diff = v6_before.diff(&v6_after);
+        assert!(v6_diff.is_empty());
+        assert!(!v6_diff.needs_rebind(None));
+    }
+
+    #[test]
+    fn only_changes_to_the_routed_interface_rebind() {
+        let lan = (2, "en0".to_string(), vec![ip("192.168.1.20")]);
+        let docker = (5, "docker0".to_string(), vec![ip("172.17.0.1")]);
+        let tun = (7, "utun3".to_string(), vec![ip("10.8.0.2")]);
+        let via = |source: &str| Some(ip(source));
+
+        let before =
+            InterfaceSet::from_interfaces(false, [lan.clone()]).with_route(via("192.168.1.20"));
+
+        // An unrelated adapter comes up; the group is still routed via en0.
+        let with_docker = InterfaceSet::from_interfaces(false, [lan.clone(), docker.clone()])
+            .with_route(via("192.168.1.20"));
+        let unrelated = before.diff(&with_docker);
+        assert_eq!(unrelated.added, ["docker0"]);
+        assert!(!unrelated.needs_rebind(None));
+
+        // A VPN comes up and takes the route.
+        let with_vpn = InterfaceSet::from_interfaces(false, [lan.clone(), tun.clone()])
+            .with_route(via("10.8.0.2"));
+        assert!(before.diff(&with_vpn).needs_rebind(None));
+        assert!(
+            with_vpn.diff(&before).needs_rebind(None),
+            "and gives it back"
+        );
+
+        // The routed interface itself gets a new address.
+        let readdressed = InterfaceSet::from_interfaces(
+            false,
+            [(2, "en0".to_string(), vec![ip("192.168.1.21")])],
+        )
+        .with_route(via("192.168.1.21"));
+        let readdressed = before.diff(&readdressed);
+        assert_eq!(readdressed.changed, ["en0"]);
+        assert!(readdressed.needs_rebind(None));
+        assert!(
+            !readdressed.needs_rebind(Some(2)),
+            "explicit choice is kept"
+        );
+    }
+}
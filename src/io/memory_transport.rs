@@ -0,0 +1,74 @@
+//! In-memory stand-in for the multicast socket.
+//!
+//! [`MemoryNetwork`] lets several in-process party instances talk to each
+//! other without real sockets. Each instance joins with its own address and
+//! [`StreamRegistry`], and gets a [`MemoryEndpoint`] to use wherever a
+//! [`NetworkSender`](super::NetworkSender) would go. Packets are serialized
+//! exactly like on the wire and delivered synchronously to every other
+//! member, mirroring multicast with self-echo already filtered.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use tracing::error;
+
+use crate::audio::AudioSample;
+use crate::party::network_stream::StreamRegistry;
+use crate::party::tagged_packet::TaggedPacket;
+use crate::pipeline::Pushable;
+
+type Member<S, const C: usize, const SR: u32> = (SocketAddr, Arc<StreamRegistry<S, C, SR>>);
+
+/// A shared in-memory "network" that all members multicast to.
+pub struct MemoryNetwork<S: AudioSample, const C: usize, const SR: u32> {
+    members: Mutex<Vec<Member<S, C, SR>>>,
+}
+
+impl<S: AudioSample, const C: usize, const SR: u32> MemoryNetwork<S, C, SR> {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            members: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Adds a member that receives packets through `registry`.
+    pub fn join(
+        self: &Arc<Self>,
+        addr: SocketAddr,
+        registry: Arc<StreamRegistry<S, C, SR>>,
+    ) -> MemoryEndpoint<S, C, SR> {
+        self.members.lock().unwrap().push((addr, registry));
+        MemoryEndpoint {
+            network: self.clone(),
+            addr,
+        }
+    }
+
+    fn deliver(&self, from: SocketAddr, data: &[u8]) {
+        let members = self.members.lock().unwrap().clone();
+        for (addr, registry) in members {
+            if addr == from {
+                continue;
+            }
+            if let Err(e) = registry.dispatch(from, data) {
+                error!("Packet handling error: {:?}", e);
+            }
+        }
+    }
+}
+
+/// One member's sending side of a [`MemoryNetwork`].
+pub struct MemoryEndpoint<S: AudioSample, const C: usize, const SR: u32> {
+    network: Arc<MemoryNetwork<S, C, SR>>,
+    addr: SocketAddr,
+}
+
+impl<S: AudioSample, const C: usize, const SR: u32> Pushable<TaggedPacket>
+    for MemoryEndpoint<S, C, SR>
+{
+    fn push(&self, input: TaggedPacket) {
+        let serialized =
+            rkyv::to_bytes::<rkyv::rancor::Error>(&input).expect("TaggedPacket serialization");
+        self.network.deliver(self.addr, &serialized);
+    }
+}
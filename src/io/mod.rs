@@ -5,18 +5,26 @@
 //! - [`AudioInput`] / [`AudioOutput`] - Microphone capture and speaker playback via cpal
 //! - [`LoopbackInput`] - System audio capture (loopback recording) via cpal
 //! - [`network`] - UDP multicast socket creation and [`NetworkSender`]
+//! - [`crypto`] - Optional passphrase encryption of every datagram
+//! - [`interface_watch`] - Detects interface changes that call for a socket rebind
+//! - [`memory_transport`] - In-process network for end-to-end tests (test builds only)
 //! - [`MulticastLock`] - Android multicast lock (no-op on other platforms)
 //! - [`file_picker`] - Native file picker for Android (JNI-based)
 
 pub mod audio;
+pub mod crypto;
 pub mod file_picker;
+pub mod interface_watch;
+#[cfg(test)]
+pub mod memory_transport;
 pub mod multicast_lock;
 pub mod network;
 
 pub use audio::{AudioInput, AudioOutput, LoopbackInput};
+pub use crypto::{CipherCache, PacketCipher, Passphrase};
 pub use file_picker::{FilePickerResult, pick_audio_file};
 pub use multicast_lock::MulticastLock;
 pub use network::{
-    MULTICAST_ADDR_V4, MULTICAST_ADDR_V6, MULTICAST_PORT, NetworkSender, SendTarget, TTL,
-    create_multicast_socket,
+    MULTICAST_ADDR_V4, MULTICAST_ADDR_V6, MULTICAST_PORT, MulticastConfig, NetworkSender,
+    SendStats, SendTarget, TTL, create_multicast_socket,
 };
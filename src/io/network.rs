@@ -6,6 +6,9 @@
 //!
 //! # Multicast Configuration
 //!
+//! The defaults below can be changed with [`MulticastConfig`], so that
+//! separate parties on one network don't hear each other.
+//!
 //! IPv4:
 //! - Address: `239.255.43.2` (administratively scoped multicast)
 //! - Port: `7667`
@@ -17,14 +20,16 @@
 //! - Hop limit: `1` (local network only)
 
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use network_interface::NetworkInterfaceConfig;
 use socket2::{Domain, Protocol, Socket, Type};
 use tracing::{error, info, warn};
 
-use crate::party::tagged_packet::TaggedPacket;
+use crate::io::crypto::PacketCipher;
+use crate::party::tagged_packet::{InstanceId, TaggedPacket, UNKNOWN_INSTANCE};
 use crate::pipeline::Pushable;
 
 pub const MULTICAST_ADDR_V4: &str = "239.255.43.2";
@@ -32,6 +37,59 @@ pub const MULTICAST_ADDR_V6: &str = "ff02::7667";
 pub const MULTICAST_PORT: u16 = 7667;
 pub const TTL: u32 = 1;
 
+/// Multicast group, port and TTL a party meets on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MulticastConfig {
+    /// Group to join. `None` uses [`MULTICAST_ADDR_V4`] or
+    /// [`MULTICAST_ADDR_V6`], whichever matches the address family.
+    pub group: Option<IpAddr>,
+    pub port: u16,
+    /// Hops our packets may take; 1 keeps them on the local network.
+    pub ttl: u32,
+}
+
+impl Default for MulticastConfig {
+    fn default() -> Self {
+        Self {
+            group: None,
+            port: MULTICAST_PORT,
+            ttl: TTL,
+        }
+    }
+}
+
+impl MulticastConfig {
+    /// The group to join over IPv6 or IPv4, checked to be a multicast
+    /// address of that family.
+    pub fn group_addr(&self, ipv6: bool) -> Result<IpAddr> {
+        let group = match self.group {
+            Some(group) => group,
+            None if ipv6 => MULTICAST_ADDR_V6
+                .parse::<Ipv6Addr>()
+                .context("Invalid IPv6 multicast address")?
+                .into(),
+            None => MULTICAST_ADDR_V4
+                .parse::<Ipv4Addr>()
+                .context("Invalid multicast address")?
+                .into(),
+        };
+        if group.is_ipv6() != ipv6 {
+            bail!(
+                "Multicast group {} is not an {} address",
+                group,
+                if ipv6 { "IPv6" } else { "IPv4" }
+            );
+        }
+        if !group.is_multicast() {
+            bail!(
+                "{} is not a multicast address (IPv4 groups are 224.0.0.0 to 239.255.255.255, IPv6 groups start with ff)",
+                group
+            );
+        }
+        Ok(group)
+    }
+}
+
 const DSCP_EF: u32 = 0xB8;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -153,17 +211,65 @@ fn allow_awdl(socket: &Socket, allow: bool) -> Result<()> {
     }
 }
 
+/// Multicast options of a socket, separate from [`Socket`] so that what
+/// [`apply_multicast_options`] sets can be checked without a network.
+trait MulticastOptions {
+    fn set_multicast_hops(&self, ipv6: bool, hops: u32) -> std::io::Result<()>;
+    fn set_multicast_loop(&self, ipv6: bool, enabled: bool) -> std::io::Result<()>;
+}
+
+impl MulticastOptions for Socket {
+    fn set_multicast_hops(&self, ipv6: bool, hops: u32) -> std::io::Result<()> {
+        if ipv6 {
+            self.set_multicast_hops_v6(hops)
+        } else {
+            self.set_multicast_ttl_v4(hops)
+        }
+    }
+
+    fn set_multicast_loop(&self, ipv6: bool, enabled: bool) -> std::io::Result<()> {
+        if ipv6 {
+            self.set_multicast_loop_v6(enabled)
+        } else {
+            self.set_multicast_loop_v4(enabled)
+        }
+    }
+}
+
+/// Limits multicast to the local network and sets whether our own packets
+/// are looped back to sockets on this host.
+///
+/// Some platforms don't loop them back by default, which leaves several
+/// instances on one machine deaf to each other. Own packets that do come
+/// back are dropped by the packet dispatcher unless self-filtering is off.
+fn apply_multicast_options(
+    socket: &impl MulticastOptions,
+    ipv6: bool,
+    ttl: u32,
+    multicast_loop: bool,
+) -> Result<()> {
+    socket
+        .set_multicast_hops(ipv6, ttl)
+        .context("Failed to set multicast hops")?;
+    socket
+        .set_multicast_loop(ipv6, multicast_loop)
+        .context("Failed to set multicast loopback")?;
+    Ok(())
+}
+
 /// Creates an IPv4 multicast socket ready for sending and receiving.
 ///
 /// Returns the socket, multicast address, list of local IPs (for filtering own
 /// packets), and the IP of the send interface (if one was explicitly chosen).
 pub fn create_multicast_socket_v4(
+    multicast: &MulticastConfig,
     send_interface_index: Option<u32>,
+    multicast_loop: bool,
 ) -> Result<(UdpSocket, SocketAddr, Vec<IpAddr>, Option<IpAddr>)> {
-    let multicast_ip: Ipv4Addr = MULTICAST_ADDR_V4
-        .parse()
-        .context("Invalid multicast address")?;
-    let multicast_addr = SocketAddr::new(IpAddr::V4(multicast_ip), MULTICAST_PORT);
+    let IpAddr::V4(multicast_ip) = multicast.group_addr(false)? else {
+        unreachable!("group_addr checks the address family");
+    };
+    let multicast_addr = SocketAddr::new(IpAddr::V4(multicast_ip), multicast.port);
 
     let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))
         .context("Failed to create socket")?;
@@ -175,12 +281,7 @@ pub fn create_multicast_socket_v4(
     socket
         .set_nonblocking(true)
         .context("Failed to set nonblocking")?;
-    socket
-        .set_multicast_ttl_v4(TTL)
-        .context("Failed to set multicast_ttl_v4")?;
-    socket
-        .set_multicast_loop_v4(false)
-        .context("Failed to set multicast_loop_v4")?;
+    apply_multicast_options(&socket, false, multicast.ttl, multicast_loop)?;
     set_socket_dscp(&socket, false);
     let _ = allow_awdl(&socket, true);
 
@@ -189,7 +290,7 @@ pub fn create_multicast_socket_v4(
     #[cfg(not(target_os = "android"))]
     let bind_ip = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
 
-    let bind_addr = SocketAddr::new(bind_ip, MULTICAST_PORT);
+    let bind_addr = SocketAddr::new(bind_ip, multicast.port);
     socket
         .bind(&bind_addr.into())
         .context(format!("Failed to bind to {:?}", bind_addr))?;
@@ -230,10 +331,7 @@ pub fn create_multicast_socket_v4(
         info!("Send interface set to {}", ip);
     }
 
-    info!(
-        "IPv4 multicast socket ready on {}:{}",
-        MULTICAST_ADDR_V4, MULTICAST_PORT
-    );
+    info!("IPv4 multicast socket ready on {}", multicast_addr);
     Ok((
         socket.into(),
         multicast_addr,
@@ -247,12 +345,14 @@ pub fn create_multicast_socket_v4(
 /// Returns the socket, multicast address, list of local IPs (for filtering own
 /// packets), and the IP of the send interface (if one was explicitly chosen).
 pub fn create_multicast_socket_v6(
+    multicast: &MulticastConfig,
     send_interface_index: Option<u32>,
+    multicast_loop: bool,
 ) -> Result<(UdpSocket, SocketAddr, Vec<IpAddr>, Option<IpAddr>)> {
-    let multicast_ip: Ipv6Addr = MULTICAST_ADDR_V6
-        .parse()
-        .context("Invalid IPv6 multicast address")?;
-    let multicast_addr = SocketAddr::V6(SocketAddrV6::new(multicast_ip, MULTICAST_PORT, 0, 0));
+    let IpAddr::V6(multicast_ip) = multicast.group_addr(true)? else {
+        unreachable!("group_addr checks the address family");
+    };
+    let multicast_addr = SocketAddr::V6(SocketAddrV6::new(multicast_ip, multicast.port, 0, 0));
 
     let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))
         .context("Failed to create IPv6 socket")?;
@@ -264,12 +364,7 @@ pub fn create_multicast_socket_v6(
     socket
         .set_nonblocking(true)
         .context("Failed to set nonblocking")?;
-    socket
-        .set_multicast_hops_v6(TTL)
-        .context("Failed to set multicast_hops_v6")?;
-    socket
-        .set_multicast_loop_v6(false)
-        .context("Failed to set multicast_loop_v6")?;
+    apply_multicast_options(&socket, true, multicast.ttl, multicast_loop)?;
     set_socket_dscp(&socket, true);
     let _ = allow_awdl(&socket, true);
 
@@ -280,7 +375,7 @@ pub fn create_multicast_socket_v6(
         info!("Send interface set to index {}", index);
     }
 
-    let bind_addr = SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, MULTICAST_PORT, 0, 0);
+    let bind_addr = SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, multicast.port, 0, 0);
     socket
         .bind(&bind_addr.into())
         .context(format!("Failed to bind to {:?}", bind_addr))?;
@@ -316,10 +411,7 @@ pub fn create_multicast_socket_v6(
         }
     }
 
-    info!(
-        "IPv6 multicast socket ready on [{}]:{}",
-        MULTICAST_ADDR_V6, MULTICAST_PORT
-    );
+    info!("IPv6 multicast socket ready on {}", multicast_addr);
     Ok((
         socket.into(),
         multicast_addr,
@@ -331,12 +423,37 @@ pub fn create_multicast_socket_v6(
 /// Creates a multicast socket based on the IPv6 flag.
 pub fn create_multicast_socket(
     ipv6: bool,
+    multicast: &MulticastConfig,
     send_interface_index: Option<u32>,
+    multicast_loop: bool,
 ) -> Result<(UdpSocket, SocketAddr, Vec<IpAddr>, Option<IpAddr>)> {
     if ipv6 {
-        create_multicast_socket_v6(send_interface_index)
+        create_multicast_socket_v6(multicast, send_interface_index, multicast_loop)
     } else {
-        create_multicast_socket_v4(send_interface_index)
+        create_multicast_socket_v4(multicast, send_interface_index, multicast_loop)
+    }
+}
+
+/// Packets a [`NetworkSender`] has sent, and dropped because the send
+/// failed, e.g. with the socket buffer full on a saturated uplink.
+#[derive(Debug, Default)]
+pub struct SendStats {
+    sent: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl SendStats {
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn record(&self, sent: bool) {
+        let counter = if sent { &self.sent } else { &self.dropped };
+        counter.fetch_add(1, Ordering::Relaxed);
     }
 }
 
@@ -354,6 +471,11 @@ pub struct NetworkSender {
     socket: Arc<UdpSocket>,
     multicast_addr: SocketAddr,
     send_target: Arc<Mutex<SendTarget>>,
+    stats: Arc<SendStats>,
+    /// Stamped on every packet sent.
+    instance: InstanceId,
+    /// Encrypts every datagram when set.
+    cipher: Option<Arc<PacketCipher>>,
 }
 
 impl NetworkSender {
@@ -368,11 +490,34 @@ impl NetworkSender {
             socket: Arc::new(socket),
             multicast_addr,
             send_target,
+            stats: Arc::new(SendStats::default()),
+            instance: UNKNOWN_INSTANCE,
+            cipher: None,
         }
     }
 
+    /// Encrypts every datagram with `cipher`; `None` sends in the clear.
+    pub fn with_cipher(mut self, cipher: Option<Arc<PacketCipher>>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Names this instance in every packet, so receivers recognize it
+    /// across address changes.
+    pub fn with_instance(mut self, instance: InstanceId) -> Self {
+        self.instance = instance;
+        self
+    }
+
+    /// Counters shared by all clones of this sender.
+    pub fn stats(&self) -> Arc<SendStats> {
+        self.stats.clone()
+    }
+
     fn send_packet(&self, packet: &TaggedPacket) {
-        if let Err(error) = self.send_inner(packet) {
+        let result = self.send_inner(packet);
+        self.stats.record(result.is_ok());
+        if let Err(error) = result {
             error!("{:?}", error);
         }
     }
@@ -380,6 +525,10 @@ impl NetworkSender {
     fn send_inner(&self, packet: &TaggedPacket) -> Result<()> {
         let serialized =
             rkyv::to_bytes::<rkyv::rancor::Error>(packet).context("Failed to serialize packet")?;
+        let serialized = match &self.cipher {
+            Some(cipher) => cipher.seal(&serialized),
+            None => serialized.into_vec(),
+        };
 
         let addr = self
             .send_target
@@ -400,7 +549,72 @@ impl NetworkSender {
 }
 
 impl Pushable<TaggedPacket> for NetworkSender {
-    fn push(&self, input: TaggedPacket) {
+    fn push(&self, mut input: TaggedPacket) {
+        input.instance = self.instance;
         self.send_packet(&input);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records the options set on it instead of touching a socket.
+    #[derive(Default)]
+    struct RecordingSocket {
+        set: Mutex<Vec<(&'static str, bool, u32)>>,
+    }
+
+    impl MulticastOptions for RecordingSocket {
+        fn set_multicast_hops(&self, ipv6: bool, hops: u32) -> std::io::Result<()> {
+            self.set.lock().unwrap().push(("hops", ipv6, hops));
+            Ok(())
+        }
+
+        fn set_multicast_loop(&self, ipv6: bool, enabled: bool) -> std::io::Result<()> {
+            self.set
+                .lock()
+                .unwrap()
+                .push(("loop", ipv6, enabled as u32));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn multicast_loopback_follows_the_config() {
+        for ipv6 in [false, true] {
+            for multicast_loop in [true, false] {
+                let socket = RecordingSocket::default();
+                apply_multicast_options(&socket, ipv6, TTL, multicast_loop).unwrap();
+                assert_eq!(
+                    *socket.set.lock().unwrap(),
+                    [("hops", ipv6, TTL), ("loop", ipv6, multicast_loop as u32)]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn multicast_group_must_be_a_multicast_address() {
+        let config = |group: &str| MulticastConfig {
+            group: Some(group.parse().unwrap()),
+            ..MulticastConfig::default()
+        };
+
+        assert_eq!(
+            config("239.255.43.2").group_addr(false).unwrap(),
+            "239.255.43.2".parse::<IpAddr>().unwrap()
+        );
+        let err = config("10.0.0.1").group_addr(false).unwrap_err();
+        assert!(err.to_string().contains("not a multicast address"));
+        assert!(config("ff02::7667").group_addr(false).is_err());
+        assert!(config("ff02::7667").group_addr(true).is_ok());
+    }
+
+    #[test]
+    fn default_groups_are_valid() {
+        let config = MulticastConfig::default();
+        assert!(config.group_addr(false).unwrap().is_ipv4());
+        assert!(config.group_addr(true).unwrap().is_ipv6());
+    }
+}
@@ -23,7 +23,7 @@ mod state;
 mod ui;
 
 use anyhow::{Context, Result};
-use party::PartyConfig;
+use party::{PartyConfig, saved_settings};
 use state::AppState;
 use tracing::{error, info};
 
@@ -64,7 +64,8 @@ fn run() -> Result<()> {
     //     .start()
     //     .expect("Failed to initialize detector");
 
-    let config = PartyConfig::default();
+    let mut config = PartyConfig::default();
+    saved_settings::restore(&mut config);
     let state = AppState::new(config).context("Failed to initialize application")?;
 
     info!("Application setup complete. Audio pipelines are live.");
@@ -126,7 +126,7 @@ fn file_button(ctx: MusicProviderContext, action: FileAction) -> Element {
 
                 let res = match action {
                     FileAction::PlayNow => ctx.play_now(bytes.to_vec(), file_name),
-                    FileAction::Queue => ctx.queue(bytes.to_vec(), file_name),
+                    FileAction::Queue => ctx.queue_file(bytes.to_vec(), file_name, file.path()),
                 };
                 if let Err(e) = res {
                     error!("Failed to submit audio: {}", e);
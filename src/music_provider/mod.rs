@@ -1,4 +1,5 @@
 use dioxus::core::Element;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 #[cfg(feature = "music-provider-apple-music")]
@@ -15,6 +16,7 @@ pub mod local_file;
 pub struct MusicProviderContext {
     play_now: Arc<dyn Fn(Vec<u8>, String) -> anyhow::Result<()> + Send + Sync>,
     queue: Arc<dyn Fn(Vec<u8>, String) -> anyhow::Result<()> + Send + Sync>,
+    queue_file: Option<Arc<dyn Fn(Vec<u8>, String, PathBuf) -> anyhow::Result<()> + Send + Sync>>,
 }
 
 impl MusicProviderContext {
@@ -29,9 +31,20 @@ impl MusicProviderContext {
         Self {
             play_now: Arc::new(play_now),
             queue: Arc::new(queue),
+            queue_file: None,
         }
     }
 
+    /// Queue songs that come from a local file through `queue_file`, which
+    /// also gets the file's path so the queue can be saved as a playlist.
+    pub fn with_queue_file(
+        mut self,
+        queue_file: impl Fn(Vec<u8>, String, PathBuf) -> anyhow::Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.queue_file = Some(Arc::new(queue_file));
+        self
+    }
+
     /// Start immediate playback of the given audio data.
     pub fn play_now(&self, data: Vec<u8>, title: String) -> anyhow::Result<()> {
         (self.play_now)(data, title)
@@ -41,6 +54,14 @@ impl MusicProviderContext {
     pub fn queue(&self, data: Vec<u8>, title: String) -> anyhow::Result<()> {
         (self.queue)(data, title)
     }
+
+    /// Add audio read from the local file at `path` to the shared playlist.
+    pub fn queue_file(&self, data: Vec<u8>, title: String, path: PathBuf) -> anyhow::Result<()> {
+        match &self.queue_file {
+            Some(queue_file) => queue_file(data, title, path),
+            None => self.queue(data, title),
+        }
+    }
 }
 
 /// Type alias for a provider factory function.
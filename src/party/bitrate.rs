@@ -0,0 +1,358 @@
+//! Opus bitrate adapted to the loss receivers report.
+//!
+//! Every receiver periodically multicasts a
+//! [`LossFeedback`](super::realtime_stream::LossFeedback) for each
+//! realtime stream it hears, with the loss its jitter buffer measures.
+//! Like NACKs, feedback names the sender by the newest frame received from
+//! it, so each sender only keeps the reports about its own streams, in
+//! [`LossReports`].
+//!
+//! [`BitrateController`] then steers each local stream's encoder AIMD
+//! style: while the worst receiver sees heavy loss the bitrate is cut by a
+//! factor, and while every receiver hears it cleanly it is raised by a
+//! fixed step, always within [`BitrateConfig::min_bitrate`] and
+//! [`BitrateConfig::max_bitrate`]. Between the two thresholds, or with no
+//! fresh reports, it is left where it is.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+use crate::audio::opus::OPUS_BITRATE;
+use crate::audio::{AudioSample, OpusEncoder};
+
+/// Bounds and pace of the bitrate adaptation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitrateConfig {
+    pub min_bitrate: i32,
+    pub max_bitrate: i32,
+    /// Added per check while no receiver sees much loss.
+    pub increase_step: i32,
+    /// The bitrate is multiplied by this per check with heavy loss.
+    pub decrease_factor: f64,
+    /// Worst reported loss rate (0.0 - 1.0) at which the bitrate is cut.
+    pub high_loss: f64,
+    /// Worst reported loss rate below which the bitrate is raised.
+    pub low_loss: f64,
+    /// How often receivers report and the bitrate is adjusted.
+    pub interval: Duration,
+    /// Reports older than this are ignored, e.g. from a receiver that left.
+    pub report_timeout: Duration,
+}
+
+impl BitrateConfig {
+    /// These bounds lowered to at most `bitrate`, an encoder's configured
+    /// bitrate, so adaptation never raises a stream above what was chosen
+    /// for it.
+    pub fn capped_at(self, bitrate: i32) -> Self {
+        Self {
+            min_bitrate: self.min_bitrate.min(bitrate),
+            max_bitrate: self.max_bitrate.min(bitrate),
+            ..self
+        }
+    }
+}
+
+impl Default for BitrateConfig {
+    fn default() -> Self {
+        Self {
+            min_bitrate: 24_000,
+            max_bitrate: OPUS_BITRATE,
+            increase_step: 8_000,
+            decrease_factor: 0.75,
+            high_loss: 0.05,
+            low_loss: 0.01,
+            interval: Duration::from_secs(1),
+            report_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// AIMD steps of one stream's bitrate.
+#[derive(Debug)]
+pub struct BitrateEstimator {
+    config: BitrateConfig,
+    bitrate: i32,
+}
+
+impl BitrateEstimator {
+    /// Starts at the maximum bitrate.
+    pub fn new(config: BitrateConfig) -> Self {
+        Self {
+            config,
+            bitrate: config.max_bitrate,
+        }
+    }
+
+    pub fn bitrate(&self) -> i32 {
+        self.bitrate
+    }
+
+    /// Updates the bitrate with the worst loss reported since the last
+    /// check, or `None` without any fresh report.
+    pub fn update(&mut self, worst_loss: Option<f64>) -> i32 {
+        let config = &self.config;
+        let bitrate = match worst_loss {
+            Some(loss) if loss >= config.high_loss => {
+                (self.bitrate as f64 * config.decrease_factor) as i32
+            }
+            Some(loss) if loss < config.low_loss => self.bitrate + config.increase_step,
+            _ => self.bitrate,
+        };
+        self.bitrate = bitrate.clamp(config.min_bitrate, config.max_bitrate);
+        self.bitrate
+    }
+}
+
+/// Loss reported by each receiver about one of our streams.
+#[derive(Default)]
+pub struct LossReports {
+    reports: Mutex<HashMap<SocketAddr, (f64, Instant)>>,
+}
+
+impl LossReports {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the latest report from `receiver`, replacing its previous one.
+    pub fn record(&self, receiver: SocketAddr, loss_rate: f64) {
+        self.record_at(receiver, loss_rate, Instant::now());
+    }
+
+    fn record_at(&self, receiver: SocketAddr, loss_rate: f64, at: Instant) {
+        self.reports
+            .lock()
+            .unwrap()
+            .insert(receiver, (loss_rate, at));
+    }
+
+    /// The highest loss among reports no older than `timeout`, dropping
+    /// the stale ones, or `None` if there are none.
+    pub fn worst(&self, timeout: Duration) -> Option<f64> {
+        let now = Instant::now();
+        let mut reports = self.reports.lock().unwrap();
+        reports.retain(|_, (_, at)| now.duration_since(*at) < timeout);
+        reports.values().map(|(loss, _)| *loss).reduce(f64::max)
+    }
+}
+
+/// One local stream the controller adjusts.
+pub struct AdaptedStream<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    pub name: &'static str,
+    pub encoder: Arc<OpusEncoder<Sample, CHANNELS, SAMPLE_RATE>>,
+    pub reports: Arc<LossReports>,
+}
+
+/// Periodically adjusts each local stream's bitrate to the worst loss its
+/// receivers report.
+///
+/// Each stream starts at, and never goes above, the bitrate its encoder
+/// was configured with when the controller was created.
+pub struct BitrateController<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    config: BitrateConfig,
+    streams: Vec<(
+        AdaptedStream<Sample, CHANNELS, SAMPLE_RATE>,
+        Mutex<BitrateEstimator>,
+    )>,
+}
+
+impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    BitrateController<Sample, CHANNELS, SAMPLE_RATE>
+{
+    pub fn new(
+        config: BitrateConfig,
+        streams: Vec<AdaptedStream<Sample, CHANNELS, SAMPLE_RATE>>,
+    ) -> Self {
+        Self {
+            config,
+            streams: streams
+                .into_iter()
+                .map(|stream| {
+                    let configured = stream.encoder.config().bitrate_bps;
+                    let estimator = BitrateEstimator::new(config.capped_at(configured));
+                    (stream, Mutex::new(estimator))
+                })
+                .collect(),
+        }
+    }
+
+    /// Adjusts each stream's bitrate to its fresh reports.
+    pub fn check(&self) {
+        for (stream, estimator) in &self.streams {
+            let worst_loss = stream.reports.worst(self.config.report_timeout);
+            let mut estimator = estimator.lock().unwrap();
+            let previous = estimator.bitrate();
+            let bitrate = estimator.update(worst_loss);
+            if bitrate == previous {
+                continue;
+            }
+            info!(
+                "{} bitrate {} -> {} (worst reported loss {:.1}%)",
+                stream.name,
+                previous,
+                bitrate,
+                worst_loss.unwrap_or(0.0) * 100.0
+            );
+            if let Err(e) = stream.encoder.set_bitrate(bitrate) {
+                warn!("Failed to set {} bitrate: {:?}", stream.name, e);
+            }
+        }
+    }
+
+    /// Runs [`check`](Self::check) every interval.
+    ///
+    /// Must be called from within a Tokio runtime context.
+    pub fn start(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.interval);
+            loop {
+                interval.tick().await;
+                self.check();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BitrateConfig {
+        BitrateConfig {
+            min_bitrate: 16_000,
+            max_bitrate: 64_000,
+            increase_step: 8_000,
+            decrease_factor: 0.5,
+            ..BitrateConfig::default()
+        }
+    }
+
+    fn trajectory(estimator: &mut BitrateEstimator, losses: &[Option<f64>]) -> Vec<i32> {
+        losses.iter().map(|&loss| estimator.update(loss)).collect()
+    }
+
+    #[test]
+    fn loss_cuts_multiplicatively_and_recovery_is_additive() {
+        let mut estimator = BitrateEstimator::new(config());
+        assert_eq!(estimator.bitrate(), 64_000);
+
+        let heavy = Some(0.2);
+        assert_eq!(
+            trajectory(&mut estimator, &[heavy, heavy, heavy, heavy]),
+            [32_000, 16_000, 16_000, 16_000],
+            "halves down to the minimum"
+        );
+
+        // Moderate loss and silence from the receivers hold the bitrate.
+        assert_eq!(
+            trajectory(&mut estimator, &[Some(0.03), None, Some(0.03)]),
+            [16_000, 16_000, 16_000]
+        );
+
+        let clean = Some(0.0);
+        let recovery = trajectory(&mut estimator, &[clean; 8]);
+        assert_eq!(
+            recovery,
+            [
+                24_000, 32_000, 40_000, 48_000, 56_000, 64_000, 64_000, 64_000
+            ],
+            "climbs back one step at a time up to the maximum"
+        );
+    }
+
+    #[test]
+    fn worst_fresh_reporter_counts() {
+        let reports = LossReports::new();
+        let near = "192.168.1.2:7667".parse().unwrap();
+        let far = "192.168.1.3:7667".parse().unwrap();
+        let gone = "192.168.1.4:7667".parse().unwrap();
+        let timeout = Duration::from_secs(5);
+        assert_eq!(reports.worst(timeout), None);
+
+        reports.record(near, 0.0);
+        reports.record(far, 0.08);
+        reports.record_at(gone, 0.5, Instant::now() - Duration::from_secs(10));
+        assert_eq!(reports.worst(timeout), Some(0.08));
+
+        // A receiver's new report replaces its old one.
+        reports.record(far, 0.02);
+        assert_eq!(reports.worst(timeout), Some(0.02));
+    }
+
+    #[test]
+    fn controller_follows_the_worst_receiver() {
+        let encoder = Arc::new(OpusEncoder::<f32, 2, 48000>::new().unwrap());
+        let reports = Arc::new(LossReports::new());
+        let controller = BitrateController::new(
+            config(),
+            vec![AdaptedStream {
+                name: "Mic",
+                encoder: encoder.clone(),
+                reports: reports.clone(),
+            }],
+        );
+        let near = "192.168.1.2:7667".parse().unwrap();
+        let far = "192.168.1.3:7667".parse().unwrap();
+
+        reports.record(near, 0.0);
+        reports.record(far, 0.1);
+        controller.check();
+        assert_eq!(encoder.config().bitrate_bps, 32_000);
+
+        reports.record(far, 0.0);
+        controller.check();
+        assert_eq!(encoder.config().bitrate_bps, 40_000);
+    }
+
+    #[test]
+    fn each_stream_is_capped_at_its_configured_bitrate() {
+        use crate::audio::opus::OpusEncoderConfig;
+
+        let preset = |n: usize| {
+            let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+            encoder.set_config(OpusEncoderConfig::PRESETS[n].1).unwrap();
+            Arc::new(encoder)
+        };
+        let (voice, music) = (preset(1), preset(2));
+        let (voice_reports, music_reports) =
+            (Arc::new(LossReports::new()), Arc::new(LossReports::new()));
+        let controller = BitrateController::new(
+            BitrateConfig::default(),
+            vec![
+                AdaptedStream {
+                    name: "Mic",
+                    encoder: voice.clone(),
+                    reports: voice_reports.clone(),
+                },
+                AdaptedStream {
+                    name: "System audio",
+                    encoder: music.clone(),
+                    reports: music_reports.clone(),
+                },
+            ],
+        );
+        let receiver = "192.168.1.2:7667".parse().unwrap();
+
+        voice_reports.record(receiver, 0.2);
+        music_reports.record(receiver, 0.2);
+        controller.check();
+        assert_eq!(voice.config().bitrate_bps, 24_000, "already at its floor");
+        assert_eq!(music.config().bitrate_bps, 72_000);
+
+        voice_reports.record(receiver, 0.0);
+        music_reports.record(receiver, 0.0);
+        for _ in 0..10 {
+            controller.check();
+        }
+        assert_eq!(voice.config().bitrate_bps, 24_000);
+        assert_eq!(
+            music.config().bitrate_bps,
+            96_000,
+            "recovers to 96k, not 128k"
+        );
+    }
+}
@@ -2,21 +2,33 @@
 //!
 //! Provides utilities for splitting and mixing audio streams:
 //! - [`Tee`] - Splits data to two destinations (implements `Pushable`)
+//! - [`Decoupled`] - Pushes on its own thread so a slow sink can't stall the caller
+//! - [`Taps`] / [`Tap`] - Hand copies of what flows through a pipeline to observers
 //! - [`DynamicMixer`] - Runtime-configurable mixer using DashMap (implements `Pullable`)
+//! - [`UnderrunFill`] - Decides what the speaker hears when its source is starved
+//! - [`SilenceWatchdog`] - Resets the pull path when the output stays silent
+//!   while streams are active
 
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
 use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 use dashmap::DashMap;
 
 use crate::audio::AudioSample;
 use crate::audio::frame::AudioBuffer;
-use crate::pipeline::{Pullable, Pushable};
+use crate::pipeline::{Node, Pullable, Pushable};
 
 /// Splits pushed data to two destinations.
 ///
 /// When data is pushed to a `Tee`, it clones the data and pushes to both
 /// destination A and destination B.
+///
+/// Both pushes happen on the caller's thread, one after the other, so a
+/// branch that blocks holds up the other. Wrap a branch that may be slow in
+/// [`Decoupled`] to keep the other one immediate.
 pub struct Tee<T, A, B>
 where
     A: Pushable<T>,
@@ -48,6 +60,161 @@ impl<T: Clone + Send + Sync, A: Pushable<T>, B: Pushable<T>> Pushable<T> for Tee
     }
 }
 
+/// Pushes into a sink from its own thread, through a bounded queue.
+///
+/// `push` never waits: it queues the item and returns, and when the queue
+/// is full the item is dropped. A sink that blocks or runs slow thus loses
+/// items instead of stalling whoever pushes, e.g. the other branch of a
+/// [`Tee`]. Items that do go through reach the sink in order.
+pub struct Decoupled<T> {
+    sender: Option<SyncSender<T>>,
+    worker: Option<JoinHandle<()>>,
+    dropped: AtomicU64,
+}
+
+impl<T: Send + 'static> Decoupled<T> {
+    /// Starts a thread named `name` pushing into `sink`, with room for
+    /// `capacity` items (at least one) waiting on it.
+    pub fn new(sink: impl Pushable<T> + 'static, capacity: usize, name: &str) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<T>(capacity.max(1));
+        let worker = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                for item in receiver {
+                    sink.push(item);
+                }
+            })
+            .expect("Failed to spawn decoupled sink thread");
+        Self {
+            sender: Some(sender),
+            worker: Some(worker),
+            dropped: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<T: Send + 'static> Pushable<T> for Decoupled<T> {
+    fn push(&self, input: T) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        match sender.try_send(input) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                if dropped.is_power_of_two() {
+                    tracing::warn!("Decoupled sink is behind, {} items dropped", dropped);
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+impl<T> Drop for Decoupled<T> {
+    /// Lets the sink finish what is queued, then joins its thread.
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+pub type TapId = u64;
+
+/// Runs an observer callback as a [`Pushable`].
+struct Observer<F>(F);
+
+impl<T, F: Fn(T) + Send + Sync> Pushable<T> for Observer<F> {
+    fn push(&self, input: T) {
+        (self.0)(input)
+    }
+}
+
+/// Observers of the data flowing through a [`Tap`], added and removed at
+/// runtime, e.g. to feed external processing or a recording.
+///
+/// Each observer gets a copy of every item on its own thread, through a
+/// [`Decoupled`] queue, so a slow one only loses items of its own and never
+/// holds up the pipeline it watches.
+pub struct Taps<T> {
+    observers: DashMap<TapId, Decoupled<T>>,
+    next_id: AtomicU64,
+}
+
+impl<T: Clone + Send + 'static> Taps<T> {
+    pub fn new() -> Self {
+        Self {
+            observers: DashMap::new(),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Calls `observer` with a copy of every item from now on, with room
+    /// for `capacity` items waiting on it. Returns ID for later removal.
+    pub fn add(&self, observer: impl Fn(T) + Send + Sync + 'static, capacity: usize) -> TapId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let sink = Decoupled::new(Observer(observer), capacity, &format!("tap-{id}"));
+        self.observers.insert(id, sink);
+        id
+    }
+
+    /// Removes an observer once it has seen what is already queued for it.
+    /// Returns true if it was found.
+    pub fn remove(&self, id: TapId) -> bool {
+        self.observers.remove(&id).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+}
+
+impl<T: Clone + Send + 'static> Default for Taps<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone + Send + 'static> Pushable<T> for Taps<T> {
+    fn push(&self, input: T) {
+        for observer in self.observers.iter() {
+            observer.value().push(input.clone());
+        }
+    }
+}
+
+/// Passes data through unchanged, handing a copy to its [`Taps`].
+///
+/// Works in both push and pull chains; with no observers it costs nothing
+/// but a check.
+pub struct Tap<T> {
+    taps: Arc<Taps<T>>,
+}
+
+impl<T> Tap<T> {
+    pub fn new(taps: Arc<Taps<T>>) -> Self {
+        Self { taps }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Node for Tap<T> {
+    type Input = T;
+    type Output = T;
+
+    fn process(&self, input: T) -> Option<T> {
+        if !self.taps.is_empty() {
+            self.taps.push(input.clone());
+        }
+        Some(input)
+    }
+}
+
 struct SelectState {
     logical_frames: u64,
     consumed_frames: Vec<u64>,
@@ -102,6 +269,11 @@ impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         }
     }
 
+    /// Logical output position in frames.
+    pub fn position(&self) -> u64 {
+        self.state.lock().unwrap().logical_frames
+    }
+
     pub fn discard_to(&self, frame_pos: u64) {
         let mut state = self.state.lock().unwrap();
         state.logical_frames = state.logical_frames.max(frame_pos);
@@ -186,6 +358,8 @@ pub type InputId = u64;
 pub struct Mixer<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     inputs: DashMap<InputId, Arc<dyn Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>>,
     next_id: AtomicU64,
+    /// Sum of the inputs, kept between pulls so mixing doesn't allocate.
+    scratch: Mutex<Vec<f64>>,
 }
 
 impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
@@ -196,6 +370,7 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         Self {
             inputs: DashMap::new(),
             next_id: AtomicU64::new(0),
+            scratch: Mutex::new(Vec::new()),
         }
     }
 
@@ -232,40 +407,66 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         self.inputs.len()
     }
 
-    fn pull_and_mix(&self, len: usize) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
-        let buffers: Vec<_> = self
-            .inputs
-            .iter()
-            .filter_map(|entry| entry.value().pull(len))
-            .collect();
+    /// Mixes up to `len` samples from every input into `out`, without
+    /// allocating the result. Returns how many samples were written, or
+    /// `None` if no input had audio.
+    pub fn mix_into(&self, out: &mut [Sample], len: usize) -> Option<usize> {
+        let len = len.min(out.len());
+        let mut scratch = self.scratch.lock().unwrap();
+        let accumulate = |scratch: &mut [f64], data: &[Sample]| {
+            for (mixed, sample) in scratch.iter_mut().zip(data) {
+                *mixed += sample.to_f64_normalized();
+            }
+        };
 
-        if buffers.is_empty() {
-            return None;
+        // A lone input is copied through untouched; summing starts with
+        // the second.
+        let mut first = None;
+        let mut count = 0;
+        for entry in self.inputs.iter() {
+            let Some(buffer) = entry.value().pull(len) else {
+                continue;
+            };
+            count += 1;
+            if count == 1 {
+                first = Some(buffer);
+                continue;
+            }
+            if let Some(first) = first.take() {
+                scratch.clear();
+                scratch.resize(len, 0.0);
+                accumulate(&mut scratch, first.data());
+            }
+            accumulate(&mut scratch, buffer.data());
         }
 
         tracing::trace!(
             "DynamicMixer: pulled {} buffers from {} inputs",
-            buffers.len(),
+            count,
             self.inputs.len()
         );
 
-        if buffers.len() == 1 {
-            return Some(buffers.into_iter().next().unwrap());
-        }
-
-        let mut mixed: Vec<f64> = vec![0.0; len];
-
-        for buffer in &buffers {
-            for (i, sample) in buffer.data().iter().enumerate() {
-                if i < len {
-                    mixed[i] += sample.to_f64_normalized();
+        match (count, first) {
+            (0, _) => None,
+            (_, Some(first)) => {
+                let written = first.data().len().min(len);
+                out[..written].copy_from_slice(&first.data()[..written]);
+                Some(written)
+            }
+            _ => {
+                for (out, mixed) in out.iter_mut().zip(scratch.iter()) {
+                    *out = Sample::from_f64_normalized(*mixed);
                 }
+                Some(len)
             }
         }
+    }
 
-        let result: Vec<Sample> = mixed.into_iter().map(Sample::from_f64_normalized).collect();
-
-        AudioBuffer::new(result).ok()
+    fn pull_and_mix(&self, len: usize) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        let mut out = vec![Sample::silence(); len];
+        let written = self.mix_into(&mut out, len)?;
+        out.truncate(written);
+        AudioBuffer::new(out).ok()
     }
 }
 
@@ -285,6 +486,262 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
     }
 }
 
+/// Fade length for [`UnderrunPolicy::LastBuffer`].
+const UNDERRUN_FADE_MS: u64 = 30;
+/// Peak amplitude of [`UnderrunPolicy::ComfortNoise`] (about -70 dBFS).
+const COMFORT_NOISE_LEVEL: f64 = 0.0003;
+
+/// Whether every sample of `buffer` is exactly zero.
+fn is_digital_silence<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>(
+    buffer: &AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>,
+) -> bool {
+    buffer
+        .data()
+        .iter()
+        .all(|sample| sample.to_f64_normalized() == 0.0)
+}
+
+/// What to play when the output source has nothing, or only silence.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnderrunPolicy {
+    /// Hard digital silence.
+    #[default]
+    Silence,
+    /// Keep repeating the last real buffer while fading it out to silence.
+    LastBuffer,
+    /// Very low-level white noise, for DACs that pop when muting on silence.
+    ComfortNoise,
+}
+
+impl UnderrunPolicy {
+    pub const ALL: [Self; 3] = [Self::Silence, Self::LastBuffer, Self::ComfortNoise];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Silence => "Silence",
+            Self::LastBuffer => "Fade out last audio",
+            Self::ComfortNoise => "Comfort noise",
+        }
+    }
+}
+
+struct UnderrunState<Sample> {
+    last: Vec<Sample>,
+    /// Position in `last` to continue repeating from.
+    cursor: usize,
+    /// Current fade gain for `LastBuffer`, 1.0 right after real audio.
+    gain: f64,
+    noise_seed: u32,
+}
+
+/// Wraps the speaker source and fills underruns according to an [`UnderrunPolicy`].
+///
+/// A starved source either returns `None` or, like the jitter buffers and
+/// the mixer, a buffer of digital silence; both count as an underrun.
+///
+/// With [`UnderrunPolicy::Silence`] this is a pass-through: `None` is returned
+/// and the output callback writes silence as before.
+pub struct UnderrunFill<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    source: Arc<dyn Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+    policy: UnderrunPolicy,
+    state: Mutex<UnderrunState<Sample>>,
+}
+
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    UnderrunFill<Sample, CHANNELS, SAMPLE_RATE>
+{
+    pub fn new(
+        source: Arc<dyn Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+        policy: UnderrunPolicy,
+    ) -> Self {
+        Self {
+            source,
+            policy,
+            state: Mutex::new(UnderrunState {
+                last: Vec::new(),
+                cursor: 0,
+                gain: 0.0,
+                noise_seed: 0x9E37_79B9,
+            }),
+        }
+    }
+
+    fn fade_out_last(
+        state: &mut UnderrunState<Sample>,
+        len: usize,
+    ) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        if state.last.is_empty() || state.gain <= 0.0 {
+            return None;
+        }
+
+        let fade_frames = (SAMPLE_RATE as u64 * UNDERRUN_FADE_MS / 1000).max(1);
+        let step = 1.0 / fade_frames as f64;
+        let mut out = Vec::with_capacity(len);
+        for frame in 0..len / CHANNELS {
+            let gain = (state.gain - step * (frame + 1) as f64).max(0.0);
+            for _ in 0..CHANNELS {
+                let sample = state.last[state.cursor];
+                state.cursor = (state.cursor + 1) % state.last.len();
+                out.push(Sample::from_f64_normalized(
+                    sample.to_f64_normalized() * gain,
+                ));
+            }
+        }
+        state.gain = (state.gain - step * (len / CHANNELS) as f64).max(0.0);
+
+        AudioBuffer::new(out).ok()
+    }
+
+    fn comfort_noise(
+        state: &mut UnderrunState<Sample>,
+        len: usize,
+    ) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        let samples = (0..len - len % CHANNELS)
+            .map(|_| {
+                state.noise_seed = state
+                    .noise_seed
+                    .wrapping_mul(1_664_525)
+                    .wrapping_add(1_013_904_223);
+                let unit = (state.noise_seed >> 8) as f64 / (1u32 << 24) as f64;
+                Sample::from_f64_normalized((unit * 2.0 - 1.0) * COMFORT_NOISE_LEVEL)
+            })
+            .collect();
+        AudioBuffer::new(samples).ok()
+    }
+}
+
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>
+    for UnderrunFill<Sample, CHANNELS, SAMPLE_RATE>
+{
+    fn pull(&self, len: usize) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        let pulled = self.source.pull(len);
+        if self.policy == UnderrunPolicy::Silence || len == 0 {
+            return pulled;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        match pulled {
+            Some(buffer) if !is_digital_silence(&buffer) => {
+                if self.policy == UnderrunPolicy::LastBuffer {
+                    state.last.clear();
+                    state.last.extend_from_slice(buffer.data());
+                    state.cursor = 0;
+                    state.gain = 1.0;
+                }
+                Some(buffer)
+            }
+            // Starved jitter buffers, and the mixer over them, hand back
+            // digital silence rather than `None`.
+            pulled => {
+                let filled = match self.policy {
+                    UnderrunPolicy::Silence => None,
+                    UnderrunPolicy::LastBuffer => Self::fade_out_last(&mut state, len),
+                    UnderrunPolicy::ComfortNoise => Self::comfort_noise(&mut state, len),
+                };
+                filled.or(pulled)
+            }
+        }
+    }
+}
+
+/// Decides when silent output means the pull path is stuck.
+///
+/// Silence while streams are supposedly active, for longer than the
+/// threshold, points at a stuck read pointer or a deadlock rather than
+/// quiet senders.
+pub struct SilentOutputDetector {
+    threshold_frames: u64,
+    silent_frames: u64,
+}
+
+impl SilentOutputDetector {
+    pub fn new(threshold: Duration, sample_rate: u32) -> Self {
+        Self {
+            threshold_frames: (threshold.as_secs_f64() * sample_rate as f64) as u64,
+            silent_frames: 0,
+        }
+    }
+
+    /// Records an output buffer of `frames` and returns whether recovery
+    /// should run. `active_streams` is only asked while the output is
+    /// silent; without active streams silence is expected.
+    pub fn observe(
+        &mut self,
+        frames: u64,
+        silent: bool,
+        active_streams: impl FnOnce() -> usize,
+    ) -> bool {
+        if !silent || active_streams() == 0 {
+            self.silent_frames = 0;
+            return false;
+        }
+        self.silent_frames += frames;
+        if self.silent_frames < self.threshold_frames {
+            return false;
+        }
+        self.silent_frames = 0;
+        true
+    }
+}
+
+/// Wraps the speaker source and runs `recover` when it has been silent for
+/// the threshold while `active_streams` reports streams.
+///
+/// Only digital silence counts, so it belongs before [`UnderrunFill`].
+pub struct SilenceWatchdog<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    source: Arc<dyn Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+    detector: Mutex<SilentOutputDetector>,
+    active_streams: Box<dyn Fn() -> usize + Send + Sync>,
+    recover: Box<dyn Fn() + Send + Sync>,
+}
+
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    SilenceWatchdog<Sample, CHANNELS, SAMPLE_RATE>
+{
+    pub fn new(
+        source: Arc<dyn Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+        threshold: Duration,
+        active_streams: impl Fn() -> usize + Send + Sync + 'static,
+        recover: impl Fn() + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            source,
+            detector: Mutex::new(SilentOutputDetector::new(threshold, SAMPLE_RATE)),
+            active_streams: Box::new(active_streams),
+            recover: Box::new(recover),
+        }
+    }
+}
+
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>
+    for SilenceWatchdog<Sample, CHANNELS, SAMPLE_RATE>
+{
+    fn pull(&self, len: usize) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        let pulled = self.source.pull(len);
+        if len == 0 {
+            return pulled;
+        }
+
+        let silent = pulled.as_ref().is_none_or(is_digital_silence);
+        let stuck = self
+            .detector
+            .lock()
+            .unwrap()
+            .observe((len / CHANNELS) as u64, silent, || (self.active_streams)());
+        if stuck {
+            let active = (self.active_streams)();
+            tracing::warn!(
+                "Output silent with {} active streams, resetting the pull path",
+                active
+            );
+            (self.recover)();
+        }
+        pulled
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -311,6 +768,44 @@ mod tests {
         ])
     }
 
+    #[test]
+    fn mix_into_matches_pull_and_reuses_buffers() {
+        let mixer_with = |sources: usize| {
+            let mixer = Mixer::<f32, 2, 48_000>::new();
+            for n in 0..sources {
+                let source = SimpleBuffer::<f32, 2, 48_000>::new();
+                for chunk in 0..4 {
+                    let level = 0.01 * (n + chunk + 1) as f32;
+                    source.push(audio(&[(level, -level); 64]));
+                }
+                mixer.add_input(Arc::new(source));
+            }
+            mixer
+        };
+
+        for sources in [1, 2, 5] {
+            let pulling = mixer_with(sources);
+            let mixing = mixer_with(sources);
+            let mut out = vec![0.0f32; 128];
+            let out_ptr = out.as_ptr();
+            let mut scratch_ptr = None;
+
+            for chunk in 0..4 {
+                let pulled = pulling.pull(128).unwrap();
+                let written = mixing.mix_into(&mut out, 128).unwrap();
+                assert_eq!(&out[..written], pulled.data(), "{sources} sources");
+                let level: f32 = (0..sources).map(|n| 0.01 * (n + chunk + 1) as f32).sum();
+                assert!((out[0] - level).abs() < 1e-6 && (out[1] + level).abs() < 1e-6);
+
+                let scratch = mixing.scratch.lock().unwrap().as_ptr();
+                assert_eq!(*scratch_ptr.get_or_insert(scratch), scratch);
+            }
+            assert_eq!(out.as_ptr(), out_ptr);
+            assert_eq!(out.capacity(), 128);
+            assert!(mixing.mix_into(&mut out, 128).is_none(), "sources drained");
+        }
+    }
+
     #[test]
     fn synchronized_select_consumes_inactive_input() {
         let raw = SimpleBuffer::<f32, 2, 48_000>::new();
@@ -355,4 +850,244 @@ mod tests {
         let second = selector.pull(4).unwrap();
         assert_eq!(second.data(), &[30.0, 30.0, 40.0, 40.0]);
     }
+
+    #[test]
+    fn last_buffer_underrun_fades_to_silence() {
+        let source = SimpleBuffer::<f32, 2, 48_000>::new();
+        let fill = UnderrunFill::new(
+            Arc::new(source.clone()) as Arc<dyn Pullable<TestBuffer>>,
+            UnderrunPolicy::LastBuffer,
+        );
+
+        source.push(audio(&[(0.5, -0.5); 240]));
+        assert!(
+            fill.pull(480)
+                .unwrap()
+                .data()
+                .iter()
+                .all(|s| s.abs() == 0.5)
+        );
+
+        // Starved from here on: output must ramp down, never jump to zero.
+        let mut previous = 0.5f32;
+        let mut reached_silence = false;
+        for _ in 0..20 {
+            let Some(buffer) = fill.pull(480) else {
+                reached_silence = true;
+                break;
+            };
+            for frame in buffer.data().chunks(2) {
+                let level = frame[0].abs();
+                assert_eq!(frame[0], -frame[1]);
+                assert!(level <= previous, "fade must not increase");
+                assert!(previous - level < 0.01, "fade must not jump");
+                previous = level;
+            }
+        }
+        assert!(reached_silence, "fade should end in silence");
+        assert!(
+            previous < 1e-3,
+            "fade should reach zero, ended at {previous}"
+        );
+    }
+
+    #[test]
+    fn starved_jitter_buffer_behind_mixer_is_filled() {
+        use crate::audio::JitterBuffer;
+        use crate::audio::frame::AudioFrame;
+
+        let fill_with = |policy| {
+            let jitter = Arc::new(JitterBuffer::<f32, 2, 48_000>::new(16));
+            let mixer = Mixer::<f32, 2, 48_000>::new();
+            mixer.add_input(jitter.clone());
+            let fill = UnderrunFill::new(Arc::new(mixer) as Arc<dyn Pullable<TestBuffer>>, policy);
+            for seq in 1..=2 {
+                jitter.push(AudioFrame::new(seq, vec![0.5f32; 1920]).unwrap());
+            }
+            for _ in 0..2 {
+                let played = fill.pull(1920).unwrap();
+                assert!(played.data().iter().all(|s| *s == 0.5));
+            }
+            fill
+        };
+
+        // Once the frames run out the mixer returns silence, not `None`.
+        let fill = fill_with(UnderrunPolicy::LastBuffer);
+        let starved = fill.pull(480).unwrap();
+        assert!(starved.data()[0] > 0.45, "no jump to silence");
+        assert!(starved.data()[479] < starved.data()[0], "fades");
+        let mut tail = Vec::new();
+        for _ in 0..10 {
+            tail.extend_from_slice(fill.pull(480).unwrap().data());
+        }
+        assert!(tail.iter().rev().take(480).all(|s| *s == 0.0));
+
+        let fill = fill_with(UnderrunPolicy::ComfortNoise);
+        let starved = fill.pull(480).unwrap();
+        assert!(starved.data().iter().any(|s| *s != 0.0));
+        assert!(
+            starved
+                .data()
+                .iter()
+                .all(|s| s.abs() as f64 <= COMFORT_NOISE_LEVEL)
+        );
+    }
+
+    #[test]
+    fn silence_underrun_passes_through() {
+        let source = SimpleBuffer::<f32, 2, 48_000>::new();
+        let fill = UnderrunFill::new(
+            Arc::new(source.clone()) as Arc<dyn Pullable<TestBuffer>>,
+            UnderrunPolicy::Silence,
+        );
+        source.push(audio(&[(0.5, 0.5); 4]));
+        assert!(fill.pull(8).is_some());
+        assert!(fill.pull(8).is_none());
+    }
+
+    #[test]
+    fn silent_output_with_active_streams_triggers_recovery() {
+        // 10 ms callbacks against a 50 ms threshold.
+        let frames = 480;
+        let mut detector = SilentOutputDetector::new(Duration::from_millis(50), 48_000);
+
+        for _ in 0..4 {
+            assert!(!detector.observe(frames, true, || 2));
+        }
+        assert!(detector.observe(frames, true, || 2), "5th silent buffer");
+        // Counting starts over after recovery.
+        assert!(!detector.observe(frames, true, || 2));
+
+        // Audio or idle streams break the run.
+        let mut detector = SilentOutputDetector::new(Duration::from_millis(50), 48_000);
+        for _ in 0..4 {
+            assert!(!detector.observe(frames, true, || 1));
+        }
+        assert!(!detector.observe(frames, false, || 1));
+        for _ in 0..4 {
+            assert!(!detector.observe(frames, true, || 1));
+        }
+        assert!(!detector.observe(frames, true, || 0));
+        for _ in 0..10 {
+            assert!(!detector.observe(frames, true, || 0), "silence is expected");
+        }
+
+        let recoveries = Arc::new(AtomicUsize::new(0));
+        let source = SimpleBuffer::<f32, 2, 48_000>::new();
+        let watchdog = SilenceWatchdog::new(
+            Arc::new(source.clone()) as Arc<dyn Pullable<TestBuffer>>,
+            Duration::from_millis(50),
+            || 1,
+            {
+                let recoveries = recoveries.clone();
+                move || {
+                    recoveries.fetch_add(1, Ordering::Relaxed);
+                }
+            },
+        );
+        for _ in 0..5 {
+            source.push(audio(&[(0.0, 0.0); 480]));
+            watchdog.pull(960);
+        }
+        assert_eq!(recoveries.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn slow_decoupled_branch_does_not_delay_the_other() {
+        use std::time::Instant;
+
+        struct Recorder(Mutex<Vec<(u32, Instant)>>, Duration);
+        impl Pushable<u32> for Arc<Recorder> {
+            fn push(&self, input: u32) {
+                thread::sleep(self.1);
+                self.0.lock().unwrap().push((input, Instant::now()));
+            }
+        }
+
+        let slow = Arc::new(Recorder(Mutex::new(Vec::new()), Duration::from_millis(50)));
+        let fast = Arc::new(Recorder(Mutex::new(Vec::new()), Duration::ZERO));
+        let tee = Tee::new(Decoupled::new(slow.clone(), 2, "slow-sink"), fast.clone());
+
+        let mut pushed_at = Vec::new();
+        for n in 0..10 {
+            pushed_at.push(Instant::now());
+            tee.push(n);
+        }
+
+        // The fast branch got every item right away, not behind the slow one.
+        let delivered = fast.0.lock().unwrap().clone();
+        assert_eq!(
+            delivered.iter().map(|(n, _)| *n).collect::<Vec<_>>(),
+            (0..10).collect::<Vec<_>>()
+        );
+        for ((_, at), pushed) in delivered.iter().zip(&pushed_at) {
+            assert!(at.duration_since(*pushed) < Duration::from_millis(20));
+        }
+
+        // The slow branch kept what fit in its queue, in order, and dropped
+        // the rest.
+        let dropped = tee.a.dropped.load(Ordering::Relaxed);
+        assert!(dropped > 0);
+        drop(tee);
+        let slow_got: Vec<u32> = slow.0.lock().unwrap().iter().map(|(n, _)| *n).collect();
+        assert_eq!(slow_got.len() as u64 + dropped, 10);
+        assert!(slow_got.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(slow_got[0], 0);
+    }
+
+    #[test]
+    fn mix_tap_receives_what_is_played() {
+        let mixer = Mixer::<f32, 2, 48_000>::new();
+        for n in 0..2 {
+            let source = SimpleBuffer::<f32, 2, 48_000>::new();
+            for chunk in 0..4 {
+                let level = 0.01 * (n + chunk + 1) as f32;
+                source.push(audio(&[(level, -level); 64]));
+            }
+            mixer.add_input(Arc::new(source));
+        }
+        let taps = Arc::new(Taps::<TestBuffer>::new());
+        let output = crate::pull_chain![Arc::new(mixer) =>, Tap::new(taps.clone())];
+
+        // Pulled before anyone listens: not seen.
+        let mut played = vec![output.pull(128).unwrap()];
+        let tapped = Arc::new(Mutex::new(Vec::new()));
+        let id = taps.add(
+            {
+                let tapped = tapped.clone();
+                move |buffer: TestBuffer| tapped.lock().unwrap().push(buffer)
+            },
+            8,
+        );
+        while let Some(buffer) = output.pull(128) {
+            played.push(buffer);
+        }
+
+        // Removing the tap waits for it to catch up.
+        assert!(taps.remove(id));
+        assert!(taps.is_empty());
+        let tapped = tapped.lock().unwrap();
+        assert_eq!(tapped.len(), 3);
+        for (tapped, played) in tapped.iter().zip(&played[1..]) {
+            assert_eq!(tapped.data(), played.data());
+        }
+    }
+
+    #[test]
+    fn comfort_noise_underrun_is_quiet_but_not_silent() {
+        let source = SimpleBuffer::<f32, 2, 48_000>::new();
+        let fill = UnderrunFill::new(
+            Arc::new(source.clone()) as Arc<dyn Pullable<TestBuffer>>,
+            UnderrunPolicy::ComfortNoise,
+        );
+        let noise = fill.pull(480).unwrap();
+        assert_eq!(noise.data().len(), 480);
+        assert!(noise.data().iter().any(|s| *s != 0.0));
+        assert!(
+            noise
+                .data()
+                .iter()
+                .all(|s| s.abs() as f64 <= COMFORT_NOISE_LEVEL)
+        );
+    }
 }
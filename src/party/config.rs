@@ -1,11 +1,384 @@
-//! Configuration for Party audio/network devices.
+//! Configuration for Party audio/network devices, and versioning of the
+//! data we save to disk.
+//!
+//! # Saved data
+//!
+//! Files we persist as JSON carry a `version`, the number of
+//! [`Migration`]s their type has had plus one; files from before
+//! versioning count as version 1. Loading one written by an older version
+//! runs the migrations from there on before parsing it, so old files keep
+//! their data when the format changes. New fields that don't need
+//! migrating can simply be given serde defaults.
 
-use cpal::DeviceId;
+use std::path::PathBuf;
+use std::time::Duration;
 
-#[derive(Clone, Default, Debug)]
+use anyhow::{Context, Result, bail};
+use cpal::{DeviceId, SampleFormat};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tracing::info;
+
+use crate::audio::buffers::Concealment;
+use crate::audio::buffers::monitor_buffer::DEFAULT_MONITOR_BACKLOG;
+use crate::audio::effects::{AgcConfig, DEFAULT_HIGH_PASS_HZ, DeEsserConfig, ReverbConfig};
+use crate::audio::{AudioCodec, OpusEncoderConfig, OpusFrameDuration};
+use crate::io::audio::DEFAULT_MAX_OUTPUT_PULL_FRAMES;
+use crate::io::{MulticastConfig, Passphrase};
+use crate::party::bitrate::BitrateConfig;
+use crate::party::combinator::UnderrunPolicy;
+use crate::party::frame_clock::TimestampSource;
+use crate::party::mic_check::MicCheckConfig;
+use crate::party::network_stream::DEFAULT_STATS_REFRESH_HZ;
+use crate::party::presence::PresenceConfig;
+use crate::party::realtime_stream::LossMuteConfig;
+use crate::party::share_music::receiver::{
+    DEFAULT_END_GAP_GRACE, DEFAULT_RESYNC_THRESHOLD, DEFAULT_SEEK_READY_FRAMES,
+};
+use crate::party::share_music::saved_playlist;
+use crate::party::share_music::{CoalesceConfig, RetransmitWindow};
+use crate::party::uplink::UplinkConfig;
+
+/// An effect on the mic path whose position in the chain can be changed.
+///
+/// The safety limiter is not one of them: it always runs after the whole
+/// chain, so no effect or gain setting can push a peak past its ceiling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MicEffect {
+    /// Sibilance reduction, bypassed by default.
+    DeEsser,
+    /// Room reverb, bypassed by default.
+    Reverb,
+    /// Input gain slider.
+    Gain,
+}
+
+impl MicEffect {
+    /// Order used until the user rearranges the chain.
+    pub const DEFAULT_ORDER: [MicEffect; 3] =
+        [MicEffect::DeEsser, MicEffect::Reverb, MicEffect::Gain];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MicEffect::DeEsser => "De-esser",
+            MicEffect::Reverb => "Reverb",
+            MicEffect::Gain => "Input Gain",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct PartyConfig {
     pub input_device_id: Option<DeviceId>,
     pub output_device_id: Option<DeviceId>,
+    /// Hardware output (0-based) for each pipeline channel, e.g. `[2, 3]` to
+    /// play on outputs 3/4 of a multichannel interface. `None` uses the
+    /// first outputs.
+    pub output_channel_map: Option<Vec<usize>>,
+    /// Sample format to open the output device with. `None` picks the best
+    /// one it supports: f32, then i16, then i32.
+    pub output_sample_format: Option<SampleFormat>,
+    /// Most frames pulled through the output pipeline at once; larger
+    /// device callbacks are filled in several pulls.
+    pub max_output_pull_frames: usize,
     pub ipv6: bool,
+    /// Multicast group, port and TTL to meet on. Parties in the same
+    /// building pick different ones to stay apart.
+    pub multicast: MulticastConfig,
     pub send_interface_index: Option<u32>,
+    /// Losslessly compress shared WAV (integer PCM) music on the wire.
+    pub compress_pcm_music: bool,
+    /// What the speaker plays when no source has audio.
+    pub underrun_policy: UnderrunPolicy,
+    /// Total bytes all realtime jitter buffers may use. Buffers shrink their
+    /// slot count and history to fit, trading jitter tolerance for memory.
+    /// `None` keeps every buffer at full size.
+    pub jitter_memory_budget: Option<usize>,
+    /// Let realtime jitter buffers use frame timestamps to tell a sender
+    /// that reconnected with fresh sequence numbers from late packets: a
+    /// frame more than this much newer than any seen starts a new epoch.
+    /// `None` goes by sequence numbers alone.
+    pub jitter_epoch_tolerance: Option<Duration>,
+    /// How realtime jitter buffers fill frames that never arrived: silence,
+    /// or the last frame fading out over a few lost in a row.
+    pub jitter_concealment: Concealment,
+    /// Play received DTX gaps as digital silence instead of comfort noise.
+    pub dtx_silence: bool,
+    /// Turn down remote streams whose loss stays high, so one bad
+    /// connection doesn't fill the mix with concealment artifacts. `None`
+    /// plays every stream as is.
+    pub loss_mute: Option<LossMuteConfig>,
+    /// Ask senders to retransmit lost realtime frames that can still be
+    /// played in time. Peers' requests are answered either way.
+    pub realtime_nack: bool,
+    /// Threads decoding received realtime streams, each stream pinned to
+    /// one of them. 0 decodes on the receive task.
+    pub decode_workers: usize,
+    /// Mic buffers that may wait for the network send, which then runs on
+    /// its own thread so a slow send drops audio instead of delaying the
+    /// loopback monitor. `None` sends on the audio thread.
+    pub mic_send_queue: Option<usize>,
+    /// Send mic audio without the safety limiter that keeps peaks below
+    /// full scale.
+    pub disable_input_limiter: bool,
+    /// Cutoff of the high-pass filter at the front of the mic chain, which
+    /// removes DC offset and rumble before encoding. `None` turns it off.
+    pub mic_high_pass_hz: Option<f64>,
+    /// Automatic gain control after the high-pass filter, evening out the
+    /// level of singers moving towards and away from the mic. `None` turns
+    /// it off.
+    pub mic_agc: Option<AgcConfig>,
+    /// Audio per Opus packet of the mic stream, independent of the capture
+    /// buffer size. Shorter frames lower the latency of speech.
+    pub mic_frame_duration: OpusFrameDuration,
+    /// Bitrate, complexity and signal type of the mic encoder. Can be
+    /// changed while running with [`Party::set_mic_encoder_config`](crate::party::Party::set_mic_encoder_config).
+    pub mic_encoder: OpusEncoderConfig,
+    /// How the mic and system audio are coded. Raw PCM has no codec delay
+    /// but needs about ten times the bandwidth, so it's only for fast,
+    /// quiet LANs, and its frames are capped at
+    /// [`AudioCodec::max_frame_ms`] to fit a packet. Receivers play either.
+    /// Shared music isn't affected: it's sent in its file's own codec,
+    /// which `SyncedStreamMeta::codec_params` already names.
+    pub realtime_codec: AudioCodec,
+    /// Measure peers' network jitter for this long after joining (10 s is
+    /// plenty), then pick the mic frame duration for it in place of
+    /// `mic_frame_duration`. `None` keeps `mic_frame_duration`.
+    pub auto_mic_frame_duration: Option<Duration>,
+    /// Lower the mic encoder's complexity while encoding a frame takes a
+    /// large share of its duration, and raise it again with headroom.
+    /// Helps slow or busy machines keep the mic from stuttering. Overrides
+    /// the complexity in `mic_encoder`.
+    pub adaptive_mic_complexity: bool,
+    /// Audio per Opus packet of the shared system audio.
+    pub system_frame_duration: OpusFrameDuration,
+    /// Audio per Opus packet of the no-vocal track of shared music, which
+    /// is buffered anyway, so longer frames save bandwidth.
+    pub music_frame_duration: OpusFrameDuration,
+    /// Crossover frequency and threshold of the mic de-esser.
+    pub de_esser: DeEsserConfig,
+    /// Room size, damping and wet/dry mix of the mic reverb.
+    pub reverb: ReverbConfig,
+    /// Lookahead and cap for synced-music retransmission requests.
+    pub retransmit_window: RetransmitWindow,
+    /// Send the no-vocal track of shared songs as mono when their channels
+    /// are effectively identical, saving the bits of an empty side signal.
+    pub auto_mono_music: bool,
+    /// Pack small synced-music frames sent within a short window into
+    /// shared datagrams, for formats with tiny frames. `None` sends every
+    /// frame in its own datagram.
+    pub synced_coalesce: Option<CoalesceConfig>,
+    /// How far ahead of the send position shared songs are read. Packets
+    /// are dropped again a couple of seconds after they play, so a long
+    /// file doesn't stay in memory whole. Too little leaves no slack for
+    /// network hiccups. `None` reads and keeps the whole file right away.
+    pub music_read_ahead: Option<Duration>,
+    /// Stamp shared-music frames with their sample offset in the song, so
+    /// receivers size the silence for lost frames exactly even when frame
+    /// sizes vary.
+    pub music_sample_offsets: bool,
+    /// Shed outgoing load when the uplink saturates: music redundancy
+    /// first, then system audio, and mic bitrate last. `None` always sends
+    /// everything at full quality.
+    pub uplink_adaptation: Option<UplinkConfig>,
+    /// Report the loss on peers' streams back to them, and adjust our mic
+    /// and system audio bitrate to the loss they report. Peers only report
+    /// with this set too. It sets the same bitrates as
+    /// `uplink_adaptation`, so use one or the other. `None` does neither.
+    pub adaptive_bitrate: Option<BitrateConfig>,
+    /// Heartbeat interval and the name announced to other participants.
+    pub presence: PresenceConfig,
+    /// Drop packets whose source IP is one of ours. Turn off to run several
+    /// instances on one machine, told apart by port.
+    pub ignore_self: bool,
+    /// Have the OS loop our own multicast packets back to this host, which
+    /// several instances on one machine need to hear each other. Turn off
+    /// to keep them from coming back at all.
+    pub multicast_loopback: bool,
+    /// Name this instance in every packet with a random id, so peers keep
+    /// recognizing it, buffers and settings included, if its IP changes.
+    /// Off, peers go by address alone.
+    pub stable_instance_id: bool,
+    /// Clock used to timestamp outgoing realtime frames.
+    pub timestamp_source: TimestampSource,
+    /// Fade applied to the speaker when the output is restarted, e.g. on a
+    /// device switch: out on the old device, in on the new one.
+    pub output_switch_ramp: Duration,
+    /// Synced music drift from the party clock beyond which playback jumps
+    /// to the right position; smaller drift is corrected gradually.
+    pub music_resync_threshold: Duration,
+    /// Synced music frames held per source while waiting for the stream's
+    /// metadata; 0 drops them as before.
+    pub pre_meta_frames: usize,
+    /// How long synced music packets still missing when the sender ends a
+    /// stream are waited for before being played as silence. `None` waits
+    /// for retransmission indefinitely.
+    pub synced_end_gap_grace: Option<Duration>,
+    /// Synced music frames from a seek target that must arrive before the
+    /// stream stops showing as seeking; missing ones are requested right
+    /// away. 0 leaves seeks to regular gap detection.
+    pub seek_ready_frames: u64,
+    /// Keep synced music from rewinding when the party clock steps back,
+    /// e.g. on an NTP correction; it holds its position until the clock
+    /// catches up.
+    pub monotonic_music_clock: bool,
+    /// Play music shared by several people at once side by side, mixed.
+    /// Off, whoever starts a song last takes over from everyone else.
+    pub concurrent_music: bool,
+    /// Hold synced music back until the party clock is synced, so a
+    /// newcomer doesn't play a song from a wrong position first.
+    pub music_waits_for_clock_sync: bool,
+    /// How long the speaker may play digital silence while streams are
+    /// delivering audible audio before the pull path is reset. Streams in
+    /// DTX or sending exact zeros don't count. `None` never resets it.
+    pub silent_output_watchdog: Option<Duration>,
+    /// Level check of the input device offered on startup.
+    pub mic_check: MicCheckConfig,
+    /// Reset the mic's Opus encoder whenever the mic starts again, so the
+    /// new stream doesn't carry over state from before the restart.
+    pub reset_encoder_on_restart: bool,
+    /// How often to check the network interfaces, e.g. for a VPN coming up
+    /// or going down. With automatic interface selection a change to the
+    /// interface the party's traffic is routed through rebinds the sockets.
+    /// `None` never checks.
+    pub interface_watch_interval: Option<Duration>,
+    /// How many times per second host and stream stats are refreshed for
+    /// the UI, within
+    /// [`STATS_REFRESH_HZ`](crate::party::network_stream::STATS_REFRESH_HZ).
+    pub stats_refresh_hz: u32,
+    /// Directory named playlists of shared files are saved in.
+    pub playlist_dir: PathBuf,
+    /// Most of your own mic left queued for the monitor when the next mic
+    /// block arrives; anything older is dropped. Remote streams' jitter
+    /// buffering doesn't affect the monitor.
+    pub monitor_backlog: Duration,
+    /// Encrypt everything we send with a key derived from this passphrase,
+    /// and drop whatever doesn't decrypt with it. Everyone at the party
+    /// needs the same one. `None` sends in the clear.
+    pub encryption: Option<Passphrase>,
+}
+
+impl PartyConfig {
+    /// This config on the system's default audio devices and send interface,
+    /// to fall back to when the chosen ones fail.
+    pub fn on_default_devices(&self) -> Self {
+        Self {
+            input_device_id: None,
+            output_device_id: None,
+            output_channel_map: None,
+            output_sample_format: None,
+            send_interface_index: None,
+            ..self.clone()
+        }
+    }
+}
+
+impl Default for PartyConfig {
+    fn default() -> Self {
+        Self {
+            input_device_id: None,
+            output_device_id: None,
+            output_channel_map: None,
+            output_sample_format: None,
+            max_output_pull_frames: DEFAULT_MAX_OUTPUT_PULL_FRAMES,
+            ipv6: false,
+            multicast: MulticastConfig::default(),
+            send_interface_index: None,
+            compress_pcm_music: false,
+            underrun_policy: UnderrunPolicy::default(),
+            jitter_memory_budget: None,
+            jitter_epoch_tolerance: None,
+            jitter_concealment: Concealment::Silence,
+            dtx_silence: false,
+            loss_mute: None,
+            realtime_nack: false,
+            decode_workers: 0,
+            mic_send_queue: None,
+            disable_input_limiter: false,
+            mic_high_pass_hz: Some(DEFAULT_HIGH_PASS_HZ),
+            mic_agc: None,
+            mic_frame_duration: OpusFrameDuration::Ms20,
+            mic_encoder: OpusEncoderConfig::default(),
+            realtime_codec: AudioCodec::Opus,
+            auto_mic_frame_duration: None,
+            adaptive_mic_complexity: false,
+            system_frame_duration: OpusFrameDuration::Ms10,
+            music_frame_duration: OpusFrameDuration::Ms20,
+            de_esser: DeEsserConfig::default(),
+            reverb: ReverbConfig::default(),
+            retransmit_window: RetransmitWindow::default(),
+            synced_coalesce: None,
+            music_read_ahead: None,
+            music_sample_offsets: true,
+            auto_mono_music: false,
+            uplink_adaptation: None,
+            adaptive_bitrate: None,
+            presence: PresenceConfig::default(),
+            ignore_self: true,
+            multicast_loopback: true,
+            stable_instance_id: true,
+            timestamp_source: TimestampSource::default(),
+            output_switch_ramp: Duration::from_millis(50),
+            music_resync_threshold: DEFAULT_RESYNC_THRESHOLD,
+            pre_meta_frames: 0,
+            synced_end_gap_grace: Some(DEFAULT_END_GAP_GRACE),
+            seek_ready_frames: DEFAULT_SEEK_READY_FRAMES,
+            monotonic_music_clock: true,
+            concurrent_music: false,
+            music_waits_for_clock_sync: true,
+            silent_output_watchdog: Some(Duration::from_secs(10)),
+            mic_check: MicCheckConfig::default(),
+            reset_encoder_on_restart: true,
+            interface_watch_interval: Some(Duration::from_secs(5)),
+            stats_refresh_hz: DEFAULT_STATS_REFRESH_HZ,
+            playlist_dir: saved_playlist::default_dir(),
+            monitor_backlog: DEFAULT_MONITOR_BACKLOG,
+            encryption: None,
+        }
+    }
+}
+
+/// Upgrades saved JSON from the version before this migration to its own.
+pub type Migration = fn(&mut Value) -> Result<()>;
+
+/// Serializes `value` as pretty JSON stamped with the latest version of
+/// its type, the one after `migrations`.
+pub fn to_versioned_json<T: Serialize>(value: &T, migrations: &[Migration]) -> Result<String> {
+    let mut json = serde_json::to_value(value)?;
+    let Value::Object(fields) = &mut json else {
+        bail!("Only objects can be versioned");
+    };
+    fields.insert("version".into(), (migrations.len() + 1).into());
+    Ok(serde_json::to_string_pretty(&json)?)
+}
+
+/// Parses JSON written by [`to_versioned_json`] with any earlier version of
+/// `migrations`, upgrading it to the latest first. `what` names the data in
+/// the log.
+pub fn from_versioned_json<T: DeserializeOwned>(
+    json: &str,
+    what: &str,
+    migrations: &[Migration],
+) -> Result<T> {
+    let mut json: Value = serde_json::from_str(json)?;
+    let latest = migrations.len() + 1;
+    let version = match json.get("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .filter(|&v| v >= 1)
+            .with_context(|| format!("Invalid version {version}"))?
+            as usize,
+    };
+    if version > latest {
+        bail!("Saved by a newer version (format {version}, this build reads up to {latest})");
+    }
+    for migration in &migrations[version - 1..] {
+        migration(&mut json)?;
+    }
+    if version < latest {
+        info!("Migrated {} from format {} to {}", what, version, latest);
+    }
+    Ok(serde_json::from_value(json)?)
 }
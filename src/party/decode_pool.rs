@@ -0,0 +1,148 @@
+//! Worker threads for decoding received realtime streams.
+//!
+//! By default every received frame is decoded on the receive task, so with
+//! many participants one expensive stream holds up all the others.
+//! [`DecodePool`] moves the decoding onto a few worker threads. Each stream
+//! is pinned to one worker when its decode chain is created, which keeps its
+//! frames in arrival order while different streams decode in parallel.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+use tracing::warn;
+
+use crate::pipeline::Pushable;
+
+type Job<T> = (Arc<dyn Pushable<T>>, T);
+
+/// A fixed set of threads, each pushing its queued items in order.
+pub struct DecodePool<T> {
+    senders: Vec<Sender<Job<T>>>,
+    workers: Vec<JoinHandle<()>>,
+    next_worker: AtomicUsize,
+}
+
+impl<T: Send + 'static> DecodePool<T> {
+    /// Starts `workers` threads (at least one).
+    pub fn new(workers: usize) -> Self {
+        let mut senders = Vec::new();
+        let mut handles = Vec::new();
+        for index in 0..workers.max(1) {
+            let (sender, receiver) = mpsc::channel::<Job<T>>();
+            let handle = thread::Builder::new()
+                .name(format!("decode-{index}"))
+                .spawn(move || {
+                    for (target, item) in receiver {
+                        target.push(item);
+                    }
+                })
+                .expect("Failed to spawn decode worker");
+            senders.push(sender);
+            handles.push(handle);
+        }
+        Self {
+            senders,
+            workers: handles,
+            next_worker: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Picks the worker for a new stream, round robin.
+    pub fn assign(&self) -> usize {
+        self.next_worker.fetch_add(1, Ordering::Relaxed) % self.workers.len()
+    }
+
+    /// Queues `item` to be pushed into `target` on `worker`, after everything
+    /// queued on that worker before it.
+    pub fn submit(&self, worker: usize, target: Arc<dyn Pushable<T>>, item: T) {
+        let sender = &self.senders[worker % self.senders.len()];
+        if sender.send((target, item)).is_err() {
+            warn!("Decode worker {} has stopped, dropping frame", worker);
+        }
+    }
+}
+
+impl<T> Drop for DecodePool<T> {
+    /// Lets the workers finish what is queued, then joins them.
+    fn drop(&mut self) {
+        self.senders.clear();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    /// Stands in for a stream's decode chain: records what it is pushed,
+    /// optionally waiting for a gate first.
+    #[derive(Default)]
+    struct RecordingChain {
+        received: Mutex<Vec<u64>>,
+        gate: Mutex<Option<mpsc::Receiver<()>>>,
+    }
+
+    impl Pushable<u64> for RecordingChain {
+        fn push(&self, sequence: u64) {
+            if let Some(gate) = self.gate.lock().unwrap().take() {
+                gate.recv().unwrap();
+            }
+            self.received.lock().unwrap().push(sequence);
+        }
+    }
+
+    #[test]
+    fn streams_on_separate_workers_stay_ordered_and_independent() {
+        const FRAMES: u64 = 500;
+        let pool = Arc::new(DecodePool::new(2));
+        let slow = Arc::new(RecordingChain::default());
+        let fast = Arc::new(RecordingChain::default());
+        let (release_slow, gate) = mpsc::channel();
+        *slow.gate.lock().unwrap() = Some(gate);
+
+        let slow_worker = pool.assign();
+        let fast_worker = pool.assign();
+        assert_ne!(slow_worker, fast_worker);
+
+        let producers: Vec<_> = [(slow.clone(), slow_worker), (fast.clone(), fast_worker)]
+            .into_iter()
+            .map(|(chain, worker)| {
+                let pool = pool.clone();
+                thread::spawn(move || {
+                    for sequence in 0..FRAMES {
+                        pool.submit(worker, chain.clone(), sequence);
+                    }
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        // The slow stream is stuck on its first frame; the fast one still
+        // gets everything.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while fast.received.lock().unwrap().len() < FRAMES as usize {
+            assert!(Instant::now() < deadline, "fast stream was held up");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(slow.received.lock().unwrap().is_empty());
+
+        release_slow.send(()).unwrap();
+        drop(Arc::into_inner(pool).unwrap());
+
+        let expected: Vec<u64> = (0..FRAMES).collect();
+        assert_eq!(*fast.received.lock().unwrap(), expected);
+        assert_eq!(*slow.received.lock().unwrap(), expected);
+    }
+}
@@ -0,0 +1,268 @@
+//! Explicit goodbyes.
+//!
+//! Without them a participant that closes the app, or a stream that is
+//! switched off, only disappears from everyone's lists once its packets
+//! have been missing for `HOST_TIMEOUT`. Instead the sender multicasts a
+//! [`Bye`] naming the streams that stopped, or all of them when it leaves
+//! the party, and receivers drop them at once. Being UDP, every bye is sent
+//! a few times.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use rkyv::{Archive, Deserialize, Serialize};
+
+use crate::audio::AudioSample;
+use crate::io::NetworkSender;
+use crate::party::network_stream::{NetworkStream, NetworkStreamContext};
+use crate::party::presence::PresenceService;
+use crate::party::realtime_stream::{RealtimeAudioStream, RealtimeStreamId};
+use crate::party::share_music::receiver::SyncedAudioStreamManager;
+use crate::party::tagged_packet::{BYE_TAG, PacketTag, TaggedPacket};
+use crate::pipeline::Pushable;
+use crate::state::PartyViewState;
+
+/// Copies of each bye sent, in case some are lost.
+const BYE_COPIES: usize = 3;
+/// Gap between the copies, so one burst of loss doesn't take all of them.
+const BYE_SPACING: Duration = Duration::from_millis(20);
+/// How often the transmit switches are checked for streams turned off.
+const TRANSMIT_POLL: Duration = Duration::from_millis(100);
+
+/// Says that the sender stopped some of its realtime streams.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Bye {
+    pub stream_ids: Vec<RealtimeStreamId>,
+    /// The sender left the party: also forget its music and presence.
+    pub leaving: bool,
+}
+
+impl Bye {
+    /// Bye for every stream, sent when leaving the party.
+    pub fn leaving() -> Self {
+        Self {
+            stream_ids: vec![RealtimeStreamId::Mic, RealtimeStreamId::System],
+            leaving: true,
+        }
+    }
+
+    fn packet(&self) -> TaggedPacket {
+        let payload = rkyv::to_bytes::<rkyv::rancor::Error>(self)
+            .expect("Bye serialization")
+            .into_vec();
+        TaggedPacket::new(BYE_TAG, payload)
+    }
+}
+
+/// Sends our byes and drops what others say goodbye to.
+pub struct DepartureService<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    sender: NetworkSender,
+    realtime: Arc<RealtimeAudioStream<Sample, CHANNELS, SAMPLE_RATE>>,
+    synced: Arc<SyncedAudioStreamManager<Sample, CHANNELS, SAMPLE_RATE>>,
+    presence: Arc<PresenceService>,
+    /// Switches of our own streams, watched to say goodbye when one is
+    /// turned off.
+    transmit: Vec<(RealtimeStreamId, Arc<AtomicBool>)>,
+    /// Refreshed right after a bye, set once the network is up.
+    view_state: OnceLock<Arc<PartyViewState>>,
+}
+
+impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    DepartureService<Sample, CHANNELS, SAMPLE_RATE>
+{
+    pub fn new(
+        sender: NetworkSender,
+        realtime: Arc<RealtimeAudioStream<Sample, CHANNELS, SAMPLE_RATE>>,
+        synced: Arc<SyncedAudioStreamManager<Sample, CHANNELS, SAMPLE_RATE>>,
+        presence: Arc<PresenceService>,
+    ) -> Self {
+        Self {
+            sender,
+            realtime,
+            synced,
+            presence,
+            transmit: Vec::new(),
+            view_state: OnceLock::new(),
+        }
+    }
+
+    /// Says goodbye for a stream whenever its switch in `transmit` is
+    /// turned off.
+    pub fn with_transmit(mut self, transmit: Vec<(RealtimeStreamId, Arc<AtomicBool>)>) -> Self {
+        self.transmit = transmit;
+        self
+    }
+
+    /// Drops what `source` said goodbye to.
+    pub fn receive(&self, source: SocketAddr, bye: &Bye) {
+        self.realtime.remove_streams(source, &bye.stream_ids);
+        if bye.leaving {
+            self.synced.remove_source(source);
+            self.presence.remove(source);
+        }
+
+        if let Some(view_state) = self.view_state.get() {
+            self.realtime.update_view_state(view_state);
+            self.presence.update_view_state(view_state);
+            if bye.leaving {
+                view_state.set_synced_streams(self.synced.active_streams());
+            }
+        }
+    }
+
+    /// Starts the task saying goodbye for streams that are turned off.
+    ///
+    /// Must be called from within a Tokio runtime context.
+    fn start_transmit_watch(self: &Arc<Self>) {
+        if self.transmit.is_empty() {
+            return;
+        }
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TRANSMIT_POLL);
+            let mut was_on: Vec<bool> = service
+                .transmit
+                .iter()
+                .map(|(_, flag)| flag.load(Ordering::Relaxed))
+                .collect();
+            loop {
+                interval.tick().await;
+                for ((stream_id, flag), was_on) in service.transmit.iter().zip(&mut was_on) {
+                    let on = flag.load(Ordering::Relaxed);
+                    if *was_on && !on {
+                        let bye = Bye {
+                            stream_ids: vec![*stream_id],
+                            leaving: false,
+                        };
+                        let sender = service.sender.clone();
+                        tokio::spawn(async move {
+                            for _ in 0..BYE_COPIES {
+                                sender.push(bye.packet());
+                                tokio::time::sleep(BYE_SPACING).await;
+                            }
+                        });
+                    }
+                    *was_on = on;
+                }
+            }
+        });
+    }
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    DepartureService<Sample, CHANNELS, SAMPLE_RATE>
+{
+    /// Tells everyone we're leaving. Blocks for the gaps between copies.
+    pub fn leave(&self) {
+        let packet = Bye::leaving().packet();
+        for copy in 0..BYE_COPIES {
+            if copy > 0 {
+                std::thread::sleep(BYE_SPACING);
+            }
+            self.sender.push(packet.clone());
+        }
+    }
+}
+
+impl<S: AudioSample + 'static, const C: usize, const SR: u32> NetworkStream<S, C, SR>
+    for DepartureService<S, C, SR>
+{
+    fn tags(&self) -> &'static [PacketTag] {
+        &[BYE_TAG]
+    }
+
+    fn handle(&self, source: SocketAddr, _tag: PacketTag, bytes: &[u8]) -> anyhow::Result<()> {
+        let bye = rkyv::from_bytes::<Bye, rkyv::rancor::Error>(bytes)
+            .map_err(|e| anyhow::anyhow!("Bye deserialize: {:?}", e))?;
+        self.receive(source, &bye);
+        Ok(())
+    }
+
+    fn start(self: Arc<Self>, ctx: NetworkStreamContext) {
+        let _ = self.view_state.set(ctx.view_state);
+        self.start_transmit_watch();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::UdpSocket;
+
+    use crate::audio::OpusEncoder;
+    use crate::audio::frame::AudioBuffer;
+    use crate::party::presence::PresenceConfig;
+    use crate::party::realtime_stream::RealtimeFramePacker;
+    use crate::pipeline::Node;
+    use crate::state::HostId;
+
+    fn service() -> DepartureService<f32, 2, 48000> {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = NetworkSender::new(
+            socket,
+            "127.0.0.1:9999".parse().unwrap(),
+            Arc::new(std::sync::Mutex::new(crate::io::SendTarget::Multicast)),
+        );
+        DepartureService::new(
+            sender,
+            Arc::new(RealtimeAudioStream::new()),
+            Arc::new(SyncedAudioStreamManager::new(
+                || 0,
+                Arc::new(AtomicBool::new(false)),
+            )),
+            Arc::new(PresenceService::new(PresenceConfig::default())),
+        )
+    }
+
+    fn deliver(service: &DepartureService<f32, 2, 48000>, source: SocketAddr, bye: &Bye) {
+        let packet = bye.packet();
+        NetworkStream::<f32, 2, 48000>::handle(service, source, packet.tag, &packet.payload)
+            .unwrap();
+    }
+
+    #[test]
+    fn bye_removes_streams_without_waiting_for_the_timeout() {
+        let service = service();
+        let view_state = Arc::new(PartyViewState::new());
+        let _ = service.view_state.set(view_state.clone());
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let alice: SocketAddr = "192.168.1.2:7667".parse().unwrap();
+        let bob: SocketAddr = "192.168.1.3:7667".parse().unwrap();
+        for (source, stream_id) in [
+            (alice, RealtimeStreamId::Mic),
+            (alice, RealtimeStreamId::System),
+            (bob, RealtimeStreamId::Mic),
+        ] {
+            let packer = RealtimeFramePacker::new(stream_id);
+            let input = AudioBuffer::new(vec![0.1; 1920]).unwrap();
+            let packet = packer.process(encoder.process(input).unwrap()).unwrap();
+            NetworkStream::handle(&*service.realtime, source, packet.tag, &packet.payload).unwrap();
+        }
+        assert_eq!(service.realtime.buffer_count(), 3);
+        service.realtime.update_view_state(&view_state);
+        assert_eq!(view_state.realtime_hosts().len(), 2);
+
+        // Alice turns off system audio.
+        let stopped = Bye {
+            stream_ids: vec![RealtimeStreamId::System],
+            leaving: false,
+        };
+        deliver(&service, alice, &stopped);
+        assert_eq!(service.realtime.buffer_count(), 2);
+
+        // Then leaves; the repeated copies change nothing.
+        for _ in 0..BYE_COPIES {
+            deliver(&service, alice, &Bye::leaving());
+        }
+        assert_eq!(service.realtime.buffer_count(), 1);
+        let hosts = view_state.realtime_hosts();
+        assert_eq!(hosts.len(), 1, "the host list follows at once");
+        assert_eq!(hosts[0].id, HostId::from(bob.ip()));
+
+        deliver(&service, bob, &Bye::leaving());
+        assert_eq!(service.realtime.buffer_count(), 0);
+    }
+}
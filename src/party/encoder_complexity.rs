@@ -0,0 +1,189 @@
+//! Scaling of the mic encoder's complexity with the time it has to spare.
+//!
+//! Opus at its highest complexity sounds best but takes the most CPU. On a
+//! slow or busy machine, encoding a frame can take long enough that the
+//! audio thread misses its deadline and the mic stutters. With scaling on,
+//! [`ComplexityController`] times every encoded frame against the frame's
+//! duration and, when encoding eats into that budget, lowers the
+//! complexity; once there is headroom again it raises it step by step.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use tracing::{info, warn};
+
+use crate::audio::frame::AudioBuffer;
+use crate::audio::opus::OpusPacket;
+use crate::audio::{AudioSample, OpusEncoder};
+use crate::pipeline::Node;
+
+/// Lowest complexity used, however busy the machine.
+const MIN_COMPLEXITY: i32 = 2;
+/// Highest complexity, used while there is headroom.
+const MAX_COMPLEXITY: i32 = 10;
+/// Complexity dropped per check once encoding nears the deadline.
+const DEGRADE_STEP: i32 = 2;
+/// Share of a frame's duration spent encoding it at which complexity is
+/// lowered.
+const DEGRADE_LOAD: f64 = 0.5;
+/// Share below which a check counts as having headroom.
+const RECOVER_LOAD: f64 = 0.2;
+/// Checks with headroom in a row before raising complexity one step.
+const RECOVER_CHECKS: u32 = 3;
+/// Frames between checks (one second of 20 ms frames).
+const CHECK_INTERVAL: u64 = 50;
+
+/// Hysteresis picking the complexity from the peak encoding load of each
+/// check.
+#[derive(Debug)]
+pub struct ComplexityHysteresis {
+    complexity: i32,
+    calm_checks: u32,
+}
+
+impl Default for ComplexityHysteresis {
+    fn default() -> Self {
+        Self {
+            complexity: MAX_COMPLEXITY,
+            calm_checks: 0,
+        }
+    }
+}
+
+impl ComplexityHysteresis {
+    /// Complexity to configure after a check whose slowest frame took
+    /// `load` of its duration to encode.
+    pub fn update(&mut self, load: f64) -> i32 {
+        if load >= DEGRADE_LOAD {
+            self.complexity = (self.complexity - DEGRADE_STEP).max(MIN_COMPLEXITY);
+            self.calm_checks = 0;
+        } else if load < RECOVER_LOAD {
+            self.calm_checks += 1;
+            if self.calm_checks >= RECOVER_CHECKS {
+                self.complexity = (self.complexity + 1).min(MAX_COMPLEXITY);
+                self.calm_checks = 0;
+            }
+        } else {
+            self.calm_checks = 0;
+        }
+        self.complexity
+    }
+}
+
+/// Node wrapping an encoder that times each frame it encodes and scales
+/// the encoder's complexity to keep encoding well within the frame's
+/// duration. Disabled, it only encodes.
+pub struct ComplexityController<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    encoder: Arc<OpusEncoder<Sample, CHANNELS, SAMPLE_RATE>>,
+    enabled: bool,
+    /// Hysteresis, the complexity set, and the peak load since the last
+    /// check.
+    state: Mutex<(ComplexityHysteresis, i32, f64)>,
+    frames: AtomicU64,
+}
+
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    ComplexityController<Sample, CHANNELS, SAMPLE_RATE>
+{
+    pub fn new(encoder: Arc<OpusEncoder<Sample, CHANNELS, SAMPLE_RATE>>, enabled: bool) -> Self {
+        if enabled && let Err(e) = encoder.set_complexity(MAX_COMPLEXITY) {
+            warn!("Failed to set encoder complexity: {:?}", e);
+        }
+        Self {
+            encoder,
+            enabled,
+            state: Mutex::new((ComplexityHysteresis::default(), MAX_COMPLEXITY, 0.0)),
+            frames: AtomicU64::new(0),
+        }
+    }
+
+    /// Records one frame's load, and every check interval applies the
+    /// complexity for the peak load seen.
+    fn record(&self, load: f64) {
+        let mut state = self.state.lock().unwrap();
+        state.2 = state.2.max(load);
+        if self.frames.fetch_add(1, Ordering::Relaxed) % CHECK_INTERVAL != CHECK_INTERVAL - 1 {
+            return;
+        }
+        let peak = std::mem::take(&mut state.2);
+        let complexity = state.0.update(peak);
+        if complexity == state.1 {
+            return;
+        }
+        match self.encoder.set_complexity(complexity) {
+            Ok(()) => {
+                info!(
+                    "Encoder complexity {} -> {} (encoding took up to {:.0}% of a frame)",
+                    state.1,
+                    complexity,
+                    peak * 100.0
+                );
+                state.1 = complexity;
+            }
+            Err(e) => warn!("Failed to set encoder complexity: {:?}", e),
+        }
+    }
+}
+
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> Node
+    for ComplexityController<Sample, CHANNELS, SAMPLE_RATE>
+{
+    type Input = AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>;
+    type Output = OpusPacket;
+
+    fn process(&self, input: Self::Input) -> Option<Self::Output> {
+        if !self.enabled {
+            return self.encoder.process(input);
+        }
+        let duration = input.samples_per_channel() as f64 / SAMPLE_RATE as f64;
+        let start = Instant::now();
+        let output = self.encoder.process(input);
+        if duration > 0.0 {
+            self.record(start.elapsed().as_secs_f64() / duration);
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complexity_follows_encoding_headroom_within_bounds() {
+        let mut hysteresis = ComplexityHysteresis::default();
+        assert_eq!(hysteresis.update(0.1), MAX_COMPLEXITY);
+
+        // Encoding nearing the frame deadline lowers complexity check by
+        // check, down to the floor.
+        let lowered: Vec<i32> = [0.55, 0.7, 0.9, 0.95, 0.99]
+            .into_iter()
+            .map(|load| hysteresis.update(load))
+            .collect();
+        assert_eq!(lowered, [8, 6, 4, 2, 2]);
+
+        // Moderate load holds it where it is.
+        for _ in 0..10 {
+            assert_eq!(hysteresis.update(0.3), MIN_COMPLEXITY);
+        }
+
+        // Ample headroom raises it again, one step per calm stretch, up to
+        // the ceiling.
+        let mut raised = Vec::new();
+        for _ in 0..30 {
+            raised.push(hysteresis.update(0.05));
+        }
+        assert_eq!(raised[..3], [2, 2, 3]);
+        assert_eq!(raised[RECOVER_CHECKS as usize * 8 - 1], MAX_COMPLEXITY);
+        assert_eq!(hysteresis.update(0.05), MAX_COMPLEXITY);
+
+        // A single slow frame among calm checks interrupts the recovery.
+        hysteresis.update(0.6);
+        assert_eq!(hysteresis.update(0.05), 8);
+        assert_eq!(hysteresis.update(0.3), 8);
+        assert_eq!(hysteresis.update(0.05), 8);
+        assert_eq!(hysteresis.update(0.05), 8);
+        assert_eq!(hysteresis.update(0.05), 9);
+    }
+}
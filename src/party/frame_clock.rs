@@ -0,0 +1,171 @@
+//! Timestamps for outgoing realtime frames.
+//!
+//! Frames are stamped when packed. By default the stamp is the OS wall clock,
+//! which jumps on NTP steps and after suspend/resume. [`FrameClock`] can
+//! instead derive the stamp from a monotonic clock plus the party clock
+//! offset, so it only ever moves forward.
+//!
+//! The monotonic clock is the boot clock, which keeps counting while the
+//! machine sleeps. `Instant` stops during suspend on Linux, Android and
+//! Apple platforms, so stamps based on it would lag by however long the
+//! machine slept.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::party::ntp::NtpService;
+
+/// Where realtime frame timestamps come from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampSource {
+    /// Local wall time (`SystemTime::now()`), following every OS clock step.
+    #[default]
+    WallClock,
+    /// Wall time sampled once, advanced by the boot clock and shifted by the
+    /// NTP offset into party time. Never runs backwards.
+    Monotonic,
+}
+
+/// Stamps realtime frames in microseconds since the Unix epoch.
+pub struct FrameClock {
+    source: TimestampSource,
+    wall_now: Arc<dyn Fn() -> u64 + Send + Sync>,
+    offset: Arc<dyn Fn() -> i64 + Send + Sync>,
+    baseline: u64,
+    baseline_micros: u64,
+    last: AtomicU64,
+}
+
+impl FrameClock {
+    /// Plain wall clock, the historical behavior.
+    pub fn wall_clock() -> Self {
+        Self::with_sources(
+            TimestampSource::WallClock,
+            NtpService::local_now_micros,
+            || 0,
+        )
+    }
+
+    /// Clock for `source`, using the party clock offset from `ntp`.
+    pub fn new(source: TimestampSource, ntp: Arc<NtpService>) -> Self {
+        Self::with_sources(source, NtpService::local_now_micros, move || {
+            ntp.offset_micros()
+        })
+    }
+
+    /// Clock reading wall time and the party clock offset from the given
+    /// functions.
+    pub fn with_sources(
+        source: TimestampSource,
+        wall_now: impl Fn() -> u64 + Send + Sync + 'static,
+        offset: impl Fn() -> i64 + Send + Sync + 'static,
+    ) -> Self {
+        let baseline_micros = wall_now();
+        Self {
+            source,
+            wall_now: Arc::new(wall_now),
+            offset: Arc::new(offset),
+            baseline: boot_micros(),
+            baseline_micros,
+            last: AtomicU64::new(0),
+        }
+    }
+
+    pub fn now_micros(&self) -> u64 {
+        match self.source {
+            TimestampSource::WallClock => (self.wall_now)(),
+            TimestampSource::Monotonic => {
+                let elapsed = boot_micros().saturating_sub(self.baseline);
+                let now = (self.baseline_micros + elapsed).saturating_add_signed((self.offset)());
+                // The NTP offset can be corrected downwards; hold the last
+                // stamp rather than step back.
+                self.last.fetch_max(now, Ordering::Relaxed).max(now)
+            }
+        }
+    }
+}
+
+/// Microseconds since boot, including time spent suspended.
+#[cfg(unix)]
+fn boot_micros() -> u64 {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const CLOCK: libc::clockid_t = libc::CLOCK_BOOTTIME;
+    // Darwin's CLOCK_MONOTONIC counts sleep; CLOCK_UPTIME_RAW, which
+    // `Instant` uses there, does not.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const CLOCK: libc::clockid_t = libc::CLOCK_MONOTONIC;
+
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // Cannot fail for a supported clock id and a valid pointer.
+    unsafe { libc::clock_gettime(CLOCK, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000 + ts.tv_nsec as u64 / 1_000
+}
+
+/// Microseconds since the first stamp. `Instant` is backed by
+/// `QueryPerformanceCounter` on Windows, which counts suspended time.
+#[cfg(not(unix))]
+fn boot_micros() -> u64 {
+    use std::sync::OnceLock;
+    use std::time::Instant;
+
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_micros() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR_MICROS: u64 = 3_600_000_000;
+
+    #[test]
+    fn monotonic_survives_wall_clock_jump() {
+        let wall = Arc::new(AtomicU64::new(10 * HOUR_MICROS));
+        let wall_now = {
+            let wall = wall.clone();
+            move || wall.load(Ordering::Relaxed)
+        };
+        let monotonic =
+            FrameClock::with_sources(TimestampSource::Monotonic, wall_now.clone(), || 0);
+        let wall_clock = FrameClock::with_sources(TimestampSource::WallClock, wall_now, || 0);
+
+        let mono_before = monotonic.now_micros();
+        let wall_before = wall_clock.now_micros();
+        wall.fetch_sub(HOUR_MICROS, Ordering::Relaxed);
+        let mono_after = monotonic.now_micros();
+        let wall_after = wall_clock.now_micros();
+
+        assert!(wall_after < wall_before, "wall clock follows the step");
+        assert!(mono_after >= mono_before);
+        assert!(mono_after - mono_before < 1_000_000);
+    }
+
+    #[test]
+    fn boot_clock_advances_with_real_time() {
+        let before = boot_micros();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let elapsed = boot_micros() - before;
+        assert!((20_000..1_000_000).contains(&elapsed), "{elapsed}");
+    }
+
+    #[test]
+    fn monotonic_holds_through_offset_correction() {
+        let offset = Arc::new(AtomicU64::new(500_000));
+        let clock = {
+            let offset = offset.clone();
+            FrameClock::with_sources(
+                TimestampSource::Monotonic,
+                || HOUR_MICROS,
+                move || offset.load(Ordering::Relaxed) as i64,
+            )
+        };
+
+        let before = clock.now_micros();
+        assert!(before >= HOUR_MICROS + 500_000);
+        offset.store(0, Ordering::Relaxed);
+        assert!(clock.now_micros() >= before);
+    }
+}
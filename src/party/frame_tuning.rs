@@ -0,0 +1,84 @@
+//! Picking the mic frame duration from measured network jitter.
+//!
+//! Short frames cut latency but spend more of the bitrate on packet
+//! overhead. How much latency they actually save depends on the network:
+//! receivers' jitter buffers already hold back about as much audio as
+//! arrivals wander, so frames shorter than the jitter buy little. With
+//! tuning on, the jitter of peers' streams is measured for a while after
+//! joining and the mic's frame duration is set from it with
+//! [`recommend_frame_duration`].
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::audio::{AudioBatcher, AudioSample, OpusFrameDuration};
+use crate::party::realtime_stream::RealtimeAudioStream;
+
+/// Jitter below which 10 ms frames are worth their overhead, in ms.
+const SHORT_FRAME_MAX_JITTER_MS: f64 = 10.0;
+/// Jitter below which 20 ms frames are used; above it, 40 ms.
+const MEDIUM_FRAME_MAX_JITTER_MS: f64 = 30.0;
+
+/// Frame duration for a network with `jitter_ms` of measured jitter.
+pub fn recommend_frame_duration(jitter_ms: f64) -> OpusFrameDuration {
+    if jitter_ms < SHORT_FRAME_MAX_JITTER_MS {
+        OpusFrameDuration::Ms10
+    } else if jitter_ms < MEDIUM_FRAME_MAX_JITTER_MS {
+        OpusFrameDuration::Ms20
+    } else {
+        OpusFrameDuration::Ms40
+    }
+}
+
+/// After `window`, sets `batcher` to the frame duration recommended for the
+/// worst jitter measured on `realtime`. Without any stream to measure, the
+/// configured duration stays.
+///
+/// Must be called from within a Tokio runtime context.
+pub fn start<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u32>(
+    window: Duration,
+    realtime: Arc<RealtimeAudioStream<Sample, CHANNELS, SAMPLE_RATE>>,
+    batcher: Arc<AudioBatcher<Sample, CHANNELS, SAMPLE_RATE>>,
+) {
+    tokio::spawn(async move {
+        tokio::time::sleep(window).await;
+        match realtime.worst_jitter_ms() {
+            Some(jitter_ms) => {
+                let duration = recommend_frame_duration(jitter_ms);
+                info!(
+                    "Measured {:.1} ms of jitter, sending {} ms mic frames",
+                    jitter_ms,
+                    duration.as_millis()
+                );
+                batcher.set_min_ms(duration.as_millis());
+            }
+            None => info!("No streams to measure jitter on, keeping the mic frame duration"),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_ranges_map_to_frame_durations() {
+        for (jitter_ms, expected) in [
+            (0.0, OpusFrameDuration::Ms10),
+            (9.9, OpusFrameDuration::Ms10),
+            (10.0, OpusFrameDuration::Ms20),
+            (20.0, OpusFrameDuration::Ms20),
+            (29.9, OpusFrameDuration::Ms20),
+            (30.0, OpusFrameDuration::Ms40),
+            (250.0, OpusFrameDuration::Ms40),
+        ] {
+            assert_eq!(
+                recommend_frame_duration(jitter_ms),
+                expected,
+                "{jitter_ms} ms of jitter"
+            );
+        }
+    }
+}
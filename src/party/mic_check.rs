@@ -0,0 +1,182 @@
+//! Startup microphone check.
+//!
+//! New users often can't tell whether their mic works. Before they go live,
+//! a [`MicCheck`] taps the party's mic input for a moment, without sending
+//! anything to the party, and classifies the captured level with
+//! [`calculate_rms_level`]. An input that isn't running is started for the
+//! check with the mic pipeline closed behind the tap, so nothing is played
+//! back or metered either.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::audio::AudioSample;
+use crate::audio::effects::calculate_rms_level;
+use crate::audio::frame::AudioBuffer;
+use crate::io::AudioInput;
+use crate::party::combinator::{TapId, Taps};
+
+/// Room for captured buffers waiting on the check's tap.
+const CAPTURE_QUEUE: usize = 64;
+
+/// Whether to check the mic on startup, for how long, and how loud it must be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MicCheckConfig {
+    pub enabled: bool,
+    pub duration: Duration,
+    /// Lowest level (0-100, as [`calculate_rms_level`]) counted as a signal.
+    /// The default of 1 is an RMS of about -40 dBFS.
+    pub min_level: u32,
+}
+
+impl Default for MicCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            duration: Duration::from_secs(1),
+            min_level: 1,
+        }
+    }
+}
+
+/// Outcome of a mic check, with the measured level (0-100).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MicCheckResult {
+    Ok { level: u32 },
+    NoSignal { level: u32 },
+}
+
+impl MicCheckResult {
+    pub fn message(&self) -> &'static str {
+        match self {
+            MicCheckResult::Ok { .. } => "Microphone is working",
+            MicCheckResult::NoSignal { .. } => "No signal detected — is the right device selected?",
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        matches!(self, MicCheckResult::Ok { .. })
+    }
+}
+
+/// Classifies captured samples against `min_level`.
+pub fn classify<Sample: AudioSample>(samples: &[Sample], min_level: u32) -> MicCheckResult {
+    let level = calculate_rms_level(samples);
+    if level >= min_level.max(1) {
+        MicCheckResult::Ok { level }
+    } else {
+        MicCheckResult::NoSignal { level }
+    }
+}
+
+/// The party's mic as a check needs it.
+pub struct MicTap<'a, Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    pub input: &'a AudioInput<Sample, CHANNELS, SAMPLE_RATE>,
+    /// Observers of the captured audio before any processing.
+    pub taps: &'a Arc<Taps<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+    /// Whether captured audio goes on past the taps into the mic pipeline.
+    pub open: &'a AtomicBool,
+}
+
+/// A mic check under way.
+///
+/// Starting and finishing touch the mic input and must be serialized with
+/// turning the mic on and off; [`wait`](Self::wait) doesn't, and is meant to
+/// run without holding whatever does that.
+pub struct MicCheck<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    config: MicCheckConfig,
+    samples: Arc<Mutex<Vec<Sample>>>,
+    taps: Arc<Taps<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+    tap: TapId,
+    /// Whether the check started the input and should stop it again.
+    started: bool,
+}
+
+impl<Sample: AudioSample + cpal::SizedSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    MicCheck<Sample, CHANNELS, SAMPLE_RATE>
+{
+    /// Starts capturing from `mic`, starting its input with the pipeline
+    /// closed if it isn't running.
+    pub fn start(
+        mic: MicTap<'_, Sample, CHANNELS, SAMPLE_RATE>,
+        config: MicCheckConfig,
+    ) -> Result<Self> {
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let capture = samples.clone();
+        let tap = mic.taps.add(
+            move |buffer: AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>| {
+                capture.lock().unwrap().extend_from_slice(buffer.data());
+            },
+            CAPTURE_QUEUE,
+        );
+        let started = !mic.input.is_enabled();
+        if started {
+            mic.open.store(false, Ordering::Release);
+            if let Err(e) = mic.input.enable() {
+                mic.taps.remove(tap);
+                return Err(e);
+            }
+        }
+        Ok(Self {
+            config,
+            samples,
+            taps: mic.taps.clone(),
+            tap,
+            started,
+        })
+    }
+
+    /// Blocks for the check duration and classifies what was captured.
+    pub fn wait(&self) -> MicCheckResult {
+        std::thread::sleep(self.config.duration);
+        self.taps.remove(self.tap);
+        let samples = self.samples.lock().unwrap();
+        let result = classify(&samples, self.config.min_level);
+        info!("Mic check over {} samples: {:?}", samples.len(), result);
+        result
+    }
+
+    /// Stops the input again if the check started it and the mic wasn't
+    /// turned on meanwhile.
+    pub fn finish(self, mic: MicTap<'_, Sample, CHANNELS, SAMPLE_RATE>) {
+        self.taps.remove(self.tap);
+        if self.started && !mic.open.load(Ordering::Acquire) {
+            mic.input.disable();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silent_capture_is_no_signal_and_speech_is_ok() {
+        let min_level = MicCheckConfig::default().min_level;
+
+        let silence = vec![0.0f32; 48000 * 2];
+        assert_eq!(
+            classify(&silence, min_level),
+            MicCheckResult::NoSignal { level: 0 }
+        );
+
+        // A dead input often still carries a little hiss.
+        let hiss: Vec<f32> = (0..48000 * 2)
+            .map(|n| if n % 2 == 0 { 0.002 } else { -0.002 })
+            .collect();
+        assert!(!classify(&hiss, min_level).is_ok());
+
+        let voice: Vec<f32> = (0..48000 * 2)
+            .map(|n| 0.2 * (n as f32 * 0.03).sin())
+            .collect();
+        let result = classify(&voice, min_level);
+        assert!(result.is_ok());
+        assert_eq!(result, MicCheckResult::Ok { level: 14 });
+
+        assert!(!classify(&voice, 20).is_ok(), "threshold is respected");
+    }
+}
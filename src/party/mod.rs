@@ -23,31 +23,68 @@
 //!             └───────────────────┘
 //! ```
 //!
+//! # Local monitor
+//!
+//! Hearing your own mic is kept apart from the remote streams. It is teed
+//! off the mic chain before encoding and fed to the speaker mixer through a
+//! [`MonitorBuffer`](crate::audio::MonitorBuffer), so it never passes the
+//! network, Opus or a jitter buffer. Its delay is set by the audio
+//! callbacks and [`PartyConfig::monitor_backlog`] alone, however much
+//! latency the jitter buffers of remote streams have grown to.
+//!
 //! # Submodules
 //!
 //! - [`party`] - Main [`Party`] orchestrator that wires everything together
 //! - [`stream`] - Realtime audio stream abstraction ([`NetworkPacket`], [`RealtimeAudioStream`])
 //! - [`share_music`] - Synchronized music sharing (sender + receiver)
+//! - [`encoder_complexity`] - Mic encoder complexity scaled with CPU headroom
+//! - [`frame_clock`] - Timestamp source for outgoing realtime frames
+//! - [`frame_tuning`] - Mic frame duration picked from measured jitter
 //! - [`packet_dispatcher`] - Network packet receiving and dispatching
+//! - [`decode_pool`] - Worker threads for decoding received streams
+//! - [`presence`] - Heartbeats that keep silent participants listed
+//! - [`departure`] - Byes that drop departed hosts and stopped streams at once
 //! - [`combinator`] - Pipeline routing utilities (tee, switch, mix)
+//! - [`saved_settings`] - Device and network settings remembered across launches
+//! - [`snapshot`] - Serializable point-in-time view of the party ([`PartySnapshot`])
+//! - [`uplink`] - Shedding outgoing load on a saturated uplink
+//! - [`bitrate`] - Opus bitrate adapted to the loss receivers report
 
+pub mod bitrate;
 pub mod combinator;
 pub mod config;
+pub mod decode_pool;
+pub mod departure;
+pub mod encoder_complexity;
+pub mod frame_clock;
+pub mod frame_tuning;
+pub mod mic_check;
 pub mod network_stream;
 pub mod ntp;
 pub mod packet_dispatcher;
 pub mod party;
+pub mod presence;
 pub mod realtime_stream;
+pub mod saved_settings;
 pub mod share_music;
+pub mod snapshot;
 pub mod tagged_packet;
+pub mod uplink;
 
 mod tests;
 
-pub use config::PartyConfig;
+pub use bitrate::BitrateConfig;
+pub use combinator::{TapId, Taps, UnderrunPolicy};
+pub use config::{MicEffect, PartyConfig};
+pub use frame_clock::TimestampSource;
+pub use mic_check::{MicCheckConfig, MicCheckResult};
 
 pub use ntp::NtpDebugInfo;
 pub use party::Party;
+pub use presence::PresenceConfig;
 pub use realtime_stream::StreamSnapshot;
 pub use share_music::{
-    PlaylistEntry, PlaylistOp, PlaylistState, SharedPlaylist, SyncedStreamId, SyncedStreamState,
+    AutoBalanceSettings, DuckingSettings, LoopRegion, PlaylistEntry, PlaylistOp, PlaylistState,
+    SavedPlaylist, SharedPlaylist, SyncedStreamId, SyncedStreamState,
 };
+pub use snapshot::PartySnapshot;
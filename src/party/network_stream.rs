@@ -11,22 +11,96 @@
 //! 2. Implement `NetworkStream` on the new stream struct.
 //! 3. In `Party::run`: construct the stream, call `registry.register(arc)`.
 //! 4. Put any runtime startup in [`NetworkStream::start`].
+//!
+//! # Stats refresh
+//!
+//! Streams copy their statistics into [`PartyViewState`] on a timer started
+//! with [`spawn_stats_task`], every [`NetworkStreamContext::stats_interval`].
+//! The rate comes from `PartyConfig::stats_refresh_hz`, and the UI polls the
+//! view state at the same interval.
+//!
+//! # Identity
+//!
+//! Streams key their state by the sender's address. Packets that name
+//! their instance are handed on with the address that instance was first
+//! heard from, so a participant whose IP changes keeps its buffers and
+//! settings instead of showing up as a new one. An address belongs to
+//! whoever sends from it, though: once another device is given the old IP,
+//! the instance that moved is re-keyed to its new address and the streams
+//! [forget](NetworkStream::forget) what they held for the old one.
+//! Instances not heard from for `HOST_TIMEOUT` are dropped.
 
 use std::collections::HashMap;
+use std::future::Future;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::ops::RangeInclusive;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use tracing::warn;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
 
 use crate::audio::AudioSample;
 use crate::io::NetworkSender;
-use crate::party::tagged_packet::{PacketTag, TaggedPacket};
+use crate::party::realtime_stream::HOST_TIMEOUT;
+use crate::party::tagged_packet::{InstanceId, PacketTag, TaggedPacket, UNKNOWN_INSTANCE};
 use crate::state::PartyViewState;
 
 #[derive(Clone)]
 pub struct NetworkStreamContext {
     pub view_state: Arc<PartyViewState>,
     pub sender: NetworkSender,
+    /// How often streams refresh their stats in the view state.
+    pub stats_interval: Duration,
+}
+
+/// Stats refresh rates accepted from the config, in Hz.
+pub const STATS_REFRESH_HZ: RangeInclusive<u32> = 1..=60;
+/// Stats refresh rate used unless configured otherwise, in Hz.
+pub const DEFAULT_STATS_REFRESH_HZ: u32 = 10;
+
+/// Interval between stats refreshes at `hz` per second. Rates outside
+/// [`STATS_REFRESH_HZ`] are clamped to it with a warning.
+pub fn stats_interval(hz: u32) -> Duration {
+    let clamped = hz.clamp(*STATS_REFRESH_HZ.start(), *STATS_REFRESH_HZ.end());
+    if clamped != hz {
+        warn!("stats refresh rate {hz} Hz out of range, using {clamped} Hz");
+    }
+    Duration::from_secs(1) / clamped
+}
+
+/// Wait handed out by the timer of [`run_stats_task`].
+type SleepFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Runs `sync` right away and then every `interval` until the task is
+/// aborted. Deadlines advance by exactly `interval`, so a slow `sync`
+/// doesn't shift the ones after it.
+pub fn spawn_stats_task<F>(interval: Duration, sync: F) -> JoinHandle<()>
+where
+    F: FnMut() + Send + 'static,
+{
+    tokio::spawn(run_stats_task(
+        interval,
+        |deadline| -> SleepFuture { Box::pin(tokio::time::sleep_until(deadline.into())) },
+        sync,
+    ))
+}
+
+/// Body of [`spawn_stats_task`], waiting on `sleep_until` so tests can
+/// drive it without a real timer.
+async fn run_stats_task<S, F>(interval: Duration, mut sleep_until: S, mut sync: F)
+where
+    S: FnMut(Instant) -> SleepFuture,
+    F: FnMut(),
+{
+    let mut deadline = Instant::now();
+    loop {
+        sync();
+        // After a stall, skip the missed refreshes instead of bursting.
+        deadline = (deadline + interval).max(Instant::now());
+        sleep_until(deadline).await;
+    }
 }
 
 /// A self-contained handler for one or more [`PacketTag`] values.
@@ -42,12 +116,26 @@ pub trait NetworkStream<S: AudioSample, const C: usize, const SR: u32>: Send + S
 
     /// Start stream-owned background tasks inside the network runtime.
     fn start(self: Arc<Self>, _ctx: NetworkStreamContext) {}
+
+    /// Drop everything held for `source`, whose address now belongs to a
+    /// different participant.
+    fn forget(&self, _source: SocketAddr) {}
+}
+
+/// Where a tagged instance is heard from.
+struct Alias {
+    /// Address streams know the instance by.
+    key: SocketAddr,
+    /// Address it last sent from.
+    latest: SocketAddr,
+    last_seen: Instant,
 }
 
 /// Routes incoming [`TaggedPacket`]s to the correct [`NetworkStream`].
 pub struct StreamRegistry<S: AudioSample, const C: usize, const SR: u32> {
     streams: Vec<Arc<dyn NetworkStream<S, C, SR>>>,
     by_tag: HashMap<PacketTag, Arc<dyn NetworkStream<S, C, SR>>>,
+    instances: Mutex<HashMap<InstanceId, Alias>>,
 }
 
 impl<S: AudioSample, const C: usize, const SR: u32> StreamRegistry<S, C, SR> {
@@ -55,6 +143,7 @@ impl<S: AudioSample, const C: usize, const SR: u32> StreamRegistry<S, C, SR> {
         Self {
             streams: Vec::new(),
             by_tag: HashMap::new(),
+            instances: Mutex::new(HashMap::new()),
         }
     }
 
@@ -85,6 +174,7 @@ impl<S: AudioSample, const C: usize, const SR: u32> StreamRegistry<S, C, SR> {
         let envelope = rkyv::from_bytes::<TaggedPacket, rkyv::rancor::Error>(data)
             .map_err(|e| anyhow::anyhow!("envelope deserialize: {:?}", e))?;
 
+        let source = self.identify(envelope.instance, source, Instant::now());
         match self.by_tag.get(&envelope.tag) {
             Some(stream) => stream.handle(source, envelope.tag, &envelope.payload),
             None => {
@@ -93,4 +183,224 @@ impl<S: AudioSample, const C: usize, const SR: u32> StreamRegistry<S, C, SR> {
             }
         }
     }
+
+    /// The address streams know `instance` by: the one it was first heard
+    /// from. Packets without an instance id keep their own address.
+    ///
+    /// `source` is taken back from any other instance still known by it
+    /// but sending from elsewhere, so a device given a departed host's IP
+    /// never lands in that host's buffers.
+    fn identify(&self, instance: InstanceId, source: SocketAddr, now: Instant) -> SocketAddr {
+        let mut instances = self.instances.lock().unwrap();
+        instances.retain(|_, alias| now.saturating_duration_since(alias.last_seen) < HOST_TIMEOUT);
+
+        let mut reused = false;
+        for (other, alias) in instances.iter_mut() {
+            if *other != instance && alias.key == source && alias.latest != source {
+                debug!(
+                    "{source} reused, instance {other:032x} now known as {}",
+                    alias.latest
+                );
+                alias.key = alias.latest;
+                reused = true;
+            }
+        }
+        let known = if instance == UNKNOWN_INSTANCE {
+            source
+        } else {
+            let alias = instances.entry(instance).or_insert(Alias {
+                key: source,
+                latest: source,
+                last_seen: now,
+            });
+            alias.latest = source;
+            alias.last_seen = now;
+            if alias.key != source {
+                debug!(
+                    "Instance {instance:032x} at {source}, known as {}",
+                    alias.key
+                );
+            }
+            alias.key
+        };
+        drop(instances);
+
+        if reused {
+            for stream in &self.streams {
+                stream.forget(source);
+            }
+        }
+        known
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn stats_task_runs_at_the_configured_interval() {
+        let interval = stats_interval(4);
+        assert_eq!(interval, Duration::from_millis(250));
+        assert_eq!(stats_interval(0), Duration::from_secs(1));
+        assert_eq!(stats_interval(1000), Duration::from_secs(1) / 60);
+
+        // The mocked timer records each deadline and returns at once, then
+        // stalls for good after three waits.
+        let deadlines = Arc::new(Mutex::new(Vec::new()));
+        let syncs = Arc::new(AtomicUsize::new(0));
+        let recorded = deadlines.clone();
+        let sleep_until = move |deadline: Instant| -> SleepFuture {
+            let mut recorded = recorded.lock().unwrap();
+            recorded.push(deadline);
+            if recorded.len() < 3 {
+                Box::pin(std::future::ready(()))
+            } else {
+                Box::pin(std::future::pending())
+            }
+        };
+        let count = syncs.clone();
+        let start = Instant::now();
+        let task = tokio::spawn(run_stats_task(interval, sleep_until, move || {
+            count.fetch_add(1, Ordering::Relaxed);
+        }));
+        while deadlines.lock().unwrap().len() < 3 {
+            tokio::task::yield_now().await;
+        }
+        task.abort();
+
+        assert_eq!(syncs.load(Ordering::Relaxed), 3, "one sync per wait");
+        let deadlines = deadlines.lock().unwrap();
+        assert!(deadlines[0] >= start + interval);
+        assert!(deadlines[0] < start + interval * 2);
+        for pair in deadlines.windows(2) {
+            assert_eq!(pair[1] - pair[0], interval);
+        }
+    }
+
+    #[test]
+    fn frames_from_a_new_address_reach_the_same_participant() {
+        use crate::audio::OpusEncoder;
+        use crate::audio::frame::AudioBuffer;
+        use crate::party::realtime_stream::{
+            RealtimeAudioStream, RealtimeFramePacker, RealtimeStreamId,
+        };
+        use crate::party::tagged_packet::new_instance_id;
+        use crate::pipeline::Node;
+
+        let realtime = Arc::new(RealtimeAudioStream::<f32, 2, 48000>::new());
+        let registry = StreamRegistry::from_streams(vec![
+            realtime.clone() as Arc<dyn NetworkStream<f32, 2, 48000>>
+        ]);
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let packer = RealtimeFramePacker::new(RealtimeStreamId::Mic);
+        let send = |instance: InstanceId, from: &str| {
+            let audio = AudioBuffer::new(vec![0.1; 1920]).unwrap();
+            let mut packet = packer.process(encoder.process(audio).unwrap()).unwrap();
+            packet.instance = instance;
+            let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&packet).unwrap();
+            registry.dispatch(from.parse().unwrap(), &bytes).unwrap();
+        };
+        let sources = || {
+            let mut sources: Vec<SocketAddr> = realtime
+                .stream_snapshots()
+                .iter()
+                .map(|stream| stream.source_addr)
+                .collect();
+            sources.sort();
+            sources
+        };
+        let first: SocketAddr = "10.0.0.1:7667".parse().unwrap();
+
+        // The IP changes mid-stream: the frames keep going to the buffers
+        // the first address created.
+        let instance = new_instance_id();
+        send(instance, "10.0.0.1:7667");
+        send(instance, "10.0.0.99:7667");
+        send(instance, "10.0.0.99:7667");
+        assert_eq!(sources(), [first]);
+
+        // Another instance, or one without an id, is its own participant.
+        send(new_instance_id(), "10.0.0.2:7667");
+        send(UNKNOWN_INSTANCE, "10.0.0.3:7667");
+        assert_eq!(
+            sources(),
+            [
+                first,
+                "10.0.0.2:7667".parse().unwrap(),
+                "10.0.0.3:7667".parse().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn new_device_on_an_old_address_is_its_own_participant() {
+        use crate::audio::OpusEncoder;
+        use crate::audio::frame::AudioBuffer;
+        use crate::party::realtime_stream::{
+            RealtimeAudioStream, RealtimeFramePacker, RealtimeStreamId,
+        };
+        use crate::party::tagged_packet::new_instance_id;
+        use crate::pipeline::Node;
+
+        let realtime = Arc::new(RealtimeAudioStream::<f32, 2, 48000>::new());
+        let registry = StreamRegistry::from_streams(vec![
+            realtime.clone() as Arc<dyn NetworkStream<f32, 2, 48000>>
+        ]);
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let packer = RealtimeFramePacker::new(RealtimeStreamId::Mic);
+        let send = |instance: InstanceId, from: SocketAddr| {
+            let audio = AudioBuffer::new(vec![0.1; 1920]).unwrap();
+            let mut packet = packer.process(encoder.process(audio).unwrap()).unwrap();
+            packet.instance = instance;
+            let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&packet).unwrap();
+            registry.dispatch(from, &bytes).unwrap();
+        };
+        let sources = || {
+            let mut sources: Vec<SocketAddr> = realtime
+                .stream_snapshots()
+                .iter()
+                .map(|stream| stream.source_addr)
+                .collect();
+            sources.sort();
+            sources
+        };
+        let old: SocketAddr = "10.0.0.1:7667".parse().unwrap();
+        let new: SocketAddr = "10.0.0.99:7667".parse().unwrap();
+
+        // Host A moves from `old` to `new`, then DHCP hands `old` to B.
+        let (a, b) = (new_instance_id(), new_instance_id());
+        send(a, old);
+        send(a, new);
+        assert_eq!(sources(), [old]);
+        send(b, old);
+
+        // Each is its own participant at its own address from then on.
+        send(a, new);
+        send(b, old);
+        assert_eq!(sources(), [old, new]);
+        assert_eq!(registry.identify(a, new, Instant::now()), new);
+        assert_eq!(registry.identify(b, old, Instant::now()), old);
+    }
+
+    #[test]
+    fn silent_instances_are_forgotten() {
+        let registry = StreamRegistry::<f32, 2, 48000>::new();
+        let instance = 7;
+        let first: SocketAddr = "10.0.0.1:7667".parse().unwrap();
+        let moved: SocketAddr = "10.0.0.99:7667".parse().unwrap();
+        let start = Instant::now();
+
+        assert_eq!(registry.identify(instance, first, start), first);
+        assert_eq!(
+            registry.identify(instance, moved, start + HOST_TIMEOUT / 2),
+            first
+        );
+        // Back after timing out, it is a new participant at its new address.
+        let later = start + HOST_TIMEOUT / 2 + HOST_TIMEOUT;
+        assert_eq!(registry.identify(instance, moved, later), moved);
+        assert_eq!(registry.instances.lock().unwrap().len(), 1);
+    }
 }
@@ -25,6 +25,22 @@
 //! party_now() = local_now() + offset
 //! ```
 //!
+//! # Filtering
+//!
+//! A reply delayed on one leg of its round trip skews its offset by half
+//! the delay, so single exchanges can't be trusted. A sample whose RTT is
+//! more than `RTT_OUTLIER_FACTOR` times the median of the last
+//! `OFFSET_SAMPLE_WINDOW` RTTs is rejected outright. Rejected RTTs count
+//! towards that median too, so it follows a lasting change of the path
+//! instead of rejecting everything after it. The last
+//! `OFFSET_SAMPLE_WINDOW` accepted samples are kept; of those, offsets far
+//! from the median are ignored, and the offset measured with the smallest
+//! RTT wins, as in NTP's clock filter.
+//!
+//! The party clock then slews towards that offset, at most
+//! `MAX_SLEW_MICROS` per update, so synced music doesn't jump. Only an
+//! offset more than `STEP_OFFSET_MICROS` away is stepped to at once.
+//!
 //! # Decentralization
 //!
 //! - First host defines party clock (offset = 0)
@@ -44,7 +60,7 @@ use tracing::{debug, info, warn};
 use std::net::SocketAddr;
 
 use crate::io::NetworkSender;
-use crate::party::network_stream::{NetworkStream, NetworkStreamContext};
+use crate::party::network_stream::{NetworkStream, NetworkStreamContext, spawn_stats_task};
 use crate::party::tagged_packet::{NTP_TAG, PacketTag, TaggedPacket};
 use crate::pipeline::Pushable;
 
@@ -56,6 +72,10 @@ pub struct NtpDebugInfo {
     pub last_rtt_micros: Option<i64>,
     pub best_rtt_micros: Option<i64>,
     pub offset_sample_count: usize,
+    /// Samples used since joining.
+    pub accepted_samples: u64,
+    /// Samples dropped for their RTT since joining.
+    pub rejected_samples: u64,
     pub local_time_micros: u64,
     pub party_time_micros: u64,
     pub party_time_formatted: String,
@@ -72,11 +92,15 @@ const FIRST_HOST_TIMEOUT_MS: u64 = 1500;
 const OFFSET_SAMPLE_WINDOW: usize = 16;
 const MAX_SAMPLE_RTT_MICROS: i64 = 250_000;
 const MIN_OUTLIER_THRESHOLD_MICROS: i64 = 5_000;
-const RTT_WEIGHT_FLOOR_MICROS: f64 = 1_000.0;
-const SAMPLE_RECENCY_DECAY: f64 = 0.85;
-const LARGE_OFFSET_MICROS: i64 = 500_000;
-const MEDIUM_OFFSET_MICROS: i64 = 100_000;
-const SMALL_OFFSET_MICROS: i64 = 20_000;
+/// Samples with an RTT this many times the median are rejected.
+const RTT_OUTLIER_FACTOR: i64 = 3;
+/// The median RTT is taken to be at least this, so a LAN's tiny RTTs
+/// don't make ordinary scheduling noise look like outliers.
+const MIN_MEDIAN_RTT_MICROS: i64 = 1_000;
+/// Most the applied offset moves per update while slewing.
+const MAX_SLEW_MICROS: i64 = 2_000;
+/// Offsets further off than this are stepped to instead of slewed.
+const STEP_OFFSET_MICROS: i64 = 50_000;
 
 #[derive(Archive, Serialize, Deserialize, Debug, Clone)]
 #[rkyv(compare(PartialEq))]
@@ -123,9 +147,13 @@ struct NtpServiceInner {
     seen_responses: Vec<SeenResponse>,
     pending_responses: Vec<PendingNtpResponse>,
     offset_samples: VecDeque<OffsetSample>,
+    /// RTTs of the last samples, rejected ones included.
+    rtt_history: VecDeque<i64>,
     last_raw_offset_micros: Option<i64>,
     last_rtt_micros: Option<i64>,
     best_rtt_micros: Option<i64>,
+    accepted_samples: u64,
+    rejected_samples: u64,
     last_sync_request: Option<Instant>,
     first_request_sent_at: Option<Instant>,
 }
@@ -140,9 +168,12 @@ impl Default for NtpServiceInner {
             seen_responses: Vec::new(),
             pending_responses: Vec::new(),
             offset_samples: VecDeque::with_capacity(OFFSET_SAMPLE_WINDOW),
+            rtt_history: VecDeque::with_capacity(OFFSET_SAMPLE_WINDOW),
             last_raw_offset_micros: None,
             last_rtt_micros: None,
             best_rtt_micros: None,
+            accepted_samples: 0,
+            rejected_samples: 0,
             last_sync_request: None,
             first_request_sent_at: None,
         }
@@ -173,12 +204,8 @@ impl NtpService {
 
     pub fn start_view_task(self: &Arc<Self>, ctx: NetworkStreamContext) {
         let service = self.clone();
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(Duration::from_millis(100));
-            loop {
-                interval.tick().await;
-                ctx.view_state.update_ntp(service.debug_info());
-            }
+        spawn_stats_task(ctx.stats_interval, move || {
+            ctx.view_state.update_ntp(service.debug_info())
         });
     }
 
@@ -195,6 +222,11 @@ impl NtpService {
         local.saturating_add_signed(inner.offset)
     }
 
+    /// Current party clock offset from local wall time.
+    pub fn offset_micros(&self) -> i64 {
+        self.inner.lock().unwrap().offset
+    }
+
     pub fn is_synced(&self) -> bool {
         self.inner.lock().unwrap().synced
     }
@@ -223,6 +255,8 @@ impl NtpService {
             last_rtt_micros: inner.last_rtt_micros,
             best_rtt_micros: inner.best_rtt_micros,
             offset_sample_count: inner.offset_samples.len(),
+            accepted_samples: inner.accepted_samples,
+            rejected_samples: inner.rejected_samples,
             local_time_micros: local_time,
             party_time_micros: party_time,
             party_time_formatted,
@@ -315,6 +349,7 @@ impl NtpService {
 
         if rtt < 0 || rtt > MAX_SAMPLE_RTT_MICROS as i128 {
             debug!("Ignoring NTP sample: offset={}µs, RTT={}µs", offset, rtt);
+            inner.rejected_samples += 1;
             return;
         }
 
@@ -329,9 +364,7 @@ impl NtpService {
             inner.offset = filtered_offset;
             inner.synced = true;
         } else {
-            let delta = filtered_offset.saturating_sub(inner.offset);
-            let alpha = Self::correction_alpha(delta);
-            inner.offset = Self::blend_offsets(inner.offset, filtered_offset, alpha);
+            inner.offset = Self::slew(inner.offset, filtered_offset);
         }
 
         info!(
@@ -362,12 +395,11 @@ impl NtpService {
         let payload = rkyv::to_bytes::<rkyv::rancor::Error>(packet)
             .expect("NtpPacket serialization")
             .into_vec();
-        self.sender.push(TaggedPacket {
-            tag: NTP_TAG,
-            payload,
-        });
+        self.sender.push(TaggedPacket::new(NTP_TAG, payload));
     }
 
+    /// Adds a sample to the window and returns the offset the window
+    /// points to, or `None` if the sample is rejected for its RTT.
     fn update_offset_filter(
         inner: &mut NtpServiceInner,
         offset_micros: i64,
@@ -376,6 +408,26 @@ impl NtpService {
         inner.last_raw_offset_micros = Some(offset_micros);
         inner.last_rtt_micros = Some(rtt_micros);
 
+        let median_rtt = (inner.rtt_history.len() >= 3).then(|| {
+            let mut rtts: Vec<_> = inner.rtt_history.iter().copied().collect();
+            Self::median(&mut rtts).max(MIN_MEDIAN_RTT_MICROS)
+        });
+        inner.rtt_history.push_back(rtt_micros);
+        while inner.rtt_history.len() > OFFSET_SAMPLE_WINDOW {
+            inner.rtt_history.pop_front();
+        }
+        if let Some(median_rtt) = median_rtt
+            && rtt_micros > median_rtt.saturating_mul(RTT_OUTLIER_FACTOR)
+        {
+            debug!(
+                "Rejecting NTP sample: offset={}µs, RTT={}µs, median RTT={}µs",
+                offset_micros, rtt_micros, median_rtt
+            );
+            inner.rejected_samples += 1;
+            return None;
+        }
+        inner.accepted_samples += 1;
+
         inner.offset_samples.push_back(OffsetSample {
             offset_micros,
             rtt_micros,
@@ -391,50 +443,26 @@ impl NtpService {
             .collect();
         let median = Self::median(&mut offsets);
 
-        let filtered_samples: Vec<(usize, &OffsetSample)> = if inner.offset_samples.len() >= 3 {
+        let threshold = if inner.offset_samples.len() >= 3 {
             let mut deviations: Vec<_> = inner
                 .offset_samples
                 .iter()
                 .map(|sample| sample.offset_micros.saturating_sub(median).abs())
                 .collect();
             let mad = Self::median(&mut deviations);
-            let threshold = MIN_OUTLIER_THRESHOLD_MICROS.max(mad.saturating_mul(3));
-
-            inner
-                .offset_samples
-                .iter()
-                .enumerate()
-                .filter(|(_, sample)| {
-                    sample.offset_micros.saturating_sub(median).abs() <= threshold
-                })
-                .collect()
+            MIN_OUTLIER_THRESHOLD_MICROS.max(mad.saturating_mul(3))
         } else {
-            inner.offset_samples.iter().enumerate().collect()
+            i64::MAX
         };
 
-        let best = filtered_samples
+        let best = inner
+            .offset_samples
             .iter()
-            .min_by_key(|(_, sample)| sample.rtt_micros)?;
-        inner.best_rtt_micros = Some(best.1.rtt_micros);
-
-        let newest_index = inner.offset_samples.len().saturating_sub(1);
-        let mut weighted_sum = 0.0;
-        let mut weight_sum = 0.0;
-        for (index, sample) in filtered_samples {
-            let sample_age = newest_index.saturating_sub(index) as i32;
-            let recency_weight = SAMPLE_RECENCY_DECAY.powi(sample_age);
-            let rtt = (sample.rtt_micros as f64).max(RTT_WEIGHT_FLOOR_MICROS);
-            let rtt_weight = 1.0 / (rtt * rtt);
-            let weight = recency_weight * rtt_weight;
-            weighted_sum += sample.offset_micros as f64 * weight;
-            weight_sum += weight;
-        }
-
-        if weight_sum == 0.0 {
-            return None;
-        }
-
-        Some((weighted_sum / weight_sum).round() as i64)
+            .filter(|sample| sample.offset_micros.saturating_sub(median).abs() <= threshold)
+            .min_by_key(|sample| sample.rtt_micros)?;
+        let best_offset = best.offset_micros;
+        inner.best_rtt_micros = Some(best.rtt_micros);
+        Some(best_offset)
     }
 
     fn median(values: &mut [i64]) -> i64 {
@@ -442,24 +470,16 @@ impl NtpService {
         values[values.len() / 2]
     }
 
-    fn correction_alpha(delta_micros: i64) -> f64 {
-        let abs_delta = delta_micros.abs();
-        if abs_delta >= LARGE_OFFSET_MICROS {
-            0.75
-        } else if abs_delta >= MEDIUM_OFFSET_MICROS {
-            0.5
-        } else if abs_delta >= SMALL_OFFSET_MICROS {
-            0.25
+    /// The offset one update moves `current` to on the way to `target`.
+    fn slew(current: i64, target: i64) -> i64 {
+        let delta = target.saturating_sub(current);
+        if delta.abs() > STEP_OFFSET_MICROS {
+            target
         } else {
-            0.1
+            current + delta.clamp(-MAX_SLEW_MICROS, MAX_SLEW_MICROS)
         }
     }
 
-    fn blend_offsets(current: i64, target: i64, alpha: f64) -> i64 {
-        let delta = target.saturating_sub(current) as f64;
-        current.saturating_add((delta * alpha).round() as i64)
-    }
-
     async fn run(&self) {
         info!("NTP service task started");
 
@@ -612,4 +632,76 @@ mod tests {
 
         assert!(service.is_synced());
     }
+
+    #[test]
+    fn test_offset_follows_clean_samples_despite_outliers() {
+        let mut inner = NtpServiceInner::default();
+        // (offset, RTT): a clean burst around 10 ms, the quickest exchange
+        // being the most exact, plus replies delayed on one leg.
+        let clean = [
+            (10_300, 2_400),
+            (9_800, 2_200),
+            (10_200, 2_600),
+            (10_020, 1_500),
+            (9_900, 2_300),
+        ];
+        let delayed = [(30_000, 40_000), (-8_000, 36_000), (25_000, 20_000)];
+
+        let mut filtered = None;
+        for (n, &(offset, rtt)) in clean.iter().enumerate() {
+            filtered = NtpService::update_offset_filter(&mut inner, offset, rtt);
+            if n >= 3 {
+                let (offset, rtt) = delayed[n - 3];
+                assert_eq!(
+                    NtpService::update_offset_filter(&mut inner, offset, rtt),
+                    None,
+                    "RTT {rtt}µs is rejected"
+                );
+            }
+        }
+        let (offset, rtt) = delayed[2];
+        assert_eq!(
+            NtpService::update_offset_filter(&mut inner, offset, rtt),
+            None
+        );
+
+        assert_eq!(filtered, Some(10_020), "offset of the quickest exchange");
+        assert_eq!(inner.best_rtt_micros, Some(1_500));
+        assert_eq!(inner.accepted_samples, clean.len() as u64);
+        assert_eq!(inner.rejected_samples, delayed.len() as u64);
+        assert_eq!(inner.offset_samples.len(), clean.len());
+    }
+
+    #[test]
+    fn test_offset_tracks_a_lasting_rtt_rise() {
+        let mut inner = NtpServiceInner::default();
+        for _ in 0..10 {
+            NtpService::update_offset_filter(&mut inner, 10_000, 2_000);
+        }
+
+        // The path gets ten times slower for good, and the clocks drift.
+        let results: Vec<_> = (0..40)
+            .map(|_| NtpService::update_offset_filter(&mut inner, 12_000, 20_000))
+            .collect();
+        assert_eq!(results[0], None, "a single slow exchange is rejected");
+        let first_accepted = results.iter().position(Option::is_some).unwrap();
+        assert!(
+            first_accepted <= OFFSET_SAMPLE_WINDOW / 2,
+            "the median RTT follows the new level, accepted from sample {first_accepted}"
+        );
+        assert_eq!(results.last(), Some(&Some(12_000)));
+        assert_eq!(inner.best_rtt_micros, Some(20_000));
+    }
+
+    #[test]
+    fn test_offset_slews_unless_far_off() {
+        assert_eq!(NtpService::slew(10_000, 10_500), 10_500);
+        assert_eq!(NtpService::slew(10_000, 20_000), 10_000 + MAX_SLEW_MICROS);
+        assert_eq!(NtpService::slew(10_000, 5_000), 10_000 - MAX_SLEW_MICROS);
+        assert_eq!(
+            NtpService::slew(10_000, 10_000 + STEP_OFFSET_MICROS + 1),
+            10_000 + STEP_OFFSET_MICROS + 1,
+            "grossly wrong clocks are stepped"
+        );
+    }
 }
@@ -2,42 +2,103 @@
 //!
 //! [`PacketDispatcher`] runs a background task that:
 //! 1. Receives UDP datagrams from the multicast socket.
-//! 2. Ignores packets sent by this process (self-echo).
-//! 3. Passes each datagram to [`StreamRegistry::dispatch`], which
+//! 2. Ignores packets from our own IPs (self-echo), unless disabled to run
+//!    several instances on one machine.
+//! 3. Decrypts it when the party has a passphrase, silently dropping
+//!    anything that doesn't authenticate.
+//! 4. Passes each datagram to [`StreamRegistry::dispatch`], which
 //!    deserializes the [`TaggedPacket`] envelope and routes the payload
 //!    to the matching [`NetworkStream`].
 
-use std::net::{IpAddr, UdpSocket};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
 use std::sync::Arc;
 
 use tokio::task::JoinHandle;
 use tracing::{error, info};
 
 use crate::audio::AudioSample;
+use crate::io::PacketCipher;
 use crate::party::network_stream::StreamRegistry;
 use crate::state::{AppState, ConnectionStatus};
 
+/// Decides whether a datagram came from this host and should be skipped,
+/// and decrypts the rest.
+///
+/// Every instance sends from the shared multicast port, so our own echo and
+/// other instances on this machine can only be told apart by IP. With
+/// `ignore_self` off, both are processed.
+struct SelfFilter {
+    local_ips: Vec<IpAddr>,
+    ignore_self: bool,
+    cipher: Option<Arc<PacketCipher>>,
+}
+
+impl SelfFilter {
+    fn accepts(&self, source: SocketAddr) -> bool {
+        !(self.ignore_self && self.local_ips.contains(&source.ip()))
+    }
+
+    /// Dispatches `data` unless it is our own echo or doesn't decrypt.
+    fn handle<S: AudioSample, const C: usize, const SR: u32>(
+        &self,
+        registry: &StreamRegistry<S, C, SR>,
+        source: SocketAddr,
+        data: &[u8],
+    ) {
+        if !self.accepts(source) {
+            return;
+        }
+        let decrypted;
+        let data = match &self.cipher {
+            Some(cipher) => match cipher.open(data) {
+                Some(plaintext) => {
+                    decrypted = plaintext;
+                    &decrypted[..]
+                }
+                // Not from our party, or tampered with.
+                None => return,
+            },
+            None => data,
+        };
+        if let Err(e) = registry.dispatch(source, data) {
+            error!("Packet handling error: {:?}", e);
+        }
+    }
+}
+
 pub struct PacketDispatcher;
 
 impl PacketDispatcher {
     pub fn start<S: AudioSample, const C: usize, const SR: u32>(
         socket: UdpSocket,
         local_ips: Vec<IpAddr>,
+        ignore_self: bool,
+        cipher: Option<Arc<PacketCipher>>,
         state: Arc<AppState>,
         registry: Arc<StreamRegistry<S, C, SR>>,
     ) -> JoinHandle<()> {
+        let filter = SelfFilter {
+            local_ips,
+            ignore_self,
+            cipher,
+        };
         tokio::spawn(async move {
-            Self::run(socket, local_ips, state, registry).await;
+            Self::run(socket, filter, state, registry).await;
         })
     }
 
     async fn run<S: AudioSample, const C: usize, const SR: u32>(
         socket: UdpSocket,
-        local_ips: Vec<IpAddr>,
+        filter: SelfFilter,
         state: Arc<AppState>,
         registry: Arc<StreamRegistry<S, C, SR>>,
     ) {
-        info!("Packet dispatcher started, local IPs: {:?}", local_ips);
+        info!(
+            "Packet dispatcher started, local IPs: {:?}, ignore self: {}, encrypted: {}",
+            filter.local_ips,
+            filter.ignore_self,
+            filter.cipher.is_some()
+        );
 
         let socket = Arc::new(
             tokio::net::UdpSocket::from_std(socket).expect("Failed to convert to tokio UdpSocket"),
@@ -48,16 +109,95 @@ impl PacketDispatcher {
         let mut buf = [0u8; 65536];
         loop {
             match socket.recv_from(&mut buf).await {
-                Ok((size, source_addr)) => {
-                    if local_ips.contains(&source_addr.ip()) {
-                        continue;
-                    }
-                    if let Err(e) = registry.dispatch(source_addr, &buf[..size]) {
-                        error!("Packet handling error: {:?}", e);
-                    }
-                }
+                Ok((size, source_addr)) => filter.handle(&registry, source_addr, &buf[..size]),
                 Err(e) => error!("Failed to receive UDP packet: {:?}", e),
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::io::Passphrase;
+    use crate::party::network_stream::NetworkStream;
+    use crate::party::tagged_packet::{PacketTag, TaggedPacket};
+
+    const TEST_TAG: PacketTag = 999;
+
+    #[derive(Default)]
+    struct CountingStream {
+        handled: AtomicUsize,
+    }
+
+    impl NetworkStream<f32, 2, 48000> for CountingStream {
+        fn tags(&self) -> &'static [PacketTag] {
+            &[TEST_TAG]
+        }
+
+        fn handle(
+            &self,
+            _source: SocketAddr,
+            _tag: PacketTag,
+            _bytes: &[u8],
+        ) -> anyhow::Result<()> {
+            self.handled.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    fn deliver(ignore_self: bool, source: &str) -> usize {
+        deliver_with(ignore_self, source, None, None)
+    }
+
+    /// Sends a packet sealed with `sender_key` (in the clear if `None`) to a
+    /// dispatcher decrypting with `receiver_key`.
+    fn deliver_with(
+        ignore_self: bool,
+        source: &str,
+        sender_key: Option<&str>,
+        receiver_key: Option<&str>,
+    ) -> usize {
+        let cipher = |key: &str| Arc::new(PacketCipher::new(&Passphrase::new(key).unwrap()));
+        let stream = Arc::new(CountingStream::default());
+        let registry = StreamRegistry::from_streams(vec![
+            stream.clone() as Arc<dyn NetworkStream<f32, 2, 48000>>
+        ]);
+        let filter = SelfFilter {
+            local_ips: vec!["192.168.1.10".parse().unwrap()],
+            ignore_self,
+            cipher: receiver_key.map(cipher),
+        };
+        let packet =
+            rkyv::to_bytes::<rkyv::rancor::Error>(&TaggedPacket::new(TEST_TAG, Vec::new()))
+                .unwrap();
+        let packet = match sender_key {
+            Some(key) => cipher(key).seal(&packet),
+            None => packet.into_vec(),
+        };
+
+        filter.handle(&registry, source.parse().unwrap(), &packet);
+        stream.handled.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn self_filter_drops_local_packets_when_enabled() {
+        assert_eq!(deliver(true, "192.168.1.10:40000"), 0);
+        assert_eq!(deliver(true, "192.168.1.11:40000"), 1);
+    }
+
+    #[test]
+    fn self_filter_off_processes_local_packets() {
+        assert_eq!(deliver(false, "192.168.1.10:40000"), 1);
+    }
+
+    #[test]
+    fn encrypted_packets_need_the_party_passphrase() {
+        let remote = "192.168.1.11:40000";
+        assert_eq!(deliver_with(true, remote, Some("party"), Some("party")), 1);
+        assert_eq!(deliver_with(true, remote, Some("other"), Some("party")), 0);
+        assert_eq!(deliver_with(true, remote, None, Some("party")), 0);
+    }
+}
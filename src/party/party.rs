@@ -5,33 +5,58 @@
 
 use std::net::{IpAddr, UdpSocket};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 
 use anyhow::{Context, Result};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
-use crate::audio::effects::Switch;
-use crate::audio::{AudioBatcher, AudioSample, Gain, LevelMeter, OpusEncoder, SimpleBuffer};
+use crate::audio::effects::{
+    Agc, Bypass, DEFAULT_HIGH_PASS_HZ, DEFAULT_LIMITER_CEILING, DEFAULT_MUTE_FADE, DeEsser,
+    EffectChain, FadeRamp, HighPass, PeakLimiter, Reverb, Switch,
+};
+use crate::audio::file::HttpSource;
+use crate::audio::frame::AudioBuffer;
+use crate::audio::{
+    AudioBatcher, AudioSample, LevelMeter, LiveGain, MonitorBuffer, OpusEncoder, OpusEncoderConfig,
+    OpusFrameDuration,
+};
 use crate::io::{
-    AudioInput, AudioOutput, LoopbackInput, MulticastLock, NetworkSender, SendTarget,
+    AudioInput, AudioOutput, CipherCache, LoopbackInput, MulticastLock, NetworkSender, SendTarget,
     create_multicast_socket,
 };
-use crate::pipeline::Pushable;
+use crate::pipeline::{Pullable, Pushable};
 use crate::state::{AppState, MusicStreamProgress};
 use crate::{pull_chain, push_chain};
 
-use super::combinator::{Mixer, Tee};
-use super::config::PartyConfig;
-use super::network_stream::{NetworkStream, NetworkStreamContext, StreamRegistry};
+use super::bitrate::{AdaptedStream, BitrateController};
+use super::combinator::{Decoupled, Mixer, SilenceWatchdog, Tap, Taps, Tee, UnderrunFill};
+use super::config::{MicEffect, PartyConfig};
+use super::departure::DepartureService;
+use super::encoder_complexity::ComplexityController;
+use super::frame_clock::FrameClock;
+use super::frame_tuning;
+use super::mic_check::{MicCheck, MicTap};
+use super::network_stream::{NetworkStream, NetworkStreamContext, StreamRegistry, stats_interval};
 use super::ntp::NtpService;
 use super::packet_dispatcher::PacketDispatcher;
-use super::realtime_stream::{RealtimeAudioStream, RealtimeFramePacker, RealtimeStreamId};
-use super::share_music::{ShareMusicService, SharedPlaylist, SyncedStreamId};
+use super::presence::PresenceService;
+use super::realtime_stream::{
+    FecController, MonitorTarget, RealtimeAudioStream, RealtimeFramePacker, RealtimeStreamId,
+    StreamMonitor, StreamTaps,
+};
+use super::share_music::{
+    AutoBalance, Ducker, LoopRegion, ShareMusicService, SharedPlaylist, SyncedStreamId,
+};
+use super::snapshot::PartySnapshot;
+use super::tagged_packet::{InstanceId, UNKNOWN_INSTANCE, new_instance_id};
+use super::uplink::{UplinkController, UplinkLevers};
 
 struct NetworkStreamBundle<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     ntp_service: Arc<NtpService>,
     share_music: Arc<ShareMusicService<Sample, CHANNELS, SAMPLE_RATE>>,
     playlist: Arc<SharedPlaylist>,
+    departure: Arc<DepartureService<Sample, CHANNELS, SAMPLE_RATE>>,
     registry: Arc<StreamRegistry<Sample, CHANNELS, SAMPLE_RATE>>,
 }
 
@@ -42,33 +67,126 @@ pub struct Party<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     share_music: Option<Arc<ShareMusicService<Sample, CHANNELS, SAMPLE_RATE>>>,
     playlist: Option<Arc<SharedPlaylist>>,
     ntp_service: Option<Arc<NtpService>>,
+    /// Says goodbye to the party on stop.
+    departure: Option<Arc<DepartureService<Sample, CHANNELS, SAMPLE_RATE>>>,
     mic_input: Option<Arc<AudioInput<Sample, CHANNELS, SAMPLE_RATE>>>,
+    mic_encoder: Option<Arc<OpusEncoder<Sample, CHANNELS, SAMPLE_RATE>>>,
+    mic_packer: Option<Arc<RealtimeFramePacker>>,
+    /// Observers of the raw mic capture, kept across restarts.
+    mic_taps: Arc<Taps<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+    /// Lets the mic capture on past [`Self::mic_taps`]; closed while the
+    /// input only runs for a mic check.
+    mic_open: Arc<AtomicBool>,
+    /// Speaker fade, ramped down before the output is torn down on restart.
+    output_ramp: Option<Arc<FadeRamp<Sample, CHANNELS, SAMPLE_RATE>>>,
+    /// Observers of what the speaker plays, kept across restarts.
+    output_taps: Arc<Taps<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+    /// Observers of each remote stream's decoded audio, kept across restarts.
+    stream_taps: StreamTaps<Sample, CHANNELS, SAMPLE_RATE>,
     _audio_streams: Vec<cpal::Stream>,
     dispatcher_abort: Option<tokio::task::AbortHandle>,
     network_thread: Option<thread::JoinHandle<()>>,
+    /// Named in every packet sent, kept across restarts.
+    instance_id: InstanceId,
     #[allow(dead_code)]
     multicast_lock: Option<MulticastLock>,
+    /// Packet key, kept across restarts since deriving it is slow.
+    cipher_cache: CipherCache,
 }
 
 impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u32>
     Party<Sample, CHANNELS, SAMPLE_RATE>
 {
     pub fn new(state: Arc<AppState>, config: PartyConfig) -> Self {
+        let instance_id = if config.stable_instance_id {
+            new_instance_id()
+        } else {
+            UNKNOWN_INSTANCE
+        };
+        let stream_taps = StreamTaps::default();
         Self {
+            realtime_stream: Self::new_realtime_stream(&config, &state, &stream_taps),
             state,
             config,
-            realtime_stream: Arc::new(RealtimeAudioStream::new()),
             share_music: None,
             playlist: None,
             ntp_service: None,
+            departure: None,
             mic_input: None,
+            mic_encoder: None,
+            mic_packer: None,
+            mic_taps: Arc::new(Taps::new()),
+            mic_open: Arc::new(AtomicBool::new(false)),
+            output_ramp: None,
+            output_taps: Arc::new(Taps::new()),
+            stream_taps,
             _audio_streams: Vec::new(),
             dispatcher_abort: None,
             network_thread: None,
+            instance_id,
             multicast_lock: None,
+            cipher_cache: CipherCache::default(),
         }
     }
 
+    /// Runs the reorderable mic `effects`, then the safety limiter as a
+    /// fixed last stage before `next`, so neither an effect nor the gain
+    /// slider can send a peak past the ceiling to the encoder.
+    fn limited_mic_effects(
+        effects: EffectChain<MicEffect, AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>,
+        config: &PartyConfig,
+        limiter_bypass: Arc<AtomicBool>,
+        next: Arc<dyn Pushable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+    ) -> Arc<dyn Pushable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>> {
+        let ceiling = if config.disable_input_limiter {
+            f64::INFINITY
+        } else {
+            DEFAULT_LIMITER_CEILING
+        };
+        push_chain![
+            effects,
+            Bypass::new(
+                PeakLimiter::<Sample, CHANNELS, SAMPLE_RATE>::new(ceiling),
+                limiter_bypass,
+            ),
+            => next
+        ]
+    }
+
+    fn new_realtime_stream(
+        config: &PartyConfig,
+        state: &AppState,
+        taps: &StreamTaps<Sample, CHANNELS, SAMPLE_RATE>,
+    ) -> Arc<RealtimeAudioStream<Sample, CHANNELS, SAMPLE_RATE>> {
+        Arc::new(
+            RealtimeAudioStream::new()
+                .with_memory_budget(config.jitter_memory_budget)
+                .with_epoch_tolerance(config.jitter_epoch_tolerance)
+                .with_concealment(config.jitter_concealment)
+                .with_dtx_comfort_noise(!config.dtx_silence)
+                .with_decode_workers(config.decode_workers)
+                .with_loss_mute(config.loss_mute)
+                .with_nack(config.realtime_nack)
+                .with_loss_feedback(config.adaptive_bitrate.map(|bitrate| bitrate.interval))
+                .with_host_volumes(state.host_volumes.clone())
+                .with_taps(taps.clone()),
+        )
+    }
+
+    /// Taps on the final mix as it goes to the speaker, for external
+    /// processing or recording; see [`Taps::add`].
+    pub fn output_taps(&self) -> &Arc<Taps<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>> {
+        &self.output_taps
+    }
+
+    /// Taps on one remote stream's decoded audio before it is mixed.
+    pub fn stream_taps(
+        &self,
+        target: MonitorTarget,
+    ) -> Arc<Taps<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>> {
+        self.stream_taps.get(target)
+    }
+
     pub fn mic_input(&self) -> Option<&Arc<AudioInput<Sample, CHANNELS, SAMPLE_RATE>>> {
         self.mic_input.as_ref()
     }
@@ -83,6 +201,20 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
         &self.config
     }
 
+    /// Turns the startup mic check on or off for the rest of the session.
+    pub fn set_mic_check_enabled(&mut self, enabled: bool) {
+        self.config.mic_check.enabled = enabled;
+    }
+
+    /// Retunes the running mic encoder, keeping the stream going.
+    pub fn set_mic_encoder_config(&mut self, config: OpusEncoderConfig) -> Result<()> {
+        if let Some(encoder) = &self.mic_encoder {
+            encoder.set_config(config)?;
+        }
+        self.config.mic_encoder = config;
+        Ok(())
+    }
+
     pub fn pause_music(&self, stream_id: SyncedStreamId) -> Result<()> {
         self.share_music()?.pause(stream_id)
     }
@@ -95,10 +227,43 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
         self.share_music()?.seek(stream_id, position_ms)
     }
 
+    pub fn set_music_loop(
+        &self,
+        stream_id: SyncedStreamId,
+        region: Option<LoopRegion>,
+    ) -> Result<()> {
+        self.share_music()?.set_loop(stream_id, region)
+    }
+
+    pub fn stop_music_stream(&self, stream_id: SyncedStreamId) -> Result<()> {
+        self.share_music()?.stop(stream_id)
+    }
+
     pub fn set_music_vocal_removal(&self, stream_id: SyncedStreamId, enabled: bool) -> Result<()> {
         self.share_music()?.set_vocal_removal(stream_id, enabled)
     }
 
+    pub fn set_music_pitch(&self, stream_id: SyncedStreamId, semitones: i8) -> Result<()> {
+        self.share_music()?.set_pitch(stream_id, semitones)
+    }
+
+    pub fn set_music_tempo(&self, stream_id: SyncedStreamId, percent: u16) -> Result<()> {
+        self.share_music()?.set_tempo(stream_id, percent)
+    }
+
+    /// Captures hosts, stream stats, NTP state and synced streams in one
+    /// serializable structure.
+    pub fn snapshot(&self) -> PartySnapshot {
+        PartySnapshot::assemble(
+            self.realtime_stream.stream_snapshots(),
+            self.ntp_service.as_ref().map(|ntp| ntp.debug_info()),
+            self.share_music
+                .as_ref()
+                .map(|share_music| share_music.receiver().active_streams())
+                .unwrap_or_default(),
+        )
+    }
+
     // -- Playlist delegation --
 
     fn playlist(&self) -> Result<&Arc<SharedPlaylist>> {
@@ -159,29 +324,149 @@ impl<
     const SAMPLE_RATE: u32,
 > Party<Sample, CHANNELS, SAMPLE_RATE>
 {
+    /// Starts capturing the mic. Unless turned off in the config, a mic that
+    /// wasn't running starts from a freshly reset encoder, in a new epoch so
+    /// listeners reset their decoders too.
+    pub fn enable_mic(&self) -> Result<()> {
+        let mic_input = self
+            .mic_input
+            .as_ref()
+            .context("Mic input not initialized")?;
+        let was_open = self.mic_open.swap(true, Ordering::AcqRel);
+        if self.config.reset_encoder_on_restart && !(was_open && mic_input.is_enabled()) {
+            if let Some(encoder) = &self.mic_encoder {
+                encoder.reset();
+            }
+            if let Some(packer) = &self.mic_packer {
+                packer.restart();
+            }
+        }
+        mic_input.enable()
+    }
+
+    /// Starts checking the mic input picks up sound, without sending
+    /// anything. Wait on the check without holding the party, then hand it
+    /// back to [`finish_mic_check`](Self::finish_mic_check).
+    pub fn start_mic_check(&self) -> Result<MicCheck<Sample, CHANNELS, SAMPLE_RATE>> {
+        MicCheck::start(self.mic_tap()?, self.config.mic_check)
+    }
+
+    pub fn finish_mic_check(&self, check: MicCheck<Sample, CHANNELS, SAMPLE_RATE>) {
+        if let Ok(mic) = self.mic_tap() {
+            check.finish(mic);
+        }
+    }
+
+    fn mic_tap(&self) -> Result<MicTap<'_, Sample, CHANNELS, SAMPLE_RATE>> {
+        Ok(MicTap {
+            input: self
+                .mic_input
+                .as_ref()
+                .context("Mic input not initialized")?,
+            taps: &self.mic_taps,
+            open: &self.mic_open,
+        })
+    }
+
     pub fn run(&mut self) -> Result<()> {
         info!("Starting Party pipelines with config {:#?}", self.config);
 
         self.multicast_lock = MulticastLock::acquire();
         self.normalize_send_target_for_config();
 
-        let (socket, multicast_addr, local_ips, send_ip) =
-            create_multicast_socket(self.config.ipv6, self.config.send_interface_index)?;
+        let (socket, multicast_addr, local_ips, send_ip) = create_multicast_socket(
+            self.config.ipv6,
+            &self.config.multicast,
+            self.config.send_interface_index,
+            self.config.multicast_loopback,
+        )?;
 
         let send_socket: UdpSocket = socket
             .try_clone()
             .context("Failed to clone socket for sender")?;
+        let cipher = self.cipher_cache.get(self.config.encryption.as_ref());
         let network_sender =
-            NetworkSender::new(send_socket, multicast_addr, self.state.send_target.clone());
+            NetworkSender::new(send_socket, multicast_addr, self.state.send_target.clone())
+                .with_instance(self.instance_id)
+                .with_cipher(cipher.clone());
 
         let stream_bundle =
             self.build_stream_bundle(network_sender.clone(), local_ips.clone(), send_ip);
 
         let realtime_stream = self.realtime_stream.clone();
         let synced_stream = stream_bundle.share_music.receiver();
+        synced_stream.set_resync_threshold(self.config.music_resync_threshold);
+        synced_stream.set_pre_meta_capacity(self.config.pre_meta_frames);
+        synced_stream.set_end_gap_grace(self.config.synced_end_gap_grace);
+        synced_stream.set_seek_ready_frames(self.config.seek_ready_frames);
+        synced_stream.set_monotonic_party_time(self.config.monotonic_music_clock);
+        synced_stream.set_concurrent_streams(self.config.concurrent_music);
+        synced_stream.set_wait_for_clock_sync(self.config.music_waits_for_clock_sync);
         self.ntp_service = Some(stream_bundle.ntp_service.clone());
         self.share_music = Some(stream_bundle.share_music.clone());
         self.playlist = Some(stream_bundle.playlist.clone());
+        self.departure = Some(stream_bundle.departure.clone());
+
+        let mic_encoder = Arc::new(
+            OpusEncoder::<Sample, CHANNELS, SAMPLE_RATE>::with_config(self.config.mic_encoder)?
+                .with_codec(self.config.realtime_codec),
+        );
+        let codec = self.config.realtime_codec;
+        let frame_cap = codec.max_frame_ms(CHANNELS, SAMPLE_RATE);
+        let batcher = |duration: OpusFrameDuration| {
+            let batcher = AudioBatcher::<Sample, CHANNELS, SAMPLE_RATE>::new(duration.as_millis());
+            match frame_cap {
+                Some(max_ms) if duration.as_millis() > max_ms => {
+                    warn!(
+                        "{} ms {} frames don't fit a packet, sending {} ms frames",
+                        duration.as_millis(),
+                        codec,
+                        max_ms
+                    );
+                    batcher.with_max_ms(max_ms)
+                }
+                Some(max_ms) => batcher.with_max_ms(max_ms),
+                None => batcher,
+            }
+        };
+        let mic_batcher = Arc::new(batcher(self.config.mic_frame_duration));
+        let system_encoder = Arc::new(
+            OpusEncoder::<Sample, CHANNELS, SAMPLE_RATE>::new()?
+                .with_codec(self.config.realtime_codec),
+        );
+        let system_uplink_allowed = Arc::new(AtomicBool::new(true));
+        let uplink = self.config.uplink_adaptation.map(|config| {
+            let realtime_for_uplink = self.realtime_stream.clone();
+            Arc::new(UplinkController::new(
+                config,
+                network_sender.stats(),
+                move || realtime_for_uplink.worst_loss_rate(),
+                UplinkLevers {
+                    mic_encoder: mic_encoder.clone(),
+                    system_encoder: system_encoder.clone(),
+                    system_allowed: system_uplink_allowed.clone(),
+                    music_redundant: stream_bundle.share_music.music_redundancy(),
+                },
+            ))
+        });
+
+        let bitrate = self.config.adaptive_bitrate.map(|config| {
+            Arc::new(BitrateController::new(
+                config,
+                vec![
+                    AdaptedStream {
+                        name: "Mic",
+                        encoder: mic_encoder.clone(),
+                        reports: self.realtime_stream.loss_reports(RealtimeStreamId::Mic),
+                    },
+                    AdaptedStream {
+                        name: "System audio",
+                        encoder: system_encoder.clone(),
+                        reports: self.realtime_stream.loss_reports(RealtimeStreamId::System),
+                    },
+                ],
+            ))
+        });
 
         let (abort_tx, abort_rx) = std::sync::mpsc::sync_channel(1);
 
@@ -190,6 +475,13 @@ impl<
             let view_state = self.state.view_state.clone();
             let registry = stream_bundle.registry.clone();
             let network_sender = network_sender.clone();
+            let ignore_self = self.config.ignore_self;
+            let stats_interval = stats_interval(self.config.stats_refresh_hz);
+            view_state.set_refresh_interval(stats_interval);
+            let frame_tuning = self
+                .config
+                .auto_mic_frame_duration
+                .map(|window| (window, self.realtime_stream.clone(), mic_batcher.clone()));
 
             move || {
                 let rt = tokio::runtime::Builder::new_multi_thread()
@@ -201,9 +493,26 @@ impl<
                     registry.start_all(NetworkStreamContext {
                         view_state,
                         sender: network_sender,
+                        stats_interval,
                     });
+                    if let Some(uplink) = uplink {
+                        uplink.start();
+                    }
+                    if let Some(bitrate) = bitrate {
+                        bitrate.start();
+                    }
+                    if let Some((window, realtime, batcher)) = frame_tuning {
+                        frame_tuning::start(window, realtime, batcher);
+                    }
 
-                    let handle = PacketDispatcher::start(socket, local_ips, state, registry);
+                    let handle = PacketDispatcher::start(
+                        socket,
+                        local_ips,
+                        ignore_self,
+                        cipher,
+                        state,
+                        registry,
+                    );
                     let _ = abort_tx.send(handle.abort_handle());
                     handle.await.ok();
                 });
@@ -212,24 +521,98 @@ impl<
 
         self.dispatcher_abort = Some(abort_rx.recv().expect("network thread failed to start"));
 
-        let loopback_buffer = Arc::new(SimpleBuffer::<Sample, CHANNELS, SAMPLE_RATE>::new());
+        // The local monitor goes straight from the mic chain to the speaker
+        // mixer, past the network and every jitter buffer; its delay depends
+        // only on the callbacks and `monitor_backlog`.
+        let loopback_buffer = Arc::new(MonitorBuffer::<Sample, CHANNELS, SAMPLE_RATE>::new(
+            self.config.monitor_backlog,
+        ));
         let network_sink_arc: Arc<dyn Pushable<_>> = Arc::new(network_sender);
 
+        let frame_clock = Arc::new(FrameClock::new(
+            self.config.timestamp_source,
+            stream_bundle.ntp_service.clone(),
+        ));
+        self.mic_encoder = Some(mic_encoder.clone());
+        let realtime_for_fec = self.realtime_stream.clone();
+        let mic_fec = FecController::new(mic_encoder.clone(), move || {
+            realtime_for_fec.worst_loss_rate()
+        });
+        let mic_complexity =
+            ComplexityController::new(mic_encoder.clone(), self.config.adaptive_mic_complexity);
+        let mic_effects = EffectChain::new(self.state.mic_effect_order.clone())
+            .with_effect(
+                MicEffect::DeEsser,
+                Bypass::new(
+                    DeEsser::<Sample, CHANNELS, SAMPLE_RATE>::new(self.config.de_esser),
+                    self.state.de_esser_bypass.clone(),
+                ),
+            )
+            .with_effect(
+                MicEffect::Reverb,
+                Bypass::new(
+                    Reverb::<Sample, CHANNELS, SAMPLE_RATE>::new(self.config.reverb),
+                    self.state.reverb_bypass.clone(),
+                ),
+            )
+            .with_effect(
+                MicEffect::Gain,
+                LiveGain::<Sample, CHANNELS, SAMPLE_RATE>::new(self.state.mic_volume.clone()),
+            );
+        let mic_high_pass = Bypass::new(
+            HighPass::<Sample, CHANNELS, SAMPLE_RATE>::new(
+                self.config.mic_high_pass_hz.unwrap_or(DEFAULT_HIGH_PASS_HZ),
+            ),
+            Arc::new(AtomicBool::new(self.config.mic_high_pass_hz.is_none())),
+        );
+        let mic_agc = Bypass::new(
+            Agc::<Sample, CHANNELS, SAMPLE_RATE>::new(self.config.mic_agc.unwrap_or_default()),
+            Arc::new(AtomicBool::new(self.config.mic_agc.is_none())),
+        );
+        let mic_packer = Arc::new(
+            RealtimeFramePacker::new(RealtimeStreamId::Mic)
+                .with_history(self.realtime_stream.sent_frames(RealtimeStreamId::Mic))
+                .with_clock(frame_clock.clone())
+                .with_channels(CHANNELS),
+        );
+        self.mic_packer = Some(mic_packer.clone());
+        let mic_send = push_chain![
+            Switch::<Sample, CHANNELS, SAMPLE_RATE>::new(
+                self.state.transmit.flag(RealtimeStreamId::Mic)
+            )
+            .with_fade(DEFAULT_MUTE_FADE),
+            mic_batcher,
+            mic_complexity,
+            mic_fec,
+            mic_packer,
+            => network_sink_arc.clone()
+        ];
+        let mic_send = match self.config.mic_send_queue {
+            Some(capacity) => {
+                Arc::new(Decoupled::new(mic_send, capacity, "mic-send")) as Arc<dyn Pushable<_>>
+            }
+            None => mic_send,
+        };
         let mic_pipeline = push_chain![
+            Tap::new(self.mic_taps.clone()),
+            Switch::<Sample, CHANNELS, SAMPLE_RATE>::new(self.mic_open.clone()),
+            mic_high_pass,
+            mic_agc,
+            // Ahead of the reorderable effects, so the meter shows the
+            // input level wherever the gain stage is moved.
             LevelMeter::<Sample, CHANNELS, SAMPLE_RATE>::new(self.state.mic_audio_level.clone()),
-            Gain::<Sample, CHANNELS, SAMPLE_RATE>::new(self.state.mic_volume.clone()),
-            => Arc::new(Tee::new(
-                push_chain![
-                    AudioBatcher::<Sample, CHANNELS, SAMPLE_RATE>::new(20),
-                    OpusEncoder::<Sample, CHANNELS, SAMPLE_RATE>::new()?,
-                    RealtimeFramePacker::new(RealtimeStreamId::Mic),
-                    => network_sink_arc.clone()
-                ],
-                push_chain![
-                    Switch::<Sample, CHANNELS, SAMPLE_RATE>::new(self.state.loopback_enabled.clone()),
-                    => loopback_buffer.clone()
-                ]
-            ))
+            => Self::limited_mic_effects(
+                mic_effects,
+                &self.config,
+                self.state.limiter_bypass.clone(),
+                Arc::new(Tee::new(
+                    mic_send,
+                    push_chain![
+                        Switch::<Sample, CHANNELS, SAMPLE_RATE>::new(self.state.loopback_enabled.clone()),
+                        => loopback_buffer.clone()
+                    ]
+                )),
+            )
         ];
 
         self.mic_input = Some(Arc::new(AudioInput::new(
@@ -239,10 +622,17 @@ impl<
 
         let system_pipeline = push_chain![
             LevelMeter::<Sample, CHANNELS, SAMPLE_RATE>::new(self.state.system_audio_level.clone()),
-            Switch::<Sample, CHANNELS, SAMPLE_RATE>::new(self.state.system_audio_enabled.clone()),
-            AudioBatcher::<Sample, CHANNELS, SAMPLE_RATE>::new(10),
-            OpusEncoder::<Sample, CHANNELS, SAMPLE_RATE>::new()?,
-            RealtimeFramePacker::new(RealtimeStreamId::System),
+            Switch::<Sample, CHANNELS, SAMPLE_RATE>::new(
+                self.state.transmit.flag(RealtimeStreamId::System)
+            )
+            .with_fade(DEFAULT_MUTE_FADE),
+            Switch::<Sample, CHANNELS, SAMPLE_RATE>::new(system_uplink_allowed),
+            batcher(self.config.system_frame_duration),
+            system_encoder,
+            RealtimeFramePacker::new(RealtimeStreamId::System)
+                .with_history(self.realtime_stream.sent_frames(RealtimeStreamId::System))
+                .with_clock(frame_clock)
+                .with_channels(CHANNELS),
             => network_sink_arc.clone()
         ];
 
@@ -271,8 +661,48 @@ impl<
             loopback_buffer.clone(),
         ]);
 
-        let audio_output = AudioOutput::new(output_mixer);
+        let mut speaker_source: Arc<dyn Pullable<_>> = Arc::new(StreamMonitor::new(
+            self.state.monitor_stream.clone(),
+            realtime_stream.clone(),
+            output_mixer,
+        ));
+        if let Some(threshold) = self.config.silent_output_watchdog {
+            let listen_enabled = self.state.listen_enabled.clone();
+            let monitor_stream = self.state.monitor_stream.clone();
+            let (realtime_active, synced_active) = (realtime_stream.clone(), synced_stream.clone());
+            let (realtime_reset, synced_reset) = (realtime_stream.clone(), synced_stream.clone());
+            speaker_source = Arc::new(SilenceWatchdog::new(
+                speaker_source,
+                threshold,
+                // Muted or monitoring a single stream: silence is expected.
+                move || {
+                    if !listen_enabled.load(Ordering::Relaxed)
+                        || monitor_stream.lock().unwrap().is_some()
+                    {
+                        return 0;
+                    }
+                    realtime_active.audible_stream_count() + synced_active.playing_stream_count()
+                },
+                move || {
+                    realtime_reset.reset_pull_path();
+                    synced_reset.reset_pull_path();
+                },
+            ));
+        }
+        let output_ramp = Arc::new(FadeRamp::<Sample, CHANNELS, SAMPLE_RATE>::silent(
+            self.config.output_switch_ramp,
+        ));
+        let audio_output = AudioOutput::new(pull_chain![
+            Arc::new(UnderrunFill::new(speaker_source, self.config.underrun_policy)) =>,
+            output_ramp.clone(),
+            Tap::new(self.output_taps.clone())
+        ])
+        .with_channel_map(self.config.output_channel_map.clone())
+        .with_sample_format(self.config.output_sample_format)
+        .with_max_pull_frames(self.config.max_output_pull_frames);
         let output_stream = audio_output.start(self.config.output_device_id.as_ref())?;
+        output_ramp.fade_in();
+        self.output_ramp = Some(output_ramp);
 
         let mut streams = vec![output_stream];
         if let Some(sys_stream) = system_stream {
@@ -285,15 +715,63 @@ impl<
         Ok(())
     }
 
+    /// Restarts with `config`. If it doesn't start, e.g. because a device is
+    /// gone or doesn't support the format, the previous config is restored,
+    /// or failing that the system default devices, and the error returned.
+    ///
+    /// The old streams are stopped before the new ones are built, since
+    /// many audio backends can't open the same device twice.
     pub fn restart_with_config(&mut self, config: PartyConfig) -> Result<()> {
         info!("Restarting Party with new config...");
 
+        let previous = self.config.clone();
+        let fallback = previous.on_default_devices();
+        // Restarting isn't leaving: peers only hear a bye if nothing starts.
+        let departure = self.departure.clone();
+        let restarted = start_with_rollback(config, previous, fallback, |config| {
+            self.stop();
+            self.config = config.clone();
+            self.realtime_stream =
+                Self::new_realtime_stream(&self.config, &self.state, &self.stream_taps);
+            self.run()
+        });
+        match restarted {
+            Ok(Restarted { error: None, .. }) => Ok(()),
+            Ok(Restarted {
+                error: Some(e),
+                rolled_back_to,
+            }) => Err(e.context(format!(
+                "Couldn't apply the new settings, restored the {rolled_back_to}"
+            ))),
+            Err(e) => {
+                self.stop();
+                if let Some(departure) = departure {
+                    departure.leave();
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Stops all streams and network tasks, fading the speaker out first.
+    ///
+    /// Doesn't say goodbye, since it's also how a restart begins.
+    fn stop(&mut self) {
+        self.departure = None;
+
+        if let Some(ramp) = self.output_ramp.take() {
+            ramp.fade_out();
+            thread::sleep(ramp.duration());
+        }
+
         if let Some(abort) = self.dispatcher_abort.take() {
             abort.abort();
         }
 
         self._audio_streams.clear();
         self.mic_input = None;
+        self.mic_encoder = None;
+        self.mic_packer = None;
         if let Some(share_music) = self.share_music.take() {
             share_music.clear();
         }
@@ -309,11 +787,6 @@ impl<
         if let Some(handle) = self.network_thread.take() {
             let _ = handle.join();
         }
-
-        self.config = config;
-        self.realtime_stream = Arc::new(RealtimeAudioStream::new());
-
-        self.run()
     }
 
     pub fn start_music_stream(
@@ -325,6 +798,16 @@ impl<
         self.share_music()?.start_stream(data, file_name, progress)
     }
 
+    pub fn start_music_url(
+        &self,
+        source: HttpSource,
+        file_name: String,
+        progress: Arc<MusicStreamProgress>,
+    ) -> Result<()> {
+        self.share_music()?
+            .start_url_stream(source, file_name, progress)
+    }
+
     fn build_stream_bundle(
         &mut self,
         network_sender: NetworkSender,
@@ -334,34 +817,76 @@ impl<
         let ntp_service = NtpService::new(network_sender.clone());
 
         let ntp_for_synced = ntp_service.clone();
-        let share_music = Arc::new(ShareMusicService::new(
-            ntp_service.clone(),
-            network_sender.clone(),
-            move || ntp_for_synced.party_now(),
-            self.state.vocal_removal_enabled.clone(),
-        ));
+        let local_mic_level = self.state.mic_audio_level.clone();
+        let realtime_for_ducking = self.realtime_stream.clone();
+        let mic_level = move || {
+            local_mic_level
+                .load(Ordering::Relaxed)
+                .max(realtime_for_ducking.peak_level(RealtimeStreamId::Mic))
+        };
+        let ducker = Ducker::new(self.state.ducking.clone(), mic_level.clone());
+        let share_music = Arc::new(
+            ShareMusicService::new(
+                ntp_service.clone(),
+                network_sender.clone(),
+                move || ntp_for_synced.party_now(),
+                self.state.vocal_removal_enabled.clone(),
+                self.config.compress_pcm_music,
+                ducker,
+                self.config.retransmit_window,
+            )
+            .with_music_frame_duration(self.config.music_frame_duration)
+            .with_coalescing(self.config.synced_coalesce)
+            .with_read_ahead(self.config.music_read_ahead)
+            .with_sample_offsets(self.config.music_sample_offsets)
+            .with_auto_mono(self.config.auto_mono_music),
+        );
+        share_music.set_auto_balance(AutoBalance::new(self.state.auto_balance.clone(), mic_level));
 
         let ntp_for_playlist = ntp_service.clone();
         let playlist = Arc::new(SharedPlaylist::new(
             Arc::downgrade(&self.state),
-            network_sender,
+            network_sender.clone(),
             local_ips,
             send_ip,
             self.state.view_state.clone(),
             move || ntp_for_playlist.party_now(),
         ));
 
+        let presence = Arc::new(PresenceService::new(self.config.presence.clone()));
+        let departure = Arc::new(
+            DepartureService::new(
+                network_sender.clone(),
+                self.realtime_stream.clone(),
+                share_music.receiver(),
+                presence.clone(),
+            )
+            .with_transmit(vec![
+                (
+                    RealtimeStreamId::Mic,
+                    self.state.transmit.flag(RealtimeStreamId::Mic),
+                ),
+                (
+                    RealtimeStreamId::System,
+                    self.state.transmit.flag(RealtimeStreamId::System),
+                ),
+            ]),
+        );
+
         let streams: Vec<Arc<dyn NetworkStream<Sample, CHANNELS, SAMPLE_RATE>>> = vec![
             self.realtime_stream.clone() as Arc<dyn NetworkStream<Sample, CHANNELS, SAMPLE_RATE>>,
             share_music.clone() as Arc<dyn NetworkStream<Sample, CHANNELS, SAMPLE_RATE>>,
             ntp_service.clone() as Arc<dyn NetworkStream<Sample, CHANNELS, SAMPLE_RATE>>,
             playlist.clone() as Arc<dyn NetworkStream<Sample, CHANNELS, SAMPLE_RATE>>,
+            presence as Arc<dyn NetworkStream<Sample, CHANNELS, SAMPLE_RATE>>,
+            departure.clone() as Arc<dyn NetworkStream<Sample, CHANNELS, SAMPLE_RATE>>,
         ];
 
         NetworkStreamBundle {
             ntp_service,
             share_music,
             playlist,
+            departure,
             registry: Arc::new(StreamRegistry::from_streams(streams)),
         }
     }
@@ -386,3 +911,163 @@ impl<
         }
     }
 }
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Drop
+    for Party<Sample, CHANNELS, SAMPLE_RATE>
+{
+    /// Says goodbye when the party is dropped, so others don't wait for it
+    /// to time out.
+    fn drop(&mut self) {
+        if let Some(departure) = self.departure.take() {
+            departure.leave();
+        }
+    }
+}
+
+/// A restart that started some config.
+struct Restarted {
+    /// Why the new config didn't start, if it didn't.
+    error: Option<anyhow::Error>,
+    /// What was started instead of it.
+    rolled_back_to: &'static str,
+}
+
+/// Starts `new`, or if that fails `previous`, then `fallback`. `start` is
+/// called with each in turn until one starts, and must clean up after a
+/// failed attempt itself. Fails only if none started.
+fn start_with_rollback<C>(
+    new: C,
+    previous: C,
+    fallback: C,
+    mut start: impl FnMut(&C) -> Result<()>,
+) -> Result<Restarted> {
+    let error = match start(&new) {
+        Ok(()) => {
+            return Ok(Restarted {
+                error: None,
+                rolled_back_to: "new settings",
+            });
+        }
+        Err(e) => e,
+    };
+    error!("New config failed to start: {:?}", error);
+
+    for (config, rolled_back_to) in [
+        (previous, "previous settings"),
+        (fallback, "system default devices"),
+    ] {
+        match start(&config) {
+            Ok(()) => {
+                return Ok(Restarted {
+                    error: Some(error),
+                    rolled_back_to,
+                });
+            }
+            Err(e) => error!("Rolling back to the {} failed: {:?}", rolled_back_to, e),
+        }
+    }
+    Err(error.context("Couldn't start the new settings or roll back"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::bail;
+
+    #[test]
+    fn failed_restart_keeps_the_previous_config() {
+        let mut active = None;
+        let restarted = start_with_rollback("bad device", "working", "default", |config| {
+            active = None;
+            if *config == "bad device" {
+                bail!("Device not available");
+            }
+            active = Some(*config);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(active, Some("working"));
+        assert_eq!(restarted.rolled_back_to, "previous settings");
+        assert_eq!(restarted.error.unwrap().to_string(), "Device not available");
+    }
+
+    #[test]
+    fn restart_falls_back_to_default_devices() {
+        let mut tried = Vec::new();
+        let restarted = start_with_rollback("new", "previous", "default", |config| {
+            tried.push(*config);
+            if *config != "default" {
+                bail!("{config} failed");
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(tried, ["new", "previous", "default"]);
+        assert_eq!(restarted.rolled_back_to, "system default devices");
+        assert_eq!(restarted.error.unwrap().to_string(), "new failed");
+
+        let result = start_with_rollback("new", "previous", "default", |config| {
+            bail!("{config} failed")
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn limiter_caps_the_mic_after_input_gain() {
+        use std::sync::Mutex;
+
+        use crate::audio::effects::gain_cell;
+
+        type Buffer = AudioBuffer<f32, 2, 48000>;
+        struct Peak(Mutex<f32>);
+        impl Pushable<Buffer> for Arc<Peak> {
+            fn push(&self, input: Buffer) {
+                let peak = input
+                    .data()
+                    .iter()
+                    .fold(0.0f32, |peak, s| peak.max(s.abs()));
+                let mut max = self.0.lock().unwrap();
+                *max = max.max(peak);
+            }
+        }
+
+        // Gain at the top of the slider, last in the reorderable chain.
+        let order = Arc::new(Mutex::new(MicEffect::DEFAULT_ORDER.to_vec()));
+        let effects =
+            EffectChain::new(order).with_effect(MicEffect::Gain, LiveGain::new(gain_cell(2.0)));
+        let sent = Arc::new(Peak(Mutex::new(0.0)));
+        let mic = Party::<f32, 2, 48000>::limited_mic_effects(
+            effects,
+            &PartyConfig::default(),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(sent.clone()),
+        );
+
+        for block in 0..50 {
+            let samples = (0..960)
+                .flat_map(|n| {
+                    let s = 0.9 * ((block * 960 + n) as f32 * 0.05).sin();
+                    [s, -s]
+                })
+                .collect();
+            mic.push(AudioBuffer::new(samples).unwrap());
+        }
+        let peak = *sent.0.lock().unwrap();
+        assert!(peak > 0.5, "limiter muted the mic: {peak}");
+        assert!(peak < 1.0, "peak {peak} reached full scale");
+    }
+
+    #[test]
+    fn successful_restart_reports_no_error() {
+        let mut tried = Vec::new();
+        let restarted = start_with_rollback("new", "previous", "default", |config| {
+            tried.push(*config);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(tried, ["new"]);
+        assert!(restarted.error.is_none());
+    }
+}
@@ -0,0 +1,221 @@
+//! Heartbeat-based presence.
+//!
+//! Realtime audio alone only reveals hosts that are talking or sharing system
+//! audio; a listen-only or muted participant would never show up. Every party
+//! instance therefore multicasts a small [`Heartbeat`] at a fixed interval,
+//! and [`PresenceService`] keeps a host listed for as long as either its
+//! heartbeats or its audio keep arriving within `HOST_TIMEOUT`.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use rkyv::{Archive, Deserialize, Serialize};
+use tracing::info;
+
+use crate::audio::AudioSample;
+use crate::io::NetworkSender;
+use crate::party::network_stream::{NetworkStream, NetworkStreamContext};
+use crate::party::realtime_stream::HOST_TIMEOUT;
+use crate::party::tagged_packet::{HEARTBEAT_TAG, PacketTag, TaggedPacket};
+use crate::pipeline::Pushable;
+use crate::state::{HostId, PartyViewState};
+
+/// Announces that a party instance is alive.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Heartbeat {
+    /// Random id picked at startup; tells instances on one machine apart.
+    pub party_id: u64,
+    /// Display name chosen by the user. Empty if unset.
+    pub name: String,
+}
+
+/// How often this instance announces itself, and under which name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresenceConfig {
+    pub heartbeat_interval: Duration,
+    pub name: String,
+}
+
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval: Duration::from_secs(1),
+            name: String::new(),
+        }
+    }
+}
+
+struct Peer {
+    name: String,
+    last_seen: Instant,
+}
+
+/// Sends our heartbeats and tracks the hosts whose heartbeats we receive.
+pub struct PresenceService {
+    party_id: u64,
+    config: PresenceConfig,
+    peers: DashMap<SocketAddr, Peer>,
+}
+
+impl PresenceService {
+    pub fn new(config: PresenceConfig) -> Self {
+        Self {
+            party_id: rand::random(),
+            config,
+            peers: DashMap::new(),
+        }
+    }
+
+    pub fn heartbeat(&self) -> Heartbeat {
+        Heartbeat {
+            party_id: self.party_id,
+            name: self.config.name.clone(),
+        }
+    }
+
+    /// Records a heartbeat from `source`.
+    pub fn receive(&self, source: SocketAddr, heartbeat: Heartbeat) {
+        if heartbeat.party_id == self.party_id {
+            return;
+        }
+        let previous = self.peers.insert(
+            source,
+            Peer {
+                name: heartbeat.name,
+                last_seen: Instant::now(),
+            },
+        );
+        if previous.is_none() {
+            info!("Host {} joined (heartbeat)", source);
+        }
+    }
+
+    /// Forgets peers whose last heartbeat is older than `HOST_TIMEOUT` at `now`.
+    pub fn cleanup_stale(&self, now: Instant) {
+        self.peers.retain(|source, peer| {
+            let alive = now.saturating_duration_since(peer.last_seen) < HOST_TIMEOUT;
+            if !alive {
+                info!("Host {} timed out (no heartbeat)", source);
+            }
+            alive
+        });
+    }
+
+    /// Forgets `source` right away, for a host that said goodbye.
+    pub fn remove(&self, source: SocketAddr) {
+        if self.peers.remove(&source).is_some() {
+            info!("Host {} left", source);
+        }
+    }
+
+    /// Hosts currently present by heartbeat, with their display names.
+    pub fn present_hosts(&self) -> HashMap<HostId, String> {
+        self.peers
+            .iter()
+            .map(|entry| (HostId::from(*entry.key()), entry.name.clone()))
+            .collect()
+    }
+
+    fn send_heartbeat(&self, sender: &NetworkSender) {
+        let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&self.heartbeat())
+            .expect("Heartbeat serialization")
+            .into_vec();
+        sender.push(TaggedPacket::new(HEARTBEAT_TAG, payload));
+    }
+
+    pub(crate) fn update_view_state(&self, view_state: &PartyViewState) {
+        view_state.set_present_hosts(self.present_hosts());
+    }
+
+    /// Starts the heartbeat and expiry task.
+    ///
+    /// Must be called from within a Tokio runtime context.
+    pub fn start_task(self: &Arc<Self>, ctx: NetworkStreamContext) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut heartbeat = tokio::time::interval(service.config.heartbeat_interval);
+            let mut cleanup = tokio::time::interval(Duration::from_millis(500));
+            loop {
+                tokio::select! {
+                    _ = heartbeat.tick() => service.send_heartbeat(&ctx.sender),
+                    _ = cleanup.tick() => {
+                        service.cleanup_stale(Instant::now());
+                        service.update_view_state(&ctx.view_state);
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl<S: AudioSample, const C: usize, const SR: u32> NetworkStream<S, C, SR> for PresenceService {
+    fn tags(&self) -> &'static [PacketTag] {
+        &[HEARTBEAT_TAG]
+    }
+
+    fn forget(&self, source: SocketAddr) {
+        self.remove(source);
+    }
+
+    fn handle(&self, source: SocketAddr, _tag: PacketTag, bytes: &[u8]) -> anyhow::Result<()> {
+        let heartbeat = rkyv::from_bytes::<Heartbeat, rkyv::rancor::Error>(bytes)
+            .map_err(|e| anyhow::anyhow!("Heartbeat deserialize: {:?}", e))?;
+        self.receive(source, heartbeat);
+        Ok(())
+    }
+
+    fn start(self: Arc<Self>, ctx: NetworkStreamContext) {
+        self.start_task(ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heartbeat_bytes(party_id: u64, name: &str) -> Vec<u8> {
+        let heartbeat = Heartbeat {
+            party_id,
+            name: name.to_string(),
+        };
+        rkyv::to_bytes::<rkyv::rancor::Error>(&heartbeat)
+            .unwrap()
+            .into_vec()
+    }
+
+    fn handle(service: &PresenceService, source: SocketAddr, bytes: &[u8]) {
+        NetworkStream::<f32, 2, 48000>::handle(service, source, HEARTBEAT_TAG, bytes).unwrap();
+    }
+
+    #[test]
+    fn heartbeats_alone_keep_host_present() {
+        let service = PresenceService::new(PresenceConfig::default());
+        let view = PartyViewState::new();
+        let peer: SocketAddr = "10.0.0.7:5000".parse().unwrap();
+
+        handle(&service, peer, &heartbeat_bytes(42, "Alice"));
+        service.cleanup_stale(Instant::now() + HOST_TIMEOUT / 2);
+        service.update_view_state(&view);
+
+        let hosts = view.realtime_hosts();
+        assert_eq!(hosts.len(), 1);
+        assert_eq!(hosts[0].id, HostId::from(peer));
+        assert_eq!(hosts[0].name.as_deref(), Some("Alice"));
+        assert!(hosts[0].streams.is_empty());
+
+        service.cleanup_stale(Instant::now() + HOST_TIMEOUT + Duration::from_secs(1));
+        service.update_view_state(&view);
+        assert!(view.realtime_hosts().is_empty());
+    }
+
+    #[test]
+    fn own_heartbeat_is_ignored() {
+        let service = PresenceService::new(PresenceConfig::default());
+        let own = service.heartbeat();
+        service.receive("10.0.0.8:5000".parse().unwrap(), own);
+        assert!(service.present_hosts().is_empty());
+    }
+}
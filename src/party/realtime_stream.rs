@@ -13,31 +13,75 @@
 //! The mixer is shared across all sources, enabling dynamic addition/removal
 //! of network hosts without rebuilding the pipeline.
 //!
+//! # Retransmission
+//!
+//! With NACKs enabled, holes a jitter buffer still has time to fill are
+//! asked for again with a [`RealtimeNack`]. A hole is first left open for
+//! `NACK_REORDER_WINDOW`, since the frame may only be arriving out of
+//! order, then asked for every `NACK_RETRY_INTERVAL` until it fills, is
+//! played, or `NACK_TIMEOUT` passes. Senders keep their last few frames
+//! in a [`SentFrames`] history to answer them.
+//!
+//! # Loss feedback
+//!
+//! With loss feedback enabled, the loss measured on each stream is reported
+//! back to its sender in a [`LossFeedback`], addressed the same way as a
+//! NACK. Reports about our own streams are kept in their [`LossReports`]
+//! for the [`BitrateController`](super::bitrate::BitrateController).
+//!
 //! For synchronized music playback, see [`share_music`](super::share_music).
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::net::SocketAddr;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::{Duration, Instant};
 
 use dashmap::DashMap;
 use rkyv::{Archive, Deserialize, Serialize};
-use tracing::info;
+use tracing::{info, warn};
 
+use crate::audio::buffers::Concealment;
+use crate::audio::effects::{gain_cell, load_gain, store_gain};
 use crate::audio::frame::AudioBuffer;
+pub use crate::audio::opus::AudioCodec;
 use crate::audio::opus::OpusPacket;
-use crate::audio::{AudioSample, JitterBuffer, RealtimeFrameDecoder, RealtimeOpusFrame};
-use crate::party::combinator::{InputId, Mixer};
-use crate::party::network_stream::{NetworkStream, NetworkStreamContext};
-use crate::party::tagged_packet::{PacketTag, REALTIME_TAG, TaggedPacket};
+use crate::audio::{
+    AudioSample, JitterBuffer, LiveGain, OpusEncoder, RealtimeFrameDecoder, RealtimeOpusFrame,
+};
+use crate::io::NetworkSender;
+use crate::party::bitrate::LossReports;
+use crate::party::combinator::{InputId, Mixer, Tap, Taps};
+use crate::party::decode_pool::DecodePool;
+use crate::party::frame_clock::FrameClock;
+use crate::party::network_stream::{NetworkStream, NetworkStreamContext, spawn_stats_task};
+use crate::party::snapshot::RealtimeStreamSnapshot;
+use crate::party::tagged_packet::{
+    LOSS_FEEDBACK_TAG, PacketTag, REALTIME_NACK_TAG, REALTIME_TAG, TaggedPacket,
+};
 use crate::pipeline::{GraphNode, Pullable, Pushable};
+use crate::pull_chain;
 use crate::state::{HostId, PartyViewState, StreamViewKey};
 
 pub use crate::audio::PullSnapshot as StreamSnapshot;
 
-const HOST_TIMEOUT: Duration = Duration::from_secs(5);
+pub(crate) const HOST_TIMEOUT: Duration = Duration::from_secs(5);
 const JITTER_BUFFER_CAPACITY: usize = 64;
+/// Streams that have not delivered a packet for this long no longer count
+/// towards [`RealtimeAudioStream::peak_level`].
+const LEVEL_ACTIVITY_WINDOW: Duration = Duration::from_millis(200);
+/// Frames of each local stream kept to answer NACKs.
+const SENT_FRAMES_HISTORY: usize = 64;
+/// Most frames asked for in one NACK.
+const NACK_MAX_SEQS: usize = 8;
+/// How often receivers look for holes to NACK.
+const NACK_INTERVAL: Duration = Duration::from_millis(10);
+/// How long a hole is left for a reordered frame before it is NACKed.
+const NACK_REORDER_WINDOW: Duration = Duration::from_millis(20);
+/// How long an unanswered NACK waits before it is sent again.
+const NACK_RETRY_INTERVAL: Duration = Duration::from_millis(40);
+/// How long after a hole appears it stops being asked for.
+const NACK_TIMEOUT: Duration = Duration::from_millis(300);
 
 /// Identifies a realtime audio stream instance.
 #[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -56,6 +100,166 @@ impl std::fmt::Display for RealtimeStreamId {
     }
 }
 
+impl std::str::FromStr for RealtimeStreamId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Mic" => Ok(RealtimeStreamId::Mic),
+            "System" => Ok(RealtimeStreamId::System),
+            _ => anyhow::bail!("Unknown realtime stream id: {s}"),
+        }
+    }
+}
+
+/// Which of our realtime streams are sent. Each flag gates its stream's
+/// outgoing pipeline through a [`Switch`](crate::audio::effects::Switch),
+/// so any combination can be sent, e.g. system audio without the mic.
+///
+/// Kept in the app state, the selection survives party restarts.
+#[derive(Debug, Default)]
+pub struct TransmitSet {
+    mic: Arc<AtomicBool>,
+    system: Arc<AtomicBool>,
+}
+
+impl TransmitSet {
+    fn cell(&self, stream: RealtimeStreamId) -> &Arc<AtomicBool> {
+        match stream {
+            RealtimeStreamId::Mic => &self.mic,
+            RealtimeStreamId::System => &self.system,
+        }
+    }
+
+    /// Flag gating `stream`'s outgoing pipeline.
+    pub fn flag(&self, stream: RealtimeStreamId) -> Arc<AtomicBool> {
+        self.cell(stream).clone()
+    }
+
+    pub fn is_enabled(&self, stream: RealtimeStreamId) -> bool {
+        self.cell(stream).load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, stream: RealtimeStreamId, enabled: bool) {
+        self.cell(stream).store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Output volume of each remote host, applied to all of its streams in
+/// the mixer. Hosts never set play at full volume.
+///
+/// Kept in the app state, so volumes survive party restarts. The UI writes
+/// a host's cell and the decode chains read it per buffer, without locks.
+#[derive(Debug, Default, Clone)]
+pub struct HostVolumes(Arc<DashMap<HostId, Arc<AtomicU32>>>);
+
+impl HostVolumes {
+    /// Gain cell of `host`, shared by all of its decode chains.
+    fn cell(&self, host: HostId) -> Arc<AtomicU32> {
+        self.0.entry(host).or_insert_with(|| gain_cell(1.0)).clone()
+    }
+
+    pub fn get(&self, host: HostId) -> f32 {
+        self.0.get(&host).map_or(1.0, |cell| load_gain(&cell))
+    }
+
+    /// Sets `host`'s volume (1.0 = unchanged, 0 mutes it).
+    pub fn set(&self, host: HostId, volume: f32) {
+        store_gain(&self.cell(host), volume);
+    }
+}
+
+/// [`Taps`] on the decoded audio of each remote stream, before it is
+/// mixed.
+///
+/// Kept by the party across restarts, so a tap added for a stream keeps
+/// seeing it through restarts and before its first frame arrives.
+pub struct StreamTaps<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32>(
+    Arc<DashMap<MonitorTarget, Arc<Taps<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>>>,
+);
+
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    StreamTaps<Sample, CHANNELS, SAMPLE_RATE>
+{
+    /// Taps of `target`'s stream, shared by all of its decode chains.
+    pub fn get(
+        &self,
+        target: MonitorTarget,
+    ) -> Arc<Taps<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>> {
+        self.0.entry(target).or_default().clone()
+    }
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Default
+    for StreamTaps<Sample, CHANNELS, SAMPLE_RATE>
+{
+    fn default() -> Self {
+        Self(Arc::default())
+    }
+}
+
+impl<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> Clone
+    for StreamTaps<Sample, CHANNELS, SAMPLE_RATE>
+{
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+/// Averages over the jitter buffers of all remote realtime streams.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AggregateStats {
+    /// Mean target latency in milliseconds.
+    pub avg_target_latency_ms: f64,
+    /// Mean packet loss rate (0.0 - 1.0).
+    pub avg_loss_rate: f64,
+    /// Remote streams averaged over.
+    pub active_source_count: usize,
+}
+
+impl AggregateStats {
+    /// Averages the stats of `buffers`, converting each one's target
+    /// latency to milliseconds by its own frame size. All zero for none.
+    pub fn of<'a, Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>(
+        buffers: impl IntoIterator<Item = &'a JitterBuffer<Sample, CHANNELS, SAMPLE_RATE>>,
+    ) -> Self {
+        let mut stats = Self::default();
+        for buffer in buffers {
+            stats.avg_target_latency_ms += buffer.target_latency_ms();
+            stats.avg_loss_rate += buffer.stats().loss_rate();
+            stats.active_source_count += 1;
+        }
+        if stats.active_source_count > 0 {
+            stats.avg_target_latency_ms /= stats.active_source_count as f64;
+            stats.avg_loss_rate /= stats.active_source_count as f64;
+        }
+        stats
+    }
+}
+
+/// Codec and bitrate of a received stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamCodec {
+    pub codec: AudioCodec,
+    /// Bitrate the sender's encoder targets, in bits per second.
+    pub declared_bitrate: u32,
+    /// Bitrate actually arriving, smoothed over recent frames.
+    pub measured_bitrate: u32,
+}
+
+impl StreamCodec {
+    /// Short label such as "Opus 128k", using the declared bitrate when the
+    /// sender provided one.
+    pub fn label(&self) -> String {
+        let bitrate = if self.declared_bitrate > 0 {
+            self.declared_bitrate
+        } else {
+            self.measured_bitrate
+        };
+        format!("{} {}k", self.codec, bitrate / 1000)
+    }
+}
+
 /// Frame format for realtime audio streams (Opus-encoded).
 #[derive(Archive, Serialize, Deserialize, Debug, Clone)]
 #[rkyv(compare(PartialEq))]
@@ -65,6 +269,18 @@ pub struct RealtimeFrame {
     pub timestamp: u64,
     pub opus_data: Vec<u8>,
     pub frame_size: u32,
+    /// Sender was silent and skipped encoding this frame (DTX); `opus_data`
+    /// is empty and the receiver fills in comfort noise.
+    pub dtx: bool,
+    pub codec: AudioCodec,
+    /// Target bitrate of the sender's encoder in bits per second, 0 if unknown.
+    pub bitrate: u32,
+    /// Channels the sender captured and encoded with; `frame_size` counts
+    /// interleaved samples in this layout, which may differ from ours.
+    pub channels: u8,
+    /// Which run of the sender's stream this frame belongs to. Sequence
+    /// numbers start over with each new epoch.
+    pub epoch: u32,
 }
 
 impl RealtimeFrame {
@@ -80,13 +296,53 @@ impl RealtimeFrame {
             timestamp,
             opus_data: opus_packet.data,
             frame_size: opus_packet.frame_size as u32,
+            dtx: false,
+            codec: opus_packet.codec,
+            bitrate: opus_packet.bitrate,
+            channels: 2,
+            epoch: 0,
         }
     }
 
+    /// Replaces the creation-time timestamp.
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Sets the sender's channel count, stereo unless set.
+    pub fn with_channels(mut self, channels: usize) -> Self {
+        self.channels = channels as u8;
+        self
+    }
+
+    pub fn with_epoch(mut self, epoch: u32) -> Self {
+        self.epoch = epoch;
+        self
+    }
+
+    /// A frame marking sender silence of `frame_size` samples.
+    pub fn dtx(stream_id: RealtimeStreamId, sequence_number: u64, frame_size: usize) -> Self {
+        let mut frame = Self::new(
+            stream_id,
+            sequence_number,
+            OpusPacket {
+                data: Vec::new(),
+                frame_size,
+                codec: AudioCodec::Opus,
+                bitrate: 0,
+            },
+        );
+        frame.dtx = true;
+        frame
+    }
+
     pub fn to_opus_packet(&self) -> OpusPacket {
         OpusPacket {
             data: self.opus_data.clone(),
             frame_size: self.frame_size as usize,
+            codec: self.codec,
+            bitrate: self.bitrate,
         }
     }
 
@@ -96,10 +352,98 @@ impl RealtimeFrame {
             timestamp: self.timestamp,
             opus_data: self.opus_data.clone(),
             frame_size: self.frame_size as usize,
+            dtx: self.dtx,
+            channels: self.channels.max(1) as usize,
+            codec: self.codec,
+            epoch: self.epoch,
         }
     }
 }
 
+/// Asks the sender of a realtime stream to send frames again.
+///
+/// NACKs are multicast like everything else, so the sender is named by the
+/// newest frame received from it, `anchor_seq` stamped `anchor_timestamp`.
+/// Only the instance that sent that exact frame answers.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[rkyv(compare(PartialEq))]
+pub struct RealtimeNack {
+    pub stream_id: RealtimeStreamId,
+    pub anchor_seq: u64,
+    pub anchor_timestamp: u64,
+    pub seqs: Vec<u64>,
+}
+
+/// Loss a receiver measures on one realtime stream, reported back to its
+/// sender. The sender is named like in a [`RealtimeNack`].
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[rkyv(compare(PartialEq))]
+pub struct LossFeedback {
+    pub stream_id: RealtimeStreamId,
+    pub anchor_seq: u64,
+    pub anchor_timestamp: u64,
+    pub loss_rate: f32,
+}
+
+/// A frame sent recently, kept by [`SentFrames`].
+struct SentFrame {
+    seq: u64,
+    timestamp: u64,
+    payload: Vec<u8>,
+}
+
+/// The last frames [`RealtimeFramePacker`] sent for one local stream,
+/// serialized as they went out, to be sent again on a [`RealtimeNack`].
+pub struct SentFrames {
+    frames: Mutex<VecDeque<SentFrame>>,
+    capacity: usize,
+}
+
+impl SentFrames {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    fn record(&self, frame: &RealtimeFrame, payload: &[u8]) {
+        let mut frames = self.frames.lock().unwrap();
+        if frames.len() == self.capacity {
+            frames.pop_front();
+        }
+        frames.push_back(SentFrame {
+            seq: frame.sequence_number,
+            timestamp: frame.timestamp,
+            payload: payload.to_vec(),
+        });
+    }
+
+    /// Whether we sent the frame `seq` stamped `timestamp` recently, i.e.
+    /// whether a NACK or feedback anchored on it is meant for us.
+    pub fn contains(&self, seq: u64, timestamp: u64) -> bool {
+        self.frames
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|f| f.seq == seq && f.timestamp == timestamp)
+    }
+
+    /// Packets answering `nack`: the frames it asks for that are still
+    /// kept, or none if it's for another sender.
+    pub fn answer(&self, nack: &RealtimeNack) -> Vec<TaggedPacket> {
+        if !self.contains(nack.anchor_seq, nack.anchor_timestamp) {
+            return Vec::new();
+        }
+        let frames = self.frames.lock().unwrap();
+        nack.seqs
+            .iter()
+            .filter_map(|&seq| frames.iter().find(|f| f.seq == seq))
+            .map(|f| TaggedPacket::new(REALTIME_TAG, f.payload.clone()))
+            .collect()
+    }
+}
+
 /// Key for identifying a specific decode chain.
 /// We use SocketAddr (IP + Port) here to distinguish between multiple
 /// instances running on the same machine.
@@ -115,29 +459,251 @@ struct BufferKey {
 /// - `decoder`: Entry point for pushing decoded frames
 /// - `jitter_buffer`: Stores decoded frames, registered with mixer for pulling
 /// - `mixer_input_id`: ID for removing from mixer on cleanup
+/// - `worker`: Decode pool worker the stream is pinned to, if any
+/// - `gain`: Applied between the jitter buffer and the mixer, lowered while
+///   the stream is turned down for loss. The host's volume follows it.
+/// - `newest`: Sequence number and timestamp of the newest frame, naming
+///   the sender in NACKs
+/// - `holes`: Missing sequence numbers with when they were first seen and
+///   last NACKed
 struct DecodeChain<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     decoder: Arc<dyn Pushable<RealtimeOpusFrame>>,
     jitter_buffer: Arc<JitterBuffer<Sample, CHANNELS, SAMPLE_RATE>>,
     mixer_input_id: InputId,
+    worker: Option<usize>,
+    gain: Arc<AtomicU32>,
+    loss_mute: LossMute,
     last_seen: Instant,
+    codec: Option<StreamCodec>,
+    epoch: Option<u32>,
+    newest: Option<(u64, u64)>,
+    holes: BTreeMap<u64, Hole>,
+}
+
+/// A frame missing from a jitter buffer, tracked for NACKing.
+#[derive(Debug, Clone, Copy)]
+struct Hole {
+    seen: Instant,
+    nacked: Option<Instant>,
+}
+
+impl Hole {
+    /// Whether the frame should be asked for at `now`.
+    fn due(&self, now: Instant) -> bool {
+        let next = match self.nacked {
+            None => self.seen + NACK_REORDER_WINDOW,
+            Some(nacked) => nacked + NACK_RETRY_INTERVAL,
+        };
+        now >= next && now < self.seen + NACK_TIMEOUT
+    }
 }
 
 fn create_decode_chain<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>(
     mixer: &Arc<Mixer<Sample, CHANNELS, SAMPLE_RATE>>,
+    dtx_comfort_noise: bool,
+    epoch_tolerance: Option<Duration>,
+    concealment: Concealment,
+    worker: Option<usize>,
+    host_volume: Arc<AtomicU32>,
+    taps: Arc<Taps<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
 ) -> DecodeChain<Sample, CHANNELS, SAMPLE_RATE> {
-    let jitter_buffer = Arc::new(JitterBuffer::new(JITTER_BUFFER_CAPACITY));
-    let decoder = Arc::new(GraphNode::new(
-        RealtimeFrameDecoder::new().expect("Failed to create Opus decoder"),
-    ));
+    let frame_decoder = Arc::new(
+        RealtimeFrameDecoder::new()
+            .expect("Failed to create Opus decoder")
+            .with_comfort_noise(dtx_comfort_noise)
+            // Repeating the last frame was asked for instead of PLC.
+            .with_plc(concealment == Concealment::Silence),
+    );
+    let jitter_buffer = Arc::new(
+        JitterBuffer::new(JITTER_BUFFER_CAPACITY)
+            .with_epoch_tolerance(epoch_tolerance)
+            .with_concealment(concealment)
+            .with_recovery({
+                let frame_decoder = frame_decoder.clone();
+                move |seq, frame_size| {
+                    frame_decoder
+                        .recover(seq, frame_size)
+                        .map(AudioBuffer::into_inner)
+                }
+            }),
+    );
+    let decoder = Arc::new(GraphNode::new(frame_decoder));
 
     decoder.add_output(jitter_buffer.clone());
-    let mixer_input_id = mixer.add_input(jitter_buffer.clone());
+    let gain = gain_cell(1.0);
+    let mixer_input_id = mixer.add_input(pull_chain![
+        jitter_buffer.clone() =>,
+        Tap::new(taps),
+        LiveGain::new(gain.clone()),
+        LiveGain::new(host_volume),
+    ]);
 
     DecodeChain {
         decoder,
         jitter_buffer,
         mixer_input_id,
+        worker,
+        gain,
+        loss_mute: LossMute::default(),
         last_seen: Instant::now(),
+        codec: None,
+        epoch: None,
+        newest: None,
+        holes: BTreeMap::new(),
+    }
+}
+
+/// Weight of the newest frame in the measured bitrate average.
+const BITRATE_SMOOTHING: f64 = 0.05;
+
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    DecodeChain<Sample, CHANNELS, SAMPLE_RATE>
+{
+    /// Follows the sender into a new epoch of its stream: the sequence
+    /// numbers start over, so what's buffered and asked for is forgotten.
+    fn record_epoch(&mut self, frame: &RealtimeFrame) {
+        let Some(previous) = self.epoch.replace(frame.epoch) else {
+            return;
+        };
+        if previous == frame.epoch {
+            return;
+        }
+        info!(
+            "Stream {:?} restarted (epoch {} -> {})",
+            frame.stream_id, previous, frame.epoch
+        );
+        self.jitter_buffer.reset();
+        self.newest = None;
+        self.holes.clear();
+    }
+
+    /// Updates the stream's codec info from a received frame.
+    fn record_codec(&mut self, frame: &RealtimeFrame) {
+        let channels = frame.channels.max(1) as f64;
+        let seconds = frame.frame_size as f64 / channels / SAMPLE_RATE as f64;
+        if seconds <= 0.0 {
+            return;
+        }
+        let frame_bitrate = frame.opus_data.len() as f64 * 8.0 / seconds;
+        let measured = match self.codec {
+            Some(codec) => {
+                let previous = codec.measured_bitrate as f64;
+                previous + (frame_bitrate - previous) * BITRATE_SMOOTHING
+            }
+            None => frame_bitrate,
+        };
+        self.codec = Some(StreamCodec {
+            codec: frame.codec,
+            declared_bitrate: frame.bitrate,
+            measured_bitrate: measured.round() as u32,
+        });
+    }
+
+    /// Remembers `frame` if it's the newest one received. One far behind
+    /// the newest means the sender restarted its sequence numbers.
+    fn record_newest(&mut self, frame: &RealtimeFrame) {
+        let seq = frame.sequence_number;
+        match self.newest {
+            Some((newest, _)) if seq <= newest => {
+                if newest - seq <= SENT_FRAMES_HISTORY as u64 {
+                    return;
+                }
+                self.holes.clear();
+            }
+            _ => {}
+        }
+        self.newest = Some((seq, frame.timestamp));
+    }
+
+    /// A NACK for the holes due to be asked for at `now`, if there are any.
+    fn nack(&mut self, stream_id: RealtimeStreamId, now: Instant) -> Option<RealtimeNack> {
+        let (anchor_seq, anchor_timestamp) = self.newest?;
+        let missing = self.jitter_buffer.missing_seqs();
+        // Filled or played holes are done with.
+        self.holes
+            .retain(|seq, _| missing.binary_search(seq).is_ok());
+
+        let mut seqs = Vec::new();
+        for seq in missing {
+            let hole = self.holes.entry(seq).or_insert(Hole {
+                seen: now,
+                nacked: None,
+            });
+            if seqs.len() < NACK_MAX_SEQS && hole.due(now) {
+                hole.nacked = Some(now);
+                seqs.push(seq);
+            }
+        }
+        if seqs.is_empty() {
+            return None;
+        }
+        Some(RealtimeNack {
+            stream_id,
+            anchor_seq,
+            anchor_timestamp,
+            seqs,
+        })
+    }
+
+    /// Feedback with the loss currently measured, once a frame has arrived.
+    fn loss_feedback(&self, stream_id: RealtimeStreamId) -> Option<LossFeedback> {
+        let (anchor_seq, anchor_timestamp) = self.newest?;
+        Some(LossFeedback {
+            stream_id,
+            anchor_seq,
+            anchor_timestamp,
+            loss_rate: self.jitter_buffer.stats().loss_rate() as f32,
+        })
+    }
+}
+
+/// When to turn down a remote stream whose loss makes it sound broken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LossMuteConfig {
+    /// Loss rate (0.0 - 1.0) above which the stream is turned down.
+    pub threshold: f64,
+    /// Loss rate the stream has to drop below to be restored. Well under
+    /// `threshold` so a stream on the edge doesn't flap.
+    pub recover_below: f64,
+    /// How long the loss has to stay above `threshold` first.
+    pub sustain: Duration,
+    /// Gain while turned down; 0 mutes.
+    pub gain: f32,
+}
+
+impl Default for LossMuteConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 0.2,
+            recover_below: 0.05,
+            sustain: Duration::from_secs(3),
+            gain: 0.2,
+        }
+    }
+}
+
+/// Hysteresis deciding whether a stream is turned down for its loss.
+#[derive(Debug, Default)]
+struct LossMute {
+    above_since: Option<Instant>,
+    muted: bool,
+}
+
+impl LossMute {
+    /// Whether the stream should be turned down, given its loss rate at `now`.
+    fn update(&mut self, config: &LossMuteConfig, loss_rate: f64, now: Instant) -> bool {
+        if self.muted {
+            if loss_rate < config.recover_below {
+                self.muted = false;
+                self.above_since = None;
+            }
+        } else if loss_rate > config.threshold {
+            let since = *self.above_since.get_or_insert(now);
+            self.muted = now.duration_since(since) >= config.sustain;
+        } else {
+            self.above_since = None;
+        }
+        self.muted
     }
 }
 
@@ -145,9 +711,46 @@ fn create_decode_chain<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_
 ///
 /// Each network source gets a `DecodeChain` that feeds into a shared `DynamicMixer`.
 /// The mixer handles combining audio from all sources.
+///
+/// With a memory budget set, the budget is split evenly across all jitter
+/// buffers whenever a stream joins or leaves, so a crowded party trades
+/// per-stream jitter tolerance for bounded memory.
+///
+/// With decode workers set, frames are decoded on a [`DecodePool`] instead
+/// of the receive task, each stream on its own worker.
+///
+/// With a [`LossMuteConfig`], streams whose loss stays high are turned down
+/// by [`check_loss`](Self::check_loss) until their connection recovers.
+///
+/// Each host's streams are mixed at its volume in [`HostVolumes`]. A muted
+/// host's chains are still pulled, so their buffers keep draining, but add
+/// only silence to the sum.
+///
+/// With NACKs enabled, lost frames are asked for again while there is still
+/// time to play them. NACKs from others are answered from the
+/// [`SentFrames`] of our own streams either way.
+///
+/// With a loss feedback interval set, each stream's loss is reported to its
+/// sender that often. Reports about our own streams are kept either way.
 pub struct RealtimeAudioStream<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     chains: DashMap<BufferKey, DecodeChain<Sample, CHANNELS, SAMPLE_RATE>>,
     mixer: Arc<Mixer<Sample, CHANNELS, SAMPLE_RATE>>,
+    memory_budget: Option<usize>,
+    dtx_comfort_noise: bool,
+    epoch_tolerance: Option<Duration>,
+    concealment: Concealment,
+    decode_pool: Option<DecodePool<RealtimeOpusFrame>>,
+    loss_mute: Option<LossMuteConfig>,
+    host_volumes: HostVolumes,
+    taps: StreamTaps<Sample, CHANNELS, SAMPLE_RATE>,
+    nack: bool,
+    sent_mic: Arc<SentFrames>,
+    sent_system: Arc<SentFrames>,
+    loss_feedback: Option<Duration>,
+    reports_mic: Arc<LossReports>,
+    reports_system: Arc<LossReports>,
+    /// Where NACKs are answered to, set once the network is up.
+    retransmit_sink: OnceLock<NetworkSender>,
 }
 
 impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
@@ -157,6 +760,126 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         Self {
             chains: DashMap::new(),
             mixer: Arc::new(Mixer::new()),
+            memory_budget: None,
+            dtx_comfort_noise: true,
+            epoch_tolerance: None,
+            concealment: Concealment::Silence,
+            decode_pool: None,
+            loss_mute: None,
+            host_volumes: HostVolumes::default(),
+            taps: StreamTaps::default(),
+            nack: false,
+            sent_mic: Arc::new(SentFrames::new(SENT_FRAMES_HISTORY)),
+            sent_system: Arc::new(SentFrames::new(SENT_FRAMES_HISTORY)),
+            loss_feedback: None,
+            reports_mic: Arc::new(LossReports::new()),
+            reports_system: Arc::new(LossReports::new()),
+            retransmit_sink: OnceLock::new(),
+        }
+    }
+
+    /// Asks senders to retransmit lost frames, see [`RealtimeNack`].
+    pub fn with_nack(mut self, enabled: bool) -> Self {
+        self.nack = enabled;
+        self
+    }
+
+    /// History of our own `stream_id` frames, for its packer to record
+    /// into so NACKs for it can be answered.
+    pub fn sent_frames(&self, stream_id: RealtimeStreamId) -> Arc<SentFrames> {
+        match stream_id {
+            RealtimeStreamId::Mic => self.sent_mic.clone(),
+            RealtimeStreamId::System => self.sent_system.clone(),
+        }
+    }
+
+    /// Reports each stream's loss to its sender every `interval`, see
+    /// [`LossFeedback`]. `None` doesn't report.
+    pub fn with_loss_feedback(mut self, interval: Option<Duration>) -> Self {
+        self.loss_feedback = interval;
+        self
+    }
+
+    /// Loss receivers reported about our own `stream_id`.
+    pub fn loss_reports(&self, stream_id: RealtimeStreamId) -> Arc<LossReports> {
+        match stream_id {
+            RealtimeStreamId::Mic => self.reports_mic.clone(),
+            RealtimeStreamId::System => self.reports_system.clone(),
+        }
+    }
+
+    /// Mixes each host's streams at its volume in `volumes`.
+    pub fn with_host_volumes(mut self, volumes: HostVolumes) -> Self {
+        self.host_volumes = volumes;
+        self
+    }
+
+    /// Hands each stream's decoded audio to its taps in `taps`.
+    pub fn with_taps(mut self, taps: StreamTaps<Sample, CHANNELS, SAMPLE_RATE>) -> Self {
+        self.taps = taps;
+        self
+    }
+
+    /// Turns down streams with sustained high loss, see [`LossMuteConfig`].
+    pub fn with_loss_mute(mut self, config: Option<LossMuteConfig>) -> Self {
+        self.loss_mute = config;
+        self
+    }
+
+    /// Decodes on `workers` threads instead of the receive task. 0 keeps
+    /// decoding inline.
+    pub fn with_decode_workers(mut self, workers: usize) -> Self {
+        self.decode_pool = (workers > 0).then(|| DecodePool::new(workers));
+        if let Some(pool) = &self.decode_pool {
+            info!(
+                "Decoding realtime streams on {} workers",
+                pool.worker_count()
+            );
+        }
+        self
+    }
+
+    /// Whether DTX gaps are filled with comfort noise (default) or silence.
+    pub fn with_dtx_comfort_noise(mut self, enabled: bool) -> Self {
+        self.dtx_comfort_noise = enabled;
+        self
+    }
+
+    /// Lets jitter buffers start a new epoch on frames stamped more than
+    /// `tolerance` past the newest, see [`JitterBuffer::with_epoch_tolerance`].
+    pub fn with_epoch_tolerance(mut self, tolerance: Option<Duration>) -> Self {
+        self.epoch_tolerance = tolerance;
+        self
+    }
+
+    /// How jitter buffers fill lost frames, see [`JitterBuffer::with_concealment`].
+    pub fn with_concealment(mut self, concealment: Concealment) -> Self {
+        self.concealment = concealment;
+        self
+    }
+
+    /// Limits the total memory of all jitter buffers to `budget` bytes.
+    pub fn with_memory_budget(mut self, budget: Option<usize>) -> Self {
+        self.memory_budget = budget;
+        self
+    }
+
+    /// Estimated bytes held by all jitter buffers.
+    pub fn memory_usage(&self) -> usize {
+        self.chains
+            .iter()
+            .map(|entry| entry.jitter_buffer.memory_usage())
+            .sum()
+    }
+
+    /// Splits the memory budget evenly across the active jitter buffers.
+    fn rebalance_memory(&self) {
+        let Some(budget) = self.memory_budget else {
+            return;
+        };
+        let count = self.chains.len().max(1);
+        for entry in self.chains.iter() {
+            entry.jitter_buffer.fit_memory(budget / count);
         }
     }
 
@@ -171,25 +894,150 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
             stream_id: frame.stream_id,
         };
 
+        let mut created = false;
         let mut entry = self.chains.entry(key).or_insert_with(|| {
             info!(
                 "Creating decode chain for source {} stream {:?}",
                 source_addr, frame.stream_id
             );
-            create_decode_chain(&self.mixer)
+            created = true;
+            create_decode_chain(
+                &self.mixer,
+                self.dtx_comfort_noise,
+                self.epoch_tolerance,
+                self.concealment,
+                self.decode_pool.as_ref().map(DecodePool::assign),
+                self.host_volumes.cell(HostId::from(source_addr)),
+                self.taps.get((HostId::from(source_addr), frame.stream_id)),
+            )
         });
 
         entry.last_seen = Instant::now();
+        entry.record_codec(&frame);
+        entry.record_epoch(&frame);
+        entry.record_newest(&frame);
 
         let opus_frame = frame.to_realtime_opus_frame();
-        entry.decoder.push(opus_frame);
+        match (&self.decode_pool, entry.worker) {
+            (Some(pool), Some(worker)) => pool.submit(worker, entry.decoder.clone(), opus_frame),
+            _ => entry.decoder.push(opus_frame),
+        }
+        drop(entry);
+
+        if created {
+            self.rebalance_memory();
+        }
     }
 
     /// Pulls mixed audio from the shared mixer.
     pub fn pull_and_mix(&self, len: usize) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        if len == 0 {
+            return AudioBuffer::new(Vec::new()).ok();
+        }
         Pullable::pull(&*self.mixer, len)
     }
 
+    /// Like [`pull_and_mix`](Self::pull_and_mix), but writes into `out`
+    /// instead of allocating. Returns how many samples were written.
+    pub fn mix_into(&self, out: &mut [Sample], len: usize) -> Option<usize> {
+        if len == 0 {
+            return Some(0);
+        }
+        self.mixer.mix_into(out, len)
+    }
+
+    /// Pulls from the decode chain of one stream only, bypassing the mixer.
+    ///
+    /// If the host runs several instances, the first matching chain is used.
+    pub fn pull_single(
+        &self,
+        target: MonitorTarget,
+        len: usize,
+    ) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        let (host_id, stream_id) = target;
+        let jitter_buffer = self
+            .chains
+            .iter()
+            .find(|entry| {
+                entry.key().source_addr.ip() == host_id.ip() && entry.key().stream_id == stream_id
+            })
+            .map(|entry| entry.jitter_buffer.clone())?;
+        jitter_buffer.pull(len)
+    }
+
+    /// NACKs for the holes in every stream that are due at `now`.
+    pub fn pending_nacks(&self, now: Instant) -> Vec<RealtimeNack> {
+        self.chains
+            .iter_mut()
+            .filter_map(|mut entry| {
+                let stream_id = entry.key().stream_id;
+                entry.nack(stream_id, now)
+            })
+            .collect()
+    }
+
+    /// Packets answering a NACK for one of our own streams.
+    pub fn answer_nack(&self, nack: &RealtimeNack) -> Vec<TaggedPacket> {
+        self.sent_frames(nack.stream_id).answer(nack)
+    }
+
+    /// Starts the background task sending NACKs.
+    ///
+    /// Must be called from within a Tokio runtime context.
+    pub fn start_nack_task(self: &Arc<Self>, sender: NetworkSender) {
+        let stream = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(NACK_INTERVAL);
+            loop {
+                interval.tick().await;
+                for nack in stream.pending_nacks(Instant::now()) {
+                    let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&nack)
+                        .expect("RealtimeNack serialization")
+                        .into_vec();
+                    sender.push(TaggedPacket::new(REALTIME_NACK_TAG, payload));
+                }
+            }
+        });
+    }
+
+    /// Feedback on every stream that has received a frame.
+    pub fn loss_feedback(&self) -> Vec<LossFeedback> {
+        self.chains
+            .iter()
+            .filter_map(|entry| entry.loss_feedback(entry.key().stream_id))
+            .collect()
+    }
+
+    /// Records `feedback` from `source` if it's about one of our streams.
+    pub fn record_feedback(&self, source: SocketAddr, feedback: &LossFeedback) {
+        if self
+            .sent_frames(feedback.stream_id)
+            .contains(feedback.anchor_seq, feedback.anchor_timestamp)
+        {
+            self.loss_reports(feedback.stream_id)
+                .record(source, feedback.loss_rate as f64);
+        }
+    }
+
+    /// Starts the background task sending loss feedback every `interval`.
+    ///
+    /// Must be called from within a Tokio runtime context.
+    pub fn start_feedback_task(self: &Arc<Self>, sender: NetworkSender, interval: Duration) {
+        let stream = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                for feedback in stream.loss_feedback() {
+                    let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&feedback)
+                        .expect("LossFeedback serialization")
+                        .into_vec();
+                    sender.push(TaggedPacket::new(LOSS_FEEDBACK_TAG, payload));
+                }
+            }
+        });
+    }
+
     /// Removes decode chains that haven't received data within the timeout period.
     pub fn cleanup_stale(&self) {
         let now = Instant::now();
@@ -204,6 +1052,154 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
             }
             alive
         });
+        // Also picks up frame sizes learned since the last rebalance.
+        self.rebalance_memory();
+    }
+
+    /// Removes the decode chains of `stream_ids` from `source` at once,
+    /// for a sender that stopped them. Returns how many there were.
+    pub fn remove_streams(&self, source: SocketAddr, stream_ids: &[RealtimeStreamId]) -> usize {
+        let mut removed = 0;
+        for &stream_id in stream_ids {
+            let key = BufferKey {
+                source_addr: source,
+                stream_id,
+            };
+            if let Some((_, entry)) = self.chains.remove(&key) {
+                info!(
+                    "Removing decode chain for source {} stream {:?} (stopped)",
+                    source, stream_id
+                );
+                self.mixer.remove_input(entry.mixer_input_id);
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            self.rebalance_memory();
+        }
+        removed
+    }
+
+    /// Number of decode chains, one per remote stream.
+    pub fn buffer_count(&self) -> usize {
+        self.chains.len()
+    }
+
+    /// Turns down streams whose loss stayed above the threshold and
+    /// restores the ones that recovered. Does nothing without a
+    /// [`LossMuteConfig`].
+    pub fn check_loss(&self) {
+        self.check_loss_at(Instant::now());
+    }
+
+    fn check_loss_at(&self, now: Instant) {
+        let Some(config) = self.loss_mute else {
+            return;
+        };
+        for mut entry in self.chains.iter_mut() {
+            let loss_rate = entry.jitter_buffer.stats().loss_rate();
+            let was_muted = entry.loss_mute.muted;
+            let muted = entry.loss_mute.update(&config, loss_rate, now);
+            if muted == was_muted {
+                continue;
+            }
+            info!(
+                "{} stream {:?} from {} (loss {:.1}%)",
+                if muted { "Turning down" } else { "Restoring" },
+                entry.key().stream_id,
+                entry.key().source_addr,
+                loss_rate * 100.0
+            );
+            store_gain(&entry.gain, if muted { config.gain } else { 1.0 });
+        }
+    }
+
+    /// Returns the current statistics of every active decode chain.
+    pub fn stream_snapshots(&self) -> Vec<RealtimeStreamSnapshot> {
+        self.chains
+            .iter()
+            .map(|entry| {
+                let stats = entry.value().jitter_buffer.stats();
+                RealtimeStreamSnapshot {
+                    source_addr: entry.key().source_addr,
+                    stream_id: entry.key().stream_id.to_string(),
+                    packet_loss: stats.loss_rate(),
+                    target_latency: stats.target_latency(),
+                    target_latency_ms: entry.value().jitter_buffer.target_latency_ms(),
+                    audio_level: stats.audio_level(),
+                    codec: entry.value().codec.map(|codec| codec.label()),
+                    declared_bitrate: entry.value().codec.map(|codec| codec.declared_bitrate),
+                    measured_bitrate: entry.value().codec.map(|codec| codec.measured_bitrate),
+                    loss_muted: entry.value().loss_mute.muted,
+                }
+            })
+            .collect()
+    }
+
+    /// Target latency and loss averaged over all remote streams.
+    pub fn aggregate_stats(&self) -> AggregateStats {
+        let buffers: Vec<_> = self
+            .chains
+            .iter()
+            .map(|entry| entry.jitter_buffer.clone())
+            .collect();
+        AggregateStats::of(buffers.iter().map(|buffer| &**buffer))
+    }
+
+    /// Highest audio level (0-100) among recently active streams of `stream_id`.
+    pub fn peak_level(&self, stream_id: RealtimeStreamId) -> u32 {
+        let now = Instant::now();
+        self.chains
+            .iter()
+            .filter(|entry| {
+                entry.key().stream_id == stream_id
+                    && now.duration_since(entry.last_seen) < LEVEL_ACTIVITY_WINDOW
+            })
+            .map(|entry| entry.jitter_buffer.stats().audio_level())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Streams that recently delivered audio other than digital silence.
+    /// Streams in DTX or sending exact zeros still receive frames, but
+    /// don't count: silent output is expected from them.
+    pub fn audible_stream_count(&self) -> usize {
+        let now = Instant::now();
+        self.chains
+            .iter()
+            .filter(|entry| {
+                entry
+                    .jitter_buffer
+                    .stats()
+                    .last_audible()
+                    .is_some_and(|at| now.duration_since(at) < LEVEL_ACTIVITY_WINDOW)
+            })
+            .count()
+    }
+
+    /// Restarts reading in every jitter buffer from the next frame to
+    /// arrive, for when the output has gone silent despite active streams.
+    pub fn reset_pull_path(&self) {
+        for entry in self.chains.iter() {
+            entry.jitter_buffer.reset();
+        }
+    }
+
+    /// Highest packet loss rate (0.0 - 1.0) among active streams.
+    pub fn worst_loss_rate(&self) -> f64 {
+        self.chains
+            .iter()
+            .map(|entry| entry.jitter_buffer.stats().loss_rate())
+            .fold(0.0, f64::max)
+    }
+
+    /// Highest network jitter (ms) measured among active streams, or `None`
+    /// while none has been measured.
+    pub fn worst_jitter_ms(&self) -> Option<f64> {
+        self.chains
+            .iter()
+            .filter_map(|entry| entry.jitter_buffer.jitter_ms())
+            .reduce(f64::max)
     }
 
     fn has_multiple_instances(&self, ip: std::net::IpAddr, stream_id: RealtimeStreamId) -> bool {
@@ -229,22 +1225,17 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
             loop {
                 interval.tick().await;
                 stream.cleanup_stale();
+                stream.check_loss();
             }
         });
     }
 
-    pub fn start_view_task(self: &Arc<Self>, view_state: Arc<PartyViewState>) {
+    pub fn start_view_task(self: &Arc<Self>, view_state: Arc<PartyViewState>, interval: Duration) {
         let stream = self.clone();
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(Duration::from_millis(100));
-            loop {
-                interval.tick().await;
-                stream.update_view_state(&view_state);
-            }
-        });
+        spawn_stats_task(interval, move || stream.update_view_state(&view_state));
     }
 
-    fn update_view_state(&self, view_state: &PartyViewState) {
+    pub(crate) fn update_view_state(&self, view_state: &PartyViewState) {
         let mut active = HashSet::new();
 
         for entry in self.chains.iter() {
@@ -264,53 +1255,243 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
             active.insert(view_key.clone());
 
             let stats = entry.value().jitter_buffer.stats();
-            view_state.realtime_stream(view_key, stream_name).update(
+            let view = view_state.realtime_stream(view_key, stream_name);
+            view.update(
                 stats.loss_rate() as f32,
                 stats.target_latency() as u32,
+                entry.value().jitter_buffer.target_latency_ms() as f32,
                 stats.audio_level(),
+                entry.value().codec,
                 stats.recent_snapshots(),
             );
+            view.set_loss_muted(entry.value().loss_mute.muted);
         }
 
-        view_state.retain_realtime_streams(&active);
+        view_state.retain_realtime_streams(&active);
+        view_state.set_aggregate_stats(self.aggregate_stats());
+    }
+}
+
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> Default
+    for RealtimeAudioStream<Sample, CHANNELS, SAMPLE_RATE>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: AudioSample, const C: usize, const SR: u32> NetworkStream<S, C, SR>
+    for RealtimeAudioStream<S, C, SR>
+{
+    fn tags(&self) -> &'static [PacketTag] {
+        &[REALTIME_TAG, REALTIME_NACK_TAG, LOSS_FEEDBACK_TAG]
+    }
+
+    fn forget(&self, source: SocketAddr) {
+        self.remove_streams(source, &[RealtimeStreamId::Mic, RealtimeStreamId::System]);
+    }
+
+    fn handle(&self, source: SocketAddr, tag: PacketTag, bytes: &[u8]) -> anyhow::Result<()> {
+        match tag {
+            REALTIME_TAG => {
+                let frame = rkyv::from_bytes::<RealtimeFrame, rkyv::rancor::Error>(bytes)
+                    .map_err(|e| anyhow::anyhow!("RealtimeFrame deserialize: {:?}", e))?;
+                self.receive(source, frame);
+            }
+            REALTIME_NACK_TAG => {
+                let nack = rkyv::from_bytes::<RealtimeNack, rkyv::rancor::Error>(bytes)
+                    .map_err(|e| anyhow::anyhow!("RealtimeNack deserialize: {:?}", e))?;
+                if let Some(sink) = self.retransmit_sink.get() {
+                    for packet in self.answer_nack(&nack) {
+                        sink.push(packet);
+                    }
+                }
+            }
+            LOSS_FEEDBACK_TAG => {
+                let feedback = rkyv::from_bytes::<LossFeedback, rkyv::rancor::Error>(bytes)
+                    .map_err(|e| anyhow::anyhow!("LossFeedback deserialize: {:?}", e))?;
+                self.record_feedback(source, &feedback);
+            }
+            _ => unreachable!("RealtimeAudioStream received unexpected tag {tag}"),
+        }
+        Ok(())
+    }
+
+    fn start(self: Arc<Self>, ctx: NetworkStreamContext) {
+        self.start_cleanup_task();
+        if self.nack {
+            self.start_nack_task(ctx.sender.clone());
+        }
+        if let Some(interval) = self.loss_feedback {
+            self.start_feedback_task(ctx.sender.clone(), interval);
+        }
+        let _ = self.retransmit_sink.set(ctx.sender);
+        self.start_view_task(ctx.view_state, ctx.stats_interval);
+    }
+}
+
+/// One remote stream to listen to on its own.
+pub type MonitorTarget = (HostId, RealtimeStreamId);
+
+/// Speaker source that can solo a single realtime stream for debugging.
+///
+/// While `target` is set, only that stream's jitter buffer is played and
+/// `mix` is not pulled at all. This is purely local: nothing is sent, so
+/// what other participants hear is unchanged.
+pub struct StreamMonitor<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    target: Arc<Mutex<Option<MonitorTarget>>>,
+    realtime: Arc<RealtimeAudioStream<Sample, CHANNELS, SAMPLE_RATE>>,
+    mix: Arc<dyn Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+}
+
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    StreamMonitor<Sample, CHANNELS, SAMPLE_RATE>
+{
+    pub fn new(
+        target: Arc<Mutex<Option<MonitorTarget>>>,
+        realtime: Arc<RealtimeAudioStream<Sample, CHANNELS, SAMPLE_RATE>>,
+        mix: Arc<dyn Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+    ) -> Self {
+        Self {
+            target,
+            realtime,
+            mix,
+        }
     }
 }
 
-impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> Default
-    for RealtimeAudioStream<Sample, CHANNELS, SAMPLE_RATE>
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    Pullable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>
+    for StreamMonitor<Sample, CHANNELS, SAMPLE_RATE>
 {
-    fn default() -> Self {
-        Self::new()
+    fn pull(&self, len: usize) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        let target = *self.target.lock().unwrap();
+        match target {
+            Some(target) => self.realtime.pull_single(target, len),
+            None => self.mix.pull(len),
+        }
     }
 }
 
-impl<S: AudioSample, const C: usize, const SR: u32> NetworkStream<S, C, SR>
-    for RealtimeAudioStream<S, C, SR>
+/// Loss rate at which inband FEC is turned on.
+const FEC_ENABLE_LOSS: f64 = 0.05;
+/// Loss rate below which FEC is turned off again. Well under
+/// [`FEC_ENABLE_LOSS`] so the setting doesn't flap.
+const FEC_DISABLE_LOSS: f64 = 0.02;
+/// Cap on the expected loss given to the encoder.
+const FEC_MAX_LOSS_PERCENT: u8 = 30;
+/// Packets between loss checks (one second of 20 ms frames).
+const FEC_CHECK_INTERVAL: u64 = 50;
+
+/// Hysteresis deciding whether inband FEC should be on for a loss rate.
+#[derive(Debug, Default)]
+pub struct FecHysteresis {
+    enabled: bool,
+}
+
+impl FecHysteresis {
+    /// Expected loss percentage to configure, or `None` for FEC off.
+    pub fn update(&mut self, loss_rate: f64) -> Option<u8> {
+        if loss_rate >= FEC_ENABLE_LOSS {
+            self.enabled = true;
+        } else if loss_rate < FEC_DISABLE_LOSS {
+            self.enabled = false;
+        }
+        self.enabled
+            .then(|| ((loss_rate * 100.0).ceil() as u8).clamp(1, FEC_MAX_LOSS_PERCENT))
+    }
+}
+
+/// Pass-through node after an encoder that turns its inband FEC on when
+/// the loss we measure on incoming streams is high, and off on a clean
+/// network to save bandwidth.
+///
+/// Peers share the same network, so their loss is used as a proxy for the
+/// loss our own packets see.
+///
+/// Only encoders whose [`OpusSignal`](crate::audio::opus::OpusSignal)
+/// carries FEC are touched. The default CELT-only mode can't, and switching
+/// it to a SILK mode on loss would change the encoder's latency mid-stream,
+/// so such streams rely on PLC.
+pub struct FecController<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    encoder: Arc<OpusEncoder<Sample, CHANNELS, SAMPLE_RATE>>,
+    loss_rate: Box<dyn Fn() -> f64 + Send + Sync>,
+    state: Mutex<(FecHysteresis, Option<u8>)>,
+    packets: AtomicU64,
+}
+
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    FecController<Sample, CHANNELS, SAMPLE_RATE>
 {
-    fn tags(&self) -> &'static [PacketTag] {
-        &[REALTIME_TAG]
+    pub fn new(
+        encoder: Arc<OpusEncoder<Sample, CHANNELS, SAMPLE_RATE>>,
+        loss_rate: impl Fn() -> f64 + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            encoder,
+            loss_rate: Box::new(loss_rate),
+            state: Mutex::new((FecHysteresis::default(), None)),
+            packets: AtomicU64::new(0),
+        }
     }
 
-    fn handle(&self, source: SocketAddr, _tag: PacketTag, bytes: &[u8]) -> anyhow::Result<()> {
-        let frame = rkyv::from_bytes::<RealtimeFrame, rkyv::rancor::Error>(bytes)
-            .map_err(|e| anyhow::anyhow!("RealtimeFrame deserialize: {:?}", e))?;
-        self.receive(source, frame);
-        Ok(())
+    fn check(&self) {
+        if !self.encoder.config().signal_type.carries_fec() {
+            return;
+        }
+        let loss_rate = (self.loss_rate)();
+        let mut state = self.state.lock().unwrap();
+        let setting = state.0.update(loss_rate);
+        if setting == state.1 {
+            return;
+        }
+        match self.encoder.set_fec(setting) {
+            Ok(()) => {
+                info!(
+                    "Inband FEC {} (loss {:.1}%)",
+                    setting.map_or("off".to_string(), |p| format!("on, expecting {p}%")),
+                    loss_rate * 100.0
+                );
+                state.1 = setting;
+            }
+            Err(e) => warn!("Failed to update inband FEC: {:?}", e),
+        }
     }
+}
 
-    fn start(self: Arc<Self>, ctx: NetworkStreamContext) {
-        self.start_cleanup_task();
-        self.start_view_task(ctx.view_state);
+impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> crate::pipeline::Node
+    for FecController<Sample, CHANNELS, SAMPLE_RATE>
+{
+    type Input = OpusPacket;
+    type Output = OpusPacket;
+
+    fn process(&self, input: Self::Input) -> Option<Self::Output> {
+        if self.packets.fetch_add(1, Ordering::Relaxed) % FEC_CHECK_INTERVAL == 0 {
+            self.check();
+        }
+        Some(input)
     }
 }
 
 /// Packs OpusPacket into a tagged realtime frame packet.
 ///
 /// Each instance maintains its own sequence counter for independent
-/// packet ordering per stream.
+/// packet ordering per stream. Frames are stamped by a [`FrameClock`],
+/// wall clock unless set with [`with_clock`](Self::with_clock), and
+/// declare the encoder's channel count set with
+/// [`with_channels`](Self::with_channels). With a history set, every frame
+/// is also recorded there to answer NACKs.
+///
+/// Frames carry an epoch, random per packer and bumped by
+/// [`restart`](Self::restart), so receivers can tell a stream starting over
+/// from frames arriving late.
 pub struct RealtimeFramePacker {
     stream_id: RealtimeStreamId,
     sequence_number: AtomicU64,
+    epoch: AtomicU32,
+    clock: Arc<FrameClock>,
+    channels: usize,
+    history: Option<Arc<SentFrames>>,
 }
 
 impl RealtimeFramePacker {
@@ -318,8 +1499,33 @@ impl RealtimeFramePacker {
         Self {
             stream_id,
             sequence_number: AtomicU64::new(0),
+            epoch: AtomicU32::new(rand::random()),
+            clock: Arc::new(FrameClock::wall_clock()),
+            channels: 2,
+            history: None,
         }
     }
+
+    pub fn with_history(mut self, history: Arc<SentFrames>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<FrameClock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_channels(mut self, channels: usize) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Starts a new epoch, numbering frames from the start again.
+    pub fn restart(&self) {
+        self.epoch.fetch_add(1, Ordering::Relaxed);
+        self.sequence_number.store(0, Ordering::Relaxed);
+    }
 }
 
 impl crate::pipeline::Node for RealtimeFramePacker {
@@ -328,21 +1534,23 @@ impl crate::pipeline::Node for RealtimeFramePacker {
 
     fn process(&self, input: Self::Input) -> Option<Self::Output> {
         let seq = self.sequence_number.fetch_add(1, Ordering::Relaxed) + 1;
-        let frame = RealtimeFrame::new(self.stream_id, seq, input);
+        let frame = RealtimeFrame::new(self.stream_id, seq, input)
+            .with_timestamp(self.clock.now_micros())
+            .with_channels(self.channels)
+            .with_epoch(self.epoch.load(Ordering::Relaxed));
         let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&frame)
             .expect("RealtimeFrame serialization")
             .into_vec();
-        Some(TaggedPacket {
-            tag: REALTIME_TAG,
-            payload,
-        })
+        if let Some(history) = &self.history {
+            history.record(&frame, &payload);
+        }
+        Some(TaggedPacket::new(REALTIME_TAG, payload))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::audio::OpusEncoder;
     use crate::audio::frame::AudioBuffer;
     use crate::pipeline::Node;
 
@@ -351,6 +1559,8 @@ mod tests {
         let opus_packet = OpusPacket {
             data: vec![0u8; 100],
             frame_size: 960 * 2,
+            codec: AudioCodec::Opus,
+            bitrate: 0,
         };
         let frame = RealtimeFrame::new(RealtimeStreamId::Mic, 1, opus_packet);
 
@@ -367,6 +1577,8 @@ mod tests {
         let opus_packet = OpusPacket {
             data: vec![0u8; 100],
             frame_size: 960 * 2,
+            codec: AudioCodec::Opus,
+            bitrate: 0,
         };
         let tagged = packer.process(opus_packet).expect("packer produced None");
         assert_eq!(tagged.tag, crate::party::tagged_packet::REALTIME_TAG);
@@ -377,6 +1589,73 @@ mod tests {
         assert_eq!(frame.sequence_number, 1);
     }
 
+    #[test]
+    fn test_packer_restart_starts_a_new_epoch() {
+        let packer = RealtimeFramePacker::new(RealtimeStreamId::Mic);
+        let pack = || {
+            let packet = OpusPacket {
+                data: vec![0u8; 100],
+                frame_size: 960 * 2,
+                codec: AudioCodec::Opus,
+                bitrate: 0,
+            };
+            let tagged = packer.process(packet).unwrap();
+            rkyv::from_bytes::<RealtimeFrame, rkyv::rancor::Error>(&tagged.payload).unwrap()
+        };
+        let before: Vec<_> = (0..3).map(|_| pack()).collect();
+        assert_eq!(before[2].sequence_number, 3);
+        assert!(before.iter().all(|frame| frame.epoch == before[0].epoch));
+
+        packer.restart();
+        let after = pack();
+        assert_eq!(after.sequence_number, 1);
+        assert_ne!(after.epoch, before[0].epoch);
+    }
+
+    #[test]
+    fn test_raw_pcm_and_opus_share_the_receive_path() {
+        let samples: Vec<f32> = (0..1920 * 5)
+            .map(|i| (i as f32 * 0.01).sin() * 0.5)
+            .collect();
+        let roundtrip = |codec: AudioCodec| -> Vec<f32> {
+            let encoder = OpusEncoder::<f32, 2, 48000>::new()
+                .unwrap()
+                .with_codec(codec);
+            let packer = RealtimeFramePacker::new(RealtimeStreamId::Mic);
+            let decoder = RealtimeFrameDecoder::<f32, 2, 48000>::new().unwrap();
+            samples
+                .chunks(1920)
+                .flat_map(|chunk| {
+                    let input = AudioBuffer::new(chunk.to_vec()).unwrap();
+                    let tagged = packer.process(encoder.process(input).unwrap()).unwrap();
+                    let frame =
+                        rkyv::from_bytes::<RealtimeFrame, rkyv::rancor::Error>(&tagged.payload)
+                            .unwrap();
+                    assert_eq!(frame.codec, codec);
+                    let decoded = decoder.process(frame.to_realtime_opus_frame()).unwrap();
+                    decoded.samples.data().to_vec()
+                })
+                .collect()
+        };
+        let max_error = |decoded: &[f32], offset: usize| {
+            samples
+                .iter()
+                .zip(&decoded[offset..])
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f32::max)
+        };
+
+        let raw = roundtrip(AudioCodec::RawPcmI16);
+        assert_eq!(raw.len(), samples.len());
+        let raw_error = max_error(&raw, 0);
+        assert!(raw_error <= 2.0 / 32768.0, "raw PCM off by {raw_error}");
+
+        // Opus is lossy, and delayed by its lookahead.
+        let opus = roundtrip(AudioCodec::Opus);
+        assert_eq!(opus.len(), samples.len());
+        assert!(max_error(&opus, 240) > raw_error);
+    }
+
     #[test]
     fn test_opus_encode_decode_roundtrip() {
         use crate::audio::opus::OpusDecoder;
@@ -514,6 +1793,592 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_realtime_stream_zero_length_pull() {
+        use std::net::SocketAddr;
+
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let stream = RealtimeAudioStream::<f32, 2, 48000>::new();
+        let source_addr = "127.0.0.1:12345".parse::<SocketAddr>().unwrap();
+
+        assert!(stream.pull_and_mix(0).unwrap().data().is_empty());
+
+        for seq in 1..=3u64 {
+            let input = AudioBuffer::<f32, 2, 48000>::new(vec![0.1; 1920]).unwrap();
+            let opus_packet = encoder.process(input).unwrap();
+            stream.receive(
+                source_addr,
+                RealtimeFrame::new(RealtimeStreamId::Mic, seq, opus_packet),
+            );
+        }
+        let latency_before: Vec<u64> = stream
+            .chains
+            .iter()
+            .map(|entry| entry.jitter_buffer.latency())
+            .collect();
+
+        assert!(stream.pull_and_mix(0).unwrap().data().is_empty());
+
+        let latency_after: Vec<u64> = stream
+            .chains
+            .iter()
+            .map(|entry| entry.jitter_buffer.latency())
+            .collect();
+        assert_eq!(latency_before, latency_after);
+        assert_eq!(stream.pull_and_mix(1920).unwrap().data().len(), 1920);
+    }
+
+    #[test]
+    fn test_monitor_plays_only_target_stream() {
+        use std::net::SocketAddr;
+
+        let alice = "10.0.0.1:5000".parse::<SocketAddr>().unwrap();
+        let bob = "10.0.0.2:5000".parse::<SocketAddr>().unwrap();
+        let stream = Arc::new(RealtimeAudioStream::<f32, 2, 48000>::new());
+        // Receives only Alice's mic, as the expected monitor output.
+        let reference = RealtimeAudioStream::<f32, 2, 48000>::new();
+        let target = Arc::new(Mutex::new(None));
+        let monitor = StreamMonitor::new(
+            target.clone(),
+            stream.clone(),
+            stream.mixer().clone() as Arc<dyn Pullable<AudioBuffer<f32, 2, 48000>>>,
+        );
+
+        let alice_encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let alice_reference_encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let bob_encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let tone = |seq: u64, step: f32| {
+            let samples = (0..1920)
+                .map(|i| ((i as f32 + seq as f32 * 1920.0) * step).sin() * 0.3)
+                .collect();
+            AudioBuffer::<f32, 2, 48000>::new(samples).unwrap()
+        };
+
+        *target.lock().unwrap() = Some((HostId::from(alice), RealtimeStreamId::Mic));
+        for seq in 1..=10u64 {
+            let alice_frame = |encoder: &OpusEncoder<f32, 2, 48000>| {
+                let packet = encoder.process(tone(seq, 0.05)).unwrap();
+                RealtimeFrame::new(RealtimeStreamId::Mic, seq, packet)
+            };
+            stream.receive(alice, alice_frame(&alice_encoder));
+            reference.receive(alice, alice_frame(&alice_reference_encoder));
+            let bob_packet = bob_encoder.process(tone(seq, 0.2)).unwrap();
+            stream.receive(
+                bob,
+                RealtimeFrame::new(RealtimeStreamId::Mic, seq, bob_packet),
+            );
+
+            let monitored = monitor.pull(1920).unwrap();
+            let expected = reference.pull_and_mix(1920).unwrap();
+            assert_eq!(monitored.data(), expected.data());
+        }
+
+        // Monitoring a stream that doesn't exist plays nothing.
+        *target.lock().unwrap() = Some((HostId::from(bob), RealtimeStreamId::System));
+        assert!(monitor.pull(1920).is_none());
+
+        *target.lock().unwrap() = None;
+        let mixed = monitor.pull(1920).unwrap();
+        assert_eq!(mixed.data().len(), 1920);
+    }
+
+    #[test]
+    fn test_muted_host_leaves_only_the_active_source() {
+        use std::net::SocketAddr;
+
+        let alice = "10.0.0.1:5000".parse::<SocketAddr>().unwrap();
+        let bob = "10.0.0.2:5000".parse::<SocketAddr>().unwrap();
+        let volumes = HostVolumes::default();
+        volumes.set(HostId::from(bob), 0.0);
+        let stream = RealtimeAudioStream::<f32, 2, 48000>::new().with_host_volumes(volumes.clone());
+        // Receives only Alice's mic, as the expected mix.
+        let reference = RealtimeAudioStream::<f32, 2, 48000>::new();
+
+        let alice_encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let alice_reference_encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let bob_encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let tone = |seq: u64, step: f32| {
+            let samples = (0..1920)
+                .map(|i| ((i as f32 + seq as f32 * 1920.0) * step).sin() * 0.3)
+                .collect();
+            AudioBuffer::<f32, 2, 48000>::new(samples).unwrap()
+        };
+
+        for seq in 1..=10u64 {
+            let alice_frame = |encoder: &OpusEncoder<f32, 2, 48000>| {
+                let packet = encoder.process(tone(seq, 0.05)).unwrap();
+                RealtimeFrame::new(RealtimeStreamId::Mic, seq, packet)
+            };
+            stream.receive(alice, alice_frame(&alice_encoder));
+            reference.receive(alice, alice_frame(&alice_reference_encoder));
+            let bob_packet = bob_encoder.process(tone(seq, 0.2)).unwrap();
+            stream.receive(
+                bob,
+                RealtimeFrame::new(RealtimeStreamId::Mic, seq, bob_packet),
+            );
+
+            let mixed = stream.pull_and_mix(1920).unwrap();
+            let expected = reference.pull_and_mix(1920).unwrap();
+            assert_eq!(mixed.data(), expected.data());
+        }
+        assert_eq!(volumes.get(HostId::from(alice)), 1.0);
+    }
+
+    #[test]
+    fn test_aggregate_stats_average_the_buffers() {
+        use crate::audio::frame::AudioFrame;
+
+        assert_eq!(
+            AggregateStats::of::<f32, 2, 48000>([]),
+            AggregateStats::default()
+        );
+
+        let clean = JitterBuffer::<f32, 2, 48000>::new(JITTER_BUFFER_CAPACITY);
+        let lossy = JitterBuffer::<f32, 2, 48000>::new(JITTER_BUFFER_CAPACITY);
+        for seq in 1..=40u64 {
+            // 20 ms frames.
+            let frame = || AudioFrame::new(seq, vec![0.1f32; 1920]).unwrap();
+            clean.push(frame());
+            if seq % 4 != 0 {
+                lossy.push(frame());
+            }
+            clean.pull(1920);
+            lossy.pull(1920);
+        }
+
+        let (clean_loss, lossy_loss) = (clean.stats().loss_rate(), lossy.stats().loss_rate());
+        assert!(lossy_loss > clean_loss);
+        let latency_frames = clean.stats().target_latency() + lossy.stats().target_latency();
+
+        let stats = AggregateStats::of([&clean, &lossy]);
+        assert_eq!(stats.active_source_count, 2);
+        assert!((stats.avg_loss_rate - (clean_loss + lossy_loss) / 2.0).abs() < 1e-12);
+        assert!((stats.avg_target_latency_ms - latency_frames as f64 * 20.0 / 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_declared_codec_surfaces_in_snapshot() {
+        use std::net::SocketAddr;
+
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let stream = RealtimeAudioStream::<f32, 2, 48000>::new();
+        let source_addr = "127.0.0.1:12345".parse::<SocketAddr>().unwrap();
+
+        for seq in 1..=5u64 {
+            let samples = (0..1920).map(|i| (i as f32 * 0.05).sin() * 0.3).collect();
+            let input = AudioBuffer::<f32, 2, 48000>::new(samples).unwrap();
+            let opus_packet = encoder.process(input).unwrap();
+            let mut frame = RealtimeFrame::new(RealtimeStreamId::Mic, seq, opus_packet);
+            frame.bitrate = 96_000;
+            stream.receive(source_addr, frame);
+        }
+
+        let snapshots = stream.stream_snapshots();
+        assert_eq!(snapshots.len(), 1);
+        let snapshot = &snapshots[0];
+        assert_eq!(snapshot.codec.as_deref(), Some("Opus 96k"));
+        assert_eq!(snapshot.declared_bitrate, Some(96_000));
+        assert!(snapshot.measured_bitrate.is_some_and(|bitrate| bitrate > 0));
+    }
+
+    #[test]
+    fn test_frame_declares_the_encoders_current_bitrate() {
+        use crate::audio::OpusEncoderConfig;
+
+        let (name, config) = OpusEncoderConfig::PRESETS[1];
+        assert_eq!(name, "Voice (24 kbps)");
+        let encoder = OpusEncoder::<f32, 2, 48000>::with_config(config).unwrap();
+        let packer = RealtimeFramePacker::new(RealtimeStreamId::Mic);
+        let send = || {
+            let samples = (0..1920).map(|i| (i as f32 * 0.05).sin() * 0.3).collect();
+            let input = AudioBuffer::<f32, 2, 48000>::new(samples).unwrap();
+            let tagged = packer.process(encoder.process(input).unwrap()).unwrap();
+            rkyv::from_bytes::<RealtimeFrame, rkyv::rancor::Error>(&tagged.payload)
+                .unwrap()
+                .bitrate
+        };
+
+        assert_eq!(send(), 24_000);
+        // A bitrate controller shedding bitrate shows up in the next frame.
+        encoder.set_bitrate(16_000).unwrap();
+        assert_eq!(send(), 16_000);
+    }
+
+    #[test]
+    fn test_fec_toggles_with_hysteresis() {
+        let mut fec = FecHysteresis::default();
+        assert_eq!(fec.update(0.01), None);
+        assert_eq!(fec.update(0.04), None);
+
+        // Crossing the threshold turns FEC on, tuned to the loss.
+        assert_eq!(fec.update(0.08), Some(8));
+        // Dipping just below it keeps FEC on.
+        assert_eq!(fec.update(0.03), Some(3));
+        // Well below it turns FEC off.
+        assert_eq!(fec.update(0.01), None);
+        assert_eq!(fec.update(0.03), None);
+
+        assert_eq!(fec.update(0.9), Some(FEC_MAX_LOSS_PERCENT));
+    }
+
+    #[test]
+    fn test_fec_is_left_off_in_celt_mode() {
+        use crate::audio::OpusEncoderConfig;
+
+        let run = |config: OpusEncoderConfig| {
+            let encoder = Arc::new(OpusEncoder::<f32, 2, 48000>::with_config(config).unwrap());
+            let controller = FecController::new(encoder.clone(), || 0.25);
+            let packet = OpusPacket {
+                data: vec![0; 8],
+                frame_size: 960,
+                codec: AudioCodec::Opus,
+                bitrate: 0,
+            };
+            controller.process(packet);
+            encoder.fec()
+        };
+        assert_eq!(run(OpusEncoderConfig::default()), None);
+        assert_eq!(run(OpusEncoderConfig::PRESETS[1].1), Some(25));
+    }
+
+    #[test]
+    fn test_sustained_loss_turns_stream_down_until_recovered() {
+        let config = LossMuteConfig::default();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut mute = LossMute::default();
+
+        // A short burst of loss is tolerated.
+        assert!(!mute.update(&config, 0.5, at(0)));
+        assert!(!mute.update(&config, 0.01, at(1)));
+        assert!(!mute.update(&config, 0.5, at(2)));
+        assert!(!mute.update(&config, 0.5, at(4)));
+
+        // Loss that stays high for the sustain period turns it down.
+        assert!(mute.update(&config, 0.4, at(5)));
+        // Improving, but not yet recovered.
+        assert!(mute.update(&config, 0.1, at(6)));
+        // Recovered.
+        assert!(!mute.update(&config, 0.02, at(7)));
+
+        // Recovery restarts the sustain period.
+        assert!(!mute.update(&config, 0.5, at(8)));
+        assert!(!mute.update(&config, 0.5, at(10)));
+        assert!(mute.update(&config, 0.5, at(11)));
+    }
+
+    #[test]
+    fn test_dtx_gap_is_comfort_noise_and_loss_is_silence() {
+        use std::net::SocketAddr;
+
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let stream = RealtimeAudioStream::<f32, 2, 48000>::new();
+        let source_addr = "127.0.0.1:12345".parse::<SocketAddr>().unwrap();
+        let rms = |data: &[f32]| {
+            (data.iter().map(|s| (s * s) as f64).sum::<f64>() / data.len() as f64).sqrt()
+        };
+
+        let send = |seq: u64| {
+            let samples: Vec<f32> = (0..1920)
+                .map(|i| ((i as f32 + seq as f32 * 1920.0) * 0.05).sin() * 0.3)
+                .collect();
+            let input = AudioBuffer::<f32, 2, 48000>::new(samples).unwrap();
+            let opus_packet = encoder.process(input).unwrap();
+            stream.receive(
+                source_addr,
+                RealtimeFrame::new(RealtimeStreamId::Mic, seq, opus_packet),
+            );
+        };
+        for seq in 1..=3 {
+            send(seq);
+            stream.pull_and_mix(1920).unwrap();
+        }
+
+        // Sender went quiet: seq 4 arrives as a DTX frame.
+        stream.receive(
+            source_addr,
+            RealtimeFrame::dtx(RealtimeStreamId::Mic, 4, 1920),
+        );
+        let dtx = stream.pull_and_mix(1920).unwrap().into_inner();
+        let dtx_rms = rms(&dtx);
+        assert!(dtx_rms > 0.0, "DTX gap should not be digital silence");
+        assert!(dtx_rms < 0.01, "comfort noise too loud: rms {}", dtx_rms);
+
+        // Seq 5 is lost: the jitter buffer conceals it with silence.
+        send(6);
+        let lost = stream.pull_and_mix(1920).unwrap().into_inner();
+        assert!(
+            lost.iter().all(|&s| s == 0.0),
+            "lost frame should be silent"
+        );
+
+        let resumed = stream.pull_and_mix(1920).unwrap().into_inner();
+        assert!(rms(&resumed) > 0.05, "audio after the loss should play");
+    }
+
+    #[test]
+    fn test_silent_stream_does_not_trip_the_watchdog() {
+        use std::net::SocketAddr;
+        use std::sync::atomic::AtomicUsize;
+
+        use crate::party::combinator::SilenceWatchdog;
+
+        let stream =
+            Arc::new(RealtimeAudioStream::<f32, 2, 48000>::new().with_dtx_comfort_noise(false));
+        let encoder = OpusEncoder::<f32, 2, 48000>::new()
+            .unwrap()
+            .with_codec(AudioCodec::RawPcmI16);
+        let source_addr = "127.0.0.1:12345".parse::<SocketAddr>().unwrap();
+        let recoveries = Arc::new(AtomicUsize::new(0));
+        let watchdog = SilenceWatchdog::new(
+            stream.mixer().clone() as Arc<dyn Pullable<AudioBuffer<f32, 2, 48000>>>,
+            Duration::from_millis(100),
+            {
+                let stream = stream.clone();
+                move || stream.audible_stream_count()
+            },
+            {
+                let recoveries = recoveries.clone();
+                move || {
+                    recoveries.fetch_add(1, Ordering::Relaxed);
+                }
+            },
+        );
+
+        // A mic delivering exact zeros, then a DTX gap: the stream keeps
+        // receiving frames but has nothing to play.
+        for seq in 1..=20u64 {
+            let frame = if seq <= 10 {
+                let zeros = AudioBuffer::new(vec![0.0; 1920]).unwrap();
+                RealtimeFrame::new(RealtimeStreamId::Mic, seq, encoder.process(zeros).unwrap())
+            } else {
+                RealtimeFrame::dtx(RealtimeStreamId::Mic, seq, 1920)
+            };
+            stream.receive(source_addr, frame);
+            watchdog.pull(1920);
+        }
+        assert_eq!(stream.stream_snapshots().len(), 1);
+        assert_eq!(stream.audible_stream_count(), 0);
+        assert_eq!(recoveries.load(Ordering::Relaxed), 0);
+
+        let tone = (0..1920).map(|i| (i as f32 * 0.05).sin() * 0.3).collect();
+        let frame = RealtimeFrame::new(
+            RealtimeStreamId::Mic,
+            21,
+            encoder.process(AudioBuffer::new(tone).unwrap()).unwrap(),
+        );
+        stream.receive(source_addr, frame);
+        assert_eq!(stream.audible_stream_count(), 1);
+    }
+
+    #[test]
+    fn test_nack_fills_hole_before_it_is_played() {
+        use std::net::SocketAddr;
+
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let sender = RealtimeAudioStream::<f32, 2, 48000>::new();
+        let packer = RealtimeFramePacker::new(RealtimeStreamId::Mic)
+            .with_history(sender.sent_frames(RealtimeStreamId::Mic));
+        let receiver = RealtimeAudioStream::<f32, 2, 48000>::new().with_nack(true);
+        let source_addr = "127.0.0.1:12345".parse::<SocketAddr>().unwrap();
+
+        let packets: Vec<TaggedPacket> = (1..=4u64)
+            .map(|seq| {
+                let samples: Vec<f32> = (0..1920)
+                    .map(|i| ((i as f32 + seq as f32 * 1920.0) * 0.05).sin() * 0.3)
+                    .collect();
+                let input = AudioBuffer::<f32, 2, 48000>::new(samples).unwrap();
+                packer.process(encoder.process(input).unwrap()).unwrap()
+            })
+            .collect();
+        let deliver = |packet: &TaggedPacket| {
+            NetworkStream::handle(&receiver, source_addr, packet.tag, &packet.payload).unwrap();
+        };
+
+        // Frame 3 is lost on the way.
+        for packet in [&packets[0], &packets[1], &packets[3]] {
+            deliver(packet);
+        }
+        let start = Instant::now();
+        assert!(
+            receiver.pending_nacks(start).is_empty(),
+            "frame 3 may only be reordered"
+        );
+        let asked_at = start + NACK_REORDER_WINDOW;
+        let nacks = receiver.pending_nacks(asked_at);
+        assert_eq!(nacks.len(), 1);
+        assert_eq!(nacks[0].seqs, [3]);
+        assert!(
+            receiver.pending_nacks(asked_at).is_empty(),
+            "hole asked for twice at once"
+        );
+        let retried = receiver.pending_nacks(asked_at + NACK_RETRY_INTERVAL);
+        assert_eq!(retried.len(), 1, "unanswered NACK is retried");
+        assert_eq!(retried[0].seqs, [3]);
+        assert!(
+            receiver.pending_nacks(start + NACK_TIMEOUT).is_empty(),
+            "hole given up on after the timeout"
+        );
+
+        // Only the instance that sent the anchor frame answers.
+        let stranger = RealtimeAudioStream::<f32, 2, 48000>::new();
+        assert!(stranger.answer_nack(&nacks[0]).is_empty());
+        let mut wrong_anchor = nacks[0].clone();
+        wrong_anchor.anchor_timestamp += 1;
+        assert!(sender.answer_nack(&wrong_anchor).is_empty());
+
+        let resent = sender.answer_nack(&nacks[0]);
+        assert_eq!(resent.len(), 1);
+        resent.iter().for_each(deliver);
+
+        for seq in 1..=4 {
+            let pulled = receiver.pull_and_mix(1920).unwrap().into_inner();
+            assert!(
+                pulled.iter().any(|&s| s != 0.0),
+                "frame {seq} played as silence"
+            );
+        }
+        let snapshot = &receiver.stream_snapshots()[0];
+        assert_eq!(snapshot.packet_loss, 0.0);
+    }
+
+    #[test]
+    fn test_reordered_frame_is_not_nacked() {
+        use std::net::SocketAddr;
+
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let packer = RealtimeFramePacker::new(RealtimeStreamId::Mic);
+        let receiver = RealtimeAudioStream::<f32, 2, 48000>::new().with_nack(true);
+        let source_addr = "127.0.0.1:12345".parse::<SocketAddr>().unwrap();
+
+        let packets: Vec<TaggedPacket> = (1..=4u64)
+            .map(|_| {
+                let input = AudioBuffer::<f32, 2, 48000>::new(vec![0.1; 1920]).unwrap();
+                packer.process(encoder.process(input).unwrap()).unwrap()
+            })
+            .collect();
+        let deliver = |packet: &TaggedPacket| {
+            NetworkStream::handle(&receiver, source_addr, packet.tag, &packet.payload).unwrap();
+        };
+
+        // Frame 3 arrives after frame 4, within the reorder window.
+        let start = Instant::now();
+        for packet in [&packets[0], &packets[1], &packets[3]] {
+            deliver(packet);
+        }
+        assert!(receiver.pending_nacks(start).is_empty());
+        deliver(&packets[2]);
+        assert!(
+            receiver
+                .pending_nacks(start + NACK_REORDER_WINDOW)
+                .is_empty()
+        );
+        assert!(receiver.pending_nacks(start + NACK_TIMEOUT / 2).is_empty());
+    }
+
+    #[test]
+    fn test_loss_feedback_reaches_only_its_sender() {
+        use std::net::SocketAddr;
+
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let sender = RealtimeAudioStream::<f32, 2, 48000>::new();
+        let packer = RealtimeFramePacker::new(RealtimeStreamId::Mic)
+            .with_history(sender.sent_frames(RealtimeStreamId::Mic));
+        let receiver = RealtimeAudioStream::<f32, 2, 48000>::new();
+        let sender_addr = "127.0.0.1:12345".parse::<SocketAddr>().unwrap();
+        let receiver_addr = "127.0.0.2:12345".parse::<SocketAddr>().unwrap();
+        assert!(receiver.loss_feedback().is_empty());
+
+        // The packer numbers frames from 1; frame 3 is lost on the way.
+        for seq in 1..=4 {
+            let input = AudioBuffer::<f32, 2, 48000>::new(vec![0.1; 1920]).unwrap();
+            let packet = packer.process(encoder.process(input).unwrap()).unwrap();
+            if seq != 3 {
+                NetworkStream::handle(&receiver, sender_addr, packet.tag, &packet.payload).unwrap();
+            }
+        }
+        for _ in 0..4 {
+            receiver.pull_and_mix(1920);
+        }
+
+        let feedback = receiver.loss_feedback();
+        assert_eq!(feedback.len(), 1);
+        assert_eq!(feedback[0].stream_id, RealtimeStreamId::Mic);
+        let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&feedback[0]).unwrap();
+
+        let stranger = RealtimeAudioStream::<f32, 2, 48000>::new();
+        NetworkStream::handle(&stranger, receiver_addr, LOSS_FEEDBACK_TAG, &payload).unwrap();
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            stranger.loss_reports(RealtimeStreamId::Mic).worst(timeout),
+            None
+        );
+
+        NetworkStream::handle(&sender, receiver_addr, LOSS_FEEDBACK_TAG, &payload).unwrap();
+        let reported = sender.loss_reports(RealtimeStreamId::Mic).worst(timeout);
+        assert_eq!(reported, Some(feedback[0].loss_rate as f64));
+        assert_eq!(
+            sender.loss_reports(RealtimeStreamId::System).worst(timeout),
+            None
+        );
+    }
+
+    #[test]
+    fn test_memory_budget_shrinks_buffers_with_many_streams() {
+        use std::net::SocketAddr;
+
+        const BUDGET: usize = 1024 * 1024;
+        let encoder = OpusEncoder::<f32, 2, 48000>::new().unwrap();
+        let stream = RealtimeAudioStream::<f32, 2, 48000>::new().with_memory_budget(Some(BUDGET));
+
+        let send_frames = |source_addr: SocketAddr| {
+            for seq in 1..=5u64 {
+                let input = AudioBuffer::<f32, 2, 48000>::new(vec![0.1; 1920]).unwrap();
+                let opus_packet = encoder.process(input).unwrap();
+                stream.receive(
+                    source_addr,
+                    RealtimeFrame::new(RealtimeStreamId::Mic, seq, opus_packet),
+                );
+            }
+        };
+
+        send_frames("10.0.0.1:5000".parse().unwrap());
+        let full_history = stream
+            .chains
+            .iter()
+            .next()
+            .unwrap()
+            .jitter_buffer
+            .stats()
+            .snapshot_limit();
+
+        for host in 2..=12 {
+            send_frames(format!("10.0.0.{host}:5000").parse().unwrap());
+        }
+        for _ in 0..300 {
+            stream.pull_and_mix(480);
+        }
+        stream.cleanup_stale();
+
+        assert_eq!(stream.chains.len(), 12);
+        for entry in stream.chains.iter() {
+            let history = entry.jitter_buffer.stats().snapshot_limit();
+            assert!(
+                history < full_history,
+                "history {} should shrink below {}",
+                history,
+                full_history
+            );
+            assert!(entry.jitter_buffer.stats().recent_snapshots().len() <= history);
+        }
+        assert!(
+            stream.memory_usage() <= BUDGET,
+            "memory usage {} exceeds budget {}",
+            stream.memory_usage(),
+            BUDGET
+        );
+    }
+
     #[test]
     #[ignore]
     fn test_local_simulation_to_wav() {
@@ -0,0 +1,284 @@
+//! Device and network settings, and whether to check the mic on startup,
+//! remembered across launches.
+//!
+//! What's chosen under Device Settings is saved as JSON in the platform
+//! config directory whenever it's applied, and laid over the defaults on
+//! the next start. Devices are saved by id and by name: cpal's ids aren't
+//! stable across reboots on every platform, so a saved id that no longer
+//! resolves falls back to the first device with the saved name, and to the
+//! system default if neither is found.
+
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{Device, DeviceId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::party::PartyConfig;
+use crate::party::config::{Migration, from_versioned_json, to_versioned_json};
+
+/// Format changes of the settings file, oldest first.
+const MIGRATIONS: [Migration; 1] = [add_mic_check];
+
+/// Format 1 to 2: whether to check the mic on startup is remembered, and
+/// was always on before.
+fn add_mic_check(settings: &mut Value) -> Result<()> {
+    let Some(settings) = settings.as_object_mut() else {
+        bail!("Format 1 settings aren't an object");
+    };
+    settings.insert("mic_check".into(), true.into());
+    Ok(())
+}
+
+/// A device as saved: the key it's listed under and its name.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SavedDevice {
+    /// The device id as shown by `Debug`, the key Device Settings lists
+    /// devices under.
+    pub id: String,
+    pub name: String,
+}
+
+/// The settings that are remembered.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SavedSettings {
+    pub input_device: Option<SavedDevice>,
+    pub output_device: Option<SavedDevice>,
+    pub ipv6: bool,
+    pub send_interface_index: Option<u32>,
+    pub multicast_group: Option<IpAddr>,
+    /// Whether to check the mic on startup.
+    pub mic_check: bool,
+}
+
+/// Where the settings are saved: the platform config directory, falling
+/// back to the working directory.
+pub fn default_path() -> PathBuf {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default();
+    config_dir.join("wifi-party").join("settings.json")
+}
+
+/// The key and name a device is saved under.
+#[allow(deprecated)]
+fn saved_device(device: &Device) -> Option<(SavedDevice, DeviceId)> {
+    let id = device.id().ok()?;
+    let name = device.description().ok()?.name().to_string();
+    Some((
+        SavedDevice {
+            id: format!("{:?}", id),
+            name,
+        },
+        id,
+    ))
+}
+
+/// The device to open for `saved` among `devices`: the one with its id, or
+/// else the first with its name.
+pub fn resolve_device<T>(
+    saved: &SavedDevice,
+    devices: impl IntoIterator<Item = (SavedDevice, T)>,
+) -> Option<T> {
+    let mut by_name = None;
+    for (device, value) in devices {
+        if device.id == saved.id {
+            return Some(value);
+        }
+        if by_name.is_none() && device.name == saved.name {
+            by_name = Some(value);
+        }
+    }
+    by_name
+}
+
+/// The input (or output) devices there are now, as saved.
+fn current_devices(input: bool) -> Vec<(SavedDevice, DeviceId)> {
+    let host = cpal::default_host();
+    let devices = if input {
+        host.input_devices().map(|d| d.collect::<Vec<_>>())
+    } else {
+        host.output_devices().map(|d| d.collect::<Vec<_>>())
+    };
+    devices
+        .unwrap_or_default()
+        .iter()
+        .filter_map(saved_device)
+        .collect()
+}
+
+/// How `id` is saved, looked up among the current devices for its name.
+fn save_device(id: &DeviceId, input: bool) -> Option<SavedDevice> {
+    let key = format!("{:?}", id);
+    current_devices(input)
+        .into_iter()
+        .map(|(saved, _)| saved)
+        .find(|saved| saved.id == key)
+}
+
+impl SavedSettings {
+    /// The remembered part of `config`.
+    pub fn from_config(config: &PartyConfig) -> Self {
+        Self {
+            input_device: config
+                .input_device_id
+                .as_ref()
+                .and_then(|id| save_device(id, true)),
+            output_device: config
+                .output_device_id
+                .as_ref()
+                .and_then(|id| save_device(id, false)),
+            ipv6: config.ipv6,
+            send_interface_index: config.send_interface_index,
+            multicast_group: config.multicast.group,
+            mic_check: config.mic_check.enabled,
+        }
+    }
+
+    /// Lays the settings over `config`, opening the devices `resolve`
+    /// finds for the saved ones. `resolve` is told whether it's the input.
+    pub fn apply_to(
+        &self,
+        config: &mut PartyConfig,
+        resolve: impl Fn(&SavedDevice, bool) -> Option<DeviceId>,
+    ) {
+        let open = |saved: &Option<SavedDevice>, input: bool| {
+            let saved = saved.as_ref()?;
+            let id = resolve(saved, input);
+            if id.is_none() {
+                warn!("Saved device {:?} not found, using the default", saved.name);
+            }
+            id
+        };
+        config.input_device_id = open(&self.input_device, true);
+        config.output_device_id = open(&self.output_device, false);
+        config.ipv6 = self.ipv6;
+        config.send_interface_index = self.send_interface_index;
+        config.multicast.group = self.multicast_group;
+        config.mic_check.enabled = self.mic_check;
+    }
+
+    /// Writes the settings to `file`, replacing what was saved before.
+    pub fn save(&self, file: &Path) -> Result<()> {
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let json = to_versioned_json(self, &MIGRATIONS)?;
+        std::fs::write(file, json).with_context(|| format!("Failed to write {}", file.display()))
+    }
+
+    /// Reads the settings saved in `file`, or `None` if nothing was saved.
+    pub fn load(file: &Path) -> Result<Option<Self>> {
+        let json = match std::fs::read_to_string(file) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", file.display())),
+        };
+        from_versioned_json(&json, "settings", &MIGRATIONS)
+            .map(Some)
+            .with_context(|| format!("Malformed {}", file.display()))
+    }
+}
+
+/// Lays the settings saved at [`default_path`] over `config`, resolving
+/// the saved devices among the current ones. Logs and keeps `config` as
+/// it is if they can't be read.
+pub fn restore(config: &mut PartyConfig) {
+    let file = default_path();
+    match SavedSettings::load(&file) {
+        Ok(Some(settings)) => {
+            info!("Restoring settings from {}", file.display());
+            settings.apply_to(config, |saved, input| {
+                resolve_device(saved, current_devices(input))
+            });
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Ignoring saved settings: {:?}", e),
+    }
+}
+
+/// Saves the remembered part of `config` at [`default_path`], logging
+/// failures.
+pub fn remember(config: &PartyConfig) {
+    if let Err(e) = SavedSettings::from_config(config).save(&default_path()) {
+        warn!("Failed to save settings: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(id: &str, name: &str) -> SavedDevice {
+        SavedDevice {
+            id: id.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = SavedSettings {
+            input_device: Some(device("DeviceId(CoreAudio, \"mic-1\")", "USB Mic")),
+            output_device: None,
+            ipv6: true,
+            send_interface_index: Some(7),
+            multicast_group: Some("ff02::1234".parse().unwrap()),
+            mic_check: false,
+        };
+        let dir =
+            std::env::temp_dir().join(format!("wifi-party-settings-{}", rand::random::<u64>()));
+        let file = dir.join("settings.json");
+
+        assert_eq!(SavedSettings::load(&file).unwrap(), None);
+        settings.save(&file).unwrap();
+        let loaded = SavedSettings::load(&file).unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded, settings);
+
+        let mut config = PartyConfig::default();
+        loaded.apply_to(&mut config, |_, _| None);
+        assert!(config.ipv6);
+        assert_eq!(config.send_interface_index, Some(7));
+        assert_eq!(config.multicast.group, settings.multicast_group);
+        assert!(!config.mic_check.enabled);
+        assert_eq!(config.input_device_id, None, "missing device falls back");
+    }
+
+    #[test]
+    fn device_is_found_by_name_when_its_id_changed() {
+        let current = || {
+            vec![
+                (device("id-3", "Built-in Mic"), 3),
+                (device("id-4", "USB Mic"), 4),
+                (device("id-5", "USB Mic"), 5),
+            ]
+        };
+
+        assert_eq!(
+            resolve_device(&device("id-5", "USB Mic"), current()),
+            Some(5)
+        );
+        assert_eq!(
+            resolve_device(&device("id-1", "USB Mic"), current()),
+            Some(4),
+            "first one by name after a reboot"
+        );
+        assert_eq!(resolve_device(&device("id-1", "Headset"), current()), None);
+    }
+
+    #[test]
+    fn format_1_settings_keep_checking_the_mic() {
+        let v1 = r#"{ "input_device": null, "output_device": null, "ipv6": false,
+            "send_interface_index": null, "multicast_group": null }"#;
+        let settings: SavedSettings = from_versioned_json(v1, "settings", &MIGRATIONS).unwrap();
+        assert!(settings.mic_check);
+    }
+}
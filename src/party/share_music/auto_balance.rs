@@ -0,0 +1,211 @@
+//! Automatic level matching of synced music against the mics.
+//!
+//! Mic and music levels are independent, so the voice either drowns in the
+//! music or towers over it. With [`AutoBalance`] on, the music mix is kept a
+//! set number of dB below the mics by comparing their levels and slowly
+//! adjusting the music gain. Unlike the [`Ducker`](super::Ducker), which
+//! reacts to every phrase, this follows the overall levels over seconds and
+//! holds its gain while either side is silent.
+
+use std::sync::{Arc, Mutex};
+
+use crate::audio::AudioSample;
+
+/// Mic level (0-100) below which nobody counts as singing.
+const MIN_MIC_LEVEL: u32 = 1;
+/// Music RMS below which the music counts as silent (-60 dBFS).
+const MIN_MUSIC_RMS: f64 = 0.001;
+
+/// User-tunable balance parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoBalanceSettings {
+    pub enabled: bool,
+    /// How far the mics should sit above the music, in dB.
+    pub mic_above_music_db: f32,
+    /// Most the music gain may move from unity either way, in dB.
+    pub max_adjust_db: f32,
+    /// How fast the music gain follows, in dB per second.
+    pub rate_db_per_sec: f32,
+}
+
+impl Default for AutoBalanceSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mic_above_music_db: 6.0,
+            max_adjust_db: 12.0,
+            rate_db_per_sec: 3.0,
+        }
+    }
+}
+
+/// Scales the synced-music mix to keep it balanced against the mic level.
+pub struct AutoBalance {
+    settings: Arc<Mutex<AutoBalanceSettings>>,
+    mic_level_fn: Box<dyn Fn() -> u32 + Send + Sync>,
+    gain_db: Mutex<f64>,
+}
+
+impl AutoBalance {
+    pub fn new<F>(settings: Arc<Mutex<AutoBalanceSettings>>, mic_level_fn: F) -> Self
+    where
+        F: Fn() -> u32 + Send + Sync + 'static,
+    {
+        Self {
+            settings,
+            mic_level_fn: Box::new(mic_level_fn),
+            gain_db: Mutex::new(0.0),
+        }
+    }
+
+    /// Current music gain in dB (0 = unchanged).
+    pub fn gain_db(&self) -> f64 {
+        *self.gain_db.lock().unwrap()
+    }
+
+    /// Music gain that puts the mics `mic_above_music_db` above the music,
+    /// or `None` while either is silent.
+    fn target_db(settings: &AutoBalanceSettings, mic_level: u32, music_rms: f64) -> Option<f64> {
+        if mic_level < MIN_MIC_LEVEL || music_rms < MIN_MUSIC_RMS {
+            return None;
+        }
+        let mic_db = 20.0 * (mic_level as f64 / 100.0).log10();
+        let music_db = 20.0 * music_rms.log10();
+        let limit = settings.max_adjust_db.max(0.0) as f64;
+        Some((mic_db - settings.mic_above_music_db as f64 - music_db).clamp(-limit, limit))
+    }
+
+    /// Measures the music in `mixed`, moves the gain toward the balance
+    /// point and scales the samples in place, ramping across the block.
+    ///
+    /// `mixed` holds interleaved accumulator samples of `source_count`
+    /// streams as produced by the synced stream mixer.
+    pub fn process<Sample: AudioSample>(
+        &self,
+        mixed: &mut [i64],
+        source_count: usize,
+        channels: usize,
+        sample_rate: u32,
+    ) {
+        if mixed.is_empty() {
+            return;
+        }
+        let settings = *self.settings.lock().unwrap();
+        let mut gain_db = self.gain_db.lock().unwrap();
+        let start_db = *gain_db;
+
+        let target = if settings.enabled {
+            let sum_sq: f64 = mixed
+                .iter()
+                .map(|&s| {
+                    let v = Sample::from_i64_mixed(s, source_count.max(1)).to_f64_normalized();
+                    v * v
+                })
+                .sum();
+            let music_rms = (sum_sq / mixed.len() as f64).sqrt();
+            Self::target_db(&settings, (self.mic_level_fn)(), music_rms)
+        } else {
+            // Turned off: ease back to unity.
+            Some(0.0)
+        };
+
+        if let Some(target) = target {
+            let seconds = (mixed.len() / channels) as f64 / sample_rate as f64;
+            let step = settings.rate_db_per_sec.max(0.0) as f64 * seconds;
+            *gain_db = if *gain_db > target {
+                (*gain_db - step).max(target)
+            } else {
+                (*gain_db + step).min(target)
+            };
+        }
+
+        if start_db == 0.0 && *gain_db == 0.0 {
+            return;
+        }
+        let start = 10f64.powf(start_db / 20.0);
+        let end = 10f64.powf(*gain_db / 20.0);
+        let frames = mixed.len() / channels;
+        for (frame, samples) in mixed.chunks_mut(channels).enumerate() {
+            let gain = start + (end - start) * (frame + 1) as f64 / frames.max(1) as f64;
+            for sample in samples {
+                *sample = (*sample as f64 * gain) as i64;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    const SR: u32 = 48000;
+
+    /// RMS of a block of f32 accumulator samples from one source.
+    fn rms(block: &[i64]) -> f64 {
+        let sum_sq: f64 = block
+            .iter()
+            .map(|&s| f32::from_i64_mixed(s, 1) as f64)
+            .map(|v| v * v)
+            .sum();
+        (sum_sq / block.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn loud_music_is_lowered_toward_offset_below_quiet_mic() {
+        let settings = Arc::new(Mutex::new(AutoBalanceSettings {
+            enabled: true,
+            mic_above_music_db: 6.0,
+            max_adjust_db: 30.0,
+            rate_db_per_sec: 6.0,
+        }));
+        // Mic at RMS 0.05 (-26 dBFS), music at 0.5 (-6 dBFS): the music has
+        // to come down 26 dB to sit 6 dB under the mic.
+        let mic_level = Arc::new(AtomicU32::new(5));
+        let level = mic_level.clone();
+        let balance = AutoBalance::new(settings.clone(), move || level.load(Ordering::Relaxed));
+        let loud_music = || vec![0.5f32.to_i64_for_mix(); 480 * 2];
+
+        // One second moves it by the rate, not all the way.
+        for _ in 0..100 {
+            balance.process::<f32>(&mut loud_music(), 1, 2, SR);
+        }
+        assert!((balance.gain_db() + 6.0).abs() < 1e-6);
+
+        let mut last = balance.gain_db();
+        for _ in 0..500 {
+            balance.process::<f32>(&mut loud_music(), 1, 2, SR);
+            assert!(balance.gain_db() <= last, "gain should only go down");
+            last = balance.gain_db();
+        }
+        assert!((balance.gain_db() + 26.0).abs() < 1e-6);
+        let mut block = loud_music();
+        balance.process::<f32>(&mut block, 1, 2, SR);
+        let music_db = 20.0 * rms(&block).log10();
+        let mic_db = 20.0 * 0.05f64.log10();
+        assert!(
+            (mic_db - music_db - 6.0).abs() < 0.1,
+            "mic should sit 6 dB above the music: {mic_db:.1} vs {music_db:.1}"
+        );
+
+        // Nobody singing: the gain holds instead of chasing silence.
+        mic_level.store(0, Ordering::Relaxed);
+        balance.process::<f32>(&mut loud_music(), 1, 2, SR);
+        assert!((balance.gain_db() + 26.0).abs() < 1e-6);
+
+        // The adjustment is capped.
+        settings.lock().unwrap().max_adjust_db = 12.0;
+        mic_level.store(5, Ordering::Relaxed);
+        for _ in 0..100 {
+            balance.process::<f32>(&mut loud_music(), 1, 2, SR);
+        }
+        assert!(
+            (balance.gain_db() + 20.0).abs() < 1e-6,
+            "recovers at the rate"
+        );
+        for _ in 0..200 {
+            balance.process::<f32>(&mut loud_music(), 1, 2, SR);
+        }
+        assert!((balance.gain_db() + 12.0).abs() < 1e-6);
+    }
+}
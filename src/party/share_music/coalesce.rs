@@ -0,0 +1,203 @@
+//! Packing of small synced frames into shared datagrams.
+//!
+//! Low-bitrate formats produce frames of a few hundred bytes, each sent in
+//! its own datagram, so a song costs far more packets than its size calls
+//! for. With coalescing on, the sender's [`FrameCoalescer`] holds frames for
+//! a short window and sends the ones that fit together in one
+//! [`SyncedFrameBatch`], much like Nagle's algorithm. Receivers unpack a
+//! batch with [`unpack`] and handle its frames in order.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use rkyv::{Archive, Deserialize, Serialize};
+
+use super::SyncedFrame;
+use crate::party::tagged_packet::{SYNCED_BATCH_TAG, SYNCED_TAG, TaggedPacket};
+
+/// Upper bound on the serialized size of a [`SyncedFrame`] besides its data.
+const FRAME_OVERHEAD: usize = 64;
+/// Upper bound on the batch and [`TaggedPacket`] framing around the frames.
+const BATCH_OVERHEAD: usize = 32;
+
+/// Several synced frames sent in one datagram.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+pub struct SyncedFrameBatch {
+    pub frames: Vec<SyncedFrame>,
+}
+
+/// How long frames may wait for company, and how big a datagram may get.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoalesceConfig {
+    pub window: Duration,
+    /// Largest datagram to build, in bytes. The default stays under a
+    /// typical 1500-byte MTU like unbatched fragments do.
+    pub max_datagram: usize,
+}
+
+impl Default for CoalesceConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_millis(5),
+            max_datagram: 1400,
+        }
+    }
+}
+
+/// Frames of a stream waiting to be sent together.
+pub struct FrameCoalescer {
+    config: CoalesceConfig,
+    pending: Vec<SyncedFrame>,
+    pending_bytes: usize,
+    /// When the first pending frame was queued.
+    since: Option<Instant>,
+}
+
+fn frame_size(frame: &SyncedFrame) -> usize {
+    frame.data.len() + FRAME_OVERHEAD
+}
+
+fn same_fragment(a: &SyncedFrame, b: &SyncedFrame) -> bool {
+    a.track == b.track && a.sequence_number == b.sequence_number && a.fragment_idx == b.fragment_idx
+}
+
+impl FrameCoalescer {
+    pub fn new(config: CoalesceConfig) -> Self {
+        Self {
+            config,
+            pending: Vec::new(),
+            pending_bytes: BATCH_OVERHEAD,
+            since: None,
+        }
+    }
+
+    /// Queues `frame`, returning the datagrams it completed.
+    ///
+    /// A frame that doesn't fit in what's left of the datagram, or repeats
+    /// one already in it, sends the pending ones first. Redundant copies of
+    /// a frame thus never share a datagram, and so a loss.
+    pub fn push(&mut self, frame: SyncedFrame, now: Instant) -> Vec<TaggedPacket> {
+        let mut ready = Vec::new();
+        ready.extend(self.poll(now));
+        let full = self.pending_bytes + frame_size(&frame) > self.config.max_datagram;
+        let repeat = self.pending.iter().any(|p| same_fragment(p, &frame));
+        if full || repeat {
+            ready.extend(self.flush());
+        }
+        self.pending_bytes += frame_size(&frame);
+        self.pending.push(frame);
+        self.since.get_or_insert(now);
+        ready.extend(self.poll(now));
+        ready
+    }
+
+    /// Sends the pending frames once the first has waited out the window.
+    pub fn poll(&mut self, now: Instant) -> Option<TaggedPacket> {
+        let since = self.since?;
+        if now.duration_since(since) >= self.config.window {
+            self.flush()
+        } else {
+            None
+        }
+    }
+
+    /// Sends the pending frames now. A lone frame goes out as a plain
+    /// synced frame.
+    pub fn flush(&mut self) -> Option<TaggedPacket> {
+        self.since = None;
+        self.pending_bytes = BATCH_OVERHEAD;
+        let packet = match self.pending.len() {
+            0 => return None,
+            1 => {
+                let frame = self.pending.pop().unwrap();
+                TaggedPacket::new(
+                    SYNCED_TAG,
+                    rkyv::to_bytes::<rkyv::rancor::Error>(&frame)
+                        .expect("SyncedFrame ser")
+                        .into_vec(),
+                )
+            }
+            _ => {
+                let batch = SyncedFrameBatch {
+                    frames: std::mem::take(&mut self.pending),
+                };
+                TaggedPacket::new(
+                    SYNCED_BATCH_TAG,
+                    rkyv::to_bytes::<rkyv::rancor::Error>(&batch)
+                        .expect("SyncedFrameBatch ser")
+                        .into_vec(),
+                )
+            }
+        };
+        Some(packet)
+    }
+}
+
+/// The frames of a received batch, in the order they were queued.
+pub fn unpack(bytes: &[u8]) -> Result<Vec<SyncedFrame>> {
+    let batch = rkyv::from_bytes::<SyncedFrameBatch, rkyv::rancor::Error>(bytes)
+        .map_err(|e| anyhow::anyhow!("SyncedFrameBatch deserialize: {:?}", e))?;
+    Ok(batch.frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::party::share_music::{SyncedTrack, new_stream_id};
+
+    fn datagram_len(packet: &TaggedPacket) -> usize {
+        rkyv::to_bytes::<rkyv::rancor::Error>(packet).unwrap().len()
+    }
+
+    #[test]
+    fn small_frames_share_a_datagram_until_the_budget_runs_out() {
+        let config = CoalesceConfig {
+            window: Duration::from_millis(5),
+            max_datagram: 1400,
+        };
+        let mut coalescer = FrameCoalescer::new(config);
+        let stream_id = new_stream_id();
+        let frame = |seq: u64, len: usize| {
+            SyncedFrame::for_track(
+                SyncedTrack::Original,
+                stream_id,
+                seq,
+                1152,
+                vec![seq as u8; len],
+            )
+        };
+        let start = Instant::now();
+
+        // Four 200-byte frames fit in one datagram; nothing leaves early.
+        for seq in 1..=4 {
+            let sent = coalescer.push(frame(seq, 200), start + Duration::from_millis(seq));
+            assert!(sent.is_empty(), "frame {seq} sent before the window");
+        }
+        // The fifth would push it past 1400 bytes: the four go out, and it
+        // starts the next datagram.
+        let sent = coalescer.push(frame(5, 400), start + Duration::from_millis(4));
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].tag, SYNCED_BATCH_TAG);
+        assert!(datagram_len(&sent[0]) <= config.max_datagram);
+        let frames = unpack(&sent[0].payload).unwrap();
+        let seqs: Vec<u64> = frames.iter().map(|f| f.sequence_number).collect();
+        assert_eq!(seqs, [1, 2, 3, 4]);
+        assert!(
+            frames
+                .iter()
+                .all(|f| f.data == vec![f.sequence_number as u8; 200])
+        );
+
+        // A redundant copy of frame 5 doesn't join it.
+        let sent = coalescer.push(frame(5, 400), start + Duration::from_millis(5));
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].tag, SYNCED_TAG, "a lone frame goes out unbatched");
+
+        // The timer sends whatever is pending once the window is up.
+        assert!(coalescer.poll(start + Duration::from_millis(9)).is_none());
+        let sent = coalescer.poll(start + Duration::from_millis(10)).unwrap();
+        let lone = rkyv::from_bytes::<SyncedFrame, rkyv::rancor::Error>(&sent.payload).unwrap();
+        assert_eq!(lone.sequence_number, 5);
+        assert!(coalescer.flush().is_none());
+    }
+}
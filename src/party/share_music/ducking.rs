@@ -0,0 +1,163 @@
+//! Automatic ducking of synced music under voice.
+//!
+//! While someone is singing, the mic level rises above a threshold and the
+//! [`Ducker`] ramps the synced-music mix down by a fixed depth. Once the mic
+//! goes quiet the music ramps back up. Ramps are linear in gain and applied
+//! per frame so level changes never click.
+
+use std::sync::{Arc, Mutex};
+
+/// User-tunable ducking parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DuckingSettings {
+    pub enabled: bool,
+    /// Mic level (0-100, as reported by the level meters) that triggers ducking.
+    pub threshold: u32,
+    /// How far the music is lowered while ducked, in dB.
+    pub depth_db: f32,
+    /// Time to ramp from full level down to the ducked level.
+    pub attack_ms: u32,
+    /// Time to ramp from the ducked level back up to full level.
+    pub release_ms: u32,
+}
+
+impl Default for DuckingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 5,
+            depth_db: 12.0,
+            attack_ms: 50,
+            release_ms: 600,
+        }
+    }
+}
+
+impl DuckingSettings {
+    /// Linear gain applied to the music while fully ducked.
+    pub fn depth_gain(&self) -> f64 {
+        10f64.powf(-self.depth_db.max(0.0) as f64 / 20.0)
+    }
+}
+
+/// Scales the synced-music mix according to the current mic level.
+pub struct Ducker {
+    settings: Arc<Mutex<DuckingSettings>>,
+    mic_level_fn: Box<dyn Fn() -> u32 + Send + Sync>,
+    gain: Mutex<f64>,
+}
+
+impl Ducker {
+    pub fn new<F>(settings: Arc<Mutex<DuckingSettings>>, mic_level_fn: F) -> Self
+    where
+        F: Fn() -> u32 + Send + Sync + 'static,
+    {
+        Self {
+            settings,
+            mic_level_fn: Box::new(mic_level_fn),
+            gain: Mutex::new(1.0),
+        }
+    }
+
+    /// Current music gain (1.0 = not ducked).
+    pub fn gain(&self) -> f64 {
+        *self.gain.lock().unwrap()
+    }
+
+    /// Advances the envelope by the frames in `mixed` and scales them in place.
+    ///
+    /// `mixed` holds interleaved accumulator samples as produced by the
+    /// synced stream mixer. When `mixed` is empty the envelope still advances
+    /// by `num_frames`, so ducking tracks the mic while no music is playing.
+    pub fn process(&self, mixed: &mut [i64], num_frames: usize, channels: usize, sample_rate: u32) {
+        let settings = *self.settings.lock().unwrap();
+        let ducked = settings.enabled && (self.mic_level_fn)() >= settings.threshold;
+        let depth_gain = settings.depth_gain();
+        let target = if ducked { depth_gain } else { 1.0 };
+
+        let mut gain = self.gain.lock().unwrap();
+        let ramp_ms = if target < *gain {
+            settings.attack_ms
+        } else {
+            settings.release_ms
+        };
+        let ramp_frames = sample_rate as u64 * ramp_ms as u64 / 1000;
+        let step = if ramp_frames == 0 {
+            f64::INFINITY
+        } else {
+            (1.0 - depth_gain) / ramp_frames as f64
+        };
+
+        let frames = if mixed.is_empty() {
+            num_frames
+        } else {
+            mixed.len() / channels
+        };
+        for frame in 0..frames {
+            *gain = if *gain > target {
+                (*gain - step).max(target)
+            } else {
+                (*gain + step).min(target)
+            };
+            if let Some(samples) = mixed.get_mut(frame * channels..(frame + 1) * channels) {
+                for sample in samples {
+                    *sample = (*sample as f64 * *gain) as i64;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    const SR: u32 = 48000;
+
+    fn ducker(level: Arc<AtomicU32>) -> Ducker {
+        let settings = DuckingSettings {
+            enabled: true,
+            threshold: 10,
+            depth_db: 20.0,
+            attack_ms: 10,
+            release_ms: 100,
+        };
+        Ducker::new(Arc::new(Mutex::new(settings)), move || {
+            level.load(Ordering::Relaxed)
+        })
+    }
+
+    #[test]
+    fn loud_mic_ducks_then_releases() {
+        let level = Arc::new(AtomicU32::new(50));
+        let ducker = ducker(level.clone());
+
+        // 20 ms of audio is past the 10 ms attack.
+        let mut block = vec![1_000_000i64; 960 * 2];
+        ducker.process(&mut block, 960, 2, SR);
+        assert!((ducker.gain() - 0.1).abs() < 1e-9);
+        assert!((*block.last().unwrap() - 100_000).abs() <= 1);
+
+        level.store(0, Ordering::Relaxed);
+        let mut block = vec![1_000_000i64; 2400 * 2];
+        ducker.process(&mut block, 2400, 2, SR);
+        assert!(ducker.gain() < 1.0, "release should not be instant");
+
+        let mut block = vec![1_000_000i64; 4800 * 2];
+        ducker.process(&mut block, 4800, 2, SR);
+        assert_eq!(ducker.gain(), 1.0);
+        assert_eq!(*block.last().unwrap(), 1_000_000);
+    }
+
+    #[test]
+    fn disabled_ducker_leaves_music_untouched() {
+        let level = Arc::new(AtomicU32::new(100));
+        let ducker = ducker(level);
+        ducker.settings.lock().unwrap().enabled = false;
+
+        let mut block = vec![12345i64; 480 * 2];
+        ducker.process(&mut block, 480, 2, SR);
+        assert!(block.iter().all(|&s| s == 12345));
+    }
+}
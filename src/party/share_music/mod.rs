@@ -8,25 +8,38 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
+use std::time::Duration;
 
 use rkyv::{Archive, Deserialize, Serialize};
 use tracing::info;
 
-use crate::audio::AudioSample;
+use crate::audio::file::HttpSource;
 use crate::audio::symphonia_compat::WireCodecParams;
+use crate::audio::{AudioSample, OpusFrameDuration};
 use crate::io::NetworkSender;
 use crate::party::network_stream::{NetworkStream, NetworkStreamContext};
 use crate::party::ntp::NtpService;
 use crate::party::tagged_packet::{
-    PacketTag, REQUEST_FRAMES_TAG, SYNCED_CONTROL_TAG, SYNCED_META_TAG, SYNCED_TAG,
+    PacketTag, REQUEST_FRAMES_TAG, SYNCED_BATCH_TAG, SYNCED_CONTROL_TAG, SYNCED_META_TAG,
+    SYNCED_TAG,
 };
 use crate::state::MusicStreamProgress;
 
+pub mod auto_balance;
+pub mod coalesce;
+pub mod ducking;
 pub mod playlist;
 pub mod receiver;
+pub mod saved_playlist;
 pub mod sender;
+pub mod stereo_analysis;
 
+pub use auto_balance::{AutoBalance, AutoBalanceSettings};
+pub use coalesce::CoalesceConfig;
+pub use ducking::{Ducker, DuckingSettings};
 pub use playlist::{PlaylistEntry, PlaylistOp, PlaylistState, SharedPlaylist};
+pub use receiver::RetransmitWindow;
+pub use saved_playlist::SavedPlaylist;
 
 // ---------------------------------------------------------------------------
 //  Stream ID
@@ -65,6 +78,29 @@ pub struct SyncedStreamMeta {
     pub total_frames: u64,
     pub total_samples: u64,
     pub codec_params: WireCodecParams,
+    /// Key change applied by every receiver after decoding, in semitones.
+    pub pitch_semitones: i8,
+    /// Playback speed in percent (100 = normal), applied by every receiver
+    /// after decoding without changing pitch. `total_samples` is already
+    /// scaled to the stretched length.
+    pub tempo_percent: u16,
+    /// Channels the no-vocal Opus track is coded with: 1 when the sender
+    /// found the song to be effectively mono. Plain Opus either way, so
+    /// any decoder plays it on all output channels.
+    pub no_vocal_channels: u8,
+}
+
+impl SyncedStreamMeta {
+    /// Channels the original track is coded with, which its decoder is
+    /// created for and receivers map onto their own layout.
+    pub fn channels(&self) -> usize {
+        self.codec_params.channels as usize
+    }
+
+    /// Playback speed as a factor, 1.0 being normal.
+    pub fn tempo(&self) -> f32 {
+        self.tempo_percent as f32 / 100.0
+    }
 }
 
 /// A single compressed audio packet for synced playback, sent over the network.
@@ -79,6 +115,11 @@ pub struct SyncedFrame {
     pub fragment_idx: u16,
     pub fragment_total: u16,
     pub data: Vec<u8>,
+    /// Position of the frame's first sample in the track, in samples per
+    /// channel of the codec timeline. Places frames exactly even when
+    /// frame sizes vary and some never arrive; `None` when the sender
+    /// doesn't stamp frames.
+    pub sample_offset: Option<u64>,
 }
 
 /// Max bytes of compressed audio per fragment. Leaves headroom under a
@@ -91,6 +132,8 @@ pub const MAX_FRAGMENT_DATA: usize = 1200;
 pub struct RawPacket {
     pub dur: u32,
     pub data: Vec<u8>,
+    /// See [`SyncedFrame::sample_offset`].
+    pub offset: Option<u64>,
 }
 
 impl SyncedFrame {
@@ -115,8 +158,24 @@ impl SyncedFrame {
             fragment_idx: 0,
             fragment_total: 1,
             data,
+            sample_offset: None,
         }
     }
+
+    pub fn with_sample_offset(mut self, sample_offset: Option<u64>) -> Self {
+        self.sample_offset = sample_offset;
+        self
+    }
+}
+
+/// A–B loop region of a shared track, in milliseconds of the source.
+///
+/// The sender restarts playback at `start_ms` whenever it reaches `end_ms`;
+/// receivers just follow the resulting [`SyncedControl::Start`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopRegion {
+    pub start_ms: u64,
+    pub end_ms: u64,
 }
 
 /// Playback progress for a synced stream (output type for GUI).
@@ -130,6 +189,12 @@ pub struct SyncedStreamProgress {
     /// Party clock time (microseconds) when playback started/resumed.
     /// Used by the playlist auto-advance logic to detect song completion.
     pub start_party_time: u64,
+    /// The sender ended the stream and everything up to its last packet
+    /// has been played.
+    pub is_complete: bool,
+    /// Playback jumped and the frames around the new position haven't all
+    /// arrived yet.
+    pub is_seeking: bool,
 }
 
 /// Complete state of a synced stream (output type for GUI).
@@ -139,6 +204,8 @@ pub struct SyncedStreamState {
     pub meta: SyncedStreamMeta,
     pub progress: SyncedStreamProgress,
     pub is_local_sender: bool,
+    /// Labels shown as badges next to the stream, e.g. "You" for our own.
+    pub tags: Vec<String>,
 }
 
 impl PartialEq for SyncedStreamState {
@@ -149,6 +216,7 @@ impl PartialEq for SyncedStreamState {
             && self.meta.total_frames == other.meta.total_frames
             && self.progress == other.progress
             && self.is_local_sender == other.is_local_sender
+            && self.tags == other.tags
     }
 }
 
@@ -178,6 +246,20 @@ pub enum SyncedControl {
         enabled: bool,
         party_clock_time: u64,
     },
+    /// The sender has ended this stream for good. Unlike `Pause`, receivers
+    /// drop all buffered state for it.
+    Stop {
+        stream_id: SyncedStreamId,
+    },
+    /// The sender has sent every packet of this stream; `last_seq` and
+    /// `no_vocal_last_seq` are the final sequence numbers of each track.
+    /// Receivers stop waiting for packets still missing by then and play
+    /// silence in their place, so playback can finish.
+    End {
+        stream_id: SyncedStreamId,
+        last_seq: u64,
+        no_vocal_last_seq: u64,
+    },
 }
 
 // ---------------------------------------------------------------------------
@@ -203,21 +285,73 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
         network_sender: NetworkSender,
         party_now_fn: impl Fn() -> u64 + Send + Sync + 'static,
         vocal_removal_enabled: Arc<AtomicBool>,
+        compress_pcm: bool,
+        ducker: Ducker,
+        retransmit_window: RetransmitWindow,
     ) -> Self {
-        let receiver = Arc::new(receiver::SyncedAudioStreamManager::new(
-            party_now_fn,
-            vocal_removal_enabled.clone(),
-        ));
+        let ntp_for_receiver = ntp_service.clone();
+        let receiver = Arc::new(
+            receiver::SyncedAudioStreamManager::new(party_now_fn, vocal_removal_enabled.clone())
+                .with_clock_synced(move || ntp_for_receiver.is_synced())
+                .with_ducker(ducker)
+                .with_retransmit_window(retransmit_window),
+        );
         let sender = sender::MusicStreamRegistry::new(
             ntp_service,
             network_sender,
             receiver.clone(),
             vocal_removal_enabled,
+            compress_pcm,
         );
         info!("ShareMusicService created");
         Self { sender, receiver }
     }
 
+    /// Keeps received music balanced against the mics with `balance`.
+    pub fn set_auto_balance(&self, balance: AutoBalance) {
+        self.receiver.set_auto_balance(balance);
+    }
+
+    /// Packs small outgoing music frames into shared datagrams, or sends
+    /// each on its own with `None`.
+    pub fn with_coalescing(mut self, coalesce: Option<CoalesceConfig>) -> Self {
+        self.sender = self.sender.with_coalescing(coalesce);
+        self
+    }
+
+    /// Bounds how far outgoing songs are read ahead of what has been sent.
+    pub fn with_read_ahead(mut self, read_ahead: Option<Duration>) -> Self {
+        self.sender = self.sender.with_read_ahead(read_ahead);
+        self
+    }
+
+    /// Stamps frames of outgoing songs with their sample offset. See
+    /// [`SyncedFrame::sample_offset`].
+    pub fn with_sample_offsets(mut self, enabled: bool) -> Self {
+        self.sender = self.sender.with_sample_offsets(enabled);
+        self
+    }
+
+    /// Codes the no-vocal track of outgoing songs as mono when their
+    /// channels are effectively identical.
+    pub fn with_auto_mono(mut self, auto_mono: bool) -> Self {
+        self.sender = self.sender.with_auto_mono(auto_mono);
+        self
+    }
+
+    /// Sets the frame duration of the Opus-encoded no-vocal track of
+    /// outgoing streams.
+    pub fn with_music_frame_duration(mut self, duration: OpusFrameDuration) -> Self {
+        self.sender = self.sender.with_no_vocal_frame_duration(duration);
+        self
+    }
+
+    /// Whether outgoing music frames are sent redundantly. See
+    /// [`MusicStreamRegistry::redundancy`](sender::MusicStreamRegistry::redundancy).
+    pub fn music_redundancy(&self) -> Arc<AtomicBool> {
+        self.sender.redundancy()
+    }
+
     /// Start streaming a local music file.
     pub fn start_stream(
         &self,
@@ -228,6 +362,17 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
         self.sender.start_stream(data, file_name, progress)
     }
 
+    /// Start streaming audio from an HTTP(S) response opened with
+    /// [`HttpSource::open`].
+    pub fn start_url_stream(
+        &self,
+        source: HttpSource,
+        file_name: String,
+        progress: Arc<MusicStreamProgress>,
+    ) -> anyhow::Result<()> {
+        self.sender.start_url_stream(source, file_name, progress)
+    }
+
     /// Pause a playing stream by ID.
     pub fn pause(&self, stream_id: SyncedStreamId) -> anyhow::Result<()> {
         self.sender.pause(stream_id)
@@ -243,6 +388,20 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
         self.sender.seek(stream_id, position_ms)
     }
 
+    /// Loop a section of a stream by ID for everyone, or stop looping.
+    pub fn set_loop(
+        &self,
+        stream_id: SyncedStreamId,
+        region: Option<LoopRegion>,
+    ) -> anyhow::Result<()> {
+        self.sender.set_loop(stream_id, region)
+    }
+
+    /// Stop a stream by ID and tell all receivers to drop it.
+    pub fn stop(&self, stream_id: SyncedStreamId) -> anyhow::Result<()> {
+        self.sender.stop(stream_id)
+    }
+
     /// Schedule a shared vocal-removal track switch for a local outgoing stream.
     pub fn set_vocal_removal(
         &self,
@@ -271,6 +430,7 @@ impl<S: AudioSample + 'static, const C: usize, const SR: u32> NetworkStream<S, C
     fn tags(&self) -> &'static [PacketTag] {
         &[
             SYNCED_TAG,
+            SYNCED_BATCH_TAG,
             SYNCED_META_TAG,
             SYNCED_CONTROL_TAG,
             REQUEST_FRAMES_TAG,
@@ -280,7 +440,7 @@ impl<S: AudioSample + 'static, const C: usize, const SR: u32> NetworkStream<S, C
     fn handle(&self, source: SocketAddr, tag: PacketTag, bytes: &[u8]) -> anyhow::Result<()> {
         match tag {
             REQUEST_FRAMES_TAG => self.sender.handle(source, tag, bytes),
-            SYNCED_TAG | SYNCED_META_TAG | SYNCED_CONTROL_TAG => {
+            SYNCED_TAG | SYNCED_BATCH_TAG | SYNCED_META_TAG | SYNCED_CONTROL_TAG => {
                 self.receiver.handle(source, tag, bytes)
             }
             _ => unreachable!("ShareMusicService received unexpected tag {tag}"),
@@ -11,6 +11,7 @@
 //! the next entry when the current song finishes (detected via party clock).
 
 use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock, Weak};
 use std::time::Duration;
@@ -22,6 +23,7 @@ use tracing::{info, warn};
 use crate::audio::AudioSample;
 use crate::io::NetworkSender;
 use crate::party::network_stream::{NetworkStream, NetworkStreamContext};
+use crate::party::share_music::saved_playlist::SavedTrack;
 use crate::party::tagged_packet::{PLAYLIST_TAG, PacketTag, TaggedPacket};
 use crate::pipeline::Pushable;
 use crate::state::{AppState, PartyViewState};
@@ -103,6 +105,9 @@ pub struct SharedPlaylist {
     /// Local cache of audio data for entries added by this peer.
     /// Keyed by entry_id. Stores (audio_bytes, title).
     local_audio_cache: Arc<DashMap<u64, (Vec<u8>, String)>>,
+    /// Where locally-added entries were read from, when known, so the
+    /// queue can be saved as a playlist.
+    local_paths: Arc<DashMap<u64, PathBuf>>,
     /// All local IP addresses, used to determine ownership.
     local_ips: Vec<IpAddr>,
     /// IP of the interface actually used for sending. Preferred over
@@ -130,6 +135,7 @@ impl SharedPlaylist {
             entries: Arc::new(RwLock::new(Vec::new())),
             current_entry_id: Arc::new(RwLock::new(None)),
             local_audio_cache: Arc::new(DashMap::new()),
+            local_paths: Arc::new(DashMap::new()),
             local_ips,
             send_ip,
             network_sender,
@@ -172,10 +178,8 @@ impl SharedPlaylist {
                 return;
             }
         };
-        self.network_sender.push(TaggedPacket {
-            tag: PLAYLIST_TAG,
-            payload,
-        });
+        self.network_sender
+            .push(TaggedPacket::new(PLAYLIST_TAG, payload));
     }
 
     fn update_view_state(&self) {
@@ -191,6 +195,32 @@ impl SharedPlaylist {
     /// playback starts (if the local peer is the owner, which it always is
     /// for locally-added songs).
     pub fn add_entry(&self, data: Vec<u8>, title: String) {
+        self.add_entry_from(data, title, None);
+    }
+
+    /// Add a song read from `path`, remembering the path so the queue can
+    /// be saved as a playlist.
+    pub fn add_file(&self, data: Vec<u8>, title: String, path: PathBuf) {
+        self.add_entry_from(data, title, Some(path));
+    }
+
+    /// Paths and titles of the queued entries this peer added from files,
+    /// in playlist order.
+    pub fn local_files(&self) -> Vec<SavedTrack> {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|e| {
+                self.local_paths.get(&e.entry_id).map(|p| SavedTrack {
+                    path: p.clone(),
+                    title: e.title.clone(),
+                })
+            })
+            .collect()
+    }
+
+    fn add_entry_from(&self, data: Vec<u8>, title: String, path: Option<PathBuf>) {
         let entry_id = new_entry_id();
         let entry = PlaylistEntry {
             entry_id,
@@ -200,6 +230,9 @@ impl SharedPlaylist {
 
         // Cache audio data before applying op, in case apply triggers playback.
         self.local_audio_cache.insert(entry_id, (data, title));
+        if let Some(path) = path {
+            self.local_paths.insert(entry_id, path);
+        }
 
         let op = PlaylistOp::Add { entry };
         self.apply_op(&op);
@@ -314,6 +347,7 @@ impl SharedPlaylist {
                     .unwrap()
                     .retain(|e| e.entry_id != *entry_id);
                 self.local_audio_cache.remove(entry_id);
+                self.local_paths.remove(entry_id);
                 if *self.current_entry_id.read().unwrap() == Some(*entry_id) {
                     *self.current_entry_id.write().unwrap() = None;
                 }
@@ -348,6 +382,7 @@ impl SharedPlaylist {
             PlaylistOp::Clear => {
                 self.entries.write().unwrap().clear();
                 self.local_audio_cache.clear();
+                self.local_paths.clear();
                 *self.current_entry_id.write().unwrap() = None;
             }
         }
@@ -11,38 +11,81 @@
 //! publishes both tracks; the audio callback switches between the pre-decoded
 //! buffers when it receives a shared vocal-removal control event.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use anyhow::Context;
 use dashmap::DashMap;
 use symphonia::core::codecs::DecoderOptions;
+use tokio::sync::Notify;
 use tracing::{error, info, warn};
 
 use crate::audio::AudioSample;
 use crate::audio::buffers::simple_buffer::SimpleBuffer;
 use crate::audio::decoders::{
-    CompressedPacket, FftResampler, Interleaver, PacketCounter, SymphoniaDecoder,
+    CompressedPacket, FftResampler, Interleaver, PacketCounter, SymphoniaDecoder, WireDecompressor,
 };
+use crate::audio::effects::{PitchShift, TimeStretch};
 use crate::audio::frame::AudioBuffer;
-use crate::audio::opus::{OpusDecoder, OpusPacket};
+use crate::audio::opus::{AudioCodec, OpusDecoder, OpusPacket};
 use crate::party::combinator::SynchronizedSelect;
-use crate::party::network_stream::{NetworkStream, NetworkStreamContext};
+use crate::party::network_stream::{NetworkStream, NetworkStreamContext, spawn_stats_task};
+use crate::party::share_music::auto_balance::AutoBalance;
+use crate::party::share_music::coalesce;
+use crate::party::share_music::ducking::Ducker;
 use crate::party::share_music::{
     RequestFramesPayload, SyncedControl, SyncedFrame, SyncedStreamId, SyncedStreamMeta,
     SyncedStreamProgress, SyncedStreamState, SyncedTrack,
 };
 use crate::party::tagged_packet::{
-    PacketTag, REQUEST_FRAMES_TAG, SYNCED_CONTROL_TAG, SYNCED_META_TAG, SYNCED_TAG, TaggedPacket,
+    PacketTag, REQUEST_FRAMES_TAG, SYNCED_BATCH_TAG, SYNCED_CONTROL_TAG, SYNCED_META_TAG,
+    SYNCED_TAG, TaggedPacket,
 };
 use crate::pipeline::{Pullable, Pushable};
 use crate::push_chain;
 use crate::state::PartyViewState;
 
 const SYNCED_STREAM_TIMEOUT: Duration = Duration::from_secs(30);
+/// Default drift beyond which playback jumps straight to the party-clock
+/// position instead of converging gradually.
+pub const DEFAULT_RESYNC_THRESHOLD: Duration = Duration::from_millis(10);
+/// Largest gradual correction per callback, as a fraction of its length
+/// (0.5%, well below audible pitch change).
+const MAX_CORRECTION_RATIO: usize = 200;
+/// Most sources whose frames are held while waiting for metadata.
+const MAX_PRE_META_SOURCES: usize = 4;
+/// Default time after [`SyncedControl::End`] that retransmissions get to
+/// fill gaps before the rest is played as silence.
+pub const DEFAULT_END_GAP_GRACE: Duration = Duration::from_secs(2);
+/// Default frames from a seek target that must have arrived before the
+/// stream stops reporting that it is seeking.
+pub const DEFAULT_SEEK_READY_FRAMES: u64 = 10;
+
+/// How far ahead of the feed position gaps are looked for, and how many
+/// retransmissions are requested per track per round.
+///
+/// A larger window prefetches more on fast links; a smaller one keeps slow
+/// links from being flooded with requests for packets that are still in
+/// flight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetransmitWindow {
+    /// Number of sequence numbers after the next one to feed to scan.
+    pub lookahead: u64,
+    /// Maximum sequence numbers requested per track per round.
+    pub max_requests: usize,
+}
+
+impl Default for RetransmitWindow {
+    fn default() -> Self {
+        Self {
+            lookahead: 200,
+            max_requests: 100,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct BufferKey {
@@ -57,9 +100,38 @@ struct FragmentSet {
     total: u16,
     received_count: u16,
     dur: u32,
+    sample_offset: Option<u64>,
     parts: Vec<Option<Vec<u8>>>,
 }
 
+/// Linearly resamples interleaved `input` to `out_frames` frames into
+/// `output`, replacing its contents. Used for gradual drift correction,
+/// where the ratio is within a fraction of a percent of 1.
+fn stretch_frames<Sample: AudioSample, const CHANNELS: usize>(
+    input: &[Sample],
+    out_frames: usize,
+    output: &mut Vec<Sample>,
+) {
+    output.clear();
+    let in_frames = input.len() / CHANNELS;
+    if in_frames < 2 || out_frames < 2 {
+        output.extend_from_slice(&input[..out_frames.min(in_frames) * CHANNELS]);
+        return;
+    }
+
+    let step = (in_frames - 1) as f64 / (out_frames - 1) as f64;
+    for frame in 0..out_frames {
+        let position = frame as f64 * step;
+        let index = (position as usize).min(in_frames - 2);
+        let fraction = position - index as f64;
+        for channel in 0..CHANNELS {
+            let a = input[index * CHANNELS + channel].to_f64_normalized();
+            let b = input[(index + 1) * CHANNELS + channel].to_f64_normalized();
+            output.push(Sample::from_f64_normalized(a + (b - a) * fraction));
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 //  BufferEntry — per-source/stream state using push-based pipeline
 // ---------------------------------------------------------------------------
@@ -69,6 +141,15 @@ struct TrackReceiveState {
     pending_fragments: HashMap<u64, FragmentSet>,
     next_feed_seq: u64,
     packet_counter: PacketCounter,
+    /// Duration of the last frame fed, used to size silence for frames
+    /// that never arrive.
+    last_dur: u32,
+    /// Sample offset right after the last frame fed, if the sender stamps
+    /// frames with their offset.
+    fed_until: Option<u64>,
+    /// Last frame a seek needs before playback can go on from the target;
+    /// `None` when not seeking.
+    seek_until: Option<u64>,
 }
 
 impl TrackReceiveState {
@@ -78,6 +159,9 @@ impl TrackReceiveState {
             pending_fragments: HashMap::new(),
             next_feed_seq: 1,
             packet_counter: PacketCounter::new(),
+            last_dur: 0,
+            fed_until: None,
+            seek_until: None,
         }
     }
 
@@ -85,7 +169,58 @@ impl TrackReceiveState {
         self.pending_raw.clear();
         self.pending_fragments.clear();
         self.next_feed_seq = seq;
+        self.fed_until = None;
+        self.seek_until = None;
     }
+
+    /// Notes that `frame` was fed to the decoder. Returns the source
+    /// samples between the end of the previous frame and its start, which
+    /// only frames stamped with their offset tell.
+    fn record_fed(&mut self, frame: &SyncedFrame) -> u64 {
+        let skipped = gap_samples(self.fed_until, frame.sample_offset, 0, frame.dur);
+        self.packet_counter.record_packet(frame.sequence_number);
+        self.last_dur = frame.dur;
+        self.fed_until = frame.sample_offset.map(|offset| offset + frame.dur as u64);
+        skipped
+    }
+
+    /// Stops a pending seek from waiting for frames after `last_seq`.
+    fn clamp_seek(&mut self, last_seq: u64) {
+        self.seek_until = self.seek_until.map(|until| until.min(last_seq));
+    }
+
+    /// Still waiting for the frames around a seek target.
+    fn seeking(&self) -> bool {
+        self.seek_until
+            .is_some_and(|last| self.next_feed_seq <= last)
+    }
+
+    /// Takes every frame up to and including `last_seq`, in order, with
+    /// `None` for those never received, and gives up on the rest.
+    fn drain_through(&mut self, last_seq: u64) -> Vec<Option<SyncedFrame>> {
+        let mut frames = Vec::new();
+        for seq in self.next_feed_seq..=last_seq {
+            let frame = self.pending_raw.remove(&seq);
+            if let Some(frame) = &frame {
+                self.record_fed(frame);
+            }
+            frames.push(frame);
+        }
+        self.next_feed_seq = self.next_feed_seq.max(last_seq + 1);
+        self.pending_raw.clear();
+        self.pending_fragments.clear();
+        frames
+    }
+}
+
+/// Where the sender said a stream ends, from [`SyncedControl::End`].
+#[derive(Clone, Copy)]
+struct StreamEnd {
+    last_seq: u64,
+    no_vocal_last_seq: u64,
+    received_at: Instant,
+    /// Both tracks have been fed through their last packet.
+    flushed: bool,
 }
 
 /// A buffer for a single stream from a single source.
@@ -98,8 +233,19 @@ struct BufferEntry<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     /// Head of the original-file push pipeline. Push CompressedPacket here to
     /// decode original compressed packets eagerly.
     original_pipeline_head: Arc<dyn Pushable<CompressedPacket>>,
+    /// Decoded original PCM at the output rate, before key and tempo
+    /// changes. Silence for lost packets enters here.
+    original_pcm_head: Arc<dyn Pushable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
     /// Opus decoder for sender-produced no-vocal packets.
     no_vocal_decoder: Arc<OpusDecoder<Sample, CHANNELS, SAMPLE_RATE>>,
+    /// Pushes decoded no-vocal PCM through key and tempo changes into
+    /// `output_buffer_no_vocal`.
+    no_vocal_pipeline_head: Arc<dyn Pushable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+    /// Key changes on the original and no-vocal tracks, set from
+    /// `meta.pitch_semitones`.
+    pitch_shifts: [Arc<PitchShift<Sample, CHANNELS, SAMPLE_RATE>>; 2],
+    /// Tempo changes on both tracks, set from `meta.tempo_percent`.
+    time_stretches: [Arc<TimeStretch<Sample, CHANNELS, SAMPLE_RATE>>; 2],
     /// Pull-side selector for raw/no-vocal output buffers.
     output_selector: Arc<SynchronizedSelect<Sample, CHANNELS, SAMPLE_RATE>>,
     /// Pre-decoded original audio buffer.
@@ -114,11 +260,15 @@ struct BufferEntry<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     no_vocal_track: TrackReceiveState,
     last_seen: Instant,
     empty_since: Option<Instant>,
+    end: Option<StreamEnd>,
 
     // -- Playback state --
     playing: bool,
     /// Party clock time (µs) when playback should start / resumed.
     start_party_time: u64,
+    /// Furthest past `start_party_time` the party clock has been seen, in
+    /// µs, or `None` before playback began.
+    max_elapsed_us: Option<u64>,
     /// Total samples pulled to output (for progress UI).
     samples_played: u64,
     vocal_removal_active: bool,
@@ -126,19 +276,85 @@ struct BufferEntry<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
 }
 
 enum ReadyPackets<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
-    Original(Arc<dyn Pushable<CompressedPacket>>, Vec<SyncedFrame>),
+    /// Frames to decode, each after the source samples skipped before it,
+    /// which go in as silence at the given source rate.
+    Original {
+        pipeline_head: Arc<dyn Pushable<CompressedPacket>>,
+        pcm_head: Arc<dyn Pushable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+        source_sample_rate: u32,
+        frames: Vec<(u64, SyncedFrame)>,
+    },
     NoVocal(
         Arc<OpusDecoder<Sample, CHANNELS, SAMPLE_RATE>>,
-        SimpleBuffer<Sample, CHANNELS, SAMPLE_RATE>,
-        Vec<SyncedFrame>,
+        Arc<dyn Pushable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+        Vec<(u64, SyncedFrame)>,
     ),
 }
 
+/// The rest of an ended stream, taken by `flush_ended()` to decode once the
+/// map lock is released. `None` frames were never received.
+struct EndFlush<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    original_pipeline_head: Arc<dyn Pushable<CompressedPacket>>,
+    original_pcm_head: Arc<dyn Pushable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+    no_vocal_decoder: Arc<OpusDecoder<Sample, CHANNELS, SAMPLE_RATE>>,
+    no_vocal_pipeline_head: Arc<dyn Pushable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>>,
+    source_sample_rate: u32,
+    /// Durations used to size gaps, following the frames around them.
+    original_dur: u32,
+    /// Sample offset after the last original frame fed before the flush.
+    original_fed_until: Option<u64>,
+    no_vocal_dur: u32,
+    original: Vec<Option<SyncedFrame>>,
+    no_vocal: Vec<Option<SyncedFrame>>,
+}
+
+/// Source samples lost in a run of `missing` frames. With the sample
+/// offsets of the frames on either side that's exact; otherwise each lost
+/// frame is taken to last `dur`, like the frames around it.
+fn gap_samples(fed_until: Option<u64>, next_offset: Option<u64>, missing: u64, dur: u32) -> u64 {
+    match (fed_until, next_offset) {
+        (Some(from), Some(to)) if to >= from => to - from,
+        _ => missing * dur as u64,
+    }
+}
+
 /// Manages synchronized audio streams from multiple sources.
 pub struct SyncedAudioStreamManager<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     buffers: DashMap<BufferKey, BufferEntry<Sample, CHANNELS, SAMPLE_RATE>>,
     party_now_fn: Arc<dyn Fn() -> u64 + Send + Sync>,
+    /// Whether the party clock has been synced; `None` takes it as always
+    /// synced.
+    clock_synced_fn: Option<Arc<dyn Fn() -> bool + Send + Sync>>,
+    /// Whether playback waits for the party clock to be synced.
+    wait_for_clock_sync: AtomicBool,
     vocal_removal_enabled: Arc<AtomicBool>,
+    ducker: Option<Ducker>,
+    auto_balance: Mutex<Option<AutoBalance>>,
+    retransmit_window: RetransmitWindow,
+    /// Hard-resync threshold in output frames.
+    resync_threshold_frames: AtomicU64,
+    /// Frames that arrived before their stream's metadata, with the time the
+    /// first one arrived.
+    pre_meta: DashMap<BufferKey, (Instant, VecDeque<SyncedFrame>)>,
+    /// Frames held per source while waiting for metadata; 0 drops them.
+    pre_meta_capacity: AtomicUsize,
+    /// Microseconds to wait for missing packets after a stream ends;
+    /// `u64::MAX` waits forever.
+    end_gap_grace_us: AtomicU64,
+    /// Frames from a seek target needed before playback goes on; 0 doesn't
+    /// track seeks.
+    seek_ready_frames: AtomicU64,
+    /// Whether playback positions ignore the party clock stepping back.
+    monotonic_party_time: AtomicBool,
+    /// Whether streams from different sources play side by side.
+    concurrent_streams: AtomicBool,
+    /// Mix accumulator, kept between callbacks so mixing doesn't allocate.
+    mix_scratch: Mutex<Vec<i64>>,
+    /// Drift-corrected stream audio, reused the same way.
+    stretch_scratch: Mutex<Vec<Sample>>,
+    /// Wakes the retransmit task early, so a seek asks for the frames at
+    /// its target right away instead of on the next tick.
+    retransmit_now: Notify,
 }
 
 impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
@@ -151,27 +367,149 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         Self {
             buffers: DashMap::new(),
             party_now_fn: Arc::new(party_now_fn),
+            clock_synced_fn: None,
+            wait_for_clock_sync: AtomicBool::new(true),
             vocal_removal_enabled,
+            ducker: None,
+            auto_balance: Mutex::new(None),
+            retransmit_window: RetransmitWindow::default(),
+            resync_threshold_frames: AtomicU64::new(Self::duration_to_frames(
+                DEFAULT_RESYNC_THRESHOLD,
+            )),
+            pre_meta: DashMap::new(),
+            pre_meta_capacity: AtomicUsize::new(0),
+            end_gap_grace_us: AtomicU64::new(DEFAULT_END_GAP_GRACE.as_micros() as u64),
+            seek_ready_frames: AtomicU64::new(DEFAULT_SEEK_READY_FRAMES),
+            monotonic_party_time: AtomicBool::new(true),
+            concurrent_streams: AtomicBool::new(false),
+            mix_scratch: Mutex::new(Vec::new()),
+            stretch_scratch: Mutex::new(Vec::new()),
+            retransmit_now: Notify::new(),
         }
     }
 
+    /// Sets how far playback may drift from the party clock before it jumps
+    /// to the expected position. Smaller drift is corrected gradually by
+    /// playing slightly faster or slower.
+    pub fn set_resync_threshold(&self, threshold: Duration) {
+        self.resync_threshold_frames
+            .store(Self::duration_to_frames(threshold), Ordering::Relaxed);
+    }
+
+    /// Holds up to `capacity` frames per source that arrive before the
+    /// stream's metadata, and feeds them in once it does. 0 drops them.
+    pub fn set_pre_meta_capacity(&self, capacity: usize) {
+        self.pre_meta_capacity.store(capacity, Ordering::Relaxed);
+        if capacity == 0 {
+            self.pre_meta.clear();
+        }
+    }
+
+    /// Sets how long packets still missing when the sender ends a stream
+    /// are waited for before being played as silence. `None` waits for
+    /// them indefinitely.
+    pub fn set_end_gap_grace(&self, grace: Option<Duration>) {
+        let micros = grace.map_or(u64::MAX, |grace| grace.as_micros() as u64);
+        self.end_gap_grace_us.store(micros, Ordering::Relaxed);
+    }
+
+    /// Sets how many frames from a seek target have to arrive before the
+    /// stream stops reporting that it is seeking. Until then the missing
+    /// ones are requested even if nothing after them has arrived yet. 0
+    /// leaves seeks to the regular gap detection.
+    pub fn set_seek_ready_frames(&self, frames: u64) {
+        self.seek_ready_frames.store(frames, Ordering::Relaxed);
+    }
+
+    /// Whether a party clock stepping back leaves playback positions where
+    /// they are until it catches up (the default), rather than pulling
+    /// them back with it.
+    pub fn set_monotonic_party_time(&self, enabled: bool) {
+        self.monotonic_party_time.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Whether streams shared by different sources at the same time are
+    /// mixed together. Off (the default), a new stream from anyone
+    /// replaces all others; on, it only replaces its own source's.
+    pub fn set_concurrent_streams(&self, enabled: bool) {
+        self.concurrent_streams.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Whether playback is held back, as silence, until `clock_synced`
+    /// reports the party clock synced (the default). Until then the clock
+    /// can be off by any amount, so streams would play from a wrong
+    /// position; once synced they start from the right one.
+    pub fn set_wait_for_clock_sync(&self, enabled: bool) {
+        self.wait_for_clock_sync.store(enabled, Ordering::Relaxed);
+    }
+
+    fn duration_to_frames(duration: Duration) -> u64 {
+        (duration.as_micros() * SAMPLE_RATE as u128 / 1_000_000) as u64
+    }
+
+    pub fn with_retransmit_window(mut self, window: RetransmitWindow) -> Self {
+        self.retransmit_window = window;
+        self
+    }
+
+    /// Asks `clock_synced` whether the party clock has been synced, see
+    /// [`set_wait_for_clock_sync`](Self::set_wait_for_clock_sync).
+    pub fn with_clock_synced<F>(mut self, clock_synced: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        self.clock_synced_fn = Some(Arc::new(clock_synced));
+        self
+    }
+
+    /// Whether playback is held back waiting for the party clock.
+    fn awaiting_clock_sync(&self) -> bool {
+        self.wait_for_clock_sync.load(Ordering::Relaxed)
+            && self
+                .clock_synced_fn
+                .as_ref()
+                .is_some_and(|synced| !synced())
+    }
+
+    /// Lowers the music mix under voice using `ducker`.
+    pub fn with_ducker(mut self, ducker: Ducker) -> Self {
+        self.ducker = Some(ducker);
+        self
+    }
+
+    /// Keeps the music mix balanced against the mics using `balance`,
+    /// replacing any set before.
+    pub fn set_auto_balance(&self, balance: AutoBalance) {
+        *self.auto_balance.lock().unwrap() = Some(balance);
+    }
+
     /// Receives stream metadata. This is the ONLY place entries are created/deleted.
     ///
-    /// - If stream_id differs from existing entries, clears all old entries
+    /// - If stream_id differs from existing entries, clears all old entries,
+    ///   or only the source's own with concurrent streams on
     /// - Creates decoder from codec_params; if fails, entry is not created
     /// - Updates existing entry's meta if stream_id matches
     pub fn receive_meta(&self, source_addr: SocketAddr, meta: SyncedStreamMeta) {
-        let dominated_by_other_stream = self
-            .buffers
-            .iter()
-            .any(|e| e.key().stream_id != meta.stream_id);
+        let concurrent = self.concurrent_streams.load(Ordering::Relaxed);
+        let replaces = |key: &BufferKey| {
+            key.stream_id != meta.stream_id && (!concurrent || key.source_addr == source_addr)
+        };
+        let dominated_by_other_stream = self.buffers.iter().any(|e| replaces(e.key()));
 
         if dominated_by_other_stream {
-            info!(
-                "New stream ID {} detected, clearing all old buffers",
-                meta.stream_id
-            );
-            self.buffers.clear();
+            if concurrent {
+                info!(
+                    "New stream ID {} from {}, clearing its old buffers",
+                    meta.stream_id, source_addr
+                );
+                self.buffers.retain(|key, _| !replaces(key));
+            } else {
+                info!(
+                    "New stream ID {} detected, clearing all old buffers",
+                    meta.stream_id
+                );
+                self.buffers.clear();
+            }
         }
 
         let key = BufferKey {
@@ -181,13 +519,65 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
 
         // Metadata update for existing entry.
         if let Some(mut entry) = self.buffers.get_mut(&key) {
+            if entry.meta.pitch_semitones != meta.pitch_semitones {
+                info!(
+                    "Stream {} key change: {:+} semitones",
+                    meta.stream_id, meta.pitch_semitones
+                );
+                for pitch in &entry.pitch_shifts {
+                    pitch.set_semitones(meta.pitch_semitones.into());
+                }
+            }
+            if entry.meta.tempo_percent != meta.tempo_percent {
+                info!(
+                    "Stream {} tempo change: {}%",
+                    meta.stream_id, meta.tempo_percent
+                );
+                for stretch in &entry.time_stretches {
+                    stretch.set_tempo(meta.tempo());
+                }
+            }
             entry.meta = meta;
             entry.last_seen = Instant::now();
             return;
         }
 
-        if let Err(e) = self.handle_new_stream(source_addr, key, meta) {
-            error!("Failed to create synced buffer: {e:#}");
+        match self.handle_new_stream(source_addr, key, meta) {
+            Ok(()) => {
+                if let Some((_, (_, frames))) = self.pre_meta.remove(&key) {
+                    info!(
+                        "Feeding {} frames received before metadata for stream {}",
+                        frames.len(),
+                        key.stream_id
+                    );
+                    for frame in frames {
+                        self.receive(source_addr, frame);
+                    }
+                }
+            }
+            Err(e) => {
+                self.pre_meta.remove(&key);
+                error!("Failed to create synced buffer: {e:#}");
+            }
+        }
+    }
+
+    /// Holds `frame` until its stream's metadata arrives, keeping the
+    /// earliest frames once the per-source cap is reached.
+    fn hold_pre_meta(&self, key: BufferKey, frame: SyncedFrame) {
+        let capacity = self.pre_meta_capacity.load(Ordering::Relaxed);
+        if capacity == 0 {
+            return;
+        }
+        if !self.pre_meta.contains_key(&key) && self.pre_meta.len() >= MAX_PRE_META_SOURCES {
+            return;
+        }
+        let mut pending = self
+            .pre_meta
+            .entry(key)
+            .or_insert_with(|| (Instant::now(), VecDeque::new()));
+        if pending.1.len() < capacity {
+            pending.1.push_back(frame);
         }
     }
 
@@ -204,7 +594,12 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                 &meta.codec_params.to_symphonia(),
                 &DecoderOptions::default(),
             )
-            .with_context(|| format!("create decoder for stream {stream_id}"))?;
+            .with_context(|| {
+                format!(
+                    "create {}-channel decoder for stream {stream_id}",
+                    meta.channels()
+                )
+            })?;
 
         let output_buffer_raw = SimpleBuffer::<Sample, CHANNELS, SAMPLE_RATE>::new();
         let output_buffer_raw_sink: Arc<_> = Arc::new(output_buffer_raw);
@@ -212,6 +607,7 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         let output_buffer_removed = SimpleBuffer::<Sample, CHANNELS, SAMPLE_RATE>::new();
         let output_buffer_removed_sink: Arc<_> = Arc::new(output_buffer_removed);
 
+        let decompressor_node = WireDecompressor::new(&meta.codec_params);
         let decoder_node = Arc::new(SymphoniaDecoder::<CHANNELS>::new(decoder));
         let to_output_rate_node_for_raw = Arc::new(
             FftResampler::<CHANNELS, SAMPLE_RATE>::new(meta.codec_params.sample_rate)
@@ -227,25 +623,54 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                 .with_context(|| format!("create no-vocal Opus decoder for stream {stream_id}"))?,
         );
 
-        // Wire: decoder → to_output_rate → interleaver → output_buffer_raw.
-        // The no-vocal track arrives as Opus and is decoded directly into
-        // output_buffer_removed in `receive()`.
+        let pitch_shifts = [(); 2].map(|_| {
+            let pitch = Arc::new(PitchShift::<Sample, CHANNELS, SAMPLE_RATE>::new());
+            pitch.set_semitones(meta.pitch_semitones.into());
+            pitch
+        });
+        let time_stretches = [(); 2].map(|_| {
+            let stretch = Arc::new(TimeStretch::<Sample, CHANNELS, SAMPLE_RATE>::new());
+            stretch.set_tempo(meta.tempo());
+            stretch
+        });
+
+        // Wire: decompressor → decoder → to_output_rate → interleaver → pitch → tempo
+        // → output_buffer_raw. The no-vocal track arrives as Opus; `receive()`
+        // decodes it into no_vocal_pipeline_head, which applies the same key
+        // and tempo changes before output_buffer_removed.
+        let original_pcm_head: Arc<dyn Pushable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>> = push_chain![
+            pitch_shifts[0].clone(),
+            time_stretches[0].clone(),
+            => output_buffer_raw_sink.clone()
+        ];
         let original_pipeline_head: Arc<dyn Pushable<CompressedPacket>> = push_chain![
+            decompressor_node,
             decoder_node.clone(),
             to_output_rate_node_for_raw.clone(),
             interleaver_node_for_raw.clone(),
-            => output_buffer_raw_sink.clone()
+            => original_pcm_head.clone()
+        ];
+        let no_vocal_pipeline_head: Arc<dyn Pushable<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>>> = push_chain![
+            pitch_shifts[1].clone(),
+            time_stretches[1].clone(),
+            => output_buffer_removed_sink.clone()
         ];
 
         let reset_dec = decoder_node.clone();
         let reset_to_output_rate_raw = to_output_rate_node_for_raw.clone();
         let reset_no_vocal_decoder = no_vocal_decoder.clone();
+        let reset_pitch_shifts = pitch_shifts.clone();
+        let reset_time_stretches = time_stretches.clone();
         let reset_buf_raw = output_buffer_raw_sink.clone();
         let reset_buf_removed = output_buffer_removed_sink.clone();
         let original_pipeline_reset: Box<dyn Fn() + Send + Sync> = Box::new(move || {
             reset_dec.reset();
             reset_to_output_rate_raw.reset();
             reset_no_vocal_decoder.reset();
+            reset_pitch_shifts.iter().for_each(|pitch| pitch.reset());
+            reset_time_stretches
+                .iter()
+                .for_each(|stretch| stretch.reset());
             reset_buf_raw.reset();
             reset_buf_removed.reset();
         });
@@ -272,7 +697,11 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
             key,
             BufferEntry {
                 original_pipeline_head,
+                original_pcm_head,
                 no_vocal_decoder,
+                no_vocal_pipeline_head,
+                pitch_shifts,
+                time_stretches,
                 output_selector,
                 output_buffer_raw: output_raw_for_push,
                 output_buffer_no_vocal: output_removed_for_push,
@@ -282,8 +711,10 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                 no_vocal_track: TrackReceiveState::new(),
                 last_seen: Instant::now(),
                 empty_since: Some(Instant::now()),
+                end: None,
                 playing: false,
                 start_party_time: 0,
+                max_elapsed_us: None,
                 samples_played: 0,
                 vocal_removal_active: false,
                 pending_vocal_removal: None,
@@ -299,6 +730,8 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
             SyncedControl::Start { stream_id, .. } => *stream_id,
             SyncedControl::Pause { stream_id } => *stream_id,
             SyncedControl::SetVocalRemoval { stream_id, .. } => *stream_id,
+            SyncedControl::Stop { stream_id } => *stream_id,
+            SyncedControl::End { stream_id, .. } => *stream_id,
         };
 
         let key = BufferKey {
@@ -306,6 +739,13 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
             stream_id,
         };
 
+        if let SyncedControl::Stop { .. } = control {
+            if self.buffers.remove(&key).is_some() {
+                info!("Stream {:?} stopped, buffer removed", key);
+            }
+            return;
+        }
+
         let Some(mut entry) = self.buffers.get_mut(&key) else {
             warn!("receive_control: StreamID {:?} not found", key);
             return;
@@ -320,6 +760,7 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
             } => {
                 entry.playing = true;
                 entry.start_party_time = party_clock_time;
+                entry.max_elapsed_us = None;
                 entry.last_seen = Instant::now();
                 // Reset samples_played so drift correction is relative to
                 // the new start_party_time, not accumulated from a prior session.
@@ -336,6 +777,26 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                     (entry.reset_decoder_states)();
                     entry.original_track.reset_to(seq);
                     entry.no_vocal_track.reset_to(no_vocal_seq);
+                    // The sender resends from here and ends the stream again.
+                    entry.end = None;
+
+                    // Wait for, and if need be ask for, the frames right
+                    // after the target, instead of the gap detection that
+                    // only sees gaps before frames that already arrived.
+                    let ready_frames = self.seek_ready_frames.load(Ordering::Relaxed);
+                    if ready_frames > 0 {
+                        let mut original_until = seq + ready_frames - 1;
+                        if entry.meta.total_frames > 0 {
+                            original_until = original_until.min(entry.meta.total_frames);
+                        }
+                        entry.original_track.seek_until = Some(original_until);
+                        entry.no_vocal_track.seek_until = Some(no_vocal_seq + ready_frames - 1);
+                        info!(
+                            "Stream {:?} seeking, waiting for seq {}..={}",
+                            key, seq, original_until
+                        );
+                        self.retransmit_now.notify_one();
+                    }
                 }
 
                 info!(
@@ -368,10 +829,32 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                     (party_clock_time as f64 - (self.party_now_fn)() as f64) / 1000000.0
                 );
             }
+            SyncedControl::End {
+                last_seq,
+                no_vocal_last_seq,
+                ..
+            } => {
+                entry.end = Some(StreamEnd {
+                    last_seq,
+                    no_vocal_last_seq,
+                    received_at: Instant::now(),
+                    flushed: false,
+                });
+                // A seek close to the end needs no frames past it.
+                entry.original_track.clamp_seek(last_seq);
+                entry.no_vocal_track.clamp_seek(no_vocal_last_seq);
+                entry.last_seen = Instant::now();
+                info!(
+                    "Stream {:?} ended at seq {} / no-vocal seq {}",
+                    key, last_seq, no_vocal_last_seq
+                );
+            }
+            SyncedControl::Stop { .. } => unreachable!("Stop is handled before lookup"),
         }
     }
 
-    /// Receives audio frame. Only stores if entry exists.
+    /// Receives audio frame. Frames without an entry are held for the
+    /// metadata when pre-meta buffering is enabled, and dropped otherwise.
     ///
     /// Compressed packets are pushed through the decode pipeline in sequence
     /// order. Out-of-order packets wait in `pending_raw` until predecessors
@@ -387,6 +870,7 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         // and must not block pull_and_mix (which needs iter_mut over the map).
         let action = {
             let Some(mut entry) = self.buffers.get_mut(&key) else {
+                self.hold_pre_meta(key, frame);
                 return;
             };
             let entry = &mut *entry;
@@ -399,7 +883,12 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                     if ready.is_empty() {
                         return;
                     }
-                    ReadyPackets::Original(entry.original_pipeline_head.clone(), ready)
+                    ReadyPackets::Original {
+                        pipeline_head: entry.original_pipeline_head.clone(),
+                        pcm_head: entry.original_pcm_head.clone(),
+                        source_sample_rate: entry.meta.codec_params.sample_rate,
+                        frames: ready,
+                    }
                 }
                 SyncedTrack::NoVocal => {
                     let ready = Self::collect_ready_frames(&mut entry.no_vocal_track, frame);
@@ -408,7 +897,7 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                     }
                     ReadyPackets::NoVocal(
                         entry.no_vocal_decoder.clone(),
-                        entry.output_buffer_no_vocal.clone(),
+                        entry.no_vocal_pipeline_head.clone(),
                         ready,
                     )
                 }
@@ -416,29 +905,48 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         };
 
         match action {
-            ReadyPackets::Original(pipeline_head, frames) => {
-                for frame in frames {
+            ReadyPackets::Original {
+                pipeline_head,
+                pcm_head,
+                source_sample_rate,
+                frames,
+            } => {
+                for (skipped, frame) in frames {
+                    // Keeps the decoded audio on the senders' timeline, which
+                    // is what the playback position in `mix_into` counts.
+                    let silent_frames =
+                        skipped * SAMPLE_RATE as u64 / source_sample_rate.max(1) as u64;
+                    if let Some(silence) = Self::silence(silent_frames as usize) {
+                        pcm_head.push(silence);
+                    }
                     pipeline_head.push(CompressedPacket {
                         dur: frame.dur,
                         data: frame.data,
                     });
                 }
             }
-            ReadyPackets::NoVocal(decoder, output, frames) => {
-                for frame in frames {
+            ReadyPackets::NoVocal(decoder, pipeline_head, frames) => {
+                for (_, frame) in frames {
                     let packet = OpusPacket {
                         data: frame.data,
                         frame_size: frame.dur as usize * CHANNELS,
+                        codec: AudioCodec::Opus,
+                        bitrate: 0,
                     };
                     if let Some(decoded) = decoder.decode_packet(&packet) {
-                        output.push(decoded);
+                        pipeline_head.push(decoded);
                     }
                 }
             }
         }
     }
 
-    fn collect_ready_frames(track: &mut TrackReceiveState, frame: SyncedFrame) -> Vec<SyncedFrame> {
+    /// Frames ready to feed in order now that `frame` is in, each with the
+    /// source samples skipped before it.
+    fn collect_ready_frames(
+        track: &mut TrackReceiveState,
+        frame: SyncedFrame,
+    ) -> Vec<(u64, SyncedFrame)> {
         let seq = frame.sequence_number;
 
         // Duplicate or old frame.
@@ -460,14 +968,12 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
 
         // Collect ready packets in sequence order.
         if seq == track.next_feed_seq {
-            track.packet_counter.record_packet(seq);
-            frames.push(frame);
+            frames.push((track.record_fed(&frame), frame));
             track.next_feed_seq += 1;
 
             // Drain any consecutive pending packets.
             while let Some(pending) = track.pending_raw.remove(&track.next_feed_seq) {
-                track.packet_counter.record_packet(track.next_feed_seq);
-                frames.push(pending);
+                frames.push((track.record_fed(&pending), pending));
                 track.next_feed_seq += 1;
             }
         } else {
@@ -500,6 +1006,7 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                 total,
                 received_count: 0,
                 dur: frame.dur,
+                sample_offset: frame.sample_offset,
                 parts: vec![None; total as usize],
             });
 
@@ -528,7 +1035,141 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
             data.extend_from_slice(&part);
         }
 
-        Some(SyncedFrame::for_track(track, stream_id, seq, set.dur, data))
+        Some(
+            SyncedFrame::for_track(track, stream_id, seq, set.dur, data)
+                .with_sample_offset(set.sample_offset),
+        )
+    }
+
+    /// Feeds out streams whose sender ended them more than the gap grace
+    /// ago: packets received so far are decoded in order and the ones
+    /// still missing become silence, so playback runs to the end instead
+    /// of stalling at the first gap.
+    pub fn flush_ended(&self) {
+        let grace_us = self.end_gap_grace_us.load(Ordering::Relaxed);
+        if grace_us == u64::MAX {
+            return;
+        }
+        let grace = Duration::from_micros(grace_us);
+
+        // Drain under the map lock, decode after releasing it as `receive()`
+        // does.
+        let mut flushes = Vec::new();
+        for mut entry in self.buffers.iter_mut() {
+            let Some(end) = entry.end else {
+                continue;
+            };
+            if end.flushed || end.received_at.elapsed() < grace {
+                continue;
+            }
+            let entry = &mut *entry;
+            let original_dur = entry.original_track.last_dur;
+            let original_fed_until = entry.original_track.fed_until;
+            let no_vocal_dur = entry.no_vocal_track.last_dur;
+            let original = entry.original_track.drain_through(end.last_seq);
+            let no_vocal = entry.no_vocal_track.drain_through(end.no_vocal_last_seq);
+            // Nothing fed yet: size leading gaps like the first frame that did arrive.
+            let first_dur = |frames: &[Option<SyncedFrame>]| {
+                frames.iter().flatten().next().map_or(0, |frame| frame.dur)
+            };
+            let original_dur = if original_dur > 0 {
+                original_dur
+            } else {
+                first_dur(&original)
+            };
+            let no_vocal_dur = if no_vocal_dur > 0 {
+                no_vocal_dur
+            } else {
+                first_dur(&no_vocal)
+            };
+            entry.end = Some(StreamEnd {
+                flushed: true,
+                ..end
+            });
+
+            let missing = original.iter().filter(|f| f.is_none()).count()
+                + no_vocal.iter().filter(|f| f.is_none()).count();
+            if missing > 0 {
+                warn!(
+                    "Stream {} ended with {} packets missing, playing silence in their place",
+                    entry.meta.stream_id, missing
+                );
+            }
+            flushes.push(EndFlush {
+                original_pipeline_head: entry.original_pipeline_head.clone(),
+                original_pcm_head: entry.original_pcm_head.clone(),
+                no_vocal_decoder: entry.no_vocal_decoder.clone(),
+                no_vocal_pipeline_head: entry.no_vocal_pipeline_head.clone(),
+                source_sample_rate: entry.meta.codec_params.sample_rate,
+                original_dur,
+                original_fed_until,
+                no_vocal_dur,
+                original,
+                no_vocal,
+            });
+        }
+
+        for mut flush in flushes {
+            let mut fed_until = flush.original_fed_until;
+            let mut original = flush.original.into_iter().peekable();
+            while let Some(frame) = original.next() {
+                match frame {
+                    Some(frame) => {
+                        flush.original_dur = frame.dur;
+                        fed_until = frame.sample_offset.map(|offset| offset + frame.dur as u64);
+                        flush.original_pipeline_head.push(CompressedPacket {
+                            dur: frame.dur,
+                            data: frame.data,
+                        });
+                    }
+                    None => {
+                        let mut missing = 1;
+                        while original.next_if(Option::is_none).is_some() {
+                            missing += 1;
+                        }
+                        let next_offset = original
+                            .peek()
+                            .and_then(|frame| frame.as_ref()?.sample_offset);
+                        let gap = gap_samples(fed_until, next_offset, missing, flush.original_dur);
+                        // Silence enters after resampling, so size it at
+                        // the output rate.
+                        let frames =
+                            gap * SAMPLE_RATE as u64 / flush.source_sample_rate.max(1) as u64;
+                        if let Some(silence) = Self::silence(frames as usize) {
+                            flush.original_pcm_head.push(silence);
+                        }
+                    }
+                }
+            }
+            for frame in flush.no_vocal {
+                match frame {
+                    Some(frame) => {
+                        flush.no_vocal_dur = frame.dur;
+                        let packet = OpusPacket {
+                            data: frame.data,
+                            frame_size: frame.dur as usize * CHANNELS,
+                            codec: AudioCodec::Opus,
+                            bitrate: 0,
+                        };
+                        if let Some(decoded) = flush.no_vocal_decoder.decode_packet(&packet) {
+                            flush.no_vocal_pipeline_head.push(decoded);
+                        }
+                    }
+                    None => {
+                        if let Some(silence) = Self::silence(flush.no_vocal_dur as usize) {
+                            flush.no_vocal_pipeline_head.push(silence);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn silence(frames: usize) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        if frames == 0 {
+            return None;
+        }
+        AudioBuffer::new(vec![Sample::silence(); frames * CHANNELS]).ok()
     }
 
     /// Pulls samples from all streams and mixes them together.
@@ -539,14 +1180,42 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         &self,
         num_frames: usize,
     ) -> Option<AudioBuffer<Sample, CHANNELS, SAMPLE_RATE>> {
+        let mut out = vec![Sample::silence(); num_frames * CHANNELS];
+        let written = self.mix_into(&mut out, num_frames * CHANNELS)?;
+        out.truncate(written);
+        AudioBuffer::new(out).ok()
+    }
+
+    /// Like [`pull_and_mix`](Self::pull_and_mix), but mixes `len` samples
+    /// into `out` instead of allocating. Returns how many samples were
+    /// written, or `None` if no stream played.
+    pub fn mix_into(&self, out: &mut [Sample], len: usize) -> Option<usize> {
+        let num_frames = len.min(out.len()) / CHANNELS;
+        // Nothing requested: don't advance playheads, drift correction or ducking.
+        if num_frames == 0 {
+            return Some(0);
+        }
+        // Playheads stay put, so streams start at the synced clock's
+        // position rather than wherever the unsynced one put them.
+        if self.awaiting_clock_sync() {
+            return None;
+        }
+
         let party_now = (self.party_now_fn)();
         let num_samples = num_frames * CHANNELS;
-        let mut mixed: Vec<i64> = vec![0i64; num_samples];
+        let mut mixed = self.mix_scratch.lock().unwrap();
+        mixed.clear();
+        mixed.resize(num_samples, 0);
+        let mut stretched = self.stretch_scratch.lock().unwrap();
         let mut source_count = 0usize;
         let mut actual_len = 0usize;
 
-        // 10ms lag threshold before we attempt drift correction.
-        let drift_threshold = SAMPLE_RATE as u64 * 10 / 1000;
+        let resync_threshold = self.resync_threshold_frames.load(Ordering::Relaxed);
+        // The party clock is read once per callback, so drift within one
+        // callback is just sampling granularity and left alone.
+        let dead_band = num_frames as u64;
+        let max_correction = (num_frames / MAX_CORRECTION_RATIO).max(1) as i64;
+        let monotonic = self.monotonic_party_time.load(Ordering::Relaxed);
 
         for mut entry in self.buffers.iter_mut() {
             if let Some((enabled, switch_at)) = entry.pending_vocal_removal {
@@ -557,15 +1226,28 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                 }
             }
 
-            if !entry.playing || entry.start_party_time > party_now {
+            // Once started, a stream keeps playing even if the clock steps
+            // back to before its start.
+            let started = entry.start_party_time <= party_now
+                || (monotonic && entry.max_elapsed_us.is_some());
+            if !entry.playing || !started {
                 continue;
             }
 
             // Drift correction relative to party clock.
-            let elapsed_us = party_now.saturating_sub(entry.start_party_time);
+            let mut elapsed_us = party_now.saturating_sub(entry.start_party_time);
+            if monotonic {
+                // The party clock can step back, e.g. on an NTP correction.
+                // Hold the position until it catches up instead of
+                // rewinding.
+                elapsed_us = elapsed_us.max(entry.max_elapsed_us.unwrap_or(0));
+                entry.max_elapsed_us = Some(elapsed_us);
+            }
             let expected_samples = elapsed_us * SAMPLE_RATE as u64 / 1_000_000;
 
-            if entry.samples_played + drift_threshold < expected_samples {
+            // Frames to play this callback to converge on the party clock.
+            let mut source_frames = num_frames;
+            if entry.samples_played + resync_threshold < expected_samples {
                 // Lagging: advance the selector's logical position. Each
                 // underlying buffer discards what it has now and records any
                 // remaining debt until missing packets arrive.
@@ -578,7 +1260,7 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                     expected_samples as f64 * 1000.0 / SAMPLE_RATE as f64,
                 );
                 entry.samples_played = expected_samples;
-            } else if expected_samples + drift_threshold < entry.samples_played {
+            } else if expected_samples + resync_threshold < entry.samples_played {
                 // Ahead: hold back by contributing silence this callback.
                 // Don't pull and don't advance samples_played — let the party
                 // clock catch up before resuming normal output.
@@ -587,13 +1269,20 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                     (entry.samples_played - expected_samples) as f64 * 1000.0 / SAMPLE_RATE as f64
                 );
                 continue;
+            } else if entry.samples_played.abs_diff(expected_samples) > dead_band {
+                // Small drift: consume a few frames more or less than we
+                // output, stretched to fit, so the position converges
+                // without a jump.
+                let drift = expected_samples as i64 - entry.samples_played as i64;
+                let correction = drift.clamp(-max_correction, max_correction);
+                source_frames = (num_frames as i64 + correction) as usize;
             }
 
             entry
                 .output_selector
                 .set_selected(if entry.vocal_removal_active { 1 } else { 0 });
 
-            let Some(buf) = entry.output_selector.pull(num_samples) else {
+            let Some(buf) = entry.output_selector.pull(source_frames * CHANNELS) else {
                 // The output callback still advances when this stream has no
                 // decoded data. Keep this stream's read position aligned with
                 // the party clock so late packets are discarded instead of
@@ -605,28 +1294,47 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
             };
 
             source_count += 1;
-            let buf_data = buf.data();
+            entry.samples_played += buf.data().len() as u64 / CHANNELS as u64;
+            let buf_data =
+                if source_frames != num_frames && buf.data().len() == source_frames * CHANNELS {
+                    stretch_frames::<Sample, CHANNELS>(buf.data(), num_frames, &mut stretched);
+                    &stretched[..]
+                } else {
+                    buf.data()
+                };
             actual_len = actual_len.max(buf_data.len());
-            entry.samples_played += buf_data.len() as u64 / CHANNELS as u64;
             for (i, sample) in buf_data.iter().enumerate() {
                 mixed[i] += sample.to_i64_for_mix();
             }
         }
 
+        if let Some(balance) = self.auto_balance.lock().unwrap().as_ref() {
+            balance.process::<Sample>(
+                &mut mixed[..actual_len],
+                source_count,
+                CHANNELS,
+                SAMPLE_RATE,
+            );
+        }
+        if let Some(ducker) = &self.ducker {
+            ducker.process(&mut mixed[..actual_len], num_frames, CHANNELS, SAMPLE_RATE);
+        }
+
         if source_count == 0 {
             return None;
         }
 
-        // Only return the actual amount of audio produced, not the full requested size.
-        let result: Vec<Sample> = mixed[..actual_len]
-            .iter()
-            .map(|s| Sample::from_i64_mixed(*s, source_count))
-            .collect();
-        AudioBuffer::new(result).ok()
+        // Only write the actual amount of audio produced, not the full requested size.
+        for (out, mixed) in out.iter_mut().zip(&mixed[..actual_len]) {
+            *out = Sample::from_i64_mixed(*mixed, source_count);
+        }
+        Some(actual_len)
     }
 
     pub fn cleanup_stale(&self) {
         let now = Instant::now();
+        self.pre_meta
+            .retain(|_, (since, _)| now.duration_since(*since) < SYNCED_STREAM_TIMEOUT);
         self.buffers.retain(|key, entry| {
             let buffer_empty =
                 entry.output_buffer_raw.is_empty() && entry.output_buffer_no_vocal.is_empty();
@@ -649,32 +1357,93 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         });
     }
 
+    /// Drops every stream from `source` at once, for a sender that left.
+    /// Returns how many there were.
+    pub fn remove_source(&self, source: SocketAddr) -> usize {
+        self.pre_meta.retain(|key, _| key.source_addr != source);
+        let before = self.buffers.len();
+        self.buffers.retain(|key, _| key.source_addr != source);
+        let removed = before - self.buffers.len();
+        if removed > 0 {
+            info!(
+                "Removing {} synced buffer(s) for {} (left)",
+                removed, source
+            );
+        }
+        removed
+    }
+
     pub fn active_streams(&self) -> Vec<SyncedStreamState> {
         let mut result = Vec::new();
 
         for entry in self.buffers.iter() {
             let is_local_sender =
                 entry.key().source_addr.ip().is_loopback() && entry.key().source_addr.port() == 0;
+            let selected_buffer = if entry.vocal_removal_active {
+                &entry.output_buffer_no_vocal
+            } else {
+                &entry.output_buffer_raw
+            };
+            let is_complete =
+                entry.end.is_some_and(|end| end.flushed) && selected_buffer.is_empty();
+            let selected_track = if entry.vocal_removal_active {
+                &entry.no_vocal_track
+            } else {
+                &entry.original_track
+            };
 
             result.push(SyncedStreamState {
                 stream_id: entry.key().stream_id,
                 meta: entry.meta.clone(),
                 progress: SyncedStreamProgress {
-                    samples_played: entry.samples_played,
+                    // Lost packets can leave the count short of the total.
+                    samples_played: if is_complete {
+                        entry.samples_played.max(entry.meta.total_samples)
+                    } else {
+                        entry.samples_played
+                    },
                     total_samples: entry.meta.total_samples,
                     buffered_frames: entry.original_track.packet_counter.packets_pushed(),
                     is_playing: entry.playing,
                     highest_seq_received: entry.original_track.packet_counter.highest_seq(),
                     start_party_time: entry.start_party_time,
+                    is_complete,
+                    is_seeking: selected_track.seeking(),
                 },
                 is_local_sender,
+                tags: if is_local_sender {
+                    vec!["You".to_string()]
+                } else {
+                    Vec::new()
+                },
             });
         }
 
         result
     }
 
+    /// Streams that are playing and past their start time.
+    pub fn playing_stream_count(&self) -> usize {
+        let party_now = (self.party_now_fn)();
+        self.buffers
+            .iter()
+            .filter(|entry| entry.playing && entry.start_party_time <= party_now)
+            .count()
+    }
+
+    /// Forgives the frames each stream still owes its output buffers after
+    /// lagging, so output resumes with whatever is decoded.
+    pub fn reset_pull_path(&self) {
+        for entry in self.buffers.iter() {
+            let position = entry.output_selector.position();
+            entry.output_selector.reset_to(position);
+        }
+    }
+
     /// Identifies gaps in the received packets and returns them for retransmission.
+    ///
+    /// Each list is ordered nearest-to-playhead first, so when the request
+    /// cap cuts it short the packets needed soonest are the ones asked for.
     pub fn get_missing_frames(&self) -> Vec<(SocketAddr, SyncedStreamId, SyncedTrack, Vec<u64>)> {
         let mut result = Vec::new();
 
@@ -683,7 +1452,7 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                 (SyncedTrack::Original, &entry.original_track),
                 (SyncedTrack::NoVocal, &entry.no_vocal_track),
             ] {
-                let Some(missing) = Self::missing_for_track(state) else {
+                let Some(missing) = Self::missing_for_track(state, self.retransmit_window) else {
                     continue;
                 };
                 result.push((
@@ -697,24 +1466,32 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         result
     }
 
-    fn missing_for_track(state: &TrackReceiveState) -> Option<Vec<u64>> {
+    fn missing_for_track(state: &TrackReceiveState, window: RetransmitWindow) -> Option<Vec<u64>> {
         let next_feed = state.next_feed_seq;
 
         // The highest seq we've actually received is the max of
         // (next_feed_seq - 1) and pending_raw keys. If pending_raw is
-        // empty, everything up to next_feed_seq has been fed — no gaps.
-        let Some(&highest) = state.pending_raw.keys().max() else {
+        // empty, everything up to next_feed_seq has been fed — no gaps,
+        // unless a seek still needs the frames after its target.
+        let seek_end = state
+            .seek_until
+            .filter(|_| state.seeking())
+            .map(|last| last + 1);
+        let Some(highest) = state.pending_raw.keys().max().copied().max(seek_end) else {
             return None;
         };
 
-        // Scan gaps between next_feed_seq and the highest pending packet.
+        // Scan gaps upward from next_feed_seq (the playhead of the decode
+        // pipeline), so results come out nearest-first, bounded by both the
+        // highest pending packet and the lookahead window.
+        let window_end = next_feed.saturating_add(window.lookahead);
         let mut missing = Vec::new();
-        for seq in next_feed..highest {
+        for seq in next_feed..highest.min(window_end) {
+            if missing.len() >= window.max_requests {
+                break;
+            }
             if !state.pending_raw.contains_key(&seq) {
                 missing.push(seq);
-                if missing.len() >= 100 {
-                    break;
-                }
             }
         }
 
@@ -730,6 +1507,7 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
             let mut interval = tokio::time::interval(Duration::from_secs(1));
             loop {
                 interval.tick().await;
+                stream.flush_ended();
                 stream.cleanup_stale();
             }
         });
@@ -737,7 +1515,8 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
 
     /// Starts the background retransmit request task.
     ///
-    /// Periodically checks for missing frames and sends retransmission requests.
+    /// Periodically checks for missing frames and sends retransmission requests,
+    /// and right away after a seek.
     /// Must be called from within a Tokio runtime context.
     pub fn start_retransmit_task(self: &Arc<Self>, sender: crate::io::NetworkSender) {
         use crate::pipeline::Pushable;
@@ -746,7 +1525,10 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_millis(200));
             loop {
-                interval.tick().await;
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = stream.retransmit_now.notified() => {}
+                }
                 for (_addr, stream_id, track, seqs) in stream.get_missing_frames() {
                     let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&RequestFramesPayload {
                         stream_id,
@@ -755,23 +1537,16 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                     })
                     .expect("RequestFramesPayload serialization")
                     .into_vec();
-                    sender.push(TaggedPacket {
-                        tag: REQUEST_FRAMES_TAG,
-                        payload,
-                    });
+                    sender.push(TaggedPacket::new(REQUEST_FRAMES_TAG, payload));
                 }
             }
         });
     }
 
-    pub fn start_view_task(self: &Arc<Self>, view_state: Arc<PartyViewState>) {
+    pub fn start_view_task(self: &Arc<Self>, view_state: Arc<PartyViewState>, interval: Duration) {
         let stream = self.clone();
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(Duration::from_millis(100));
-            loop {
-                interval.tick().await;
-                view_state.set_synced_streams(stream.active_streams());
-            }
+        spawn_stats_task(interval, move || {
+            view_state.set_synced_streams(stream.active_streams())
         });
     }
 }
@@ -780,7 +1555,16 @@ impl<S: AudioSample, const C: usize, const SR: u32> NetworkStream<S, C, SR>
     for SyncedAudioStreamManager<S, C, SR>
 {
     fn tags(&self) -> &'static [PacketTag] {
-        &[SYNCED_TAG, SYNCED_META_TAG, SYNCED_CONTROL_TAG]
+        &[
+            SYNCED_TAG,
+            SYNCED_BATCH_TAG,
+            SYNCED_META_TAG,
+            SYNCED_CONTROL_TAG,
+        ]
+    }
+
+    fn forget(&self, source: SocketAddr) {
+        self.remove_source(source);
     }
 
     fn handle(&self, source: SocketAddr, tag: PacketTag, bytes: &[u8]) -> anyhow::Result<()> {
@@ -790,6 +1574,11 @@ impl<S: AudioSample, const C: usize, const SR: u32> NetworkStream<S, C, SR>
                     .map_err(|e| anyhow::anyhow!("SyncedFrame deserialize: {:?}", e))?;
                 self.receive(source, frame);
             }
+            SYNCED_BATCH_TAG => {
+                for frame in coalesce::unpack(bytes)? {
+                    self.receive(source, frame);
+                }
+            }
             SYNCED_META_TAG => {
                 let meta = rkyv::from_bytes::<SyncedStreamMeta, rkyv::rancor::Error>(bytes)
                     .map_err(|e| anyhow::anyhow!("SyncedStreamMeta deserialize: {:?}", e))?;
@@ -808,7 +1597,7 @@ impl<S: AudioSample, const C: usize, const SR: u32> NetworkStream<S, C, SR>
     fn start(self: Arc<Self>, ctx: NetworkStreamContext) {
         self.start_cleanup_task();
         self.start_retransmit_task(ctx.sender);
-        self.start_view_task(ctx.view_state);
+        self.start_view_task(ctx.view_state, ctx.stats_interval);
     }
 }
 
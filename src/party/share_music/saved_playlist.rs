@@ -0,0 +1,255 @@
+//! Named playlists saved to disk.
+//!
+//! A [`SavedPlaylist`] is just a name and the local files queued under it,
+//! with their titles, stored as `<name>.json` in the playlist directory.
+//! Loading reads the files back for the shared playlist; files that were
+//! moved or deleted since saving are skipped with a warning.
+//!
+//! Playlists saved before titles were kept (format 1) only have paths;
+//! they load with each file's name as its title.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::warn;
+
+use crate::party::config::{Migration, from_versioned_json, to_versioned_json};
+
+/// Format changes of saved playlists, oldest first.
+const MIGRATIONS: [Migration; 1] = [titles_from_paths];
+
+/// A named list of local audio files.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SavedPlaylist {
+    pub name: String,
+    pub tracks: Vec<SavedTrack>,
+}
+
+/// A file in a saved playlist, and the title it was queued under.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SavedTrack {
+    pub path: PathBuf,
+    pub title: String,
+}
+
+/// A file read from a saved playlist, ready to queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedTrack {
+    pub path: PathBuf,
+    pub title: String,
+    pub data: Vec<u8>,
+}
+
+/// Where playlists are saved unless configured otherwise: the platform data
+/// directory, falling back to the working directory.
+pub fn default_dir() -> PathBuf {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .unwrap_or_default();
+    data_dir.join("wifi-party").join("playlists")
+}
+
+/// Display name of a queued file: its file name, or the whole path.
+pub fn track_title(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn playlist_file(dir: &Path, name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        bail!("Invalid playlist name {name:?}");
+    }
+    Ok(dir.join(format!("{name}.json")))
+}
+
+/// Format 1 to 2: a list of paths becomes tracks titled by file name.
+fn titles_from_paths(playlist: &mut Value) -> Result<()> {
+    let Some(paths) = playlist.as_object_mut().and_then(|p| p.remove("paths")) else {
+        bail!("No paths in format 1 playlist");
+    };
+    let paths: Vec<PathBuf> = serde_json::from_value(paths)?;
+    let tracks: Vec<Value> = paths
+        .iter()
+        .map(|path| json!({ "path": path, "title": track_title(path) }))
+        .collect();
+    playlist["tracks"] = tracks.into();
+    Ok(())
+}
+
+impl SavedPlaylist {
+    pub fn new(name: impl Into<String>, tracks: Vec<SavedTrack>) -> Self {
+        Self {
+            name: name.into(),
+            tracks,
+        }
+    }
+
+    /// Writes the playlist to `dir`, replacing one saved under the same name.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf> {
+        let file = playlist_file(dir, &self.name)?;
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let json = to_versioned_json(self, &MIGRATIONS)?;
+        std::fs::write(&file, json)
+            .with_context(|| format!("Failed to write {}", file.display()))?;
+        Ok(file)
+    }
+
+    /// Reads the playlist saved as `name` in `dir`, in any format it was
+    /// saved in.
+    pub fn load(dir: &Path, name: &str) -> Result<Self> {
+        let file = playlist_file(dir, name)?;
+        let json = std::fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        from_versioned_json(&json, &format!("playlist {name:?}"), &MIGRATIONS)
+            .with_context(|| format!("Malformed {}", file.display()))
+    }
+
+    /// Removes the playlist saved as `name` from `dir`.
+    pub fn delete(dir: &Path, name: &str) -> Result<()> {
+        let file = playlist_file(dir, name)?;
+        std::fs::remove_file(&file).with_context(|| format!("Failed to delete {}", file.display()))
+    }
+
+    /// Names of all playlists saved in `dir`, sorted. A missing directory
+    /// has none.
+    pub fn list(dir: &Path) -> Result<Vec<String>> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "json" {
+                    return None;
+                }
+                Some(path.file_stem()?.to_string_lossy().into_owned())
+            })
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Reads every file in the playlist, in order, skipping the ones that
+    /// no longer exist or can't be read.
+    pub fn read_tracks(&self) -> Vec<LoadedTrack> {
+        self.tracks
+            .iter()
+            .filter_map(|track| match std::fs::read(&track.path) {
+                Ok(data) => Some(LoadedTrack {
+                    path: track.path.clone(),
+                    title: track.title.clone(),
+                    data,
+                }),
+                Err(e) => {
+                    warn!(
+                        "Skipping {} from playlist {:?}: {}",
+                        track.path.display(),
+                        self.name,
+                        e
+                    );
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn playlist_roundtrips_and_skips_missing_files() {
+        let dir = std::env::temp_dir().join(format!("wifi-party-playlists-{}", std::process::id()));
+        let songs = dir.join("songs");
+        std::fs::create_dir_all(&songs).unwrap();
+        let first = songs.join("first.wav");
+        let gone = songs.join("gone.mp3");
+        let last = songs.join("last.flac");
+        std::fs::write(&first, b"first").unwrap();
+        std::fs::write(&gone, b"gone").unwrap();
+        std::fs::write(&last, b"last").unwrap();
+
+        let playlist = SavedPlaylist::new(
+            "Friday night",
+            [&first, &gone, &last]
+                .map(|path| SavedTrack {
+                    path: path.clone(),
+                    title: track_title(path),
+                })
+                .to_vec(),
+        );
+        playlist.save(&dir).unwrap();
+        assert_eq!(
+            SavedPlaylist::list(&dir).unwrap(),
+            vec!["Friday night".to_string()]
+        );
+
+        let loaded = SavedPlaylist::load(&dir, "Friday night").unwrap();
+        assert_eq!(loaded, playlist);
+
+        std::fs::remove_file(&gone).unwrap();
+        let tracks = loaded.read_tracks();
+        let titles: Vec<_> = tracks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, ["first.wav", "last.flac"]);
+        assert_eq!(tracks[0].data, b"first");
+        assert_eq!(tracks[1].path, last);
+
+        assert!(SavedPlaylist::load(&dir, "../escape").is_err());
+        SavedPlaylist::delete(&dir, "Friday night").unwrap();
+        assert!(SavedPlaylist::list(&dir).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn format_1_playlist_migrates_keeping_its_files() {
+        let dir = std::env::temp_dir().join(format!("wifi-party-migrate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // As saved before playlists were versioned.
+        let v1 = json!({
+            "name": "Road trip",
+            "paths": ["/music/first.flac", "/music/second.mp3"],
+        });
+        std::fs::write(dir.join("Road trip.json"), v1.to_string()).unwrap();
+
+        let loaded = SavedPlaylist::load(&dir, "Road trip").unwrap();
+        assert_eq!(loaded.name, "Road trip");
+        let tracks: Vec<(&Path, &str)> = loaded
+            .tracks
+            .iter()
+            .map(|t| (t.path.as_path(), t.title.as_str()))
+            .collect();
+        assert_eq!(
+            tracks,
+            [
+                (Path::new("/music/first.flac"), "first.flac"),
+                (Path::new("/music/second.mp3"), "second.mp3"),
+            ]
+        );
+
+        // Saved again, it's written in the current format and reads back
+        // without migrating.
+        let file = loaded.save(&dir).unwrap();
+        let saved: Value = serde_json::from_str(&std::fs::read_to_string(file).unwrap()).unwrap();
+        assert_eq!(saved["version"], MIGRATIONS.len() + 1);
+        assert!(saved.get("paths").is_none());
+        assert_eq!(SavedPlaylist::load(&dir, "Road trip").unwrap(), loaded);
+
+        // A file from a newer build isn't misread.
+        let future = json!({ "version": 99, "name": "Later", "tracks": [] });
+        std::fs::write(dir.join("Later.json"), future.to_string()).unwrap();
+        assert!(SavedPlaylist::load(&dir, "Later").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -5,7 +5,7 @@
 //! - Fast-than-realtime streaming (2x speed)
 //! - Redundant packet transmission (2x redundancy)
 //! - Handling retransmission requests from peers
-//! - Playback control (Play, Pause, Seek)
+//! - Playback control (Play, Pause, Seek, A–B loop)
 
 use std::collections::VecDeque;
 use std::io::Cursor;
@@ -19,23 +19,30 @@ use anyhow::{Context, Result, anyhow};
 use dashmap::DashMap;
 use symphonia::core::codecs::{CODEC_TYPE_NULL, DecoderOptions};
 use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
-use symphonia::core::io::MediaSourceStream;
+use symphonia::core::io::{MediaSource, MediaSourceStream};
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
+use symphonia::core::units::TimeBase;
 use tracing::{debug, error, info, warn};
 
-use crate::audio::decoders::{CompressedPacket, FftResampler, Interleaver, SymphoniaDecoder};
+use crate::audio::decoders::{
+    CompressedPacket, FftResampler, Interleaver, SymphoniaDecoder, WireCompression,
+    WireDecompressor, compress_for_wire,
+};
 use crate::audio::effects::DecodedVocalRemover;
+use crate::audio::file::HttpSource;
 use crate::audio::frame::AudioBuffer;
 use crate::audio::symphonia_compat::WireCodecParams;
-use crate::audio::{AudioSample, OpusEncoder};
+use crate::audio::{AudioSample, ChannelCoupling, OpusEncoder, OpusFrameDuration};
 use crate::io::NetworkSender;
 use crate::party::network_stream::NetworkStream;
 use crate::party::ntp::NtpService;
+use crate::party::share_music::coalesce::{CoalesceConfig, FrameCoalescer};
 use crate::party::share_music::receiver::SyncedAudioStreamManager;
+use crate::party::share_music::stereo_analysis::StereoAnalyzer;
 use crate::party::share_music::{
-    MAX_FRAGMENT_DATA, RawPacket, RequestFramesPayload, SyncedControl, SyncedFrame, SyncedStreamId,
-    SyncedStreamMeta, SyncedTrack, new_stream_id,
+    LoopRegion, MAX_FRAGMENT_DATA, RawPacket, RequestFramesPayload, SyncedControl, SyncedFrame,
+    SyncedStreamId, SyncedStreamMeta, SyncedTrack, new_stream_id,
 };
 use crate::party::tagged_packet::{
     PacketTag, REQUEST_FRAMES_TAG, SYNCED_CONTROL_TAG, SYNCED_META_TAG, SYNCED_TAG, TaggedPacket,
@@ -46,14 +53,27 @@ use crate::state::MusicStreamProgress;
 const LOCAL_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
 const SEND_RATE_MULTIPLIER: u32 = 2;
 const REDUNDANCY_COUNT: usize = 2;
-const NO_VOCAL_OPUS_FRAME_MS: u32 = 20;
 const VOCAL_REMOVER_SAMPLE_RATE: u32 = 44_100;
+/// A loop end noticed later than this, in microseconds, was already behind
+/// playback when the loop was set; playback jumps back right away instead
+/// of at that time in the past.
+const LOOP_WRAP_LATENESS_US: u64 = 100_000;
+/// Seconds from the start of a song decoded to decide on mono coding.
+const MONO_ANALYSIS_SECS: u64 = 10;
+/// Packets read from the source per loop iteration at most.
+const READ_BATCH: usize = 100;
+/// How long packets are kept after they play when reading ahead, for
+/// retransmit requests from receivers running a little behind.
+const RETRANSMIT_KEEP: Duration = Duration::from_secs(2);
 
 enum MusicCommand {
     Retransmit(SyncedTrack, Vec<u64>),
     Pause,
     Resume,
     Seek(u64),
+    SetPitch(i8),
+    SetTempo(u16),
+    SetLoop(Option<LoopRegion>),
 }
 
 /// Handle for controlling an active music stream.
@@ -62,25 +82,40 @@ pub struct MusicStream {
     stream_id: SyncedStreamId,
     is_running: Arc<AtomicBool>,
     command_tx: std::sync::mpsc::Sender<MusicCommand>,
-    _handle: Option<thread::JoinHandle<()>>,
+    handle: Option<thread::JoinHandle<()>>,
 }
 
 impl MusicStream {
-    pub fn start<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u32>(
-        data: Vec<u8>,
+    fn start<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u32>(
+        media: Box<dyn MediaSource>,
         file_name: String,
-        ntp_service: Arc<NtpService>,
-        network_sender: NetworkSender,
-        synced_stream: Arc<SyncedAudioStreamManager<Sample, CHANNELS, SAMPLE_RATE>>,
         progress: Arc<MusicStreamProgress>,
-        vocal_removal_enabled: Arc<AtomicBool>,
+        deps: MusicStreamDeps<Sample, CHANNELS, SAMPLE_RATE>,
     ) -> Result<Self> {
         info!("Starting music stream for: {}", file_name);
 
+        let MusicStreamDeps {
+            ntp_service,
+            network_sender,
+            synced_stream,
+            vocal_removal_enabled,
+            compress_pcm,
+            no_vocal_frame_duration,
+            coalesce,
+            auto_mono,
+            redundant,
+            read_ahead,
+            sample_offsets,
+        } = deps;
+
         let extension = file_name.rsplit('.').next().map(|s| s.to_lowercase());
-        let source = AudioSource::open(data, extension.as_deref())?;
-        let codec_params = WireCodecParams::from_symphonia(&source.codec_params())
+        let source = AudioSource::open(media, extension.as_deref())?;
+        let mut codec_params = WireCodecParams::from_symphonia(&source.codec_params())
             .ok_or_else(|| anyhow!("Unsupported codec"))?;
+        if compress_pcm && codec_params.codec.pcm_bytes_per_sample().is_some() {
+            info!("Compressing PCM packets on the wire");
+            codec_params.compression = WireCompression::PcmRice;
+        }
 
         let stream_id = new_stream_id();
         let is_running = Arc::new(AtomicBool::new(true));
@@ -97,19 +132,16 @@ impl MusicStream {
             total_frames: 0,
             total_samples: 0,
             codec_params,
+            pitch_semitones: 0,
+            tempo_percent: 100,
+            no_vocal_channels: CHANNELS as u8,
         };
-        let no_vocal_encoder =
-            NoVocalOpusTrack::<Sample, CHANNELS, SAMPLE_RATE>::new(meta.codec_params.clone())?;
+        let no_vocal_encoder = NoVocalOpusTrack::<Sample, CHANNELS, SAMPLE_RATE>::new(
+            meta.codec_params.clone(),
+            no_vocal_frame_duration,
+        )?;
 
-        {
-            let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&meta)
-                .expect("SyncedMeta ser")
-                .into_vec();
-            network_sender.push(TaggedPacket {
-                tag: SYNCED_META_TAG,
-                payload,
-            });
-        }
+        network_sender.push(meta_packet(&meta));
         synced_stream.receive_meta(LOCAL_ADDR, meta.clone());
 
         let start_at = ntp_service.party_now() + 500_000;
@@ -124,15 +156,7 @@ impl MusicStream {
             start_at,
             ntp_service.party_now()
         );
-        {
-            let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&control)
-                .expect("SyncedControl ser")
-                .into_vec();
-            network_sender.push(TaggedPacket {
-                tag: SYNCED_CONTROL_TAG,
-                payload,
-            });
-        }
+        network_sender.push(control_packet(&control));
         synced_stream.receive_control(LOCAL_ADDR, control);
 
         let control = SyncedControl::SetVocalRemoval {
@@ -140,15 +164,7 @@ impl MusicStream {
             enabled: vocal_removal_enabled.load(Ordering::Relaxed),
             party_clock_time: start_at,
         };
-        {
-            let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&control)
-                .expect("SyncedControl ser")
-                .into_vec();
-            network_sender.push(TaggedPacket {
-                tag: SYNCED_CONTROL_TAG,
-                payload,
-            });
-        }
+        network_sender.push(control_packet(&control));
         synced_stream.receive_control(LOCAL_ADDR, control);
 
         let ctx = StreamContext {
@@ -164,7 +180,9 @@ impl MusicStream {
             no_vocal_vault,
             command_rx,
             frames_read: 0,
+            frame_durs: Vec::new(),
             song_source_drained: false,
+            end_sent: false,
             next_original_seq_to_send: 1,
             next_no_vocal_seq_to_send: 1,
             next_original_seq_for_no_vocal: 1,
@@ -173,10 +191,18 @@ impl MusicStream {
             retransmit_queue: VecDeque::new(),
             last_pause_seq: 1,
             last_pause_no_vocal_seq: 1,
+            paused: false,
             last_start_party_time: start_at,
             last_start_seq: 1,
             last_start_no_vocal_seq: 1,
             frame_dur_us: None,
+            loop_region: None,
+            loop_wrap_at: None,
+            coalescer: coalesce.map(FrameCoalescer::new),
+            auto_mono,
+            redundant,
+            read_ahead,
+            sample_offsets,
         };
 
         let handle = thread::spawn(move || {
@@ -189,7 +215,7 @@ impl MusicStream {
             stream_id,
             is_running,
             command_tx,
-            _handle: Some(handle),
+            handle: Some(handle),
         })
     }
 
@@ -215,10 +241,40 @@ impl MusicStream {
             .context("Failed to send seek command")
     }
 
+    pub fn set_pitch(&self, semitones: i8) -> Result<()> {
+        self.command_tx
+            .send(MusicCommand::SetPitch(semitones))
+            .context("Failed to send pitch command")
+    }
+
+    pub fn set_tempo(&self, percent: u16) -> Result<()> {
+        self.command_tx
+            .send(MusicCommand::SetTempo(percent))
+            .context("Failed to send tempo command")
+    }
+
+    pub fn set_loop(&self, region: Option<LoopRegion>) -> Result<()> {
+        if region.is_some_and(|region| region.end_ms <= region.start_ms) {
+            return Err(anyhow!("Loop end must be after its start"));
+        }
+        self.command_tx
+            .send(MusicCommand::SetLoop(region))
+            .context("Failed to send loop command")
+    }
+
     pub fn stop(&self) {
         self.is_running.store(false, Ordering::Relaxed);
     }
 
+    /// Stops the worker thread and waits for it to exit, so nothing more is
+    /// sent for this stream afterwards.
+    fn shutdown(&mut self) {
+        self.stop();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+
     pub fn stream_id(&self) -> SyncedStreamId {
         self.stream_id
     }
@@ -236,6 +292,20 @@ struct MusicStreamDeps<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
     network_sender: NetworkSender,
     synced_stream: Arc<SyncedAudioStreamManager<Sample, CHANNELS, SAMPLE_RATE>>,
     vocal_removal_enabled: Arc<AtomicBool>,
+    /// Losslessly compress integer PCM (WAV) packets before sending.
+    compress_pcm: bool,
+    /// Audio per packet of the derived no-vocal Opus track.
+    no_vocal_frame_duration: OpusFrameDuration,
+    /// Pack small frames into shared datagrams.
+    coalesce: Option<CoalesceConfig>,
+    /// Code the no-vocal track as mono when the song is effectively mono.
+    auto_mono: bool,
+    /// Send each frame [`REDUNDANCY_COUNT`] times rather than once.
+    redundant: Arc<AtomicBool>,
+    /// Audio read from the source ahead of the send position at most.
+    read_ahead: Option<Duration>,
+    /// Stamp original frames with their sample offset.
+    sample_offsets: bool,
 }
 
 /// Owns outgoing music streams and routes retransmit/control operations by stream id.
@@ -255,6 +325,7 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
         network_sender: NetworkSender,
         synced_stream: Arc<SyncedAudioStreamManager<Sample, CHANNELS, SAMPLE_RATE>>,
         vocal_removal_enabled: Arc<AtomicBool>,
+        compress_pcm: bool,
     ) -> Self {
         Self {
             streams: Mutex::new(Vec::new()),
@@ -263,26 +334,90 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
                 network_sender,
                 synced_stream,
                 vocal_removal_enabled,
+                compress_pcm,
+                no_vocal_frame_duration: OpusFrameDuration::Ms20,
+                coalesce: None,
+                auto_mono: false,
+                redundant: Arc::new(AtomicBool::new(true)),
+                read_ahead: None,
+                sample_offsets: false,
             },
         }
     }
 
+    /// Whether streams send every frame more than once, which lost frames
+    /// then don't have to wait for retransmission. Shared with running
+    /// streams; clear it to save uplink bandwidth.
+    pub fn redundancy(&self) -> Arc<AtomicBool> {
+        self.deps.redundant.clone()
+    }
+
+    /// Codes the no-vocal track of streams started from now on as mono when
+    /// their channels turn out to be effectively identical.
+    pub fn with_auto_mono(mut self, auto_mono: bool) -> Self {
+        self.deps.auto_mono = auto_mono;
+        self
+    }
+
+    /// Packs small frames of streams started from now on into shared
+    /// datagrams, or sends each in its own with `None` (the default).
+    pub fn with_coalescing(mut self, coalesce: Option<CoalesceConfig>) -> Self {
+        self.deps.coalesce = coalesce;
+        self
+    }
+
+    /// Reads streams started from now on at most `read_ahead` ahead of
+    /// the packet they send next, or all at once with `None` (the
+    /// default).
+    pub fn with_read_ahead(mut self, read_ahead: Option<Duration>) -> Self {
+        self.deps.read_ahead = read_ahead;
+        self
+    }
+
+    /// Stamps original frames of streams started from now on with their
+    /// sample offset in the source. Off by default.
+    pub fn with_sample_offsets(mut self, enabled: bool) -> Self {
+        self.deps.sample_offsets = enabled;
+        self
+    }
+
+    /// Sets the frame duration of the no-vocal Opus track of streams
+    /// started from now on. 20 ms by default.
+    pub fn with_no_vocal_frame_duration(mut self, duration: OpusFrameDuration) -> Self {
+        self.deps.no_vocal_frame_duration = duration;
+        self
+    }
+
     pub fn start_stream(
         &self,
         data: Vec<u8>,
         file_name: String,
         progress: Arc<MusicStreamProgress>,
     ) -> Result<()> {
-        let deps = self.deps.clone();
+        self.start_media(Box::new(Cursor::new(data)), file_name, progress)
+    }
 
+    /// Streams audio from an opened HTTP(S) response as it downloads.
+    pub fn start_url_stream(
+        &self,
+        source: HttpSource,
+        file_name: String,
+        progress: Arc<MusicStreamProgress>,
+    ) -> Result<()> {
+        self.start_media(Box::new(source), file_name, progress)
+    }
+
+    fn start_media(
+        &self,
+        media: Box<dyn MediaSource>,
+        file_name: String,
+        progress: Arc<MusicStreamProgress>,
+    ) -> Result<()> {
         let music_stream = MusicStream::start::<Sample, CHANNELS, SAMPLE_RATE>(
-            data,
+            media,
             file_name,
-            deps.ntp_service,
-            deps.network_sender,
-            deps.synced_stream,
             progress,
-            deps.vocal_removal_enabled,
+            self.deps.clone(),
         )?;
 
         self.push(music_stream);
@@ -343,6 +478,57 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
         Err(anyhow!("Stream not found"))
     }
 
+    /// Changes the key of a local stream for everyone, in semitones.
+    pub fn set_pitch(&self, stream_id: SyncedStreamId, semitones: i8) -> Result<()> {
+        let streams = self.streams.lock().unwrap();
+        for stream in streams.iter() {
+            if stream.stream_id() == stream_id {
+                return stream.set_pitch(semitones);
+            }
+        }
+        Err(anyhow!("Stream not found"))
+    }
+
+    /// Changes the speed of a local stream for everyone, in percent.
+    pub fn set_tempo(&self, stream_id: SyncedStreamId, percent: u16) -> Result<()> {
+        let streams = self.streams.lock().unwrap();
+        for stream in streams.iter() {
+            if stream.stream_id() == stream_id {
+                return stream.set_tempo(percent);
+            }
+        }
+        Err(anyhow!("Stream not found"))
+    }
+
+    /// Loops a section of a local stream for everyone, or stops looping.
+    pub fn set_loop(&self, stream_id: SyncedStreamId, region: Option<LoopRegion>) -> Result<()> {
+        let streams = self.streams.lock().unwrap();
+        for stream in streams.iter() {
+            if stream.stream_id() == stream_id {
+                return stream.set_loop(region);
+            }
+        }
+        Err(anyhow!("Stream not found"))
+    }
+
+    /// Ends a stream for everyone: stops the worker, then broadcasts
+    /// [`SyncedControl::Stop`] so receivers free the stream's buffers.
+    pub fn stop(&self, stream_id: SyncedStreamId) -> Result<()> {
+        let mut stream = {
+            let mut streams = self.streams.lock().unwrap();
+            let idx = streams
+                .iter()
+                .position(|stream| stream.stream_id() == stream_id)
+                .ok_or_else(|| anyhow!("Stream not found"))?;
+            streams.remove(idx)
+        };
+        stream.shutdown();
+
+        self.broadcast_control(SyncedControl::Stop { stream_id });
+        info!("Stopped music stream {}", stream_id);
+        Ok(())
+    }
+
     pub fn set_vocal_removal(&self, stream_id: SyncedStreamId, enabled: bool) -> Result<()> {
         let has_local_stream = self
             .streams
@@ -364,16 +550,15 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
             enabled,
             party_clock_time: switch_at,
         };
-        let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&control)
-            .expect("SyncedControl ser")
-            .into_vec();
-        self.deps.network_sender.push(TaggedPacket {
-            tag: SYNCED_CONTROL_TAG,
-            payload,
-        });
-        self.deps.synced_stream.receive_control(LOCAL_ADDR, control);
+        self.broadcast_control(control);
         Ok(())
     }
+
+    /// Sends `control` to receivers and the local player.
+    fn broadcast_control(&self, control: SyncedControl) {
+        self.deps.network_sender.push(control_packet(&control));
+        self.deps.synced_stream.receive_control(LOCAL_ADDR, control);
+    }
 }
 
 impl<S: AudioSample + 'static, const C: usize, const SR: u32> NetworkStream<S, C, SR>
@@ -396,12 +581,40 @@ struct AudioSource {
     format: Box<dyn FormatReader>,
     track_id: u32,
     duration_secs: Option<f64>,
+    /// What packet timestamps count, if not samples.
+    time_base: Option<TimeBase>,
+    sample_rate: u32,
+}
+
+/// Timestamp `ts` in units of `time_base` as a sample offset at
+/// `sample_rate`. Without a time base, timestamps already count samples.
+fn meta_packet(meta: &SyncedStreamMeta) -> TaggedPacket {
+    let payload = rkyv::to_bytes::<rkyv::rancor::Error>(meta)
+        .expect("SyncedMeta ser")
+        .into_vec();
+    TaggedPacket::new(SYNCED_META_TAG, payload)
+}
+
+fn control_packet(control: &SyncedControl) -> TaggedPacket {
+    let payload = rkyv::to_bytes::<rkyv::rancor::Error>(control)
+        .expect("SyncedControl ser")
+        .into_vec();
+    TaggedPacket::new(SYNCED_CONTROL_TAG, payload)
+}
+
+fn ts_to_samples(ts: u64, time_base: Option<TimeBase>, sample_rate: u32) -> u64 {
+    let Some(time_base) = time_base else {
+        return ts;
+    };
+    (ts as u128 * sample_rate as u128 * time_base.numer as u128 / time_base.denom.max(1) as u128)
+        as u64
 }
 
 impl AudioSource {
-    fn open(data: Vec<u8>, extension: Option<&str>) -> Result<Self> {
-        let cursor = Cursor::new(data);
-        let mss = MediaSourceStream::new(Box::new(cursor), Default::default());
+    /// Probes `media`. Sources without a known length (live HTTP streams)
+    /// leave `duration_secs` unset and play until they end.
+    fn open(media: Box<dyn MediaSource>, extension: Option<&str>) -> Result<Self> {
+        let mss = MediaSourceStream::new(media, Default::default());
 
         let mut hint = Hint::new();
         if let Some(ext) = extension {
@@ -436,10 +649,14 @@ impl AudioSource {
             .n_frames
             .map(|frames| frames as f64 / sample_rate as f64);
 
+        let time_base = track.codec_params.time_base;
+
         Ok(Self {
             format,
             track_id,
             duration_secs,
+            time_base,
+            sample_rate,
         })
     }
 
@@ -457,6 +674,7 @@ impl AudioSource {
                     return Ok(Some(RawPacket {
                         dur: packet.dur as u32,
                         data: packet.data.to_vec(),
+                        offset: Some(ts_to_samples(packet.ts, self.time_base, self.sample_rate)),
                     }));
                 }
                 Err(symphonia::core::errors::Error::IoError(e))
@@ -485,15 +703,18 @@ impl AudioSource {
 ///
 /// The original file packets remain the canonical synced stream. This helper
 /// decodes those same packets locally, runs vocal removal at the model rate,
-/// resamples back to the app output rate, batches to Opus-valid 20 ms frames,
+/// resamples back to the app output rate, batches to Opus frames of the
+/// configured duration,
 /// and emits packets with an independent no-vocal sequence.
 struct NoVocalOpusTrack<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    decompressor: WireDecompressor,
     decoder: SymphoniaDecoder<CHANNELS>,
     to_model_rate: FftResampler<CHANNELS, VOCAL_REMOVER_SAMPLE_RATE>,
     vocal_remover: DecodedVocalRemover<CHANNELS, VOCAL_REMOVER_SAMPLE_RATE>,
     to_output_rate: FftResampler<CHANNELS, SAMPLE_RATE>,
     interleaver: Interleaver<Sample, CHANNELS, SAMPLE_RATE>,
     encoder: OpusEncoder<Sample, CHANNELS, SAMPLE_RATE>,
+    frame_duration: OpusFrameDuration,
     pending_pcm: Vec<Sample>,
     next_seq: u64,
 }
@@ -501,12 +722,13 @@ struct NoVocalOpusTrack<Sample: AudioSample, const CHANNELS: usize, const SAMPLE
 impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
     NoVocalOpusTrack<Sample, CHANNELS, SAMPLE_RATE>
 {
-    fn new(codec_params: WireCodecParams) -> Result<Self> {
+    fn new(codec_params: WireCodecParams, frame_duration: OpusFrameDuration) -> Result<Self> {
         let decoder = symphonia::default::get_codecs()
             .make(&codec_params.to_symphonia(), &DecoderOptions::default())
             .context("create no-vocal source decoder")?;
 
         Ok(Self {
+            decompressor: WireDecompressor::new(&codec_params),
             decoder: SymphoniaDecoder::<CHANNELS>::new(decoder),
             to_model_rate: FftResampler::<CHANNELS, VOCAL_REMOVER_SAMPLE_RATE>::new(
                 codec_params.sample_rate,
@@ -520,11 +742,43 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
             interleaver: Interleaver::<Sample, CHANNELS, SAMPLE_RATE>::new(),
             encoder: OpusEncoder::<Sample, CHANNELS, SAMPLE_RATE>::new()
                 .context("create no-vocal Opus encoder")?,
+            frame_duration,
             pending_pcm: Vec::new(),
             next_seq: 1,
         })
     }
 
+    /// Whether `packets` decode to channels close enough to identical that
+    /// coding them as one loses nothing.
+    fn is_mono_eligible<'a>(&mut self, packets: impl IntoIterator<Item = &'a RawPacket>) -> bool {
+        let mut analyzer = StereoAnalyzer::default();
+        for raw in packets {
+            let decoded = self
+                .decompressor
+                .process(CompressedPacket {
+                    dur: raw.dur,
+                    data: raw.data.clone(),
+                })
+                .and_then(|packet| self.decoder.process(packet));
+            if let Some(decoded) = decoded {
+                analyzer.add(&decoded.channels);
+            }
+        }
+        self.decoder.reset();
+        debug!(
+            "Stereo analysis: side/mid {:?} dB",
+            analyzer.side_to_mid_db()
+        );
+        analyzer.is_mono_eligible()
+    }
+
+    /// Codes the track as a single channel from now on.
+    fn use_mono(&mut self) -> Result<()> {
+        self.encoder = OpusEncoder::with_coupling(ChannelCoupling::Mono)
+            .context("create mono no-vocal Opus encoder")?;
+        Ok(())
+    }
+
     fn reset(&mut self, next_seq: u64) {
         self.decoder.reset();
         self.to_model_rate.reset();
@@ -538,10 +792,13 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
     fn process_raw(&mut self, raw: &RawPacket) -> Vec<(u64, RawPacket)> {
         // debug!("process_raw: got raw packet");
 
-        let Some(decoded) = self.decoder.process(CompressedPacket {
+        let Some(packet) = self.decompressor.process(CompressedPacket {
             dur: raw.dur,
             data: raw.data.clone(),
         }) else {
+            return Vec::new();
+        };
+        let Some(decoded) = self.decoder.process(packet) else {
             warn!("process_raw: Failed to decode raw data");
             return Vec::new();
         };
@@ -572,8 +829,8 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
     ) -> Vec<(u64, RawPacket)> {
         self.pending_pcm.extend(audio.into_inner());
 
-        let frame_samples = Self::opus_frame_samples_total();
-        let frame_samples_per_channel = frame_samples / CHANNELS;
+        let frame_samples_per_channel = self.frame_duration.samples_per_channel(SAMPLE_RATE);
+        let frame_samples = frame_samples_per_channel * CHANNELS;
         let mut packets = Vec::new();
 
         while self.pending_pcm.len() >= frame_samples {
@@ -592,16 +849,13 @@ impl<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RATE: u32>
                 RawPacket {
                     dur: frame_samples_per_channel as u32,
                     data: opus.data,
+                    offset: None,
                 },
             ));
         }
 
         packets
     }
-
-    fn opus_frame_samples_total() -> usize {
-        (SAMPLE_RATE as usize * CHANNELS * NO_VOCAL_OPUS_FRAME_MS as usize) / 1000
-    }
 }
 
 /// Worker context for the streaming thread.
@@ -622,7 +876,13 @@ struct StreamContext<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RA
     command_rx: std::sync::mpsc::Receiver<MusicCommand>,
 
     frames_read: u64,
+    /// Source samples in each packet read, by sequence number less one.
+    /// Kept for positioning after the packets leave the vault.
+    frame_durs: Vec<u32>,
     song_source_drained: bool,
+    /// [`SyncedControl::End`] went out for everything sent since the last
+    /// restart.
+    end_sent: bool,
     next_original_seq_to_send: u64,
     next_no_vocal_seq_to_send: u64,
     next_original_seq_for_no_vocal: u64,
@@ -631,12 +891,48 @@ struct StreamContext<Sample: AudioSample, const CHANNELS: usize, const SAMPLE_RA
     retransmit_queue: VecDeque<(SyncedTrack, u64)>,
     last_pause_seq: u64,
     last_pause_no_vocal_seq: u64,
+    paused: bool,
     last_start_party_time: u64,
     last_start_seq: u64,
     last_start_no_vocal_seq: u64,
     /// Microseconds per compressed frame, computed from the first packet's dur.
     /// `None` until the first packet is read.
     frame_dur_us: Option<u64>,
+    loop_region: Option<LoopRegion>,
+    /// Party time playback reaches the loop end, once known. Cleared
+    /// whenever playback restarts or pauses.
+    loop_wrap_at: Option<u64>,
+    /// Holds frames to send several per datagram, when coalescing is on.
+    coalescer: Option<FrameCoalescer>,
+    /// Check the start of the song for mono before sending.
+    auto_mono: bool,
+    redundant: Arc<AtomicBool>,
+    read_ahead: Option<Duration>,
+    sample_offsets: bool,
+}
+
+/// Whether another packet may be read with `frames_read` read so far and
+/// `next_seq_to_send` up next, staying within `read_ahead` of the send
+/// position. Until the frame duration is known, reading goes on.
+fn may_read_ahead(
+    read_ahead: Option<Duration>,
+    frame_dur_us: Option<u64>,
+    frames_read: u64,
+    next_seq_to_send: u64,
+) -> bool {
+    let (Some(read_ahead), Some(frame_dur_us)) = (read_ahead, frame_dur_us) else {
+        return true;
+    };
+    let max_lead = (read_ahead.as_micros() as u64).div_ceil(frame_dur_us.max(1));
+    let lead = (frames_read + 1).saturating_sub(next_seq_to_send);
+    lead < max_lead.max(1)
+}
+
+/// The first sequence number still worth keeping with `playing` playing
+/// now: earlier ones played more than [`RETRANSMIT_KEEP`] ago.
+fn retransmit_keep_from(playing: u64, frame_dur_us: u64) -> u64 {
+    let keep = (RETRANSMIT_KEEP.as_micros() as u64).div_ceil(frame_dur_us.max(1));
+    playing.saturating_sub(keep)
 }
 
 impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u32>
@@ -644,6 +940,9 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
 {
     fn run(mut self) -> Result<()> {
         self.init_with_duration();
+        if self.auto_mono {
+            self.choose_no_vocal_channels();
+        }
 
         while self.is_running.load(Ordering::Relaxed) {
             if self.should_stop_for_other_stream() {
@@ -651,12 +950,17 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
             }
 
             self.handle_commands();
+            self.check_loop();
             self.read_packets();
             self.send_retransmissions();
             self.send_packets();
+            self.poll_coalesced();
 
             thread::sleep(Duration::from_millis(10));
         }
+        if let Some(packet) = self.coalescer.as_mut().and_then(FrameCoalescer::flush) {
+            self.network_sender.push(packet);
+        }
 
         self.progress.reset();
         Ok(())
@@ -666,6 +970,63 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
         self.meta.codec_params.sample_rate
     }
 
+    /// How many times each frame goes out.
+    fn copies(&self) -> usize {
+        if self.redundant.load(Ordering::Relaxed) {
+            REDUNDANCY_COUNT
+        } else {
+            1
+        }
+    }
+
+    /// Sends a synced frame, or queues it to share a datagram with the next.
+    fn send_frame(&mut self, frame: SyncedFrame) {
+        let Some(coalescer) = &mut self.coalescer else {
+            let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&frame)
+                .expect("SyncedFrame ser")
+                .into_vec();
+            self.network_sender
+                .push(TaggedPacket::new(SYNCED_TAG, payload));
+            return;
+        };
+        for packet in coalescer.push(frame, Instant::now()) {
+            self.network_sender.push(packet);
+        }
+    }
+
+    /// Sends the queued frames whose window is up.
+    fn poll_coalesced(&mut self) {
+        if let Some(packet) = self
+            .coalescer
+            .as_mut()
+            .and_then(|coalescer| coalescer.poll(Instant::now()))
+        {
+            self.network_sender.push(packet);
+        }
+    }
+
+    /// Sends a non-frame packet, after any queued frames so receivers see
+    /// everything in the order it was produced.
+    fn send_tagged(&mut self, packet: TaggedPacket) {
+        if let Some(queued) = self.coalescer.as_mut().and_then(FrameCoalescer::flush) {
+            self.network_sender.push(queued);
+        }
+        self.network_sender.push(packet);
+    }
+
+    /// Sends the current metadata to receivers and the local player.
+    fn broadcast_meta(&mut self) {
+        self.send_tagged(meta_packet(&self.meta));
+        self.synced_stream
+            .receive_meta(LOCAL_ADDR, self.meta.clone());
+    }
+
+    /// Sends `control` to receivers and the local player.
+    fn broadcast_control(&mut self, control: SyncedControl) {
+        self.send_tagged(control_packet(&control));
+        self.synced_stream.receive_control(LOCAL_ADDR, control);
+    }
+
     fn init_with_duration(&mut self) {
         let Some(duration) = self.source.duration_secs else {
             return;
@@ -676,29 +1037,53 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
 
         let total_samples = (duration * self.sample_rate() as f64) as u64;
         // Estimate total packets from first packet's dur, or fall back to 1024
-        let samples_per_frame = self
-            .original_vault
-            .get(&1)
-            .map(|p| p.dur as u64)
-            .unwrap_or(1024);
+        let samples_per_frame = self.frame_durs.first().map_or(1024, |&dur| dur as u64);
         let est_packets = total_samples / samples_per_frame;
         self.meta.total_frames = est_packets;
-        self.meta.total_samples = total_samples;
+        self.meta.total_samples = self.stretched(total_samples);
         self.progress
-            .streaming_total
-            .store(est_packets, Ordering::Relaxed);
+            .total_samples
+            .store(total_samples, Ordering::Relaxed);
 
-        {
-            let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&self.meta)
-                .expect("SyncedMeta ser")
-                .into_vec();
-            self.network_sender.push(TaggedPacket {
-                tag: SYNCED_META_TAG,
-                payload,
-            });
+        self.broadcast_meta();
+    }
+
+    /// Decodes the start of the song and switches the no-vocal track to mono
+    /// coding if its channels are effectively identical.
+    fn choose_no_vocal_channels(&mut self) {
+        if CHANNELS < 2 {
+            return;
         }
-        self.synced_stream
-            .receive_meta(LOCAL_ADDR, self.meta.clone());
+        let wanted = MONO_ANALYSIS_SECS * self.sample_rate() as u64;
+        let analyzed = |ctx: &Self| -> u64 {
+            (1..=ctx.frames_read)
+                .filter_map(|seq| ctx.original_vault.get(&seq).map(|p| p.dur as u64))
+                .sum()
+        };
+        while !self.song_source_drained && analyzed(self) < wanted {
+            let before = self.frames_read;
+            self.read_packets();
+            if self.frames_read == before {
+                break;
+            }
+        }
+
+        let packets: Vec<RawPacket> = (1..=self.frames_read)
+            .filter_map(|seq| self.original_vault.get(&seq).map(|p| p.clone()))
+            .collect();
+        if !self.no_vocal_encoder.is_mono_eligible(&packets) {
+            return;
+        }
+        if let Err(e) = self.no_vocal_encoder.use_mono() {
+            warn!("Keeping stereo no-vocal track: {:?}", e);
+            return;
+        }
+        info!(
+            "{} is effectively mono, sending it as such",
+            self.meta.file_name
+        );
+        self.meta.no_vocal_channels = 1;
+        self.broadcast_meta();
     }
 
     fn should_stop_for_other_stream(&self) -> bool {
@@ -726,6 +1111,9 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
                 MusicCommand::Pause => self.handle_pause(),
                 MusicCommand::Resume => self.handle_resume(),
                 MusicCommand::Seek(pos_ms) => self.handle_seek(pos_ms),
+                MusicCommand::SetPitch(semitones) => self.handle_set_pitch(semitones),
+                MusicCommand::SetTempo(percent) => self.handle_set_tempo(percent),
+                MusicCommand::SetLoop(region) => self.handle_set_loop(region),
             }
         }
     }
@@ -734,29 +1122,35 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
         let control = SyncedControl::Pause {
             stream_id: self.meta.stream_id,
         };
-        {
-            let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&control)
-                .expect("SyncedControl ser")
-                .into_vec();
-            self.network_sender.push(TaggedPacket {
-                tag: SYNCED_CONTROL_TAG,
-                payload,
-            });
-        }
-        self.synced_stream.receive_control(LOCAL_ADDR, control);
+        self.broadcast_control(control);
+
+        (self.last_pause_seq, self.last_pause_no_vocal_seq) = self.playing_seqs();
+        self.paused = true;
+        self.loop_wrap_at = None;
+    }
 
+    /// Original and no-vocal sequence numbers playing right now, assuming
+    /// playback has not been paused since the last start.
+    fn playing_seqs(&self) -> (u64, u64) {
         let party_now = self.ntp_service.party_now();
-        if party_now > self.last_start_party_time {
-            let elapsed_us = party_now - self.last_start_party_time;
-            let elapsed_samples = elapsed_us * self.sample_rate() as u64 / 1_000_000;
-            self.last_pause_seq = self.find_seq_at_samples(self.last_start_seq, elapsed_samples);
-            let elapsed_output_samples = elapsed_us * SAMPLE_RATE as u64 / 1_000_000;
-            self.last_pause_no_vocal_seq = self
-                .find_no_vocal_seq_at_samples(self.last_start_no_vocal_seq, elapsed_output_samples);
-        } else {
-            self.last_pause_seq = self.last_start_seq;
-            self.last_pause_no_vocal_seq = self.last_start_no_vocal_seq;
+        if party_now <= self.last_start_party_time {
+            return (self.last_start_seq, self.last_start_no_vocal_seq);
         }
+        // At a changed tempo, party time and source time advance at
+        // different rates.
+        let elapsed_us =
+            (party_now - self.last_start_party_time) * self.meta.tempo_percent as u64 / 100;
+        let elapsed_samples = elapsed_us * self.sample_rate() as u64 / 1_000_000;
+        let elapsed_output_samples = elapsed_us * SAMPLE_RATE as u64 / 1_000_000;
+        (
+            self.find_seq_at_samples(self.last_start_seq, elapsed_samples),
+            self.find_no_vocal_seq_at_samples(self.last_start_no_vocal_seq, elapsed_output_samples),
+        )
+    }
+
+    /// Source length `samples` played at the current tempo.
+    fn stretched(&self, samples: u64) -> u64 {
+        samples * 100 / self.meta.tempo_percent.max(1) as u64
     }
 
     fn handle_resume(&mut self) {
@@ -767,31 +1161,112 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
             seq: self.last_pause_seq,
             no_vocal_seq: self.last_pause_no_vocal_seq,
         };
-        {
-            let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&control)
-                .expect("SyncedControl ser")
-                .into_vec();
-            self.network_sender.push(TaggedPacket {
-                tag: SYNCED_CONTROL_TAG,
-                payload,
-            });
-        }
-        self.synced_stream.receive_control(LOCAL_ADDR, control);
+        self.broadcast_control(control);
 
         self.last_start_party_time = resume_at;
         self.last_start_seq = self.last_pause_seq;
         self.last_start_no_vocal_seq = self.last_pause_no_vocal_seq;
+        self.paused = false;
+        self.loop_wrap_at = None;
+    }
+
+    /// Broadcasts the new key in the stream metadata; receivers shift
+    /// their decoded audio by it.
+    fn handle_set_pitch(&mut self, semitones: i8) {
+        self.meta.pitch_semitones = semitones;
+        self.broadcast_meta();
+    }
+
+    /// Broadcasts the new speed in the stream metadata. While playing, also
+    /// restarts at the current position, so receivers drop audio stretched
+    /// at the old tempo and every timeline is measured from the change. A
+    /// paused stream picks the change up when it resumes.
+    fn handle_set_tempo(&mut self, percent: u16) {
+        let playing = (!self.paused).then(|| self.playing_seqs());
+
+        self.meta.tempo_percent = percent.max(1);
+        self.meta.total_samples =
+            self.stretched(self.progress.total_samples.load(Ordering::Relaxed));
+        self.broadcast_meta();
+
+        if let Some((seq, no_vocal_seq)) = playing {
+            self.restart_at(seq, no_vocal_seq);
+        }
     }
 
     fn handle_seek(&mut self, pos_ms: u64) {
-        let seek_at = self.ntp_service.party_now();
+        let (seq, no_vocal_seq) = self.seek_source(pos_ms);
+        self.restart_at(seq, no_vocal_seq);
+    }
+
+    fn handle_set_loop(&mut self, region: Option<LoopRegion>) {
+        info!("MusicStream: loop region {:?}", region);
+        self.loop_region = region;
+        self.loop_wrap_at = None;
+        *self.progress.loop_region.lock().unwrap() = region;
+    }
+
+    /// Jumps back to the loop start once playback reaches the loop end.
+    ///
+    /// The jump is scheduled at the party time the end is reached, so
+    /// every receiver plays the packet at the loop start right after the
+    /// last one before the end.
+    fn check_loop(&mut self) {
+        let Some(region) = self.loop_region else {
+            return;
+        };
+        if self.paused {
+            return;
+        }
+        if self.loop_wrap_at.is_none() {
+            self.loop_wrap_at = self.loop_end_party_time(region);
+        }
+        let Some(wrap_at) = self.loop_wrap_at else {
+            return;
+        };
+        let party_now = self.ntp_service.party_now();
+        if party_now < wrap_at {
+            return;
+        }
+
+        let start_at = if party_now - wrap_at > LOOP_WRAP_LATENESS_US {
+            party_now
+        } else {
+            wrap_at
+        };
+        info!(
+            "MusicStream: reached loop end {} ms, back to {} ms",
+            region.end_ms, region.start_ms
+        );
+        let (seq, no_vocal_seq) = self.seek_source(region.start_ms);
+        self.restart_at_party_time(seq, no_vocal_seq, start_at);
+    }
+
+    /// Party time at which playback since the last start reaches the end of
+    /// `region`, or `None` while the packets up to there aren't read yet.
+    fn loop_end_party_time(&self, region: LoopRegion) -> Option<u64> {
+        let sample_rate = self.sample_rate() as u64;
+        let end_seq = self.find_seq_at_samples(1, region.end_ms * sample_rate / 1000);
+        if end_seq > self.frame_durs.len() as u64 && !self.song_source_drained {
+            return None;
+        }
+        let until_end = self
+            .samples_before_seq(end_seq)
+            .saturating_sub(self.samples_before_seq(self.last_start_seq));
+        Some(self.last_start_party_time + self.stretched(until_end) * 1_000_000 / sample_rate)
+    }
+
+    /// Points the reader and the no-vocal encoder at `pos_ms` and returns
+    /// the original and no-vocal sequence numbers playing from there.
+    fn seek_source(&mut self, pos_ms: u64) -> (u64, u64) {
         let target_samples = pos_ms * self.sample_rate() as u64 / 1000;
         let seq = self.find_seq_at_samples(1, target_samples);
         let target_output_samples = pos_ms * SAMPLE_RATE as u64 / 1000;
         let no_vocal_seq = self.find_no_vocal_seq_at_samples(1, target_output_samples);
 
-        // If seeking beyond what we've read, seek the format reader.
-        if seq > self.frames_read {
+        // If seeking beyond what we've read, or back to what's been dropped
+        // since, seek the format reader.
+        if seq > self.frames_read || !self.original_vault.contains_key(&seq) {
             if let Err(e) = self.source.seek(target_samples) {
                 warn!("Failed to seek: {}", e);
             } else {
@@ -801,49 +1276,70 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
             }
         }
         self.no_vocal_encoder.reset(no_vocal_seq);
+        self.next_original_seq_for_no_vocal = seq;
+
+        (seq, no_vocal_seq)
+    }
+
+    /// Starts playback for everyone at `seq` right now and resends from there.
+    fn restart_at(&mut self, seq: u64, no_vocal_seq: u64) {
+        self.restart_at_party_time(seq, no_vocal_seq, self.ntp_service.party_now());
+    }
 
+    /// Starts playback for everyone at `seq` at party time `start_at` and
+    /// resends from there.
+    fn restart_at_party_time(&mut self, seq: u64, no_vocal_seq: u64, start_at: u64) {
         let control = SyncedControl::Start {
             stream_id: self.meta.stream_id,
-            party_clock_time: seek_at,
+            party_clock_time: start_at,
             seq,
             no_vocal_seq,
         };
-        {
-            let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&control)
-                .expect("SyncedControl ser")
-                .into_vec();
-            self.network_sender.push(TaggedPacket {
-                tag: SYNCED_CONTROL_TAG,
-                payload,
-            });
-        }
-        self.synced_stream.receive_control(LOCAL_ADDR, control);
+        self.broadcast_control(control);
 
-        self.last_start_party_time = seek_at;
+        self.last_start_party_time = start_at;
         self.last_start_seq = seq;
         self.last_start_no_vocal_seq = no_vocal_seq;
         self.last_pause_seq = seq;
         self.last_pause_no_vocal_seq = no_vocal_seq;
+        self.paused = false;
+        self.end_sent = false;
+        self.loop_wrap_at = None;
         self.next_original_seq_to_send = seq;
         self.next_no_vocal_seq_to_send = no_vocal_seq;
-        self.next_original_seq_for_no_vocal = seq;
+        self.progress
+            .sent_samples
+            .store(self.samples_before_seq(seq), Ordering::Relaxed);
+    }
+
+    /// Total source samples in packets `1..seq`, i.e. the sent position once
+    /// sending resumes at `seq`.
+    fn samples_before_seq(&self, seq: u64) -> u64 {
+        self.frame_durs
+            .iter()
+            .take(seq.saturating_sub(1) as usize)
+            .map(|&dur| dur as u64)
+            .sum()
     }
 
     fn find_seq_at_samples(&self, start_seq: u64, target_samples: u64) -> u64 {
         let mut cum = 0u64;
         let mut seq = start_seq;
-        while let Some(pkt) = self.original_vault.get(&seq) {
+        while let Some(&dur) = self.frame_durs.get(seq.saturating_sub(1) as usize) {
             if cum >= target_samples {
                 break;
             }
-            cum += pkt.dur as u64;
+            cum += dur as u64;
             seq += 1;
         }
         seq
     }
 
     fn find_no_vocal_seq_at_samples(&self, start_seq: u64, target_samples: u64) -> u64 {
-        let frame_samples = (SAMPLE_RATE as u64 * NO_VOCAL_OPUS_FRAME_MS as u64) / 1000;
+        let frame_samples = self
+            .no_vocal_encoder
+            .frame_duration
+            .samples_per_channel(SAMPLE_RATE) as u64;
         start_seq + target_samples / frame_samples.max(1)
     }
 
@@ -852,31 +1348,45 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
             return;
         }
 
-        for _ in 0..100 {
+        for _ in 0..READ_BATCH {
+            if !may_read_ahead(
+                self.read_ahead,
+                self.frame_dur_us,
+                self.frames_read,
+                self.next_original_seq_to_send,
+            ) {
+                break;
+            }
             match self.source.next_packet() {
-                Ok(Some(raw)) => {
+                Ok(Some(mut raw)) => {
                     self.frames_read += 1;
+                    raw.data = compress_for_wire(&self.meta.codec_params, raw.data);
+                    if !self.sample_offsets {
+                        raw.offset = None;
+                    }
                     // Compute frame duration from the first packet
                     if self.frame_dur_us.is_none() && raw.dur > 0 {
                         self.frame_dur_us =
                             Some(raw.dur as u64 * 1_000_000 / self.sample_rate() as u64);
                     }
 
+                    let index = self.frames_read as usize - 1;
+                    match self.frame_durs.get_mut(index) {
+                        Some(dur) => *dur = raw.dur,
+                        None => self.frame_durs.push(raw.dur),
+                    }
                     self.original_vault.insert(self.frames_read, raw);
                 }
                 Ok(None) => {
                     // EOF - calculate exact total_samples from all packets
                     self.song_source_drained = true;
                     self.meta.total_frames = self.frames_read;
-                    self.meta.total_samples =
-                        self.original_vault.iter().map(|r| r.dur as u64).sum();
-                    let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&self.meta)
-                        .expect("SyncedMeta ser")
-                        .into_vec();
-                    self.network_sender.push(TaggedPacket {
-                        tag: SYNCED_META_TAG,
-                        payload,
-                    });
+                    let total_samples = self.frame_durs.iter().map(|&dur| dur as u64).sum();
+                    self.meta.total_samples = self.stretched(total_samples);
+                    self.progress
+                        .total_samples
+                        .store(total_samples, Ordering::Relaxed);
+                    self.broadcast_meta();
                     break;
                 }
                 Err(e) => {
@@ -893,18 +1403,12 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
                 break;
             };
             let vault = match track {
-                SyncedTrack::Original => &self.original_vault,
-                SyncedTrack::NoVocal => &self.no_vocal_vault,
+                SyncedTrack::Original => self.original_vault.clone(),
+                SyncedTrack::NoVocal => self.no_vocal_vault.clone(),
             };
             if let Some(packet) = vault.get(&seq) {
                 for frame in fragment_raw_packet(track, self.meta.stream_id, seq, &packet) {
-                    let payload = rkyv::to_bytes::<rkyv::rancor::Error>(&frame)
-                        .expect("SyncedFrame ser")
-                        .into_vec();
-                    self.network_sender.push(TaggedPacket {
-                        tag: SYNCED_TAG,
-                        payload,
-                    });
+                    self.send_frame(frame);
                 }
             }
         }
@@ -913,6 +1417,72 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
     fn send_packets(&mut self) {
         self.send_original_packets();
         self.send_no_vocal_packets();
+        self.send_end_if_done();
+        self.prune_vaults();
+    }
+
+    /// Drops the packets played too long ago to be asked for again, so
+    /// reading ahead bounds the memory a song takes. Without a read-ahead
+    /// the whole song stays in memory.
+    fn prune_vaults(&mut self) {
+        let (Some(_), Some(frame_dur_us)) = (self.read_ahead, self.frame_dur_us) else {
+            return;
+        };
+        let (playing, playing_no_vocal) = if self.paused {
+            (self.last_pause_seq, self.last_pause_no_vocal_seq)
+        } else {
+            self.playing_seqs()
+        };
+        // The no-vocal track is still encoded from the original packets.
+        let original_from = retransmit_keep_from(
+            playing.min(self.next_original_seq_for_no_vocal),
+            frame_dur_us,
+        );
+        self.original_vault.retain(|&seq, _| seq >= original_from);
+        let no_vocal_dur_us = self.no_vocal_encoder.frame_duration.as_millis() as u64 * 1000;
+        let no_vocal_from = retransmit_keep_from(playing_no_vocal, no_vocal_dur_us);
+        self.no_vocal_vault.retain(|&seq, _| seq >= no_vocal_from);
+    }
+
+    /// Whether the no-vocal track has nothing left to send: the source is
+    /// drained, the next packet isn't encoded, and no original packets are
+    /// left to encode it from.
+    fn no_vocal_send_exhausted(
+        song_source_drained: bool,
+        next_packet_buffered: bool,
+        original_pending: bool,
+    ) -> bool {
+        song_source_drained && !next_packet_buffered && !original_pending
+    }
+
+    /// Tells receivers the final sequence numbers once both tracks are
+    /// fully sent, so they stop waiting for packets that never arrive.
+    fn send_end_if_done(&mut self) {
+        if self.end_sent
+            || !self.song_source_drained
+            || self.next_original_seq_to_send <= self.meta.total_frames
+        {
+            return;
+        }
+        let no_vocal_exhausted = Self::no_vocal_send_exhausted(
+            self.song_source_drained,
+            self.no_vocal_vault
+                .contains_key(&self.next_no_vocal_seq_to_send),
+            self.original_vault
+                .contains_key(&self.next_original_seq_for_no_vocal),
+        );
+        if !no_vocal_exhausted {
+            return;
+        }
+
+        let control = SyncedControl::End {
+            stream_id: self.meta.stream_id,
+            last_seq: self.meta.total_frames,
+            no_vocal_last_seq: self.next_no_vocal_seq_to_send - 1,
+        };
+        info!("MusicStream: all packets sent, sending {:?}", control);
+        self.broadcast_control(control);
+        self.end_sent = true;
     }
 
     fn process_no_vocal_packets_until(&mut self, target_no_vocal_seq: u64) {
@@ -949,34 +1519,26 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
             return;
         }
 
+        let vault = self.original_vault.clone();
         for _ in 0..frames_to_send {
-            if let Some(packet) = self.original_vault.get(&self.next_original_seq_to_send) {
+            if let Some(packet) = vault.get(&self.next_original_seq_to_send) {
                 let seq = self.next_original_seq_to_send;
                 let fragments =
                     fragment_raw_packet(SyncedTrack::Original, self.meta.stream_id, seq, &packet);
 
-                for _ in 0..REDUNDANCY_COUNT {
+                for _ in 0..self.copies() {
                     for frame in &fragments {
-                        {
-                            let payload = rkyv::to_bytes::<rkyv::rancor::Error>(frame)
-                                .expect("SyncedFrame ser")
-                                .into_vec();
-                            self.network_sender.push(TaggedPacket {
-                                tag: SYNCED_TAG,
-                                payload,
-                            });
-                        }
+                        self.send_frame(frame.clone());
                     }
                 }
 
                 // Local loopback skips the wire, so hand it the unfragmented frame.
                 let local =
-                    SyncedFrame::whole(self.meta.stream_id, seq, packet.dur, packet.data.clone());
+                    SyncedFrame::whole(self.meta.stream_id, seq, packet.dur, packet.data.clone())
+                        .with_sample_offset(packet.offset);
                 self.synced_stream.receive(LOCAL_ADDR, local);
 
-                self.progress
-                    .streaming_current
-                    .store(seq, Ordering::Relaxed);
+                self.progress.record_sent(packet.dur as u64);
                 self.next_original_seq_to_send += 1;
             } else if self.song_source_drained
                 && self.next_original_seq_to_send > self.meta.total_frames
@@ -992,7 +1554,7 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
     fn send_no_vocal_packets(&mut self) {
         let now = Instant::now();
         let elapsed_us = now.duration_since(self.last_no_vocal_send_time).as_micros() as u64;
-        let frame_dur_us = NO_VOCAL_OPUS_FRAME_MS as u64 * 1000;
+        let frame_dur_us = self.no_vocal_encoder.frame_duration.as_millis() as u64 * 1000;
         let frames_to_send = (elapsed_us * SEND_RATE_MULTIPLIER as u64) / frame_dur_us;
 
         // debug!(
@@ -1007,21 +1569,16 @@ impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u3
         self.process_no_vocal_packets_until(target_seq);
 
         let mut sent_frames = 0u64;
+        let vault = self.no_vocal_vault.clone();
         for _ in 0..frames_to_send {
-            if let Some(packet) = self.no_vocal_vault.get(&self.next_no_vocal_seq_to_send) {
+            if let Some(packet) = vault.get(&self.next_no_vocal_seq_to_send) {
                 let seq = self.next_no_vocal_seq_to_send;
                 let fragments =
                     fragment_raw_packet(SyncedTrack::NoVocal, self.meta.stream_id, seq, &packet);
 
-                for _ in 0..REDUNDANCY_COUNT {
+                for _ in 0..self.copies() {
                     for frame in &fragments {
-                        let payload = rkyv::to_bytes::<rkyv::rancor::Error>(frame)
-                            .expect("SyncedFrame ser")
-                            .into_vec();
-                        self.network_sender.push(TaggedPacket {
-                            tag: SYNCED_TAG,
-                            payload,
-                        });
+                        self.send_frame(frame.clone());
                     }
                 }
 
@@ -1064,13 +1621,10 @@ fn fragment_raw_packet(
 ) -> Vec<SyncedFrame> {
     let data = &packet.data;
     if data.len() <= MAX_FRAGMENT_DATA {
-        return vec![SyncedFrame::for_track(
-            track,
-            stream_id,
-            seq,
-            packet.dur,
-            data.clone(),
-        )];
+        return vec![
+            SyncedFrame::for_track(track, stream_id, seq, packet.dur, data.clone())
+                .with_sample_offset(packet.offset),
+        ];
     }
 
     let total = data.len().div_ceil(MAX_FRAGMENT_DATA) as u16;
@@ -1086,6 +1640,7 @@ fn fragment_raw_packet(
                 fragment_idx: idx,
                 fragment_total: total,
                 data: data[start..end].to_vec(),
+                sample_offset: packet.offset,
             }
         })
         .collect()
@@ -1094,6 +1649,8 @@ fn fragment_raw_packet(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::io::SendTarget;
+    use std::net::UdpSocket;
 
     #[test]
     fn no_vocal_sender_keeps_sending_after_original_eof() {
@@ -1110,4 +1667,365 @@ mod tests {
             "no-vocal sender should stop only after original EOF and no derived work remains"
         );
     }
+
+    #[test]
+    fn reading_pauses_at_the_configured_lead_and_resumes_with_sending() {
+        // 20 ms packets, read at most one second ahead: 50 packets.
+        let read_ahead = Some(Duration::from_secs(1));
+        let frame_dur_us = Some(20_000);
+        let mut frames_read = 0;
+        let mut next_seq_to_send = 1;
+        let read = |frames_read: &mut u64, next_seq_to_send: u64| {
+            let before = *frames_read;
+            while *frames_read < before + READ_BATCH as u64
+                && may_read_ahead(read_ahead, frame_dur_us, *frames_read, next_seq_to_send)
+            {
+                *frames_read += 1;
+            }
+            *frames_read - before
+        };
+
+        assert_eq!(read(&mut frames_read, next_seq_to_send), 50);
+        assert_eq!(
+            read(&mut frames_read, next_seq_to_send),
+            0,
+            "paused at the lead"
+        );
+
+        // Sending ten packets makes room for ten more.
+        next_seq_to_send += 10;
+        assert_eq!(read(&mut frames_read, next_seq_to_send), 10);
+        assert_eq!(frames_read, 60);
+
+        // Seeking back into what's been read leaves nothing to read for
+        // a while.
+        next_seq_to_send = 5;
+        assert_eq!(read(&mut frames_read, next_seq_to_send), 0);
+        next_seq_to_send = 20;
+        assert_eq!(read(&mut frames_read, next_seq_to_send), 9);
+
+        // Unbounded, or before the first packet, reading never waits.
+        assert!(may_read_ahead(None, frame_dur_us, 10_000, 1));
+        assert!(may_read_ahead(read_ahead, None, 10_000, 1));
+    }
+
+    #[test]
+    fn timestamps_become_sample_offsets() {
+        // Matroska and WebM count milliseconds.
+        let millis = Some(TimeBase::new(1, 1000));
+        assert_eq!(ts_to_samples(1500, millis, 48_000), 72_000);
+        // MP4 tracks often count in their sample rate already.
+        assert_eq!(
+            ts_to_samples(44_100, Some(TimeBase::new(1, 44_100)), 44_100),
+            44_100
+        );
+        assert_eq!(ts_to_samples(1234, None, 44_100), 1234);
+    }
+
+    #[test]
+    fn packets_are_kept_for_a_while_after_they_play() {
+        // 20 ms packets: two seconds is 100 of them.
+        assert_eq!(retransmit_keep_from(500, 20_000), 400);
+        assert_eq!(retransmit_keep_from(50, 20_000), 0, "nothing dropped yet");
+        // 40 ms no-vocal packets.
+        assert_eq!(retransmit_keep_from(500, 40_000), 450);
+    }
+
+    #[test]
+    fn configured_frame_durations_roundtrip_with_matching_sample_counts() {
+        use crate::audio::AudioBatcher;
+        use crate::audio::opus::{AudioCodec, OpusDecoder, OpusPacket};
+        use crate::party::realtime_stream::{RealtimeFrame, RealtimeStreamId};
+
+        let tone = |frames: usize| -> AudioBuffer<f32, 2, 48_000> {
+            let samples = (0..frames)
+                .flat_map(|n| [0.3 * (n as f32 * 0.05).sin(); 2])
+                .collect();
+            AudioBuffer::new(samples).unwrap()
+        };
+
+        // Music: the no-vocal track packetizes into 40 ms frames.
+        let data = std::fs::read("assets/read_you.m4a").expect("assets/read_you.m4a not found");
+        let source = AudioSource::open(Box::new(Cursor::new(data)), Some("m4a")).unwrap();
+        let codec_params = WireCodecParams::from_symphonia(&source.codec_params()).unwrap();
+        let mut music =
+            NoVocalOpusTrack::<f32, 2, 48_000>::new(codec_params, OpusFrameDuration::Ms40).unwrap();
+        let music_decoder = OpusDecoder::<f32, 2, 48_000>::new().unwrap();
+
+        let packets = music.encode_available(tone(4800));
+        assert_eq!(packets.len(), 2, "100 ms is two full 40 ms frames");
+        assert_eq!(
+            packets.iter().map(|(seq, _)| *seq).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        for (_, raw) in packets {
+            assert_eq!(raw.dur, 1920);
+            // As the receiver decodes no-vocal packets.
+            let decoded = music_decoder
+                .process(OpusPacket {
+                    data: raw.data,
+                    frame_size: raw.dur as usize * 2,
+                    codec: AudioCodec::Opus,
+                    bitrate: 0,
+                })
+                .unwrap();
+            assert_eq!(decoded.samples_per_channel(), 1920);
+        }
+
+        // Mic: 5 ms capture buffers go out as 10 ms frames.
+        let batcher = AudioBatcher::<f32, 2, 48_000>::new(OpusFrameDuration::Ms10.as_millis());
+        let mic_encoder = OpusEncoder::<f32, 2, 48_000>::new().unwrap();
+        let mic_decoder = OpusDecoder::<f32, 2, 48_000>::new().unwrap();
+        let mut frames = Vec::new();
+        for seq in 0..8 {
+            if let Some(batch) = batcher.process(tone(240)) {
+                let packet = mic_encoder.process(batch).unwrap();
+                frames.push(RealtimeFrame::new(RealtimeStreamId::Mic, seq, packet));
+            }
+        }
+        assert_eq!(frames.len(), 4);
+        for frame in frames {
+            assert_eq!(frame.frame_size, 960);
+            let decoded = mic_decoder.process(frame.to_opus_packet()).unwrap();
+            assert_eq!(decoded.samples_per_channel(), 480);
+        }
+    }
+
+    #[test]
+    fn dual_mono_file_is_mono_eligible_and_stereo_file_is_not() {
+        fn wav(right: impl Fn(i16) -> i16) -> Vec<u8> {
+            let spec = hound::WavSpec {
+                channels: 2,
+                sample_rate: 44100,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            let mut cursor = Cursor::new(Vec::new());
+            let mut writer = hound::WavWriter::new(&mut cursor, spec).unwrap();
+            for n in 0..44100 {
+                let left = ((n as f32 * 0.07).sin() * 12000.0) as i16;
+                writer.write_sample(left).unwrap();
+                writer.write_sample(right(left)).unwrap();
+            }
+            writer.finalize().unwrap();
+            cursor.into_inner()
+        }
+        fn mono_eligible(data: Vec<u8>) -> bool {
+            let mut source = AudioSource::open(Box::new(Cursor::new(data)), Some("wav")).unwrap();
+            let codec_params = WireCodecParams::from_symphonia(&source.codec_params()).unwrap();
+            let mut track =
+                NoVocalOpusTrack::<f32, 2, 48_000>::new(codec_params, OpusFrameDuration::Ms20)
+                    .unwrap();
+            let mut packets = Vec::new();
+            while let Some(raw) = source.next_packet().unwrap() {
+                packets.push(raw);
+            }
+            track.is_mono_eligible(&packets)
+        }
+
+        assert!(mono_eligible(wav(|left| left)), "L == R is mono");
+        assert!(
+            !mono_eligible(wav(|left| -left)),
+            "opposite polarity is all side"
+        );
+        assert!(
+            !mono_eligible(wav(|left| left / 2)),
+            "a panned source is stereo"
+        );
+    }
+
+    type TestSyncedStream = Arc<SyncedAudioStreamManager<f32, 2, 48_000>>;
+
+    /// A registry sending to a local socket, the socket, and the local
+    /// receiver it loops back to.
+    fn registry_on_wire() -> (
+        MusicStreamRegistry<f32, 2, 48_000>,
+        UdpSocket,
+        TestSyncedStream,
+    ) {
+        let wire = UdpSocket::bind("127.0.0.1:0").unwrap();
+        wire.set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let network_sender = NetworkSender::new(
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            wire.local_addr().unwrap(),
+            Arc::new(Mutex::new(SendTarget::Multicast)),
+        );
+        let synced_stream = Arc::new(SyncedAudioStreamManager::<f32, 2, 48_000>::new(
+            || 0,
+            Arc::new(AtomicBool::new(false)),
+        ));
+        let registry = MusicStreamRegistry::new(
+            NtpService::new(network_sender.clone()),
+            network_sender,
+            synced_stream.clone(),
+            Arc::new(AtomicBool::new(false)),
+            false,
+        );
+        (registry, wire, synced_stream)
+    }
+
+    #[test]
+    fn stop_clears_sender_and_broadcasts_end() {
+        let (registry, wire, synced_stream) = registry_on_wire();
+
+        let data = std::fs::read("assets/read_you.m4a").expect("assets/read_you.m4a not found");
+        let progress = Arc::new(MusicStreamProgress::new());
+        registry
+            .start_stream(data, "read_you.m4a".to_string(), progress.clone())
+            .unwrap();
+        let stream_id = synced_stream.active_streams()[0].stream_id;
+
+        registry.stop(stream_id).unwrap();
+
+        assert!(registry.streams.lock().unwrap().is_empty());
+        assert!(synced_stream.active_streams().is_empty());
+        assert!(!progress.is_streaming.load(Ordering::Relaxed));
+        assert!(registry.stop(stream_id).is_err());
+
+        let mut buf = vec![0u8; 65536];
+        let mut saw_stop = false;
+        while let Ok(len) = wire.recv(&mut buf) {
+            let packet =
+                rkyv::from_bytes::<TaggedPacket, rkyv::rancor::Error>(&buf[..len]).unwrap();
+            if packet.tag != SYNCED_CONTROL_TAG {
+                continue;
+            }
+            let control =
+                rkyv::from_bytes::<SyncedControl, rkyv::rancor::Error>(&packet.payload).unwrap();
+            if matches!(control, SyncedControl::Stop { stream_id: id } if id == stream_id) {
+                saw_stop = true;
+                break;
+            }
+        }
+        assert!(saw_stop, "stop should be broadcast to receivers");
+    }
+
+    #[test]
+    fn sent_progress_is_sample_based_and_reaches_total() {
+        let (registry, _wire, synced_stream) = registry_on_wire();
+        let data = std::fs::read("assets/read_you.m4a").expect("assets/read_you.m4a not found");
+
+        // Sent positions the sender may report: the end of every packet.
+        let mut source =
+            AudioSource::open(Box::new(Cursor::new(data.clone())), Some("m4a")).unwrap();
+        let sample_rate = source.codec_params().sample_rate.unwrap() as u64;
+        let mut boundaries = std::collections::HashSet::from([0]);
+        let mut total = 0;
+        while let Some(raw) = source.next_packet().unwrap() {
+            total += raw.dur as u64;
+            boundaries.insert(total);
+        }
+
+        let progress = Arc::new(MusicStreamProgress::new());
+        registry
+            .start_stream(data, "read_you.m4a".to_string(), progress.clone())
+            .unwrap();
+        let stream_id = synced_stream.active_streams()[0].stream_id;
+
+        let mut last_sent = 0;
+        let mut last_fraction = 0.0;
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < deadline {
+            let sent = progress.sent_samples.load(Ordering::Relaxed);
+            let fraction = progress.sent_fraction();
+            assert!(
+                boundaries.contains(&sent),
+                "{sent} is not a packet boundary"
+            );
+            assert!(sent >= last_sent, "sent samples went backwards");
+            assert!(fraction >= last_fraction, "sent fraction went backwards");
+            last_sent = sent;
+            last_fraction = fraction;
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(last_sent > 0, "nothing was sent");
+
+        // Skip to just before the end and let the sender finish.
+        registry
+            .seek(stream_id, total * 1000 / sample_rate - 500)
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let done = || {
+            progress.sent_fraction() == 1.0
+                && progress.sent_samples.load(Ordering::Relaxed)
+                    == progress.total_samples.load(Ordering::Relaxed)
+        };
+        while !done() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        registry.stop(stream_id).unwrap();
+        assert!(done(), "sending the last packet should reach the total");
+    }
+
+    #[test]
+    fn loop_region_wraps_from_end_back_to_start() {
+        let (registry, wire, synced_stream) = registry_on_wire();
+        let data = std::fs::read("assets/read_you.m4a").expect("assets/read_you.m4a not found");
+        let progress = Arc::new(MusicStreamProgress::new());
+        registry
+            .start_stream(data, "read_you.m4a".to_string(), progress.clone())
+            .unwrap();
+        let stream_id = synced_stream.active_streams()[0].stream_id;
+
+        let backwards = LoopRegion {
+            start_ms: 400,
+            end_ms: 100,
+        };
+        assert!(registry.set_loop(stream_id, Some(backwards)).is_err());
+        let region = LoopRegion {
+            start_ms: 100,
+            end_ms: 400,
+        };
+        registry.set_loop(stream_id, Some(region)).unwrap();
+
+        // (party time, seq) of the initial start and two trips around the loop.
+        let mut starts = Vec::new();
+        let mut buf = vec![0u8; 65536];
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while starts.len() < 3 && Instant::now() < deadline {
+            let Ok(len) = wire.recv(&mut buf) else {
+                continue;
+            };
+            let packet =
+                rkyv::from_bytes::<TaggedPacket, rkyv::rancor::Error>(&buf[..len]).unwrap();
+            if packet.tag != SYNCED_CONTROL_TAG {
+                continue;
+            }
+            let control =
+                rkyv::from_bytes::<SyncedControl, rkyv::rancor::Error>(&packet.payload).unwrap();
+            if let SyncedControl::Start {
+                party_clock_time,
+                seq,
+                ..
+            } = control
+            {
+                starts.push((party_clock_time, seq));
+            }
+        }
+        registry.stop(stream_id).unwrap();
+        assert_eq!(starts.len(), 3, "expected two wraps, got {starts:?}");
+        assert_eq!(*progress.loop_region.lock().unwrap(), None);
+
+        let (initial_at, initial_seq) = starts[0];
+        let (first_wrap_at, loop_seq) = starts[1];
+        let (second_wrap_at, second_loop_seq) = starts[2];
+        assert_eq!(initial_seq, 1);
+        assert!(loop_seq > 1, "loop starts after the beginning");
+        assert_eq!(second_loop_seq, loop_seq);
+
+        // Each wrap lands on the party time the loop end is reached, at
+        // packet granularity (an AAC packet is about 23 ms).
+        let packet_us = 25_000;
+        let to_end = first_wrap_at - initial_at;
+        assert!(
+            (400_000..400_000 + packet_us).contains(&to_end),
+            "{to_end} us"
+        );
+        let around = second_wrap_at - first_wrap_at;
+        assert!(
+            (300_000 - packet_us..300_000 + packet_us).contains(&around),
+            "{around} us"
+        );
+    }
 }
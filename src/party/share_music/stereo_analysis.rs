@@ -0,0 +1,53 @@
+//! Detection of music that is stereo in name only.
+//!
+//! Plenty of files carry two identical channels, or a stereo image too
+//! narrow to hear. Coding those as stereo spends bits on a side signal of
+//! nothing. [`StereoAnalyzer`] accumulates the mid (L+R) and side (L-R)
+//! energy of decoded audio; when the side is negligible next to the mid,
+//! the song can be sent as mono.
+
+/// Side energy at least this far below the mid counts as mono, in dB.
+const MONO_SIDE_THRESHOLD_DB: f64 = -40.0;
+/// Mid energy per sample below which audio counts as silence (-80 dBFS),
+/// which says nothing about the stereo image.
+const MIN_MID_ENERGY: f64 = 1e-8;
+
+/// Running mid and side energy of the first two channels.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StereoAnalyzer {
+    mid_energy: f64,
+    side_energy: f64,
+    frames: u64,
+}
+
+impl StereoAnalyzer {
+    /// Adds planar audio, one vector per channel. Audio with fewer than two
+    /// channels is ignored.
+    pub fn add(&mut self, channels: &[Vec<f32>]) {
+        let [left, right, ..] = channels else {
+            return;
+        };
+        for (&l, &r) in left.iter().zip(right) {
+            let mid = (l as f64 + r as f64) / 2.0;
+            let side = (l as f64 - r as f64) / 2.0;
+            self.mid_energy += mid * mid;
+            self.side_energy += side * side;
+        }
+        self.frames += left.len().min(right.len()) as u64;
+    }
+
+    /// Side level relative to the mid, in dB, or `None` while nothing
+    /// audible has been added.
+    pub fn side_to_mid_db(&self) -> Option<f64> {
+        if self.frames == 0 || self.mid_energy / (self.frames as f64) < MIN_MID_ENERGY {
+            return None;
+        }
+        Some(10.0 * (self.side_energy.max(f64::MIN_POSITIVE) / self.mid_energy).log10())
+    }
+
+    /// Whether the channels are close enough to identical to code as one.
+    pub fn is_mono_eligible(&self) -> bool {
+        self.side_to_mid_db()
+            .is_some_and(|db| db <= MONO_SIDE_THRESHOLD_DB)
+    }
+}
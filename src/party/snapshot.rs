@@ -0,0 +1,142 @@
+//! Serializable point-in-time view of a running party.
+//!
+//! [`PartySnapshot`] gathers everything an embedder typically wants to show or
+//! export — remote hosts and their realtime streams, NTP sync state, and the
+//! active synced music streams — into one plain-data structure that derives
+//! serde, so it can be sent over IPC or dumped as JSON.
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+
+use serde::{Deserialize, Serialize};
+
+use super::ntp::NtpDebugInfo;
+use super::share_music::{SyncedStreamId, SyncedStreamState};
+
+/// Everything observable about a party at a single moment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartySnapshot {
+    /// Remote hosts, sorted by IP address.
+    pub hosts: Vec<HostSnapshot>,
+    /// `None` until the NTP service has been started.
+    pub ntp: Option<NtpSnapshot>,
+    /// Synced streams, sorted by stream id.
+    pub synced_streams: Vec<SyncedStreamSnapshot>,
+}
+
+/// A remote host and the realtime streams it is currently sending.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostSnapshot {
+    pub ip: IpAddr,
+    /// Streams sorted by source address, then stream name.
+    pub streams: Vec<RealtimeStreamSnapshot>,
+}
+
+/// Jitter buffer statistics for one realtime stream.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RealtimeStreamSnapshot {
+    pub source_addr: SocketAddr,
+    pub stream_id: String,
+    /// Exponential moving average of the packet loss rate (0.0 - 1.0).
+    pub packet_loss: f64,
+    /// Target latency in packets.
+    pub target_latency: u64,
+    /// Target latency in milliseconds.
+    pub target_latency_ms: f64,
+    pub audio_level: u32,
+    /// Codec label such as "Opus 128k", once a frame has arrived.
+    pub codec: Option<String>,
+    /// Bitrate the sender declares, in bits per second.
+    pub declared_bitrate: Option<u32>,
+    /// Bitrate measured from received frames, in bits per second.
+    pub measured_bitrate: Option<u32>,
+    /// Turned down because its loss stayed high.
+    pub loss_muted: bool,
+}
+
+/// Clock synchronization state, mirroring [`NtpDebugInfo`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NtpSnapshot {
+    pub synced: bool,
+    pub offset_micros: i64,
+    pub last_rtt_micros: Option<i64>,
+    pub best_rtt_micros: Option<i64>,
+    pub local_time_micros: u64,
+    pub party_time_micros: u64,
+}
+
+impl From<NtpDebugInfo> for NtpSnapshot {
+    fn from(info: NtpDebugInfo) -> Self {
+        Self {
+            synced: info.synced,
+            offset_micros: info.offset_micros,
+            last_rtt_micros: info.last_rtt_micros,
+            best_rtt_micros: info.best_rtt_micros,
+            local_time_micros: info.local_time_micros,
+            party_time_micros: info.party_time_micros,
+        }
+    }
+}
+
+/// Playback state of one synced music stream.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncedStreamSnapshot {
+    pub stream_id: SyncedStreamId,
+    pub file_name: String,
+    pub is_local_sender: bool,
+    pub is_playing: bool,
+    pub samples_played: u64,
+    pub total_samples: u64,
+    pub sample_rate: u32,
+}
+
+impl From<SyncedStreamState> for SyncedStreamSnapshot {
+    fn from(state: SyncedStreamState) -> Self {
+        Self {
+            stream_id: state.stream_id,
+            file_name: state.meta.file_name,
+            is_local_sender: state.is_local_sender,
+            is_playing: state.progress.is_playing,
+            samples_played: state.progress.samples_played,
+            total_samples: state.progress.total_samples,
+            sample_rate: state.meta.codec_params.sample_rate,
+        }
+    }
+}
+
+impl PartySnapshot {
+    /// Assembles a snapshot from the per-stream views of each subsystem.
+    pub fn assemble(
+        realtime_streams: Vec<RealtimeStreamSnapshot>,
+        ntp: Option<NtpDebugInfo>,
+        synced_streams: Vec<SyncedStreamState>,
+    ) -> Self {
+        let mut by_host: BTreeMap<IpAddr, Vec<RealtimeStreamSnapshot>> = BTreeMap::new();
+        for stream in realtime_streams {
+            by_host
+                .entry(stream.source_addr.ip())
+                .or_default()
+                .push(stream);
+        }
+
+        let hosts = by_host
+            .into_iter()
+            .map(|(ip, mut streams)| {
+                streams.sort_by(|a, b| {
+                    (a.source_addr, &a.stream_id).cmp(&(b.source_addr, &b.stream_id))
+                });
+                HostSnapshot { ip, streams }
+            })
+            .collect();
+
+        let mut synced_streams: Vec<SyncedStreamSnapshot> =
+            synced_streams.into_iter().map(Into::into).collect();
+        synced_streams.sort_by_key(|s| s.stream_id);
+
+        Self {
+            hosts,
+            ntp: ntp.map(Into::into),
+            synced_streams,
+        }
+    }
+}
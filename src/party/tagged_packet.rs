@@ -9,18 +9,47 @@
 //! 1. Define a tag constant here.
 //! 2. Define the payload struct with rkyv derives in the stream module.
 //! 3. Implement [`NetworkStream`] on the stream and register it in `Party::run`.
+//!
+//! The envelope also names the sending instance with a random
+//! [`InstanceId`] chosen at startup, stamped on by the network sender. A
+//! participant whose IP changes mid-session, e.g. on a DHCP renew or a
+//! Wi-Fi roam, is thereby still recognized as the same one.
 
 use rkyv::{Archive, Deserialize, Serialize};
 
 pub type PacketTag = u32;
 
+/// Identifies one running instance across address changes.
+pub type InstanceId = u128;
+
+/// Instance id of packets sent without one; receivers go by address.
+pub const UNKNOWN_INSTANCE: InstanceId = 0;
+
+/// Picks a random id for this instance.
+pub fn new_instance_id() -> InstanceId {
+    uuid::Uuid::new_v4().as_u128()
+}
+
 /// Top-level wire envelope — the only type serialized directly to UDP.
 #[derive(Archive, Serialize, Deserialize, Debug, Clone)]
 pub struct TaggedPacket {
     pub tag: PacketTag,
+    /// Sending instance, or [`UNKNOWN_INSTANCE`].
+    pub instance: InstanceId,
     pub payload: Vec<u8>,
 }
 
+impl TaggedPacket {
+    /// A packet without an instance id; the sender fills it in.
+    pub fn new(tag: PacketTag, payload: Vec<u8>) -> Self {
+        Self {
+            tag,
+            instance: UNKNOWN_INSTANCE,
+            payload,
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Tag constants — one per payload type.
 // ---------------------------------------------------------------------------
@@ -32,3 +61,8 @@ pub const SYNCED_CONTROL_TAG: PacketTag = 4;
 pub const REQUEST_FRAMES_TAG: PacketTag = 5;
 pub const NTP_TAG: PacketTag = 6;
 pub const PLAYLIST_TAG: PacketTag = 7;
+pub const HEARTBEAT_TAG: PacketTag = 8;
+pub const SYNCED_BATCH_TAG: PacketTag = 9;
+pub const REALTIME_NACK_TAG: PacketTag = 10;
+pub const LOSS_FEEDBACK_TAG: PacketTag = 11;
+pub const BYE_TAG: PacketTag = 12;
@@ -0,0 +1,242 @@
+use std::f64::consts::PI;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::audio::buffers::monitor_buffer::DEFAULT_MONITOR_BACKLOG;
+use crate::audio::effects::Switch;
+use crate::audio::frame::AudioBuffer;
+use crate::audio::{AudioBatcher, MonitorBuffer, OpusEncoder};
+use crate::io::memory_transport::{MemoryEndpoint, MemoryNetwork};
+use crate::party::combinator::Mixer;
+use crate::party::network_stream::{NetworkStream, StreamRegistry};
+use crate::party::realtime_stream::{
+    RealtimeAudioStream, RealtimeFramePacker, RealtimeStreamId, TransmitSet,
+};
+use crate::pipeline::{Pullable, Pushable};
+use crate::push_chain;
+
+const SR: u32 = 48000;
+const CH: usize = 2;
+/// 10 ms device callbacks on both ends.
+const CALLBACK_FRAMES: usize = 480;
+const TONE_HZ: f64 = 440.0;
+const TONE_AMPLITUDE: f64 = 0.5;
+
+/// A minimal party instance: a mic chain that sends, and the realtime
+/// receive side that the speaker pulls from.
+struct TestPeer {
+    mic: Arc<dyn Pushable<AudioBuffer<f32, CH, SR>>>,
+    realtime: Arc<RealtimeAudioStream<f32, CH, SR>>,
+}
+
+impl TestPeer {
+    fn join(network: &Arc<MemoryNetwork<f32, CH, SR>>, addr: &str) -> Self {
+        let realtime = Arc::new(RealtimeAudioStream::<f32, CH, SR>::new());
+        let registry = Arc::new(StreamRegistry::from_streams(vec![
+            realtime.clone() as Arc<dyn NetworkStream<f32, CH, SR>>
+        ]));
+        let endpoint: Arc<MemoryEndpoint<f32, CH, SR>> =
+            Arc::new(network.join(addr.parse::<SocketAddr>().unwrap(), registry));
+
+        let mic = push_chain![
+            AudioBatcher::<f32, CH, SR>::new(20),
+            OpusEncoder::<f32, CH, SR>::new().unwrap(),
+            RealtimeFramePacker::new(RealtimeStreamId::Mic),
+            => endpoint
+        ];
+
+        Self { mic, realtime }
+    }
+
+    /// One speaker callback worth of interleaved output.
+    fn play(&self) -> Vec<f32> {
+        self.realtime
+            .pull_and_mix(CALLBACK_FRAMES * CH)
+            .map(|buffer| buffer.into_inner())
+            .unwrap_or_else(|| vec![0.0; CALLBACK_FRAMES * CH])
+    }
+}
+
+fn tone_block(start_frame: usize) -> AudioBuffer<f32, CH, SR> {
+    let samples = (start_frame..start_frame + CALLBACK_FRAMES)
+        .flat_map(|n| {
+            let s = (TONE_AMPLITUDE * (2.0 * PI * TONE_HZ * n as f64 / SR as f64).sin()) as f32;
+            [s; CH]
+        })
+        .collect();
+    AudioBuffer::new(samples).unwrap()
+}
+
+/// Magnitude of the `freq` component of a mono signal, normalized to the
+/// amplitude of a sine at that frequency.
+fn tone_magnitude(mono: &[f32], freq: f64) -> f64 {
+    let (re, im) = mono
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(re, im), (n, &x)| {
+            let phase = 2.0 * PI * freq * n as f64 / SR as f64;
+            (re + x as f64 * phase.cos(), im - x as f64 * phase.sin())
+        });
+    2.0 * (re * re + im * im).sqrt() / mono.len() as f64
+}
+
+/// Captured tone on one instance comes out of the other's speaker, through
+/// batching, Opus, the in-memory network, jitter buffer, decode and mix.
+#[test]
+fn test_capture_to_remote_output() {
+    let network = MemoryNetwork::<f32, CH, SR>::new();
+    let alice = TestPeer::join(&network, "10.0.0.1:5000");
+    let bob = TestPeer::join(&network, "10.0.0.2:5000");
+
+    let mut bob_output = Vec::new();
+    let mut alice_output = Vec::new();
+    for block in 0..150 {
+        alice.mic.push(tone_block(block * CALLBACK_FRAMES));
+        bob_output.extend(bob.play());
+        alice_output.extend(alice.play());
+    }
+
+    // Nobody hears themselves.
+    assert!(alice_output.iter().all(|&s| s == 0.0));
+
+    // Skip the first half second: buffering and codec warm-up.
+    let settled: Vec<f32> = bob_output[SR as usize / 2 * CH..]
+        .chunks(CH)
+        .map(|frame| frame[0])
+        .collect();
+    let tone = tone_magnitude(&settled, TONE_HZ);
+    let off_tone = tone_magnitude(&settled, 1000.0);
+
+    assert!(
+        (TONE_AMPLITUDE * 0.5..TONE_AMPLITUDE * 1.5).contains(&tone),
+        "tone should arrive at roughly its sent level, got {tone:.3}"
+    );
+    assert!(
+        tone > off_tone * 10.0,
+        "tone should dominate the output: {tone:.3} vs {off_tone:.3} at 1 kHz"
+    );
+}
+
+/// Frames of local monitor audio ahead of a marker pushed after `callbacks`
+/// speaker callbacks, with the mic running a block ahead every 7th one.
+/// Alice, when there, streams silence into Bob's jitter buffers meanwhile.
+fn monitor_delay_frames(with_remote: bool) -> (usize, Option<f64>) {
+    let network = MemoryNetwork::<f32, CH, SR>::new();
+    let alice = TestPeer::join(&network, "10.0.0.1:5000");
+    let bob = TestPeer::join(&network, "10.0.0.2:5000");
+    let monitor = Arc::new(MonitorBuffer::<f32, CH, SR>::new(DEFAULT_MONITOR_BACKLOG));
+    let speaker = Mixer::with_inputs([
+        bob.realtime.mixer().clone() as Arc<dyn Pullable<_>>,
+        monitor.clone(),
+    ]);
+    let silence = || AudioBuffer::new(vec![0.0f32; CALLBACK_FRAMES * CH]).unwrap();
+
+    for callback in 0..100 {
+        if with_remote {
+            alice.mic.push(silence());
+        }
+        monitor.push(silence());
+        if callback % 7 == 0 {
+            monitor.push(silence());
+        }
+        speaker.pull(CALLBACK_FRAMES * CH);
+    }
+
+    let mut marker = vec![0.0f32; CALLBACK_FRAMES * CH];
+    marker[..CH].fill(0.9);
+    monitor.push(AudioBuffer::new(marker).unwrap());
+    let mut output = Vec::new();
+    for _ in 0..10 {
+        let block = speaker
+            .pull(CALLBACK_FRAMES * CH)
+            .map(|buffer| buffer.into_inner())
+            .unwrap_or_else(|| vec![0.0; CALLBACK_FRAMES * CH]);
+        output.extend(block);
+    }
+    let delay = output
+        .chunks(CH)
+        .position(|frame| frame[0] > 0.5)
+        .expect("marker should reach the speaker");
+    let jitter_ms = bob
+        .realtime
+        .stream_snapshots()
+        .first()
+        .map(|stream| stream.target_latency_ms);
+    (delay, jitter_ms)
+}
+
+/// The local monitor bypasses network-side buffering: its delay stays
+/// within the monitor backlog even while the mic outpaces the speaker, and
+/// is the same whether or not remote streams are jitter buffered.
+#[test]
+fn test_monitor_delay_is_independent_of_jitter_buffering() {
+    let backlog_frames = (DEFAULT_MONITOR_BACKLOG.as_secs_f64() * SR as f64) as usize;
+
+    let (alone, none) = monitor_delay_frames(false);
+    assert!(none.is_none(), "no remote stream without Alice");
+    let (with_remote, jitter_ms) = monitor_delay_frames(true);
+    let jitter_ms = jitter_ms.expect("Alice's stream should be buffered");
+
+    assert!(
+        alone <= backlog_frames,
+        "monitor delay {alone} frames exceeds the {backlog_frames}-frame backlog"
+    );
+    assert_eq!(
+        alone, with_remote,
+        "remote streams changed the monitor delay"
+    );
+    let delay_ms = with_remote as f64 * 1000.0 / SR as f64;
+    assert!(
+        delay_ms < jitter_ms,
+        "monitor delay {delay_ms:.1} ms should sit well under the jitter target {jitter_ms:.1} ms"
+    );
+}
+
+/// Only the streams enabled in the transmit set reach peers, in any
+/// combination.
+#[test]
+fn test_transmit_set_gates_each_stream() {
+    let sent_streams = |mic: bool, system: bool| {
+        let network = MemoryNetwork::<f32, CH, SR>::new();
+        let bob = TestPeer::join(&network, "10.0.0.2:5000");
+        let alice: Arc<MemoryEndpoint<f32, CH, SR>> = Arc::new(network.join(
+            "10.0.0.1:5000".parse().unwrap(),
+            Arc::new(StreamRegistry::from_streams(Vec::new())),
+        ));
+
+        let transmit = TransmitSet::default();
+        transmit.set_enabled(RealtimeStreamId::Mic, mic);
+        transmit.set_enabled(RealtimeStreamId::System, system);
+        let chain = |stream_id: RealtimeStreamId| {
+            push_chain![
+                Switch::<f32, CH, SR>::new(transmit.flag(stream_id)),
+                AudioBatcher::<f32, CH, SR>::new(20),
+                OpusEncoder::<f32, CH, SR>::new().unwrap(),
+                RealtimeFramePacker::new(stream_id),
+                => alice.clone()
+            ]
+        };
+        let (mic_chain, system_chain) = (
+            chain(RealtimeStreamId::Mic),
+            chain(RealtimeStreamId::System),
+        );
+        for block in 0..10 {
+            mic_chain.push(tone_block(block * CALLBACK_FRAMES));
+            system_chain.push(tone_block(block * CALLBACK_FRAMES));
+        }
+
+        let mut streams: Vec<String> = bob
+            .realtime
+            .stream_snapshots()
+            .into_iter()
+            .map(|snapshot| snapshot.stream_id)
+            .collect();
+        streams.sort();
+        streams
+    };
+
+    assert_eq!(sent_streams(false, true), ["System"]);
+    assert_eq!(sent_streams(true, false), ["Mic"]);
+    assert_eq!(sent_streams(true, true), ["Mic", "System"]);
+    assert!(sent_streams(false, false).is_empty());
+}
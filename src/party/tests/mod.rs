@@ -1,2 +1,4 @@
 #[cfg(test)]
+mod full_loop;
+#[cfg(test)]
 mod sync_stream;
@@ -2,6 +2,7 @@ use std::io::Cursor;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
 
 use rubato::{FftFixedIn, Resampler};
 use symphonia::core::audio::{AudioBufferRef, Signal};
@@ -95,6 +96,9 @@ fn feed_and_start(
         total_frames: packets.len() as u64,
         total_samples: packets.iter().map(|(d, _)| *d as u64).sum(),
         codec_params,
+        pitch_semitones: 0,
+        tempo_percent: 100,
+        no_vocal_channels: 2,
     };
     mgr.receive_meta(addr, meta);
     // Start BEFORE feeding packets (seq=1 matches initial next_feed_seq=1).
@@ -134,6 +138,21 @@ fn pull_all(mgr: &SyncedAudioStreamManager<f32, CH, SR>, clock: &Arc<AtomicU64>)
     samples
 }
 
+/// Longest run of frames within `frames` whose samples are all exactly zero.
+fn longest_silence(samples: &[f32], frames: std::ops::Range<usize>) -> usize {
+    let mut longest = 0;
+    let mut run = 0;
+    for frame in samples.chunks_exact(CH).take(frames.end).skip(frames.start) {
+        run = if frame.iter().all(|&s| s == 0.0) {
+            run + 1
+        } else {
+            0
+        };
+        longest = longest.max(run);
+    }
+    longest
+}
+
 /// Decode packets through our pipeline nodes (SymphoniaDecoder + Resampler/Interleaver)
 /// using the push-based pipeline, to get a reference signal.
 fn decode_reference(codec_params: &WireCodecParams, packets: &[(u32, Vec<u8>)]) -> Vec<f32> {
@@ -570,6 +589,9 @@ fn test_out_of_order_frames_all_decoded() {
         total_frames: packets.len() as u64,
         total_samples: packets.iter().map(|(d, _)| *d as u64).sum(),
         codec_params,
+        pitch_semitones: 0,
+        tempo_percent: 100,
+        no_vocal_channels: 2,
     };
     mgr.receive_meta(test_addr(), meta);
     // Start before feeding, seq=1 matches initial next_feed_seq.
@@ -607,6 +629,310 @@ fn test_out_of_order_frames_all_decoded() {
     );
 }
 
+/// A frame lost for good leaves a gap; once the sender ends the stream, the
+/// rest is played with silence in the gap and playback completes.
+#[test]
+fn test_end_with_missing_frames_completes_playback() {
+    let sid = new_stream_id();
+    let (codec_params, packets) = load_packets(20);
+    let src_rate = codec_params.sample_rate;
+    const LOST_SEQ: u64 = 10;
+
+    let clock = Arc::new(AtomicU64::new(0));
+    let mgr = make_manager(clock.clone());
+    mgr.receive_meta(
+        test_addr(),
+        SyncedStreamMeta {
+            stream_id: sid,
+            file_name: "read_you.m4a".to_string(),
+            total_frames: packets.len() as u64,
+            total_samples: packets.iter().map(|(d, _)| *d as u64).sum(),
+            codec_params: codec_params.clone(),
+            pitch_semitones: 0,
+            tempo_percent: 100,
+            no_vocal_channels: 2,
+        },
+    );
+    mgr.receive_control(
+        test_addr(),
+        SyncedControl::Start {
+            stream_id: sid,
+            party_clock_time: 0,
+            seq: 1,
+            no_vocal_seq: 1,
+        },
+    );
+    for (seq, (dur, data)) in (1u64..).zip(&packets) {
+        if seq != LOST_SEQ {
+            mgr.receive(
+                test_addr(),
+                SyncedFrame::whole(sid, seq, *dur, data.clone()),
+            );
+        }
+    }
+    mgr.receive_control(
+        test_addr(),
+        SyncedControl::End {
+            stream_id: sid,
+            last_seq: packets.len() as u64,
+            no_vocal_last_seq: 0,
+        },
+    );
+
+    let progress = || mgr.active_streams().pop().unwrap().progress;
+    mgr.set_end_gap_grace(None);
+    mgr.flush_ended();
+    assert_eq!(progress().buffered_frames, LOST_SEQ - 1);
+    assert!(
+        !progress().is_complete,
+        "Without a grace the gap is waited on"
+    );
+
+    mgr.set_end_gap_grace(Some(Duration::ZERO));
+    mgr.flush_ended();
+    assert_eq!(progress().buffered_frames, packets.len() as u64 - 1);
+
+    let output = pull_all(&mgr, &clock);
+    let to_output = |dur: u32| dur as usize * SR as usize / src_rate as usize;
+    // Silence for the lost frame is sized like the frame before it.
+    let gap_frames = to_output(packets[LOST_SEQ as usize - 2].0);
+    let gap_at: usize = packets[..LOST_SEQ as usize - 1]
+        .iter()
+        .map(|(dur, _)| to_output(*dur))
+        .sum();
+    // Resampler latency moves the gap a little against the decoded audio.
+    let around_gap = gap_at.saturating_sub(gap_frames)..gap_at + 2 * gap_frames;
+    let filled = longest_silence(&output, around_gap.clone());
+    assert!(
+        filled.abs_diff(gap_frames) <= 2,
+        "Filled {filled} silent frames in place of a {gap_frames}-frame packet"
+    );
+
+    let reference_clock = Arc::new(AtomicU64::new(0));
+    let reference = make_manager(reference_clock.clone());
+    feed_and_start(
+        &reference,
+        test_addr(),
+        codec_params,
+        &packets,
+        new_stream_id(),
+    );
+    let reference = pull_all(&reference, &reference_clock);
+    assert!(
+        longest_silence(&reference, around_gap) < gap_frames / 2,
+        "The song itself is silent around the lost packet"
+    );
+
+    let progress = progress();
+    assert!(progress.is_complete);
+    assert!(progress.samples_played >= progress.total_samples);
+}
+
+/// With sample offsets on the frames, a lost frame's silence lasts as long
+/// as the frame really did, even when that differs from its neighbours.
+/// Summing frame sizes assumes it was as long as the one before.
+#[test]
+fn test_sample_offsets_size_gaps_exactly() {
+    let (codec_params, packets) = load_packets(20);
+    let src_rate = codec_params.sample_rate as u64;
+    const LOST_SEQ: u64 = 10;
+    // The lost frame held three frames' worth of samples.
+    const LOST_FRAMES: u64 = 3;
+
+    let offsets: Vec<u64> = (1u64..)
+        .zip(&packets)
+        .scan(0, |offset, (seq, (dur, _))| {
+            let start = *offset;
+            *offset += *dur as u64 * if seq == LOST_SEQ { LOST_FRAMES } else { 1 };
+            Some(start)
+        })
+        .collect();
+
+    let played = |stamped: bool| {
+        let sid = new_stream_id();
+        let clock = Arc::new(AtomicU64::new(0));
+        let mgr = make_manager(clock.clone());
+        mgr.receive_meta(
+            test_addr(),
+            SyncedStreamMeta {
+                stream_id: sid,
+                file_name: "read_you.m4a".to_string(),
+                total_frames: packets.len() as u64,
+                total_samples: offsets[packets.len() - 1] + packets[packets.len() - 1].0 as u64,
+                codec_params: codec_params.clone(),
+                pitch_semitones: 0,
+                tempo_percent: 100,
+                no_vocal_channels: 2,
+            },
+        );
+        mgr.receive_control(
+            test_addr(),
+            SyncedControl::Start {
+                stream_id: sid,
+                party_clock_time: 0,
+                seq: 1,
+                no_vocal_seq: 1,
+            },
+        );
+        for ((seq, (dur, data)), offset) in (1u64..).zip(&packets).zip(&offsets) {
+            if seq != LOST_SEQ {
+                mgr.receive(
+                    test_addr(),
+                    SyncedFrame::whole(sid, seq, *dur, data.clone())
+                        .with_sample_offset(stamped.then_some(*offset)),
+                );
+            }
+        }
+        mgr.receive_control(
+            test_addr(),
+            SyncedControl::End {
+                stream_id: sid,
+                last_seq: packets.len() as u64,
+                no_vocal_last_seq: 0,
+            },
+        );
+        mgr.set_end_gap_grace(Some(Duration::ZERO));
+        mgr.flush_ended();
+        pull_all(&mgr, &clock).len() as u64 / CH as u64
+    };
+
+    let dur = packets[LOST_SEQ as usize - 2].0 as u64;
+    let summed_gap = dur * SR as u64 / src_rate;
+    let exact_gap = LOST_FRAMES * dur * SR as u64 / src_rate;
+    assert_eq!(played(true) - played(false), exact_gap - summed_gap);
+}
+
+/// Source audio the sender skipped between two frames, e.g. at a
+/// discontinuity in the file, is only told by the sample offsets. The
+/// receiver plays it as silence, keeping the position in `pull_and_mix` on
+/// the sender's timeline; summing frame sizes would run that much early.
+#[test]
+fn test_sample_offsets_position_frames_after_a_skip() {
+    let (codec_params, packets) = load_packets(20);
+    let src_rate = codec_params.sample_rate as u64;
+    const SKIP_AFTER_SEQ: u64 = 10;
+    // Two frames' worth of source audio never made it into any packet.
+    let skipped = 2 * packets[0].0 as u64;
+
+    let offsets: Vec<u64> = (1u64..)
+        .zip(&packets)
+        .scan(0, |offset, (seq, (dur, _))| {
+            let start = *offset;
+            *offset += *dur as u64 + if seq == SKIP_AFTER_SEQ { skipped } else { 0 };
+            Some(start)
+        })
+        .collect();
+
+    let played = |stamped: bool| {
+        let sid = new_stream_id();
+        let clock = Arc::new(AtomicU64::new(0));
+        let mgr = make_manager(clock.clone());
+        mgr.receive_meta(
+            test_addr(),
+            SyncedStreamMeta {
+                stream_id: sid,
+                file_name: "read_you.m4a".to_string(),
+                total_frames: packets.len() as u64,
+                total_samples: offsets[packets.len() - 1] + packets[packets.len() - 1].0 as u64,
+                codec_params: codec_params.clone(),
+                pitch_semitones: 0,
+                tempo_percent: 100,
+                no_vocal_channels: 2,
+            },
+        );
+        mgr.receive_control(
+            test_addr(),
+            SyncedControl::Start {
+                stream_id: sid,
+                party_clock_time: 0,
+                seq: 1,
+                no_vocal_seq: 1,
+            },
+        );
+        for ((seq, (dur, data)), offset) in (1u64..).zip(&packets).zip(&offsets) {
+            mgr.receive(
+                test_addr(),
+                SyncedFrame::whole(sid, seq, *dur, data.clone())
+                    .with_sample_offset(stamped.then_some(*offset)),
+            );
+        }
+        mgr.receive_control(
+            test_addr(),
+            SyncedControl::End {
+                stream_id: sid,
+                last_seq: packets.len() as u64,
+                no_vocal_last_seq: 0,
+            },
+        );
+        mgr.set_end_gap_grace(Some(Duration::ZERO));
+        mgr.flush_ended();
+        pull_all(&mgr, &clock).len() as u64 / CH as u64
+    };
+
+    assert_eq!(played(true) - played(false), skipped * SR as u64 / src_rate);
+}
+
+/// Frames that arrive before the metadata are held up to the cap and decoded
+/// once the metadata creates the stream; without a cap they are dropped.
+#[test]
+fn test_pre_meta_frames_decoded_after_meta() {
+    const CAP: usize = 4;
+    let (codec_params, packets) = load_packets(CAP + 3);
+
+    for capacity in [0, CAP] {
+        let sid = new_stream_id();
+        let clock = Arc::new(AtomicU64::new(0));
+        let mgr = make_manager(clock.clone());
+        mgr.set_pre_meta_capacity(capacity);
+
+        for (seq, (dur, data)) in packets.iter().enumerate() {
+            mgr.receive(
+                test_addr(),
+                SyncedFrame::whole(sid, seq as u64 + 1, *dur, data.clone()),
+            );
+        }
+        assert!(mgr.active_streams().is_empty());
+
+        let meta = SyncedStreamMeta {
+            stream_id: sid,
+            file_name: "read_you.m4a".to_string(),
+            total_frames: packets.len() as u64,
+            total_samples: packets.iter().map(|(d, _)| *d as u64).sum(),
+            codec_params: codec_params.clone(),
+            pitch_semitones: 0,
+            tempo_percent: 100,
+            no_vocal_channels: 2,
+        };
+        mgr.receive_meta(test_addr(), meta);
+
+        let streams = mgr.active_streams();
+        let state = streams.iter().find(|s| s.stream_id == sid).unwrap();
+        assert_eq!(
+            state.progress.buffered_frames, capacity as u64,
+            "Only the first {capacity} early frames should be kept"
+        );
+
+        if capacity == 0 {
+            continue;
+        }
+        // Resume right after the held frames so they are not reset.
+        mgr.receive_control(
+            test_addr(),
+            SyncedControl::Start {
+                stream_id: sid,
+                party_clock_time: 0,
+                seq: CAP as u64 + 1,
+                no_vocal_seq: 1,
+            },
+        );
+        clock.store(0, Ordering::Relaxed);
+        assert!(
+            mgr.pull_and_mix(480).is_some(),
+            "Held frames should produce audio"
+        );
+    }
+}
+
 /// Splits one frame across two fragments and verifies it is reassembled before decoding.
 #[test]
 fn test_fragment_reassembly() {
@@ -621,6 +947,9 @@ fn test_fragment_reassembly() {
         total_frames: packets.len() as u64,
         total_samples: packets.iter().map(|(d, _)| *d as u64).sum(),
         codec_params,
+        pitch_semitones: 0,
+        tempo_percent: 100,
+        no_vocal_channels: 2,
     };
     mgr.receive_meta(test_addr(), meta);
 
@@ -637,6 +966,7 @@ fn test_fragment_reassembly() {
             fragment_idx: 0,
             fragment_total: 2,
             data: data1[..mid].to_vec(),
+            sample_offset: None,
         },
     );
     // First fragment only — frame 1 must not be fed yet
@@ -657,6 +987,7 @@ fn test_fragment_reassembly() {
             fragment_idx: 1,
             fragment_total: 2,
             data: data1[mid..].to_vec(),
+            sample_offset: None,
         },
     );
     // Send frames 2 and 3 whole
@@ -769,6 +1100,368 @@ fn test_pause_stops_output() {
     );
 }
 
+/// Verifies that a stop control removes the stream's buffer entirely.
+#[test]
+fn test_stop_removes_buffer() {
+    let sid = new_stream_id();
+    let (codec_params, packets) = load_packets(10);
+
+    let clock = Arc::new(AtomicU64::new(0));
+    let mgr = make_manager(clock.clone());
+    feed_and_start(&mgr, test_addr(), codec_params, &packets, sid);
+    assert_eq!(mgr.active_streams().len(), 1);
+
+    mgr.receive_control(test_addr(), SyncedControl::Stop { stream_id: sid });
+
+    assert!(
+        mgr.active_streams().is_empty(),
+        "Stopped stream should be removed"
+    );
+    assert!(
+        mgr.pull_and_mix(480).is_none(),
+        "Should produce no audio after stop"
+    );
+
+    // Late frames for a stopped stream must not resurrect it.
+    mgr.receive(
+        test_addr(),
+        SyncedFrame::whole(sid, 11, packets[0].0, packets[0].1.clone()),
+    );
+    assert!(mgr.active_streams().is_empty());
+}
+
+/// A party snapshot lists the current realtime and synced streams and
+/// survives a serde round trip unchanged.
+#[test]
+fn test_snapshot_reflects_streams_and_roundtrips() {
+    use crate::audio::OpusEncoder;
+    use crate::audio::frame::AudioBuffer;
+    use crate::party::PartySnapshot;
+    use crate::party::realtime_stream::{RealtimeAudioStream, RealtimeFrame, RealtimeStreamId};
+    use crate::pipeline::Node;
+
+    let sid = new_stream_id();
+    let (codec_params, packets) = load_packets(10);
+    let clock = Arc::new(AtomicU64::new(0));
+    let mgr = make_manager(clock.clone());
+    feed_and_start(&mgr, test_addr(), codec_params, &packets, sid);
+
+    let encoder = OpusEncoder::<f32, CH, SR>::new().unwrap();
+    let realtime = RealtimeAudioStream::<f32, CH, SR>::new();
+    let remote: SocketAddr = "10.0.0.2:5000".parse().unwrap();
+    for stream_id in [RealtimeStreamId::Mic, RealtimeStreamId::System] {
+        let input = AudioBuffer::<f32, CH, SR>::new(vec![0.1; 1920]).unwrap();
+        let opus_packet = encoder.process(input).unwrap();
+        realtime.receive(remote, RealtimeFrame::new(stream_id, 1, opus_packet));
+    }
+
+    let snapshot = PartySnapshot::assemble(realtime.stream_snapshots(), None, mgr.active_streams());
+
+    assert_eq!(snapshot.hosts.len(), 1);
+    assert_eq!(snapshot.hosts[0].ip, remote.ip());
+    let names: Vec<&str> = snapshot.hosts[0]
+        .streams
+        .iter()
+        .map(|s| s.stream_id.as_str())
+        .collect();
+    assert_eq!(names, ["Mic", "System"]);
+    assert!(snapshot.ntp.is_none());
+    assert_eq!(snapshot.synced_streams.len(), 1);
+    assert_eq!(snapshot.synced_streams[0].stream_id, sid);
+    assert_eq!(snapshot.synced_streams[0].file_name, "read_you.m4a");
+    assert!(snapshot.synced_streams[0].is_playing);
+
+    let json = serde_json::to_string(&snapshot).unwrap();
+    let decoded: PartySnapshot = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded, snapshot);
+}
+
+/// A loud mic ducks the synced music by the configured depth, and the music
+/// returns to full level once the release time has passed.
+#[test]
+fn test_ducking_attenuates_music_and_recovers() {
+    use crate::party::share_music::{Ducker, DuckingSettings};
+    use std::sync::Mutex;
+    use std::sync::atomic::AtomicU32;
+
+    const CHUNK: usize = 480;
+    let settings = DuckingSettings {
+        enabled: true,
+        threshold: 10,
+        depth_db: 20.0,
+        attack_ms: 10,
+        release_ms: 50,
+    };
+    let depth_gain = settings.depth_gain() as f32;
+
+    let sid = new_stream_id();
+    let (codec_params, packets) = load_packets(60);
+    let plain_clock = Arc::new(AtomicU64::new(0));
+    let plain = make_manager(plain_clock.clone());
+    feed_and_start(&plain, test_addr(), codec_params.clone(), &packets, sid);
+
+    let mic_level = Arc::new(AtomicU32::new(80));
+    let level = mic_level.clone();
+    let ducked_clock = Arc::new(AtomicU64::new(0));
+    let ducked = make_manager(ducked_clock.clone())
+        .with_ducker(Ducker::new(Arc::new(Mutex::new(settings)), move || {
+            level.load(Ordering::Relaxed)
+        }));
+    feed_and_start(&ducked, test_addr(), codec_params, &packets, sid);
+
+    let mut party_time_us = 0u64;
+    let mut pull_pair = || {
+        let a = plain.pull_and_mix(CHUNK).expect("plain manager ran dry");
+        let b = ducked.pull_and_mix(CHUNK).expect("ducked manager ran dry");
+        party_time_us += CHUNK as u64 * 1_000_000 / SR as u64;
+        plain_clock.store(party_time_us, Ordering::Relaxed);
+        ducked_clock.store(party_time_us, Ordering::Relaxed);
+        (a.into_inner(), b.into_inner())
+    };
+
+    // Two 10 ms chunks cover the attack; the third is fully ducked.
+    pull_pair();
+    pull_pair();
+    let (a, b) = pull_pair();
+    for (x, y) in a.iter().zip(&b) {
+        assert!(
+            (x * depth_gain - y).abs() < 1e-4,
+            "expected {} ducked to {}, got {}",
+            x,
+            x * depth_gain,
+            y
+        );
+    }
+
+    // Mic goes quiet: after 50 ms of release the music is back to full level.
+    mic_level.store(0, Ordering::Relaxed);
+    for _ in 0..5 {
+        pull_pair();
+    }
+    let (a, b) = pull_pair();
+    for (x, y) in a.iter().zip(&b) {
+        assert!((x - y).abs() < 1e-4, "expected {} restored, got {}", x, y);
+    }
+}
+
+/// Retransmission requests stay inside the lookahead window, respect the
+/// cap, and list the gaps closest to the playhead first.
+#[test]
+fn test_missing_frames_nearest_first_within_window() {
+    use crate::party::share_music::{RetransmitWindow, SyncedTrack};
+
+    let sid = new_stream_id();
+    let (codec_params, packets) = load_packets(120);
+    let clock = Arc::new(AtomicU64::new(0));
+    let mgr = make_manager(clock).with_retransmit_window(RetransmitWindow {
+        lookahead: 50,
+        max_requests: 5,
+    });
+
+    // Receive seq 1 plus a few far-apart packets, leaving gaps.
+    let received = [1u64, 10, 30, 100];
+    let fed: Vec<(u32, Vec<u8>)> = packets[..1].to_vec();
+    feed_and_start(&mgr, test_addr(), codec_params, &fed, sid);
+    for &seq in &received[1..] {
+        let (dur, data) = &packets[seq as usize - 1];
+        mgr.receive(
+            test_addr(),
+            SyncedFrame::whole(sid, seq, *dur, data.clone()),
+        );
+    }
+
+    let missing = mgr.get_missing_frames();
+    let (_, _, _, seqs) = missing
+        .iter()
+        .find(|(_, id, track, _)| *id == sid && *track == SyncedTrack::Original)
+        .expect("original track should report gaps");
+    assert_eq!(seqs, &[2, 3, 4, 5, 6]);
+
+    let mgr_uncapped = mgr.with_retransmit_window(RetransmitWindow {
+        lookahead: 50,
+        max_requests: 1000,
+    });
+    let missing = mgr_uncapped.get_missing_frames();
+    let (_, _, _, seqs) = missing
+        .iter()
+        .find(|(_, id, track, _)| *id == sid && *track == SyncedTrack::Original)
+        .unwrap();
+    assert!(seqs.windows(2).all(|w| w[0] < w[1]), "nearest gaps first");
+    assert!(
+        seqs.iter().all(|&seq| seq < 2 + 50),
+        "outside lookahead: {:?}",
+        seqs
+    );
+    assert!(!seqs.contains(&10) && !seqs.contains(&30));
+    assert_eq!(seqs.len(), 50 - 2);
+}
+
+/// Seeking past what has been buffered asks for the frames at the target
+/// before any of them arrive, and reports seeking until they have.
+#[test]
+fn test_seek_past_buffer_requests_target_frames() {
+    use crate::party::share_music::SyncedTrack;
+
+    let sid = new_stream_id();
+    let (codec_params, packets) = load_packets(120);
+    let clock = Arc::new(AtomicU64::new(0));
+    let mgr = make_manager(clock);
+    mgr.set_seek_ready_frames(10);
+    feed_and_start(&mgr, test_addr(), codec_params.clone(), &packets[..20], sid);
+    // The song is 120 frames long; only the first 20 have arrived.
+    mgr.receive_meta(
+        test_addr(),
+        SyncedStreamMeta {
+            stream_id: sid,
+            file_name: "read_you.m4a".to_string(),
+            total_frames: packets.len() as u64,
+            total_samples: packets.iter().map(|(d, _)| *d as u64).sum(),
+            codec_params,
+            pitch_semitones: 0,
+            tempo_percent: 100,
+            no_vocal_channels: 2,
+        },
+    );
+
+    let original_missing = |mgr: &SyncedAudioStreamManager<f32, CH, SR>| {
+        mgr.get_missing_frames()
+            .into_iter()
+            .find(|(_, id, track, _)| *id == sid && *track == SyncedTrack::Original)
+            .map(|(_, _, _, seqs)| seqs)
+    };
+    let seeking = |mgr: &SyncedAudioStreamManager<f32, CH, SR>| {
+        mgr.active_streams()
+            .iter()
+            .find(|s| s.stream_id == sid)
+            .unwrap()
+            .progress
+            .is_seeking
+    };
+    assert_eq!(original_missing(&mgr), None);
+    assert!(!seeking(&mgr), "starting from the top isn't a seek");
+
+    // Jump to frame 80, far past the 20 buffered.
+    mgr.receive_control(
+        test_addr(),
+        SyncedControl::Start {
+            stream_id: sid,
+            party_clock_time: 0,
+            seq: 80,
+            no_vocal_seq: 80,
+        },
+    );
+    assert!(seeking(&mgr));
+    assert_eq!(
+        original_missing(&mgr),
+        Some((80..90).collect::<Vec<u64>>()),
+        "nothing has arrived yet, but the target frames are wanted"
+    );
+
+    let deliver = |seqs: std::ops::Range<u64>| {
+        for seq in seqs {
+            let (dur, data) = &packets[seq as usize - 1];
+            mgr.receive(
+                test_addr(),
+                SyncedFrame::whole(sid, seq, *dur, data.clone()),
+            );
+        }
+    };
+    deliver(80..85);
+    assert!(seeking(&mgr));
+    assert_eq!(original_missing(&mgr), Some((85..90).collect::<Vec<u64>>()));
+
+    deliver(85..90);
+    assert!(!seeking(&mgr), "enough frames around the target arrived");
+    assert_eq!(original_missing(&mgr), None);
+}
+
+/// Audio resumes after seeking far ahead of the buffer and then back to
+/// before where playback was.
+#[test]
+fn test_seek_both_ways_resumes_audio() {
+    let sid = new_stream_id();
+    let (codec_params, packets) = load_packets(120);
+    let clock = Arc::new(AtomicU64::new(0));
+    let mgr = make_manager(clock.clone());
+    mgr.set_seek_ready_frames(10);
+    feed_and_start(&mgr, test_addr(), codec_params, &packets[..20], sid);
+    assert!(mgr.pull_and_mix(480).is_some());
+
+    let seek = |seq: u64| {
+        mgr.receive_control(
+            test_addr(),
+            SyncedControl::Start {
+                stream_id: sid,
+                party_clock_time: 0,
+                seq,
+                no_vocal_seq: seq,
+            },
+        );
+    };
+    let deliver = |seqs: std::ops::Range<u64>| {
+        for seq in seqs {
+            let (dur, data) = &packets[seq as usize - 1];
+            mgr.receive(
+                test_addr(),
+                SyncedFrame::whole(sid, seq, *dur, data.clone()),
+            );
+        }
+    };
+    let seeking = || {
+        mgr.active_streams()
+            .iter()
+            .find(|s| s.stream_id == sid)
+            .unwrap()
+            .progress
+            .is_seeking
+    };
+    let assert_audible = |output: &[f32], what: &str| {
+        assert!(!output.is_empty(), "no audio after seeking {}", what);
+        let rms =
+            (output.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / output.len() as f64).sqrt();
+        assert!(
+            rms > 1e-4,
+            "silent after seeking {} (RMS = {:.2e})",
+            what,
+            rms
+        );
+    };
+
+    seek(80);
+    assert!(seeking());
+    deliver(80..121);
+    assert!(!seeking());
+    assert_audible(&pull_all(&mgr, &clock), "forward");
+
+    // Back to before anything played since the first seek.
+    seek(5);
+    assert!(seeking(), "a backward seek resets too");
+    deliver(5..30);
+    assert!(!seeking());
+    assert_audible(&pull_all(&mgr, &clock), "backward");
+}
+
+/// A zero-length pull returns an empty buffer and leaves the playhead alone.
+#[test]
+fn test_zero_length_pull_leaves_playhead() {
+    let sid = new_stream_id();
+    let (codec_params, packets) = load_packets(10);
+    let clock = Arc::new(AtomicU64::new(0));
+    let mgr = make_manager(clock.clone());
+    feed_and_start(&mgr, test_addr(), codec_params, &packets, sid);
+
+    mgr.pull_and_mix(480).unwrap();
+    let before = mgr.active_streams()[0].progress.clone();
+
+    let pulled = mgr
+        .pull_and_mix(0)
+        .expect("zero-length pull should return a buffer");
+    assert!(pulled.data().is_empty());
+    assert_eq!(mgr.active_streams()[0].progress, before);
+
+    assert_eq!(mgr.pull_and_mix(480).unwrap().data().len(), 480 * CH);
+}
+
 /// Verifies that missing decoded data does not freeze the synced playhead.
 #[test]
 fn test_empty_stream_advances_playhead() {
@@ -785,6 +1478,9 @@ fn test_empty_stream_advances_playhead() {
             total_frames: 1,
             total_samples: SR as u64,
             codec_params,
+            pitch_semitones: 0,
+            tempo_percent: 100,
+            no_vocal_channels: 2,
         },
     );
     mgr.receive_control(
@@ -819,6 +1515,240 @@ fn test_empty_stream_advances_playhead() {
     );
 }
 
+/// Drift above the resync threshold jumps the playhead to the party-clock
+/// position; smaller drift is worked off a few frames per callback.
+#[test]
+fn test_drift_resync_threshold() {
+    const CHUNK: usize = 480;
+    let chunk_us = CHUNK as u64 * 1_000_000 / SR as u64;
+    let played = |mgr: &SyncedAudioStreamManager<f32, CH, SR>| {
+        mgr.active_streams()[0].progress.samples_played
+    };
+
+    let (codec_params, packets) = load_packets(100);
+    let clock = Arc::new(AtomicU64::new(0));
+    let mgr = make_manager(clock.clone());
+    mgr.set_resync_threshold(Duration::from_millis(20));
+    feed_and_start(&mgr, test_addr(), codec_params, &packets, new_stream_id());
+
+    let mut now = 0;
+    for _ in 0..5 {
+        mgr.pull_and_mix(CHUNK).unwrap();
+        now += chunk_us;
+        clock.store(now, Ordering::Relaxed);
+    }
+    assert_eq!(played(&mgr), 5 * CHUNK as u64);
+
+    // 15 ms behind: play slightly faster, output length unchanged.
+    now += 15_000;
+    clock.store(now, Ordering::Relaxed);
+    let before = played(&mgr);
+    let out = mgr.pull_and_mix(CHUNK).unwrap();
+    assert_eq!(out.data().len(), CHUNK * CH);
+    let advanced = played(&mgr) - before;
+    assert!(
+        advanced > CHUNK as u64 && advanced < CHUNK as u64 + 10,
+        "small drift should be corrected gradually, advanced {advanced}"
+    );
+
+    // Keep pulling in step with the clock: the lag keeps shrinking.
+    let lag = |mgr: &SyncedAudioStreamManager<f32, CH, SR>, now: u64| {
+        (now * SR as u64 / 1_000_000) as i64 - played(mgr) as i64
+    };
+    let lag_before = lag(&mgr, now + chunk_us);
+    for _ in 0..10 {
+        now += chunk_us;
+        clock.store(now, Ordering::Relaxed);
+        mgr.pull_and_mix(CHUNK).unwrap();
+    }
+    let lag_after = lag(&mgr, now + chunk_us);
+    assert!(lag_after < lag_before, "{lag_after} vs {lag_before}");
+    assert!(lag_after > 0, "gradual correction shouldn't jump");
+
+    // 100 ms behind: jump straight to the expected position.
+    now += 100_000 + chunk_us;
+    clock.store(now, Ordering::Relaxed);
+    mgr.pull_and_mix(CHUNK).unwrap();
+    let expected = now * SR as u64 / 1_000_000;
+    assert_eq!(played(&mgr), expected + CHUNK as u64);
+}
+
+/// The party clock stepping back, as on an NTP correction, neither rewinds
+/// nor underflows synced playback: the stream holds its position until the
+/// clock is back where it was, then carries on from there.
+#[test]
+fn test_party_clock_step_back_does_not_rewind() {
+    const CHUNK: usize = 480;
+    let chunk_us = CHUNK as u64 * 1_000_000 / SR as u64;
+    let played = |mgr: &SyncedAudioStreamManager<f32, CH, SR>| {
+        mgr.active_streams()[0].progress.samples_played
+    };
+
+    let (codec_params, packets) = load_packets(100);
+    let clock = Arc::new(AtomicU64::new(0));
+    let mgr = make_manager(clock.clone());
+    mgr.set_resync_threshold(Duration::from_millis(20));
+    feed_and_start(&mgr, test_addr(), codec_params, &packets, new_stream_id());
+
+    let mut now = 0;
+    for _ in 0..10 {
+        mgr.pull_and_mix(CHUNK).unwrap();
+        now += chunk_us;
+        clock.store(now, Ordering::Relaxed);
+    }
+    let before = played(&mgr);
+    assert_eq!(before, 10 * CHUNK as u64);
+    let before_us = now;
+
+    // Back to the stream's start: the callback still plays, from where
+    // playback was.
+    now = 0;
+    clock.store(now, Ordering::Relaxed);
+    let out = mgr
+        .pull_and_mix(CHUNK)
+        .expect("playback should go on through the step");
+    assert_eq!(out.data().len(), CHUNK * CH);
+    assert_eq!(played(&mgr), before + CHUNK as u64);
+
+    // Running on from there, the playhead never goes back, and the stream
+    // plays every callback again once the clock has caught up.
+    let mut last = played(&mgr);
+    for _ in 0..30 {
+        now += chunk_us;
+        clock.store(now, Ordering::Relaxed);
+        let out = mgr.pull_and_mix(CHUNK);
+        let position = played(&mgr);
+        assert!(
+            position >= last,
+            "playhead went back from {last} to {position}"
+        );
+        if now >= before_us {
+            assert!(out.is_some(), "no output at {now} µs after catching up");
+        }
+        last = position;
+    }
+    let expected = now * SR as u64 / 1_000_000;
+    assert!(
+        last.abs_diff(expected) <= 3 * CHUNK as u64,
+        "should be back in step with the clock: played {last}, expected {expected}"
+    );
+}
+
+/// Joining before the party clock is synced, music stays silent, and
+/// starts at the synced clock's position once it is.
+#[test]
+fn test_playback_waits_for_clock_sync() {
+    const CHUNK: usize = 480;
+    let chunk_us = CHUNK as u64 * 1_000_000 / SR as u64;
+
+    let (codec_params, packets) = load_packets(100);
+    let reference = {
+        let clock = Arc::new(AtomicU64::new(0));
+        let mgr = make_manager(clock.clone());
+        feed_and_start(
+            &mgr,
+            test_addr(),
+            codec_params.clone(),
+            &packets,
+            new_stream_id(),
+        );
+        pull_all(&mgr, &clock)
+    };
+
+    let clock = Arc::new(AtomicU64::new(0));
+    let synced = Arc::new(AtomicBool::new(false));
+    let mgr = {
+        let synced = synced.clone();
+        make_manager(clock.clone()).with_clock_synced(move || synced.load(Ordering::Relaxed))
+    };
+    mgr.set_resync_threshold(Duration::from_millis(20));
+    feed_and_start(&mgr, test_addr(), codec_params, &packets, new_stream_id());
+
+    let mut now = 0;
+    for _ in 0..10 {
+        assert!(mgr.pull_and_mix(CHUNK).is_none(), "played while unsynced");
+        now += chunk_us;
+        clock.store(now, Ordering::Relaxed);
+    }
+    assert_eq!(mgr.active_streams()[0].progress.samples_played, 0);
+
+    // Synced, the clock says the song is 100 ms in.
+    now = 100_000;
+    clock.store(now, Ordering::Relaxed);
+    synced.store(true, Ordering::Relaxed);
+    let out = mgr.pull_and_mix(CHUNK).expect("should play once synced");
+    let start = (now * SR as u64 / 1_000_000) as usize;
+    assert_eq!(
+        mgr.active_streams()[0].progress.samples_played,
+        (start + CHUNK) as u64
+    );
+    let want = &reference[start * CH..(start + CHUNK) * CH];
+    assert!(
+        out.data()
+            .iter()
+            .zip(want)
+            .all(|(a, b)| (a - b).abs() < 1e-6),
+        "should play from the synced position"
+    );
+}
+
+/// With concurrent streams on, songs shared by two people at once both
+/// play and are mixed, instead of the later one clearing the other.
+#[test]
+fn test_concurrent_streams_from_two_senders_mix() {
+    let (codec_params, packets) = load_packets(80);
+    let (first, second) = packets.split_at(40);
+    let alice: SocketAddr = "10.0.0.2:5000".parse().unwrap();
+    let bob: SocketAddr = "10.0.0.3:5000".parse().unwrap();
+
+    let play_alone = |addr, packets: &[(u32, Vec<u8>)]| {
+        let clock = Arc::new(AtomicU64::new(0));
+        let mgr = make_manager(clock.clone());
+        feed_and_start(&mgr, addr, codec_params.clone(), packets, new_stream_id());
+        pull_all(&mgr, &clock)
+    };
+    let alice_alone = play_alone(alice, first);
+    let bob_alone = play_alone(bob, second);
+    assert_eq!(alice_alone.len(), bob_alone.len());
+
+    // By default the later song takes over.
+    let clock = Arc::new(AtomicU64::new(0));
+    let mgr = make_manager(clock.clone());
+    feed_and_start(&mgr, alice, codec_params.clone(), first, new_stream_id());
+    let bob_sid = new_stream_id();
+    feed_and_start(&mgr, bob, codec_params.clone(), second, bob_sid);
+    let streams = mgr.active_streams();
+    assert_eq!(streams.len(), 1);
+    assert_eq!(streams[0].stream_id, bob_sid);
+
+    let clock = Arc::new(AtomicU64::new(0));
+    let mgr = make_manager(clock.clone());
+    mgr.set_concurrent_streams(true);
+    feed_and_start(&mgr, alice, codec_params.clone(), first, new_stream_id());
+    feed_and_start(&mgr, bob, codec_params.clone(), second, bob_sid);
+    assert_eq!(mgr.active_streams().len(), 2);
+    assert_eq!(mgr.playing_stream_count(), 2);
+
+    let mixed = pull_all(&mgr, &clock);
+    assert_eq!(mixed.len(), alice_alone.len());
+    for (i, ((m, a), b)) in mixed.iter().zip(&alice_alone).zip(&bob_alone).enumerate() {
+        let expected = (a + b) / 2.0;
+        assert!(
+            (m - expected).abs() < 1e-4,
+            "sample {i}: mixed {m}, expected {expected}"
+        );
+    }
+
+    // A new song from one of them still replaces that sender's own.
+    let alice_next = new_stream_id();
+    feed_and_start(&mgr, alice, codec_params, first, alice_next);
+    let mut sids: Vec<SyncedStreamId> = mgr.active_streams().iter().map(|s| s.stream_id).collect();
+    sids.sort();
+    let mut expected = vec![bob_sid, alice_next];
+    expected.sort();
+    assert_eq!(sids, expected);
+}
+
 /// Verifies that different pull sizes produce the same total audio content.
 /// This catches issues with leftover buffer handling at chunk boundaries.
 #[test]
@@ -1036,6 +1966,9 @@ fn test_incremental_feeding_matches_bulk() {
         total_frames: packets.len() as u64,
         total_samples: packets.iter().map(|(d, _)| *d as u64).sum(),
         codec_params,
+        pitch_semitones: 0,
+        tempo_percent: 100,
+        no_vocal_channels: 2,
     };
     mgr_inc.receive_meta(test_addr(), meta);
     mgr_inc.receive_control(
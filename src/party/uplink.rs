@@ -0,0 +1,398 @@
+//! Adaptation of outgoing streams to the available uplink.
+//!
+//! On a weak or metered uplink the socket's send buffer fills up and sends
+//! start failing. [`UplinkController`] estimates how saturated the uplink
+//! is from the share of failed sends in [`SendStats`], backed by the loss
+//! seen on peers' streams, and when it is saturated sheds load one
+//! [`UplinkLevel`] at a time.
+//!
+//! # Priority
+//!
+//! Load is shed from the least important traffic up:
+//!
+//! 1. NTP: never touched. Without clock sync nothing plays in time, and
+//!    its packets are tiny anyway.
+//! 2. Mic: degraded last, and only in bitrate; it always keeps sending.
+//! 3. System audio: lowered in bitrate, then not sent at all.
+//! 4. Music: background. Its redundant copies go first, since receivers
+//!    can ask for lost frames again.
+//!
+//! Once the uplink has stayed clean for a while, the controller steps back
+//! up one level at a time.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::audio::{AudioSample, OpusEncoder};
+use crate::io::SendStats;
+
+/// Share of its configured bitrate shared system audio keeps once reduced.
+const REDUCED_SYSTEM_SHARE: f64 = 0.375;
+/// Share of its configured bitrate the mic keeps at
+/// [`UplinkLevel::MicReduced`].
+const REDUCED_MIC_SHARE: f64 = 0.5;
+/// Share of its configured bitrate the mic keeps at
+/// [`UplinkLevel::MicMinimum`]; a quarter of the default is still fine for
+/// speech.
+const MINIMUM_MIC_SHARE: f64 = 0.25;
+/// Lowest bitrate a share is scaled down to, Opus's own floor.
+const MIN_BITRATE: i32 = 6_000;
+/// Sends per check below which the drop rate says too little to act on.
+const MIN_SENDS_PER_CHECK: u64 = 20;
+
+/// When the uplink counts as saturated, and how quickly to react.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UplinkConfig {
+    pub check_interval: Duration,
+    /// Share of failed sends at which load is shed.
+    pub degrade_drop_rate: f64,
+    /// Share of failed sends below which a check counts as clean.
+    pub recover_drop_rate: f64,
+    /// Loss on peers' streams at which load is shed even without failed
+    /// sends, as the shared network is then likely congested.
+    pub degrade_loss_rate: f64,
+    /// Clean checks in a row before stepping back up a level.
+    pub recover_checks: u32,
+}
+
+impl Default for UplinkConfig {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_secs(1),
+            degrade_drop_rate: 0.02,
+            recover_drop_rate: 0.002,
+            degrade_loss_rate: 0.15,
+            recover_checks: 5,
+        }
+    }
+}
+
+/// How much outgoing load has been shed, from none to the most. Each level
+/// includes the cuts of the ones before it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UplinkLevel {
+    #[default]
+    Full,
+    /// Music frames are sent once instead of redundantly.
+    MusicSingleCopy,
+    /// System audio is sent at a lower bitrate.
+    SystemReduced,
+    /// System audio isn't sent.
+    SystemOff,
+    /// The mic is sent at a lower bitrate.
+    MicReduced,
+    /// The mic is sent at the lowest bitrate used.
+    MicMinimum,
+}
+
+impl UplinkLevel {
+    const LADDER: [UplinkLevel; 6] = [
+        UplinkLevel::Full,
+        UplinkLevel::MusicSingleCopy,
+        UplinkLevel::SystemReduced,
+        UplinkLevel::SystemOff,
+        UplinkLevel::MicReduced,
+        UplinkLevel::MicMinimum,
+    ];
+
+    fn step(self, by: isize) -> Self {
+        let index = self as isize + by;
+        Self::LADDER[index.clamp(0, Self::LADDER.len() as isize - 1) as usize]
+    }
+
+    pub fn music_redundant(self) -> bool {
+        self < UplinkLevel::MusicSingleCopy
+    }
+
+    /// System audio bitrate given the one it was configured with, or
+    /// `None` when it isn't sent.
+    pub fn system_bitrate(self, configured: i32) -> Option<i32> {
+        match self {
+            UplinkLevel::Full | UplinkLevel::MusicSingleCopy => Some(configured),
+            UplinkLevel::SystemReduced => Some(scaled(configured, REDUCED_SYSTEM_SHARE)),
+            _ => None,
+        }
+    }
+
+    /// Mic bitrate given the one it was configured with.
+    pub fn mic_bitrate(self, configured: i32) -> i32 {
+        match self {
+            UplinkLevel::MicReduced => scaled(configured, REDUCED_MIC_SHARE),
+            UplinkLevel::MicMinimum => scaled(configured, MINIMUM_MIC_SHARE),
+            _ => configured,
+        }
+    }
+}
+
+/// `share` of `configured`, no lower than [`MIN_BITRATE`] and never above
+/// `configured`.
+fn scaled(configured: i32, share: f64) -> i32 {
+    ((configured as f64 * share) as i32)
+        .max(MIN_BITRATE)
+        .min(configured)
+}
+
+/// Hysteresis picking the [`UplinkLevel`] from one check's measurements.
+#[derive(Debug)]
+pub struct UplinkEstimator {
+    config: UplinkConfig,
+    level: UplinkLevel,
+    clean_checks: u32,
+}
+
+impl UplinkEstimator {
+    pub fn new(config: UplinkConfig) -> Self {
+        Self {
+            config,
+            level: UplinkLevel::Full,
+            clean_checks: 0,
+        }
+    }
+
+    pub fn level(&self) -> UplinkLevel {
+        self.level
+    }
+
+    /// Updates the level with the sends and drops since the last check and
+    /// the current loss on peers' streams.
+    pub fn update(&mut self, sent: u64, dropped: u64, loss_rate: f64) -> UplinkLevel {
+        let attempts = sent + dropped;
+        let drop_rate = if attempts >= MIN_SENDS_PER_CHECK {
+            dropped as f64 / attempts as f64
+        } else {
+            0.0
+        };
+        if drop_rate >= self.config.degrade_drop_rate || loss_rate >= self.config.degrade_loss_rate
+        {
+            self.level = self.level.step(1);
+            self.clean_checks = 0;
+        } else if drop_rate < self.config.recover_drop_rate {
+            self.clean_checks += 1;
+            if self.clean_checks >= self.config.recover_checks {
+                self.level = self.level.step(-1);
+                self.clean_checks = 0;
+            }
+        } else {
+            self.clean_checks = 0;
+        }
+        self.level
+    }
+}
+
+/// What the controller turns down.
+pub struct UplinkLevers<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    pub mic_encoder: Arc<OpusEncoder<Sample, CHANNELS, SAMPLE_RATE>>,
+    pub system_encoder: Arc<OpusEncoder<Sample, CHANNELS, SAMPLE_RATE>>,
+    /// Gate on the system audio pipeline, separate from the user's switch.
+    pub system_allowed: Arc<AtomicBool>,
+    pub music_redundant: Arc<AtomicBool>,
+}
+
+/// Periodically estimates the uplink and applies the resulting level to
+/// the outgoing streams.
+///
+/// Bitrates are scaled from, and never go above, the ones the encoders
+/// were configured with when the controller was created.
+pub struct UplinkController<Sample, const CHANNELS: usize, const SAMPLE_RATE: u32> {
+    config: UplinkConfig,
+    stats: Arc<SendStats>,
+    loss_rate: Box<dyn Fn() -> f64 + Send + Sync>,
+    levers: UplinkLevers<Sample, CHANNELS, SAMPLE_RATE>,
+    /// Configured mic and system audio bitrates.
+    configured: (i32, i32),
+    /// Estimator, and the send and drop counts at the last check.
+    state: Mutex<(UplinkEstimator, u64, u64)>,
+}
+
+impl<Sample: AudioSample + 'static, const CHANNELS: usize, const SAMPLE_RATE: u32>
+    UplinkController<Sample, CHANNELS, SAMPLE_RATE>
+{
+    pub fn new(
+        config: UplinkConfig,
+        stats: Arc<SendStats>,
+        loss_rate: impl Fn() -> f64 + Send + Sync + 'static,
+        levers: UplinkLevers<Sample, CHANNELS, SAMPLE_RATE>,
+    ) -> Self {
+        let (sent, dropped) = (stats.sent(), stats.dropped());
+        let configured = (
+            levers.mic_encoder.config().bitrate_bps,
+            levers.system_encoder.config().bitrate_bps,
+        );
+        Self {
+            config,
+            stats,
+            loss_rate: Box::new(loss_rate),
+            levers,
+            configured,
+            state: Mutex::new((UplinkEstimator::new(config), sent, dropped)),
+        }
+    }
+
+    /// Measures the uplink since the last check and applies a level change.
+    pub fn check(&self) {
+        let (sent, dropped) = (self.stats.sent(), self.stats.dropped());
+        let loss_rate = (self.loss_rate)();
+        let mut state = self.state.lock().unwrap();
+        let previous = state.0.level();
+        let level = state.0.update(sent - state.1, dropped - state.2, loss_rate);
+        state.1 = sent;
+        state.2 = dropped;
+        if level != previous {
+            info!("Uplink level {:?} -> {:?}", previous, level);
+            self.apply(level);
+        }
+    }
+
+    fn apply(&self, level: UplinkLevel) {
+        let levers = &self.levers;
+        let (mic_configured, system_configured) = self.configured;
+        let system_bitrate = level.system_bitrate(system_configured);
+        levers
+            .music_redundant
+            .store(level.music_redundant(), Ordering::Relaxed);
+        levers
+            .system_allowed
+            .store(system_bitrate.is_some(), Ordering::Release);
+        if let Some(bitrate) = system_bitrate
+            && let Err(e) = levers.system_encoder.set_bitrate(bitrate)
+        {
+            warn!("Failed to set system audio bitrate: {:?}", e);
+        }
+        if let Err(e) = levers
+            .mic_encoder
+            .set_bitrate(level.mic_bitrate(mic_configured))
+        {
+            warn!("Failed to set mic bitrate: {:?}", e);
+        }
+    }
+
+    /// Runs [`check`](Self::check) every check interval.
+    ///
+    /// Must be called from within a Tokio runtime context.
+    pub fn start(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.check_interval);
+            loop {
+                interval.tick().await;
+                self.check();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::opus::{OPUS_BITRATE, OpusEncoderConfig};
+
+    fn controller() -> UplinkController<f32, 2, 48000> {
+        controller_with_mic(OpusEncoder::new().unwrap())
+    }
+
+    fn controller_with_mic(mic: OpusEncoder<f32, 2, 48000>) -> UplinkController<f32, 2, 48000> {
+        let config = UplinkConfig {
+            recover_checks: 3,
+            ..UplinkConfig::default()
+        };
+        UplinkController::new(
+            config,
+            Arc::new(SendStats::default()),
+            || 0.0,
+            UplinkLevers {
+                mic_encoder: Arc::new(mic),
+                system_encoder: Arc::new(OpusEncoder::new().unwrap()),
+                system_allowed: Arc::new(AtomicBool::new(true)),
+                music_redundant: Arc::new(AtomicBool::new(true)),
+            },
+        )
+    }
+
+    fn level(controller: &UplinkController<f32, 2, 48000>) -> UplinkLevel {
+        controller.state.lock().unwrap().0.level()
+    }
+
+    /// One check interval of traffic with `dropped` of 100 sends failing.
+    fn second_of_traffic(controller: &UplinkController<f32, 2, 48000>, dropped: u64) {
+        for i in 0..100 {
+            controller.stats.record(i >= dropped);
+        }
+        controller.check();
+    }
+
+    #[test]
+    fn saturation_sheds_music_and_system_before_the_mic() {
+        let controller = controller();
+        let levers = &controller.levers;
+
+        // A clean uplink changes nothing.
+        second_of_traffic(&controller, 0);
+        assert_eq!(level(&controller), UplinkLevel::Full);
+
+        // Saturated: a tenth of all sends fail, check after check. The mic
+        // is only touched once music and system audio have been cut.
+        let mut levels = Vec::new();
+        for _ in 0..10 {
+            second_of_traffic(&controller, 10);
+            levels.push(level(&controller));
+            if level(&controller).mic_bitrate(OPUS_BITRATE) < OPUS_BITRATE {
+                assert!(!levers.music_redundant.load(Ordering::Relaxed));
+                assert!(!levers.system_allowed.load(Ordering::Relaxed));
+            }
+        }
+        assert_eq!(
+            levels[..5],
+            [
+                UplinkLevel::MusicSingleCopy,
+                UplinkLevel::SystemReduced,
+                UplinkLevel::SystemOff,
+                UplinkLevel::MicReduced,
+                UplinkLevel::MicMinimum,
+            ]
+        );
+        assert_eq!(level(&controller), UplinkLevel::MicMinimum, "bottoms out");
+
+        // Recovery gives the mic back first, one level per clean stretch.
+        for _ in 0..3 {
+            second_of_traffic(&controller, 0);
+        }
+        assert_eq!(level(&controller), UplinkLevel::MicReduced);
+        for _ in 0..9 {
+            second_of_traffic(&controller, 0);
+        }
+        assert_eq!(level(&controller), UplinkLevel::MusicSingleCopy);
+        assert!(levers.system_allowed.load(Ordering::Relaxed));
+        for _ in 0..3 {
+            second_of_traffic(&controller, 0);
+        }
+        assert_eq!(level(&controller), UplinkLevel::Full);
+        assert!(levers.music_redundant.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn mic_bitrate_scales_from_the_configured_preset() {
+        let voice = OpusEncoderConfig::PRESETS[1].1;
+        let controller = controller_with_mic(OpusEncoder::with_config(voice).unwrap());
+        let mic = &controller.levers.mic_encoder;
+
+        let mut bitrates = Vec::new();
+        for _ in 0..5 {
+            second_of_traffic(&controller, 10);
+            bitrates.push(mic.config().bitrate_bps);
+        }
+        assert_eq!(level(&controller), UplinkLevel::MicMinimum);
+        assert!(bitrates.iter().all(|&b| b <= voice.bitrate_bps));
+        assert_eq!(bitrates[3], voice.bitrate_bps / 2);
+        assert_eq!(bitrates[4], voice.bitrate_bps / 4);
+
+        // Recovering ends at the preset again, not the default bitrate.
+        for _ in 0..6 {
+            second_of_traffic(&controller, 0);
+        }
+        assert_eq!(level(&controller), UplinkLevel::SystemOff);
+        assert_eq!(mic.config().bitrate_bps, voice.bitrate_bps);
+        assert_eq!(mic.config().complexity, voice.complexity);
+    }
+}
@@ -7,12 +7,25 @@
 
 use anyhow::{Context, Result};
 use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64};
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
+use crate::audio::OpusEncoderConfig;
+use crate::audio::effects::gain_cell;
+use crate::audio::file::{HttpSource, url_file_name};
 use crate::io::SendTarget;
+use crate::io::interface_watch::InterfaceSet;
 use crate::music_provider::ProviderFactory;
-use crate::party::{Party, PartyConfig};
+use crate::party::realtime_stream::{
+    HostVolumes, MonitorTarget, RealtimeStreamId, StreamCodec, TransmitSet,
+};
+use crate::party::{
+    AutoBalanceSettings, DuckingSettings, MicEffect, Party, PartyConfig, SavedPlaylist,
+    saved_settings,
+};
 
 mod view_state;
 
@@ -56,14 +69,25 @@ pub struct StreamInfo {
     pub key: StreamViewKey,
     pub display_name: String,
     pub packet_loss: f32,
+    /// Target latency in frames.
     pub target_latency: f32,
+    /// Target latency in milliseconds.
+    pub target_latency_ms: f32,
     pub audio_level: u32,
+    /// Codec and bitrate, once a frame has arrived.
+    pub codec: Option<StreamCodec>,
+    /// Turned down because its loss stayed high.
+    pub loss_muted: bool,
+    /// App-defined labels shown as badges next to the stream.
+    pub tags: Vec<String>,
 }
 
 /// Information about a remote host
 #[derive(Debug, Clone, PartialEq)]
 pub struct HostInfo {
     pub id: HostId,
+    /// Name announced in the host's heartbeats, if it set one.
+    pub name: Option<String>,
     pub streams: Vec<StreamInfo>,
 }
 
@@ -74,12 +98,17 @@ pub enum ConnectionStatus {
     Connected,
 }
 
-/// Progress state for music stream sending and playback
+/// Progress state for music stream sending and playback.
+///
+/// Progress is counted in source-rate samples rather than packets so the bar
+/// advances in proportion to real content even when packet durations vary.
 pub struct MusicStreamProgress {
     pub file_name: Mutex<Option<String>>,
     pub is_streaming: AtomicBool,
-    pub streaming_current: AtomicU64,
-    pub streaming_total: AtomicU64,
+    pub sent_samples: AtomicU64,
+    pub total_samples: AtomicU64,
+    /// Section the stream loops, if any.
+    pub loop_region: Mutex<Option<crate::party::LoopRegion>>,
 }
 
 impl MusicStreamProgress {
@@ -87,35 +116,77 @@ impl MusicStreamProgress {
         Self {
             file_name: Mutex::new(None),
             is_streaming: AtomicBool::new(false),
-            streaming_current: AtomicU64::new(0),
-            streaming_total: AtomicU64::new(0),
+            sent_samples: AtomicU64::new(0),
+            total_samples: AtomicU64::new(0),
+            loop_region: Mutex::new(None),
         }
     }
 
+    /// Adds `samples` to the sent position after a packet goes out.
+    pub fn record_sent(&self, samples: u64) {
+        self.sent_samples
+            .fetch_add(samples, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Fraction of the file sent so far, clamped to `0.0..=1.0`.
+    pub fn sent_fraction(&self) -> f64 {
+        let sent = self.sent_samples.load(std::sync::atomic::Ordering::Relaxed);
+        let total = self
+            .total_samples
+            .load(std::sync::atomic::Ordering::Relaxed);
+        if total == 0 {
+            return 0.0;
+        }
+        (sent as f64 / total as f64).min(1.0)
+    }
+
     pub fn reset(&self) {
         *self.file_name.lock().unwrap() = None;
         self.is_streaming
             .store(false, std::sync::atomic::Ordering::Relaxed);
-        self.streaming_current
+        self.sent_samples
             .store(0, std::sync::atomic::Ordering::Relaxed);
-        self.streaming_total
+        self.total_samples
             .store(0, std::sync::atomic::Ordering::Relaxed);
+        *self.loop_region.lock().unwrap() = None;
     }
 }
 
 /// Shared application state
 pub struct AppState {
     pub connection_status: Arc<Mutex<ConnectionStatus>>,
-    pub mic_volume: Arc<Mutex<f32>>,
+    /// Mic gain as `f32` bits; see [`crate::audio::effects::store_gain`].
+    pub mic_volume: Arc<AtomicU32>,
     pub mic_audio_level: Arc<AtomicU32>,
     pub loopback_enabled: Arc<AtomicBool>,
-    pub system_audio_enabled: Arc<AtomicBool>,
+    /// Which of our realtime streams are sent.
+    pub transmit: TransmitSet,
+    /// Output volume of each remote host.
+    pub host_volumes: HostVolumes,
     pub system_audio_level: Arc<AtomicU32>,
     pub listen_enabled: Arc<AtomicBool>,
     pub vocal_removal_enabled: Arc<AtomicBool>,
+    /// Skips the input limiter, for comparing against the raw mic signal.
+    pub limiter_bypass: Arc<AtomicBool>,
+    /// Skips the mic de-esser; set by default.
+    pub de_esser_bypass: Arc<AtomicBool>,
+    /// Skips the mic reverb, leaving vocals dry; set by default.
+    pub reverb_bypass: Arc<AtomicBool>,
+    /// Order of the mic effects, read live by the mic pipeline.
+    pub mic_effect_order: Arc<Mutex<Vec<MicEffect>>>,
+    /// Music ducking under mic input, read live by the synced stream mixer.
+    pub ducking: Arc<Mutex<DuckingSettings>>,
+    /// Music level matching against the mics, read live by the synced
+    /// stream mixer.
+    pub auto_balance: Arc<Mutex<AutoBalanceSettings>>,
     pub view_state: Arc<PartyViewState>,
     pub music_progress: Arc<MusicStreamProgress>,
     pub send_target: Arc<Mutex<SendTarget>>,
+    /// Realtime stream soloed on our speaker for debugging. Local only;
+    /// what others hear is unaffected.
+    pub monitor_stream: Arc<Mutex<Option<MonitorTarget>>>,
+    /// Show jitter buffer latency in frames rather than milliseconds.
+    pub latency_in_frames: Arc<AtomicBool>,
     pub party: Mutex<Option<Party<f32, 2, 48000>>>,
     pub music_provider_factories: &'static [ProviderFactory],
 }
@@ -124,16 +195,25 @@ impl AppState {
     pub fn new(config: PartyConfig) -> Result<Arc<Self>> {
         let state = Arc::new(Self {
             connection_status: Arc::new(Mutex::new(ConnectionStatus::Disconnected)),
-            mic_volume: Arc::new(Mutex::new(1.0)),
+            mic_volume: gain_cell(1.0),
             mic_audio_level: Arc::new(AtomicU32::new(0)),
             loopback_enabled: Arc::new(AtomicBool::new(true)),
-            system_audio_enabled: Arc::new(AtomicBool::new(false)),
+            transmit: TransmitSet::default(),
+            host_volumes: HostVolumes::default(),
             system_audio_level: Arc::new(AtomicU32::new(0)),
             listen_enabled: Arc::new(AtomicBool::new(true)),
             vocal_removal_enabled: Arc::new(AtomicBool::new(false)),
+            limiter_bypass: Arc::new(AtomicBool::new(false)),
+            de_esser_bypass: Arc::new(AtomicBool::new(true)),
+            reverb_bypass: Arc::new(AtomicBool::new(true)),
+            mic_effect_order: Arc::new(Mutex::new(MicEffect::DEFAULT_ORDER.to_vec())),
+            ducking: Arc::new(Mutex::new(DuckingSettings::default())),
+            auto_balance: Arc::new(Mutex::new(AutoBalanceSettings::default())),
             view_state: Arc::new(PartyViewState::new()),
             music_progress: Arc::new(MusicStreamProgress::new()),
             send_target: Arc::new(Mutex::new(SendTarget::Multicast)),
+            monitor_stream: Arc::new(Mutex::new(None)),
+            latency_in_frames: Arc::new(AtomicBool::new(false)),
             party: Mutex::new(None),
             music_provider_factories: &[
                 crate::music_provider::local_file::factory,
@@ -142,30 +222,164 @@ impl AppState {
             ],
         });
 
+        let interface_watch_interval = config.interface_watch_interval;
         let mut party = Party::new(state.clone(), config);
         party.run()?;
         *state.party.lock().unwrap() = Some(party);
 
+        if let Some(interval) = interface_watch_interval {
+            state.start_interface_watch(interval);
+        }
+
         Ok(state)
     }
 
+    /// Checks the network interfaces every `interval` until the state is
+    /// dropped, see [`AppState::check_interfaces`].
+    fn start_interface_watch(self: &Arc<Self>, interval: Duration) {
+        let state = Arc::downgrade(self);
+        let spawned = thread::Builder::new()
+            .name("interface-watch".to_string())
+            .spawn(move || {
+                let mut known = None;
+                loop {
+                    thread::sleep(interval);
+                    let Some(state) = state.upgrade() else {
+                        return;
+                    };
+                    state.check_interfaces(&mut known);
+                }
+            });
+        if let Err(e) = spawned {
+            tracing::warn!("Failed to start interface watch: {}", e);
+        }
+    }
+
+    /// Compares the interfaces with the last check and, under automatic
+    /// interface selection, restarts the party so the sockets pick up a
+    /// change to the interface the group's traffic is routed through.
+    fn check_interfaces(&self, known: &mut Option<(bool, InterfaceSet)>) {
+        let mut party_guard = self.party.lock().expect("Party lock poisoned");
+        let Some(party) = party_guard.as_mut() else {
+            return;
+        };
+        let config = party.config().clone();
+        let current = config
+            .multicast
+            .group_addr(config.ipv6)
+            .and_then(|group| InterfaceSet::current(config.ipv6, group));
+        let current = match current {
+            Ok(current) => current,
+            Err(e) => {
+                tracing::warn!("Failed to enumerate interfaces: {:?}", e);
+                return;
+            }
+        };
+
+        // Switching IP family restarts the party anyway; start over.
+        let Some((ipv6, previous)) = known.replace((config.ipv6, current.clone())) else {
+            return;
+        };
+        if ipv6 != config.ipv6 {
+            return;
+        }
+        let diff = previous.diff(&current);
+        if diff.is_empty() {
+            return;
+        }
+
+        tracing::info!(
+            "Network interfaces changed: added {:?}, removed {:?}, readdressed {:?}",
+            diff.added,
+            diff.removed,
+            diff.changed
+        );
+        if diff.needs_rebind(config.send_interface_index) {
+            tracing::info!("Rebinding sockets to the current interfaces");
+            if let Err(e) = party.restart_with_config(config) {
+                tracing::error!("Failed to rebind after interface change: {:?}", e);
+            }
+        }
+    }
+
     pub fn enable_mic(&self) -> Result<()> {
         self.party
             .lock()
             .expect("Party lock poisoned")
             .as_ref()
             .context("Party not initialized")?
-            .mic_input()
-            .context("Mic input not initialized")?
-            .enable()
+            .enable_mic()?;
+        self.transmit.set_enabled(RealtimeStreamId::Mic, true);
+        Ok(())
     }
 
     pub fn disable_mic(&self) {
+        self.transmit.set_enabled(RealtimeStreamId::Mic, false);
         if let Some(party) = self.party.lock().expect("Party lock poisoned").as_ref() {
             if let Some(mic_input) = party.mic_input() {
                 mic_input.disable();
             }
         }
+        // The level meter stops updating once capture stops; clear the last
+        // reading so it doesn't keep ducking the music.
+        self.mic_audio_level
+            .store(0, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Whether the startup mic check should be offered.
+    pub fn mic_check_enabled(&self) -> bool {
+        self.party
+            .lock()
+            .expect("Party lock poisoned")
+            .as_ref()
+            .is_some_and(|party| party.config().mic_check.enabled)
+    }
+
+    /// Remembers whether to offer the startup mic check, also on later
+    /// launches.
+    pub fn set_mic_check_enabled(&self, enabled: bool) {
+        if let Some(party) = self.party.lock().expect("Party lock poisoned").as_mut() {
+            party.set_mic_check_enabled(enabled);
+            saved_settings::remember(party.config());
+        }
+    }
+
+    /// Bitrate, complexity and signal type of the mic encoder.
+    pub fn mic_encoder_config(&self) -> OpusEncoderConfig {
+        self.party
+            .lock()
+            .expect("Party lock poisoned")
+            .as_ref()
+            .map(|party| party.config().mic_encoder)
+            .unwrap_or_default()
+    }
+
+    /// Retunes the mic encoder without restarting the party.
+    pub fn set_mic_encoder_config(&self, config: OpusEncoderConfig) -> Result<()> {
+        self.party
+            .lock()
+            .expect("Party lock poisoned")
+            .as_mut()
+            .context("Party not initialized")?
+            .set_mic_encoder_config(config)
+    }
+
+    /// Captures a moment of mic input and reports whether it carried a
+    /// signal. Blocks for the check duration, holding the party lock only to
+    /// start and finish.
+    pub fn run_mic_check(&self) -> Result<crate::party::MicCheckResult> {
+        let check = self
+            .party
+            .lock()
+            .expect("Party lock poisoned")
+            .as_ref()
+            .context("Party not initialized")?
+            .start_mic_check()?;
+        let result = check.wait();
+        if let Some(party) = self.party.lock().expect("Party lock poisoned").as_ref() {
+            party.finish_mic_check(check);
+        }
+        Ok(result)
     }
 
     pub fn start_music_stream(&self, data: Vec<u8>, file_name: String) -> Result<()> {
@@ -177,6 +391,18 @@ impl AppState {
             .start_music_stream(data, file_name, self.music_progress.clone())
     }
 
+    /// Shares audio from an HTTP(S) URL. Blocks until the server responds,
+    /// without holding the party lock meanwhile.
+    pub fn start_music_url(&self, url: &str) -> Result<()> {
+        let source = HttpSource::open(url)?;
+        self.party
+            .lock()
+            .expect("Party lock poisoned")
+            .as_ref()
+            .context("Party not initialized")?
+            .start_music_url(source, url_file_name(url), self.music_progress.clone())
+    }
+
     pub fn pause_music(&self, stream_id: crate::party::SyncedStreamId) -> Result<()> {
         self.party
             .lock()
@@ -208,6 +434,38 @@ impl AppState {
             .seek_music(stream_id, position_ms)
     }
 
+    pub fn set_music_loop(
+        &self,
+        stream_id: crate::party::SyncedStreamId,
+        region: Option<crate::party::LoopRegion>,
+    ) -> Result<()> {
+        self.party
+            .lock()
+            .expect("Party lock poisoned")
+            .as_ref()
+            .context("Party not initialized")?
+            .set_music_loop(stream_id, region)
+    }
+
+    pub fn stop_music_stream(&self, stream_id: crate::party::SyncedStreamId) -> Result<()> {
+        self.party
+            .lock()
+            .expect("Party lock poisoned")
+            .as_ref()
+            .context("Party not initialized")?
+            .stop_music_stream(stream_id)
+    }
+
+    pub fn snapshot(&self) -> Result<crate::party::PartySnapshot> {
+        Ok(self
+            .party
+            .lock()
+            .expect("Party lock poisoned")
+            .as_ref()
+            .context("Party not initialized")?
+            .snapshot())
+    }
+
     pub fn set_music_vocal_removal(
         &self,
         stream_id: crate::party::SyncedStreamId,
@@ -221,6 +479,35 @@ impl AppState {
             .set_music_vocal_removal(stream_id, enabled)
     }
 
+    /// Transposes a stream we are sharing, for everyone in the party.
+    pub fn set_music_pitch(
+        &self,
+        stream_id: crate::party::SyncedStreamId,
+        semitones: i8,
+    ) -> Result<()> {
+        self.party
+            .lock()
+            .expect("Party lock poisoned")
+            .as_ref()
+            .context("Party not initialized")?
+            .set_music_pitch(stream_id, semitones)
+    }
+
+    /// Speeds up or slows down a stream we are sharing, for everyone in the
+    /// party, without changing its key.
+    pub fn set_music_tempo(
+        &self,
+        stream_id: crate::party::SyncedStreamId,
+        percent: u16,
+    ) -> Result<()> {
+        self.party
+            .lock()
+            .expect("Party lock poisoned")
+            .as_ref()
+            .context("Party not initialized")?
+            .set_music_tempo(stream_id, percent)
+    }
+
     // -- Playlist operations --
 
     /// Add a song to the shared playlist. The audio data is cached locally
@@ -231,6 +518,57 @@ impl AppState {
         Ok(())
     }
 
+    /// Add a song read from `path`, so it can be saved with the queue.
+    pub fn playlist_add_file(&self, data: Vec<u8>, title: String, path: PathBuf) -> Result<()> {
+        let playlist = self.playlist_handle()?;
+        playlist.add_file(data, title, path);
+        Ok(())
+    }
+
+    /// Names of the playlists saved in the configured directory.
+    pub fn saved_playlists(&self) -> Result<Vec<String>> {
+        SavedPlaylist::list(&self.playlist_dir()?)
+    }
+
+    /// Save the files this peer has queued as the playlist `name`.
+    pub fn save_playlist(&self, name: &str) -> Result<()> {
+        let tracks = self.playlist_handle()?.local_files();
+        if tracks.is_empty() {
+            anyhow::bail!("No queued files to save");
+        }
+        SavedPlaylist::new(name, tracks).save(&self.playlist_dir()?)?;
+        Ok(())
+    }
+
+    /// Queue every file of the saved playlist `name`. Files that no longer
+    /// exist are skipped with a warning; returns how many were skipped.
+    pub fn load_playlist(&self, name: &str) -> Result<usize> {
+        let saved = SavedPlaylist::load(&self.playlist_dir()?, name)?;
+        let playlist = self.playlist_handle()?;
+        let tracks = saved.read_tracks();
+        let skipped = saved.tracks.len() - tracks.len();
+        for track in tracks {
+            playlist.add_file(track.data, track.title, track.path);
+        }
+        Ok(skipped)
+    }
+
+    pub fn delete_saved_playlist(&self, name: &str) -> Result<()> {
+        SavedPlaylist::delete(&self.playlist_dir()?, name)
+    }
+
+    fn playlist_dir(&self) -> Result<PathBuf> {
+        Ok(self
+            .party
+            .lock()
+            .expect("Party lock poisoned")
+            .as_ref()
+            .context("Party not initialized")?
+            .config()
+            .playlist_dir
+            .clone())
+    }
+
     pub fn playlist_remove(&self, entry_id: u64) -> Result<()> {
         let playlist = self.playlist_handle()?;
         playlist.remove_entry(entry_id);
@@ -276,6 +614,21 @@ impl AppState {
             .playlist_handle()
     }
 
+    pub fn monitor_stream(&self) -> Option<MonitorTarget> {
+        *self
+            .monitor_stream
+            .lock()
+            .expect("Monitor stream lock poisoned")
+    }
+
+    /// Plays only `target` on the speaker, or the normal mix with `None`.
+    pub fn set_monitor_stream(&self, target: Option<MonitorTarget>) {
+        *self
+            .monitor_stream
+            .lock()
+            .expect("Monitor stream lock poisoned") = target;
+    }
+
     pub fn send_target(&self) -> SendTarget {
         self.send_target
             .lock()
@@ -1,12 +1,17 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use dashmap::DashMap;
 use dioxus::prelude::*;
 
-use crate::party::{NtpDebugInfo, PlaylistEntry, PlaylistState, StreamSnapshot, SyncedStreamState};
+use crate::party::network_stream::{DEFAULT_STATS_REFRESH_HZ, stats_interval};
+use crate::party::realtime_stream::{AggregateStats, StreamCodec};
+use crate::party::{
+    NtpDebugInfo, PlaylistEntry, PlaylistState, StreamSnapshot, SyncedStreamId, SyncedStreamState,
+};
 use crate::state::{HostId, HostInfo, StreamInfo};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -20,8 +25,12 @@ pub struct RealtimeStreamView {
     pub display_name: Arc<str>,
     packet_loss_ppm: AtomicU32,
     target_latency_frames: AtomicU32,
+    /// Target latency in milliseconds, as `f32` bits.
+    target_latency_ms: AtomicU32,
     audio_level: AtomicU32,
+    codec: Mutex<Option<StreamCodec>>,
     graph: Mutex<Vec<StreamSnapshot>>,
+    loss_muted: AtomicBool,
 }
 
 impl RealtimeStreamView {
@@ -30,8 +39,11 @@ impl RealtimeStreamView {
             display_name: Arc::from(display_name),
             packet_loss_ppm: AtomicU32::new(0),
             target_latency_frames: AtomicU32::new(0),
+            target_latency_ms: AtomicU32::new(0),
             audio_level: AtomicU32::new(0),
+            codec: Mutex::new(None),
             graph: Mutex::new(Vec::new()),
+            loss_muted: AtomicBool::new(false),
         }
     }
 
@@ -39,7 +51,9 @@ impl RealtimeStreamView {
         &self,
         packet_loss: f32,
         target_latency_frames: u32,
+        target_latency_ms: f32,
         audio_level: u32,
+        codec: Option<StreamCodec>,
         graph: Vec<StreamSnapshot>,
     ) {
         let packet_loss_ppm = (packet_loss.clamp(0.0, 1.0) * 1_000_000.0) as u32;
@@ -47,20 +61,34 @@ impl RealtimeStreamView {
             .store(packet_loss_ppm, Ordering::Relaxed);
         self.target_latency_frames
             .store(target_latency_frames, Ordering::Relaxed);
+        self.target_latency_ms
+            .store(target_latency_ms.to_bits(), Ordering::Relaxed);
         self.audio_level.store(audio_level, Ordering::Relaxed);
+        if let Ok(mut current) = self.codec.lock() {
+            *current = codec;
+        }
 
         if let Ok(mut snapshots) = self.graph.lock() {
             *snapshots = graph;
         }
     }
 
-    fn stream_info(&self, key: StreamViewKey) -> StreamInfo {
+    /// Whether the stream is currently turned down for high loss.
+    pub fn set_loss_muted(&self, muted: bool) {
+        self.loss_muted.store(muted, Ordering::Relaxed);
+    }
+
+    fn stream_info(&self, key: StreamViewKey, tags: Vec<String>) -> StreamInfo {
         StreamInfo {
             key,
+            tags,
             display_name: self.display_name.to_string(),
             packet_loss: self.packet_loss_ppm.load(Ordering::Relaxed) as f32 / 1_000_000.0,
             target_latency: self.target_latency_frames.load(Ordering::Relaxed) as f32,
+            target_latency_ms: f32::from_bits(self.target_latency_ms.load(Ordering::Relaxed)),
             audio_level: self.audio_level.load(Ordering::Relaxed),
+            codec: self.codec.lock().ok().and_then(|codec| *codec),
+            loss_muted: self.loss_muted.load(Ordering::Relaxed),
         }
     }
 
@@ -77,6 +105,8 @@ pub struct NtpView {
     last_rtt_micros: AtomicI64,
     best_rtt_micros: AtomicI64,
     offset_sample_count: AtomicU32,
+    accepted_samples: AtomicU64,
+    rejected_samples: AtomicU64,
     local_time_micros: AtomicU64,
     party_time_micros: AtomicU64,
     pending_requests: AtomicU32,
@@ -94,6 +124,8 @@ impl NtpView {
             last_rtt_micros: AtomicI64::new(i64::MIN),
             best_rtt_micros: AtomicI64::new(i64::MIN),
             offset_sample_count: AtomicU32::new(0),
+            accepted_samples: AtomicU64::new(0),
+            rejected_samples: AtomicU64::new(0),
             local_time_micros: AtomicU64::new(0),
             party_time_micros: AtomicU64::new(0),
             pending_requests: AtomicU32::new(0),
@@ -116,6 +148,10 @@ impl NtpView {
             .store(info.best_rtt_micros.unwrap_or(i64::MIN), Ordering::Relaxed);
         self.offset_sample_count
             .store(info.offset_sample_count as u32, Ordering::Relaxed);
+        self.accepted_samples
+            .store(info.accepted_samples, Ordering::Relaxed);
+        self.rejected_samples
+            .store(info.rejected_samples, Ordering::Relaxed);
         self.local_time_micros
             .store(info.local_time_micros, Ordering::Relaxed);
         self.party_time_micros
@@ -142,6 +178,8 @@ impl NtpView {
             last_rtt_micros: Self::load_optional_i64(&self.last_rtt_micros),
             best_rtt_micros: Self::load_optional_i64(&self.best_rtt_micros),
             offset_sample_count: self.offset_sample_count.load(Ordering::Relaxed) as usize,
+            accepted_samples: self.accepted_samples.load(Ordering::Relaxed),
+            rejected_samples: self.rejected_samples.load(Ordering::Relaxed),
             local_time_micros: self.local_time_micros.load(Ordering::Relaxed),
             party_time_micros: self.party_time_micros.load(Ordering::Relaxed),
             party_time_formatted: self
@@ -164,18 +202,33 @@ impl NtpView {
 
 pub struct PartyViewState {
     realtime_streams: DashMap<StreamViewKey, Arc<RealtimeStreamView>>,
+    /// Badges set by the app; kept independently of stream lifetime so a
+    /// stream can be tagged before its first packet arrives.
+    stream_tags: DashMap<StreamViewKey, Vec<String>>,
+    synced_stream_tags: DashMap<SyncedStreamId, Vec<String>>,
+    /// Hosts with live heartbeats and their announced names, listed even
+    /// when they send no audio.
+    present_hosts: DashMap<HostId, String>,
     synced_streams_signal: Mutex<Option<Signal<Vec<SyncedStreamState>, SyncStorage>>>,
     playlist_signal: Mutex<Option<Signal<PlaylistState, SyncStorage>>>,
     ntp: Arc<NtpView>,
+    realtime_aggregate: Mutex<AggregateStats>,
+    /// How often the stats shown here are refreshed, for the UI to poll at.
+    refresh_interval: Mutex<Duration>,
 }
 
 impl PartyViewState {
     pub fn new() -> Self {
         Self {
             realtime_streams: DashMap::new(),
+            stream_tags: DashMap::new(),
+            synced_stream_tags: DashMap::new(),
+            present_hosts: DashMap::new(),
             synced_streams_signal: Mutex::new(None),
             playlist_signal: Mutex::new(None),
             ntp: Arc::new(NtpView::new()),
+            realtime_aggregate: Mutex::new(AggregateStats::default()),
+            refresh_interval: Mutex::new(stats_interval(DEFAULT_STATS_REFRESH_HZ)),
         }
     }
 
@@ -194,23 +247,85 @@ impl PartyViewState {
         self.realtime_streams.retain(|key, _| active.contains(key));
     }
 
+    /// Sets the badges shown for a realtime stream. An empty list removes them.
+    pub fn set_stream_tags(&self, key: StreamViewKey, tags: Vec<String>) {
+        if tags.is_empty() {
+            self.stream_tags.remove(&key);
+        } else {
+            self.stream_tags.insert(key, tags);
+        }
+    }
+
+    /// Sets extra badges shown for a synced stream. An empty list removes them.
+    pub fn set_synced_stream_tags(&self, stream_id: SyncedStreamId, tags: Vec<String>) {
+        if tags.is_empty() {
+            self.synced_stream_tags.remove(&stream_id);
+        } else {
+            self.synced_stream_tags.insert(stream_id, tags);
+        }
+    }
+
+    /// Replaces the set of hosts known to be present by heartbeat.
+    pub fn set_present_hosts(&self, hosts: HashMap<HostId, String>) {
+        self.present_hosts.retain(|id, _| hosts.contains_key(id));
+        for (id, name) in hosts {
+            self.present_hosts.insert(id, name);
+        }
+    }
+
+    pub fn set_aggregate_stats(&self, stats: AggregateStats) {
+        *self.realtime_aggregate.lock().unwrap() = stats;
+    }
+
+    /// Target latency and loss averaged over all realtime streams.
+    pub fn aggregate_stats(&self) -> AggregateStats {
+        *self.realtime_aggregate.lock().unwrap()
+    }
+
+    pub fn set_refresh_interval(&self, interval: Duration) {
+        *self.refresh_interval.lock().unwrap() = interval;
+    }
+
+    pub fn refresh_interval(&self) -> Duration {
+        *self.refresh_interval.lock().unwrap()
+    }
+
     pub fn realtime_hosts(&self) -> Vec<HostInfo> {
         let mut hosts: Vec<HostInfo> = Vec::new();
 
         for entry in self.realtime_streams.iter() {
             let key = entry.key().clone();
-            let stream = entry.value().stream_info(key);
+            let tags = self
+                .stream_tags
+                .get(&key)
+                .map(|tags| tags.clone())
+                .unwrap_or_default();
+            let stream = entry.value().stream_info(key, tags);
 
             if let Some(host) = hosts.iter_mut().find(|h| h.id == stream.key.host_id) {
                 host.streams.push(stream);
             } else {
                 hosts.push(HostInfo {
                     id: stream.key.host_id,
+                    name: None,
                     streams: vec![stream],
                 });
             }
         }
 
+        for entry in self.present_hosts.iter() {
+            let name = Some(entry.value().clone()).filter(|name| !name.is_empty());
+            if let Some(host) = hosts.iter_mut().find(|h| h.id == *entry.key()) {
+                host.name = name;
+            } else {
+                hosts.push(HostInfo {
+                    id: *entry.key(),
+                    name,
+                    streams: Vec::new(),
+                });
+            }
+        }
+
         hosts.sort_by_key(|h| h.id.to_string());
         for host in &mut hosts {
             host.streams.sort_by(|a, b| {
@@ -236,7 +351,12 @@ impl PartyViewState {
         }
     }
 
-    pub fn set_synced_streams(&self, streams: Vec<SyncedStreamState>) {
+    pub fn set_synced_streams(&self, mut streams: Vec<SyncedStreamState>) {
+        for stream in &mut streams {
+            if let Some(tags) = self.synced_stream_tags.get(&stream.stream_id) {
+                stream.tags.extend(tags.iter().cloned());
+            }
+        }
         let signal = self
             .synced_streams_signal
             .lock()
@@ -290,6 +410,10 @@ impl PartyViewState {
 
     pub fn clear(&self) {
         self.realtime_streams.clear();
+        self.stream_tags.clear();
+        self.synced_stream_tags.clear();
+        self.present_hosts.clear();
+        self.set_aggregate_stats(AggregateStats::default());
         let synced_signal = self
             .synced_streams_signal
             .lock()
@@ -316,3 +440,57 @@ impl Default for PartyViewState {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(addr: &str, stream_id: &str) -> StreamViewKey {
+        let source_addr: SocketAddr = addr.parse().unwrap();
+        StreamViewKey {
+            host_id: HostId::from(source_addr),
+            source_addr,
+            stream_id: stream_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn stream_tags_surface_in_host_info() {
+        let view = PartyViewState::new();
+        let mic = key("10.0.0.2:5000", "Mic");
+        let system = key("10.0.0.2:5000", "System");
+
+        view.set_stream_tags(mic.clone(), vec!["Host".to_string()]);
+        view.realtime_stream(mic.clone(), "Mic".to_string()).update(
+            0.0,
+            3,
+            60.0,
+            10,
+            None,
+            Vec::new(),
+        );
+        view.realtime_stream(system.clone(), "System".to_string())
+            .update(0.0, 3, 60.0, 10, None, Vec::new());
+
+        let hosts = view.realtime_hosts();
+        assert_eq!(hosts.len(), 1);
+        let tags_of = |key: &StreamViewKey| {
+            hosts[0]
+                .streams
+                .iter()
+                .find(|s| &s.key == key)
+                .map(|s| s.tags.clone())
+                .unwrap()
+        };
+        assert_eq!(tags_of(&mic), ["Host"]);
+        assert!(tags_of(&system).is_empty());
+
+        view.set_stream_tags(mic.clone(), Vec::new());
+        assert!(
+            view.realtime_hosts()[0]
+                .streams
+                .iter()
+                .all(|s| s.tags.is_empty())
+        );
+    }
+}
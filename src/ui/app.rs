@@ -1,12 +1,15 @@
 //! Main application entry point for the UI.
 
+use crate::audio::effects::load_gain;
 use crate::state::{AppState, HostInfo};
 use dioxus::prelude::*;
 use dioxus::signals::SyncStorage;
 use std::sync::Arc;
 
+use super::mic_check::MicCheckBanner;
 use super::sidebar::{BottomNav, SidebarMenu};
 use super::sidebar_panels::{AudioControlPanel, DebugPanel, ParticipantsPanel, ShareMusicPanel};
+use crate::party::realtime_stream::{AggregateStats, RealtimeStreamId};
 use crate::party::{NtpDebugInfo, PlaylistState, SyncedStreamState};
 
 const NARROW_BREAKPOINT: u32 = 600;
@@ -21,6 +24,7 @@ pub struct UIState {
     pub system_audio_level: Signal<u32>,
     pub listen_enabled: Signal<bool>,
     pub ntp_info: Signal<Option<NtpDebugInfo>>,
+    pub realtime_stats: Signal<AggregateStats>,
     pub synced_streams: Signal<Vec<SyncedStreamState>, SyncStorage>,
     pub playlist: Signal<PlaylistState, SyncStorage>,
     pub is_narrow: Signal<bool>,
@@ -96,6 +100,7 @@ pub fn App() -> Element {
         system_audio_level: use_signal(|| 0u32),
         listen_enabled: use_signal(|| true),
         ntp_info: use_signal(|| None::<NtpDebugInfo>),
+        realtime_stats: use_signal(AggregateStats::default),
         synced_streams: synced_streams_signal,
         playlist: playlist_signal,
         is_narrow: use_signal(|| false),
@@ -144,9 +149,7 @@ fn AppLayout() -> Element {
             loop {
                 ui.active_hosts.set(state.view_state.realtime_hosts());
 
-                if let Ok(vol) = state.mic_volume.lock() {
-                    ui.mic_volume.set(*vol);
-                }
+                ui.mic_volume.set(load_gain(&state.mic_volume));
 
                 let level = state
                     .mic_audio_level
@@ -159,11 +162,8 @@ fn AppLayout() -> Element {
                         .load(std::sync::atomic::Ordering::Relaxed),
                 );
 
-                ui.system_audio_enabled.set(
-                    state
-                        .system_audio_enabled
-                        .load(std::sync::atomic::Ordering::Relaxed),
-                );
+                ui.system_audio_enabled
+                    .set(state.transmit.is_enabled(RealtimeStreamId::System));
 
                 let sys_level = state
                     .system_audio_level
@@ -177,11 +177,12 @@ fn AppLayout() -> Element {
                 );
 
                 ui.ntp_info.set(state.view_state.ntp_debug());
+                ui.realtime_stats.set(state.view_state.aggregate_stats());
 
                 // synced_streams and playlist are written directly to signals
                 // by the network layer — no polling needed.
 
-                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                tokio::time::sleep(state.view_state.refresh_interval()).await;
             }
         });
     });
@@ -193,6 +194,8 @@ fn AppLayout() -> Element {
             // app-shell flex
             class: "h-screen flex w-full bg-slate-900 text-slate-100 font-sans overflow-hidden selection:bg-indigo-500 selection:text-white safe-area-layout",
 
+            MicCheckBanner {}
+
             if (ui.is_narrow)() {
                 div {
                     // class: "app-mobile-shell flex flex-col w-full",
@@ -272,6 +275,10 @@ fn Debug() -> Element {
     let ui = use_context::<UIState>();
 
     rsx! {
-        DebugPanel { ntp_info: (ui.ntp_info)() }
+        DebugPanel {
+            ntp_info: (ui.ntp_info)(),
+            realtime_stats: (ui.realtime_stats)(),
+            hosts: (ui.active_hosts)(),
+        }
     }
 }
@@ -0,0 +1,105 @@
+//! Startup mic check banner.
+
+use crate::party::MicCheckResult;
+use crate::state::AppState;
+use dioxus::prelude::*;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq)]
+enum MicCheckStatus {
+    Running,
+    Done(MicCheckResult),
+    Hidden,
+}
+
+/// Checks the mic once on startup and shows the result until dismissed.
+/// Hidden when the check is turned off in the party config.
+#[allow(non_snake_case)]
+#[component]
+pub fn MicCheckBanner() -> Element {
+    let state_arc = use_context::<Arc<AppState>>();
+    let enabled = state_arc.mic_check_enabled();
+    let mut status = use_signal(move || {
+        if enabled {
+            MicCheckStatus::Running
+        } else {
+            MicCheckStatus::Hidden
+        }
+    });
+    let mut dont_check_again = use_signal(|| false);
+
+    let state_check = state_arc.clone();
+    use_future(move || {
+        let state = state_check.clone();
+        async move {
+            if !enabled {
+                return;
+            }
+            let result = tokio::task::spawn_blocking(move || state.run_mic_check()).await;
+            // Skipped while running: leave it hidden.
+            if status() != MicCheckStatus::Running {
+                return;
+            }
+            match result {
+                Ok(Ok(result)) => status.set(MicCheckStatus::Done(result)),
+                Ok(Err(e)) => {
+                    tracing::warn!("Mic check failed: {:?}", e);
+                    status.set(MicCheckStatus::Hidden);
+                }
+                Err(e) => {
+                    tracing::warn!("Mic check task failed: {:?}", e);
+                    status.set(MicCheckStatus::Hidden);
+                }
+            }
+        }
+    });
+
+    let state_dismiss = state_arc.clone();
+    let on_dismiss = move |_| {
+        if dont_check_again() {
+            state_dismiss.set_mic_check_enabled(false);
+        }
+        status.set(MicCheckStatus::Hidden);
+    };
+
+    let (icon, message, tone) = match status() {
+        MicCheckStatus::Hidden => return rsx! {},
+        MicCheckStatus::Running => ("🎙️", "Checking your microphone…", "text-slate-300"),
+        MicCheckStatus::Done(result) if result.is_ok() => {
+            ("✅", result.message(), "text-emerald-400")
+        }
+        MicCheckStatus::Done(result) => ("⚠️", result.message(), "text-amber-400"),
+    };
+    let running = status() == MicCheckStatus::Running;
+
+    rsx! {
+        div {
+            class: "fixed top-4 right-4 z-50 glass-card p-4 rounded-2xl max-w-sm space-y-3",
+
+            div {
+                class: "flex items-center gap-3 text-sm {tone}",
+                span { class: "text-xl", "{icon}" }
+                span { "{message}" }
+            }
+
+            div {
+                class: "flex items-center justify-between gap-3",
+                label {
+                    class: "flex items-center gap-2 text-xs text-slate-400",
+                    input {
+                        r#type: "checkbox",
+                        class: "w-4 h-4 rounded border-slate-600 bg-slate-800 text-indigo-500",
+                        checked: dont_check_again(),
+                        onchange: move |evt| dont_check_again.set(evt.checked()),
+                    }
+                    "Don't check on startup"
+                }
+                button {
+                    class: "px-3 py-1 text-xs rounded-lg bg-slate-700 hover:bg-slate-600 text-slate-200",
+                    onclick: on_dismiss,
+                    if running { "Skip" } else { "OK" }
+                }
+            }
+        }
+    }
+}
@@ -3,10 +3,12 @@
 //! This module provides the Dioxus-based UI for the application:
 //!
 //! - [`app`] - Main application entry point
+//! - [`mic_check`] - Startup microphone check banner
 //! - [`sidebar`] - Left sidebar with audio controls and status
 //! - [`participants`] - Main content area showing connected hosts
 
 mod app;
+mod mic_check;
 mod sidebar;
 mod sidebar_panels;
 
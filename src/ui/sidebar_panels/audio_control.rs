@@ -1,5 +1,8 @@
-use crate::io::SendTarget;
-use crate::party::PartyConfig;
+use crate::audio::OpusEncoderConfig;
+use crate::audio::effects::store_gain;
+use crate::io::{Passphrase, SendTarget};
+use crate::party::realtime_stream::RealtimeStreamId;
+use crate::party::{MicEffect, PartyConfig, UnderrunPolicy, saved_settings};
 use crate::state::AppState;
 use cpal::traits::{DeviceTrait, HostTrait};
 use cpal::{Device, DeviceId};
@@ -164,10 +167,8 @@ pub fn AudioControlPanel(
 
     let state_vol = state_arc.clone();
     let on_volume_change = move |evt: Event<FormData>| {
-        if let Ok(value_str) = evt.value().parse::<f32>()
-            && let Ok(mut vol) = state_vol.mic_volume.lock()
-        {
-            *vol = value_str / 100.0;
+        if let Ok(value_str) = evt.value().parse::<f32>() {
+            store_gain(&state_vol.mic_volume, value_str / 100.0);
         }
     };
 
@@ -183,12 +184,10 @@ pub fn AudioControlPanel(
 
     let state_sys = state_arc.clone();
     let on_system_audio_toggle = move |_| {
-        let current = state_sys
-            .system_audio_enabled
-            .load(std::sync::atomic::Ordering::Relaxed);
+        let current = state_sys.transmit.is_enabled(RealtimeStreamId::System);
         state_sys
-            .system_audio_enabled
-            .store(!current, std::sync::atomic::Ordering::Relaxed);
+            .transmit
+            .set_enabled(RealtimeStreamId::System, !current);
     };
 
     let state_listen = state_arc.clone();
@@ -405,6 +404,16 @@ pub fn AudioControlPanel(
                         }
                     }
 
+                    MusicDucking {}
+
+                    MusicBalance {}
+
+                    EffectBypass {}
+
+                    EffectOrder {}
+
+                    MicEncoding {}
+
                     DeviceSettings {}
                 }
             }
@@ -442,6 +451,368 @@ fn DeviceSelector(
     }
 }
 
+#[allow(non_snake_case)]
+#[component]
+fn DuckingSlider(
+    label: &'static str,
+    display: String,
+    min: i32,
+    max: i32,
+    value: i32,
+    on_change: EventHandler<i32>,
+) -> Element {
+    rsx! {
+        div {
+            div {
+                class: "flex justify-between text-sm mb-2",
+                span { class: "text-slate-400", "{label}" }
+                span { class: "font-mono font-bold text-slate-200", "{display}" }
+            }
+            input {
+                r#type: "range",
+                min,
+                max,
+                value,
+                class: "w-full",
+                oninput: move |evt: Event<FormData>| {
+                    if let Ok(v) = evt.value().parse::<i32>() {
+                        on_change.call(v);
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Bypass toggles for the effects on the mic path, for A/B listening.
+#[allow(non_snake_case)]
+#[component]
+fn EffectBypass() -> Element {
+    let state_arc = use_context::<Arc<AppState>>();
+    let mut refresh = use_signal(|| 0u32);
+    let _ = refresh();
+    let effects = [
+        ("Input Limiter", state_arc.limiter_bypass.clone()),
+        ("De-esser", state_arc.de_esser_bypass.clone()),
+        ("Reverb", state_arc.reverb_bypass.clone()),
+    ]
+    .map(|(name, bypass)| {
+        let bypassed = bypass.load(std::sync::atomic::Ordering::Relaxed);
+        (name, bypass, bypassed)
+    });
+
+    rsx! {
+        div {
+            class: "glass-card p-6 rounded-2xl",
+
+            div {
+                class: "text-xs font-bold text-slate-500 uppercase tracking-wider mb-6",
+                "Effects"
+            }
+
+            div {
+                class: "space-y-2",
+
+                for (name, bypass, bypassed) in effects {
+                    div {
+                        key: "{name}",
+                        class: "flex items-center gap-3 py-2",
+                        input {
+                            r#type: "checkbox",
+                            id: "bypass-{name}",
+                            class: "w-4 h-4 rounded border-slate-600 bg-slate-800 text-indigo-500 focus:ring-indigo-500 focus:ring-offset-slate-900",
+                            checked: bypassed,
+                            onchange: move |evt: Event<FormData>| {
+                                bypass.store(evt.checked(), std::sync::atomic::Ordering::Relaxed);
+                                refresh += 1;
+                            },
+                        }
+                        label {
+                            r#for: "bypass-{name}",
+                            class: "text-sm text-slate-300",
+                            "Bypass {name}"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Preset for the mic encoder, applied to the running stream right away.
+#[allow(non_snake_case)]
+#[component]
+fn MicEncoding() -> Element {
+    let state_arc = use_context::<Arc<AppState>>();
+    let mut error = use_signal(|| None::<String>);
+    let current = state_arc.mic_encoder_config();
+    let mut options: Vec<(String, String)> = OpusEncoderConfig::PRESETS
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| (i.to_string(), name.to_string()))
+        .collect();
+    let selected = match OpusEncoderConfig::PRESETS
+        .iter()
+        .position(|(_, config)| *config == current)
+    {
+        Some(i) => i.to_string(),
+        None => {
+            options.push((
+                String::new(),
+                format!("Custom ({} kbps)", current.bitrate_bps / 1000),
+            ));
+            String::new()
+        }
+    };
+
+    rsx! {
+        div {
+            class: "glass-card p-6 rounded-2xl",
+
+            div {
+                class: "text-xs font-bold text-slate-500 uppercase tracking-wider mb-6",
+                "Mic Encoding"
+            }
+
+            DeviceSelector {
+                label: "Quality",
+                options,
+                selected,
+                on_change: {
+                    let state = state_arc.clone();
+                    move |v: String| {
+                        let Some((_, config)) = v.parse::<usize>().ok().and_then(|i| OpusEncoderConfig::PRESETS.get(i)) else {
+                            return;
+                        };
+                        error.set(state.set_mic_encoder_config(*config).err().map(|e| e.to_string()));
+                    }
+                },
+            }
+
+            if let Some(error) = error() {
+                div {
+                    class: "text-xs text-amber-400 mt-2",
+                    "{error}"
+                }
+            }
+        }
+    }
+}
+
+/// Reorderable list of the mic effects; the top one runs first.
+#[allow(non_snake_case)]
+#[component]
+fn EffectOrder() -> Element {
+    let state_arc = use_context::<Arc<AppState>>();
+    let initial = state_arc.mic_effect_order.lock().unwrap().clone();
+    let mut order = use_signal(move || initial);
+
+    // Push every change to the order the mic pipeline reads.
+    use_effect(move || {
+        let next = order();
+        if let Ok(mut shared) = state_arc.mic_effect_order.lock() {
+            *shared = next;
+        }
+    });
+
+    let labels: Vec<&'static str> = order().iter().map(MicEffect::label).collect();
+    let last = labels.len().saturating_sub(1);
+
+    rsx! {
+        div {
+            class: "glass-card p-6 rounded-2xl",
+
+            div {
+                class: "text-xs font-bold text-slate-500 uppercase tracking-wider mb-6",
+                "Mic Effect Order"
+            }
+
+            div {
+                class: "space-y-2",
+
+                for (index, label) in labels.into_iter().enumerate() {
+                    div {
+                        key: "{label}",
+                        class: "flex items-center gap-3 rounded-lg border border-slate-700 bg-slate-800/60 px-4 py-2",
+                        span { class: "text-xs font-mono text-slate-500", "{index + 1}" }
+                        span { class: "flex-1 text-sm text-slate-200", "{label}" }
+                        button {
+                            class: "px-2 text-slate-400 hover:text-slate-200 disabled:opacity-30",
+                            disabled: index == 0,
+                            onclick: move |_| order.write().swap(index, index - 1),
+                            "↑"
+                        }
+                        button {
+                            class: "px-2 text-slate-400 hover:text-slate-200 disabled:opacity-30",
+                            disabled: index == last,
+                            onclick: move |_| order.write().swap(index, index + 1),
+                            "↓"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Live controls for lowering shared music while someone is on the mic.
+#[allow(non_snake_case)]
+#[component]
+fn MusicDucking() -> Element {
+    let state_arc = use_context::<Arc<AppState>>();
+    let initial = *state_arc.ducking.lock().unwrap();
+    let mut settings = use_signal(move || initial);
+
+    // Push every change to the shared settings the audio thread reads.
+    use_effect(move || {
+        let next = settings();
+        if let Ok(mut shared) = state_arc.ducking.lock() {
+            *shared = next;
+        }
+    });
+
+    let current = settings();
+
+    rsx! {
+        div {
+            class: "glass-card p-6 rounded-2xl",
+
+            div {
+                class: "text-xs font-bold text-slate-500 uppercase tracking-wider mb-6",
+                "Music Ducking"
+            }
+
+            div {
+                class: "space-y-4",
+
+                div {
+                    class: "flex items-center gap-3 py-2",
+                    input {
+                        r#type: "checkbox",
+                        id: "ducking-toggle",
+                        class: "w-4 h-4 rounded border-slate-600 bg-slate-800 text-indigo-500 focus:ring-indigo-500 focus:ring-offset-slate-900",
+                        checked: current.enabled,
+                        onchange: move |evt| settings.write().enabled = evt.checked(),
+                    }
+                    label {
+                        r#for: "ducking-toggle",
+                        class: "text-sm text-slate-300",
+                        "Lower music while someone sings"
+                    }
+                }
+
+                if current.enabled {
+                    DuckingSlider {
+                        label: "Mic Threshold",
+                        display: format!("{}%", current.threshold),
+                        min: 1,
+                        max: 100,
+                        value: current.threshold as i32,
+                        on_change: move |v: i32| settings.write().threshold = v as u32,
+                    }
+                    DuckingSlider {
+                        label: "Depth",
+                        display: format!("-{} dB", current.depth_db as i32),
+                        min: 0,
+                        max: 40,
+                        value: current.depth_db as i32,
+                        on_change: move |v: i32| settings.write().depth_db = v as f32,
+                    }
+                    DuckingSlider {
+                        label: "Attack",
+                        display: format!("{} ms", current.attack_ms),
+                        min: 0,
+                        max: 500,
+                        value: current.attack_ms as i32,
+                        on_change: move |v: i32| settings.write().attack_ms = v as u32,
+                    }
+                    DuckingSlider {
+                        label: "Release",
+                        display: format!("{} ms", current.release_ms),
+                        min: 50,
+                        max: 3000,
+                        value: current.release_ms as i32,
+                        on_change: move |v: i32| settings.write().release_ms = v as u32,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Live controls for keeping shared music a set level below the mics.
+#[allow(non_snake_case)]
+#[component]
+fn MusicBalance() -> Element {
+    let state_arc = use_context::<Arc<AppState>>();
+    let initial = *state_arc.auto_balance.lock().unwrap();
+    let mut settings = use_signal(move || initial);
+
+    // Push every change to the shared settings the audio thread reads.
+    use_effect(move || {
+        let next = settings();
+        if let Ok(mut shared) = state_arc.auto_balance.lock() {
+            *shared = next;
+        }
+    });
+
+    let current = settings();
+
+    rsx! {
+        div {
+            class: "glass-card p-6 rounded-2xl",
+
+            div {
+                class: "text-xs font-bold text-slate-500 uppercase tracking-wider mb-6",
+                "Mic / Music Balance"
+            }
+
+            div {
+                class: "space-y-4",
+
+                div {
+                    class: "flex items-center gap-3 py-2",
+                    input {
+                        r#type: "checkbox",
+                        id: "auto-balance-toggle",
+                        class: "w-4 h-4 rounded border-slate-600 bg-slate-800 text-indigo-500 focus:ring-indigo-500 focus:ring-offset-slate-900",
+                        checked: current.enabled,
+                        onchange: move |evt| settings.write().enabled = evt.checked(),
+                    }
+                    label {
+                        r#for: "auto-balance-toggle",
+                        class: "text-sm text-slate-300",
+                        "Keep music level matched to the mics"
+                    }
+                }
+
+                if current.enabled {
+                    DuckingSlider {
+                        label: "Mic Above Music",
+                        display: format!("{} dB", current.mic_above_music_db as i32),
+                        min: -12,
+                        max: 24,
+                        value: current.mic_above_music_db as i32,
+                        on_change: move |v: i32| settings.write().mic_above_music_db = v as f32,
+                    }
+                    DuckingSlider {
+                        label: "Max Adjustment",
+                        display: format!("±{} dB", current.max_adjust_db as i32),
+                        min: 0,
+                        max: 30,
+                        value: current.max_adjust_db as i32,
+                        on_change: move |v: i32| settings.write().max_adjust_db = v as f32,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Stereo output pairs offered for multichannel interfaces (outputs 1/2
+/// through 7/8).
+const OUTPUT_CHANNEL_PAIRS: usize = 4;
+
 #[allow(non_snake_case)]
 #[component]
 fn DeviceSettings() -> Element {
@@ -451,29 +822,53 @@ fn DeviceSettings() -> Element {
     let output_devices = use_signal(get_output_devices);
     let network_interfaces = use_signal(get_network_interfaces);
 
-    // Restore interface/ipv6 selection from the current party config so that
-    // switching tabs and back doesn't reset them to defaults.
-    let (initial_ipv6, initial_interface) = state_arc
+    // Restore settings from the current party config so that switching tabs
+    // and back doesn't reset them to defaults.
+    let initial_config = state_arc
         .party
         .lock()
         .ok()
-        .and_then(|guard| {
-            guard.as_ref().map(|party| {
-                let cfg = party.config();
-                (
-                    cfg.ipv6,
-                    cfg.send_interface_index
-                        .map(|i| i.to_string())
-                        .unwrap_or_default(),
-                )
-            })
-        })
-        .unwrap_or((false, String::new()));
-
-    let mut selected_input = use_signal(String::new);
-    let mut selected_output = use_signal(String::new);
+        .and_then(|guard| guard.as_ref().map(|party| party.config().clone()))
+        .unwrap_or_default();
+    let initial_ipv6 = initial_config.ipv6;
+    let initial_interface = initial_config
+        .send_interface_index
+        .map(|i| i.to_string())
+        .unwrap_or_default();
+    let initial_compress_pcm = initial_config.compress_pcm_music;
+    let initial_underrun_policy = initial_config.underrun_policy;
+    let initial_passphrase = initial_config
+        .encryption
+        .as_ref()
+        .map(|passphrase| passphrase.as_str().to_string())
+        .unwrap_or_default();
+    let initial_channel_offset = initial_config
+        .output_channel_map
+        .as_ref()
+        .and_then(|map| map.first())
+        .map(|first| first.to_string())
+        .unwrap_or_default();
+
+    let initial_input = initial_config
+        .input_device_id
+        .as_ref()
+        .map(|id| format!("{:?}", id))
+        .unwrap_or_default();
+    let initial_output = initial_config
+        .output_device_id
+        .as_ref()
+        .map(|id| format!("{:?}", id))
+        .unwrap_or_default();
+
+    let mut selected_input = use_signal(move || initial_input.clone());
+    let mut selected_output = use_signal(move || initial_output.clone());
+    let mut selected_channel_offset = use_signal(move || initial_channel_offset.clone());
     let mut selected_interface = use_signal(move || initial_interface.clone());
     let mut use_ipv6 = use_signal(move || initial_ipv6);
+    let mut compress_pcm_music = use_signal(move || initial_compress_pcm);
+    let mut underrun_policy = use_signal(move || initial_underrun_policy);
+    let mut passphrase = use_signal(move || initial_passphrase.clone());
+    let mut apply_error = use_signal(|| None::<String>);
 
     let input_options: Vec<(String, String)> =
         std::iter::once(("".to_string(), "System Default".to_string()))
@@ -495,6 +890,15 @@ fn DeviceSettings() -> Element {
             }))
             .collect();
 
+    // The mix is stereo; offer it on each pair of a multichannel interface.
+    let channel_options: Vec<(String, String)> =
+        std::iter::once(("".to_string(), "1/2 (Default)".to_string()))
+            .chain((1..OUTPUT_CHANNEL_PAIRS).map(|pair| {
+                let first = pair * 2;
+                (first.to_string(), format!("{}/{}", first + 1, first + 2))
+            }))
+            .collect();
+
     let ipv6 = *use_ipv6.read();
     let interface_options: Vec<(String, String)> =
         std::iter::once(("".to_string(), "System Default".to_string()))
@@ -541,6 +945,12 @@ fn DeviceSettings() -> Element {
                 }
             };
 
+            let output_channel_map: Option<Vec<usize>> = selected_channel_offset
+                .read()
+                .parse::<usize>()
+                .ok()
+                .map(|first| vec![first, first + 1]);
+
             let send_interface_index: Option<u32> = {
                 let sel = selected_interface.read();
                 if sel.is_empty() {
@@ -550,18 +960,31 @@ fn DeviceSettings() -> Element {
                 }
             };
 
-            let config = PartyConfig {
-                input_device_id: input_id,
-                output_device_id: output_id,
-                ipv6: *use_ipv6.read(),
-                send_interface_index,
-            };
-
             if let Ok(mut party_guard) = state.party.lock()
                 && let Some(party) = party_guard.as_mut()
-                && let Err(e) = party.restart_with_config(config)
             {
-                tracing::error!("Failed to restart party: {:?}", e);
+                let config = PartyConfig {
+                    input_device_id: input_id,
+                    output_device_id: output_id,
+                    output_channel_map,
+                    ipv6: *use_ipv6.read(),
+                    send_interface_index,
+                    compress_pcm_music: *compress_pcm_music.read(),
+                    underrun_policy: *underrun_policy.read(),
+                    encryption: Passphrase::new(passphrase.read().clone()),
+                    ..party.config().clone()
+                };
+
+                match party.restart_with_config(config) {
+                    Ok(()) => {
+                        saved_settings::remember(party.config());
+                        apply_error.set(None);
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to restart party: {:?}", e);
+                        apply_error.set(Some(format!("{:#}", e)));
+                    }
+                }
             }
         }
     };
@@ -592,6 +1015,32 @@ fn DeviceSettings() -> Element {
                     on_change: move |v| selected_output.set(v),
                 }
 
+                DeviceSelector {
+                    label: "Output Channels",
+                    options: channel_options,
+                    selected: selected_channel_offset(),
+                    on_change: move |v| selected_channel_offset.set(v),
+                }
+
+                DeviceSelector {
+                    label: "When No Audio Is Playing",
+                    options: UnderrunPolicy::ALL
+                        .iter()
+                        .enumerate()
+                        .map(|(i, policy)| (i.to_string(), policy.label().to_string()))
+                        .collect(),
+                    selected: UnderrunPolicy::ALL
+                        .iter()
+                        .position(|policy| *policy == underrun_policy())
+                        .unwrap_or_default()
+                        .to_string(),
+                    on_change: move |v: String| {
+                        if let Some(policy) = v.parse::<usize>().ok().and_then(|i| UnderrunPolicy::ALL.get(i)) {
+                            underrun_policy.set(*policy);
+                        }
+                    },
+                }
+
                 div {
                     class: "flex items-center gap-3 py-2",
                     input {
@@ -608,6 +1057,22 @@ fn DeviceSettings() -> Element {
                     }
                 }
 
+                div {
+                    class: "flex items-center gap-3 py-2",
+                    input {
+                        r#type: "checkbox",
+                        id: "compress-pcm-toggle",
+                        class: "w-4 h-4 rounded border-slate-600 bg-slate-800 text-indigo-500 focus:ring-indigo-500 focus:ring-offset-slate-900",
+                        checked: *compress_pcm_music.read(),
+                        onchange: move |evt| compress_pcm_music.set(evt.checked()),
+                    }
+                    label {
+                        r#for: "compress-pcm-toggle",
+                        class: "text-sm text-slate-300",
+                        "Compress shared WAV files (lossless)"
+                    }
+                }
+
                 DeviceSelector {
                     label: "Send Interface",
                     options: interface_options,
@@ -615,11 +1080,36 @@ fn DeviceSettings() -> Element {
                     on_change: move |v| selected_interface.set(v),
                 }
 
+                div {
+                    label {
+                        class: "block text-sm text-slate-400 mb-2",
+                        "Party Passphrase"
+                    }
+                    input {
+                        r#type: "password",
+                        class: "w-full bg-slate-800 border border-slate-700 rounded-lg px-4 py-3 text-sm text-slate-200 placeholder:text-slate-500 focus:outline-none focus:border-indigo-500 transition-colors",
+                        placeholder: "None (unencrypted)",
+                        value: "{passphrase}",
+                        oninput: move |evt| passphrase.set(evt.value()),
+                    }
+                    div {
+                        class: "text-xs text-slate-500 mt-2",
+                        "Everyone at the party needs the same passphrase to hear each other."
+                    }
+                }
+
                 button {
                     class: "w-full mt-6 px-4 py-3 bg-indigo-600 hover:bg-indigo-500 text-white text-sm font-medium rounded-lg transition-colors",
                     onclick: on_apply,
                     "Apply Changes"
                 }
+
+                if let Some(error) = apply_error() {
+                    div {
+                        class: "text-xs text-amber-400",
+                        "{error}"
+                    }
+                }
             }
         }
     }
@@ -1,4 +1,6 @@
 use crate::party::NtpDebugInfo;
+use crate::party::realtime_stream::AggregateStats;
+use crate::state::HostInfo;
 use dioxus::prelude::*;
 use network_interface::NetworkInterfaceConfig;
 use std::net::IpAddr;
@@ -37,6 +39,8 @@ fn get_self_interfaces() -> Vec<SelfInterfaceInfo> {
 #[component]
 pub fn DebugPanel(
     ntp_info: Option<NtpDebugInfo>,
+    realtime_stats: AggregateStats,
+    hosts: Vec<HostInfo>,
     #[props(default)] on_back: Option<EventHandler<()>>,
 ) -> Element {
     let self_interfaces = use_signal(get_self_interfaces);
@@ -188,6 +192,11 @@ pub fn DebugPanel(
                                         value: format!("{}", info.offset_sample_count),
                                     }
 
+                                    DebugInfoItem {
+                                        label: "Samples Accepted / Rejected",
+                                        value: format!("{} / {}", info.accepted_samples, info.rejected_samples),
+                                    }
+
                                     DebugInfoItem {
                                         label: "Local Time",
                                         value: format!("{} µs", info.local_time_micros),
@@ -216,6 +225,58 @@ pub fn DebugPanel(
                             }
                         }
                     }
+
+                    div {
+                        class: "glass-card p-6 rounded-2xl",
+
+                        div {
+                            class: "text-xs font-bold text-slate-500 uppercase tracking-wider mb-6",
+                            "Jitter Buffers"
+                        }
+
+                        if hosts.iter().all(|host| host.streams.is_empty()) {
+                            div {
+                                class: "text-slate-500 text-sm",
+                                "No realtime streams."
+                            }
+                        } else {
+                            div {
+                                class: "grid grid-cols-3 gap-4 mb-4",
+
+                                DebugInfoItem {
+                                    label: "Avg Target Latency",
+                                    value: format!("{:.0} ms", realtime_stats.avg_target_latency_ms),
+                                }
+
+                                DebugInfoItem {
+                                    label: "Avg Packet Loss",
+                                    value: format!("{:.1}%", realtime_stats.avg_loss_rate * 100.0),
+                                }
+
+                                DebugInfoItem {
+                                    label: "Active Streams",
+                                    value: format!("{}", realtime_stats.active_source_count),
+                                }
+                            }
+
+                            div {
+                                class: "grid grid-cols-2 gap-4",
+
+                                for host in hosts.iter() {
+                                    for stream in host.streams.iter() {
+                                        DebugInfoItem {
+                                            label: format!("{} {}", host.id.to_string(), stream.display_name),
+                                            value: format!(
+                                                "{:.0} ms target ({} frames)",
+                                                stream.target_latency_ms,
+                                                stream.target_latency
+                                            ),
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
                 }
             }
         }
@@ -10,6 +10,20 @@ pub use share_music::ShareMusicPanel;
 
 use dioxus::prelude::*;
 
+/// Small pills for app-defined stream tags.
+#[allow(non_snake_case)]
+#[component]
+pub fn TagBadges(tags: Vec<String>) -> Element {
+    rsx! {
+        for tag in tags {
+            span {
+                class: "px-1.5 py-0.5 rounded-full bg-indigo-500/20 text-indigo-300 text-[10px] font-bold border border-indigo-500/30 flex-shrink-0",
+                "{tag}"
+            }
+        }
+    }
+}
+
 #[allow(non_snake_case)]
 #[component]
 pub fn PanelHeader(
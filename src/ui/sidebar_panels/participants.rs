@@ -1,11 +1,13 @@
 //! Participant display components showing connected hosts.
 
 use crate::party::StreamSnapshot;
+use crate::party::realtime_stream::StreamCodec;
 use crate::state::{AppState, HostInfo, StreamViewKey};
 use dioxus::prelude::*;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 
-use super::PanelHeader;
+use super::{PanelHeader, TagBadges};
 
 #[allow(non_snake_case)]
 #[component]
@@ -54,6 +56,24 @@ pub fn MainContent(
 #[allow(non_snake_case)]
 #[component]
 fn HostCard(host: HostInfo) -> Element {
+    let title = host.name.clone().unwrap_or_else(|| host.id.to_string());
+    let initial = host
+        .name
+        .as_ref()
+        .and_then(|name| name.chars().next())
+        .map(|c| c.to_uppercase().to_string())
+        .unwrap_or_else(|| "U".to_string());
+
+    let state_arc = use_context::<Arc<AppState>>();
+    let host_id = host.id;
+    let mut volume = use_signal(|| state_arc.host_volumes.get(host_id));
+    let on_volume_change = move |evt: Event<FormData>| {
+        if let Ok(value) = evt.value().parse::<f32>() {
+            state_arc.host_volumes.set(host_id, value / 100.0);
+            volume.set(value / 100.0);
+        }
+    };
+
     rsx! {
         div {
             class: "glass-card p-5 rounded-2xl relative group",
@@ -64,11 +84,14 @@ fn HostCard(host: HostInfo) -> Element {
                     class: "flex items-center gap-3",
                     div {
                         class: "w-10 h-10 rounded-full bg-gradient-to-br from-indigo-500 to-purple-600 flex items-center justify-center text-white font-bold shadow-lg shadow-indigo-500/20",
-                        "U"
+                        "{initial}"
                     }
                     div {
                         class: "flex flex-col",
-                        span { class: "font-bold text-sm text-slate-200", "{host.id.to_string()}" }
+                        span { class: "font-bold text-sm text-slate-200", "{title}" }
+                        if host.name.is_some() {
+                            span { class: "text-[10px] text-slate-500", "{host.id.to_string()}" }
+                        }
                         div {
                             class: "flex items-center gap-1.5",
                             span { class: "w-1.5 h-1.5 rounded-full bg-emerald-500" }
@@ -78,6 +101,23 @@ fn HostCard(host: HostInfo) -> Element {
                 }
             }
 
+            div {
+                class: "mb-4",
+                div {
+                    class: "flex justify-between text-xs mb-1",
+                    span { class: "text-slate-400", "Volume" }
+                    span { class: "font-mono font-bold text-slate-200", "{(volume() * 100.0) as i32}%" }
+                }
+                input {
+                    r#type: "range",
+                    min: 0,
+                    max: 200,
+                    value: (volume() * 100.0) as i32,
+                    class: "w-full",
+                    oninput: on_volume_change,
+                }
+            }
+
             div {
             class: "space-y-2",
             for stream in &host.streams {
@@ -86,7 +126,11 @@ fn HostCard(host: HostInfo) -> Element {
                     display_name: stream.display_name.clone(),
                     packet_loss: stream.packet_loss,
                     target_latency: stream.target_latency,
+                    target_latency_ms: stream.target_latency_ms,
                     audio_level: stream.audio_level,
+                    codec: stream.codec,
+                    loss_muted: stream.loss_muted,
+                    tags: stream.tags.clone(),
                 }
             }
             if host.streams.is_empty() {
@@ -107,7 +151,11 @@ fn StreamIndicator(
     display_name: String,
     packet_loss: f32,
     target_latency: f32,
+    target_latency_ms: f32,
     audio_level: u32,
+    codec: Option<StreamCodec>,
+    loss_muted: bool,
+    tags: Vec<String>,
 ) -> Element {
     let state_arc = use_context::<Arc<AppState>>();
     let mut snapshots = use_signal(Vec::<StreamSnapshot>::new);
@@ -119,7 +167,23 @@ fn StreamIndicator(
         "🔊"
     };
     let packet_loss_pct = (packet_loss * 100.0) as i32;
-    let target_lat = target_latency as i32;
+    let codec_info = codec.map(|codec| (codec.label(), codec.measured_bitrate / 1000));
+    // Same click-to-render trick as the monitor button below.
+    let mut latency_unit_clicks = use_signal(|| 0u32);
+    latency_unit_clicks();
+    let latency_in_frames = state_arc.latency_in_frames.load(Ordering::Relaxed);
+    let target_text = if latency_in_frames {
+        format!("{} frames", target_latency as i32)
+    } else {
+        format!("{} ms", target_latency_ms.round() as i32)
+    };
+    let state_for_unit = state_arc.clone();
+    let on_latency_unit_click = move |_| {
+        state_for_unit
+            .latency_in_frames
+            .store(!latency_in_frames, Ordering::Relaxed);
+        latency_unit_clicks += 1;
+    };
 
     let loss_color = if packet_loss < 0.02 {
         "text-emerald-400"
@@ -137,6 +201,27 @@ fn StreamIndicator(
         "bg-red-500"
     };
 
+    let monitor_target = stream_key
+        .stream_id
+        .parse()
+        .ok()
+        .map(|stream_id| (stream_key.host_id, stream_id));
+    // Read from shared state so that soloing another stream clears this one
+    // on the next render; the signal only forces a render after a click.
+    let mut monitor_clicks = use_signal(|| 0u32);
+    monitor_clicks();
+    let monitoring = monitor_target.is_some() && state_arc.monitor_stream() == monitor_target;
+    let state_for_monitor = state_arc.clone();
+    let on_monitor_click = move |_| {
+        state_for_monitor.set_monitor_stream(monitor_target.filter(|_| !monitoring));
+        monitor_clicks += 1;
+    };
+    let monitor_class = if monitoring {
+        "bg-indigo-600 text-white"
+    } else {
+        "bg-slate-700 hover:bg-slate-600 text-slate-400 hover:text-white"
+    };
+
     let stream_key_clone = stream_key.clone();
     let on_debug_click = move |_| {
         let new_show = !show_graph();
@@ -155,6 +240,7 @@ fn StreamIndicator(
                 class: "flex items-center gap-3 w-full",
                 span { class: "text-sm flex-shrink-0", "{icon}" }
                 span { class: "text-xs text-slate-400 w-16 flex-shrink-0", "{display_name}" }
+                TagBadges { tags }
 
                 div {
                     class: "flex-1 h-1.5 bg-slate-700 rounded-full overflow-hidden",
@@ -171,9 +257,35 @@ fn StreamIndicator(
                         "Loss: "
                         span { class: "{loss_color}", "{packet_loss_pct}%" }
                     }
-                    span { class: "text-slate-500",
+                    if loss_muted {
+                        span {
+                            class: "text-red-400",
+                            title: "Turned down until the connection recovers",
+                            "Poor connection"
+                        }
+                    }
+                    if let Some((label, measured_kbps)) = codec_info {
+                        span {
+                            class: "text-slate-400",
+                            title: "Measured: {measured_kbps}k",
+                            "{label}"
+                        }
+                    }
+                    span {
+                        class: "text-slate-500 cursor-pointer",
+                        title: "Click to switch between milliseconds and frames",
+                        onclick: on_latency_unit_click,
                         "Target: "
-                        span { class: "text-indigo-400", "{target_lat} frames" }
+                        span { class: "text-indigo-400", "{target_text}" }
+                    }
+                }
+
+                if monitor_target.is_some() {
+                    button {
+                        class: "ml-2 w-6 h-6 flex-shrink-0 rounded {monitor_class} flex items-center justify-center text-xs transition-colors",
+                        title: "Listen to this stream alone (only on this device)",
+                        onclick: on_monitor_click,
+                        "🎧"
                     }
                 }
 
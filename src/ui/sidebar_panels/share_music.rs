@@ -1,18 +1,27 @@
 use crate::music_provider::{MusicProvider, MusicProviderContext};
-use crate::party::{PlaylistState, SyncedStreamState};
+use crate::party::{LoopRegion, PlaylistState, SyncedStreamId, SyncedStreamState};
 use crate::state::AppState;
 use dioxus::prelude::*;
 use std::sync::Arc;
 
-use super::PanelHeader;
+use super::{PanelHeader, TagBadges};
+
+/// Largest key change offered, in semitones either way.
+const MAX_KEY_CHANGE: i8 = 12;
+/// Tempo range and step offered, in percent of normal speed. Senders only
+/// pace packets at twice real time, so keep the top well below that.
+const MIN_TEMPO_PERCENT: u16 = 50;
+const MAX_TEMPO_PERCENT: u16 = 150;
+const TEMPO_STEP_PERCENT: u16 = 10;
 
 #[derive(Clone, PartialEq)]
 struct SenderProgressInfo {
-    frames_sent: u64,
-    total_frames: u64,
+    stream_id: SyncedStreamId,
+    sent_fraction: f64,
     samples_played: u64,
     total_samples: u64,
     sample_rate: u32,
+    loop_region: Option<LoopRegion>,
 }
 
 #[derive(Clone, PartialEq)]
@@ -46,7 +55,11 @@ pub fn ShareMusicPanel(
             let state = state_arc.clone();
             move |data, title| state.playlist_add(data, title)
         },
-    );
+    )
+    .with_queue_file({
+        let state = state_arc.clone();
+        move |data, title, path| state.playlist_add_file(data, title, path)
+    });
 
     let providers: Vec<Box<dyn MusicProvider>> = state_arc
         .music_provider_factories
@@ -58,12 +71,7 @@ pub fn ShareMusicPanel(
     let is_streaming = progress
         .is_streaming
         .load(std::sync::atomic::Ordering::Relaxed);
-    let streaming_current = progress
-        .streaming_current
-        .load(std::sync::atomic::Ordering::Relaxed);
-    let streaming_total = progress
-        .streaming_total
-        .load(std::sync::atomic::Ordering::Relaxed);
+    let sent_fraction = progress.sent_fraction();
     let local_sender_stream_id = active_streams
         .iter()
         .find(|stream| stream.is_local_sender)
@@ -156,7 +164,11 @@ pub fn ShareMusicPanel(
                                             div {
                                                 class: "flex items-center gap-2",
                                                 span { class: "text-emerald-400 text-lg", if stream.progress.is_playing { "▶" } else { "⏸" } }
-                                                span { class: "text-sm text-emerald-300 font-medium", "Now playing:" }
+                                                span {
+                                                    class: "text-sm text-emerald-300 font-medium",
+                                                    if stream.progress.is_seeking { "Seeking…" } else { "Now playing:" }
+                                                }
+                                                TagBadges { tags: stream.tags.clone() }
                                             }
                                             span {
                                                 class: "text-xs text-slate-400",
@@ -176,13 +188,13 @@ pub fn ShareMusicPanel(
 
                                                 {
                                                     if stream.is_local_sender {
-                                                        let total = streaming_total.max(meta.total_frames).max(1);
                                                         let sender_info = SenderProgressInfo {
-                                                            frames_sent: streaming_current,
-                                                            total_frames: total,
+                                                            stream_id: stream.stream_id,
+                                                            sent_fraction,
                                                             samples_played,
                                                             total_samples,
                                                             sample_rate,
+                                                            loop_region: *progress.loop_region.lock().unwrap(),
                                                         };
                                                         rsx! { SenderProgressBar { info: sender_info } }
                                                     } else {
@@ -242,6 +254,82 @@ pub fn ShareMusicPanel(
                                                     },
                                                     "⏩"
                                                 }
+                                                if stream.is_local_sender {
+                                                    button {
+                                                        class: "p-2 rounded-full hover:bg-rose-500/20 text-rose-400 transition-colors",
+                                                        title: "Stop sharing",
+                                                        onclick: {
+                                                            let state = state_arc.clone();
+                                                            let stream_id = stream.stream_id;
+                                                            move |_| {
+                                                                let _ = state.stop_music_stream(stream_id);
+                                                            }
+                                                        },
+                                                        "⏹"
+                                                    }
+                                                }
+                                                }
+
+                                                if stream.is_local_sender {
+                                                    div {
+                                                        class: "flex items-center justify-center gap-3 mt-3 text-sm text-emerald-400",
+                                                        span { class: "text-slate-400", "Key" }
+                                                        button {
+                                                            class: "px-2 rounded hover:bg-emerald-500/20 disabled:opacity-30",
+                                                            disabled: meta.pitch_semitones <= -MAX_KEY_CHANGE,
+                                                            onclick: {
+                                                                let state = state_arc.clone();
+                                                                let stream_id = stream.stream_id;
+                                                                let semitones = meta.pitch_semitones - 1;
+                                                                move |_| {
+                                                                    let _ = state.set_music_pitch(stream_id, semitones);
+                                                                }
+                                                            },
+                                                            "−"
+                                                        }
+                                                        span { class: "font-mono w-8 text-center", "{meta.pitch_semitones:+}" }
+                                                        button {
+                                                            class: "px-2 rounded hover:bg-emerald-500/20 disabled:opacity-30",
+                                                            disabled: meta.pitch_semitones >= MAX_KEY_CHANGE,
+                                                            onclick: {
+                                                                let state = state_arc.clone();
+                                                                let stream_id = stream.stream_id;
+                                                                let semitones = meta.pitch_semitones + 1;
+                                                                move |_| {
+                                                                    let _ = state.set_music_pitch(stream_id, semitones);
+                                                                }
+                                                            },
+                                                            "+"
+                                                        }
+                                                        span { class: "text-slate-400 ml-3", "Tempo" }
+                                                        button {
+                                                            class: "px-2 rounded hover:bg-emerald-500/20 disabled:opacity-30",
+                                                            disabled: meta.tempo_percent <= MIN_TEMPO_PERCENT,
+                                                            onclick: {
+                                                                let state = state_arc.clone();
+                                                                let stream_id = stream.stream_id;
+                                                                let percent = meta.tempo_percent - TEMPO_STEP_PERCENT;
+                                                                move |_| {
+                                                                    let _ = state.set_music_tempo(stream_id, percent);
+                                                                }
+                                                            },
+                                                            "−"
+                                                        }
+                                                        span { class: "font-mono w-12 text-center", "{meta.tempo_percent}%" }
+                                                        button {
+                                                            class: "px-2 rounded hover:bg-emerald-500/20 disabled:opacity-30",
+                                                            disabled: meta.tempo_percent >= MAX_TEMPO_PERCENT,
+                                                            onclick: {
+                                                                let state = state_arc.clone();
+                                                                let stream_id = stream.stream_id;
+                                                                let percent = meta.tempo_percent + TEMPO_STEP_PERCENT;
+                                                                move |_| {
+                                                                    let _ = state.set_music_tempo(stream_id, percent);
+                                                                }
+                                                            },
+                                                            "+"
+                                                        }
+                                                    }
                                                 }
                                             }
                                         }
@@ -262,6 +350,8 @@ pub fn ShareMusicPanel(
                     PlaylistSection {
                         playlist: playlist.clone(),
                     }
+
+                    SavedPlaylistsSection {}
                 }
             }
         }
@@ -278,13 +368,38 @@ fn format_time(total_ms: u64) -> String {
 #[allow(non_snake_case)]
 #[component]
 fn SenderProgressBar(info: SenderProgressInfo) -> Element {
-    let total_frames = info.total_frames.max(1);
+    let state_arc = use_context::<Arc<AppState>>();
+    // Loop start picked with "A", waiting for "B".
+    let mut pending_loop_start = use_signal(|| None::<u64>);
+
     let total_samples = info.total_samples.max(1);
-    let sent_pct = (info.frames_sent as f64 / total_frames as f64 * 100.0) as u32;
+    let sent_pct = (info.sent_fraction * 100.0) as u32;
     let played_pct = (info.samples_played as f64 / total_samples as f64 * 100.0) as u32;
 
-    let current_time = format_time(info.samples_played * 1000 / info.sample_rate as u64);
-    let total_time = format_time(total_samples * 1000 / info.sample_rate as u64);
+    let current_ms = info.samples_played * 1000 / info.sample_rate as u64;
+    let total_ms = (total_samples * 1000 / info.sample_rate as u64).max(1);
+    let current_time = format_time(current_ms);
+    let total_time = format_time(total_ms);
+    let sent_time = format_time(
+        (info.sent_fraction * total_samples as f64) as u64 * 1000 / info.sample_rate as u64,
+    );
+
+    let loop_start = pending_loop_start().or(info.loop_region.map(|region| region.start_ms));
+    let loop_span = info.loop_region.map(|region| {
+        let left = region.start_ms as f64 / total_ms as f64 * 100.0;
+        let width = (region.end_ms - region.start_ms) as f64 / total_ms as f64 * 100.0;
+        (left, width)
+    });
+    let loop_label = info.loop_region.map(|region| {
+        format!(
+            "Loop {} – {}",
+            format_time(region.start_ms),
+            format_time(region.end_ms)
+        )
+    });
+    let pending_label =
+        pending_loop_start().map(|start_ms| format!("Loop from {}…", format_time(start_ms)));
+    let can_set_end = loop_start.is_some_and(|start| current_ms > start);
 
     rsx! {
         div {
@@ -295,6 +410,12 @@ fn SenderProgressBar(info: SenderProgressInfo) -> Element {
                     class: "absolute left-0 top-0 h-full bg-sky-500",
                     style: "width: {sent_pct}%",
                 }
+                if let Some((left, width)) = loop_span {
+                    div {
+                        class: "absolute top-0 h-full bg-amber-400/50",
+                        style: "left: {left}%; width: {width}%",
+                    }
+                }
                 div {
                     class: "absolute top-0 h-full w-1 bg-white shadow-md",
                     style: "left: calc({played_pct}% - 2px)",
@@ -303,7 +424,50 @@ fn SenderProgressBar(info: SenderProgressInfo) -> Element {
             div {
                 class: "flex justify-between text-xs text-slate-400",
                 span { "{current_time} / {total_time}" }
-                span { class: "text-slate-500", "sent:{info.frames_sent} tot:{total_frames}" }
+                span { class: "text-slate-500", "sent: {sent_time}" }
+            }
+            div {
+                class: "flex items-center gap-2 text-xs text-amber-400",
+                button {
+                    class: "px-2 rounded hover:bg-amber-500/20",
+                    title: "Start the loop here",
+                    onclick: move |_| pending_loop_start.set(Some(current_ms)),
+                    "A"
+                }
+                button {
+                    class: "px-2 rounded hover:bg-amber-500/20 disabled:opacity-30",
+                    title: "End the loop here",
+                    disabled: !can_set_end,
+                    onclick: {
+                        let state = state_arc.clone();
+                        let stream_id = info.stream_id;
+                        move |_| {
+                            if let Some(start_ms) = loop_start {
+                                let region = LoopRegion { start_ms, end_ms: current_ms };
+                                let _ = state.set_music_loop(stream_id, Some(region));
+                                pending_loop_start.set(None);
+                            }
+                        }
+                    },
+                    "B"
+                }
+                if let Some(label) = loop_label {
+                    span { "{label}" }
+                    button {
+                        class: "px-2 rounded hover:bg-amber-500/20",
+                        title: "Stop looping",
+                        onclick: {
+                            let state = state_arc.clone();
+                            let stream_id = info.stream_id;
+                            move |_| {
+                                let _ = state.set_music_loop(stream_id, None);
+                            }
+                        },
+                        "✕"
+                    }
+                } else if let Some(label) = pending_label {
+                    span { class: "text-slate-500", "{label}" }
+                }
             }
             div {
                 class: "flex gap-3 text-xs text-slate-500",
@@ -544,3 +708,115 @@ fn PlaylistSection(playlist: PlaylistState) -> Element {
         }
     }
 }
+
+// ---------------------------------------------------------------------------
+//  Saved Playlists UI
+// ---------------------------------------------------------------------------
+
+#[allow(non_snake_case)]
+#[component]
+fn SavedPlaylistsSection() -> Element {
+    let state = use_context::<Arc<AppState>>();
+    let mut name = use_signal(String::new);
+    let mut status = use_signal(|| None::<String>);
+    let mut saved = use_signal({
+        let state = state.clone();
+        move || state.saved_playlists().unwrap_or_default()
+    });
+    let trimmed_name = name().trim().to_string();
+
+    rsx! {
+        div {
+            class: "glass-card p-6 rounded-2xl space-y-4",
+
+            div {
+                class: "flex items-center gap-2",
+                span { class: "text-xl", "💾" }
+                h3 { class: "text-lg font-bold text-white", "Saved Playlists" }
+            }
+
+            div {
+                class: "flex items-center gap-2",
+                input {
+                    r#type: "text",
+                    class: "flex-1 px-3 py-2 rounded-xl bg-slate-800 border border-slate-700 text-sm text-white placeholder-slate-500",
+                    placeholder: "Playlist name",
+                    value: "{name}",
+                    oninput: move |evt| name.set(evt.value()),
+                }
+                button {
+                    class: "px-4 py-2 rounded-xl bg-indigo-500/20 hover:bg-indigo-500/30 text-indigo-300 text-sm font-bold transition-colors disabled:opacity-30 disabled:cursor-not-allowed",
+                    disabled: trimmed_name.is_empty(),
+                    onclick: {
+                        let state = state.clone();
+                        let trimmed_name = trimmed_name.clone();
+                        move |_| {
+                            match state.save_playlist(&trimmed_name) {
+                                Ok(()) => {
+                                    status.set(Some(format!("Saved \"{trimmed_name}\"")));
+                                    saved.set(state.saved_playlists().unwrap_or_default());
+                                }
+                                Err(e) => status.set(Some(format!("{e:#}"))),
+                            }
+                        }
+                    },
+                    "Save queue"
+                }
+            }
+
+            if let Some(message) = status() {
+                p { class: "text-xs text-slate-400", "{message}" }
+            }
+
+            if saved().is_empty() {
+                p {
+                    class: "text-sm text-slate-500 italic",
+                    "Queue local files, then save them here under a name to load them again later."
+                }
+            } else {
+                div {
+                    class: "space-y-1",
+                    for playlist_name in saved() {
+                        div {
+                            key: "{playlist_name}",
+                            class: "flex items-center gap-3 p-3 rounded-xl bg-slate-800/60",
+                            p { class: "flex-1 min-w-0 text-sm font-medium text-white truncate", "{playlist_name}" }
+                            button {
+                                class: "px-3 py-1 text-xs rounded-lg bg-slate-700 hover:bg-slate-600 text-slate-200",
+                                onclick: {
+                                    let state = state.clone();
+                                    let playlist_name = playlist_name.clone();
+                                    move |_| {
+                                        let message = match state.load_playlist(&playlist_name) {
+                                            Ok(0) => format!("Queued \"{playlist_name}\""),
+                                            Ok(skipped) => format!(
+                                                "Queued \"{playlist_name}\"; skipped {skipped} missing file(s)"
+                                            ),
+                                            Err(e) => format!("{e:#}"),
+                                        };
+                                        status.set(Some(message));
+                                    }
+                                },
+                                "Load"
+                            }
+                            button {
+                                class: "shrink-0 w-7 h-7 rounded-full flex items-center justify-center text-slate-400 hover:text-rose-400 hover:bg-rose-500/10 transition-colors",
+                                onclick: {
+                                    let state = state.clone();
+                                    let playlist_name = playlist_name.clone();
+                                    move |_| {
+                                        if let Err(e) = state.delete_saved_playlist(&playlist_name) {
+                                            status.set(Some(format!("{e:#}")));
+                                        }
+                                        saved.set(state.saved_playlists().unwrap_or_default());
+                                    }
+                                },
+                                "✕"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}